@@ -0,0 +1,26 @@
+// 工作量证明哈希算法的挖矿吞吐量对比: cargo bench --bench pow [秒数]
+use std::{env, time::Duration};
+
+use blockchain::hash::{self, HashAlgorithm};
+
+// cargo test --benches 不带 --bench 参数运行，只确认能跑通
+const SMOKE_TEST_DURATION: Duration = Duration::from_millis(10);
+const DEFAULT_SECONDS: u64 = 3;
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let duration = if args.iter().any(|arg| arg == "--bench") {
+        let secs = args
+            .iter()
+            .find_map(|arg| arg.parse().ok())
+            .unwrap_or(DEFAULT_SECONDS);
+        Duration::from_secs(secs)
+    } else {
+        SMOKE_TEST_DURATION
+    };
+
+    for algorithm in HashAlgorithm::ALL {
+        let rate = hash::benchmark(algorithm, duration);
+        println!("{:?}: {:.0} hashes/s", algorithm, rate);
+    }
+}
@@ -0,0 +1,9 @@
+// 开启 grpc 特性时由 proto/blockchain.proto 生成 gRPC 消息和服务代码，使用内置的 protoc，不依赖系统安装
+fn main() {
+    #[cfg(feature = "grpc")]
+    {
+        println!("cargo:rerun-if-changed=proto/blockchain.proto");
+        std::env::set_var("PROTOC", protoc_bin_vendored::protoc_bin_path().unwrap());
+        tonic_build::compile_protos("proto/blockchain.proto").unwrap();
+    }
+}
@@ -0,0 +1,13 @@
+#![no_main]
+
+use blockchain::wallets::{address_from_pub_key_hash, decode_address};
+use libfuzzer_sys::fuzz_target;
+
+// 命令行和 REST 接口传入的地址
+fuzz_target!(|data: &[u8]| {
+    if let Ok(address) = std::str::from_utf8(data) {
+        if let Ok(pub_key_hash) = decode_address(address) {
+            let _ = address_from_pub_key_hash(&pub_key_hash);
+        }
+    }
+});
@@ -0,0 +1,12 @@
+#![no_main]
+
+use blockchain::block::Block;
+use libfuzzer_sys::fuzz_target;
+
+// 区块数据库和网络中的区块
+fuzz_target!(|data: &[u8]| {
+    if let Ok(block) = Block::from_bytes(data) {
+        let _ = block.get_size();
+        let _ = block.get_header();
+    }
+});
@@ -0,0 +1,9 @@
+#![no_main]
+
+use blockchain::server::bytes_to_cmd;
+use libfuzzer_sys::fuzz_target;
+
+// 节点收到的原始消息
+fuzz_target!(|data: &[u8]| {
+    let _ = bytes_to_cmd(data);
+});
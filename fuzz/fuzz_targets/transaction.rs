@@ -0,0 +1,12 @@
+#![no_main]
+
+use blockchain::transaction::Transaction;
+use libfuzzer_sys::fuzz_target;
+
+// 交易池和网络中的交易
+fuzz_target!(|data: &[u8]| {
+    if let Ok(tx) = Transaction::from_bytes(data) {
+        let _ = tx.hash();
+        let _ = tx.is_coinbase();
+    }
+});
@@ -0,0 +1,144 @@
+// 节点地址表: 把见过的节点地址连同最后握手时间和质量分数保存在数据库中，
+// 节点重启后从地址表恢复已知节点，只有地址表为空时才回退到引导节点；
+// 握手成功加分，连接失败扣分，分数过低的地址从地址表中删除
+
+use super::Result;
+use bincode::{deserialize, serialize};
+use serde::{Deserialize, Serialize};
+
+pub const PEERS_DB: &str = "peers";
+// 每次握手成功增加的分数和分数上限
+const GOOD_SCORE: i32 = 1;
+const MAX_SCORE: i32 = 100;
+// 每次连接失败扣除的分数，低于 MIN_SCORE 时删除
+const FAILURE_PENALTY: i32 = 5;
+const MIN_SCORE: i32 = -20;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct AddrInfo {
+    // 最后一次握手成功的时间(毫秒)，从未握手成功时为 0
+    pub last_seen: u128,
+    pub score: i32,
+}
+
+#[derive(Clone)]
+pub struct AddrBook {
+    db: sled::Db,
+}
+
+impl AddrBook {
+    pub fn open(path: &str) -> Result<AddrBook> {
+        Ok(AddrBook {
+            db: sled::open(path)?,
+        })
+    }
+
+    /**
+     * @desc 不落盘的地址表，用于测试
+     */
+    pub fn temporary() -> Result<AddrBook> {
+        Ok(AddrBook {
+            db: sled::Config::new().temporary(true).open()?,
+        })
+    }
+
+    pub fn get(&self, addr: &str) -> Result<Option<AddrInfo>> {
+        match self.db.get(addr)? {
+            Some(data) => Ok(Some(deserialize(&data)?)),
+            None => Ok(None),
+        }
+    }
+
+    fn put(&self, addr: &str, info: &AddrInfo) -> Result<()> {
+        self.db.insert(addr, serialize(info)?)?;
+        self.db.flush()?;
+        Ok(())
+    }
+
+    /**
+     * @desc 全部地址，按分数从高到低、最后握手时间从近到远排序
+     */
+    pub fn load(&self) -> Result<Vec<(String, AddrInfo)>> {
+        let mut addrs: Vec<(String, AddrInfo)> = Vec::new();
+        for item in self.db.iter() {
+            let (addr, data) = item?;
+            addrs.push((String::from_utf8(addr.to_vec())?, deserialize(&data)?));
+        }
+        addrs.sort_by(|(_, a), (_, b)| b.score.cmp(&a.score).then(b.last_seen.cmp(&a.last_seen)));
+
+        Ok(addrs)
+    }
+
+    /**
+     * @desc 记录新地址，已有的地址保持不变
+     */
+    pub fn add(&self, addr: &str) -> Result<()> {
+        if self.get(addr)?.is_none() {
+            self.put(addr, &AddrInfo::default())?;
+        }
+        Ok(())
+    }
+
+    /**
+     * @desc 握手成功: 更新最后握手时间并加分
+     */
+    pub fn mark_good(&self, addr: &str, now: u128) -> Result<()> {
+        let mut info = self.get(addr)?.unwrap_or_default();
+        info.last_seen = now;
+        info.score = (info.score + GOOD_SCORE).min(MAX_SCORE);
+        self.put(addr, &info)
+    }
+
+    /**
+     * @desc 连接失败: 扣分，分数过低时删除地址，返回地址是否被删除
+     */
+    pub fn mark_failed(&self, addr: &str) -> Result<bool> {
+        let mut info = match self.get(addr)? {
+            Some(info) => info,
+            None => return Ok(false),
+        };
+        info.score -= FAILURE_PENALTY;
+        if info.score < MIN_SCORE {
+            self.db.remove(addr)?;
+            self.db.flush()?;
+            return Ok(true);
+        }
+
+        self.put(addr, &info)?;
+        Ok(false)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_addr_book() {
+        let book = AddrBook::temporary().unwrap();
+        book.add("localhost:3001").unwrap();
+        book.add("localhost:3002").unwrap();
+        book.mark_good("localhost:3002", 1000).unwrap();
+        // 已有的地址不会被重置
+        book.add("localhost:3002").unwrap();
+
+        let addrs = book.load().unwrap();
+        assert_eq!(addrs[0].0, "localhost:3002");
+        assert_eq!(
+            addrs[0].1,
+            AddrInfo {
+                last_seen: 1000,
+                score: 1
+            }
+        );
+        assert_eq!(addrs[1].1, AddrInfo::default());
+
+        assert!(!book.mark_failed("localhost:3005").unwrap());
+        for _ in 0..4 {
+            assert!(!book.mark_failed("localhost:3001").unwrap());
+        }
+        assert!(book.mark_failed("localhost:3001").unwrap());
+        assert_eq!(book.get("localhost:3001").unwrap(), None);
+        assert_eq!(book.load().unwrap().len(), 1);
+    }
+}
@@ -0,0 +1,99 @@
+// 运维公告: 由配置的公告密钥签名的消息(例如 "在高度 N 之前升级")，
+// 节点验证签名后记录到日志、在 get_blockchain_info 中展示，并转发给所有已知节点；
+// 新连接的节点在握手后收到尚未过期的公告
+
+use super::Result;
+use crate::{network, wallets::Wallet};
+use bincode::serialize;
+use crypto::ed25519;
+use failure::format_err;
+use serde::{Deserialize, Serialize};
+
+pub const MAX_ALERT_LEN: usize = 256;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Alert {
+    // 公告编号，相同编号的公告只处理一次
+    pub id: u32,
+    // 过期时间(毫秒时间戳)，过期后不再展示和转发
+    pub expiration: u128,
+    pub message: String,
+    pub signature: Vec<u8>,
+}
+
+impl Alert {
+    /**
+     * @desc 用公告密钥对应的钱包签名公告
+     */
+    pub fn new(id: u32, expiration: u128, message: &str, wallet: &Wallet) -> Result<Alert> {
+        if message.is_empty() || message.len() > MAX_ALERT_LEN {
+            return Err(format_err!(
+                "Alert message must be 1 to {} bytes.",
+                MAX_ALERT_LEN
+            ));
+        }
+
+        let mut alert = Alert {
+            id,
+            expiration,
+            message: message.to_string(),
+            signature: Vec::new(),
+        };
+        alert.signature = ed25519::signature(&alert.signed_data()?, &wallet.secret_key).to_vec();
+
+        Ok(alert)
+    }
+
+    // 签名内容包含网络标识，公告不能在其他网络上重放
+    fn signed_data(&self) -> Result<Vec<u8>> {
+        Ok(serialize(&(
+            network::magic(),
+            self.id,
+            self.expiration,
+            &self.message,
+        ))?)
+    }
+
+    /**
+     * @desc 用配置的公告公钥验证签名
+     */
+    pub fn verify(&self, alert_key: &[u8]) -> Result<()> {
+        let valid = self.message.len() <= MAX_ALERT_LEN
+            && alert_key.len() == 32
+            && self.signature.len() == 64
+            && ed25519::verify(&self.signed_data()?, alert_key, &self.signature);
+        if !valid {
+            return Err(format_err!("Alert {} has an invalid signature.", self.id));
+        }
+
+        Ok(())
+    }
+
+    pub fn is_expired(&self, now: u128) -> bool {
+        self.expiration <= now
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_alert_signature() {
+        let wallet = Wallet::from_seed(&[1; 32]);
+        let other = Wallet::from_seed(&[2; 32]);
+        let alert = Alert::new(1, 1000, "Upgrade before height 100.", &wallet).unwrap();
+
+        assert!(alert.verify(&wallet.public_key).is_ok());
+        assert!(alert.verify(&other.public_key).is_err());
+
+        let mut forged = alert.clone();
+        forged.message = String::from("Downgrade now.");
+        assert!(forged.verify(&wallet.public_key).is_err());
+
+        assert!(!alert.is_expired(999));
+        assert!(alert.is_expired(1000));
+        assert!(Alert::new(2, 1000, "", &wallet).is_err());
+        assert!(Alert::new(2, 1000, &"a".repeat(MAX_ALERT_LEN + 1), &wallet).is_err());
+    }
+}
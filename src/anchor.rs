@@ -0,0 +1,209 @@
+// 跨链锚定: 把本链的区块 hash 写入另一条链(同一软件的另一个实例)交易的数据输出，
+// 或者导出由钱包密钥签名的证明文件；验证时检查锚定的区块仍在本链的主链上，
+// 数据输出在目标链上确认后，就证明本链的这个区块在该时间之前已经存在
+
+use std::time::SystemTime;
+
+use super::Result;
+use crate::{
+    transaction::{decode_hex, encode_hex, TXOutput, Transaction},
+    wallets::{address_from_pub_key_hash, hash_pub_key, Wallet},
+};
+use bincode::{deserialize, serialize};
+use crypto::ed25519;
+use failure::format_err;
+use serde::{Deserialize, Serialize};
+
+// 锚定输出的锁定数据: 标记 + 高度(4 字节大端) + 区块 hash，金额为 0，不能被花费
+const ANCHOR_MARKER: &[u8] = b"ANCH";
+const BLOCK_HASH_LEN: usize = 64;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Anchor {
+    pub height: i32,
+    pub block_hash: String,
+}
+
+// 导出到文件的锚定证明，由钱包密钥签名
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Attestation {
+    pub network: String,
+    pub anchor: Anchor,
+    pub timestamp: u128,
+    pub pub_key: Vec<u8>,
+    pub signature: Vec<u8>,
+}
+
+impl Anchor {
+    pub fn new(height: i32, block_hash: &str) -> Result<Anchor> {
+        if height < 0
+            || block_hash.len() != BLOCK_HASH_LEN
+            || !block_hash.bytes().all(|b| b.is_ascii_hexdigit())
+        {
+            return Err(format_err!("Invalid anchor: {} at {}", block_hash, height));
+        }
+
+        Ok(Anchor {
+            height,
+            block_hash: block_hash.to_string(),
+        })
+    }
+
+    pub fn to_output(&self) -> TXOutput {
+        TXOutput {
+            value: 0,
+            pub_key_hash: [
+                ANCHOR_MARKER,
+                &self.height.to_be_bytes(),
+                self.block_hash.as_bytes(),
+            ]
+            .concat(),
+        }
+    }
+
+    /**
+     * @desc 交易中的第一个锚定输出，没有时返回 None
+     */
+    pub fn from_tx(tx: &Transaction) -> Option<Anchor> {
+        tx.vout.iter().find_map(|out| {
+            let data = out.pub_key_hash.strip_prefix(ANCHOR_MARKER)?;
+            if out.value != 0 || data.len() != 4 + BLOCK_HASH_LEN {
+                return None;
+            }
+            let height = i32::from_be_bytes(data[..4].try_into().ok()?);
+            let block_hash = String::from_utf8(data[4..].to_vec()).ok()?;
+
+            Anchor::new(height, &block_hash).ok()
+        })
+    }
+
+    /**
+     * @desc 检查锚定的区块是否在本链主链上，main_hash 是本链主链在该高度的区块 hash
+     */
+    pub fn check(&self, main_hash: Option<&str>) -> Result<()> {
+        match main_hash {
+            Some(hash) if hash == self.block_hash => Ok(()),
+            Some(hash) => Err(format_err!(
+                "Block {} at height {} is not in the main chain, which has {}.",
+                self.block_hash,
+                self.height,
+                hash
+            )),
+            None => Err(format_err!(
+                "The main chain does not reach height {}.",
+                self.height
+            )),
+        }
+    }
+}
+
+impl Attestation {
+    /**
+     * @desc 用钱包密钥签名当前网络上的锚定
+     */
+    pub fn new(network: &str, anchor: Anchor, wallet: &Wallet) -> Result<Attestation> {
+        let mut attestation = Attestation {
+            network: network.to_string(),
+            anchor,
+            timestamp: SystemTime::now()
+                .duration_since(SystemTime::UNIX_EPOCH)?
+                .as_millis(),
+            pub_key: wallet.public_key.clone(),
+            signature: Vec::new(),
+        };
+        attestation.signature =
+            ed25519::signature(&attestation.message()?, &wallet.secret_key).to_vec();
+
+        Ok(attestation)
+    }
+
+    // 签名的内容: 除签名以外的全部字段
+    fn message(&self) -> Result<Vec<u8>> {
+        Ok(serialize(&(
+            &self.network,
+            &self.anchor,
+            self.timestamp,
+            &self.pub_key,
+        ))?)
+    }
+
+    pub fn verify_signature(&self) -> Result<()> {
+        let valid = self.pub_key.len() == 32
+            && self.signature.len() == 64
+            && ed25519::verify(&self.message()?, &self.pub_key, &self.signature);
+        if !valid {
+            return Err(format_err!("Attestation signature is invalid."));
+        }
+
+        Ok(())
+    }
+
+    /**
+     * @desc 签名者的地址
+     */
+    pub fn address(&self) -> String {
+        let mut pub_key_hash = self.pub_key.clone();
+        hash_pub_key(&mut pub_key_hash);
+
+        address_from_pub_key_hash(&pub_key_hash)
+    }
+
+    pub fn to_hex(&self) -> Result<String> {
+        Ok(encode_hex(&serialize(self)?))
+    }
+
+    pub fn from_hex(hex: &str) -> Result<Attestation> {
+        let data = decode_hex(hex).ok_or_else(|| format_err!("Invalid attestation hex."))?;
+
+        Ok(deserialize(&data)?)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::transaction::TX_VERSION;
+
+    #[test]
+    fn test_anchor_output() {
+        let anchor = Anchor::new(42, &"ab".repeat(32)).unwrap();
+        let mut tx = Transaction {
+            id: String::new(),
+            vin: Vec::new(),
+            vout: vec![
+                TXOutput {
+                    value: 5,
+                    pub_key_hash: vec![1; 20],
+                },
+                anchor.to_output(),
+            ],
+            version: TX_VERSION,
+            relative_lock_time: 0,
+        };
+        assert_eq!(Anchor::from_tx(&tx), Some(anchor.clone()));
+
+        assert!(anchor.check(Some(&"ab".repeat(32))).is_ok());
+        assert!(anchor.check(Some(&"cd".repeat(32))).is_err());
+        assert!(anchor.check(None).is_err());
+
+        tx.vout[1].value = 1;
+        assert_eq!(Anchor::from_tx(&tx), None);
+        assert!(Anchor::new(1, "abc").is_err());
+        assert!(Anchor::new(-1, &"ab".repeat(32)).is_err());
+    }
+
+    #[test]
+    fn test_attestation() {
+        let wallet = Wallet::from_seed(&[1; 32]);
+        let anchor = Anchor::new(7, &"01".repeat(32)).unwrap();
+        let attestation = Attestation::new("regtest", anchor, &wallet).unwrap();
+
+        let decoded = Attestation::from_hex(&attestation.to_hex().unwrap()).unwrap();
+        assert!(decoded.verify_signature().is_ok());
+        assert_eq!(decoded.address(), wallet.get_address());
+
+        let mut forged = decoded;
+        forged.anchor.height = 8;
+        assert!(forged.verify_signature().is_err());
+    }
+}
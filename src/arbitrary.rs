@@ -0,0 +1,101 @@
+// 属性测试工具: 用固定种子生成随机输入，失败时打印种子以便复现
+
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use crate::{
+    block::{Block, BlockHeader},
+    transaction::{TXInput, TXOutput, Transaction},
+};
+
+// 每个属性检查的随机用例数
+pub const CASES: u64 = 128;
+
+/**
+ * @desc 依次用种子 0..CASES 运行属性检查
+ */
+pub fn check<F: FnMut(&mut StdRng)>(mut property: F) {
+    for seed in 0..CASES {
+        let guard = SeedGuard(seed);
+        property(&mut StdRng::seed_from_u64(seed));
+        std::mem::forget(guard);
+    }
+}
+
+// 属性检查 panic 时打印当前种子
+struct SeedGuard(u64);
+
+impl Drop for SeedGuard {
+    fn drop(&mut self) {
+        eprintln!("Property failed with seed {}.", self.0);
+    }
+}
+
+pub fn bytes(rng: &mut StdRng, max_len: usize) -> Vec<u8> {
+    let len = rng.gen_range(0..=max_len);
+    (0..len).map(|_| rng.gen()).collect()
+}
+
+pub fn string(rng: &mut StdRng, max_len: usize) -> String {
+    let len = rng.gen_range(0..=max_len);
+    (0..len).map(|_| rng.gen::<char>()).collect()
+}
+
+pub fn strings(rng: &mut StdRng, max_len: usize) -> Vec<String> {
+    let len = rng.gen_range(0..=max_len);
+    (0..len).map(|_| string(rng, 64)).collect()
+}
+
+pub fn tx_input(rng: &mut StdRng) -> TXInput {
+    TXInput {
+        txid: string(rng, 64),
+        vout: rng.gen(),
+        signature: bytes(rng, 64),
+        pub_key: bytes(rng, 32),
+    }
+}
+
+pub fn tx_output(rng: &mut StdRng) -> TXOutput {
+    TXOutput {
+        value: rng.gen(),
+        pub_key_hash: bytes(rng, 20),
+    }
+}
+
+/**
+ * @desc 随机交易，id 为交易的规范哈希，版本 1 的交易没有相对锁定时间
+ */
+pub fn transaction(rng: &mut StdRng) -> Transaction {
+    let version = rng.gen_range(1..=3);
+    let mut tx = Transaction {
+        id: String::new(),
+        vin: (0..rng.gen_range(0..4)).map(|_| tx_input(rng)).collect(),
+        vout: (0..rng.gen_range(0..4)).map(|_| tx_output(rng)).collect(),
+        version,
+        relative_lock_time: if version == 1 { 0 } else { rng.gen() },
+    };
+    tx.id = tx.hash().unwrap();
+    tx
+}
+
+pub fn header(rng: &mut StdRng) -> BlockHeader {
+    BlockHeader {
+        version: rng.gen(),
+        timestamp: rng.gen(),
+        prev_block_hash: string(rng, 64),
+        hash: string(rng, 64),
+        bits: rng.gen(),
+        nonce: rng.gen(),
+        height: rng.gen(),
+    }
+}
+
+/**
+ * @desc 随机区块，不满足工作量证明
+ */
+pub fn block(rng: &mut StdRng) -> Block {
+    let header = header(rng);
+    Block::from_parts(
+        header,
+        (0..rng.gen_range(0..4)).map(|_| transaction(rng)).collect(),
+    )
+}
@@ -0,0 +1,303 @@
+#[cfg(unix)]
+use std::os::unix::fs::OpenOptionsExt;
+use std::{
+    fs::{self, OpenOptions},
+    io::{ErrorKind, Write},
+    net::IpAddr,
+};
+
+use super::Result;
+use crate::config::Config;
+use failure::format_err;
+use rand_core::{OsRng, RngCore};
+use serde::{Deserialize, Serialize};
+
+// RPC / REST 的认证: 用户名密码、自动生成的 cookie 文件、来源 ip 限制和方法权限等级
+// cookie 文件在网络数据目录中的名称
+pub const COOKIE_FILE: &str = ".cookie";
+pub const COOKIE_USER: &str = "__cookie__";
+
+// 权限等级，高等级包含低等级的权限
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, PartialOrd)]
+pub enum Permission {
+    Public,
+    Wallet,
+    Admin,
+}
+
+impl Permission {
+    pub fn from_name(name: &str) -> Result<Permission> {
+        match name {
+            "public" => Ok(Permission::Public),
+            "wallet" => Ok(Permission::Wallet),
+            "admin" => Ok(Permission::Admin),
+            _ => Err(format_err!("Unknown permission: {}", name)),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct User {
+    name: String,
+    password: String,
+    permission: Permission,
+}
+
+#[derive(Debug, Clone)]
+pub struct RpcAuth {
+    users: Vec<User>,
+    allow_ips: Vec<IpAddr>,
+    // 允许不带凭据访问公开方法
+    public: bool,
+}
+
+impl Default for RpcAuth {
+    fn default() -> RpcAuth {
+        RpcAuth {
+            users: Vec::new(),
+            allow_ips: vec![
+                IpAddr::from([127, 0, 0, 1]),
+                IpAddr::from([0, 0, 0, 0, 0, 0, 0, 1u16]),
+            ],
+            public: false,
+        }
+    }
+}
+
+impl RpcAuth {
+    /**
+     * @desc 从配置的 [rpc] 部分读取认证设置:
+     *   user / password 为管理员，users = ["name:password:public|wallet|admin"] 为其他用户，
+     *   allow_ip 为允许访问的来源 ip(默认只允许本机)，public = true 时匿名请求可以调用公开方法
+     */
+    pub fn from_config(config: &Config) -> Result<RpcAuth> {
+        let mut auth = RpcAuth::default();
+
+        if let (Some(name), Some(password)) =
+            (config.get_str("rpc.user"), config.get_str("rpc.password"))
+        {
+            auth.add_user(&name, &password, Permission::Admin);
+        }
+
+        for entry in config.get_list("rpc.users") {
+            let parts: Vec<&str> = entry.splitn(3, ':').collect();
+            if parts.len() != 3 {
+                return Err(format_err!(
+                    "Invalid rpc user {}, expect name:password:permission.",
+                    entry
+                ));
+            }
+            auth.add_user(parts[0], parts[1], Permission::from_name(parts[2])?);
+        }
+
+        let allow_ips = config.get_list("rpc.allow_ip");
+        if !allow_ips.is_empty() {
+            auth.allow_ips = allow_ips
+                .iter()
+                .map(|ip| ip.parse())
+                .collect::<std::result::Result<_, _>>()?;
+        }
+        auth.public = config.get_bool("rpc.public").unwrap_or(false);
+
+        Ok(auth)
+    }
+
+    fn add_user(&mut self, name: &str, password: &str, permission: Permission) {
+        self.users.push(User {
+            name: name.to_string(),
+            password: password.to_string(),
+            permission,
+        });
+    }
+
+    /**
+     * @desc 生成随机 cookie 并写入 cookie 文件，持有该文件的本机用户拥有管理员权限
+     */
+    pub fn generate_cookie(&mut self, path: &str) -> Result<()> {
+        let mut secret = [0; 32];
+        OsRng.fill_bytes(&mut secret);
+        let password: String = secret.iter().map(|b| format!("{:02x}", b)).collect();
+
+        // 先删除旧文件，新文件创建时就只有所有者可读写，其他本机用户拿不到管理员凭据
+        match fs::remove_file(path) {
+            Err(e) if e.kind() != ErrorKind::NotFound => return Err(e.into()),
+            _ => {}
+        }
+        let mut options = OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        options.mode(0o600);
+        let mut file = options.open(path)?;
+        write!(file, "{}:{}", COOKIE_USER, password)?;
+
+        self.users.retain(|user| user.name != COOKIE_USER);
+        self.add_user(COOKIE_USER, &password, Permission::Admin);
+
+        Ok(())
+    }
+
+    pub fn is_allowed_ip(&self, ip: IpAddr) -> bool {
+        self.allow_ips.contains(&ip)
+    }
+
+    /**
+     * @desc 校验凭据，返回对应的权限；匿名请求在开启 public 时为公开权限
+     */
+    pub fn authenticate(&self, credentials: Option<&(String, String)>) -> Option<Permission> {
+        match credentials {
+            Some((name, password)) => self
+                .users
+                .iter()
+                .find(|user| user.name == *name && constant_time_eq(&user.password, password))
+                .map(|user| user.permission),
+            None if self.public => Some(Permission::Public),
+            None => None,
+        }
+    }
+
+    /**
+     * @desc 来源 ip、凭据和所需权限都满足时返回 Ok
+     */
+    pub fn check(
+        &self,
+        ip: IpAddr,
+        credentials: Option<&(String, String)>,
+        required: Permission,
+    ) -> Result<()> {
+        if !self.is_allowed_ip(ip) {
+            return Err(format_err!("RPC access from {} is not allowed.", ip));
+        }
+
+        match self.authenticate(credentials) {
+            Some(permission) if permission >= required => Ok(()),
+            Some(_) => Err(format_err!("Permission denied.")),
+            None => Err(format_err!("Unauthorized.")),
+        }
+    }
+}
+
+/**
+ * @desc 读取 cookie 文件中的凭据
+ */
+pub fn read_cookie(path: &str) -> Result<(String, String)> {
+    let cookie = fs::read_to_string(path)?;
+    match cookie.trim().split_once(':') {
+        Some((name, password)) => Ok((name.to_string(), password.to_string())),
+        None => Err(format_err!("Invalid cookie file {}.", path)),
+    }
+}
+
+/**
+ * @desc 解析 HTTP Basic 认证头的值(base64 编码的 user:password)
+ */
+pub fn parse_basic_auth(value: &str) -> Option<(String, String)> {
+    let encoded = value.trim().strip_prefix("Basic ")?;
+    let decoded = String::from_utf8(base64_decode(encoded.trim())?).ok()?;
+    let (name, password) = decoded.split_once(':')?;
+
+    Some((name.to_string(), password.to_string()))
+}
+
+fn base64_decode(input: &str) -> Option<Vec<u8>> {
+    let mut out = Vec::new();
+    let mut buffer = 0u32;
+    let mut bits = 0;
+
+    for c in input.bytes().take_while(|c| *c != b'=') {
+        let value = match c {
+            b'A'..=b'Z' => c - b'A',
+            b'a'..=b'z' => c - b'a' + 26,
+            b'0'..=b'9' => c - b'0' + 52,
+            b'+' => 62,
+            b'/' => 63,
+            _ => return None,
+        };
+        buffer = (buffer << 6) | value as u32;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            out.push((buffer >> bits) as u8);
+        }
+    }
+
+    Some(out)
+}
+
+fn constant_time_eq(a: &str, b: &str) -> bool {
+    a.len() == b.len()
+        && a.bytes()
+            .zip(b.bytes())
+            .fold(0, |acc, (x, y)| acc | (x ^ y))
+            == 0
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_rpc_auth() {
+        let config = Config::parse(
+            r#"
+            [rpc]
+            user = "admin"
+            password = "secret"
+            users = ["reader:pw:public"]
+            "#,
+        )
+        .unwrap();
+        let auth = RpcAuth::from_config(&config).unwrap();
+        let local = IpAddr::from([127, 0, 0, 1]);
+        let admin = (String::from("admin"), String::from("secret"));
+        let reader = (String::from("reader"), String::from("pw"));
+        let wrong = (String::from("admin"), String::from("guess"));
+
+        assert!(auth.check(local, Some(&admin), Permission::Admin).is_ok());
+        assert!(auth.check(local, Some(&reader), Permission::Public).is_ok());
+        assert!(auth
+            .check(local, Some(&reader), Permission::Wallet)
+            .is_err());
+        assert!(auth.check(local, Some(&wrong), Permission::Public).is_err());
+        assert!(auth.check(local, None, Permission::Public).is_err());
+        assert!(auth
+            .check(
+                IpAddr::from([10, 0, 0, 1]),
+                Some(&admin),
+                Permission::Public
+            )
+            .is_err());
+
+        assert_eq!(
+            parse_basic_auth("Basic YWRtaW46c2VjcmV0"),
+            Some((String::from("admin"), String::from("secret")))
+        );
+        assert_eq!(parse_basic_auth("Bearer abc"), None);
+    }
+
+    #[test]
+    fn test_generate_cookie() {
+        let path = std::env::temp_dir().join(format!("cookie-{}", std::process::id()));
+        let path = path.to_str().unwrap();
+        fs::write(path, "stale").unwrap();
+
+        let mut auth = RpcAuth::default();
+        auth.generate_cookie(path).unwrap();
+        let cookie = read_cookie(path).unwrap();
+        assert_eq!(cookie.0, COOKIE_USER);
+        assert!(auth
+            .check(
+                IpAddr::from([127, 0, 0, 1]),
+                Some(&cookie),
+                Permission::Admin
+            )
+            .is_ok());
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = fs::metadata(path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+        fs::remove_file(path).ok();
+    }
+}
@@ -0,0 +1,71 @@
+// 上传限速: 令牌桶，每秒补充 rate 字节的额度，最多积累 1 秒的额度；
+// 额度可以透支，透支后的发送需要等待额度补回，长期平均速率不超过 rate
+
+use std::time::{Duration, Instant};
+
+// 限速时每次写入的最大字节数，大消息(区块)分块发送，速率更平稳
+pub const UPLOAD_CHUNK_SIZE: usize = 16 * 1024;
+
+#[derive(Debug, Clone)]
+pub struct UploadLimiter {
+    rate: u64,
+    allowance: f64,
+    last: Instant,
+}
+
+impl UploadLimiter {
+    /**
+     * @desc rate 为每秒允许上传的字节数
+     */
+    pub fn new(rate: u64) -> UploadLimiter {
+        UploadLimiter {
+            rate,
+            allowance: rate as f64,
+            last: Instant::now(),
+        }
+    }
+
+    pub fn rate(&self) -> u64 {
+        self.rate
+    }
+
+    /**
+     * @desc 预留 bytes 字节的额度，返回发送前需要等待的时间
+     */
+    pub fn reserve(&mut self, bytes: usize, now: Instant) -> Duration {
+        let elapsed = now.saturating_duration_since(self.last).as_secs_f64();
+        self.last = now;
+        self.allowance = (self.allowance + elapsed * self.rate as f64).min(self.rate as f64);
+        self.allowance -= bytes as f64;
+
+        if self.allowance >= 0.0 || self.rate == 0 {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64(-self.allowance / self.rate as f64)
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_upload_limiter() {
+        let start = Instant::now();
+        let mut limiter = UploadLimiter::new(1000);
+        limiter.last = start;
+
+        // 积累的 1 秒额度可以直接发送
+        assert_eq!(limiter.reserve(1000, start), Duration::ZERO);
+        // 透支 500 字节，需要等待 0.5 秒
+        assert_eq!(limiter.reserve(500, start), Duration::from_millis(500));
+        // 再透支 500 字节，等待时间累加
+        assert_eq!(limiter.reserve(500, start), Duration::from_secs(1));
+
+        // 空闲很久之后额度最多恢复 1 秒
+        let later = start + Duration::from_secs(10);
+        assert_eq!(limiter.reserve(1000, later), Duration::ZERO);
+        assert_eq!(limiter.reserve(100, later), Duration::from_millis(100));
+    }
+}
@@ -1,43 +1,153 @@
-use std::time::SystemTime;
+use std::{
+    collections::HashMap,
+    time::{Instant, SystemTime},
+};
 
 use super::Result;
-use crate::transaction::*;
-use bincode::serialize;
-use crypto::{digest::Digest, sha2::Sha256};
+use crate::{mining, network, transaction::*};
+use bincode::{serialize, Options};
+use failure::format_err;
 use log::info;
 use merkle_cbt::merkle_tree::{Merge, CBMT};
 use serde::{Deserialize, Serialize};
 
-const TARGET_HEXS: usize = 4;
+const SHORT_ID_LEN: usize = 12;
+// 加入 nBits 之前固定要求区块哈希前 4 个十六进制位为 0，即不大于 0x0001 << 240，旧区块按对应的目标值处理
+const LEGACY_BITS: u32 = 0x1f010000;
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Block {
+    version: i32,
     timestamp: u128,
     transactions: Vec<Transaction>,
     prev_block_hash: String,
     hash: String,
+    // 压缩编码的目标值，区块哈希不能大于目标值
+    bits: u32,
     nonce: i32,
     height: i32,
 }
 
+// 加入交易版本和 nBits 之前的区块格式，用于读取旧数据
+#[derive(Deserialize)]
+struct LegacyBlock {
+    version: i32,
+    timestamp: u128,
+    transactions: Vec<LegacyTransaction>,
+    prev_block_hash: String,
+    hash: String,
+    nonce: i32,
+    height: i32,
+}
+
+impl From<LegacyBlock> for Block {
+    fn from(block: LegacyBlock) -> Block {
+        Block {
+            version: block.version,
+            timestamp: block.timestamp,
+            transactions: block
+                .transactions
+                .into_iter()
+                .map(Transaction::from)
+                .collect(),
+            prev_block_hash: block.prev_block_hash,
+            hash: block.hash,
+            bits: LEGACY_BITS,
+            nonce: block.nonce,
+            height: block.height,
+        }
+    }
+}
+
+// 区块头，不含交易列表
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct BlockHeader {
+    pub version: i32,
+    pub timestamp: u128,
+    pub prev_block_hash: String,
+    pub hash: String,
+    pub bits: u32,
+    pub nonce: i32,
+    pub height: i32,
+}
+
+// 加入 nBits 之前的区块头格式，用于读取旧数据
+#[derive(Deserialize)]
+struct LegacyBlockHeader {
+    version: i32,
+    timestamp: u128,
+    prev_block_hash: String,
+    hash: String,
+    nonce: i32,
+    height: i32,
+}
+
+impl BlockHeader {
+    /**
+     * @desc 从字节解析区块头，不是当前格式时按旧格式解析
+     */
+    pub fn from_bytes(data: &[u8]) -> Result<BlockHeader> {
+        match bincode::deserialize(data) {
+            Ok(header) => Ok(header),
+            Err(e) => match bincode::deserialize::<LegacyBlockHeader>(data) {
+                Ok(header) => Ok(BlockHeader {
+                    version: header.version,
+                    timestamp: header.timestamp,
+                    prev_block_hash: header.prev_block_hash,
+                    hash: header.hash,
+                    bits: LEGACY_BITS,
+                    nonce: header.nonce,
+                    height: header.height,
+                }),
+                Err(_) => Err(e.into()),
+            },
+        }
+    }
+}
+
 impl Block {
     /**
-     * @desc 新建区块
+     * @desc 新建区块，工作量证明满足 bits 表示的目标值
      */
     pub fn new(
         transactions: Vec<Transaction>,
         prev_block_hash: String,
         height: i32,
+        version: i32,
+        bits: u32,
     ) -> Result<Block> {
         let timestamp = SystemTime::now()
             .duration_since(SystemTime::UNIX_EPOCH)?
             .as_millis();
 
+        Block::new_at(
+            transactions,
+            prev_block_hash,
+            height,
+            version,
+            bits,
+            timestamp,
+        )
+    }
+
+    /**
+     * @desc 用指定的时间戳(毫秒)新建区块，相同的参数得到相同的区块
+     */
+    pub fn new_at(
+        transactions: Vec<Transaction>,
+        prev_block_hash: String,
+        height: i32,
+        version: i32,
+        bits: u32,
+        timestamp: u128,
+    ) -> Result<Block> {
         let mut block = Block {
+            version,
             timestamp,
             transactions,
             prev_block_hash,
             hash: String::new(),
+            bits,
             nonce: 0,
             height,
         };
@@ -46,6 +156,83 @@ impl Block {
         Ok(block)
     }
 
+    /**
+     * @desc 新建权益证明区块，由 coinbase 交易的收款人作为出块人
+     */
+    #[cfg(feature = "pos")]
+    pub fn new_staked(
+        transactions: Vec<Transaction>,
+        prev_block_hash: String,
+        height: i32,
+        version: i32,
+        stake: i32,
+    ) -> Result<Block> {
+        let mut block = Block {
+            version,
+            timestamp: 0,
+            transactions,
+            prev_block_hash,
+            hash: String::new(),
+            bits: network::params().pow_limit_bits,
+            nonce: 0,
+            height,
+        };
+
+        block.run_proof_of_stake(stake)?;
+        Ok(block)
+    }
+
+    /**
+     * @desc 直接用给定的区块头和交易构造区块，不做工作量证明，用于测试
+     */
+    #[cfg(test)]
+    pub(crate) fn from_parts(header: BlockHeader, transactions: Vec<Transaction>) -> Block {
+        Block {
+            version: header.version,
+            timestamp: header.timestamp,
+            transactions,
+            prev_block_hash: header.prev_block_hash,
+            hash: header.hash,
+            bits: header.bits,
+            nonce: header.nonce,
+            height: header.height,
+        }
+    }
+
+    /**
+     * @desc 从字节解析区块，长度超过当前网络最大区块大小的数据直接拒绝，不是当前格式时按旧格式解析
+     */
+    pub fn from_bytes(data: &[u8]) -> Result<Block> {
+        let options = bincode::options()
+            .with_fixint_encoding()
+            .allow_trailing_bytes()
+            .with_limit(network::params().max_block_size as u64);
+
+        match options.deserialize(data) {
+            Ok(block) => Ok(block),
+            Err(e) => match options.deserialize::<LegacyBlock>(data) {
+                Ok(block) => Ok(block.into()),
+                Err(_) => Err(e.into()),
+            },
+        }
+    }
+
+    /**
+     * @desc 区块的十六进制编码，用于命令行传递原始区块
+     */
+    pub fn to_hex(&self) -> Result<String> {
+        Ok(encode_hex(&serialize(self)?))
+    }
+
+    /**
+     * @desc 解析十六进制编码的区块
+     */
+    pub fn from_hex(hex: &str) -> Result<Block> {
+        let data = decode_hex(hex).ok_or_else(|| format_err!("Invalid block hex."))?;
+
+        Block::from_bytes(&data)
+    }
+
     /**
      * @desc 获取区块 hash
      */
@@ -67,6 +254,52 @@ impl Block {
         &self.transactions
     }
 
+    /**
+     * @desc 区块 coinbase 交易中的矿工标签，没有标签时返回 None
+     */
+    pub fn coinbase_tag(&self) -> Option<String> {
+        self.transactions
+            .iter()
+            .find(|tx| tx.is_coinbase())
+            .and_then(|tx| tx.coinbase_tag())
+    }
+
+    /**
+     * @desc 获取区块头
+     */
+    pub fn get_header(&self) -> BlockHeader {
+        BlockHeader {
+            version: self.version,
+            timestamp: self.timestamp,
+            prev_block_hash: self.prev_block_hash.clone(),
+            hash: self.hash.clone(),
+            bits: self.bits,
+            nonce: self.nonce,
+            height: self.height,
+        }
+    }
+
+    /**
+     * @desc 获取区块序列化后的大小(字节)
+     */
+    pub fn get_size(&self) -> Result<usize> {
+        Ok(serialize(self)?.len())
+    }
+
+    /**
+     * @desc 获取区块时间戳(毫秒)
+     */
+    pub fn get_timestamp(&self) -> u128 {
+        self.timestamp
+    }
+
+    /**
+     * @desc 获取压缩编码的目标值
+     */
+    pub fn get_bits(&self) -> u32 {
+        self.bits
+    }
+
     /**
      * @desc 获取区块高度(当前区块在区块链中和创世区块之间的块数)
      */
@@ -75,34 +308,166 @@ impl Block {
     }
 
     /**
-     * @desc 执行算法
+     * @desc 转换为紧凑区块(区块头 + 交易短 id)，coinbase 交易直接附带
+     */
+    pub fn to_compact(&self) -> CompactBlock {
+        let mut short_ids = Vec::new();
+        let mut prefilled = Vec::new();
+
+        for (index, tx) in self.transactions.iter().enumerate() {
+            short_ids.push(short_txid(&tx.id));
+            if tx.is_coinbase() {
+                prefilled.push((index, tx.clone()));
+            }
+        }
+
+        CompactBlock {
+            version: self.version,
+            timestamp: self.timestamp,
+            prev_block_hash: self.prev_block_hash.clone(),
+            hash: self.hash.clone(),
+            bits: self.bits,
+            nonce: self.nonce,
+            height: self.height,
+            short_ids,
+            prefilled,
+        }
+    }
+
+    /**
+     * @desc 获取出块人(coinbase 交易收款人)的公钥哈希
+     */
+    #[cfg(feature = "pos")]
+    pub fn get_staker(&self) -> Option<Vec<u8>> {
+        self.transactions
+            .iter()
+            .find(|tx| tx.is_coinbase())
+            .and_then(|tx| tx.vout.first())
+            .map(|out| out.pub_key_hash.clone())
+    }
+
+    /**
+     * @desc 校验权益证明: 区块哈希正确，且出块人的权益满足 kernel 条件
+     */
+    #[cfg(feature = "pos")]
+    pub fn verify_stake(&self, stake: i32) -> Result<bool> {
+        let staker = match self.get_staker() {
+            Some(staker) => staker,
+            None => return Ok(false),
+        };
+
+        if self.compute_hash()? != self.hash {
+            return Ok(false);
+        }
+
+        Ok(crate::pos::check_kernel(
+            &self.prev_block_hash,
+            (self.timestamp / 1000) as u64,
+            &staker,
+            stake,
+        ))
+    }
+
+    /**
+     * @desc 每秒尝试一次 kernel 条件，满足后计算区块哈希
+     */
+    #[cfg(feature = "pos")]
+    fn run_proof_of_stake(&mut self, stake: i32) -> Result<()> {
+        info!("Staking the block.");
+
+        let staker = self
+            .get_staker()
+            .ok_or_else(|| format_err!("Block has no coinbase transaction to stake."))?;
+        if stake <= 0 {
+            return Err(format_err!("Staker has no coins to stake."));
+        }
+
+        loop {
+            let now = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH)?;
+            if crate::pos::check_kernel(&self.prev_block_hash, now.as_secs(), &staker, stake) {
+                self.timestamp = now.as_secs() as u128 * 1000;
+                break;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(
+                1000 - now.subsec_millis() as u64,
+            ));
+        }
+
+        self.hash = self.compute_hash()?;
+
+        Ok(())
+    }
+
+    /**
+     * @desc 执行算法，nonce 用尽时增加 coinbase 交易的 extranonce 并重建 Merkle 树
      */
     fn run_proof_of_work(&mut self) -> Result<()> {
         info!("Mining the block.");
 
-        while !self.validate()? {
-            self.nonce += 1;
+        let start = Instant::now();
+        let mut hashes = 0;
+        if target_from_bits(self.bits).is_none() {
+            return Err(format_err!("Invalid target bits {:#010x}.", self.bits));
         }
 
-        let data = self.prepare_hash_data()?;
-        let mut hasher = Sha256::new();
-        hasher.input(&data[..]);
-        self.hash = hasher.result_str();
+        let mut merkle_root = self.hash_transactions()?;
+        loop {
+            hashes += 1;
+            let hash = self.compute_hash_with(&merkle_root)?;
+            if hash_meets_target(&hash, self.bits) {
+                self.hash = hash;
+                break;
+            }
+
+            if self.nonce == i32::MAX {
+                self.roll_extranonce()?;
+                merkle_root = self.hash_transactions()?;
+                self.nonce = 0;
+            } else {
+                self.nonce += 1;
+            }
+        }
+        mining::record_attempts(hashes, start.elapsed());
 
         Ok(())
     }
 
     /**
-     * @desc 判断当前的哈希值是否满足要求
+     * @desc 增加 coinbase 交易的 extranonce，扩展 nonce 的搜索空间
+     */
+    fn roll_extranonce(&mut self) -> Result<()> {
+        let coinbase = self
+            .transactions
+            .iter_mut()
+            .find(|tx| tx.is_coinbase())
+            .ok_or_else(|| {
+                format_err!("Nonce space exhausted: block has no coinbase transaction.")
+            })?;
+        let extranonce = coinbase.get_extranonce().wrapping_add(1);
+        coinbase.set_extranonce(extranonce)
+    }
+
+    /**
+     * @desc 区块哈希是否满足区块头中的目标值
      */
-    fn validate(&self) -> Result<bool> {
-        let data = self.prepare_hash_data()?;
-        let mut hasher = Sha256::new();
-        hasher.input(&data[..]);
-        let mut vec_tmp = Vec::new();
-        vec_tmp.resize(TARGET_HEXS, '0' as u8);
+    pub fn check_proof_of_work(&self) -> bool {
+        hash_meets_target(&self.hash, self.bits)
+    }
 
-        Ok(&hasher.result_str()[0..TARGET_HEXS] == String::from_utf8(vec_tmp)?)
+    /**
+     * @desc 用当前网络的哈希算法计算区块哈希
+     */
+    fn compute_hash(&self) -> Result<String> {
+        self.compute_hash_with(&self.hash_transactions()?)
+    }
+
+    /**
+     * @desc 用已计算好的 Merkle 根计算区块哈希
+     */
+    fn compute_hash_with(&self, merkle_root: &[u8]) -> Result<String> {
+        let data = self.prepare_hash_data(merkle_root)?;
+
+        Ok(network::params().hash_algorithm.hasher().hash_hex(&data))
     }
 
     /**
@@ -122,12 +487,13 @@ impl Block {
     /**
      * @desc 获取需要被哈希的数据序列值
      */
-    fn prepare_hash_data(&self) -> Result<Vec<u8>> {
+    fn prepare_hash_data(&self, merkle_root: &[u8]) -> Result<Vec<u8>> {
         let content = (
+            self.version,
             self.prev_block_hash.clone(),
-            self.hash_transactions()?,
+            merkle_root.to_vec(),
             self.timestamp,
-            TARGET_HEXS,
+            self.bits,
             self.nonce,
         );
         let bytes = serialize(&content)?;
@@ -136,19 +502,347 @@ impl Block {
     }
 }
 
+// 紧凑区块
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct CompactBlock {
+    version: i32,
+    timestamp: u128,
+    prev_block_hash: String,
+    hash: String,
+    bits: u32,
+    nonce: i32,
+    height: i32,
+    short_ids: Vec<String>,
+    prefilled: Vec<(usize, Transaction)>,
+}
+
+impl CompactBlock {
+    /**
+     * @desc 获取区块 hash
+     */
+    pub fn get_hash(&self) -> String {
+        self.hash.clone()
+    }
+
+    /**
+     * @desc 获取区块头
+     */
+    pub fn get_header(&self) -> BlockHeader {
+        BlockHeader {
+            version: self.version,
+            timestamp: self.timestamp,
+            prev_block_hash: self.prev_block_hash.clone(),
+            hash: self.hash.clone(),
+            bits: self.bits,
+            nonce: self.nonce,
+            height: self.height,
+        }
+    }
+
+    /**
+     * @desc 用附带的交易和交易池中的交易填充各个位置，找不到的位置为 None
+     */
+    pub fn fill_from(&self, mempool: &HashMap<String, Transaction>) -> Vec<Option<Transaction>> {
+        let mut by_short_id = HashMap::new();
+        for tx in mempool.values() {
+            by_short_id.insert(short_txid(&tx.id), tx);
+        }
+
+        let mut slots: Vec<Option<Transaction>> = self
+            .short_ids
+            .iter()
+            .map(|id| by_short_id.get(id).map(|tx| (*tx).clone()))
+            .collect();
+
+        for (index, tx) in &self.prefilled {
+            if *index < slots.len() {
+                slots[*index] = Some(tx.clone());
+            }
+        }
+
+        slots
+    }
+
+    /**
+     * @desc 由完整的交易列表还原区块，并校验区块哈希
+     */
+    pub fn reconstruct(&self, transactions: Vec<Transaction>) -> Result<Block> {
+        if transactions.len() != self.short_ids.len() {
+            return Err(format_err!("Compact block transaction count mismatch."));
+        }
+
+        let block = Block {
+            version: self.version,
+            timestamp: self.timestamp,
+            transactions,
+            prev_block_hash: self.prev_block_hash.clone(),
+            hash: self.hash.clone(),
+            bits: self.bits,
+            nonce: self.nonce,
+            height: self.height,
+        };
+
+        if block.compute_hash()? != block.hash {
+            return Err(format_err!(
+                "Compact block reconstruction failed: hash mismatch."
+            ));
+        }
+
+        Ok(block)
+    }
+}
+
+/**
+ * @desc 把压缩编码 nBits 还原成 256 位目标值(大端)，编码无效或目标值为 0 时返回 None
+ *       和 Bitcoin 相同: 高 8 位是目标值的字节数，低 23 位是最高的 3 个字节，不支持负数
+ */
+pub fn target_from_bits(bits: u32) -> Option<[u8; 32]> {
+    if bits & 0x0080_0000 != 0 {
+        return None;
+    }
+
+    let size = (bits >> 24) as isize;
+    let mut target = [0; 32];
+    for (i, byte) in (bits & 0x007f_ffff).to_be_bytes()[1..].iter().enumerate() {
+        if *byte == 0 {
+            continue;
+        }
+        // 尾数第 i 个字节在目标值中的位置(从最高字节数起)，低于最低字节的部分舍去
+        let pos = 32 + i as isize - size;
+        if pos < 0 {
+            return None;
+        }
+        if pos < 32 {
+            target[pos as usize] = *byte;
+        }
+    }
+
+    if target.iter().all(|byte| *byte == 0) {
+        return None;
+    }
+    Some(target)
+}
+
+/**
+ * @desc 十六进制区块哈希作为 256 位大端整数不大于目标值
+ */
+pub fn hash_meets_target(hash: &str, bits: u32) -> bool {
+    let target = match target_from_bits(bits) {
+        Some(target) => target,
+        None => return false,
+    };
+    if hash.len() != 64 || !hash.is_ascii() {
+        return false;
+    }
+
+    let mut value = [0; 32];
+    for (i, byte) in value.iter_mut().enumerate() {
+        match u8::from_str_radix(&hash[i * 2..i * 2 + 2], 16) {
+            Ok(b) => *byte = b,
+            Err(_) => return false,
+        }
+    }
+
+    value <= target
+}
+
+/**
+ * @desc 单个区块的工作量: 满足目标值平均需要计算的哈希次数，按 2^256 / 目标值计算，超出 u128 时取最大值
+ */
+pub fn block_work(bits: u32) -> u128 {
+    if target_from_bits(bits).is_none() {
+        return 0;
+    }
+
+    // 目标值 = 尾数 * 2^(8 * (字节数 - 3))
+    let size = (bits >> 24) as i32;
+    let mantissa = (bits & 0x007f_ffff) as u128;
+    let shift = 256 - 8 * (size - 3);
+    if shift >= 128 {
+        u128::MAX / mantissa
+    } else if shift < 0 {
+        0
+    } else {
+        (1u128 << shift) / mantissa
+    }
+}
+
+/**
+ * @desc 交易短 id(交易 id 的前缀)
+ */
+pub fn short_txid(txid: &str) -> String {
+    txid.chars().take(SHORT_ID_LEN).collect()
+}
+
 struct MergeVu8 {}
 
 impl Merge for MergeVu8 {
     type Item = Vec<u8>;
 
     fn merge(left: &Self::Item, right: &Self::Item) -> Self::Item {
-        let mut hasher = Sha256::new();
         let mut data: Vec<u8> = left.clone();
         data.append(&mut right.clone());
-        hasher.input(&data);
-        let mut res: [u8; 32] = [0; 32];
-        hasher.result(&mut res);
 
-        res.to_vec()
+        network::params()
+            .hash_algorithm
+            .hasher()
+            .hash(&data)
+            .to_vec()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::arbitrary;
+    use rand_core::OsRng;
+
+    #[test]
+    fn test_block_roundtrip() {
+        arbitrary::check(|rng| {
+            let block = arbitrary::block(rng);
+            let data = serialize(&block).unwrap();
+            let decoded = Block::from_bytes(&data).unwrap();
+
+            assert_eq!(serialize(&decoded).unwrap(), data);
+            assert_eq!(decoded.get_hash(), block.get_hash());
+            assert_eq!(
+                serialize(&decoded.get_header()).unwrap(),
+                serialize(&block.get_header()).unwrap()
+            );
+
+            let hex = block.to_hex().unwrap();
+            assert_eq!(Block::from_hex(&hex).unwrap().to_hex().unwrap(), hex);
+        });
+        assert!(Block::from_hex("0g").is_err());
+    }
+
+    #[test]
+    fn test_legacy_block_decodes() {
+        arbitrary::check(|rng| {
+            let mut block = arbitrary::block(rng);
+            block.bits = LEGACY_BITS;
+            for tx in &mut block.transactions {
+                tx.version = 1;
+                tx.relative_lock_time = 0;
+            }
+
+            let legacy_txs: Vec<_> = block
+                .transactions
+                .iter()
+                .map(|tx| (&tx.id, &tx.vin, &tx.vout))
+                .collect();
+            let legacy = serialize(&(
+                block.version,
+                block.timestamp,
+                legacy_txs,
+                &block.prev_block_hash,
+                &block.hash,
+                block.nonce,
+                block.height,
+            ))
+            .unwrap();
+            let decoded = Block::from_bytes(&legacy).unwrap();
+
+            assert_eq!(serialize(&decoded).unwrap(), serialize(&block).unwrap());
+        });
+    }
+
+    #[test]
+    fn test_compact_block_roundtrip() {
+        arbitrary::check(|rng| {
+            let compact = arbitrary::block(rng).to_compact();
+            let data = serialize(&compact).unwrap();
+            let decoded: CompactBlock = bincode::deserialize(&data).unwrap();
+
+            assert_eq!(serialize(&decoded).unwrap(), data);
+        });
+    }
+
+    #[test]
+    fn test_extranonce_rolls_when_nonce_exhausted() {
+        let address = crate::wallets::Wallet::default().get_address();
+        let coinbase =
+            Transaction::new_coinbase(address, String::from("test"), &mut OsRng).unwrap();
+        let mut block = Block::from_parts(
+            BlockHeader {
+                version: 0,
+                timestamp: 0,
+                prev_block_hash: String::new(),
+                hash: String::new(),
+                bits: LEGACY_BITS,
+                nonce: i32::MAX,
+                height: 1,
+            },
+            vec![coinbase.clone()],
+        );
+
+        block.run_proof_of_work().unwrap();
+
+        let rolled = &block.get_transaction()[0];
+        assert_eq!(rolled.get_extranonce(), 1);
+        assert_ne!(rolled.id, coinbase.id);
+        assert_eq!(block.compute_hash().unwrap(), block.get_hash());
+        assert!(block.check_proof_of_work());
+    }
+
+    #[test]
+    fn test_target_from_bits() {
+        let mut target = [0; 32];
+        target[1] = 0x01;
+        assert_eq!(target_from_bits(LEGACY_BITS), Some(target));
+
+        let mut target = [0; 32];
+        target[4..6].copy_from_slice(&[0xff, 0xff]);
+        assert_eq!(target_from_bits(0x1d00ffff), Some(target));
+
+        // 负数、零和溢出的目标值无效
+        assert_eq!(target_from_bits(0x1f800000), None);
+        assert_eq!(target_from_bits(0x1f000000), None);
+        assert_eq!(target_from_bits(0x21010000), None);
+    }
+
+    #[test]
+    fn test_hash_meets_target() {
+        let limit = format!("0001{}", "0".repeat(60));
+        assert!(hash_meets_target(&limit, LEGACY_BITS));
+        assert!(hash_meets_target(
+            &format!("0000{}", "f".repeat(60)),
+            LEGACY_BITS
+        ));
+        assert!(!hash_meets_target(
+            &format!("0001{}1", "0".repeat(59)),
+            LEGACY_BITS
+        ));
+        assert!(!hash_meets_target("0000", LEGACY_BITS));
+    }
+
+    #[test]
+    fn test_block_work() {
+        // 和加入 nBits 之前每个区块的工作量(16^4)一致
+        assert_eq!(block_work(LEGACY_BITS), 65536);
+        assert_eq!(block_work(0x1e010000), 65536 * 256);
+        assert_eq!(block_work(0x1f800000), 0);
+    }
+
+    #[test]
+    fn test_legacy_header_decodes() {
+        arbitrary::check(|rng| {
+            let header = arbitrary::header(rng);
+            let legacy = serialize(&(
+                header.version,
+                header.timestamp,
+                &header.prev_block_hash,
+                &header.hash,
+                header.nonce,
+                header.height,
+            ))
+            .unwrap();
+            let decoded = BlockHeader::from_bytes(&legacy).unwrap();
+
+            assert_eq!(decoded.bits, LEGACY_BITS);
+            assert_eq!(decoded.hash, header.hash);
+            assert_eq!(decoded.height, header.height);
+        });
     }
 }
@@ -1,18 +1,86 @@
-use std::collections::HashMap;
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{Arc, Mutex, RwLock},
+};
 
 use super::Result;
-use crate::{block::*, transaction::*};
+use crate::{
+    block::*,
+    genesis::GenesisSpec,
+    governance::{self, GovernanceRecord, Tally, VoteEntry},
+    mining,
+    names::{self, NameClaim, NameRecord},
+    network,
+    schema::{self, Migration},
+    sigverify, timedata,
+    transaction::*,
+    utxo_set::UTXO_DB,
+    versionbits,
+};
 use bincode::{deserialize, serialize};
 use failure::format_err;
 use log::{debug, info};
+use serde::{Deserialize, Serialize};
 use sled;
 
-const GENESIS_COINBASE_DATA: &str = "The Rust is so hard, 淦~~";
+// 区块数据库在网络数据目录中的名称
+const BLOCKS_DB: &str = "blocks";
+const HEADERS_TREE: &str = "headers";
+const CHAIN_WORK_TREE: &str = "chain_work";
+const MAIN_CHAIN_TREE: &str = "main_chain";
+const TX_INDEX_TREE: &str = "tx_index";
+const INVALID_TREE: &str = "invalid_blocks";
+const NAMES_TREE: &str = "names";
+const VOTES_TREE: &str = "votes";
+// 区块和区块索引(区块头、累计工作量、主链、交易索引、名称索引、投票索引)的格式版本
+const BLOCKS_SCHEMA: &str = "blocks";
+const BLOCKS_VERSION: u32 = 1;
+const INDEXES_SCHEMA: &str = "indexes";
+const INDEXES_VERSION: u32 = 3;
+// 计算中位时间(MTP)使用的区块数
+const MEDIAN_TIME_SPAN: usize = 11;
+// 区块时间戳最多可以超前网络调整时间 2 小时(毫秒)
+const MAX_FUTURE_BLOCK_TIME: u128 = 2 * 60 * 60 * 1000;
 
-#[derive(Debug)]
+// 区块链句柄，clone 开销很小，所有 clone 共享同一条链
+// 查询可以并发进行，修改链的操作(存储区块、切换最新区块)由 update 锁串行化
+#[derive(Debug, Clone)]
 pub struct Blockchain {
-    tip: String,
+    tip: Arc<RwLock<String>>,
+    update: Arc<Mutex<()>>,
     db: sled::Db,
+    // 区块头单独保存，只需要区块头的操作不必反序列化整个区块
+    headers: sled::Tree,
+    // 每个区块的累计工作量，用于选择工作量最大的链
+    chain_work: sled::Tree,
+    // 主链高度 -> 区块 hash
+    main_chain: sled::Tree,
+    // 交易 id -> 包含该交易的区块 hash 列表(可能包含分叉上的区块)
+    tx_index: sled::Tree,
+    // 满足工作量证明但内容无效的区块 hash -> 区块头
+    invalid: sled::Tree,
+    // 名称 -> 注册列表(可能包含分叉上的区块)，同一区块中的注册按交易顺序排列
+    names: sled::Tree,
+    // 提案 txid -> 投票列表(可能包含分叉上的区块)
+    votes: sled::Tree,
+}
+
+// 链端状态: 主链、有效分叉、无效分叉、只有区块头
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum ChainTipStatus {
+    Active,
+    ValidFork,
+    Invalid,
+    HeadersOnly,
+}
+
+// 没有后续区块的区块，branch_len 为分叉点到链端的区块数，主链为 0
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ChainTip {
+    pub hash: String,
+    pub height: i32,
+    pub branch_len: i32,
+    pub status: ChainTipStatus,
 }
 
 pub struct BlockchainIterator<'a> {
@@ -20,6 +88,13 @@ pub struct BlockchainIterator<'a> {
     bc: &'a Blockchain,
 }
 
+// 按高度升序遍历主链上 [height, end) 范围内的区块
+pub struct BlockchainForwardIterator<'a> {
+    height: i32,
+    end: i32,
+    bc: &'a Blockchain,
+}
+
 impl Blockchain {
     /**
      * @desc 创建区块
@@ -27,7 +102,7 @@ impl Blockchain {
     pub fn new() -> Result<Blockchain> {
         info!("Open blockchain...");
 
-        let db = sled::open("data/blocks")?;
+        let db = sled::open(network::data_path(BLOCKS_DB))?;
         let hash = match db.get("LAST")? {
             Some(last) => last.to_vec(),
             None => Vec::new(),
@@ -35,76 +110,240 @@ impl Blockchain {
 
         info!("Found block database.");
 
+        // 创世配置中自定义的网络标识
+        if let Some(magic) = db.get("MAGIC")? {
+            let magic: [u8; 4] = magic
+                .as_ref()
+                .try_into()
+                .map_err(|_| format_err!("Invalid network magic in block database."))?;
+            network::set_magic(Some(magic));
+        }
+
         let last_hash = if hash.is_empty() {
             String::new()
         } else {
             String::from_utf8(hash.to_vec())?
         };
 
-        Ok(Blockchain { tip: last_hash, db })
+        Blockchain::open(db, last_hash)
+    }
+
+    fn open(db: sled::Db, tip: String) -> Result<Blockchain> {
+        let bc = Blockchain {
+            tip: Arc::new(RwLock::new(tip)),
+            update: Arc::new(Mutex::new(())),
+            headers: db.open_tree(HEADERS_TREE)?,
+            chain_work: db.open_tree(CHAIN_WORK_TREE)?,
+            main_chain: db.open_tree(MAIN_CHAIN_TREE)?,
+            tx_index: db.open_tree(TX_INDEX_TREE)?,
+            invalid: db.open_tree(INVALID_TREE)?,
+            names: db.open_tree(NAMES_TREE)?,
+            votes: db.open_tree(VOTES_TREE)?,
+            db,
+        };
+        bc.upgrade()?;
+
+        Ok(bc)
+    }
+
+    /**
+     * @desc 把旧版本的区块数据库升级到当前格式，没有区块的数据库直接记录为当前版本
+     */
+    fn upgrade(&self) -> Result<()> {
+        let fresh = self.get_tip_hash().is_empty();
+
+        schema::upgrade(&self.db, BLOCKS_SCHEMA, BLOCKS_VERSION, fresh, &[])?;
+        schema::upgrade(
+            &self.db,
+            INDEXES_SCHEMA,
+            INDEXES_VERSION,
+            fresh,
+            &[
+                Migration {
+                    from: 0,
+                    description: "index block headers, chain work, main chain and transactions",
+                    run: Box::new(|_| self.index_headers()),
+                },
+                Migration {
+                    from: 1,
+                    description: "index name registrations",
+                    run: Box::new(|_| self.index_all_blocks(|block| self.index_names(block))),
+                },
+                Migration {
+                    from: 2,
+                    description: "index governance votes",
+                    run: Box::new(|_| self.index_all_blocks(|block| self.index_votes(block))),
+                },
+            ],
+        )
     }
 
     /**
-     * @desc 创建区块链
+     * @desc 按创世配置创建区块链，相同的配置得到相同的创世区块
+     *       已有区块链时拒绝创建，force 时先删除已有的区块链
      */
-    pub fn create_blockchain(address: String) -> Result<Blockchain> {
+    pub fn create_blockchain(spec: &GenesisSpec, force: bool) -> Result<Blockchain> {
         info!("Creating new blockchain.");
 
-        std::fs::remove_dir_all("data/blocks").ok();
-        let db = sled::open("data/blocks")?;
+        let genesis_block = spec.build()?;
+
+        if Blockchain::exists()? {
+            if !force {
+                return Err(format_err!(
+                    "A blockchain already exists in {}, use --force to replace it.",
+                    network::data_path(BLOCKS_DB)
+                ));
+            }
+            Blockchain::wipe()?;
+        }
+        std::fs::remove_dir_all(network::data_path(BLOCKS_DB)).ok();
+        let db = sled::open(network::data_path(BLOCKS_DB))?;
 
         debug!("Creating new block database...");
 
-        let cbtx = Transaction::new_coinbase(address, String::from(GENESIS_COINBASE_DATA))?;
-        let genesis_block = Block::new(vec![cbtx], String::new(), 0).unwrap();
-        db.insert(genesis_block.get_hash(), serialize(&genesis_block)?)?;
-        db.insert("LAST", genesis_block.get_hash().as_bytes())?;
+        if let Some(magic) = spec.magic {
+            db.insert("MAGIC", &magic)?;
+        }
+        network::set_magic(spec.magic);
 
-        let bc = Blockchain {
-            tip: genesis_block.get_hash(),
-            db,
-        };
-        bc.db.flush()?;
+        let bc = Blockchain::open(db, String::new())?;
+        bc.store_block(&genesis_block)?;
+        bc.set_tip(&genesis_block.get_hash())?;
 
         Ok(bc)
     }
 
     /**
-     * @desc 使用提供的交易挖掘新块
+     * @desc 数据目录中是否已有区块链
      */
-    pub fn mine_block(&mut self, transactions: Vec<Transaction>) -> Result<Block> {
-        info!("A new block.");
+    pub fn exists() -> Result<bool> {
+        let path = network::data_path(BLOCKS_DB);
+        if !std::path::Path::new(&path).exists() {
+            return Ok(false);
+        }
+
+        Ok(sled::open(path)?.contains_key("LAST")?)
+    }
 
-        for tx in &transactions {
-            if !self.verify_transaction(tx)? {
-                return Err(format_err!("ERROR: Invalid transaction."));
+    /**
+     * @desc 删除区块链和由它生成的 UTXO 集，钱包不受影响
+     */
+    pub fn wipe() -> Result<()> {
+        info!("Wiping blockchain.");
+
+        for db in [BLOCKS_DB, UTXO_DB] {
+            match std::fs::remove_dir_all(network::data_path(db)) {
+                Ok(()) => {}
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+                Err(e) => return Err(e.into()),
             }
         }
 
+        Ok(())
+    }
+
+    /**
+     * @desc 在临时数据库中用给定的创世区块创建区块链，供测试使用
+     */
+    #[cfg(test)]
+    pub fn create_temporary(genesis: &Block) -> Result<Blockchain> {
+        let db = sled::Config::new().temporary(true).open()?;
+
+        let bc = Blockchain::open(db, String::new())?;
+        bc.store_block(genesis)?;
+        bc.set_tip(&genesis.get_hash())?;
+
+        Ok(bc)
+    }
+
+    /**
+     * @desc 使用提供的交易挖掘新块，挖矿期间最新区块发生变化时放弃这个区块
+     */
+    pub fn mine_block(&self, transactions: Vec<Transaction>) -> Result<Block> {
+        info!("A new block.");
+
+        if !self
+            .verify_transactions(&transactions)?
+            .iter()
+            .all(|valid| *valid)
+        {
+            return Err(format_err!("ERROR: Invalid transaction."));
+        }
+
+        mining::record_template(transactions.len());
+        self.check_transaction_rules(&transactions, self.get_best_height()? + 1)?;
+        self.check_record_rules(
+            &transactions,
+            &self.get_tip_hash(),
+            self.get_best_height()? + 1,
+        )?;
+
         let last_hash = self.db.get("LAST")?.unwrap();
+        let version =
+            versionbits::compute_block_version(self, &String::from_utf8(last_hash.to_vec())?)?;
+
+        #[cfg(not(feature = "pos"))]
         let new_block = Block::new(
             transactions,
             String::from_utf8(last_hash.to_vec())?,
             self.get_best_height()? + 1,
+            version,
+            self.get_next_work_required(&String::from_utf8(last_hash.to_vec())?),
         )?;
 
-        self.db
-            .insert(new_block.get_hash(), serialize(&new_block)?)?;
-        self.db.insert("LAST", new_block.get_hash().as_bytes())?;
-        self.db.flush()?;
+        #[cfg(feature = "pos")]
+        let new_block = {
+            let stake = match transactions.iter().find(|tx| tx.is_coinbase()) {
+                Some(cbtx) => self.get_stake(&cbtx.vout[0].pub_key_hash),
+                None => 0,
+            };
+            Block::new_staked(
+                transactions,
+                String::from_utf8(last_hash.to_vec())?,
+                self.get_best_height()? + 1,
+                version,
+                stake,
+            )?
+        };
+        self.check_block_size(&new_block)?;
+        self.check_block_time(&new_block)?;
 
-        self.tip = new_block.get_hash();
+        let _update = self.update.lock().unwrap();
+        if self.get_tip_hash() != new_block.get_prev_hash() {
+            return Err(format_err!("ERROR: Chain tip changed while mining."));
+        }
+        self.store_block(&new_block)?;
+        self.set_tip(&new_block.get_hash())?;
+        mining::record_block();
 
         Ok(new_block)
     }
 
     pub fn iter(&self) -> BlockchainIterator {
         BlockchainIterator {
-            current_hash: self.tip.clone(),
+            current_hash: self.get_tip_hash(),
             bc: &self,
         }
     }
 
+    /**
+     * @desc 从创世区块开始按高度升序遍历主链，一次只加载一个区块
+     */
+    pub fn iter_forward(&self) -> BlockchainForwardIterator<'_> {
+        self.iter_range(0, i32::MAX)
+    }
+
+    /**
+     * @desc 按高度升序遍历主链上高度在 [start, end) 范围内的区块
+     */
+    pub fn iter_range(&self, start: i32, end: i32) -> BlockchainForwardIterator<'_> {
+        BlockchainForwardIterator {
+            height: start,
+            end,
+            bc: self,
+        }
+    }
+
     /**
      * @desc 获取所有未花费交易输出
      */
@@ -154,12 +393,83 @@ impl Blockchain {
         utxos
     }
 
+    /**
+     * @desc 获取公钥哈希在当前链上持有的未花费输出总额(权益)
+     */
+    #[cfg(feature = "pos")]
+    pub fn get_stake(&self, pub_key_hash: &[u8]) -> i32 {
+        self.find_utxo()
+            .values()
+            .flat_map(|outs| outs.outputs.iter())
+            .filter(|out| out.is_locked_with_key(pub_key_hash))
+            .map(|out| out.value)
+            .sum()
+    }
+
+    /**
+     * @desc 获取主链上所有已花费的输出(交易 id -> 输出序号)
+     */
+    pub fn find_spent_outputs(&self) -> HashMap<String, Vec<i32>> {
+        let mut spent: HashMap<String, Vec<i32>> = HashMap::new();
+
+        for block in self.iter() {
+            for tx in block.get_transaction() {
+                if tx.is_coinbase() {
+                    continue;
+                }
+                for vin in &tx.vin {
+                    spent.entry(vin.txid.clone()).or_default().push(vin.vout);
+                }
+            }
+        }
+
+        spent
+    }
+
+    /**
+     * @desc 未确认的交易与主链上哪些交易花费了相同的输出(txid -> 冲突的交易 id)，
+     *       只包含有冲突的交易，这些交易已经不可能再被确认
+     */
+    pub fn find_conflicts(&self, txs: &[Transaction]) -> Result<HashMap<String, Vec<String>>> {
+        let mut unconfirmed = Vec::new();
+        for tx in txs {
+            if self.find_transaction_block(&tx.id)?.is_none() {
+                unconfirmed.push(tx);
+            }
+        }
+
+        let mut conflicts: HashMap<String, Vec<String>> = HashMap::new();
+        for block in self.iter() {
+            for confirmed in block.get_transaction() {
+                if confirmed.is_coinbase() {
+                    continue;
+                }
+                for tx in &unconfirmed {
+                    let conflicted = confirmed.vin.iter().any(|o| {
+                        tx.vin
+                            .iter()
+                            .any(|vin| vin.txid == o.txid && vin.vout == o.vout)
+                    });
+                    if conflicted {
+                        conflicts
+                            .entry(tx.id.clone())
+                            .or_default()
+                            .push(confirmed.id.clone());
+                    }
+                }
+            }
+        }
+
+        Ok(conflicts)
+    }
+
     /**
      * @desc 通过 id 获取交易
      */
     pub fn find_transaction(&self, id: &str) -> Result<Transaction> {
-        for b in self.iter() {
-            for tx in b.get_transaction() {
+        if let Some(block_hash) = self.find_transaction_block(id)? {
+            let block = self.get_block(&block_hash)?;
+            for tx in block.get_transaction() {
                 if tx.id == id {
                     return Ok(tx.clone());
                 }
@@ -170,7 +480,38 @@ impl Blockchain {
     }
 
     /**
-     * @desc 验证交易签名
+     * @desc 通过交易索引查找主链上包含该交易的区块
+     */
+    pub fn find_transaction_block(&self, id: &str) -> Result<Option<String>> {
+        let block_hashes: Vec<String> = match self.tx_index.get(id)? {
+            Some(data) => deserialize(&data)?,
+            None => return Ok(None),
+        };
+
+        for hash in block_hashes {
+            if self.is_in_main_chain(&hash)? {
+                return Ok(Some(hash));
+            }
+        }
+
+        Ok(None)
+    }
+
+    /**
+     * @desc 交易的确认数: 主链上包含交易的区块到最新区块的块数，不在主链上时为 0
+     */
+    pub fn get_confirmations(&self, id: &str) -> Result<i32> {
+        match self.find_transaction_block(id)? {
+            Some(hash) => {
+                let height = self.get_header(&hash)?.height;
+                Ok(self.get_best_height()? - height + 1)
+            }
+            None => Ok(0),
+        }
+    }
+
+    /**
+     * @desc 验证交易签名，验证通过的签名进入签名缓存
      */
     pub fn verify_transaction(&self, tx: &Transaction) -> Result<bool> {
         if tx.is_coinbase() {
@@ -178,111 +519,1771 @@ impl Blockchain {
         }
 
         let prev_txs = self.get_prev_txs(tx)?;
-        tx.verify(prev_txs)
+        Ok(sigverify::verify_transactions(std::slice::from_ref(tx), &prev_txs, true)[0])
     }
 
     /**
-     * @desc 对交易的输入进行签名
+     * @desc 批量验证交易签名，返回每笔交易是否有效，前序交易不在链上的交易视为无效
      */
-    pub fn sign_transaction(&self, tx: &mut Transaction, private_key: &[u8]) -> Result<()> {
-        let prev_txs = self.get_prev_txs(tx)?;
-        tx.sign(private_key, prev_txs)?;
+    pub fn verify_transactions(&self, txs: &[Transaction]) -> Result<Vec<bool>> {
+        let prev_txs = self.collect_prev_txs(txs, false)?;
+        Ok(sigverify::verify_transactions(txs, &prev_txs, false))
+    }
+
+    /**
+     * @desc 验证区块中全部交易的签名，输入可以引用同一区块中的交易，发现无效签名后立即停止
+     */
+    pub fn verify_block_transactions(&self, block: &Block) -> Result<bool> {
+        let txs = block.get_transaction();
+        let prev_txs = self.collect_prev_txs(txs, true)?;
+
+        Ok(sigverify::verify_transactions(txs, &prev_txs, true)
+            .iter()
+            .all(|valid| *valid))
+    }
+
+    /**
+     * @desc 检查交易的版本规则、coinbase 的输出和相对锁定时间，height 为交易所在区块的高度
+     */
+    pub fn check_transaction_rules(&self, txs: &[Transaction], height: i32) -> Result<()> {
+        for tx in txs {
+            tx.check_version()?;
+            // 创世区块的初始分配不受出块奖励限制
+            if tx.is_coinbase() && height > 0 {
+                tx.check_coinbase_outputs()?;
+            }
+            if !self.check_relative_lock(tx, height)? {
+                return Err(format_err!(
+                    "ERROR: Transaction {} is locked by relative lock time {}.",
+                    tx.id,
+                    tx.relative_lock_time
+                ));
+            }
+        }
 
         Ok(())
     }
 
     /**
-     * @desc 获取前一笔交易
+     * @desc 检查交易中的名称注册和投票，prev_block_hash 为交易所在区块的父区块
      */
-    fn get_prev_txs(&self, tx: &Transaction) -> Result<HashMap<String, Transaction>> {
-        let mut prev_txs = HashMap::new();
+    pub fn check_record_rules(
+        &self,
+        txs: &[Transaction],
+        prev_block_hash: &str,
+        height: i32,
+    ) -> Result<()> {
+        self.check_name_claims(txs, prev_block_hash, height)?;
+        self.check_votes(txs, prev_block_hash, height)
+    }
 
-        for vin in &tx.vin {
-            let prev_tx = self.find_transaction(&vin.txid)?;
-            prev_txs.insert(prev_tx.id.clone(), prev_tx);
+    /**
+     * @desc 检查交易中的名称注册，prev_block_hash 为交易所在区块的父区块，
+     *       同一批交易中后面的注册要遵守前面注册的结果
+     */
+    pub fn check_name_claims(
+        &self,
+        txs: &[Transaction],
+        prev_block_hash: &str,
+        height: i32,
+    ) -> Result<()> {
+        let mut claimed: HashMap<String, NameClaim> = HashMap::new();
+        for tx in txs {
+            let record = match NameRecord::from_tx(tx)? {
+                Some(record) => record,
+                None => continue,
+            };
+            let current = match claimed.get(&record.name) {
+                Some(claim) => Some(claim.clone()),
+                None => self.get_name_claim(&record.name, prev_block_hash)?,
+            };
+            names::check_claim(tx, &record, current.as_ref(), height)?;
+
+            claimed.insert(
+                record.name,
+                NameClaim {
+                    block_hash: String::new(),
+                    height,
+                    txid: tx.id.clone(),
+                    pub_key_hash: record.pub_key_hash,
+                },
+            );
         }
 
-        Ok(prev_txs)
+        Ok(())
     }
 
     /**
-     * @desc 添加区块
+     * @desc 以 block_hash 为链端的链上名称最近的一次注册(可能已过期)
      */
-    pub fn add_block(&mut self, block: Block) -> Result<()> {
-        let data = serialize(&block)?;
-        if let Some(_) = self.db.get(block.get_hash())? {
-            return Ok(());
-        }
-        self.db.insert(block.get_hash(), data)?;
+    pub fn get_name_claim(&self, name: &str, block_hash: &str) -> Result<Option<NameClaim>> {
+        let claims: Vec<NameClaim> = match self.names.get(name)? {
+            Some(data) => deserialize(&data)?,
+            None => return Ok(None),
+        };
 
-        let last_height = self.get_best_height()?;
-        if block.get_height() > last_height {
-            self.db.insert("LAST", block.get_hash().as_bytes())?;
-            self.tip = block.get_hash();
-            self.db.flush()?;
+        let mut latest: Option<NameClaim> = None;
+        for claim in claims {
+            if latest
+                .as_ref()
+                .is_some_and(|latest| latest.height > claim.height)
+            {
+                continue;
+            }
+            if self.is_ancestor(&claim.block_hash, block_hash)? {
+                latest = Some(claim);
+            }
         }
 
-        Ok(())
+        Ok(latest)
     }
 
     /**
-     * @desc 通过 hash 获取区块
+     * @desc 查询名称当前映射的地址，没有注册或注册已过期时为 None
      */
-    pub fn get_block(&self, block_hash: &str) -> Result<Block> {
-        let data = self.db.get(block_hash)?.unwrap();
-        let block = deserialize(&data.to_vec())?;
+    pub fn resolve_name(&self, name: &str) -> Result<Option<NameClaim>> {
+        names::check_name(name)?;
 
-        Ok(block)
+        let next_height = self.get_best_height()? + 1;
+        Ok(self
+            .get_name_claim(name, &self.get_tip_hash())?
+            .filter(|claim| next_height < claim.expires_at()))
     }
 
     /**
-     * @desc 获取最后一个区块的高度
+     * @desc 检查交易中的投票: 提案在以 prev_block_hash 为链端的链上，且 height 在投票窗口内
      */
-    pub fn get_best_height(&self) -> Result<i32> {
-        let last_hash = if let Some(height) = self.db.get("LAST")? {
-            height
-        } else {
-            return Ok(-1);
+    pub fn check_votes(
+        &self,
+        txs: &[Transaction],
+        prev_block_hash: &str,
+        height: i32,
+    ) -> Result<()> {
+        for tx in txs {
+            let proposal = match GovernanceRecord::from_tx(tx)? {
+                Some(GovernanceRecord::Vote { proposal, .. }) => proposal,
+                _ => continue,
+            };
+            let proposal_height = self
+                .get_proposal_height(&proposal, prev_block_hash)?
+                .ok_or_else(|| {
+                    format_err!(
+                        "ERROR: Transaction {} votes on unknown proposal {}.",
+                        tx.id,
+                        proposal
+                    )
+                })?;
+            if height > governance::voting_end(proposal_height) {
+                return Err(format_err!(
+                    "ERROR: Voting on proposal {} ended at height {}.",
+                    proposal,
+                    governance::voting_end(proposal_height)
+                ));
+            }
+        }
+
+        Ok(())
+    }
+
+    // 提案在以 block_hash 为链端的链上所在区块的高度，交易不在链上或不是提案时为 None
+    fn get_proposal_height(&self, proposal: &str, block_hash: &str) -> Result<Option<i32>> {
+        let block_hashes: Vec<String> = match self.tx_index.get(proposal)? {
+            Some(data) => deserialize(&data)?,
+            None => return Ok(None),
         };
 
-        let last_data = self.db.get(last_hash)?.unwrap();
-        let last_block: Block = deserialize(&last_data.to_vec())?;
+        for hash in block_hashes {
+            if !self.is_ancestor(&hash, block_hash)? {
+                continue;
+            }
+            let block = self.get_block(&hash)?;
+            let tx = block.get_transaction().iter().find(|tx| tx.id == proposal);
+            return match tx.map(GovernanceRecord::from_tx).transpose()?.flatten() {
+                Some(GovernanceRecord::Proposal { .. }) => Ok(Some(block.get_height())),
+                _ => Ok(None),
+            };
+        }
 
-        Ok(last_block.get_height())
+        Ok(None)
     }
 
     /**
-     * @desc 获取所有区块的哈希
+     * @desc 统计主链上提案的投票，每个投票人只计最后一次投票，权重是投票人在提案高度的余额
      */
-    pub fn get_block_hashes(&self) -> Vec<String> {
-        let mut list = Vec::new();
+    pub fn tally_proposal(&self, proposal: &str) -> Result<Tally> {
+        let not_found = || format_err!("Proposal {} is not found.", proposal);
+        let block_hash = self
+            .find_transaction_block(proposal)?
+            .ok_or_else(not_found)?;
+        let block = self.get_block(&block_hash)?;
+        let height = block.get_height();
+        let tx = block.get_transaction().iter().find(|tx| tx.id == proposal);
+        let title = match tx.map(GovernanceRecord::from_tx).transpose()?.flatten() {
+            Some(GovernanceRecord::Proposal { title }) => title,
+            _ => return Err(not_found()),
+        };
 
-        for b in self.iter() {
-            list.push(b.get_hash());
+        let mut votes: Vec<VoteEntry> = Vec::new();
+        if let Some(data) = self.votes.get(proposal)? {
+            for vote in deserialize::<Vec<VoteEntry>>(&data)? {
+                if self.is_in_main_chain(&vote.block_hash)? {
+                    votes.push(vote);
+                }
+            }
+        }
+        votes.sort_by_key(|vote| vote.height);
+        let mut choices: HashMap<Vec<u8>, bool> = HashMap::new();
+        for vote in votes {
+            choices.insert(vote.voter, vote.approve);
         }
 
-        list
-    }
-}
+        let balances = self.get_balances_at(height, &choices.keys().cloned().collect())?;
+        let (mut yes, mut no) = (0, 0);
+        for (voter, approve) in &choices {
+            let weight = balances.get(voter).copied().unwrap_or(0);
+            if *approve {
+                yes += weight;
+            } else {
+                no += weight;
+            }
+        }
 
-impl<'a> Iterator for BlockchainIterator<'a> {
-    type Item = Block;
+        let end_height = governance::voting_end(height);
+        Ok(Tally {
+            proposal: proposal.to_string(),
+            title,
+            height,
+            end_height,
+            yes,
+            no,
+            voters: choices.len(),
+            closed: self.get_best_height()? >= end_height,
+        })
+    }
 
-    fn next(&mut self) -> Option<Self::Item> {
-        if let Ok(encoded_block) = self.bc.db.get(&self.current_hash) {
-            return match encoded_block {
-                Some(b) => {
-                    if let Ok(block) = deserialize::<Block>(&b) {
-                        self.current_hash = block.get_prev_hash();
-                        Some(block)
-                    } else {
-                        None
+    // 主链上高度不超过 height 时各公钥哈希的未花费输出总额
+    fn get_balances_at(
+        &self,
+        height: i32,
+        pub_key_hashes: &HashSet<Vec<u8>>,
+    ) -> Result<HashMap<Vec<u8>, i64>> {
+        let mut outputs: HashMap<(String, i32), (Vec<u8>, i32)> = HashMap::new();
+        for block in self.iter_range(0, height + 1) {
+            for tx in block.get_transaction() {
+                if !tx.is_coinbase() {
+                    for vin in &tx.vin {
+                        outputs.remove(&(vin.txid.clone(), vin.vout));
                     }
                 }
-                None => None,
-            };
+                for (index, out) in tx.vout.iter().enumerate() {
+                    if pub_key_hashes.contains(&out.pub_key_hash) {
+                        outputs.insert(
+                            (tx.id.clone(), index as i32),
+                            (out.pub_key_hash.clone(), out.value),
+                        );
+                    }
+                }
+            }
         }
 
-        None
+        let mut balances = HashMap::new();
+        for (pub_key_hash, value) in outputs.into_values() {
+            *balances.entry(pub_key_hash).or_insert(0) += value as i64;
+        }
+
+        Ok(balances)
+    }
+
+    // ancestor 是否是 block_hash 或它的祖先区块
+    fn is_ancestor(&self, ancestor: &str, block_hash: &str) -> Result<bool> {
+        if block_hash.is_empty() {
+            return Ok(false);
+        }
+        let height = self.get_header(ancestor)?.height;
+        if self.is_in_main_chain(ancestor)? && self.is_in_main_chain(block_hash)? {
+            return Ok(self.get_header(block_hash)?.height >= height);
+        }
+
+        let mut header = self.get_header(block_hash)?;
+        while header.height > height {
+            header = self.get_header(&header.prev_block_hash)?;
+        }
+
+        Ok(header.hash == ancestor)
+    }
+
+    // 输入引用的交易在 height 时至少被确认 relative_lock_time 个区块，同一区块中的前序交易确认数为 0
+    fn check_relative_lock(&self, tx: &Transaction, height: i32) -> Result<bool> {
+        if tx.version < TX_VERSION_RELATIVE_LOCK || tx.relative_lock_time == 0 || tx.is_coinbase() {
+            return Ok(true);
+        }
+
+        for vin in &tx.vin {
+            let prev_height = self.get_transaction_height(&vin.txid)?.unwrap_or(height);
+            if ((height - prev_height) as i64) < tx.relative_lock_time as i64 {
+                return Ok(false);
+            }
+        }
+
+        Ok(true)
+    }
+
+    // 包含交易的区块高度，优先取主链上的区块，只在分叉上时取最高的区块
+    fn get_transaction_height(&self, id: &str) -> Result<Option<i32>> {
+        if let Some(hash) = self.find_transaction_block(id)? {
+            return Ok(Some(self.get_header(&hash)?.height));
+        }
+
+        let block_hashes: Vec<String> = match self.tx_index.get(id)? {
+            Some(data) => deserialize(&data)?,
+            None => return Ok(None),
+        };
+        let mut height = None;
+        for hash in block_hashes {
+            height = height.max(Some(self.get_header(&hash)?.height));
+        }
+
+        Ok(height)
+    }
+
+    // 收集输入引用的前序交易，in_batch 时先在同一批交易中查找，链上包括分叉上的区块
+    fn collect_prev_txs(
+        &self,
+        txs: &[Transaction],
+        in_batch: bool,
+    ) -> Result<HashMap<String, Transaction>> {
+        let batch: HashMap<&str, &Transaction> = if in_batch {
+            txs.iter().map(|tx| (tx.id.as_str(), tx)).collect()
+        } else {
+            HashMap::new()
+        };
+
+        let mut prev_txs = HashMap::new();
+        for tx in txs.iter().filter(|tx| !tx.is_coinbase()) {
+            for vin in &tx.vin {
+                if prev_txs.contains_key(&vin.txid) {
+                    continue;
+                }
+
+                let prev_tx = match batch.get(vin.txid.as_str()) {
+                    Some(prev_tx) => Some((*prev_tx).clone()),
+                    None => self.find_indexed_transaction(&vin.txid)?,
+                };
+                if let Some(prev_tx) = prev_tx {
+                    prev_txs.insert(vin.txid.clone(), prev_tx);
+                }
+            }
+        }
+
+        Ok(prev_txs)
+    }
+
+    // 在交易索引记录的所有区块(包括分叉上的区块)中查找交易
+    fn find_indexed_transaction(&self, id: &str) -> Result<Option<Transaction>> {
+        let block_hashes: Vec<String> = match self.tx_index.get(id)? {
+            Some(data) => deserialize(&data)?,
+            None => return Ok(None),
+        };
+
+        for block_hash in block_hashes {
+            let block = self.get_block(&block_hash)?;
+            if let Some(tx) = block.get_transaction().iter().find(|tx| tx.id == id) {
+                return Ok(Some(tx.clone()));
+            }
+        }
+
+        Ok(None)
+    }
+
+    /**
+     * @desc 获取交易手续费(输入总额 - 输出总额)，coinbase 交易为 0
+     */
+    pub fn get_tx_fee(&self, tx: &Transaction) -> Result<i32> {
+        if tx.is_coinbase() {
+            return Ok(0);
+        }
+
+        tx_fee(tx, &self.get_prev_txs(tx)?)
+    }
+
+    /**
+     * @desc 获取交易费率(每 1000 字节的手续费)，前序交易只在链上查找，
+     *       花费交易池中交易的交易由节点连同交易池一起计算
+     */
+    pub fn get_fee_rate(&self, tx: &Transaction) -> Result<i32> {
+        let fee = self.get_tx_fee(tx)?;
+        let size = tx.get_size()? as i32;
+
+        Ok(fee * 1000 / size.max(1))
+    }
+
+    /**
+     * @desc 获取前一笔交易
+     */
+    fn get_prev_txs(&self, tx: &Transaction) -> Result<HashMap<String, Transaction>> {
+        let mut prev_txs = HashMap::new();
+
+        for vin in &tx.vin {
+            let prev_tx = self.find_transaction(&vin.txid)?;
+            prev_txs.insert(prev_tx.id.clone(), prev_tx);
+        }
+
+        Ok(prev_txs)
+    }
+
+    /**
+     * @desc 添加区块，发生回滚时返回被断开区块中的交易
+     */
+    pub fn add_block(&self, block: Block) -> Result<Vec<Transaction>> {
+        let _update = self.update.lock().unwrap();
+        if self.has_block(&block.get_hash())? {
+            return Ok(Vec::new());
+        }
+
+        #[cfg(feature = "pos")]
+        if block.get_height() > 0 {
+            let stake = match block.get_staker() {
+                Some(staker) => self.get_stake(&staker),
+                None => 0,
+            };
+            if !block.verify_stake(stake)? {
+                return Err(format_err!("ERROR: Invalid proof of stake."));
+            }
+        }
+        #[cfg(not(feature = "pos"))]
+        self.check_block_work(&block.get_header())?;
+        if self.invalid.contains_key(block.get_hash())? {
+            return Err(format_err!(
+                "ERROR: Block {} is known to be invalid.",
+                block.get_hash()
+            ));
+        }
+        self.check_block_time(&block)?;
+        // 父区块未知(孤块或乱序到达)时缺少验证交易所需的上下文，只拒绝不记为无效，父区块到达后还可以重新接收；
+        // 父区块已知时内容无效是共识错误，区块和它的后续区块都记为无效，不再重复验证
+        let checked = if self.invalid.contains_key(block.get_prev_hash())? {
+            Err(format_err!(
+                "ERROR: Block {} builds on an invalid block.",
+                block.get_hash()
+            ))
+        } else {
+            self.check_block_height(&block)?;
+            self.check_block_contents(&block)
+        };
+        if let Err(e) = checked {
+            self.invalid
+                .insert(block.get_hash(), serialize(&block.get_header())?)?;
+            return Err(e);
+        }
+        self.store_block(&block)?;
+
+        // 按累计工作量而不是高度选择主链
+        if self.get_chain_work(&block.get_hash())? <= self.get_chain_work(&self.get_tip_hash())? {
+            return Ok(Vec::new());
+        }
+
+        let disconnected = self.set_tip(&block.get_hash())?;
+        self.get_disconnected_txs(&disconnected)
+    }
+
+    /**
+     * @desc 回滚: 收集被断开的区块中没有进入新主链的交易
+     */
+    fn get_disconnected_txs(&self, disconnected: &[String]) -> Result<Vec<Transaction>> {
+        let mut disconnected_txs = Vec::new();
+        for hash in disconnected.iter().rev() {
+            for tx in self.get_block(hash)?.get_transaction() {
+                if !tx.is_coinbase() && self.find_transaction_block(&tx.id)?.is_none() {
+                    disconnected_txs.push(tx.clone());
+                }
+            }
+        }
+
+        Ok(disconnected_txs)
+    }
+
+    /**
+     * @desc 手动把区块和它的后续区块标记为无效，区块在主链上时切换到剩下工作量最大的链，
+     *       返回被断开区块中的交易
+     */
+    pub fn invalidate_block(&self, block_hash: &str) -> Result<Vec<Transaction>> {
+        let _update = self.update.lock().unwrap();
+        let header = self.get_header(block_hash)?;
+        if header.prev_block_hash.is_empty() {
+            return Err(format_err!("ERROR: Cannot invalidate the genesis block."));
+        }
+
+        for hash in self
+            .get_descendants(block_hash)?
+            .iter()
+            .chain([&header.hash])
+        {
+            self.invalid
+                .insert(hash, serialize(&self.get_header(hash)?)?)?;
+        }
+        info!("Invalidate block {}.", block_hash);
+
+        self.activate_best_chain()
+    }
+
+    /**
+     * @desc 清除区块、它的祖先和后续区块的无效标记，并重新选择工作量最大的链，
+     *       返回被断开区块中的交易
+     */
+    pub fn reconsider_block(&self, block_hash: &str) -> Result<Vec<Transaction>> {
+        let _update = self.update.lock().unwrap();
+        if !self.has_block(block_hash)? && !self.invalid.contains_key(block_hash)? {
+            return Err(format_err!("Block {} is not found.", block_hash));
+        }
+
+        for hash in self.get_descendants(block_hash)? {
+            self.invalid.remove(&hash)?;
+        }
+        let mut hash = block_hash.to_string();
+        while let Some(data) = self.invalid.remove(&hash)? {
+            hash = BlockHeader::from_bytes(&data)?.prev_block_hash;
+        }
+        info!("Reconsider block {}.", block_hash);
+
+        self.activate_best_chain()
+    }
+
+    // 区块的所有后续区块(包括分叉)
+    fn get_descendants(&self, block_hash: &str) -> Result<Vec<String>> {
+        let mut children: HashMap<String, Vec<String>> = HashMap::new();
+        for kv in self.headers.iter().chain(self.invalid.iter()) {
+            let (_, data) = kv?;
+            let header = BlockHeader::from_bytes(&data)?;
+            let siblings = children.entry(header.prev_block_hash).or_default();
+            if !siblings.contains(&header.hash) {
+                siblings.push(header.hash);
+            }
+        }
+
+        let mut descendants = Vec::new();
+        let mut stack = vec![block_hash.to_string()];
+        while let Some(hash) = stack.pop() {
+            for child in children.remove(&hash).unwrap_or_default() {
+                stack.push(child.clone());
+                descendants.push(child);
+            }
+        }
+
+        Ok(descendants)
+    }
+
+    // 在有效并连接到主链的链端中选择累计工作量最大的作为最新区块，工作量相同时保留当前最新区块
+    fn activate_best_chain(&self) -> Result<Vec<Transaction>> {
+        let mut best = self.get_tip_hash();
+        while self.invalid.contains_key(&best)? {
+            best = self.get_header(&best)?.prev_block_hash;
+        }
+
+        for tip in self.get_chain_tips()? {
+            let connected = self.get_branch_len(&self.get_header(&tip.hash)?)?.1;
+            if tip.status == ChainTipStatus::ValidFork
+                && connected
+                && self.get_chain_work(&tip.hash)? > self.get_chain_work(&best)?
+            {
+                best = tip.hash;
+            }
+        }
+
+        if best == self.get_tip_hash() {
+            return Ok(Vec::new());
+        }
+        info!("Switch chain tip to {}.", best);
+        let disconnected = self.set_tip(&best)?;
+        self.get_disconnected_txs(&disconnected)
+    }
+
+    /**
+     * @desc 检查区块大小、交易规则和签名，调用前父区块必须已知
+     */
+    fn check_block_contents(&self, block: &Block) -> Result<()> {
+        self.check_block_size(block)?;
+        self.check_transaction_rules(block.get_transaction(), block.get_height())?;
+        self.check_record_rules(
+            block.get_transaction(),
+            &block.get_prev_hash(),
+            block.get_height(),
+        )?;
+        if !self.verify_block_transactions(block)? {
+            return Err(format_err!(
+                "ERROR: Block {} contains an invalid transaction.",
+                block.get_hash()
+            ));
+        }
+
+        Ok(())
+    }
+
+    /**
+     * @desc 列出所有链端(包括分叉和无效区块)，按高度降序
+     */
+    pub fn get_chain_tips(&self) -> Result<Vec<ChainTip>> {
+        // 手动标记为无效的区块同时在两个树中
+        let mut headers = HashMap::new();
+        for kv in self.headers.iter().chain(self.invalid.iter()) {
+            let (_, data) = kv?;
+            let header = BlockHeader::from_bytes(&data)?;
+            headers.insert(header.hash.clone(), header);
+        }
+        let parents: HashSet<&str> = headers
+            .values()
+            .map(|header| header.prev_block_hash.as_str())
+            .collect();
+
+        let mut tips = Vec::new();
+        for header in headers
+            .values()
+            .filter(|h| !parents.contains(h.hash.as_str()))
+        {
+            let status = if self.invalid.contains_key(&header.hash)? {
+                ChainTipStatus::Invalid
+            } else if self.is_in_main_chain(&header.hash)? {
+                ChainTipStatus::Active
+            } else if !self.db.contains_key(&header.hash)? {
+                ChainTipStatus::HeadersOnly
+            } else {
+                ChainTipStatus::ValidFork
+            };
+
+            tips.push(ChainTip {
+                hash: header.hash.clone(),
+                height: header.height,
+                branch_len: self.get_branch_len(header)?.0,
+                status,
+            });
+        }
+        tips.sort_by_key(|tip| std::cmp::Reverse(tip.height));
+
+        Ok(tips)
+    }
+
+    // 从链端往回走到主链上的区块，父区块未知时停止，同时返回是否连接到了主链
+    fn get_branch_len(&self, tip: &BlockHeader) -> Result<(i32, bool)> {
+        let mut branch_len = 0;
+        let mut header = tip.clone();
+
+        while !self.is_in_main_chain(&header.hash)? {
+            branch_len += 1;
+            let data = match self.headers.get(&header.prev_block_hash)? {
+                Some(data) => data,
+                None => match self.invalid.get(&header.prev_block_hash)? {
+                    Some(data) => data,
+                    None => return Ok((branch_len, false)),
+                },
+            };
+            header = BlockHeader::from_bytes(&data)?;
+        }
+
+        Ok((branch_len, true))
+    }
+
+    /**
+     * @desc 下一个区块的目标值(nBits)，目前没有难度调整，沿用前一个区块的目标值(即创世配置的难度)，
+     *       找不到前一个区块时为网络的最低难度
+     */
+    pub fn get_next_work_required(&self, prev_block_hash: &str) -> u32 {
+        match self.get_header(prev_block_hash) {
+            Ok(header) => header.bits,
+            Err(_) => network::params().pow_limit_bits,
+        }
+    }
+
+    /**
+     * @desc 校验区块头的 nBits 和难度调整算法给出的一致，并且区块哈希满足目标值；
+     *       只用到区块头，收到紧凑区块时在补全交易之前就可以校验
+     */
+    pub fn check_block_work(&self, header: &BlockHeader) -> Result<()> {
+        let expected = self.get_next_work_required(&header.prev_block_hash);
+        if header.bits != expected {
+            return Err(format_err!(
+                "ERROR: Block {} has incorrect target bits {:#010x}, expected {:#010x}.",
+                header.hash,
+                header.bits,
+                expected
+            ));
+        }
+        if !hash_meets_target(&header.hash, header.bits) {
+            return Err(format_err!(
+                "ERROR: Block {} does not meet its target.",
+                header.hash
+            ));
+        }
+
+        Ok(())
+    }
+
+    /**
+     * @desc 校验区块高度等于父区块高度 + 1，父区块必须已知；没有父区块的只能是高度为 0 的创世区块
+     */
+    pub fn check_block_height(&self, block: &Block) -> Result<()> {
+        let prev_hash = block.get_prev_hash();
+        let expected = if prev_hash.is_empty() {
+            0
+        } else {
+            match self.headers.get(&prev_hash)? {
+                Some(data) => BlockHeader::from_bytes(&data)?.height + 1,
+                None => {
+                    return Err(format_err!(
+                        "ERROR: Parent {} of block {} is unknown.",
+                        prev_hash,
+                        block.get_hash()
+                    ))
+                }
+            }
+        };
+        if block.get_height() != expected {
+            return Err(format_err!(
+                "ERROR: Block {} has height {}, expected {}.",
+                block.get_hash(),
+                block.get_height(),
+                expected
+            ));
+        }
+
+        Ok(())
+    }
+
+    /**
+     * @desc 校验区块和其中每笔交易的大小不超过当前网络的上限
+     */
+    pub fn check_block_size(&self, block: &Block) -> Result<()> {
+        let params = network::params();
+
+        let size = block.get_size()?;
+        if size > params.max_block_size {
+            return Err(format_err!(
+                "ERROR: Block {} size {} exceeds the limit {}.",
+                block.get_hash(),
+                size,
+                params.max_block_size
+            ));
+        }
+
+        for tx in block.get_transaction() {
+            check_tx_size(tx)?;
+        }
+
+        Ok(())
+    }
+
+    /**
+     * @desc 校验区块时间戳: 必须大于前 11 个区块的中位时间，且不能超前网络调整时间 2 小时
+     */
+    pub fn check_block_time(&self, block: &Block) -> Result<()> {
+        let now = timedata::get_adjusted_time();
+        if block.get_timestamp() > now + MAX_FUTURE_BLOCK_TIME {
+            return Err(format_err!(
+                "ERROR: Block {} timestamp is too far in the future.",
+                block.get_hash()
+            ));
+        }
+
+        // 前一个区块未知时(创世区块或乱序到达)无法计算中位时间
+        if block.get_height() == 0 || !self.has_block(&block.get_prev_hash())? {
+            return Ok(());
+        }
+
+        let median = self.get_median_time_past(&block.get_prev_hash())?;
+        if block.get_timestamp() <= median {
+            return Err(format_err!(
+                "ERROR: Block {} timestamp is not after median time past.",
+                block.get_hash()
+            ));
+        }
+
+        Ok(())
+    }
+
+    /**
+     * @desc 获取以 block_hash 结尾的最近 11 个区块的中位时间
+     */
+    pub fn get_median_time_past(&self, block_hash: &str) -> Result<u128> {
+        let mut timestamps = Vec::new();
+        let mut hash = block_hash.to_string();
+
+        while timestamps.len() < MEDIAN_TIME_SPAN && !hash.is_empty() {
+            let header = self.get_header(&hash)?;
+            timestamps.push(header.timestamp);
+            hash = header.prev_block_hash;
+        }
+
+        Ok(median_time(timestamps))
+    }
+
+    /**
+     * @desc 通过 hash 获取区块
+     */
+    pub fn get_block(&self, block_hash: &str) -> Result<Block> {
+        let data = self
+            .db
+            .get(block_hash)?
+            .ok_or_else(|| format_err!("Block {} is not found.", block_hash))?;
+
+        Block::from_bytes(&data)
+    }
+
+    /**
+     * @desc 通过 hash 获取区块头
+     */
+    pub fn get_header(&self, block_hash: &str) -> Result<BlockHeader> {
+        let data = self
+            .headers
+            .get(block_hash)?
+            .ok_or_else(|| format_err!("Block header {} is not found.", block_hash))?;
+
+        BlockHeader::from_bytes(&data)
+    }
+
+    /**
+     * @desc 判断区块是否已存在
+     */
+    pub fn has_block(&self, block_hash: &str) -> Result<bool> {
+        Ok(self.headers.contains_key(block_hash)?)
+    }
+
+    /**
+     * @desc 分别保存区块头和完整区块
+     */
+    fn store_block(&self, block: &Block) -> Result<()> {
+        self.headers
+            .insert(block.get_hash(), serialize(&block.get_header())?)?;
+        self.db.insert(block.get_hash(), serialize(block)?)?;
+        self.store_chain_work(&block.get_header())?;
+        self.index_transactions(block)?;
+        self.index_names(block)?;
+        self.index_votes(block)?;
+
+        Ok(())
+    }
+
+    /**
+     * @desc 把区块中的交易加入交易索引
+     */
+    fn index_transactions(&self, block: &Block) -> Result<()> {
+        for tx in block.get_transaction() {
+            let mut block_hashes: Vec<String> = match self.tx_index.get(&tx.id)? {
+                Some(data) => deserialize(&data)?,
+                None => Vec::new(),
+            };
+            if !block_hashes.contains(&block.get_hash()) {
+                block_hashes.push(block.get_hash());
+                self.tx_index.insert(&tx.id, serialize(&block_hashes)?)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /**
+     * @desc 把区块中的名称注册加入名称索引
+     */
+    fn index_names(&self, block: &Block) -> Result<()> {
+        for tx in block.get_transaction() {
+            let record = match NameRecord::from_tx(tx)? {
+                Some(record) => record,
+                None => continue,
+            };
+            let mut claims: Vec<NameClaim> = match self.names.get(&record.name)? {
+                Some(data) => deserialize(&data)?,
+                None => Vec::new(),
+            };
+            if claims
+                .iter()
+                .any(|claim| claim.block_hash == block.get_hash() && claim.txid == tx.id)
+            {
+                continue;
+            }
+            claims.push(NameClaim {
+                block_hash: block.get_hash(),
+                height: block.get_height(),
+                txid: tx.id.clone(),
+                pub_key_hash: record.pub_key_hash,
+            });
+            self.names.insert(&record.name, serialize(&claims)?)?;
+        }
+
+        Ok(())
+    }
+
+    /**
+     * @desc 把区块中的投票加入投票索引
+     */
+    fn index_votes(&self, block: &Block) -> Result<()> {
+        for tx in block.get_transaction() {
+            let (proposal, approve) = match GovernanceRecord::from_tx(tx)? {
+                Some(GovernanceRecord::Vote { proposal, approve }) => (proposal, approve),
+                _ => continue,
+            };
+            let mut votes: Vec<VoteEntry> = match self.votes.get(&proposal)? {
+                Some(data) => deserialize(&data)?,
+                None => Vec::new(),
+            };
+            if votes
+                .iter()
+                .any(|vote| vote.block_hash == block.get_hash() && vote.txid == tx.id)
+            {
+                continue;
+            }
+            votes.push(VoteEntry {
+                block_hash: block.get_hash(),
+                height: block.get_height(),
+                txid: tx.id.clone(),
+                voter: governance::voter(tx),
+                approve,
+            });
+            self.votes.insert(&proposal, serialize(&votes)?)?;
+        }
+
+        Ok(())
+    }
+
+    /**
+     * @desc 用 index 为已保存的全部区块(包括分叉上的区块)建立索引
+     */
+    fn index_all_blocks(&self, index: impl Fn(&Block) -> Result<()>) -> Result<()> {
+        for kv in self.headers.iter() {
+            let (hash, _) = kv?;
+            index(&self.get_block(&String::from_utf8(hash.to_vec())?)?)?;
+        }
+        self.db.flush()?;
+
+        Ok(())
+    }
+
+    /**
+     * @desc 切换最新区块，并更新主链高度索引，返回被断开的区块 hash(按高度降序)；
+     *       新分支的高度由分叉点沿父区块链接推出，和区块声明的高度不一致时不切换
+     */
+    fn set_tip(&self, block_hash: &str) -> Result<Vec<String>> {
+        // 从新最新区块沿父区块往回走，直到和主链重合
+        let mut branch = Vec::new();
+        let mut header = self.get_header(block_hash)?;
+        let fork_height = loop {
+            if self.is_in_main_chain(&header.hash)? {
+                break header.height;
+            }
+            let prev_hash = header.prev_block_hash.clone();
+            branch.push(header);
+            if prev_hash.is_empty() {
+                break -1;
+            }
+            header = self.get_header(&prev_hash)?;
+        };
+        branch.reverse();
+        for (height, header) in (fork_height + 1..).zip(&branch) {
+            if header.height != height {
+                return Err(format_err!(
+                    "ERROR: Block {} declares height {} but is at height {} of its chain.",
+                    header.hash,
+                    header.height,
+                    height
+                ));
+            }
+        }
+
+        // 删除分叉点以上的旧主链记录
+        let mut disconnected = Vec::new();
+        let old_height = self.get_best_height()?;
+        for height in ((fork_height + 1)..=old_height).rev() {
+            if let Some(old) = self.main_chain.remove(height.to_be_bytes())? {
+                disconnected.push(String::from_utf8(old.to_vec())?);
+            }
+        }
+        for (height, header) in (fork_height + 1..).zip(&branch) {
+            self.main_chain
+                .insert(height.to_be_bytes(), header.hash.as_bytes())?;
+        }
+
+        self.db.insert("LAST", block_hash.as_bytes())?;
+        *self.tip.write().unwrap() = block_hash.to_string();
+        self.db.flush()?;
+
+        Ok(disconnected)
+    }
+
+    /**
+     * @desc 获取主链上指定高度的区块 hash
+     */
+    pub fn get_block_hash_at(&self, height: i32) -> Result<Option<String>> {
+        match self.main_chain.get(height.to_be_bytes())? {
+            Some(hash) => Ok(Some(String::from_utf8(hash.to_vec())?)),
+            None => Ok(None),
+        }
+    }
+
+    /**
+     * @desc 判断区块是否在主链上
+     */
+    pub fn is_in_main_chain(&self, block_hash: &str) -> Result<bool> {
+        let header = match self.get_header(block_hash) {
+            Ok(header) => header,
+            Err(_) => return Ok(false),
+        };
+
+        Ok(self.main_chain.get(header.height.to_be_bytes())?.as_deref()
+            == Some(block_hash.as_bytes()))
+    }
+
+    /**
+     * @desc 区块所在分支上不属于主链的区块数，切换主链后用旧的最新区块计算回滚深度
+     */
+    pub fn reorg_depth(&self, block_hash: &str) -> Result<i32> {
+        let mut depth = 0;
+        let mut hash = block_hash.to_string();
+        while !hash.is_empty() && !self.is_in_main_chain(&hash)? {
+            depth += 1;
+            hash = self.get_header(&hash)?.prev_block_hash;
+        }
+
+        Ok(depth)
+    }
+
+    /**
+     * @desc 累计工作量 = 前一个区块的累计工作量 + 当前区块的工作量
+     */
+    fn store_chain_work(&self, header: &BlockHeader) -> Result<()> {
+        let work = self.get_chain_work(&header.prev_block_hash)? + block_work(header.bits);
+        self.chain_work
+            .insert(&header.hash, &work.to_be_bytes()[..])?;
+
+        Ok(())
+    }
+
+    /**
+     * @desc 获取区块的累计工作量，未知区块为 0
+     */
+    pub fn get_chain_work(&self, block_hash: &str) -> Result<u128> {
+        match self.chain_work.get(block_hash)? {
+            Some(data) => {
+                let mut bytes = [0; 16];
+                bytes.copy_from_slice(&data);
+                Ok(u128::from_be_bytes(bytes))
+            }
+            None => Ok(0),
+        }
+    }
+
+    /**
+     * @desc 获取最新区块的 hash
+     */
+    pub fn get_tip_hash(&self) -> String {
+        self.tip.read().unwrap().clone()
+    }
+
+    /**
+     * @desc 为旧版本数据库中的链建立区块头、累计工作量、主链和交易索引
+     */
+    fn index_headers(&self) -> Result<()> {
+        if self.get_tip_hash().is_empty() || !self.main_chain.is_empty() {
+            return Ok(());
+        }
+
+        info!("Indexing block headers...");
+        let mut blocks: Vec<Block> = self.iter().collect();
+        blocks.reverse();
+        for block in blocks {
+            let header = block.get_header();
+            self.headers.insert(&header.hash, serialize(&header)?)?;
+            self.store_chain_work(&header)?;
+            self.index_transactions(&block)?;
+            self.main_chain
+                .insert(header.height.to_be_bytes(), header.hash.as_bytes())?;
+        }
+        self.db.flush()?;
+
+        Ok(())
+    }
+
+    /**
+     * @desc 获取最后一个区块的高度
+     */
+    pub fn get_best_height(&self) -> Result<i32> {
+        let last_hash = if let Some(height) = self.db.get("LAST")? {
+            height
+        } else {
+            return Ok(-1);
+        };
+
+        let last_hash = String::from_utf8(last_hash.to_vec())?;
+
+        Ok(self.get_header(&last_hash)?.height)
+    }
+
+    /**
+     * @desc 获取所有区块的哈希
+     */
+    pub fn get_block_hashes(&self) -> Vec<String> {
+        let mut list = Vec::new();
+        let mut hash = self.get_tip_hash();
+
+        while let Ok(header) = self.get_header(&hash) {
+            list.push(header.hash);
+            hash = header.prev_block_hash;
+        }
+
+        list
+    }
+
+    /**
+     * @desc 获取区块定位器(从最新区块开始，间隔呈指数增长的区块哈希)
+     */
+    pub fn get_block_locator(&self) -> Vec<String> {
+        build_locator(&self.get_block_hashes())
+    }
+
+    /**
+     * @desc 根据对方的区块定位器找到分叉点，返回分叉点之后的区块哈希(按高度升序，最多 limit 个)
+     */
+    pub fn get_hashes_after_locator(&self, locator: &[String], limit: usize) -> Vec<String> {
+        let mut hashes = self.get_block_hashes();
+        hashes.reverse();
+
+        let start = locator
+            .iter()
+            .find_map(|hash| hashes.iter().position(|h| h == hash))
+            .map(|pos| pos + 1)
+            .unwrap_or(0);
+
+        hashes.into_iter().skip(start).take(limit).collect()
+    }
+}
+
+/**
+ * @desc 由按高度降序排列的区块哈希生成定位器：前 10 个逐一取，之后步长翻倍，最后总是包含创世区块
+ */
+fn build_locator(hashes: &[String]) -> Vec<String> {
+    let mut locator = Vec::new();
+    let mut index = 0;
+    let mut step = 1;
+
+    while index < hashes.len() {
+        locator.push(hashes[index].clone());
+        if locator.len() >= 10 {
+            step *= 2;
+        }
+        index += step;
+    }
+
+    if let Some(genesis) = hashes.last() {
+        if locator.last() != Some(genesis) {
+            locator.push(genesis.clone());
+        }
+    }
+
+    locator
+}
+
+/**
+ * @desc 由前序交易计算交易的手续费: 输入总额减去输出总额
+ */
+pub fn tx_fee(tx: &Transaction, prev_txs: &HashMap<String, Transaction>) -> Result<i32> {
+    let mut input_value = 0;
+    for vin in &tx.vin {
+        let prev_out = prev_txs
+            .get(&vin.txid)
+            .and_then(|prev_tx| prev_tx.vout.get(vin.vout as usize));
+        match prev_out {
+            Some(out) => input_value += out.value,
+            None => return Err(format_err!("ERROR: Referenced output does not exist.")),
+        }
+    }
+
+    let output_value: i32 = tx.vout.iter().map(|out| out.value).sum();
+
+    Ok(input_value - output_value)
+}
+
+/**
+ * @desc 校验交易大小不超过当前网络的上限
+ */
+pub fn check_tx_size(tx: &Transaction) -> Result<()> {
+    let size = tx.get_size()?;
+    let max_tx_size = network::params().max_tx_size;
+    if size > max_tx_size {
+        return Err(format_err!(
+            "ERROR: Transaction {} size {} exceeds the limit {}.",
+            tx.id,
+            size,
+            max_tx_size
+        ));
+    }
+
+    Ok(())
+}
+
+fn median_time(mut timestamps: Vec<u128>) -> u128 {
+    if timestamps.is_empty() {
+        return 0;
+    }
+
+    timestamps.sort_unstable();
+    timestamps[timestamps.len() / 2]
+}
+
+impl<'a> Iterator for BlockchainIterator<'a> {
+    type Item = Block;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Ok(encoded_block) = self.bc.db.get(&self.current_hash) {
+            return match encoded_block {
+                Some(b) => {
+                    if let Ok(block) = Block::from_bytes(&b) {
+                        self.current_hash = block.get_prev_hash();
+                        Some(block)
+                    } else {
+                        None
+                    }
+                }
+                None => None,
+            };
+        }
+
+        None
+    }
+}
+
+impl<'a> Iterator for BlockchainForwardIterator<'a> {
+    type Item = Block;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.height >= self.end {
+            return None;
+        }
+        let hash = self.bc.get_block_hash_at(self.height).ok()??;
+        let block = self.bc.get_block(&hash).ok()?;
+        self.height += 1;
+
+        Some(block)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rand_core::OsRng;
+
+    #[test]
+    fn test_build_locator() {
+        let hashes: Vec<String> = (0..100).rev().map(|h| h.to_string()).collect();
+        let locator = build_locator(&hashes);
+
+        let expected: Vec<String> = [
+            99, 98, 97, 96, 95, 94, 93, 92, 91, 90, 88, 84, 76, 60, 28, 0,
+        ]
+        .iter()
+        .map(|h| h.to_string())
+        .collect();
+        assert_eq!(locator, expected);
+
+        assert!(build_locator(&[]).is_empty());
+        assert_eq!(build_locator(&[String::from("a")]), vec![String::from("a")]);
+    }
+
+    #[test]
+    fn test_median_time() {
+        assert_eq!(median_time(vec![]), 0);
+        assert_eq!(median_time(vec![5]), 5);
+        assert_eq!(median_time(vec![9, 1, 5, 3, 7]), 5);
+        assert_eq!(median_time((1..=11).rev().collect()), 6);
+    }
+
+    #[test]
+    fn test_chain_tips() {
+        let address = crate::wallets::Wallet::default().get_address();
+        let mut spec = GenesisSpec::default();
+        spec.allocations.push((address.clone(), SUBSIDY));
+        let genesis = spec.build().unwrap();
+        let bc = Blockchain::create_temporary(&genesis).unwrap();
+        let coinbase =
+            || Transaction::new_coinbase(address.clone(), String::new(), &mut OsRng).unwrap();
+        let block_on = |prev: &Block, txs: Vec<Transaction>| {
+            Block::new(
+                txs,
+                prev.get_hash(),
+                prev.get_height() + 1,
+                versionbits::VERSIONBITS_TOP_BITS,
+                bc.get_next_work_required(&prev.get_hash()),
+            )
+            .unwrap()
+        };
+
+        let first = bc.mine_block(vec![coinbase()]).unwrap();
+        let tip = bc.mine_block(vec![coinbase()]).unwrap();
+        bc.add_block(block_on(&genesis, vec![coinbase()])).unwrap();
+
+        // 花费不存在的输出的区块无效，它的后续区块也无效
+        let mut spend = coinbase();
+        spend.vin[0].txid = "0".repeat(64);
+        spend.id = spend.hash().unwrap();
+        let invalid = block_on(&first, vec![coinbase(), spend]);
+        assert!(bc.add_block(invalid.clone()).is_err());
+        assert!(bc.add_block(invalid.clone()).is_err());
+        let child = block_on(&invalid, vec![coinbase()]);
+        assert!(bc.add_block(child.clone()).is_err());
+
+        let tips: Vec<(String, i32, i32, ChainTipStatus)> = bc
+            .get_chain_tips()
+            .unwrap()
+            .into_iter()
+            .map(|tip| (tip.hash, tip.height, tip.branch_len, tip.status))
+            .collect();
+        assert_eq!(tips.len(), 3);
+        assert_eq!(tips[0], (child.get_hash(), 3, 2, ChainTipStatus::Invalid));
+        assert_eq!(tips[1], (tip.get_hash(), 2, 0, ChainTipStatus::Active));
+        assert_eq!(tips[2].1, 1);
+        assert_eq!(tips[2].2, 1);
+        assert_eq!(tips[2].3, ChainTipStatus::ValidFork);
+    }
+
+    #[test]
+    fn test_block_height_must_follow_parent() {
+        let address = crate::wallets::Wallet::default().get_address();
+        let mut spec = GenesisSpec::default();
+        spec.allocations.push((address.clone(), SUBSIDY));
+        let genesis = spec.build().unwrap();
+        let bc = Blockchain::create_temporary(&genesis).unwrap();
+        let coinbase =
+            || Transaction::new_coinbase(address.clone(), String::new(), &mut OsRng).unwrap();
+        let block_at = |prev_hash: String, height: i32| {
+            Block::new(
+                vec![coinbase()],
+                prev_hash.clone(),
+                height,
+                versionbits::VERSIONBITS_TOP_BITS,
+                bc.get_next_work_required(&prev_hash),
+            )
+            .unwrap()
+        };
+
+        bc.mine_block(vec![coinbase()]).unwrap();
+        let tip = bc.mine_block(vec![coinbase()]).unwrap();
+
+        // 声称高度 1 的子区块不能成为最新区块
+        let wrong_height = block_at(tip.get_hash(), 1);
+        assert!(bc.add_block(wrong_height.clone()).is_err());
+        assert!(!bc.has_block(&wrong_height.get_hash()).unwrap());
+        assert_eq!(bc.get_tip_hash(), tip.get_hash());
+        assert_eq!(bc.get_best_height().unwrap(), 2);
+
+        // 父区块未知
+        assert!(bc.add_block(block_at("0".repeat(64), 3)).is_err());
+
+        let child = block_at(tip.get_hash(), 3);
+        bc.add_block(child.clone()).unwrap();
+        assert_eq!(bc.get_tip_hash(), child.get_hash());
+        assert_eq!(bc.get_best_height().unwrap(), 3);
+    }
+
+    #[test]
+    fn test_out_of_order_block_is_not_marked_invalid() {
+        let wallet = crate::wallets::Wallet::from_seed(&[1; 32]);
+        let other = crate::wallets::Wallet::from_seed(&[2; 32]);
+        let mut spec = GenesisSpec::default();
+        spec.allocations.push((wallet.get_address(), SUBSIDY));
+        let genesis = spec.build().unwrap();
+        let bc = Blockchain::create_temporary(&genesis).unwrap();
+        let mut utxos = crate::utxo_set::MemoryUtxoSet::default();
+        utxos.add_transaction(genesis.get_transaction()[0].clone());
+        let block_on = |prev_hash: String, height: i32, tx: Transaction| {
+            let cbtx =
+                Transaction::new_coinbase(wallet.get_address(), String::new(), &mut OsRng).unwrap();
+            Block::new(
+                vec![cbtx, tx],
+                prev_hash,
+                height,
+                versionbits::VERSIONBITS_TOP_BITS,
+                bc.get_next_work_required(&genesis.get_hash()),
+            )
+            .unwrap()
+        };
+
+        // 子区块花费父区块中的交易，先于父区块到达
+        let tx = Transaction::new_utxo(&wallet, &other.get_address(), 5, &utxos).unwrap();
+        utxos.add_transaction(tx.clone());
+        let child_tx = Transaction::new_utxo(&other, &wallet.get_address(), 5, &utxos).unwrap();
+        let parent = block_on(genesis.get_hash(), 1, tx);
+        let child = block_on(parent.get_hash(), 2, child_tx);
+
+        // 缺少父区块时只拒绝，不记为无效，父区块到达后子区块可以接上
+        assert!(bc.add_block(child.clone()).is_err());
+        assert!(!bc.invalid.contains_key(child.get_hash()).unwrap());
+        bc.add_block(parent).unwrap();
+        bc.add_block(child.clone()).unwrap();
+        assert_eq!(bc.get_tip_hash(), child.get_hash());
+    }
+
+    #[test]
+    fn test_reorg_rejects_inconsistent_heights() {
+        let address = crate::wallets::Wallet::default().get_address();
+        let mut spec = GenesisSpec::default();
+        spec.allocations.push((address.clone(), SUBSIDY));
+        let genesis = spec.build().unwrap();
+        let bc = Blockchain::create_temporary(&genesis).unwrap();
+        let coinbase =
+            || Transaction::new_coinbase(address.clone(), String::new(), &mut OsRng).unwrap();
+        let block_at = |prev: &Block, height: i32| {
+            Block::new(
+                vec![coinbase()],
+                prev.get_hash(),
+                height,
+                versionbits::VERSIONBITS_TOP_BITS,
+                bc.get_next_work_required(&prev.get_hash()),
+            )
+            .unwrap()
+        };
+
+        bc.mine_block(vec![coinbase()]).unwrap();
+        let tip = bc.mine_block(vec![coinbase()]).unwrap();
+
+        // 工作量更大的分支中间有一个区块声明了错误的高度，绕过添加区块时的检查直接保存
+        let fork_1 = block_at(&genesis, 1);
+        let fork_2 = block_at(&fork_1, 5);
+        let fork_3 = block_at(&fork_2, 6);
+        for block in [&fork_1, &fork_2, &fork_3] {
+            bc.store_block(block).unwrap();
+        }
+        assert!(
+            bc.get_chain_work(&fork_3.get_hash()).unwrap()
+                > bc.get_chain_work(&tip.get_hash()).unwrap()
+        );
+        assert!(bc.set_tip(&fork_3.get_hash()).is_err());
+        assert_eq!(bc.get_tip_hash(), tip.get_hash());
+        assert_eq!(
+            bc.get_block_hash_at(1).unwrap(),
+            Some(bc.get_header(&tip.get_prev_hash()).unwrap().hash)
+        );
+        assert_eq!(bc.get_block_hash_at(5).unwrap(), None);
+
+        // 高度一致的分支正常切换，主链高度由父区块链接推出
+        let fork_2 = block_at(&fork_1, 2);
+        let fork_3 = block_at(&fork_2, 3);
+        bc.add_block(fork_2.clone()).unwrap();
+        bc.add_block(fork_3.clone()).unwrap();
+        assert_eq!(bc.get_tip_hash(), fork_3.get_hash());
+        assert_eq!(bc.get_best_height().unwrap(), 3);
+        for (height, block) in [(1, &fork_1), (2, &fork_2), (3, &fork_3)] {
+            assert_eq!(
+                bc.get_block_hash_at(height).unwrap(),
+                Some(block.get_hash())
+            );
+        }
+        assert!(!bc.is_in_main_chain(&tip.get_hash()).unwrap());
+    }
+
+    #[test]
+    fn test_coinbase_cannot_exceed_reward() {
+        let address = crate::wallets::Wallet::default().get_address();
+        let mut spec = GenesisSpec::default();
+        // 创世区块的初始分配可以超过出块奖励
+        spec.allocations.push((address.clone(), SUBSIDY * 10));
+        let genesis = spec.build().unwrap();
+        let bc = Blockchain::create_temporary(&genesis).unwrap();
+
+        let mut inflated =
+            Transaction::new_coinbase(address.clone(), String::new(), &mut OsRng).unwrap();
+        inflated.vout[0].value = SUBSIDY + 1;
+        inflated.id = inflated.hash().unwrap();
+        assert!(bc.mine_block(vec![inflated]).is_err());
+
+        let split = crate::reward::RewardSplit::parse(&[format!("{}:50", address)]).unwrap();
+        let coinbase =
+            Transaction::new_coinbase(address.clone(), String::new(), &mut OsRng).unwrap();
+        let coinbase = split.apply(coinbase, &address).unwrap();
+        assert!(bc.mine_block(vec![coinbase]).is_ok());
+    }
+
+    #[test]
+    fn test_invalidate_and_reconsider_block() {
+        let address = crate::wallets::Wallet::default().get_address();
+        let mut spec = GenesisSpec::default();
+        spec.allocations.push((address.clone(), SUBSIDY));
+        let genesis = spec.build().unwrap();
+        let bc = Blockchain::create_temporary(&genesis).unwrap();
+        let coinbase =
+            || Transaction::new_coinbase(address.clone(), String::new(), &mut OsRng).unwrap();
+        let block_on = |prev: &Block| {
+            Block::new(
+                vec![coinbase()],
+                prev.get_hash(),
+                prev.get_height() + 1,
+                versionbits::VERSIONBITS_TOP_BITS,
+                bc.get_next_work_required(&prev.get_hash()),
+            )
+            .unwrap()
+        };
+
+        let main_1 = bc.mine_block(vec![coinbase()]).unwrap();
+        let main_2 = bc.mine_block(vec![coinbase()]).unwrap();
+        let fork_1 = block_on(&genesis);
+        bc.add_block(fork_1.clone()).unwrap();
+        let fork_2 = block_on(&fork_1);
+        bc.add_block(fork_2.clone()).unwrap();
+        assert_eq!(bc.get_tip_hash(), main_2.get_hash());
+
+        // 主链被标记无效后切换到分叉，分叉上的后续区块不再被接受
+        bc.invalidate_block(&main_1.get_hash()).unwrap();
+        assert_eq!(bc.get_tip_hash(), fork_2.get_hash());
+        assert!(!bc.is_in_main_chain(&main_1.get_hash()).unwrap());
+        assert!(bc.add_block(block_on(&main_2)).is_err());
+        let status = |hash: String| {
+            bc.get_chain_tips()
+                .unwrap()
+                .into_iter()
+                .find(|tip| tip.hash == hash)
+                .map(|tip| tip.status)
+        };
+        assert_eq!(status(main_2.get_hash()), None);
+
+        // 重新考虑后两条链工作量相同，保留当前最新区块
+        bc.reconsider_block(&main_2.get_hash()).unwrap();
+        assert_eq!(bc.get_tip_hash(), fork_2.get_hash());
+        assert_eq!(status(main_2.get_hash()), Some(ChainTipStatus::ValidFork));
+
+        bc.invalidate_block(&fork_1.get_hash()).unwrap();
+        assert_eq!(bc.get_tip_hash(), main_2.get_hash());
+        assert!(bc.invalidate_block(&genesis.get_hash()).is_err());
+    }
+
+    #[test]
+    fn test_shared_handle() {
+        let address = crate::wallets::Wallet::default().get_address();
+        let mut spec = GenesisSpec::default();
+        spec.allocations.push((address.clone(), SUBSIDY));
+        let bc = Blockchain::create_temporary(&spec.build().unwrap()).unwrap();
+
+        // 挖矿的同时其他句柄可以并发查询，并且看到同一条链
+        let reader = bc.clone();
+        let handle = std::thread::spawn(move || {
+            let mut height = 0;
+            while height < 3 {
+                height = reader.get_best_height().unwrap();
+                reader.get_header(&reader.get_tip_hash()).unwrap();
+            }
+            reader.get_tip_hash()
+        });
+        for _ in 0..3 {
+            let cbtx =
+                Transaction::new_coinbase(address.clone(), String::new(), &mut OsRng).unwrap();
+            bc.mine_block(vec![cbtx]).unwrap();
+        }
+
+        assert_eq!(handle.join().unwrap(), bc.get_tip_hash());
+        assert_eq!(bc.clone().get_best_height().unwrap(), 3);
+    }
+
+    #[test]
+    fn test_iter_forward() {
+        let address = crate::wallets::Wallet::default().get_address();
+        let mut spec = GenesisSpec::default();
+        spec.allocations.push((address.clone(), SUBSIDY));
+        let genesis = spec.build().unwrap();
+        let bc = Blockchain::create_temporary(&genesis).unwrap();
+        for _ in 0..3 {
+            let cbtx =
+                Transaction::new_coinbase(address.clone(), String::new(), &mut OsRng).unwrap();
+            bc.mine_block(vec![cbtx]).unwrap();
+        }
+
+        let forward: Vec<String> = bc.iter_forward().map(|block| block.get_hash()).collect();
+        let mut backward: Vec<String> = bc.iter().map(|block| block.get_hash()).collect();
+        backward.reverse();
+        assert_eq!(forward.len(), 4);
+        assert_eq!(forward[0], genesis.get_hash());
+        assert_eq!(forward, backward);
+
+        let range: Vec<String> = bc.iter_range(1, 3).map(|block| block.get_hash()).collect();
+        assert_eq!(range, forward[1..3].to_vec());
+        assert_eq!(bc.iter_range(3, 10).count(), 1);
+    }
+
+    #[test]
+    fn test_find_conflicts() {
+        let wallet = crate::wallets::Wallet::from_seed(&[1; 32]);
+        let other = crate::wallets::Wallet::from_seed(&[2; 32]);
+        let mut spec = GenesisSpec::default();
+        spec.allocations.push((wallet.get_address(), SUBSIDY));
+        let genesis = spec.build().unwrap();
+        let bc = Blockchain::create_temporary(&genesis).unwrap();
+        let mut utxos = crate::utxo_set::MemoryUtxoSet::default();
+        utxos.add_transaction(genesis.get_transaction()[0].clone());
+
+        // 花费同一输出的两笔交易只有一笔进入主链，另一笔与它冲突
+        let confirmed = Transaction::new_utxo(&wallet, &other.get_address(), 5, &utxos).unwrap();
+        let conflicted = Transaction::new_utxo(&wallet, &other.get_address(), 6, &utxos).unwrap();
+        utxos.add_transaction(confirmed.clone());
+        let child = Transaction::new_utxo(&other, &wallet.get_address(), 5, &utxos).unwrap();
+        let cbtx =
+            Transaction::new_coinbase(wallet.get_address(), String::new(), &mut OsRng).unwrap();
+        bc.mine_block(vec![cbtx, confirmed.clone()]).unwrap();
+
+        let conflicts = bc
+            .find_conflicts(&[confirmed.clone(), conflicted.clone(), child])
+            .unwrap();
+        assert_eq!(conflicts.len(), 1);
+        assert_eq!(conflicts[&conflicted.id], vec![confirmed.id]);
+    }
+
+    #[test]
+    fn test_name_registration() {
+        let owners = [
+            crate::wallets::Wallet::from_seed(&[1; 32]),
+            crate::wallets::Wallet::from_seed(&[2; 32]),
+        ];
+        let mut spec = GenesisSpec::default();
+        for owner in &owners {
+            spec.allocations.push((owner.get_address(), SUBSIDY));
+        }
+        let genesis = spec.build().unwrap();
+        let bc = Blockchain::create_temporary(&genesis).unwrap();
+        let genesis_tx = genesis.get_transaction()[0].clone();
+        let coinbase = || {
+            Transaction::new_coinbase(owners[0].get_address(), String::new(), &mut OsRng).unwrap()
+        };
+        let block_on = |prev: &Block, txs: Vec<Transaction>| {
+            Block::new(
+                txs,
+                prev.get_hash(),
+                prev.get_height() + 1,
+                versionbits::VERSIONBITS_TOP_BITS,
+                bc.get_next_work_required(&prev.get_hash()),
+            )
+            .unwrap()
+        };
+        // 第 i 个钱包花费创世分配的输出注册 alice
+        let register = |i: usize| {
+            let record = NameRecord::new("alice", &owners[i].get_address()).unwrap();
+            let mut tx = Transaction {
+                id: String::new(),
+                vin: vec![TXInput {
+                    txid: genesis_tx.id.clone(),
+                    vout: i as i32,
+                    signature: Vec::new(),
+                    pub_key: owners[i].public_key.clone(),
+                }],
+                vout: vec![record.to_output()],
+                version: names::TX_VERSION_NAME,
+                relative_lock_time: 0,
+            };
+            tx.id = tx.hash().unwrap();
+            let prev_txs = HashMap::from([(genesis_tx.id.clone(), genesis_tx.clone())]);
+            tx.sign(&owners[i].secret_key, prev_txs).unwrap();
+            tx
+        };
+
+        assert_eq!(bc.resolve_name("alice").unwrap(), None);
+        let first = bc.mine_block(vec![coinbase(), register(0)]).unwrap();
+        let claim = bc.resolve_name("alice").unwrap().unwrap();
+        assert_eq!(claim.address(), owners[0].get_address());
+        assert_eq!(claim.block_hash, first.get_hash());
+
+        // 名称已被注册，其他人的注册无效
+        assert!(bc
+            .add_block(block_on(&first, vec![coinbase(), register(1)]))
+            .is_err());
+        assert!(bc.mine_block(vec![coinbase(), register(1)]).is_err());
+
+        // 分叉上名称没有被注册，分叉成为主链后名称属于分叉上的注册者
+        let fork = block_on(&genesis, vec![coinbase(), register(1)]);
+        bc.add_block(fork.clone()).unwrap();
+        assert_eq!(
+            bc.resolve_name("alice").unwrap().unwrap().address(),
+            owners[0].get_address()
+        );
+        bc.add_block(block_on(&fork, vec![coinbase()])).unwrap();
+        assert_eq!(
+            bc.resolve_name("alice").unwrap().unwrap().address(),
+            owners[1].get_address()
+        );
+        assert!(bc.resolve_name("Alice").is_err());
+    }
+
+    #[test]
+    fn test_voting() {
+        let voters: Vec<crate::wallets::Wallet> = (1..=3)
+            .map(|seed| crate::wallets::Wallet::from_seed(&[seed; 32]))
+            .collect();
+        let mut spec = GenesisSpec::default();
+        for (i, voter) in voters.iter().enumerate() {
+            spec.allocations
+                .push((voter.get_address(), SUBSIDY * (i as i32 + 1)));
+        }
+        let genesis = spec.build().unwrap();
+        let bc = Blockchain::create_temporary(&genesis).unwrap();
+        let coinbase = || {
+            Transaction::new_coinbase(voters[0].get_address(), String::new(), &mut OsRng).unwrap()
+        };
+        // 花费 prev 的第 vout 个输出，全部金额找零回到投票人
+        let record_tx = |i: usize, prev: &Transaction, vout: usize, record: GovernanceRecord| {
+            let change = TXOutput {
+                value: prev.vout[vout].value,
+                pub_key_hash: prev.vout[vout].pub_key_hash.clone(),
+            };
+            let mut tx = Transaction {
+                id: String::new(),
+                vin: vec![TXInput {
+                    txid: prev.id.clone(),
+                    vout: vout as i32,
+                    signature: Vec::new(),
+                    pub_key: voters[i].public_key.clone(),
+                }],
+                vout: vec![record.to_output(), change],
+                version: governance::TX_VERSION_GOVERNANCE,
+                relative_lock_time: 0,
+            };
+            tx.id = tx.hash().unwrap();
+            let prev_txs = HashMap::from([(prev.id.clone(), prev.clone())]);
+            tx.sign(&voters[i].secret_key, prev_txs).unwrap();
+            tx
+        };
+        let genesis_tx = genesis.get_transaction()[0].clone();
+
+        let proposal = record_tx(
+            0,
+            &genesis_tx,
+            0,
+            GovernanceRecord::proposal("Raise the block size").unwrap(),
+        );
+        let vote = |i: usize, prev: &Transaction, vout: usize, approve: bool| {
+            record_tx(
+                i,
+                prev,
+                vout,
+                GovernanceRecord::vote(&proposal.id, approve).unwrap(),
+            )
+        };
+        // 提案所在的区块中不能投票
+        let early = vote(1, &genesis_tx, 1, true);
+        assert!(bc
+            .mine_block(vec![coinbase(), proposal.clone(), early])
+            .is_err());
+        bc.mine_block(vec![coinbase(), proposal.clone()]).unwrap();
+
+        // 投票人 2 先反对后改为赞成，只计最后一次
+        let yes_1 = vote(1, &genesis_tx, 1, true);
+        let no_2 = vote(2, &genesis_tx, 2, false);
+        bc.mine_block(vec![coinbase(), yes_1, no_2.clone()])
+            .unwrap();
+        let tally = bc.tally_proposal(&proposal.id).unwrap();
+        assert_eq!((tally.yes, tally.no, tally.voters), (20, 30, 2));
+
+        let yes_2 = vote(2, &no_2, 1, true);
+        bc.mine_block(vec![coinbase(), yes_2]).unwrap();
+        let tally = bc.tally_proposal(&proposal.id).unwrap();
+        assert_eq!(tally.title, "Raise the block size");
+        assert_eq!((tally.yes, tally.no, tally.voters), (50, 0, 2));
+        assert_eq!(tally.end_height, 1 + network::params().voting_period);
+        assert!(!tally.closed);
+
+        // 投给不存在的提案无效
+        let mut unknown = vote(0, &genesis_tx, 0, true);
+        unknown.vout[0] = GovernanceRecord::vote(&genesis_tx.id, true)
+            .unwrap()
+            .to_output();
+        assert!(bc.mine_block(vec![coinbase(), unknown]).is_err());
+        assert!(bc.tally_proposal(&genesis_tx.id).is_err());
     }
 }
@@ -0,0 +1,193 @@
+// 手续费追加: 钱包发出的交易长时间没有确认时，提高手续费让矿工优先打包；
+// 交易的输入都由同一个本机密钥签名、找零还没有被花费时重新签署一笔找零更少的交易替换原交易(RBF)，
+// 否则用找零输出构建一笔子交易，由子交易支付追加的手续费(CPFP)
+
+use std::collections::HashMap;
+
+use super::Result;
+use crate::{
+    transaction::*,
+    wallets::{hash_pub_key, Wallet},
+};
+use failure::format_err;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BumpMethod {
+    // 替换原交易
+    Replace,
+    // 子交易为父交易付费
+    ChildPaysForParent,
+}
+
+fn pub_key_hash(wallet: &Wallet) -> Vec<u8> {
+    let mut pub_key_hash = wallet.public_key.clone();
+    hash_pub_key(&mut pub_key_hash);
+
+    pub_key_hash
+}
+
+/**
+ * @desc 追加 extra_fee 的手续费，自动选择替换原交易或构建子交易；
+ *       prev_txs 是已经查到的输入所花费的交易，pending 是钱包中尚未确认的交易
+ */
+pub fn bump_fee(
+    tx: &Transaction,
+    wallets: &[&Wallet],
+    extra_fee: i32,
+    prev_txs: &HashMap<String, Transaction>,
+    pending: &[Transaction],
+) -> Result<(BumpMethod, Transaction)> {
+    if extra_fee <= 0 {
+        return Err(format_err!("Invalid extra fee {}.", extra_fee));
+    }
+
+    // 找零: 支付到本机钱包且足够支付追加手续费的最后一个输出
+    let (vout, owner) = tx
+        .vout
+        .iter()
+        .enumerate()
+        .rev()
+        .find_map(|(index, out)| {
+            wallets
+                .iter()
+                .find(|wallet| out.is_locked_with_key(&pub_key_hash(wallet)))
+                .filter(|_| out.value > extra_fee)
+                .map(|wallet| (index, *wallet))
+        })
+        .ok_or_else(|| {
+            format_err!(
+                "Transaction {} has no wallet output to pay the extra fee.",
+                tx.id
+            )
+        })?;
+
+    match replace(tx, wallets, vout, extra_fee, prev_txs, pending)? {
+        Some(replacement) => Ok((BumpMethod::Replace, replacement)),
+        None => Ok((
+            BumpMethod::ChildPaysForParent,
+            child_pays_for_parent(tx, owner, vout, extra_fee)?,
+        )),
+    }
+}
+
+// 重新签署找零减少 extra_fee 的交易，不能替换时返回 None
+fn replace(
+    tx: &Transaction,
+    wallets: &[&Wallet],
+    vout: usize,
+    extra_fee: i32,
+    prev_txs: &HashMap<String, Transaction>,
+    pending: &[Transaction],
+) -> Result<Option<Transaction>> {
+    if tx.version != TX_VERSION {
+        return Ok(None);
+    }
+    let signer = match wallets
+        .iter()
+        .find(|wallet| tx.vin.iter().all(|vin| vin.pub_key == wallet.public_key))
+    {
+        Some(signer) => signer,
+        None => return Ok(None),
+    };
+    // 替换后原交易的子交易会失效，输出已经被花费时只能追加子交易
+    let spent = pending
+        .iter()
+        .any(|child| child.vin.iter().any(|vin| vin.txid == tx.id));
+    if spent || tx.vin.iter().any(|vin| !prev_txs.contains_key(&vin.txid)) {
+        return Ok(None);
+    }
+
+    let mut replacement = tx.clone();
+    for vin in &mut replacement.vin {
+        vin.signature = Vec::new();
+    }
+    replacement.vout[vout].value -= extra_fee;
+    replacement.id = replacement.hash()?;
+    replacement.sign(&signer.secret_key, prev_txs.clone())?;
+
+    Ok(Some(replacement))
+}
+
+// 把找零输出转回同一地址，金额扣除 extra_fee
+fn child_pays_for_parent(
+    tx: &Transaction,
+    owner: &Wallet,
+    vout: usize,
+    extra_fee: i32,
+) -> Result<Transaction> {
+    let change = &tx.vout[vout];
+    let mut child = Transaction {
+        id: String::new(),
+        vin: vec![TXInput {
+            txid: tx.id.clone(),
+            vout: vout as i32,
+            signature: Vec::new(),
+            pub_key: owner.public_key.clone(),
+        }],
+        vout: vec![TXOutput {
+            value: change.value - extra_fee,
+            pub_key_hash: change.pub_key_hash.clone(),
+        }],
+        version: TX_VERSION,
+        relative_lock_time: 0,
+    };
+    child.id = child.hash()?;
+    child.sign(
+        &owner.secret_key,
+        HashMap::from([(tx.id.clone(), tx.clone())]),
+    )?;
+
+    Ok(child)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::utxo_set::MemoryUtxoSet;
+    use rand_core::OsRng;
+
+    #[test]
+    fn test_bump_fee() {
+        // 测试按序号引用付款(0)和找零(1)输出
+        set_canonical_order(false);
+        let wallet = Wallet::from_seed(&[1; 32]);
+        let other = Wallet::from_seed(&[2; 32]);
+        let coinbase =
+            Transaction::new_coinbase(wallet.get_address(), String::from("bump"), &mut OsRng)
+                .unwrap();
+        let mut utxos = MemoryUtxoSet::default();
+        utxos.add_transaction(coinbase.clone());
+        let prev_txs = HashMap::from([(coinbase.id.clone(), coinbase.clone())]);
+
+        let tx = Transaction::new_utxo(&wallet, &other.get_address(), 5, &utxos).unwrap();
+        assert!(bump_fee(&tx, &[&wallet], 0, &prev_txs, &[]).is_err());
+        assert!(bump_fee(&tx, &[&other], SUBSIDY, &prev_txs, &[]).is_err());
+
+        // 找零减少，重新签名后 txid 改变
+        let (method, replacement) = bump_fee(&tx, &[&other, &wallet], 2, &prev_txs, &[]).unwrap();
+        assert_eq!(method, BumpMethod::Replace);
+        assert_ne!(replacement.id, tx.id);
+        assert_eq!(replacement.vin[0].txid, tx.vin[0].txid);
+        assert_eq!(replacement.vout[1].value, tx.vout[1].value - 2);
+        assert!(replacement.verify(prev_txs.clone()).unwrap());
+
+        // 缺少输入花费的交易或者输出已经被花费时构建子交易
+        utxos.add_transaction(tx.clone());
+        let child = Transaction::new_utxo(&other, &wallet.get_address(), 5, &utxos).unwrap();
+        for (prev_txs, pending) in [(HashMap::new(), vec![]), (prev_txs, vec![child])] {
+            let (method, child) = bump_fee(&tx, &[&wallet], 2, &prev_txs, &pending).unwrap();
+            assert_eq!(method, BumpMethod::ChildPaysForParent);
+            assert_eq!(child.vin[0].txid, tx.id);
+            assert_eq!(child.vin[0].vout, 1);
+            assert_eq!(child.vout[0].value, tx.vout[1].value - 2);
+            assert!(child
+                .verify(HashMap::from([(tx.id.clone(), tx.clone())]))
+                .unwrap());
+        }
+
+        // 收款方没有签名的密钥，只能用收到的输出追加子交易
+        let (method, _) = bump_fee(&tx, &[&other], 2, &HashMap::new(), &[]).unwrap();
+        assert_eq!(method, BumpMethod::ChildPaysForParent);
+        set_canonical_order(true);
+    }
+}
@@ -0,0 +1,445 @@
+// 双方支付通道(单向): 付款方把资金锁定在双方公钥的 2-of-2 多签输出中，
+// 开通时收款方签署一笔带相对锁定时间的退款交易，通道超时后付款方可以单方面取回资金；
+// 每次支付付款方签署一笔新的关闭交易(付给收款方的累计金额递增)并在链下交给收款方，
+// 收款方只保存最新的一笔，在超时前补上自己的签名广播即可关闭通道
+
+use std::collections::HashMap;
+
+use super::Result;
+use crate::{
+    descriptor::Descriptor,
+    transaction::*,
+    utxo_set::UtxoProvider,
+    wallets::{hash_pub_key, Wallet},
+};
+use bincode::{deserialize, serialize};
+use crypto::ed25519;
+use failure::format_err;
+use serde::{Deserialize, Serialize};
+
+// 资金交易中多签输出的序号
+const FUNDING_VOUT: i32 = 0;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum Role {
+    Payer,
+    Payee,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Channel {
+    pub role: Role,
+    pub funding: Transaction,
+    pub payer: Vec<u8>,
+    pub payee: Vec<u8>,
+    pub capacity: i32,
+    // 退款和关闭交易的手续费，从付款方的部分扣除
+    pub fee: i32,
+    // 退款交易的相对锁定时间(区块数)
+    pub timeout: u32,
+    // 收款方对退款交易的签名
+    pub refund_signature: Vec<u8>,
+    // 已支付的累计金额，以及付款方对对应关闭交易的签名
+    pub paid: i32,
+    pub payer_signature: Vec<u8>,
+}
+
+// 链下传递的一次支付
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Payment {
+    pub channel: String,
+    pub paid: i32,
+    pub signature: Vec<u8>,
+}
+
+impl Channel {
+    /**
+     * @desc 付款方创建通道: 资金交易把 capacity 锁定在多签输出中，找零回到付款方，
+     *       资金交易要在收到收款方对退款交易的签名之后再广播
+     */
+    pub fn open(
+        wallet: &Wallet,
+        payee: &[u8],
+        capacity: i32,
+        fee: i32,
+        timeout: u32,
+        utxo: &impl UtxoProvider,
+    ) -> Result<Channel> {
+        if payee.len() != 32 {
+            return Err(format_err!("Invalid payee public key."));
+        }
+        if fee < 0 || capacity <= fee || timeout == 0 {
+            return Err(format_err!(
+                "Invalid channel: capacity {}, fee {}, timeout {}.",
+                capacity,
+                fee,
+                timeout
+            ));
+        }
+
+        let mut pub_key_hash = wallet.public_key.clone();
+        hash_pub_key(&mut pub_key_hash);
+        let (accumulated, outputs) = utxo.find_spendable_outputs(&pub_key_hash, capacity + fee)?;
+        if accumulated < capacity + fee {
+            return Err(format_err!(
+                "Not Enough balance: current balance {}.",
+                accumulated
+            ));
+        }
+
+        let mut channel = Channel {
+            role: Role::Payer,
+            funding: Transaction {
+                id: String::new(),
+                vin: Vec::new(),
+                vout: Vec::new(),
+                version: TX_VERSION,
+                relative_lock_time: 0,
+            },
+            payer: wallet.public_key.clone(),
+            payee: payee.to_vec(),
+            capacity,
+            fee,
+            timeout,
+            refund_signature: Vec::new(),
+            paid: 0,
+            payer_signature: Vec::new(),
+        };
+
+        let mut prev_txs = HashMap::new();
+        for (txid, outs) in outputs {
+            for out in outs {
+                channel.funding.vin.push(TXInput {
+                    txid: txid.clone(),
+                    vout: out,
+                    signature: Vec::new(),
+                    pub_key: wallet.public_key.clone(),
+                });
+            }
+            prev_txs.insert(txid.clone(), utxo.find_transaction(&txid)?);
+        }
+        channel.funding.vout.push(TXOutput {
+            value: capacity,
+            pub_key_hash: channel.descriptor().pub_key_hash(),
+        });
+        if accumulated > capacity + fee {
+            channel.funding.vout.push(TXOutput {
+                value: accumulated - capacity - fee,
+                pub_key_hash,
+            });
+        }
+        channel.funding.id = channel.funding.hash()?;
+        channel.funding.sign(&wallet.secret_key, prev_txs)?;
+
+        Ok(channel)
+    }
+
+    /**
+     * @desc 通道 id 是资金交易的 txid
+     */
+    pub fn id(&self) -> String {
+        self.funding.id.clone()
+    }
+
+    pub fn descriptor(&self) -> Descriptor {
+        Descriptor::Multi {
+            threshold: 2,
+            keys: vec![self.payer.clone(), self.payee.clone()],
+        }
+    }
+
+    /**
+     * @desc 退款交易: 资金全部退回付款方，资金交易确认 timeout 个区块后才能进入区块
+     */
+    pub fn refund_tx(&self) -> Result<Transaction> {
+        self.spend(0, self.timeout)
+    }
+
+    /**
+     * @desc 累计支付 paid 时的关闭交易
+     */
+    pub fn close_tx(&self, paid: i32) -> Result<Transaction> {
+        self.spend(paid, 0)
+    }
+
+    // 花费多签输出，paid 付给收款方，其余扣除手续费后退回付款方
+    fn spend(&self, paid: i32, relative_lock_time: u32) -> Result<Transaction> {
+        let mut vout = Vec::new();
+        if paid > 0 {
+            vout.push(TXOutput {
+                value: paid,
+                pub_key_hash: pub_key_hash(&self.payee),
+            });
+        }
+        if self.capacity - self.fee - paid > 0 {
+            vout.push(TXOutput {
+                value: self.capacity - self.fee - paid,
+                pub_key_hash: pub_key_hash(&self.payer),
+            });
+        }
+
+        let mut tx = Transaction {
+            id: String::new(),
+            vin: vec![TXInput {
+                txid: self.funding.id.clone(),
+                vout: FUNDING_VOUT,
+                signature: Vec::new(),
+                pub_key: self.descriptor().script(),
+            }],
+            vout,
+            version: TX_VERSION_MULTISIG,
+            relative_lock_time,
+        };
+        tx.id = tx.hash()?;
+
+        Ok(tx)
+    }
+
+    fn prev_txs(&self) -> HashMap<String, Transaction> {
+        HashMap::from([(self.funding.id.clone(), self.funding.clone())])
+    }
+
+    // key 对交易唯一输入的签名是否有效
+    fn check_signature(&self, tx: &Transaction, key: &[u8], signature: &[u8]) -> Result<()> {
+        let sighash = tx.signature_hash(0, &self.prev_txs())?;
+        if signature.len() != 64 || !ed25519::verify(sighash.as_bytes(), key, signature) {
+            return Err(format_err!("Invalid signature for channel {}.", self.id()));
+        }
+
+        Ok(())
+    }
+
+    /**
+     * @desc 收款方接受通道: 检查资金输出，签署退款交易，返回收款方保存的通道
+     */
+    pub fn accept(mut self, wallet: &Wallet) -> Result<Channel> {
+        if wallet.public_key != self.payee {
+            return Err(format_err!(
+                "Channel {} does not pay this wallet.",
+                self.id()
+            ));
+        }
+        let funded = self
+            .funding
+            .vout
+            .get(FUNDING_VOUT as usize)
+            .is_some_and(|out| {
+                out.value == self.capacity && out.pub_key_hash == self.descriptor().pub_key_hash()
+            });
+        if !funded {
+            return Err(format_err!(
+                "Channel {} is not funded correctly.",
+                self.id()
+            ));
+        }
+
+        self.role = Role::Payee;
+        self.refund_signature =
+            self.refund_tx()?
+                .sign_input(0, &wallet.secret_key, &self.prev_txs())?;
+        self.paid = 0;
+        self.payer_signature = Vec::new();
+
+        Ok(self)
+    }
+
+    /**
+     * @desc 付款方保存收款方对退款交易的签名，签名有效后才能广播资金交易
+     */
+    pub fn set_refund_signature(&mut self, signature: &[u8]) -> Result<()> {
+        self.check_signature(&self.refund_tx()?, &self.payee, signature)?;
+        self.refund_signature = signature.to_vec();
+
+        Ok(())
+    }
+
+    /**
+     * @desc 付款方支付 amount: 签署累计金额增加后的关闭交易
+     */
+    pub fn pay(&mut self, wallet: &Wallet, amount: i32) -> Result<Payment> {
+        if self.role != Role::Payer || wallet.public_key != self.payer {
+            return Err(format_err!(
+                "Only the payer can pay in channel {}.",
+                self.id()
+            ));
+        }
+        if self.refund_signature.is_empty() {
+            return Err(format_err!(
+                "Channel {} has no refund signature yet.",
+                self.id()
+            ));
+        }
+        let paid = self.paid + amount;
+        if amount <= 0 || paid > self.capacity - self.fee {
+            return Err(format_err!(
+                "Invalid amount {}: paid {} of {}.",
+                amount,
+                self.paid,
+                self.capacity - self.fee
+            ));
+        }
+
+        let signature = self
+            .close_tx(paid)?
+            .sign_input(0, &wallet.secret_key, &self.prev_txs())?;
+        self.paid = paid;
+        self.payer_signature = signature.clone();
+
+        Ok(Payment {
+            channel: self.id(),
+            paid,
+            signature,
+        })
+    }
+
+    /**
+     * @desc 收款方收到支付: 累计金额必须增加，付款方的签名必须有效
+     */
+    pub fn receive(&mut self, payment: &Payment) -> Result<()> {
+        if self.role != Role::Payee || payment.channel != self.id() {
+            return Err(format_err!("Payment is not for channel {}.", self.id()));
+        }
+        if payment.paid <= self.paid || payment.paid > self.capacity - self.fee {
+            return Err(format_err!(
+                "Invalid payment: paid {} after {}.",
+                payment.paid,
+                self.paid
+            ));
+        }
+        self.check_signature(
+            &self.close_tx(payment.paid)?,
+            &self.payer,
+            &payment.signature,
+        )?;
+
+        self.paid = payment.paid;
+        self.payer_signature = payment.signature.clone();
+
+        Ok(())
+    }
+
+    /**
+     * @desc 关闭通道: 收款方补上签名广播最新的关闭交易(双方签名)，
+     *       付款方补上签名广播退款交易(超时后单方关闭)
+     */
+    pub fn close(&self, wallet: &Wallet) -> Result<Transaction> {
+        let (mut tx, signatures) = match self.role {
+            Role::Payee => {
+                if self.paid == 0 {
+                    return Err(format_err!("Nothing paid in channel {}.", self.id()));
+                }
+                let tx = self.close_tx(self.paid)?;
+                let signature = tx.sign_input(0, &wallet.secret_key, &self.prev_txs())?;
+                (tx, [self.payer_signature.clone(), signature].concat())
+            }
+            Role::Payer => {
+                let tx = self.refund_tx()?;
+                let signature = tx.sign_input(0, &wallet.secret_key, &self.prev_txs())?;
+                (tx, [signature, self.refund_signature.clone()].concat())
+            }
+        };
+        tx.vin[0].signature = signatures;
+
+        Ok(tx)
+    }
+
+    pub fn to_hex(&self) -> Result<String> {
+        Ok(encode_hex(&serialize(self)?))
+    }
+
+    pub fn from_hex(hex: &str) -> Result<Channel> {
+        let data = decode_hex(hex).ok_or_else(|| format_err!("Invalid channel hex."))?;
+
+        Ok(deserialize(&data)?)
+    }
+}
+
+impl Payment {
+    pub fn to_hex(&self) -> Result<String> {
+        Ok(encode_hex(&serialize(self)?))
+    }
+
+    pub fn from_hex(hex: &str) -> Result<Payment> {
+        let data = decode_hex(hex).ok_or_else(|| format_err!("Invalid payment hex."))?;
+
+        Ok(deserialize(&data)?)
+    }
+}
+
+fn pub_key_hash(pub_key: &[u8]) -> Vec<u8> {
+    let mut pub_key_hash = pub_key.to_vec();
+    hash_pub_key(&mut pub_key_hash);
+
+    pub_key_hash
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::utxo_set::MemoryUtxoSet;
+    use rand_core::OsRng;
+
+    #[test]
+    fn test_channel() {
+        let payer = Wallet::from_seed(&[1; 32]);
+        let payee = Wallet::from_seed(&[2; 32]);
+        let mut utxos = MemoryUtxoSet::default();
+        utxos.add_transaction(
+            Transaction::new_coinbase(payer.get_address(), String::from("channel"), &mut OsRng)
+                .unwrap(),
+        );
+
+        let mut channel = Channel::open(&payer, &payee.public_key, 8, 1, 10, &utxos).unwrap();
+        assert_eq!(
+            channel.funding.vout[0].pub_key_hash,
+            channel.descriptor().pub_key_hash()
+        );
+        assert_eq!(channel.funding.vout[1].value, SUBSIDY - 9);
+
+        // 经过序列化传给收款方
+        let mut accepted = Channel::from_hex(&channel.to_hex().unwrap())
+            .unwrap()
+            .accept(&payee)
+            .unwrap();
+        assert!(Channel::from_hex(&channel.to_hex().unwrap())
+            .unwrap()
+            .accept(&payer)
+            .is_err());
+
+        assert!(channel.pay(&payer, 1).is_err());
+        assert!(channel.set_refund_signature(&[0; 64]).is_err());
+        channel
+            .set_refund_signature(&accepted.refund_signature)
+            .unwrap();
+
+        for amount in [2, 3] {
+            let payment = channel.pay(&payer, amount).unwrap();
+            let payment = Payment::from_hex(&payment.to_hex().unwrap()).unwrap();
+            accepted.receive(&payment).unwrap();
+            // 旧的支付不能重放
+            assert!(accepted.receive(&payment).is_err());
+        }
+        assert_eq!(accepted.paid, 5);
+        assert!(channel.pay(&payer, 3).is_err());
+
+        let mut forged = channel.pay(&payer, 1).unwrap();
+        forged.paid = 7;
+        assert!(accepted.receive(&forged).is_err());
+
+        let prev_txs = channel.prev_txs();
+        let close = accepted.close(&payee).unwrap();
+        assert_eq!(close.vout[0].value, 5);
+        assert_eq!(close.relative_lock_time, 0);
+        assert!(close.verify(prev_txs.clone()).unwrap());
+
+        let refund = channel.close(&payer).unwrap();
+        assert_eq!(refund.vout[0].value, 7);
+        assert_eq!(refund.relative_lock_time, 10);
+        assert!(refund.verify(prev_txs.clone()).unwrap());
+
+        // 只有一方的签名不能花费多签输出
+        let mut partial = refund;
+        partial.vin[0].signature.truncate(64);
+        assert!(!partial.verify(prev_txs).unwrap());
+    }
+}
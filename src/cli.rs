@@ -1,11 +1,66 @@
-use std::process::exit;
+use std::{
+    collections::HashMap,
+    io::Write,
+    net::TcpListener,
+    path::Path,
+    process::exit,
+    sync::RwLock,
+    time::{Duration, SystemTime},
+};
 
 use super::Result;
-use crate::{blockchain::*, server::*, transaction::*, utxo_set::*, wallets::*};
-use bitcoincash_addr::Address;
-use clap::{Arg, Command};
+use crate::{
+    addrbook::PEERS_DB,
+    alert::Alert,
+    anchor::{Anchor, Attestation},
+    auth::*,
+    block::Block,
+    blockchain::*,
+    bumpfee::{bump_fee, BumpMethod},
+    channel::{Channel, Payment, Role},
+    config::*,
+    consolidate::*,
+    descriptor::Descriptor,
+    dnsseed,
+    export::{self, ExportFormat},
+    fixtures,
+    genesis::*,
+    governance::GovernanceRecord,
+    hash,
+    identity::{parse_trusted_node, NodeIdentity, IDENTITY_FILE},
+    mdns,
+    names::NameRecord,
+    network::*,
+    paper::{self, PaperFormat},
+    payment::*,
+    psbt::Psbt,
+    qr::QrCode,
+    reward::RewardSplit,
+    rpc,
+    server::*,
+    sigverify, stats, tor,
+    transaction::*,
+    units::{self, format_amount, parse_amount, Units},
+    utxo_set::*,
+    vanity,
+    vault::{Vault, VaultState},
+    vm,
+    wallets::*,
+};
+use clap::{Arg, ArgMatches, Command, ValueSource};
+use failure::format_err;
 use log::info;
+use rand::{rngs::StdRng, SeedableRng};
+use rand_core::{OsRng, RngCore};
 
+const DEFAULT_REST_BIND: &str = "127.0.0.1";
+const WATCH_BLOCKS_TIMEOUT: u64 = 60;
+// 轻钱包模式使用的远程节点
+static LIGHT_NODE: RwLock<Option<String>> = RwLock::new(None);
+// 锚定目标是其他链节点的 RPC 地址时的前缀
+const ANCHOR_RPC_PREFIX: &str = "rpc://";
+
+#[derive(Default)]
 pub struct Cli {}
 
 impl Cli {
@@ -20,56 +75,894 @@ impl Cli {
             .version("0.1.0")
             .author("SummerSnake")
             .about("A simple blockchain for learning.")
+            .arg(
+                Arg::new("config")
+                    .long("config")
+                    .global(true)
+                    .takes_value(true)
+                    .default_value(DEFAULT_CONFIG_PATH)
+                    .help("Path of the node config file."),
+            )
+            .arg(
+                Arg::new("rpc_user")
+                    .long("rpc_user")
+                    .global(true)
+                    .takes_value(true)
+                    .help("User name for RPC calls, defaults to the cookie file."),
+            )
+            .arg(
+                Arg::new("rpc_password")
+                    .long("rpc_password")
+                    .global(true)
+                    .takes_value(true)
+                    .help("Password for RPC calls."),
+            )
+            .arg(
+                Arg::new("network")
+                    .long("network")
+                    .global(true)
+                    .takes_value(true)
+                    .possible_values(["main", "test", "regtest"])
+                    .help("Network to use, decides the consensus parameters."),
+            )
+            .arg(
+                Arg::new("seed")
+                    .long("seed")
+                    .global(true)
+                    .takes_value(true)
+                    .help("RNG seed for new wallets and coinbase transactions, for reproducible demos."),
+            )
+            .arg(
+                Arg::new("units")
+                    .long("units")
+                    .global(true)
+                    .takes_value(true)
+                    .possible_values(["base", "coins"])
+                    .help("Units of amounts and fees on the command line and in JSON output."),
+            )
+            .arg(
+                Arg::new("decimals")
+                    .long("decimals")
+                    .global(true)
+                    .takes_value(true)
+                    .help("Decimals of a coin when --units is coins."),
+            )
+            .arg(
+                Arg::new("light")
+                    .long("light")
+                    .global(true)
+                    .help("Never open the chain databases, query and broadcast through a remote node."),
+            )
+            .arg(
+                Arg::new("light_node")
+                    .long("light_node")
+                    .global(true)
+                    .takes_value(true)
+                    .help("RPC address of the remote node in --light mode, defaults to the local node on the network's default port."),
+            )
+            .arg(
+                Arg::new("bootstrap")
+                    .long("bootstrap")
+                    .global(true)
+                    .takes_value(true)
+                    .help("Bootstrap node (host:port), defaults to localhost on the network's default port."),
+            )
             .subcommand(Command::new("print_chain").about("Print all the chain blocks."))
-            .subcommand(Command::new("create_wallets").about("Create a wallet."))
+            .subcommand(
+                Command::new("export")
+                    .about("Export blocks, transactions, inputs and outputs of the main chain as flat files for analysis.")
+                    .arg(
+                        Arg::new("format")
+                            .long("format")
+                            .takes_value(true)
+                            .default_value("csv")
+                            .help("csv or jsonl."),
+                    )
+                    .arg(Arg::new("path").required(true).help("Directory to write the files to.")),
+            )
+            .subcommand(
+                Command::new("create_wallets").about("Create a wallet.").arg(
+                    Arg::new("name")
+                        .long("name")
+                        .takes_value(true)
+                        .help("Create a named wallet that derives a new address from its seed on demand."),
+                ),
+            )
             .subcommand(Command::new("list_addresses").about("List all addresses."))
-            .subcommand(Command::new("reindex").about("Reindex UTXO."))
+            .subcommand(
+                Command::new("vanity_address")
+                    .about("Search for a key whose address starts with a prefix and add it to the wallet.")
+                    .arg(
+                        Arg::new("prefix")
+                            .required(true)
+                            .help("Address prefix, including the leading character every address shares."),
+                    )
+                    .arg(
+                        Arg::new("threads")
+                            .long("threads")
+                            .takes_value(true)
+                            .default_value("0")
+                            .help("Worker threads, 0 uses every core."),
+                    ),
+            )
+            .subcommand(
+                Command::new("derive_address")
+                    .about("Derive the address of a descriptor, e.g. pkh(<key>) or multi(2,<k1>,<k2>).")
+                    .arg(Arg::new("descriptor").required(true)),
+            )
+            .subcommand(
+                Command::new("import_descriptor")
+                    .about("Import a descriptor into the wallet as a watch-only address.")
+                    .arg(Arg::new("descriptor").required(true)),
+            )
+            .subcommand(Command::new("audit_wallet").about(
+                "Re-derive every wallet address from its key, seed or descriptor and report corrupted or orphaned entries.",
+            ))
+            .subcommand(
+                Command::new("list_descriptors")
+                    .about("List the descriptors of all wallet addresses, watch-only included."),
+            )
+            .subcommand(
+                Command::new("show_address")
+                    .about("Show a wallet address as a payment request, optionally as a QR code.")
+                    .arg(Arg::new("address").required(true))
+                    .arg(
+                        Arg::new("amount")
+                            .long("amount")
+                            .takes_value(true)
+                            .help("Amount to request."),
+                    )
+                    .arg(
+                        Arg::new("label")
+                            .long("label")
+                            .takes_value(true)
+                            .help("Label for the payment request."),
+                    )
+                    .arg(
+                        Arg::new("qr")
+                            .long("qr")
+                            .help("Render the payment request as a QR code."),
+                    ),
+            )
+            .subcommand(
+                Command::new("export_paper_wallet")
+                    .about("Export a wallet key as a printable paper wallet with QR codes for cold storage.")
+                    .arg(Arg::new("address").required(true))
+                    .arg(
+                        Arg::new("passphrase")
+                            .long("passphrase")
+                            .takes_value(true)
+                            .help("Encrypt the private key with a passphrase."),
+                    )
+                    .arg(
+                        Arg::new("format")
+                            .long("format")
+                            .takes_value(true)
+                            .default_value("text")
+                            .help("text or html."),
+                    )
+                    .arg(
+                        Arg::new("output")
+                            .long("output")
+                            .takes_value(true)
+                            .help("File to write the paper wallet to instead of printing it."),
+                    ),
+            )
+            .subcommand(
+                Command::new("import_paper_wallet")
+                    .about("Import the private key of a paper wallet into the wallet.")
+                    .arg(Arg::new("key").required(true))
+                    .arg(
+                        Arg::new("passphrase")
+                            .long("passphrase")
+                            .takes_value(true)
+                            .help("Passphrase of an encrypted private key."),
+                    ),
+            )
+            .subcommand(
+                Command::new("reindex").about("Reindex UTXO.").arg(
+                    Arg::new("threads")
+                        .long("threads")
+                        .takes_value(true)
+                        .help("Scan block ranges on this many threads, 0 uses every core."),
+                ),
+            )
             .subcommand(
                 Command::new("get_balance")
                     .about("Get balance in the blockchain.")
-                    .arg(Arg::new("address").takes_value(true)),
+                    .arg(Arg::new("address").takes_value(true))
+                    .arg(remote_node_arg()),
+            )
+            .subcommand(
+                Command::new("get_wallet_balance")
+                    .about("Get the total balance of every address in the wallet.")
+                    .arg(
+                        Arg::new("verbose")
+                            .long("verbose")
+                            .help("Also print the balance of each address."),
+                    )
+                    .arg(remote_node_arg()),
+            )
+            .subcommand(
+                Command::new("list_address_balances")
+                    .about("List every address with a non-zero balance, largest first.")
+                    .arg(remote_node_arg()),
+            )
+            .subcommand(
+                Command::new("analyze_utxos")
+                    .about("Report the count and value distribution of the wallet's outputs.")
+                    .arg(
+                        Arg::new("address")
+                            .long("address")
+                            .takes_value(true)
+                            .help("Only analyze this wallet address."),
+                    )
+                    .arg(
+                        Arg::new("dust_threshold")
+                            .long("dust_threshold")
+                            .takes_value(true)
+                            .help("Outputs worth at most this value are counted as small."),
+                    )
+                    .arg(remote_node_arg()),
             )
             .subcommand(
                 Command::new("create_blockchain")
                     .about("Create blockchain.")
-                    .arg(Arg::new("address")),
+                    .arg(Arg::new("address"))
+                    .arg(
+                        Arg::new("genesis")
+                            .long("genesis")
+                            .takes_value(true)
+                            .help("Path of the genesis spec file."),
+                    )
+                    .arg(
+                        Arg::new("allocate")
+                            .long("allocate")
+                            .takes_value(true)
+                            .multiple_occurrences(true)
+                            .help("Initial allocation <address>:<amount>, may be repeated."),
+                    )
+                    .arg(
+                        Arg::new("force")
+                            .long("force")
+                            .help("Replace the existing blockchain."),
+                    ),
+            )
+            .subcommand(
+                Command::new("wipe_chain")
+                    .about("Delete the blockchain and UTXO set, wallets are kept.")
+                    .arg(
+                        Arg::new("yes")
+                            .long("yes")
+                            .help("Do not ask for confirmation."),
+                    ),
             )
             .subcommand(
                 Command::new("send")
                     .about("Send in the blockchain.")
                     .arg(Arg::new("from"))
-                    .arg(Arg::new("to"))
-                    .arg(Arg::new("amount")),
+                    .arg(Arg::new("to").help("Address or payment request URI."))
+                    .arg(Arg::new("amount"))
+                    .arg(
+                        Arg::new("mine")
+                            .long("mine")
+                            .help("Mine the transaction into a block locally."),
+                    )
+                    .arg(
+                        Arg::new("raw")
+                            .long("raw")
+                            .help("Print the signed transaction as hex instead of sending it."),
+                    )
+                    .arg(
+                        Arg::new("note")
+                            .long("note")
+                            .takes_value(true)
+                            .help("Private note saved in the wallet, defaults to the payment request label."),
+                    )
+                    .arg(remote_node_arg()),
+            )
+            .subcommand(
+                Command::new("sweep")
+                    .about("Send every spendable output of the wallet to one address.")
+                    .arg(Arg::new("destination").required(true))
+                    .arg(
+                        Arg::new("from")
+                            .long("from")
+                            .takes_value(true)
+                            .help("Only sweep this wallet address."),
+                    )
+                    .arg(
+                        Arg::new("fee_rate")
+                            .long("fee_rate")
+                            .default_value("0")
+                            .help("Fee per 1000 bytes, taken from the swept amount."),
+                    )
+                    .arg(
+                        Arg::new("raw")
+                            .long("raw")
+                            .help("Print the signed transactions as hex instead of sending them."),
+                    )
+                    .arg(remote_node_arg()),
+            )
+            .subcommand(
+                Command::new("register_name")
+                    .about("Register or renew a name pointing to an address.")
+                    .arg(Arg::new("from").required(true).help("Wallet address paying the fee."))
+                    .arg(Arg::new("name").required(true))
+                    .arg(
+                        Arg::new("to")
+                            .long("to")
+                            .takes_value(true)
+                            .help("Address the name points to, defaults to the paying address."),
+                    )
+                    .arg(
+                        Arg::new("fee")
+                            .long("fee")
+                            .default_value("1")
+                            .help("Fee paid by the registration."),
+                    )
+                    .arg(
+                        Arg::new("raw")
+                            .long("raw")
+                            .help("Print the signed transaction as hex instead of sending it."),
+                    )
+                    .arg(remote_node_arg()),
+            )
+            .subcommand(
+                Command::new("create_proposal")
+                    .about("Create a proposal that coin holders can vote on.")
+                    .arg(Arg::new("from").required(true).help("Wallet address paying the fee."))
+                    .arg(Arg::new("title").required(true))
+                    .arg(
+                        Arg::new("fee")
+                            .long("fee")
+                            .default_value("1")
+                            .help("Fee paid by the proposal."),
+                    )
+                    .arg(
+                        Arg::new("raw")
+                            .long("raw")
+                            .help("Print the signed transaction as hex instead of sending it."),
+                    )
+                    .arg(remote_node_arg()),
+            )
+            .subcommand(
+                Command::new("vote")
+                    .about("Vote on a proposal, weighted by the balance at the proposal height.")
+                    .arg(Arg::new("from").required(true).help("Voting wallet address."))
+                    .arg(Arg::new("proposal").required(true).help("Txid of the proposal."))
+                    .arg(
+                        Arg::new("choice")
+                            .required(true)
+                            .possible_values(["yes", "no"]),
+                    )
+                    .arg(
+                        Arg::new("fee")
+                            .long("fee")
+                            .default_value("1")
+                            .help("Fee paid by the vote."),
+                    )
+                    .arg(
+                        Arg::new("raw")
+                            .long("raw")
+                            .help("Print the signed transaction as hex instead of sending it."),
+                    )
+                    .arg(remote_node_arg()),
+            )
+            .subcommand(
+                Command::new("open_channel")
+                    .about("Create a payment channel funded by a wallet address, printed as hex for the payee.")
+                    .arg(Arg::new("from").required(true).help("Wallet address of the payer."))
+                    .arg(
+                        Arg::new("payee")
+                            .required(true)
+                            .help("Descriptor pkh(<public key>) of the payee, see list_descriptors."),
+                    )
+                    .arg(Arg::new("capacity").required(true))
+                    .arg(
+                        Arg::new("fee")
+                            .long("fee")
+                            .default_value("1")
+                            .help("Fee paid by the funding transaction and again by the closing transaction."),
+                    )
+                    .arg(
+                        Arg::new("timeout")
+                            .long("timeout")
+                            .default_value("144")
+                            .help("Blocks after funding before the payer can take the funds back."),
+                    )
+                    .arg(remote_node_arg()),
+            )
+            .subcommand(
+                Command::new("accept_channel")
+                    .about("Accept a channel as the payee, printing the refund signature for the payer.")
+                    .arg(Arg::new("channel").required(true).help("Hex printed by open_channel.")),
+            )
+            .subcommand(
+                Command::new("fund_channel")
+                    .about("Store the payee's refund signature and broadcast the funding transaction.")
+                    .arg(Arg::new("id").required(true))
+                    .arg(Arg::new("signature").required(true).help("Hex printed by accept_channel."))
+                    .arg(remote_node_arg()),
+            )
+            .subcommand(
+                Command::new("channel_pay")
+                    .about("Pay through a channel off-chain, printing the payment as hex for the payee.")
+                    .arg(Arg::new("id").required(true))
+                    .arg(Arg::new("amount").required(true)),
+            )
+            .subcommand(
+                Command::new("channel_receive")
+                    .about("Check and store a payment received through a channel.")
+                    .arg(Arg::new("payment").required(true).help("Hex printed by channel_pay.")),
+            )
+            .subcommand(
+                Command::new("close_channel")
+                    .about("Close a channel as the payee with the latest payment.")
+                    .arg(Arg::new("id").required(true))
+                    .arg(
+                        Arg::new("refund")
+                            .long("refund")
+                            .help("Close as the payer with the refund, valid after the timeout."),
+                    )
+                    .arg(
+                        Arg::new("raw")
+                            .long("raw")
+                            .help("Print the signed transaction as hex instead of sending it."),
+                    )
+                    .arg(remote_node_arg()),
+            )
+            .subcommand(Command::new("list_channels").about("List the payment channels in the wallet."))
+            .subcommand(
+                Command::new("vault_deposit")
+                    .about("Lock funds in a vault: the recovery key can take them any time, the normal key only after a delay.")
+                    .arg(Arg::new("from").required(true).help("Wallet address of the normal key, paying the deposit."))
+                    .arg(Arg::new("recovery").required(true).help("Wallet address of the recovery key."))
+                    .arg(Arg::new("amount").required(true))
+                    .arg(
+                        Arg::new("delay")
+                            .long("delay")
+                            .default_value("144")
+                            .help("Blocks between triggering a withdrawal and completing it."),
+                    )
+                    .arg(
+                        Arg::new("fee")
+                            .long("fee")
+                            .default_value("1")
+                            .help("Fee paid by the deposit and again by the trigger transaction."),
+                    )
+                    .arg(remote_node_arg()),
+            )
+            .subcommand(
+                Command::new("vault_trigger")
+                    .about("Start a withdrawal from a vault, moving the funds to the delayed output.")
+                    .arg(Arg::new("id").required(true))
+                    .arg(remote_node_arg()),
+            )
+            .subcommand(
+                Command::new("vault_withdraw")
+                    .about("Complete a withdrawal after the delay, paying everything but the fee to an address.")
+                    .arg(Arg::new("id").required(true))
+                    .arg(Arg::new("to").required(true))
+                    .arg(Arg::new("fee").long("fee").default_value("1"))
+                    .arg(remote_node_arg()),
+            )
+            .subcommand(
+                Command::new("vault_cancel")
+                    .about("Move the vault funds to the recovery key, before or during a withdrawal.")
+                    .arg(Arg::new("id").required(true))
+                    .arg(Arg::new("fee").long("fee").default_value("1"))
+                    .arg(remote_node_arg()),
+            )
+            .subcommand(Command::new("list_vaults").about("List the vaults in the wallet."))
+            .subcommand(
+                Command::new("program_address")
+                    .about("Show the address of outputs locked by a program, e.g. \"OP_SHA256 0x<hash> OP_EQUAL\".")
+                    .arg(Arg::new("program").required(true)),
+            )
+            .subcommand(
+                Command::new("spend_program")
+                    .about("Spend an output locked by a program, paying everything but the fee to an address.")
+                    .arg(Arg::new("txid").required(true))
+                    .arg(Arg::new("vout").required(true))
+                    .arg(Arg::new("program").required(true))
+                    .arg(
+                        Arg::new("witness")
+                            .required(true)
+                            .help("Data pushed before the program runs, e.g. \"0x<preimage>\"."),
+                    )
+                    .arg(Arg::new("to").required(true))
+                    .arg(
+                        Arg::new("fee")
+                            .long("fee")
+                            .default_value("1")
+                            .help("Fee taken from the spent output."),
+                    )
+                    .arg(
+                        Arg::new("raw")
+                            .long("raw")
+                            .help("Print the transaction as hex instead of sending it."),
+                    )
+                    .arg(remote_node_arg()),
+            )
+            .subcommand(
+                Command::new("anchor")
+                    .about("Anchor the best block hash into another chain or a signed attestation file.")
+                    .arg(Arg::new("from").required(true).help("Wallet address paying the fee or signing."))
+                    .arg(
+                        Arg::new("target")
+                            .long("target")
+                            .required(true)
+                            .takes_value(true)
+                            .help("File to write the attestation to, or rpc://<host:port> of a node of the other chain."),
+                    )
+                    .arg(
+                        Arg::new("fee")
+                            .long("fee")
+                            .default_value("1")
+                            .help("Fee paid on the other chain."),
+                    )
+                    .arg(remote_node_arg()),
+            )
+            .subcommand(
+                Command::new("verify_anchor")
+                    .about("Check that an anchored block is still in the main chain.")
+                    .arg(
+                        Arg::new("target")
+                            .long("target")
+                            .required(true)
+                            .takes_value(true)
+                            .help("Attestation file, or rpc://<host:port> of a node of the other chain."),
+                    )
+                    .arg(
+                        Arg::new("txid")
+                            .long("txid")
+                            .takes_value(true)
+                            .help("Anchor transaction on the other chain, required with rpc://."),
+                    )
+                    .arg(remote_node_arg()),
+            )
+            .subcommand(
+                Command::new("tally")
+                    .about("Tally the votes of a proposal.")
+                    .arg(Arg::new("proposal").required(true))
+                    .arg(Arg::new("node").default_value(rpc::DEFAULT_RPC_NODE)),
+            )
+            .subcommand(
+                Command::new("resolve_name")
+                    .about("Show the address a registered name points to.")
+                    .arg(Arg::new("name").required(true))
+                    .arg(Arg::new("node").default_value(rpc::DEFAULT_RPC_NODE)),
+            )
+            .subcommand(
+                Command::new("list_wallet_txs")
+                    .about("List pending and noted wallet transactions, with the confirmed transactions they conflict with.")
+                    .arg(remote_node_arg()),
+            )
+            .subcommand(
+                Command::new("abandon_tx")
+                    .about("Stop rebroadcasting an unconfirmed wallet transaction and its children, and drop them from the running node's mempool.")
+                    .arg(Arg::new("txid").required(true))
+                    .arg(remote_node_arg()),
+            )
+            .subcommand(
+                Command::new("bump_fee")
+                    .about("Raise the fee of an unconfirmed wallet transaction by replacing it or by spending its change in a child transaction.")
+                    .arg(Arg::new("txid").required(true))
+                    .arg(Arg::new("fee").long("fee").default_value("1").help("Extra fee to pay."))
+                    .arg(remote_node_arg()),
+            )
+            .subcommand(
+                Command::new("send_raw_transaction")
+                    .about("Submit a hex encoded transaction to a running node's mempool.")
+                    .arg(Arg::new("hex").required(true))
+                    .arg(Arg::new("node").default_value(rpc::DEFAULT_RPC_NODE)),
+            )
+            .subcommand(
+                Command::new("test_mempool_accept")
+                    .about("Check whether a running node would accept a hex encoded transaction.")
+                    .arg(Arg::new("hex").required(true))
+                    .arg(Arg::new("node").default_value(rpc::DEFAULT_RPC_NODE)),
+            )
+            .subcommand(
+                Command::new("generate")
+                    .about("Mine blocks to an address immediately, regtest only.")
+                    .arg(Arg::new("address").required(true))
+                    .arg(Arg::new("count").default_value("1")),
             )
             .subcommand(
                 Command::new("start_node")
                     .about("Start the node server.")
-                    .arg(Arg::new("port").takes_value(true)),
+                    .arg(
+                        Arg::new("port")
+                            .takes_value(true)
+                            .help("Listening port, defaults to the network's default port, 0 picks a free port."),
+                    )
+                    .args(node_args()),
             )
             .subcommand(
                 Command::new("start_miner")
                     .about("Start the miner server.")
                     .arg(Arg::new("port"))
-                    .arg(Arg::new("address")),
+                    .arg(Arg::new("address").help(
+                        "Address to pay block rewards to, or the name of a wallet to pay each block to a new address of.",
+                    ))
+                    .args(node_args()),
+            )
+            .subcommand(
+                Command::new("get_peer_info")
+                    .about("Get peer info from a running node.")
+                    .arg(Arg::new("node").default_value(rpc::DEFAULT_RPC_NODE)),
+            )
+            .subcommand(
+                Command::new("get_network_info")
+                    .about("Get network info from a running node.")
+                    .arg(Arg::new("node").default_value(rpc::DEFAULT_RPC_NODE)),
+            )
+            .subcommand(
+                Command::new("get_net_totals")
+                    .about("Get bytes sent and received per message type and per peer from a running node.")
+                    .arg(Arg::new("node").default_value(rpc::DEFAULT_RPC_NODE)),
+            )
+            .subcommand(
+                Command::new("get_sync_status")
+                    .about("Get block sync status from a running node.")
+                    .arg(Arg::new("node").default_value(rpc::DEFAULT_RPC_NODE)),
+            )
+            .subcommand(
+                Command::new("get_blockchain_info")
+                    .about("Get blockchain info from a running node.")
+                    .arg(Arg::new("node").default_value(rpc::DEFAULT_RPC_NODE)),
+            )
+            .subcommand(
+                Command::new("get_mining_info")
+                    .about("Get hash rate and mining statistics from a running node.")
+                    .arg(Arg::new("node").default_value(rpc::DEFAULT_RPC_NODE)),
+            )
+            .subcommand(
+                Command::new("get_transaction")
+                    .about("Get a transaction and its confirmations from a running node.")
+                    .arg(Arg::new("txid").required(true))
+                    .arg(Arg::new("node").default_value(rpc::DEFAULT_RPC_NODE)),
+            )
+            .subcommand(
+                Command::new("get_block_stats")
+                    .about("Get transaction, fee rate and size statistics of a block.")
+                    .arg(
+                        Arg::new("block")
+                            .required(true)
+                            .help("Block height or hash."),
+                    )
+                    .arg(Arg::new("node").default_value(rpc::DEFAULT_RPC_NODE)),
+            )
+            .subcommand(
+                Command::new("get_chain_stats")
+                    .about("Aggregate block statistics over a range of heights.")
+                    .arg(Arg::new("node").default_value(rpc::DEFAULT_RPC_NODE))
+                    .arg(
+                        Arg::new("from")
+                            .long("from")
+                            .takes_value(true)
+                            .default_value("0")
+                            .help("First height of the range."),
+                    )
+                    .arg(
+                        Arg::new("to")
+                            .long("to")
+                            .takes_value(true)
+                            .help("Last height of the range, defaults to the best height."),
+                    ),
+            )
+            .subcommand(
+                Command::new("chain_stats")
+                    .about("Block interval, difficulty, transaction throughput and fees per window of blocks, to measure PoW tuning.")
+                    .arg(Arg::new("node").default_value(rpc::DEFAULT_RPC_NODE))
+                    .arg(
+                        Arg::new("window")
+                            .long("window")
+                            .takes_value(true)
+                            .default_value("10")
+                            .help("Number of blocks per window."),
+                    )
+                    .arg(
+                        Arg::new("from")
+                            .long("from")
+                            .takes_value(true)
+                            .default_value("0")
+                            .help("First height of the range."),
+                    )
+                    .arg(
+                        Arg::new("to")
+                            .long("to")
+                            .takes_value(true)
+                            .help("Last height of the range, defaults to the best height."),
+                    )
+                    .arg(
+                        Arg::new("csv")
+                            .long("csv")
+                            .help("Print the windows as CSV for plotting."),
+                    ),
+            )
+            .subcommand(
+                Command::new("get_chain_tips")
+                    .about("List every known chain tip, including forks and invalid blocks.")
+                    .arg(Arg::new("node").default_value(rpc::DEFAULT_RPC_NODE)),
+            )
+            .subcommand(
+                Command::new("get_fork_stats")
+                    .about("Get reorg depth and orphan block counters from a running node.")
+                    .arg(Arg::new("node").default_value(rpc::DEFAULT_RPC_NODE)),
+            )
+            .subcommand(
+                Command::new("simulate_partition")
+                    .about("Regtest only: make a running node ignore the given peers, or reconnect all of them when none are given.")
+                    .arg(Arg::new("peers").multiple_values(true))
+                    .arg(
+                        Arg::new("node")
+                            .long("node")
+                            .default_value(rpc::DEFAULT_RPC_NODE),
+                    ),
+            )
+            .subcommand(
+                Command::new("send_alert")
+                    .about("Sign an operator announcement with the alert key and broadcast it through a running node.")
+                    .arg(Arg::new("from").required(true).help("Wallet address of the alert key."))
+                    .arg(Arg::new("message").required(true))
+                    .arg(
+                        Arg::new("id")
+                            .long("id")
+                            .takes_value(true)
+                            .required(true)
+                            .help("Alert id, an id is only relayed once."),
+                    )
+                    .arg(
+                        Arg::new("expires")
+                            .long("expires")
+                            .takes_value(true)
+                            .default_value("24")
+                            .help("Hours until the alert expires."),
+                    )
+                    .arg(Arg::new("node").default_value(rpc::DEFAULT_RPC_NODE)),
+            )
+            .subcommand(
+                Command::new("create_psbt")
+                    .about("Spend from a multisig address, signing with local keys and sending the partial signatures to the co-signers through a running node.")
+                    .arg(Arg::new("from").required(true).help("Multisig address imported with import_descriptor."))
+                    .arg(Arg::new("to").required(true))
+                    .arg(Arg::new("amount").required(true))
+                    .arg(Arg::new("fee").long("fee").default_value("1"))
+                    .arg(Arg::new("node").default_value(rpc::DEFAULT_RPC_NODE)),
+            )
+            .subcommand(
+                Command::new("sign_psbt")
+                    .about("Add the signatures of local keys to a partially signed transaction received from a co-signer.")
+                    .arg(Arg::new("id").required(true))
+                    .arg(Arg::new("node").default_value(rpc::DEFAULT_RPC_NODE)),
+            )
+            .subcommand(Command::new("list_psbts").about("List the partially signed transactions waiting for signatures."))
+            .subcommand(
+                Command::new("submit_block")
+                    .about("Submit a hex-encoded block to a running node.")
+                    .arg(Arg::new("hex").required(true))
+                    .arg(Arg::new("node").default_value(rpc::DEFAULT_RPC_NODE)),
+            )
+            .subcommand(
+                Command::new("get_block")
+                    .about("Get a block's header, miner tag and transaction ids from a running node.")
+                    .arg(
+                        Arg::new("block")
+                            .required(true)
+                            .help("Block height or hash."),
+                    )
+                    .arg(Arg::new("node").default_value(rpc::DEFAULT_RPC_NODE)),
+            )
+            .subcommand(
+                Command::new("get_block_raw")
+                    .about("Get a block from a running node as hex.")
+                    .arg(
+                        Arg::new("block")
+                            .required(true)
+                            .help("Block height or hash."),
+                    )
+                    .arg(Arg::new("node").default_value(rpc::DEFAULT_RPC_NODE)),
+            )
+            .subcommand(
+                Command::new("invalidate_block")
+                    .about("Mark a block and its descendants invalid, reorganizing away from it.")
+                    .arg(Arg::new("hash").required(true))
+                    .arg(Arg::new("node").default_value(rpc::DEFAULT_RPC_NODE)),
+            )
+            .subcommand(
+                Command::new("reconsider_block")
+                    .about("Clear the invalid mark of a block and choose the best chain again.")
+                    .arg(Arg::new("hash").required(true))
+                    .arg(Arg::new("node").default_value(rpc::DEFAULT_RPC_NODE)),
+            )
+            .subcommand(
+                Command::new("watch_blocks")
+                    .about("Print new blocks as a running node accepts them.")
+                    .arg(Arg::new("node").default_value(rpc::DEFAULT_RPC_NODE)),
+            )
+            .subcommand(
+                Command::new("bench_pow")
+                    .about("Compare mining throughput of the PoW hash algorithms.")
+                    .arg(Arg::new("seconds").default_value("3")),
+            )
+            .subcommand(
+                Command::new("gen_fixtures")
+                    .about("Write the deterministic test chain as JSON fixtures for unit tests.")
+                    .arg(Arg::new("dir").default_value(fixtures::DEFAULT_FIXTURES_DIR)),
             )
             .get_matches();
 
+        let config = Config::load(matches.get_one::<String>("config").unwrap())?;
+        if let Some(name) = matches.get_one::<String>("network") {
+            set_network(Network::from_name(name)?);
+        } else if let Some(name) = config.get_str("network") {
+            set_network(Network::from_name(&name)?);
+        }
+        // cookie 文件在网络数据目录中，需要先确定网络
+        let credentials = rpc_credentials(&matches, &config);
+        units::set_units(cli_units(&matches, &config)?);
+        set_light_node(cli_light_node(&matches, &config));
+        set_bootstrap_node(
+            matches
+                .get_one::<String>("bootstrap")
+                .cloned()
+                .or_else(|| config.get_str("bootstrap")),
+        );
+        let mut rng = cli_rng(&matches, &config)?;
+
         // 创建区块链
         if let Some(ref matches) = matches.subcommand_matches("create_blockchain") {
+            check_no_local_node(&credentials)?;
+            let mut spec = match matches.get_one::<String>("genesis") {
+                Some(path) => GenesisSpec::load(path)?,
+                None => GenesisSpec::default(),
+            };
+            // 命令行给出的地址获得一份区块奖励
             if let Some(address) = matches.get_one::<String>("address") {
-                let address = String::from(address);
-                let bc = Blockchain::create_blockchain(address)?;
-                let utxo_set = UTXOSet { blockchain: bc };
-                utxo_set.reindex()?;
+                spec.allocations.push((String::from(address), SUBSIDY));
+            }
+            if let Some(allocations) = matches.get_many::<String>("allocate") {
+                for allocation in allocations {
+                    spec.allocations.push(parse_allocation(allocation)?);
+                }
+            }
+
+            let bc = Blockchain::create_blockchain(&spec, matches.is_present("force"))?;
+            let genesis_hash = bc.get_tip_hash();
+            let utxo_set = UTXOSet { blockchain: bc };
+            utxo_set.reindex()?;
 
-                println!("Create blockchain success.");
+            println!("Create blockchain success. Genesis: {}", genesis_hash);
+        }
+
+        // 删除区块链
+        if let Some(matches) = matches.subcommand_matches("wipe_chain") {
+            check_no_local_node(&credentials)?;
+            if !Blockchain::exists()? {
+                println!("No blockchain to wipe.");
+            } else if matches.is_present("yes")
+                || confirm(&format!(
+                    "Delete the blockchain of the {} network?",
+                    current().name()
+                ))?
+            {
+                Blockchain::wipe()?;
+                println!("Wipe blockchain success.");
+            } else {
+                println!("Aborted.");
             }
         }
 
         // 创建钱包
-        if let Some(_) = matches.subcommand_matches("create_wallets") {
+        if let Some(matches) = matches.subcommand_matches("create_wallets") {
             let mut wlts = Wallets::new()?;
-            let address = wlts.create_wallet();
+            let address = match matches.get_one::<String>("name") {
+                Some(name) => wlts.create_named_wallet(name, &mut rng)?,
+                None => wlts.create_wallet(&mut rng),
+            };
             wlts.save_all()?;
 
             println!("Create wallets success, the wallets address: {}", address);
@@ -77,13 +970,85 @@ impl Cli {
 
         // 打印区块链
         if let Some(_) = matches.subcommand_matches("print_chain") {
-            let bc = Blockchain::new()?;
+            let bc = Backend::local(&credentials)?.blockchain;
 
             for b in bc.iter() {
                 println!("block: {:#?}", b);
             }
         }
 
+        // 导出区块、交易、输入和输出供数据分析工具使用
+        if let Some(matches) = matches.subcommand_matches("export") {
+            let format = ExportFormat::parse(matches.get_one::<String>("format").unwrap())?;
+            let path = matches.get_one::<String>("path").unwrap();
+            let bc = Backend::local(&credentials)?.blockchain;
+            let summary = export::export(&bc, format, Path::new(path))?;
+
+            println!(
+                "Export success: {} blocks, {} transactions, {} inputs, {} outputs to {}",
+                summary.blocks, summary.transactions, summary.inputs, summary.outputs, path
+            );
+        }
+
+        // 显示收款地址和付款请求
+        if let Some(matches) = matches.subcommand_matches("show_address") {
+            let address = matches.get_one::<String>("address").unwrap();
+            if Wallets::new()?.get_wallet(address).is_none() {
+                return Err(format_err!("Address {} is not in the wallet.", address));
+            }
+            let amount = match matches.get_one::<String>("amount") {
+                Some(amount) => Some(
+                    Some(parse_amount(amount)?)
+                        .filter(|amount| *amount > 0)
+                        .ok_or_else(|| format_err!("Invalid amount: {}", amount))?,
+                ),
+                None => None,
+            };
+
+            let mut request = PaymentRequest::new(address);
+            request.amount = amount;
+            request.label = matches.get_one::<String>("label").cloned();
+
+            let uri = request.to_uri(current());
+            if matches.is_present("qr") {
+                print!("{}", QrCode::encode(uri.as_bytes())?.to_terminal_string());
+            }
+            println!("{}", uri);
+        }
+
+        // 导出纸钱包
+        if let Some(matches) = matches.subcommand_matches("export_paper_wallet") {
+            let address = matches.get_one::<String>("address").unwrap();
+            let wlts = Wallets::new()?;
+            let wallet = wlts
+                .get_wallet(address)
+                .ok_or_else(|| format_err!("Address {} is not in the wallet.", address))?;
+            let passphrase = matches.get_one::<String>("passphrase").map(String::as_str);
+            let format = PaperFormat::parse(matches.get_one::<String>("format").unwrap())?;
+
+            let sheet = paper::render(wallet, passphrase, format)?;
+            match matches.get_one::<String>("output") {
+                Some(path) => {
+                    std::fs::write(path, sheet)?;
+                    println!("Paper wallet for {} written to {}", address, path);
+                }
+                None => print!("{}", sheet),
+            }
+        }
+
+        // 导入纸钱包的私钥
+        if let Some(matches) = matches.subcommand_matches("import_paper_wallet") {
+            let key = matches.get_one::<String>("key").unwrap();
+            let passphrase = matches.get_one::<String>("passphrase").map(String::as_str);
+            let wallet = paper::decode_key(key, passphrase)?;
+
+            let mut wlts = Wallets::new()?;
+            let address = wlts.add_wallet(wallet);
+            wlts.save_all()?;
+
+            println!("Imported paper wallet: {}", address);
+        }
+
         // 打印所有钱包地址
         if let Some(_) = matches.subcommand_matches("list_addresses") {
             let wlt = Wallets::new()?;
@@ -95,11 +1060,104 @@ impl Cli {
             }
         }
 
+        // 搜索靓号地址并导入钱包
+        if let Some(matches) = matches.subcommand_matches("vanity_address") {
+            let prefix = matches.get_one::<String>("prefix").unwrap();
+            let threads = match matches.get_one::<String>("threads").unwrap().parse()? {
+                0 => std::thread::available_parallelism().map_or(1, |n| n.get()),
+                threads => threads,
+            };
+            let expected = 1.0 / vanity::probability(prefix)?;
+            println!(
+                "Searching for {} with {} threads, about {:.0} keys expected.",
+                prefix, threads, expected
+            );
+
+            let wallet = vanity::search(prefix, threads, |attempts, elapsed| {
+                let rate = attempts as f64 / elapsed.as_secs_f64();
+                let eta = (expected - attempts as f64).max(0.0) / rate;
+                println!(
+                    "Searched {} keys, {:.0} keys/s, ETA {:.0}s.",
+                    attempts, rate, eta
+                );
+            })?;
+
+            let mut wlts = Wallets::new()?;
+            let address = wlts.add_wallet(wallet);
+            wlts.save_all()?;
+
+            println!(
+                "Found vanity address, imported into the wallet: {}",
+                address
+            );
+        }
+
+        // 由描述符推导地址
+        if let Some(matches) = matches.subcommand_matches("derive_address") {
+            let descriptor = Descriptor::parse(matches.get_one::<String>("descriptor").unwrap())?;
+
+            println!("{}", descriptor.address());
+        }
+
+        // 审计钱包
+        if matches.subcommand_matches("audit_wallet").is_some() {
+            let audit = audit_wallets()?;
+            println!("{:#?}", audit);
+
+            if !audit.issues.is_empty() {
+                return Err(format_err!(
+                    "Wallet audit found {} issues.",
+                    audit.issues.len()
+                ));
+            }
+        }
+
+        // 导入只读描述符
+        if let Some(matches) = matches.subcommand_matches("import_descriptor") {
+            let descriptor = Descriptor::parse(matches.get_one::<String>("descriptor").unwrap())?;
+            let mut wlts = Wallets::new()?;
+            let address = wlts.import_descriptor(descriptor);
+            wlts.save_all()?;
+
+            println!("Imported watch-only address: {}", address);
+        }
+
+        // 打印所有地址的描述符
+        if matches.subcommand_matches("list_descriptors").is_some() {
+            let wlts = Wallets::new()?;
+            let mut addresses = wlts.get_all_addresses();
+            addresses.sort();
+
+            for address in addresses {
+                println!("{} {}", address, wlts.get_descriptor(&address).unwrap());
+            }
+            for address in wlts.get_watch_only_addresses() {
+                println!(
+                    "{} {} (watch-only)",
+                    address,
+                    wlts.get_descriptor(&address).unwrap()
+                );
+            }
+        }
+
         // 重新构建 UTXO 集
-        if let Some(_) = matches.subcommand_matches("reindex") {
-            let bc = Blockchain::new()?;
-            let utxo_set = UTXOSet { blockchain: bc };
-            utxo_set.reindex()?;
+        if let Some(matches) = matches.subcommand_matches("reindex") {
+            let utxo_set = Backend::local(&credentials)?;
+            let threads = match matches.get_one::<String>("threads") {
+                Some(threads) => threads.parse()?,
+                None => 1,
+            };
+            let threads = match threads {
+                0 => std::thread::available_parallelism().map_or(1, |n| n.get()),
+                threads => threads,
+            };
+            if threads > 1 {
+                utxo_set.reindex_parallel(threads)?;
+            } else {
+                utxo_set.reindex_with_progress(|done, total| {
+                    println!("Reindexed {}/{} blocks.", done, total);
+                })?;
+            }
 
             let count = utxo_set.count_transactions()?;
 
@@ -109,17 +1167,88 @@ impl Cli {
         // 获取余额
         if let Some(ref matches) = matches.subcommand_matches("get_balance") {
             if let Some(address) = matches.get_one::<String>("address") {
-                let pub_key_hash = Address::decode(address).unwrap().body;
-                let bc = Blockchain::new()?;
-                let utxo_set = UTXOSet { blockchain: bc };
-                let utxos = utxo_set.find_utxos(&pub_key_hash)?;
+                let pub_key_hash = decode_address(address)?;
+
+                let backend = Backend::connect(matches.get_one::<String>("node"), &credentials)?;
+                let utxos = backend.find_utxos(&pub_key_hash)?;
 
                 let mut balance = 0;
                 for out in utxos.outputs {
                     balance += out.value;
                 }
 
-                println!("Balance: {}\n", balance);
+                println!("Balance: {}\n", format_amount(balance));
+            }
+        }
+
+        // 钱包中所有地址的总余额，找零回到付款地址，所以钱包的全部余额都在这些地址上
+        // 只读地址的余额也计算在内
+        if let Some(matches) = matches.subcommand_matches("get_wallet_balance") {
+            let wlts = Wallets::new()?;
+            let mut addresses = wlts.get_all_addresses();
+            addresses.sort();
+            addresses.extend(wlts.get_watch_only_addresses());
+
+            let backend = Backend::connect(matches.get_one::<String>("node"), &credentials)?;
+            let balances: HashMap<String, i32> = backend.address_balances()?.into_iter().collect();
+
+            let mut total = 0;
+            for address in addresses {
+                let balance = balances.get(&address).copied().unwrap_or(0);
+                if matches.is_present("verbose") {
+                    println!("{}: {}", address, format_amount(balance));
+                }
+                total += balance;
+            }
+
+            println!("Balance: {}\n", format_amount(total));
+        }
+
+        // 所有地址的余额
+        if let Some(matches) = matches.subcommand_matches("list_address_balances") {
+            let backend = Backend::connect(matches.get_one::<String>("node"), &credentials)?;
+            for (address, balance) in backend.address_balances()? {
+                println!("{}: {}", address, format_amount(balance));
+            }
+        }
+
+        // 钱包输出的碎片情况: 数量、小额输出和金额分布
+        if let Some(matches) = matches.subcommand_matches("analyze_utxos") {
+            let addresses = match matches.get_one::<String>("address") {
+                Some(address) => vec![address.clone()],
+                None => Wallets::new()?.get_all_addresses(),
+            };
+            let dust_threshold = match matches.get_one::<String>("dust_threshold") {
+                Some(threshold) => parse_amount(threshold)?,
+                None => DEFAULT_DUST_THRESHOLD,
+            };
+
+            let backend = Backend::connect(matches.get_one::<String>("node"), &credentials)?;
+            let mut values = Vec::new();
+            for address in addresses {
+                let pub_key_hash = decode_address(&address)?;
+                for out in backend.find_utxos(&pub_key_hash)?.outputs {
+                    values.push(out.value);
+                }
+            }
+
+            let report = Fragmentation::analyze(&values, dust_threshold);
+            println!("Outputs: {}", report.count);
+            println!("Total: {}", format_amount(report.total));
+            println!(
+                "Small outputs (<= {}): {} ({})",
+                format_amount(dust_threshold),
+                report.dust_count,
+                format_amount(report.dust_total)
+            );
+            for bucket in report.buckets {
+                println!(
+                    "{:>12} - {:<12} {:>6} {:>14}",
+                    format_amount(bucket.min),
+                    format_amount(bucket.max),
+                    bucket.count,
+                    format_amount(bucket.total)
+                );
             }
         }
 
@@ -139,47 +1268,122 @@ impl Cli {
                 exit(1)
             };
 
-            let amount: i32 = if let Some(amount) = matches.get_one::<String>("amount") {
-                amount.parse()?
+            // 收款方可以是付款请求 URI，URI 中的金额和命令行给出的金额必须一致，标签作为默认的交易备注
+            let request = if is_payment_uri(to) {
+                PaymentRequest::parse(to, current())?
             } else {
-                println!(
-                    "Amount in send not supply!: usage\n{}",
-                    matches.args_present()
-                );
-                exit(1)
+                PaymentRequest::new(to)
+            };
+            let to = request.address;
+            let note = matches.get_one::<String>("note").cloned().or(request.label);
+            let amount: i32 = match (matches.get_one::<String>("amount"), request.amount) {
+                (Some(amount), Some(requested)) if parse_amount(amount)? != requested => {
+                    return Err(format_err!(
+                        "Amount {} does not match the payment request amount {}.",
+                        amount,
+                        format_amount(requested)
+                    ));
+                }
+                (Some(amount), _) => parse_amount(amount)?,
+                (None, Some(requested)) => requested,
+                (None, None) => {
+                    println!(
+                        "Amount in send not supply!: usage\n{}",
+                        matches.args_present()
+                    );
+                    exit(1)
+                }
             };
 
-            let bc = Blockchain::new()?;
-            let mut utxo_set = UTXOSet { blockchain: bc };
             let wlts = Wallets::new()?;
             let wlt = wlts.get_wallet(from).unwrap();
-            let tx = Transaction::new_utxo(wlt, to, amount, &utxo_set)?;
 
+            // 钱包与节点分离: 有节点时只通过 RPC 查询和广播，不打开本地区块链
+            let backend = Backend::connect(matches.get_one::<String>("node"), &credentials)?;
+            let utxo_set = match backend {
+                Backend::Local(utxo_set) => utxo_set,
+                Backend::Node(_) if matches.is_present("mine") => {
+                    return Err(format_err!(
+                        "--mine needs the local database, stop the running node first."
+                    ));
+                }
+                Backend::Node(node) => {
+                    let tx = Transaction::new_utxo(wlt, &to, amount, &node)?;
+                    if let Some(note) = &note {
+                        set_tx_note(&tx.id, note)?;
+                    }
+                    if matches.is_present("raw") {
+                        println!("{}", tx.to_hex()?);
+                        return Ok(());
+                    }
+                    let txid = node.send_raw_transaction(&tx)?;
+                    add_pending_tx(&tx)?;
+
+                    println!("Send success: {}", txid);
+                    return Ok(());
+                }
+            };
+            let tx = Transaction::new_utxo(wlt, &to, amount, &utxo_set)?;
+            if let Some(note) = &note {
+                set_tx_note(&tx.id, note)?;
+            }
+
+            if matches.is_present("raw") {
+                println!("{}", tx.to_hex()?);
+                return Ok(());
+            }
+
+            let txid = tx.id.clone();
             if matches.is_present("mine") {
-                let cbtx = Transaction::new_coinbase(from.to_string(), String::from("reward!"))?;
+                let cbtx =
+                    Transaction::new_coinbase(from.to_string(), String::from("reward!"), &mut rng)?;
                 let new_block = utxo_set.blockchain.mine_block(vec![cbtx, tx])?;
 
                 utxo_set.update(&new_block)?;
             } else {
+                // 先记录再发送，发送失败时节点启动后会重新广播
+                add_pending_tx(&tx)?;
                 Server::send_transaction(&tx, utxo_set)?;
             }
-            println!("Send success");
+            println!("Send success: {}", txid);
         }
 
-        // 开始节点
-        if let Some(ref matches) = matches.subcommand_matches("start_node") {
-            if let Some(port) = matches.get_one::<String>("port") {
-                println!("Start node...");
+        // 立即挖出区块
+        if let Some(matches) = matches.subcommand_matches("generate") {
+            if current() != Network::Regtest {
+                return Err(format_err!("generate is only available on regtest."));
+            }
+
+            let address = matches.get_one::<String>("address").unwrap();
+            let count: usize = matches.get_one::<String>("count").unwrap().parse()?;
 
-                let bc = Blockchain::new()?;
-                let utxo_set = UTXOSet { blockchain: bc };
-                let server = Server::new(port, "", utxo_set)?;
-                server.start_server()?;
+            let utxo_set = Backend::local(&credentials)?;
+            for _ in 0..count {
+                let cbtx = Transaction::new_coinbase(address.to_string(), String::new(), &mut rng)?;
+                let new_block = utxo_set.blockchain.mine_block(vec![cbtx])?;
+                utxo_set.update(&new_block)?;
+
+                println!("{}", new_block.get_hash());
             }
         }
 
-        // 矿工节点
-        if let Some(ref matches) = matches.subcommand_matches("start_miner") {
+        // 开始节点
+        if let Some(ref matches) = matches.subcommand_matches("start_node") {
+            let port = cli_port(matches, &config)?;
+            println!("Start node on port {}...", port);
+
+            let utxo_set = Backend::local(&credentials)?;
+            let server = new_server(matches, &config, &port, "", utxo_set)?;
+            configure_server(&server, matches, &config)?;
+            std::fs::write(
+                data_path(rpc::LOCAL_NODE_FILE),
+                format!("localhost:{}", port),
+            )?;
+            server.start_server()?;
+        }
+
+        // 矿工节点
+        if let Some(ref matches) = matches.subcommand_matches("start_miner") {
             let address = if let Some(address) = matches.get_one::<String>("address") {
                 address
             } else {
@@ -187,20 +1391,1587 @@ impl Cli {
                 exit(1)
             };
 
-            let port = if let Some(port) = matches.get_one::<String>("port") {
-                port
+            let port = cli_port(matches, &config)?;
+
+            println!("Start miner node on port {}...", port);
+            // 钱包名: 每个区块的奖励支付到从该钱包新派生的地址
+            let mining_wallet = Wallets::new()?.get_named_wallet(address).is_some();
+            let utxo_set = Backend::local(&credentials)?;
+            let server = if mining_wallet {
+                let server = new_server(matches, &config, &port, "", utxo_set)?;
+                server.set_mining_wallet(address);
+                server
             } else {
-                println!("From not supply!: usage\n{}", matches.args_present());
-                exit(1)
+                new_server(matches, &config, &port, address, utxo_set)?
             };
-
-            println!("Start miner node...");
-            let bc = Blockchain::new()?;
-            let utxo_set = UTXOSet { blockchain: bc };
-            let server = Server::new(port, address, utxo_set)?;
+            configure_server(&server, matches, &config)?;
+            std::fs::write(
+                data_path(rpc::LOCAL_NODE_FILE),
+                format!("localhost:{}", port),
+            )?;
             server.start_server()?;
         }
 
+        // 节点信息
+        if let Some(matches) = matches.subcommand_matches("get_peer_info") {
+            let node = &rpc_node_arg(matches);
+            let response = rpc::call(node, credentials.clone(), rpc::RpcRequest::GetPeerInfo)?;
+
+            println!("{:#?}", response);
+        }
+
+        // 网络信息
+        if let Some(matches) = matches.subcommand_matches("get_network_info") {
+            let node = &rpc_node_arg(matches);
+            let response = rpc::call(node, credentials.clone(), rpc::RpcRequest::GetNetworkInfo)?;
+
+            println!("{:#?}", response);
+        }
+
+        // 流量统计
+        if let Some(matches) = matches.subcommand_matches("get_net_totals") {
+            let node = &rpc_node_arg(matches);
+            let response = rpc::call(node, credentials.clone(), rpc::RpcRequest::GetNetTotals)?;
+
+            println!("{:#?}", response);
+        }
+
+        // 同步状态
+        if let Some(matches) = matches.subcommand_matches("get_sync_status") {
+            let node = &rpc_node_arg(matches);
+            let response = rpc::call(node, credentials.clone(), rpc::RpcRequest::GetSyncStatus)?;
+
+            println!("{:#?}", response);
+        }
+
+        // 区块链信息
+        if let Some(matches) = matches.subcommand_matches("get_blockchain_info") {
+            let node = &rpc_node_arg(matches);
+            let response = rpc::call(
+                node,
+                credentials.clone(),
+                rpc::RpcRequest::GetBlockchainInfo,
+            )?;
+
+            println!("{:#?}", response);
+        }
+
+        // 归集钱包的全部输出，每个地址单独签名，超过交易大小限制时分成多笔
+        if let Some(matches) = matches.subcommand_matches("sweep") {
+            let to = matches.get_one::<String>("destination").unwrap();
+            decode_address(to)?;
+            let fee_rate = parse_amount(matches.get_one::<String>("fee_rate").unwrap())?;
+            if fee_rate < 0 {
+                return Err(format_err!("Fee rate must not be negative."));
+            }
+
+            let wlts = Wallets::new()?;
+            let backend = Backend::connect(matches.get_one::<String>("node"), &credentials)?;
+            let addresses = match matches.get_one::<String>("from") {
+                Some(from) => vec![from.clone()],
+                None => {
+                    // 跳过没有余额的地址和目标地址本身
+                    let balances: HashMap<String, i32> =
+                        backend.address_balances()?.into_iter().collect();
+                    let mut addresses: Vec<String> = wlts
+                        .get_all_addresses()
+                        .into_iter()
+                        .filter(|address| address != to && balances.contains_key(address))
+                        .collect();
+                    addresses.sort();
+                    addresses
+                }
+            };
+
+            let mut txs = Vec::new();
+            for address in addresses {
+                let wallet = wlts
+                    .get_wallet(&address)
+                    .ok_or_else(|| format_err!("Address {} is not in the wallet.", address))?;
+                txs.extend(Transaction::new_sweep(wallet, to, fee_rate, &backend)?);
+            }
+            if txs.is_empty() {
+                println!("Nothing to sweep.");
+                return Ok(());
+            }
+
+            if matches.is_present("raw") {
+                for tx in &txs {
+                    println!("{}", tx.to_hex()?);
+                }
+                return Ok(());
+            }
+
+            for tx in &txs {
+                add_pending_tx(tx)?;
+                match &backend {
+                    Backend::Node(node) => {
+                        node.send_raw_transaction(tx)?;
+                    }
+                    Backend::Local(utxo_set) => {
+                        let utxo_set = UTXOSet {
+                            blockchain: utxo_set.blockchain.clone(),
+                        };
+                        Server::send_transaction(tx, utxo_set)?;
+                    }
+                }
+                println!("Sweep {} in {}", format_amount(tx.vout[0].value), tx.id);
+            }
+        }
+
+        // 注册或续期名称
+        if let Some(matches) = matches.subcommand_matches("register_name") {
+            let from = matches.get_one::<String>("from").unwrap();
+            let name = matches.get_one::<String>("name").unwrap();
+            let to = matches.get_one::<String>("to").unwrap_or(from);
+            let fee = parse_amount(matches.get_one::<String>("fee").unwrap())?;
+            if fee < 0 {
+                return Err(format_err!("Fee must not be negative."));
+            }
+            let record = NameRecord::new(name, to)?;
+
+            let wlts = Wallets::new()?;
+            let wallet = wlts
+                .get_wallet(from)
+                .ok_or_else(|| format_err!("Address {} is not in the wallet.", from))?;
+            let backend = Backend::connect(matches.get_one::<String>("node"), &credentials)?;
+            let tx = Transaction::new_name_registration(wallet, &record, fee, &backend)?;
+
+            if matches.is_present("raw") {
+                println!("{}", tx.to_hex()?);
+                return Ok(());
+            }
+
+            backend.send_wallet_tx(&tx)?;
+            println!("Register {} to {} in {}", name, to, tx.id);
+        }
+
+        // 创建提案或投票
+        for command in ["create_proposal", "vote"] {
+            let matches = match matches.subcommand_matches(command) {
+                Some(matches) => matches,
+                None => continue,
+            };
+            let from = matches.get_one::<String>("from").unwrap();
+            let fee = parse_amount(matches.get_one::<String>("fee").unwrap())?;
+            if fee < 0 {
+                return Err(format_err!("Fee must not be negative."));
+            }
+            let record = match matches.get_one::<String>("title") {
+                Some(title) => GovernanceRecord::proposal(title)?,
+                None => GovernanceRecord::vote(
+                    matches.get_one::<String>("proposal").unwrap(),
+                    matches.get_one::<String>("choice").unwrap() == "yes",
+                )?,
+            };
+
+            let wlts = Wallets::new()?;
+            let wallet = wlts
+                .get_wallet(from)
+                .ok_or_else(|| format_err!("Address {} is not in the wallet.", from))?;
+            let backend = Backend::connect(matches.get_one::<String>("node"), &credentials)?;
+            let tx = Transaction::new_governance(wallet, &record, fee, &backend)?;
+
+            if matches.is_present("raw") {
+                println!("{}", tx.to_hex()?);
+                return Ok(());
+            }
+
+            backend.send_wallet_tx(&tx)?;
+            match record {
+                GovernanceRecord::Proposal { .. } => println!("Proposal: {}", tx.id),
+                GovernanceRecord::Vote { .. } => println!("Vote: {}", tx.id),
+            }
+        }
+
+        // 支付通道: 付款方创建通道，收款方签署退款交易后付款方广播资金交易
+        if let Some(matches) = matches.subcommand_matches("open_channel") {
+            let from = matches.get_one::<String>("from").unwrap();
+            let payee = match Descriptor::parse(matches.get_one::<String>("payee").unwrap())? {
+                Descriptor::Pkh(key) => key,
+                _ => return Err(format_err!("The payee must be a pkh descriptor.")),
+            };
+            let capacity = parse_amount(matches.get_one::<String>("capacity").unwrap())?;
+            let fee = parse_amount(matches.get_one::<String>("fee").unwrap())?;
+            let timeout = matches.get_one::<String>("timeout").unwrap().parse()?;
+
+            let wlts = Wallets::new()?;
+            let wallet = wlts
+                .get_wallet(from)
+                .ok_or_else(|| format_err!("Address {} is not in the wallet.", from))?;
+            let backend = Backend::connect(matches.get_one::<String>("node"), &credentials)?;
+            let channel = Channel::open(wallet, &payee, capacity, fee, timeout, &backend)?;
+            save_channel(&channel)?;
+
+            println!("Channel: {}", channel.id());
+            println!("{}", channel.to_hex()?);
+        }
+
+        if let Some(matches) = matches.subcommand_matches("accept_channel") {
+            let channel = Channel::from_hex(matches.get_one::<String>("channel").unwrap())?;
+            let address = Descriptor::Pkh(channel.payee.clone()).address();
+
+            let wlts = Wallets::new()?;
+            let wallet = wlts
+                .get_wallet(&address)
+                .ok_or_else(|| format_err!("Address {} is not in the wallet.", address))?;
+            let channel = channel.accept(wallet)?;
+            save_channel(&channel)?;
+
+            println!(
+                "Accept channel {}: capacity {}, timeout {} blocks",
+                channel.id(),
+                format_amount(channel.capacity),
+                channel.timeout
+            );
+            println!("{}", encode_hex(&channel.refund_signature));
+        }
+
+        if let Some(matches) = matches.subcommand_matches("fund_channel") {
+            let id = matches.get_one::<String>("id").unwrap();
+            let mut channel = get_channel(id, Role::Payer)?
+                .ok_or_else(|| format_err!("Channel {} is not opened by this wallet.", id))?;
+            let signature = decode_hex(matches.get_one::<String>("signature").unwrap())
+                .ok_or_else(|| format_err!("Invalid signature hex."))?;
+            channel.set_refund_signature(&signature)?;
+            save_channel(&channel)?;
+
+            let backend = Backend::connect(matches.get_one::<String>("node"), &credentials)?;
+            backend.send_wallet_tx(&channel.funding)?;
+            println!("Fund channel {}", channel.id());
+        }
+
+        if let Some(matches) = matches.subcommand_matches("channel_pay") {
+            let id = matches.get_one::<String>("id").unwrap();
+            let amount = parse_amount(matches.get_one::<String>("amount").unwrap())?;
+            let mut channel = get_channel(id, Role::Payer)?
+                .ok_or_else(|| format_err!("Channel {} is not opened by this wallet.", id))?;
+
+            let wlts = Wallets::new()?;
+            let address = Descriptor::Pkh(channel.payer.clone()).address();
+            let wallet = wlts
+                .get_wallet(&address)
+                .ok_or_else(|| format_err!("Address {} is not in the wallet.", address))?;
+            let payment = channel.pay(wallet, amount)?;
+            save_channel(&channel)?;
+
+            println!("Paid {} in channel {}", format_amount(payment.paid), id);
+            println!("{}", payment.to_hex()?);
+        }
+
+        if let Some(matches) = matches.subcommand_matches("channel_receive") {
+            let payment = Payment::from_hex(matches.get_one::<String>("payment").unwrap())?;
+            let mut channel = get_channel(&payment.channel, Role::Payee)?.ok_or_else(|| {
+                format_err!(
+                    "Channel {} is not accepted by this wallet.",
+                    payment.channel
+                )
+            })?;
+            channel.receive(&payment)?;
+            save_channel(&channel)?;
+
+            println!(
+                "Received {} in channel {}",
+                format_amount(channel.paid),
+                channel.id()
+            );
+        }
+
+        // 收款方用最新的支付协作关闭，付款方超时后用退款交易单方关闭
+        if let Some(matches) = matches.subcommand_matches("close_channel") {
+            let id = matches.get_one::<String>("id").unwrap();
+            let role = if matches.is_present("refund") {
+                Role::Payer
+            } else {
+                Role::Payee
+            };
+            let channel =
+                get_channel(id, role)?.ok_or_else(|| format_err!("Channel {} not found.", id))?;
+
+            let wlts = Wallets::new()?;
+            let key = match role {
+                Role::Payer => &channel.payer,
+                Role::Payee => &channel.payee,
+            };
+            let address = Descriptor::Pkh(key.clone()).address();
+            let wallet = wlts
+                .get_wallet(&address)
+                .ok_or_else(|| format_err!("Address {} is not in the wallet.", address))?;
+            let tx = channel.close(wallet)?;
+
+            if matches.is_present("raw") {
+                println!("{}", tx.to_hex()?);
+                return Ok(());
+            }
+
+            let backend = Backend::connect(matches.get_one::<String>("node"), &credentials)?;
+            backend.send_wallet_tx(&tx)?;
+            println!("Close channel {} in {}", id, tx.id);
+        }
+
+        if matches.subcommand_matches("list_channels").is_some() {
+            for channel in list_channels()? {
+                println!(
+                    "{} {:?}: capacity {}, paid {}, timeout {} blocks",
+                    channel.id(),
+                    channel.role,
+                    format_amount(channel.capacity),
+                    format_amount(channel.paid),
+                    channel.timeout
+                );
+            }
+        }
+
+        // 保险库: 正常密钥和恢复密钥都在本节点的钱包中
+        if let Some(matches) = matches.subcommand_matches("vault_deposit") {
+            let from = matches.get_one::<String>("from").unwrap();
+            let recovery = matches.get_one::<String>("recovery").unwrap();
+            let amount = parse_amount(matches.get_one::<String>("amount").unwrap())?;
+            let delay = matches.get_one::<String>("delay").unwrap().parse()?;
+            let fee = parse_amount(matches.get_one::<String>("fee").unwrap())?;
+
+            let wlts = Wallets::new()?;
+            let wallet = wlts
+                .get_wallet(from)
+                .ok_or_else(|| format_err!("Address {} is not in the wallet.", from))?;
+            let recovery = wlts
+                .get_wallet(recovery)
+                .ok_or_else(|| format_err!("Address {} is not in the wallet.", recovery))?;
+            let backend = Backend::connect(matches.get_one::<String>("node"), &credentials)?;
+            let vault = Vault::deposit(wallet, recovery, amount, delay, fee, &backend)?;
+            backend.send_wallet_tx(&vault.deposit)?;
+            save_vault(&vault)?;
+
+            println!("Vault: {}", vault.id());
+        }
+
+        if let Some(matches) = matches.subcommand_matches("vault_trigger") {
+            let id = matches.get_one::<String>("id").unwrap();
+            let mut vault = get_vault(id)?.ok_or_else(|| format_err!("Vault {} not found.", id))?;
+
+            let wlts = Wallets::new()?;
+            let address = Descriptor::Pkh(vault.normal.clone()).address();
+            let wallet = wlts
+                .get_wallet(&address)
+                .ok_or_else(|| format_err!("Address {} is not in the wallet.", address))?;
+            let tx = vault.trigger(wallet)?;
+
+            let backend = Backend::connect(matches.get_one::<String>("node"), &credentials)?;
+            backend.send_wallet_tx(&tx)?;
+            vault.state = VaultState::Triggered;
+            save_vault(&vault)?;
+            println!(
+                "Trigger vault {} in {}, withdraw after {} blocks",
+                id, tx.id, vault.delay
+            );
+        }
+
+        if let Some(matches) = matches.subcommand_matches("vault_withdraw") {
+            let id = matches.get_one::<String>("id").unwrap();
+            let to = matches.get_one::<String>("to").unwrap();
+            let fee = parse_amount(matches.get_one::<String>("fee").unwrap())?;
+            let mut vault = get_vault(id)?.ok_or_else(|| format_err!("Vault {} not found.", id))?;
+
+            let wlts = Wallets::new()?;
+            let address = Descriptor::Pkh(vault.normal.clone()).address();
+            let wallet = wlts
+                .get_wallet(&address)
+                .ok_or_else(|| format_err!("Address {} is not in the wallet.", address))?;
+            let tx = vault.withdraw(wallet, to, fee)?;
+
+            let backend = Backend::connect(matches.get_one::<String>("node"), &credentials)?;
+            backend.send_wallet_tx(&tx)?;
+            vault.state = VaultState::Withdrawn;
+            save_vault(&vault)?;
+            println!("Withdraw vault {} in {}", id, tx.id);
+        }
+
+        if let Some(matches) = matches.subcommand_matches("vault_cancel") {
+            let id = matches.get_one::<String>("id").unwrap();
+            let fee = parse_amount(matches.get_one::<String>("fee").unwrap())?;
+            let mut vault = get_vault(id)?.ok_or_else(|| format_err!("Vault {} not found.", id))?;
+
+            let wlts = Wallets::new()?;
+            let address = Descriptor::Pkh(vault.recovery.clone()).address();
+            let wallet = wlts
+                .get_wallet(&address)
+                .ok_or_else(|| format_err!("Address {} is not in the wallet.", address))?;
+            let tx = vault.cancel(wallet, fee)?;
+
+            let backend = Backend::connect(matches.get_one::<String>("node"), &credentials)?;
+            backend.send_wallet_tx(&tx)?;
+            vault.state = VaultState::Cancelled;
+            save_vault(&vault)?;
+            println!(
+                "Cancel vault {} in {}, funds sent to {}",
+                id, tx.id, address
+            );
+        }
+
+        if matches.subcommand_matches("list_vaults").is_some() {
+            for vault in list_vaults()? {
+                println!(
+                    "{} {:?}: amount {}, delay {} blocks",
+                    vault.id(),
+                    vault.state,
+                    format_amount(vault.amount),
+                    vault.delay
+                );
+            }
+        }
+
+        // 程序锁定的输出: 向程序地址转账即可锁定，花费时提供程序和见证数据
+        if let Some(matches) = matches.subcommand_matches("program_address") {
+            let code = vm::assemble(matches.get_one::<String>("program").unwrap())?;
+            let pub_key_hash = vm::program_hash(&code);
+
+            println!("Address: {}", address_from_pub_key_hash(&pub_key_hash));
+            println!("Program: {}", vm::disassemble(&code)?);
+            println!("Script: {}", encode_hex(&vm::lock_script(&code)));
+        }
+
+        if let Some(matches) = matches.subcommand_matches("spend_program") {
+            let txid = matches.get_one::<String>("txid").unwrap();
+            let vout = matches.get_one::<String>("vout").unwrap().parse()?;
+            let code = vm::assemble(matches.get_one::<String>("program").unwrap())?;
+            let witness = vm::assemble(matches.get_one::<String>("witness").unwrap())?;
+            let to = matches.get_one::<String>("to").unwrap();
+            let fee = parse_amount(matches.get_one::<String>("fee").unwrap())?;
+
+            let backend = Backend::connect(matches.get_one::<String>("node"), &credentials)?;
+            let tx =
+                Transaction::new_program_spend(txid, vout, &code, &witness, to, fee, &backend)?;
+
+            if matches.is_present("raw") {
+                println!("{}", tx.to_hex()?);
+                return Ok(());
+            }
+
+            backend.send_wallet_tx(&tx)?;
+            println!("Spend {}:{} in {}", txid, vout, tx.id);
+        }
+
+        // 跨链锚定: 目标是其他链的节点时发送带锚定输出的交易，否则写入签名的证明文件
+        if let Some(matches) = matches.subcommand_matches("anchor") {
+            let from = matches.get_one::<String>("from").unwrap();
+            let target = matches.get_one::<String>("target").unwrap();
+            let fee = parse_amount(matches.get_one::<String>("fee").unwrap())?;
+
+            let wlts = Wallets::new()?;
+            let wallet = wlts
+                .get_wallet(from)
+                .ok_or_else(|| format_err!("Address {} is not in the wallet.", from))?;
+            let backend = Backend::connect(matches.get_one::<String>("node"), &credentials)?;
+            let (height, block_hash) = backend.best_block()?;
+            let anchor = Anchor::new(height, &block_hash)?;
+
+            match target.strip_prefix(ANCHOR_RPC_PREFIX) {
+                Some(addr) => {
+                    let other = rpc::RemoteNode::new(addr, credentials.clone());
+                    let tx = Transaction::new_anchor(wallet, &anchor, fee, &other)?;
+                    other.send_raw_transaction(&tx)?;
+                    println!(
+                        "Anchor block {} at height {} in {} on {}",
+                        block_hash, height, tx.id, addr
+                    );
+                }
+                None => {
+                    let attestation = Attestation::new(current().name(), anchor, wallet)?;
+                    std::fs::write(target, attestation.to_hex()?)?;
+                    println!(
+                        "Attest block {} at height {} in {}",
+                        block_hash, height, target
+                    );
+                }
+            }
+        }
+
+        if let Some(matches) = matches.subcommand_matches("verify_anchor") {
+            let target = matches.get_one::<String>("target").unwrap();
+            let backend = Backend::connect(matches.get_one::<String>("node"), &credentials)?;
+
+            let anchor = match target.strip_prefix(ANCHOR_RPC_PREFIX) {
+                Some(addr) => {
+                    let txid = matches
+                        .get_one::<String>("txid")
+                        .ok_or_else(|| format_err!("--txid is required with {}.", target))?;
+                    let info =
+                        rpc::RemoteNode::new(addr, credentials.clone()).get_transaction(txid)?;
+                    let anchor = Anchor::from_tx(&info.transaction)
+                        .ok_or_else(|| format_err!("Transaction {} has no anchor.", txid))?;
+                    println!("Confirmations on {}: {}", addr, info.confirmations);
+                    anchor
+                }
+                None => {
+                    let attestation = Attestation::from_hex(&std::fs::read_to_string(target)?)?;
+                    attestation.verify_signature()?;
+                    if attestation.network != current().name() {
+                        return Err(format_err!(
+                            "Attestation is for network {}.",
+                            attestation.network
+                        ));
+                    }
+                    println!(
+                        "Signed by {} at {}",
+                        attestation.address(),
+                        attestation.timestamp
+                    );
+                    attestation.anchor
+                }
+            };
+
+            anchor.check(backend.block_hash_at(anchor.height)?.as_deref())?;
+            println!(
+                "Anchor of block {} at height {} is valid",
+                anchor.block_hash, anchor.height
+            );
+        }
+
+        // 计票
+        if let Some(matches) = matches.subcommand_matches("tally") {
+            let proposal = matches.get_one::<String>("proposal").unwrap();
+            let node = &rpc_node_arg(matches);
+            let tally = rpc::RemoteNode::new(node, credentials.clone()).tally_proposal(proposal)?;
+
+            println!("Proposal: {}", tally.title);
+            println!(
+                "Voting: heights {} - {} ({})",
+                tally.height + 1,
+                tally.end_height,
+                if tally.closed { "closed" } else { "open" }
+            );
+            println!("Yes: {}", format_amount(tally.yes));
+            println!("No: {}", format_amount(tally.no));
+            println!("Voters: {}", tally.voters);
+        }
+
+        // 查询名称
+        if let Some(matches) = matches.subcommand_matches("resolve_name") {
+            let name = matches.get_one::<String>("name").unwrap();
+            let node = &rpc_node_arg(matches);
+
+            match rpc::RemoteNode::new(node, credentials.clone()).resolve_name(name)? {
+                Some(claim) => println!(
+                    "{} -> {} (registered at height {}, expires at height {})",
+                    name,
+                    claim.address(),
+                    claim.height,
+                    claim.expires_at()
+                ),
+                None => println!("Name {} is not registered.", name),
+            }
+        }
+
+        // 钱包交易列表，标出与已确认交易冲突、不可能再被确认的交易
+        if let Some(matches) = matches.subcommand_matches("list_wallet_txs") {
+            let pending = get_pending_txs()?;
+            let conflicts = if pending.is_empty() {
+                HashMap::new()
+            } else {
+                Backend::connect(matches.get_one::<String>("node"), &credentials)?
+                    .find_conflicts(&pending)?
+            };
+            for mut tx in list_wallet_txs()? {
+                if let Some(conflicts) = conflicts.get(&tx.txid) {
+                    tx.conflicts.clone_from(conflicts);
+                }
+                println!("{:#?}", tx);
+            }
+        }
+
+        // 放弃未确认的钱包交易，节点在运行时同时从交易池移除，输入可以重新花费
+        if let Some(matches) = matches.subcommand_matches("abandon_tx") {
+            let txid = matches.get_one::<String>("txid").unwrap();
+            let mut abandoned = abandon_pending_tx(txid)?;
+            if let Some(node) = running_node(matches.get_one::<String>("node"), &credentials) {
+                abandoned.extend(node.abandon_transaction(txid)?);
+            }
+            abandoned.sort();
+            abandoned.dedup();
+
+            println!("Abandon transaction success: {}", abandoned.join(", "));
+        }
+
+        // 追加未确认钱包交易的手续费
+        if let Some(matches) = matches.subcommand_matches("bump_fee") {
+            let txid = matches.get_one::<String>("txid").unwrap();
+            let extra_fee = parse_amount(matches.get_one::<String>("fee").unwrap())?;
+            let tx = get_pending_tx(txid)?.ok_or_else(|| {
+                format_err!("Transaction {} is not a pending wallet transaction.", txid)
+            })?;
+
+            let wlts = Wallets::new()?;
+            let addresses = wlts.get_all_addresses();
+            let wallets: Vec<&Wallet> = addresses
+                .iter()
+                .filter_map(|address| wlts.get_wallet(address))
+                .collect();
+            let backend = Backend::connect(matches.get_one::<String>("node"), &credentials)?;
+            // 查不到输入花费的交易时不能重新签名，只能追加子交易
+            let mut prev_txs = HashMap::new();
+            for vin in &tx.vin {
+                if let Ok(prev_tx) = backend.find_transaction(&vin.txid) {
+                    prev_txs.insert(vin.txid.clone(), prev_tx);
+                }
+            }
+
+            let (method, bumped) =
+                bump_fee(&tx, &wallets, extra_fee, &prev_txs, &get_pending_txs()?)?;
+            backend.send_wallet_tx(&bumped)?;
+            if method == BumpMethod::Replace {
+                remove_pending_tx(&tx.id)?;
+            }
+
+            println!("Bump fee success ({:?}): {}", method, bumped.id);
+        }
+
+        // 提交原始交易
+        if let Some(matches) = matches.subcommand_matches("send_raw_transaction") {
+            let tx = Transaction::from_hex(matches.get_one::<String>("hex").unwrap())?;
+            let node = &rpc_node_arg(matches);
+            let txid = rpc::RemoteNode::new(node, credentials.clone()).send_raw_transaction(&tx)?;
+
+            println!("{}", txid);
+        }
+
+        // 检查交易能否进入交易池
+        if let Some(matches) = matches.subcommand_matches("test_mempool_accept") {
+            let tx = Transaction::from_hex(matches.get_one::<String>("hex").unwrap())?;
+            let node = &rpc_node_arg(matches);
+            let result =
+                rpc::RemoteNode::new(node, credentials.clone()).test_mempool_accept(&tx)?;
+
+            println!("{:#?}", result);
+        }
+
+        // 挖矿信息
+        if let Some(matches) = matches.subcommand_matches("get_mining_info") {
+            let node = &rpc_node_arg(matches);
+            let response = rpc::call(node, credentials.clone(), rpc::RpcRequest::GetMiningInfo)?;
+
+            println!("{:#?}", response);
+        }
+
+        // 查询交易
+        if let Some(matches) = matches.subcommand_matches("get_transaction") {
+            let txid = matches.get_one::<String>("txid").unwrap();
+            let node = &rpc_node_arg(matches);
+            let response = rpc::call(
+                node,
+                credentials.clone(),
+                rpc::RpcRequest::GetTransaction(txid.clone()),
+            )?;
+
+            println!("{:#?}", response);
+        }
+
+        // 区块统计
+        if let Some(matches) = matches.subcommand_matches("get_block_stats") {
+            let block = matches.get_one::<String>("block").unwrap();
+            let node = &rpc_node_arg(matches);
+            let response = rpc::call(
+                node,
+                credentials.clone(),
+                rpc::RpcRequest::GetBlockStats(block.clone()),
+            )?;
+
+            println!("{:#?}", response);
+        }
+
+        // 区块范围统计
+        if let Some(matches) = matches.subcommand_matches("get_chain_stats") {
+            let node = &rpc_node_arg(matches);
+            let from = matches.get_one::<String>("from").unwrap().parse()?;
+            let to = match matches.get_one::<String>("to") {
+                Some(to) => Some(to.parse()?),
+                None => None,
+            };
+            let response = rpc::call(
+                node,
+                credentials.clone(),
+                rpc::RpcRequest::GetChainStats { from, to },
+            )?;
+
+            println!("{:#?}", response);
+        }
+
+        // 按窗口统计出块间隔和难度的变化
+        if let Some(matches) = matches.subcommand_matches("chain_stats") {
+            let node = &rpc_node_arg(matches);
+            let from = matches.get_one::<String>("from").unwrap().parse()?;
+            let to = match matches.get_one::<String>("to") {
+                Some(to) => Some(to.parse()?),
+                None => None,
+            };
+            let window = matches.get_one::<String>("window").unwrap().parse()?;
+            let windows = rpc::RemoteNode::new(node, credentials.clone())
+                .get_chain_history(from, to, window)?;
+
+            if matches.is_present("csv") {
+                print!("{}", stats::history_to_csv(&windows));
+            } else {
+                for window in windows {
+                    println!("{:#?}", window);
+                }
+            }
+        }
+
+        // 链端列表
+        if let Some(matches) = matches.subcommand_matches("get_chain_tips") {
+            let node = &rpc_node_arg(matches);
+            let response = rpc::call(node, credentials.clone(), rpc::RpcRequest::GetChainTips)?;
+
+            println!("{:#?}", response);
+        }
+
+        // 分叉统计
+        if let Some(matches) = matches.subcommand_matches("get_fork_stats") {
+            let node = rpc::RemoteNode::new(&rpc_node_arg(matches), credentials.clone());
+            let stats = node.get_fork_stats()?;
+
+            println!("{:#?}", stats);
+            println!("Orphan rate: {:.4}", stats.orphan_rate());
+        }
+
+        // 模拟网络分区
+        if let Some(matches) = matches.subcommand_matches("simulate_partition") {
+            let peers: Vec<String> = matches
+                .get_many::<String>("peers")
+                .map(|peers| peers.cloned().collect())
+                .unwrap_or_default();
+            let node = rpc::RemoteNode::new(&rpc_node_arg(matches), credentials.clone());
+            let partitioned = node.simulate_partition(&peers)?;
+
+            println!("Ignored peers: {:?}", partitioned);
+        }
+
+        // 签名并广播运维公告
+        if let Some(matches) = matches.subcommand_matches("send_alert") {
+            let from = matches.get_one::<String>("from").unwrap();
+            let message = matches.get_one::<String>("message").unwrap();
+            let id: u32 = matches.get_one::<String>("id").unwrap().parse()?;
+            let hours: u64 = matches.get_one::<String>("expires").unwrap().parse()?;
+            let expiration = SystemTime::now()
+                .duration_since(SystemTime::UNIX_EPOCH)?
+                .as_millis()
+                + hours as u128 * 3600 * 1000;
+
+            let wlts = Wallets::new()?;
+            let wallet = wlts
+                .get_wallet(from)
+                .ok_or_else(|| format_err!("Wallet not found: {}", from))?;
+            let alert = Alert::new(id, expiration, message, wallet)?;
+            let node = &rpc_node_arg(matches);
+            let id = rpc::RemoteNode::new(node, credentials.clone()).submit_alert(&alert)?;
+
+            println!("Alert {} sent.", id);
+        }
+
+        // 多签签名方通过节点交换部分签名交易，签名达到门限后由节点广播
+        if let Some(matches) = matches.subcommand_matches("create_psbt") {
+            let from = matches.get_one::<String>("from").unwrap();
+            let to = matches.get_one::<String>("to").unwrap();
+            let amount = parse_amount(matches.get_one::<String>("amount").unwrap())?;
+            let fee = parse_amount(matches.get_one::<String>("fee").unwrap())?;
+
+            let wlts = Wallets::new()?;
+            let descriptor = wlts
+                .get_descriptor(from)
+                .ok_or_else(|| format_err!("Address {} is not in the wallet.", from))?;
+            let node = rpc::RemoteNode::new(&rpc_node_arg(matches), credentials.clone());
+            let mut psbt = Psbt::new(&descriptor, to, amount, fee, &node)?;
+            let signed = sign_psbt_locally(&mut psbt, &wlts)?;
+            let id = node.submit_psbt(&psbt)?;
+
+            println!("Psbt: {}, signed {} inputs", id, signed);
+        }
+
+        if let Some(matches) = matches.subcommand_matches("sign_psbt") {
+            let id = matches.get_one::<String>("id").unwrap();
+            let mut psbt = get_psbt(id)?.ok_or_else(|| format_err!("Psbt {} not found.", id))?;
+
+            let wlts = Wallets::new()?;
+            let signed = sign_psbt_locally(&mut psbt, &wlts)?;
+            if signed == 0 {
+                return Err(format_err!("No local key can add a signature to {}.", id));
+            }
+            let node = rpc::RemoteNode::new(&rpc_node_arg(matches), credentials.clone());
+            node.submit_psbt(&psbt)?;
+
+            if psbt.is_complete() {
+                println!("Psbt {} is fully signed and broadcast.", id);
+            } else {
+                println!(
+                    "Psbt {}: signed {} inputs, waiting for co-signers.",
+                    id, signed
+                );
+            }
+        }
+
+        if matches.subcommand_matches("list_psbts").is_some() {
+            for psbt in list_psbts()? {
+                let signatures: Vec<String> = psbt
+                    .signatures
+                    .iter()
+                    .map(|signatures| signatures.len().to_string())
+                    .collect();
+                println!("{}: signatures {}", psbt.id(), signatures.join(","));
+                for out in &psbt.tx.vout {
+                    println!(
+                        "  pay {} to {}",
+                        format_amount(out.value),
+                        address_from_pub_key_hash(&out.pub_key_hash)
+                    );
+                }
+            }
+        }
+
+        // 提交原始区块
+        if let Some(matches) = matches.subcommand_matches("submit_block") {
+            let block = Block::from_hex(matches.get_one::<String>("hex").unwrap())?;
+            let node = &rpc_node_arg(matches);
+            let hash = rpc::RemoteNode::new(node, credentials.clone()).submit_block(&block)?;
+
+            println!("{}", hash);
+        }
+
+        // 获取区块概要
+        if let Some(matches) = matches.subcommand_matches("get_block") {
+            let block = matches.get_one::<String>("block").unwrap();
+            let node = &rpc_node_arg(matches);
+            let block = rpc::RemoteNode::new(node, credentials.clone()).get_block(block)?;
+
+            println!("{:#?}", rpc::BlockInfo::new(&block));
+        }
+
+        // 获取原始区块
+        if let Some(matches) = matches.subcommand_matches("get_block_raw") {
+            let block = matches.get_one::<String>("block").unwrap();
+            let node = &rpc_node_arg(matches);
+            let block = rpc::RemoteNode::new(node, credentials.clone()).get_block(block)?;
+
+            println!("{}", block.to_hex()?);
+        }
+
+        // 标记区块无效
+        if let Some(matches) = matches.subcommand_matches("invalidate_block") {
+            let hash = matches.get_one::<String>("hash").unwrap();
+            let node = &rpc_node_arg(matches);
+            let response = rpc::call(
+                node,
+                credentials.clone(),
+                rpc::RpcRequest::InvalidateBlock(hash.clone()),
+            )?;
+
+            println!("{:#?}", response);
+        }
+
+        // 清除区块的无效标记
+        if let Some(matches) = matches.subcommand_matches("reconsider_block") {
+            let hash = matches.get_one::<String>("hash").unwrap();
+            let node = &rpc_node_arg(matches);
+            let response = rpc::call(
+                node,
+                credentials.clone(),
+                rpc::RpcRequest::ReconsiderBlock(hash.clone()),
+            )?;
+
+            println!("{:#?}", response);
+        }
+
+        // 订阅新区块
+        if let Some(matches) = matches.subcommand_matches("watch_blocks") {
+            let addr = &rpc_node_arg(matches);
+            let node = rpc::RemoteNode::new(addr, credentials.clone());
+            let mut tip = match rpc::call(
+                addr,
+                credentials.clone(),
+                rpc::RpcRequest::GetBlockchainInfo,
+            )? {
+                rpc::RpcResponse::BlockchainInfo(info) => info.best_block_hash,
+                _ => String::new(),
+            };
+
+            loop {
+                let header = node.wait_for_block(&tip, WATCH_BLOCKS_TIMEOUT)?;
+                if header.hash != tip {
+                    println!("{:#?}", header);
+                    tip = header.hash;
+                }
+            }
+        }
+
+        // 哈希算法性能对比
+        if let Some(matches) = matches.subcommand_matches("bench_pow") {
+            let secs: u64 = matches.get_one::<String>("seconds").unwrap().parse()?;
+
+            for algorithm in hash::HashAlgorithm::ALL {
+                let rate = hash::benchmark(algorithm, Duration::from_secs(secs));
+                println!("{:?}: {:.0} hashes/s", algorithm, rate);
+            }
+        }
+
+        // 生成测试向量
+        if let Some(matches) = matches.subcommand_matches("gen_fixtures") {
+            for path in fixtures::write_fixtures(matches.get_one::<String>("dir").unwrap())? {
+                println!("Wrote {}", path);
+            }
+        }
+
         Ok(())
     }
 }
+
+/**
+ * @desc 在终端上确认危险操作，输入 yes 才继续
+ */
+fn confirm(prompt: &str) -> Result<bool> {
+    print!("{} Type 'yes' to continue: ", prompt);
+    std::io::stdout().flush()?;
+
+    let mut answer = String::new();
+    std::io::stdin().read_line(&mut answer)?;
+
+    Ok(answer.trim() == "yes")
+}
+
+/**
+ * @desc 钱包通过 RPC 连接的节点，设置后不打开本地区块链
+ */
+// 命令行访问区块链的方式: 指定了节点或本机节点正在运行(数据库被节点锁定)时通过 RPC，
+// 否则直接打开本地数据库
+enum Backend {
+    Node(rpc::RemoteNode),
+    Local(UTXOSet),
+}
+
+impl Backend {
+    /**
+     * @desc 优先使用命令行指定的节点，其次是本机正在运行的节点，最后打开本地数据库
+     */
+    fn connect(node: Option<&String>, credentials: &Option<(String, String)>) -> Result<Backend> {
+        if let Some(node) = running_node(node, credentials) {
+            return Ok(Backend::Node(node));
+        }
+
+        Ok(Backend::Local(UTXOSet {
+            blockchain: Blockchain::new()?,
+        }))
+    }
+
+    /**
+     * @desc 余额不为 0 的全部地址和余额，按余额从大到小排序
+     */
+    fn address_balances(&self) -> Result<Vec<(String, i32)>> {
+        match self {
+            Backend::Node(node) => node.list_address_balances(),
+            Backend::Local(utxo_set) => utxo_set.address_balances(),
+        }
+    }
+
+    /**
+     * @desc 主链最新区块的高度和 hash
+     */
+    fn best_block(&self) -> Result<(i32, String)> {
+        match self {
+            Backend::Node(node) => {
+                let info = node.get_blockchain_info()?;
+                Ok((info.height, info.best_block_hash))
+            }
+            Backend::Local(utxo_set) => Ok((
+                utxo_set.blockchain.get_best_height()?,
+                utxo_set.blockchain.get_tip_hash(),
+            )),
+        }
+    }
+
+    /**
+     * @desc 主链上指定高度的区块 hash，超出最新高度时为 None
+     */
+    fn block_hash_at(&self, height: i32) -> Result<Option<String>> {
+        match self {
+            Backend::Node(node) => {
+                if height > node.get_blockchain_info()?.height {
+                    return Ok(None);
+                }
+                Ok(Some(node.get_block(&height.to_string())?.get_hash()))
+            }
+            Backend::Local(utxo_set) => utxo_set.blockchain.get_block_hash_at(height),
+        }
+    }
+
+    /**
+     * @desc 未确认的交易与主链上哪些交易冲突
+     */
+    fn find_conflicts(&self, txs: &[Transaction]) -> Result<HashMap<String, Vec<String>>> {
+        match self {
+            Backend::Node(node) => node.get_wallet_conflicts(txs),
+            Backend::Local(utxo_set) => utxo_set.blockchain.find_conflicts(txs),
+        }
+    }
+
+    /**
+     * @desc 记录并广播钱包交易，先记录再发送，发送失败时节点启动后会重新广播
+     */
+    fn send_wallet_tx(self, tx: &Transaction) -> Result<()> {
+        add_pending_tx(tx)?;
+        match self {
+            Backend::Node(node) => {
+                node.send_raw_transaction(tx)?;
+            }
+            Backend::Local(utxo_set) => Server::send_transaction(tx, utxo_set)?,
+        }
+
+        Ok(())
+    }
+
+    /**
+     * @desc 打开本地数据库，供没有 RPC 对应的命令使用
+     */
+    fn local(credentials: &Option<(String, String)>) -> Result<UTXOSet> {
+        check_no_local_node(credentials)?;
+
+        Ok(UTXOSet {
+            blockchain: Blockchain::new()?,
+        })
+    }
+}
+
+impl UtxoProvider for Backend {
+    fn find_spendable_outputs(
+        &self,
+        pub_key_hash: &[u8],
+        amount: i32,
+    ) -> Result<(i32, std::collections::HashMap<String, Vec<i32>>)> {
+        match self {
+            Backend::Node(node) => node.find_spendable_outputs(pub_key_hash, amount),
+            Backend::Local(utxo_set) => utxo_set.find_spendable_outputs(pub_key_hash, amount),
+        }
+    }
+
+    fn find_utxos(&self, pub_key_hash: &[u8]) -> Result<TXOutputs> {
+        match self {
+            Backend::Node(node) => node.find_utxos(pub_key_hash),
+            Backend::Local(utxo_set) => utxo_set.find_utxos(pub_key_hash),
+        }
+    }
+
+    fn find_transaction(&self, id: &str) -> Result<Transaction> {
+        match self {
+            Backend::Node(node) => node.find_transaction(id),
+            Backend::Local(utxo_set) => utxo_set.find_transaction(id),
+        }
+    }
+}
+
+/**
+ * @desc 本机节点运行时数据库被它锁定，需要直接访问数据库的命令先检查，轻钱包模式下不能访问数据库
+ */
+fn check_no_local_node(credentials: &Option<(String, String)>) -> Result<()> {
+    if let Some(node) = light_node() {
+        return Err(format_err!(
+            "The chain database is not used in --light mode, use the node {} instead.",
+            node
+        ));
+    }
+
+    match rpc::local_node(credentials.clone()) {
+        Some(node) => Err(format_err!(
+            "A local node is running on {}, stop it before using the database directly.",
+            node
+        )),
+        None => Ok(()),
+    }
+}
+
+/**
+ * @desc 轻钱包模式: 设置后命令行不打开区块链数据库，全部查询和广播都通过这个节点的 RPC
+ */
+fn set_light_node(node: Option<String>) {
+    *LIGHT_NODE.write().unwrap() = node;
+}
+
+fn light_node() -> Option<String> {
+    LIGHT_NODE.read().unwrap().clone()
+}
+
+/**
+ * @desc 读取轻钱包模式: 命令行参数优先，其次是配置文件，未开启时为 None
+ */
+fn cli_light_node(matches: &ArgMatches, config: &Config) -> Option<String> {
+    if !matches.is_present("light") && config.get_bool("light") != Some(true) {
+        return None;
+    }
+
+    let node = matches
+        .get_one::<String>("light_node")
+        .cloned()
+        .or_else(|| config.get_str("light_node"))
+        .unwrap_or_else(rpc::default_node);
+
+    Some(node)
+}
+
+/**
+ * @desc 命令行指定的节点，其次是本机正在运行的节点，都没有时为 None
+ */
+fn running_node(
+    node: Option<&String>,
+    credentials: &Option<(String, String)>,
+) -> Option<rpc::RemoteNode> {
+    if let Some(node) = node.cloned().or_else(light_node) {
+        return Some(rpc::RemoteNode::new(&node, credentials.clone()));
+    }
+    let node = rpc::local_node(credentials.clone())?;
+    info!("Local node {} is running, use it over RPC.", node);
+
+    Some(rpc::RemoteNode::new(&node, credentials.clone()))
+}
+
+/**
+ * @desc 用本机钱包中多签的全部公钥签名部分签名交易，返回新签名的输入数
+ */
+fn sign_psbt_locally(psbt: &mut Psbt, wlts: &Wallets) -> Result<usize> {
+    let mut signed = 0;
+    for address in wlts.get_all_addresses() {
+        if let Some(wallet) = wlts.get_wallet(&address) {
+            signed += psbt.sign(wallet)?;
+        }
+    }
+
+    Ok(signed)
+}
+
+/**
+ * @desc 只通过 RPC 访问节点的命令的节点地址: 没有指定时轻钱包模式使用配置的节点
+ */
+fn rpc_node_arg(matches: &ArgMatches) -> String {
+    let node = matches.get_one::<String>("node").unwrap();
+    if matches.value_source("node") != Some(ValueSource::DefaultValue) {
+        return node.clone();
+    }
+
+    light_node().unwrap_or_else(rpc::default_node)
+}
+
+/**
+ * @desc 读取节点的监听端口: 命令行参数优先，其次是配置文件，默认是当前网络的默认端口；
+ *       端口 0 时选择一个空闲端口
+ */
+fn cli_port(matches: &ArgMatches, config: &Config) -> Result<String> {
+    let port: u16 = match matches.get_one::<String>("port") {
+        Some(port) => port.parse()?,
+        None => match config.get_int("port") {
+            Some(port) => u16::try_from(port)?,
+            None => params().default_port,
+        },
+    };
+    if port != 0 {
+        return Ok(port.to_string());
+    }
+
+    // 绑定端口 0 由系统分配空闲端口，释放后交给节点监听
+    let port = TcpListener::bind("localhost:0")?.local_addr()?.port();
+    println!("Picked free port {}.", port);
+
+    Ok(port.to_string())
+}
+
+fn lan_discovery_enabled(matches: &ArgMatches, config: &Config) -> bool {
+    matches.is_present("lan_discovery") || config.get_bool("lan_discovery").unwrap_or(false)
+}
+
+/**
+ * @desc 创建节点服务，开启局域网发现时以本机的局域网地址作为节点地址
+ */
+fn new_server(
+    matches: &ArgMatches,
+    config: &Config,
+    port: &str,
+    miner_address: &str,
+    utxo_set: UTXOSet,
+) -> Result<Server> {
+    if !lan_discovery_enabled(matches, config) {
+        return Server::new(port, miner_address, utxo_set);
+    }
+
+    let ip = mdns::local_ip()?;
+    println!("Listen on LAN address {}:{}.", ip, port);
+    Server::with_host(&ip.to_string(), port, miner_address, utxo_set)
+}
+
+fn remote_node_arg<'a>() -> Arg<'a> {
+    Arg::new("node")
+        .long("node")
+        .takes_value(true)
+        .help("Query and broadcast through this node over RPC instead of the local chain.")
+}
+
+/**
+ * @desc 读取金额单位: 命令行参数优先，其次是配置文件，默认是最小单位
+ */
+fn cli_units(matches: &ArgMatches, config: &Config) -> Result<Units> {
+    let name = match matches.get_one::<String>("units") {
+        Some(name) => name.clone(),
+        None => config
+            .get_str("units")
+            .unwrap_or_else(|| String::from("base")),
+    };
+    let decimals = match matches.get_one::<String>("decimals") {
+        Some(decimals) => Some(decimals.parse()?),
+        None => config.get_int("decimals").map(|decimals| decimals as u32),
+    };
+
+    Units::from_name(&name, decimals)
+}
+
+/**
+ * @desc 读取金额或费率参数: 命令行参数优先，其次是配置文件，都按当前单位解析
+ */
+fn amount_arg(matches: &ArgMatches, config: &Config, key: &str) -> Result<Option<i32>> {
+    if let Some(amount) = matches.get_one::<String>(key) {
+        return Ok(Some(parse_amount(amount)?));
+    }
+    if let Some(amount) = config.get_int(key) {
+        return Ok(Some(parse_amount(&amount.to_string())?));
+    }
+    match config.get_str(key) {
+        Some(amount) => Ok(Some(parse_amount(&amount)?)),
+        None => Ok(None),
+    }
+}
+
+/**
+ * @desc 读取随机数种子: 命令行参数优先，其次是配置文件
+ */
+fn rng_seed(matches: &ArgMatches, config: &Config) -> Result<Option<u64>> {
+    match matches.get_one::<String>("seed") {
+        Some(seed) => Ok(Some(seed.parse()?)),
+        None => Ok(config.get_int("seed").map(|seed| seed as u64)),
+    }
+}
+
+/**
+ * @desc 新钱包和 coinbase 交易使用的随机数生成器，设置了种子时结果可以复现，否则使用系统随机数
+ */
+fn cli_rng(matches: &ArgMatches, config: &Config) -> Result<Box<dyn RngCore>> {
+    Ok(match rng_seed(matches, config)? {
+        Some(seed) => Box::new(StdRng::seed_from_u64(seed)),
+        None => Box::new(OsRng),
+    })
+}
+
+/**
+ * @desc RPC 凭据: 命令行参数优先，其次是配置文件，最后读取本机节点的 cookie 文件
+ */
+fn rpc_credentials(matches: &ArgMatches, config: &Config) -> Option<(String, String)> {
+    let user = matches
+        .get_one::<String>("rpc_user")
+        .cloned()
+        .or_else(|| config.get_str("rpc.user"));
+    let password = matches
+        .get_one::<String>("rpc_password")
+        .cloned()
+        .or_else(|| config.get_str("rpc.password"));
+
+    match (user, password) {
+        (Some(user), Some(password)) => Some((user, password)),
+        _ => read_cookie(&data_path(COOKIE_FILE)).ok(),
+    }
+}
+
+/**
+ * @desc 节点和矿工共用的启动参数
+ */
+fn node_args<'a>() -> Vec<Arg<'a>> {
+    vec![
+        Arg::new("consolidate")
+            .long("consolidate")
+            .help("Consolidate small reward outputs of the mining address when fees are low."),
+        Arg::new("consolidate_max_value")
+            .long("consolidate_max_value")
+            .takes_value(true)
+            .help("Largest output value that auto consolidation spends."),
+        Arg::new("consolidate_min_outputs")
+            .long("consolidate_min_outputs")
+            .takes_value(true)
+            .help("Small outputs needed before auto consolidation starts."),
+        Arg::new("consolidate_max_fee_rate")
+            .long("consolidate_max_fee_rate")
+            .takes_value(true)
+            .help("Only consolidate while no mempool transaction pays a higher fee rate."),
+        Arg::new("coinbase_tag")
+            .long("coinbase_tag")
+            .takes_value(true)
+            .help("Short tag written into the coinbase of mined blocks to identify this miner."),
+        Arg::new("reward_split")
+            .long("reward_split")
+            .takes_value(true)
+            .multiple_occurrences(true)
+            .help("Pay a share of each block reward to <address>:<percent>; can be repeated."),
+        Arg::new("min_relay_fee")
+            .long("min_relay_fee")
+            .takes_value(true)
+            .help("Minimum fee per 1000 bytes to accept and relay a transaction."),
+        Arg::new("limit_ancestor_count")
+            .long("limit_ancestor_count")
+            .takes_value(true)
+            .help("Maximum unconfirmed ancestors of a mempool transaction, itself included."),
+        Arg::new("limit_ancestor_size")
+            .long("limit_ancestor_size")
+            .takes_value(true)
+            .help("Maximum total bytes of a mempool transaction and its ancestors."),
+        Arg::new("limit_descendant_count")
+            .long("limit_descendant_count")
+            .takes_value(true)
+            .help("Maximum unconfirmed descendants of a mempool transaction, itself included."),
+        Arg::new("limit_descendant_size")
+            .long("limit_descendant_size")
+            .takes_value(true)
+            .help("Maximum total bytes of a mempool transaction and its descendants."),
+        Arg::new("mempool_expiry")
+            .long("mempool_expiry")
+            .takes_value(true)
+            .help("Hours before an unconfirmed transaction is evicted from the mempool, defaults to 336."),
+        Arg::new("stale_tip_timeout")
+            .long("stale_tip_timeout")
+            .takes_value(true)
+            .help("Seconds without a new block before re-syncing from all peers."),
+        Arg::new("proxy")
+            .long("proxy")
+            .takes_value(true)
+            .help("Connect to peers through a SOCKS5 proxy (host:port)."),
+        Arg::new("nat_pmp")
+            .long("nat_pmp")
+            .help("Map the listening port on the gateway with NAT-PMP."),
+        Arg::new("lan_discovery")
+            .long("lan_discovery")
+            .help("Listen on the LAN address and find other nodes on the local network with mDNS."),
+        Arg::new("lan_discovery_port")
+            .long("lan_discovery_port")
+            .takes_value(true)
+            .help("UDP port of the mDNS announcements, every node on the LAN must use the same port."),
+        Arg::new("alert_key")
+            .long("alert_key")
+            .takes_value(true)
+            .help("Public key (hex) that signs operator alerts, as shown by list_descriptors."),
+        Arg::new("trusted_node")
+            .long("trusted_node")
+            .takes_value(true)
+            .multiple_occurrences(true)
+            .help("Pin the identity of a peer, <identity>@<host:port>; can be repeated."),
+        Arg::new("max_upload")
+            .long("max_upload")
+            .takes_value(true)
+            .help("Limit the upload rate to peers in KB/s, whitelisted peers are not limited."),
+        Arg::new("onion")
+            .long("onion")
+            .help("Create a Tor onion service for the listening port and advertise it."),
+        Arg::new("tor_control")
+            .long("tor_control")
+            .takes_value(true)
+            .help("Tor control port (host:port), defaults to 127.0.0.1:9051."),
+        Arg::new("tor_password")
+            .long("tor_password")
+            .takes_value(true)
+            .help("Password for the Tor control port."),
+        Arg::new("prefer_onion")
+            .long("prefer_onion")
+            .help("Prefer onion peers, use with --proxy pointing at the Tor SOCKS port."),
+        Arg::new("par_verify")
+            .long("par_verify")
+            .takes_value(true)
+            .possible_values(["true", "false"])
+            .help("Verify transaction signatures on all cores, defaults to true."),
+        Arg::new("rest_port")
+            .long("rest_port")
+            .takes_value(true)
+            .help("Serve the read-only REST API (and explorer) on this port."),
+        Arg::new("rest_bind")
+            .long("rest_bind")
+            .takes_value(true)
+            .help("Address the REST and gRPC APIs listen on, defaults to 127.0.0.1."),
+        Arg::new("grpc_port")
+            .long("grpc_port")
+            .takes_value(true)
+            .help("Serve the gRPC API on this port, needs a build with --features grpc."),
+    ]
+}
+
+/**
+ * @desc 根据命令行参数和配置文件设置节点，命令行参数优先
+ */
+fn configure_server(server: &Server, matches: &ArgMatches, config: &Config) -> Result<()> {
+    if let Some(fee_rate) = amount_arg(matches, config, "min_relay_fee")? {
+        server.set_min_relay_fee_rate(fee_rate);
+    }
+
+    let mut limits = MempoolLimits::default();
+    for (name, limit) in [
+        ("limit_ancestor_count", &mut limits.max_ancestors),
+        ("limit_ancestor_size", &mut limits.max_ancestor_size),
+        ("limit_descendant_count", &mut limits.max_descendants),
+        ("limit_descendant_size", &mut limits.max_descendant_size),
+    ] {
+        if let Some(value) = matches.get_one::<String>(name) {
+            *limit = value.parse()?;
+        } else if let Some(value) = config.get_int(name) {
+            *limit = value as usize;
+        }
+    }
+    server.set_mempool_limits(limits);
+
+    if let Some(hours) = matches.get_one::<String>("mempool_expiry") {
+        server.set_mempool_expiry(hours.parse()?);
+    } else if let Some(hours) = config.get_int("mempool_expiry") {
+        server.set_mempool_expiry(hours as u64);
+    }
+
+    if let Some(secs) = matches.get_one::<String>("stale_tip_timeout") {
+        server.set_stale_tip_timeout(secs.parse()?);
+    } else if let Some(secs) = config.get_int("stale_tip_timeout") {
+        server.set_stale_tip_timeout(secs as u64);
+    }
+
+    if let Some(seed) = rng_seed(matches, config)? {
+        server.set_rng_seed(seed);
+    }
+
+    let coinbase_tag = matches
+        .get_one::<String>("coinbase_tag")
+        .cloned()
+        .or_else(|| config.get_str("coinbase_tag"));
+    if let Some(tag) = coinbase_tag {
+        server.set_coinbase_tag(&tag)?;
+    }
+
+    let mut reward_split = config.get_list("reward_split");
+    if let Some(shares) = matches.get_many::<String>("reward_split") {
+        reward_split.extend(shares.cloned());
+    }
+    server.set_reward_split(RewardSplit::parse(&reward_split)?);
+
+    if matches.is_present("consolidate") || config.get_bool("consolidate").unwrap_or(false) {
+        let mut policy = ConsolidationPolicy::default();
+        if let Some(value) = amount_arg(matches, config, "consolidate_max_value")? {
+            policy.max_value = value;
+        }
+        if let Some(count) = matches.get_one::<String>("consolidate_min_outputs") {
+            policy.min_outputs = count.parse()?;
+        } else if let Some(count) = config.get_int("consolidate_min_outputs") {
+            policy.min_outputs = count as usize;
+        }
+        if let Some(fee_rate) = amount_arg(matches, config, "consolidate_max_fee_rate")? {
+            policy.max_fee_rate = fee_rate;
+        }
+        server.set_consolidation(policy);
+    }
+
+    if let Some(proxy) = matches.get_one::<String>("proxy") {
+        server.set_proxy(proxy);
+    } else if let Some(proxy) = config.get_str("proxy") {
+        server.set_proxy(&proxy);
+    }
+
+    server.set_whitelist(config.get_list("whitelist"));
+
+    let alert_key = matches
+        .get_one::<String>("alert_key")
+        .cloned()
+        .or_else(|| config.get_str("alert_key"));
+    if let Some(key) = alert_key {
+        let key = decode_hex(&key)
+            .filter(|key| key.len() == 32)
+            .ok_or_else(|| format_err!("Invalid alert key: {}", key))?;
+        server.set_alert_key(key);
+    }
+
+    let identity = NodeIdentity::load_or_create(&data_path(IDENTITY_FILE))?;
+    println!("Node identity: {}", identity.id());
+    server.set_identity(identity);
+
+    let mut trusted_nodes = config.get_list("trusted_nodes");
+    if let Some(nodes) = matches.get_many::<String>("trusted_node") {
+        trusted_nodes.extend(nodes.cloned());
+    }
+    server.set_trusted_nodes(
+        trusted_nodes
+            .iter()
+            .map(|node| parse_trusted_node(node))
+            .collect::<Result<_>>()?,
+    );
+    // 地址表为空时才使用 DNS 种子
+    if server.load_addr_book(&data_path(PEERS_DB))? == 0 {
+        let seeds = config.get_list("dns_seeds");
+        server.add_seed_nodes(dnsseed::resolve(&seeds, params().default_port));
+    }
+
+    if let Some(enabled) = matches.get_one::<String>("par_verify") {
+        sigverify::set_par_verify(enabled.parse()?);
+    } else if let Some(enabled) = config.get_bool("par_verify") {
+        sigverify::set_par_verify(enabled);
+    }
+
+    if matches.is_present("nat_pmp") || config.get_bool("nat_pmp").unwrap_or(false) {
+        server.enable_port_mapping()?;
+    }
+
+    if lan_discovery_enabled(matches, config) {
+        let port: u16 = match matches.get_one::<String>("lan_discovery_port") {
+            Some(port) => port.parse()?,
+            None => match config.get_int("lan_discovery_port") {
+                Some(port) => u16::try_from(port)?,
+                None => mdns::MDNS_PORT,
+            },
+        };
+        server.enable_lan_discovery(port)?;
+    }
+
+    if let Some(rate) = matches.get_one::<String>("max_upload") {
+        server.set_upload_limit(rate.parse::<u64>()? * 1024);
+    } else if let Some(rate) = config.get_int("max_upload") {
+        server.set_upload_limit(rate as u64 * 1024);
+    }
+
+    if matches.is_present("onion") || config.get_bool("onion").unwrap_or(false) {
+        let control = match matches.get_one::<String>("tor_control") {
+            Some(control) => control.clone(),
+            None => config
+                .get_str("tor_control")
+                .unwrap_or_else(|| String::from(tor::DEFAULT_CONTROL_ADDR)),
+        };
+        let password = matches
+            .get_one::<String>("tor_password")
+            .cloned()
+            .or_else(|| config.get_str("tor_password"));
+        server.enable_onion_service(&control, password.as_deref())?;
+    }
+    if matches.is_present("prefer_onion") || config.get_bool("prefer_onion").unwrap_or(false) {
+        server.set_prefer_onion(true);
+    }
+
+    let mut auth = RpcAuth::from_config(config)?;
+    if config.get_bool("rpc.cookie").unwrap_or(true) {
+        auth.generate_cookie(&data_path(COOKIE_FILE))?;
+    }
+    server.set_rpc_auth(auth);
+
+    let rest_bind = match matches.get_one::<String>("rest_bind") {
+        Some(bind) => bind.clone(),
+        None => config
+            .get_str("rest_bind")
+            .unwrap_or_else(|| String::from(DEFAULT_REST_BIND)),
+    };
+    if let Some(port) = matches.get_one::<String>("rest_port") {
+        server.start_rest(&rest_bind, port.parse()?)?;
+    } else if let Some(port) = config.get_int("rest_port") {
+        server.start_rest(&rest_bind, port as u16)?;
+    }
+    if let Some(port) = matches.get_one::<String>("grpc_port") {
+        server.start_grpc(&rest_bind, port.parse()?)?;
+    } else if let Some(port) = config.get_int("grpc_port") {
+        server.start_grpc(&rest_bind, port as u16)?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,201 @@
+use std::{collections::HashMap, fs};
+
+use super::Result;
+use failure::format_err;
+
+pub const DEFAULT_CONFIG_PATH: &str = "data/config.toml";
+
+// 配置值
+#[derive(Debug, Clone, PartialEq)]
+enum Value {
+    Str(String),
+    Int(i64),
+    Bool(bool),
+    List(Vec<String>),
+}
+
+// 节点配置，支持 TOML 的一个子集: key = value、[section]、# 注释
+#[derive(Debug, Clone, Default)]
+pub struct Config {
+    values: HashMap<String, Value>,
+}
+
+impl Config {
+    /**
+     * @desc 读取配置文件，文件不存在时返回空配置
+     */
+    pub fn load(path: &str) -> Result<Config> {
+        match fs::read_to_string(path) {
+            Ok(text) => Config::parse(&text),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Config::default()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /**
+     * @desc 解析配置文本，section 中的 key 保存为 section.key
+     */
+    pub fn parse(text: &str) -> Result<Config> {
+        let mut config = Config::default();
+        let mut section = String::new();
+
+        for (line_no, line) in text.lines().enumerate() {
+            let line = strip_comment(line).trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            if line.starts_with('[') && line.ends_with(']') {
+                section = line[1..line.len() - 1].trim().to_string();
+                continue;
+            }
+
+            let (key, value) = match line.split_once('=') {
+                Some((key, value)) => (key.trim(), value.trim()),
+                None => return Err(format_err!("Invalid config line {}: {}", line_no + 1, line)),
+            };
+
+            let key = if section.is_empty() {
+                key.to_string()
+            } else {
+                format!("{}.{}", section, key)
+            };
+            let value = parse_value(value)
+                .ok_or_else(|| format_err!("Invalid config value at line {}.", line_no + 1))?;
+            config.values.insert(key, value);
+        }
+
+        Ok(config)
+    }
+
+    pub fn get_str(&self, key: &str) -> Option<String> {
+        match self.values.get(key) {
+            Some(Value::Str(s)) => Some(s.clone()),
+            _ => None,
+        }
+    }
+
+    pub fn get_int(&self, key: &str) -> Option<i64> {
+        match self.values.get(key) {
+            Some(Value::Int(i)) => Some(*i),
+            _ => None,
+        }
+    }
+
+    pub fn get_bool(&self, key: &str) -> Option<bool> {
+        match self.values.get(key) {
+            Some(Value::Bool(b)) => Some(*b),
+            _ => None,
+        }
+    }
+
+    /**
+     * @desc section 中的全部 key(不含 section 前缀)，按字典序排列
+     */
+    pub fn section_keys(&self, section: &str) -> Vec<String> {
+        let prefix = format!("{}.", section);
+        let mut keys: Vec<String> = self
+            .values
+            .keys()
+            .filter_map(|key| key.strip_prefix(&prefix).map(String::from))
+            .collect();
+        keys.sort();
+
+        keys
+    }
+
+    pub fn get_list(&self, key: &str) -> Vec<String> {
+        match self.values.get(key) {
+            Some(Value::List(list)) => list.clone(),
+            Some(Value::Str(s)) => vec![s.clone()],
+            _ => Vec::new(),
+        }
+    }
+}
+
+fn strip_comment(line: &str) -> &str {
+    let mut in_str = false;
+
+    for (i, c) in line.char_indices() {
+        match c {
+            '"' => in_str = !in_str,
+            '#' if !in_str => return &line[..i],
+            _ => {}
+        }
+    }
+
+    line
+}
+
+fn parse_value(value: &str) -> Option<Value> {
+    if let Some(s) = parse_str(value) {
+        return Some(Value::Str(s));
+    }
+
+    if value.starts_with('[') && value.ends_with(']') {
+        let inner = value[1..value.len() - 1].trim();
+        if inner.is_empty() {
+            return Some(Value::List(Vec::new()));
+        }
+
+        let mut list = Vec::new();
+        for item in inner.split(',') {
+            let item = item.trim();
+            if item.is_empty() {
+                continue;
+            }
+            list.push(parse_str(item)?);
+        }
+        return Some(Value::List(list));
+    }
+
+    match value {
+        "true" => Some(Value::Bool(true)),
+        "false" => Some(Value::Bool(false)),
+        _ => value.replace('_', "").parse().ok().map(Value::Int),
+    }
+}
+
+fn parse_str(value: &str) -> Option<String> {
+    if value.len() >= 2 && value.starts_with('"') && value.ends_with('"') {
+        Some(value[1..value.len() - 1].to_string())
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_config() {
+        let config = Config::parse(
+            r#"
+            # node settings
+            proxy = "127.0.0.1:9050"   # tor
+            stale_tip_timeout = 1_200
+            nat_pmp = true
+            whitelist = ["localhost:3001", "localhost:3002"]
+
+            [rpc]
+            user = "alice#1"
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(config.get_str("proxy").unwrap(), "127.0.0.1:9050");
+        assert_eq!(config.get_int("stale_tip_timeout"), Some(1200));
+        assert_eq!(config.get_bool("nat_pmp"), Some(true));
+        assert_eq!(
+            config.get_list("whitelist"),
+            vec!["localhost:3001", "localhost:3002"]
+        );
+        assert_eq!(config.get_str("rpc.user").unwrap(), "alice#1");
+        assert_eq!(config.get_str("missing"), None);
+        assert_eq!(config.section_keys("rpc"), vec!["user"]);
+
+        assert!(Config::parse("no equals sign").is_err());
+        assert!(Config::parse("key = [1, 2]").is_err());
+    }
+}
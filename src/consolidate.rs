@@ -0,0 +1,165 @@
+// UTXO 碎片分析和自动归集: 统计钱包输出的数量和金额分布，
+// 矿工节点可以在交易池费率低的时候把自己的小额出块奖励归集成一个输出。
+
+use crate::transaction::SUBSIDY;
+
+// 金额不超过该值的输出视为小额输出，默认是一次出块奖励
+pub const DEFAULT_DUST_THRESHOLD: i32 = SUBSIDY;
+// 小额输出达到该数量时才自动归集
+pub const DEFAULT_CONSOLIDATE_MIN_OUTPUTS: usize = 20;
+// 交易池中没有费率高于该值的交易时视为低费率时段(每 1000 字节的手续费)
+pub const DEFAULT_CONSOLIDATE_MAX_FEE_RATE: i32 = 0;
+
+// 金额分布中的一个区间 [min, max]，按十进制位数划分
+#[derive(Debug, Clone, PartialEq)]
+pub struct Bucket {
+    pub min: i64,
+    pub max: i64,
+    pub count: usize,
+    pub total: i64,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Fragmentation {
+    pub count: usize,
+    pub total: i64,
+    pub dust_count: usize,
+    pub dust_total: i64,
+    // 只包含有输出的区间，按金额从小到大排序
+    pub buckets: Vec<Bucket>,
+}
+
+impl Fragmentation {
+    /**
+     * @desc 统计输出金额的数量、总额、小额输出和按位数划分的金额分布
+     */
+    pub fn analyze(values: &[i32], dust_threshold: i32) -> Fragmentation {
+        let mut report = Fragmentation::default();
+
+        for &value in values {
+            let value = value as i64;
+            report.count += 1;
+            report.total += value;
+            if value <= dust_threshold as i64 {
+                report.dust_count += 1;
+                report.dust_total += value;
+            }
+
+            let (min, max) = bucket_range(value);
+            match report.buckets.iter_mut().find(|bucket| bucket.min == min) {
+                Some(bucket) => {
+                    bucket.count += 1;
+                    bucket.total += value;
+                }
+                None => report.buckets.push(Bucket {
+                    min,
+                    max,
+                    count: 1,
+                    total: value,
+                }),
+            }
+        }
+        report.buckets.sort_by_key(|bucket| bucket.min);
+
+        report
+    }
+}
+
+// 0 单独一个区间，其余按位数: 1-9, 10-99, 100-999 ...
+fn bucket_range(value: i64) -> (i64, i64) {
+    if value <= 0 {
+        return (value, value);
+    }
+
+    let mut min = 1;
+    while value >= min * 10 {
+        min *= 10;
+    }
+    (min, min * 10 - 1)
+}
+
+// 矿工节点自动归集出块奖励的配置
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ConsolidationPolicy {
+    // 只归集金额不超过 max_value 的输出
+    pub max_value: i32,
+    pub min_outputs: usize,
+    pub max_fee_rate: i32,
+}
+
+impl Default for ConsolidationPolicy {
+    fn default() -> Self {
+        ConsolidationPolicy {
+            max_value: DEFAULT_DUST_THRESHOLD,
+            min_outputs: DEFAULT_CONSOLIDATE_MIN_OUTPUTS,
+            max_fee_rate: DEFAULT_CONSOLIDATE_MAX_FEE_RATE,
+        }
+    }
+}
+
+impl ConsolidationPolicy {
+    /**
+     * @desc 小额输出数量达到 min_outputs，且交易池最高费率不超过 max_fee_rate 时归集
+     */
+    pub fn should_consolidate(&self, values: &[i32], mempool_fee_rates: &[i32]) -> bool {
+        let small = values
+            .iter()
+            .filter(|&&value| value <= self.max_value)
+            .count();
+
+        small >= self.min_outputs.max(2)
+            && mempool_fee_rates
+                .iter()
+                .all(|&fee_rate| fee_rate <= self.max_fee_rate)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_analyze_fragmentation() {
+        let report = Fragmentation::analyze(&[10, 10, 3, 250, 10, 99], 10);
+        assert_eq!(report.count, 6);
+        assert_eq!(report.total, 382);
+        assert_eq!((report.dust_count, report.dust_total), (4, 33));
+
+        let buckets: Vec<(i64, i64, usize, i64)> = report
+            .buckets
+            .iter()
+            .map(|bucket| (bucket.min, bucket.max, bucket.count, bucket.total))
+            .collect();
+        assert_eq!(
+            buckets,
+            vec![(1, 9, 1, 3), (10, 99, 4, 129), (100, 999, 1, 250)]
+        );
+
+        let report = Fragmentation::analyze(&[i32::MAX, i32::MAX], 10);
+        assert_eq!(report.total, 2 * i32::MAX as i64);
+        assert_eq!(report.buckets[0].min, 1_000_000_000);
+        assert_eq!(Fragmentation::analyze(&[], 10), Fragmentation::default());
+    }
+
+    #[test]
+    fn test_should_consolidate() {
+        let policy = ConsolidationPolicy {
+            max_value: 10,
+            min_outputs: 3,
+            max_fee_rate: 5,
+        };
+
+        assert!(policy.should_consolidate(&[10, 10, 10, 50], &[]));
+        assert!(policy.should_consolidate(&[10, 10, 10], &[0, 5]));
+        // 小额输出不够，或者交易池中有更高费率的交易
+        assert!(!policy.should_consolidate(&[10, 10, 50, 50], &[]));
+        assert!(!policy.should_consolidate(&[10, 10, 10], &[6]));
+
+        // 至少两个输出才有归集的意义
+        let policy = ConsolidationPolicy {
+            min_outputs: 0,
+            ..policy
+        };
+        assert!(!policy.should_consolidate(&[10], &[]));
+    }
+}
@@ -0,0 +1,215 @@
+// 输出描述符: 借鉴比特币的 descriptor，用文本描述钱包关注的输出，
+// 钱包不再只能处理单个密钥的输出
+//   pkh(<公钥>)                 单个公钥，地址和普通钱包地址相同
+//   multi(<k>, <公钥1>, ...)    k-of-n 多签，地址是多签脚本的哈希
+
+use std::fmt;
+
+use super::Result;
+use crate::{
+    transaction::{decode_hex, encode_hex},
+    wallets::{address_from_pub_key_hash, hash_pub_key},
+};
+use failure::format_err;
+
+// ed25519 公钥长度
+const PUB_KEY_LEN: usize = 32;
+// 多签最多的公钥数量
+pub const MAX_MULTI_KEYS: usize = 16;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Descriptor {
+    Pkh(Vec<u8>),
+    Multi {
+        threshold: usize,
+        keys: Vec<Vec<u8>>,
+    },
+}
+
+impl Descriptor {
+    /**
+     * @desc 解析描述符文本，公钥是十六进制编码，参数之间可以有空格
+     */
+    pub fn parse(text: &str) -> Result<Descriptor> {
+        let invalid = || format_err!("Invalid descriptor: {}", text);
+
+        let (name, args) = text
+            .trim()
+            .strip_suffix(')')
+            .and_then(|text| text.split_once('('))
+            .ok_or_else(invalid)?;
+        let args: Vec<&str> = args.split(',').map(|arg| arg.trim()).collect();
+
+        match (name.trim(), args.as_slice()) {
+            ("pkh", [key]) => Ok(Descriptor::Pkh(parse_key(key)?)),
+            ("multi", [threshold, keys @ ..]) => {
+                let threshold: usize = threshold.parse().map_err(|_| invalid())?;
+                let keys = keys
+                    .iter()
+                    .map(|key| parse_key(key))
+                    .collect::<Result<Vec<_>>>()?;
+                if keys.len() > MAX_MULTI_KEYS {
+                    return Err(format_err!(
+                        "Too many keys in descriptor: {}, at most {}.",
+                        keys.len(),
+                        MAX_MULTI_KEYS
+                    ));
+                }
+                if threshold == 0 || threshold > keys.len() {
+                    return Err(format_err!(
+                        "Invalid threshold {} for {} keys.",
+                        threshold,
+                        keys.len()
+                    ));
+                }
+
+                Ok(Descriptor::Multi { threshold, keys })
+            }
+            _ => Err(invalid()),
+        }
+    }
+
+    /**
+     * @desc 锁定脚本: pkh 是公钥本身，multi 是 [k, n, 公钥1, ...]
+     */
+    pub fn script(&self) -> Vec<u8> {
+        match self {
+            Descriptor::Pkh(key) => key.clone(),
+            Descriptor::Multi { threshold, keys } => {
+                let mut script = vec![*threshold as u8, keys.len() as u8];
+                for key in keys {
+                    script.extend_from_slice(key);
+                }
+                script
+            }
+        }
+    }
+
+    /**
+     * @desc 由锁定脚本还原描述符，script 的逆操作
+     */
+    pub fn from_script(script: &[u8]) -> Result<Descriptor> {
+        let invalid = || format_err!("Invalid script: {}", encode_hex(script));
+        if script.len() == PUB_KEY_LEN {
+            return Ok(Descriptor::Pkh(script.to_vec()));
+        }
+
+        let (threshold, count, keys) = match script {
+            [threshold, count, keys @ ..] => (*threshold as usize, *count as usize, keys),
+            _ => return Err(invalid()),
+        };
+        if count == 0
+            || count > MAX_MULTI_KEYS
+            || threshold == 0
+            || threshold > count
+            || keys.len() != count * PUB_KEY_LEN
+        {
+            return Err(invalid());
+        }
+
+        Ok(Descriptor::Multi {
+            threshold,
+            keys: keys.chunks(PUB_KEY_LEN).map(|key| key.to_vec()).collect(),
+        })
+    }
+
+    /**
+     * @desc 输出锁定的公钥哈希
+     */
+    pub fn pub_key_hash(&self) -> Vec<u8> {
+        let mut pub_key_hash = self.script();
+        hash_pub_key(&mut pub_key_hash);
+
+        pub_key_hash
+    }
+
+    pub fn address(&self) -> String {
+        address_from_pub_key_hash(&self.pub_key_hash())
+    }
+}
+
+// 规范文本，parse 的逆操作
+impl fmt::Display for Descriptor {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Descriptor::Pkh(key) => write!(f, "pkh({})", encode_hex(key)),
+            Descriptor::Multi { threshold, keys } => {
+                write!(f, "multi({}", threshold)?;
+                for key in keys {
+                    write!(f, ",{}", encode_hex(key))?;
+                }
+                write!(f, ")")
+            }
+        }
+    }
+}
+
+fn parse_key(text: &str) -> Result<Vec<u8>> {
+    decode_hex(text)
+        .filter(|key| key.len() == PUB_KEY_LEN)
+        .ok_or_else(|| format_err!("Invalid public key: {}", text))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::wallets::Wallet;
+
+    #[test]
+    fn test_pkh_matches_wallet_address() {
+        let wallet = Wallet::from_seed(&[1; 32]);
+        let text = format!("pkh({})", encode_hex(&wallet.public_key));
+
+        let descriptor = Descriptor::parse(&text).unwrap();
+        assert_eq!(descriptor, wallet.descriptor());
+        assert_eq!(descriptor.address(), wallet.get_address());
+        assert_eq!(descriptor.to_string(), text);
+    }
+
+    #[test]
+    fn test_multi() {
+        let keys: Vec<String> = (1..=3)
+            .map(|seed| encode_hex(&Wallet::from_seed(&[seed; 32]).public_key))
+            .collect();
+
+        let text = format!("multi( 2, {}, {} ,{})", keys[0], keys[1], keys[2]);
+        let descriptor = Descriptor::parse(&text).unwrap();
+        assert_eq!(
+            descriptor.to_string(),
+            format!("multi(2,{},{},{})", keys[0], keys[1], keys[2])
+        );
+        assert_eq!(
+            Descriptor::parse(&descriptor.to_string()).unwrap(),
+            descriptor
+        );
+        assert_eq!(
+            Descriptor::from_script(&descriptor.script()).unwrap(),
+            descriptor
+        );
+        assert!(Descriptor::from_script(&descriptor.script()[1..]).is_err());
+
+        // 门限和公钥顺序都会改变地址
+        let other = Descriptor::parse(&format!("multi(1,{},{},{})", keys[0], keys[1], keys[2]));
+        assert_ne!(other.unwrap().address(), descriptor.address());
+        let other = Descriptor::parse(&format!("multi(2,{},{},{})", keys[1], keys[0], keys[2]));
+        assert_ne!(other.unwrap().address(), descriptor.address());
+    }
+
+    #[test]
+    fn test_invalid_descriptors() {
+        let key = encode_hex(&Wallet::from_seed(&[1; 32]).public_key);
+        for text in [
+            String::from("pkh()"),
+            format!("pkh({}", key),
+            format!("pkh({},{})", key, key),
+            format!("pk({})", key),
+            format!("pkh({})", &key[2..]),
+            format!("multi(0,{})", key),
+            format!("multi(2,{})", key),
+            format!("multi(x,{})", key),
+            String::from("multi(1)"),
+        ] {
+            assert!(Descriptor::parse(&text).is_err(), "{}", text);
+        }
+    }
+}
@@ -0,0 +1,51 @@
+// DNS 种子: 配置中的种子域名在启动时解析为 A/AAAA 记录，得到的地址加入已知节点，
+// 公开的测试网络只需要维护种子域名，新节点不需要手工配置 IP 就能加入网络
+
+use std::net::ToSocketAddrs;
+
+use log::{info, warn};
+
+/**
+ * @desc 解析种子域名，没有指定端口时使用 default_port；解析失败的种子跳过，返回去重后的 "ip:port" 地址
+ */
+pub fn resolve(seeds: &[String], default_port: u16) -> Vec<String> {
+    let mut nodes = Vec::new();
+    for seed in seeds {
+        let resolved = match seed.rsplit_once(':') {
+            Some((_, port)) if port.parse::<u16>().is_ok() => seed.to_socket_addrs(),
+            _ => (seed.as_str(), default_port).to_socket_addrs(),
+        };
+        match resolved {
+            Ok(addrs) => {
+                let before = nodes.len();
+                for addr in addrs {
+                    let node = addr.to_string();
+                    if !nodes.contains(&node) {
+                        nodes.push(node);
+                    }
+                }
+                info!("DNS seed {} resolved {} nodes.", seed, nodes.len() - before);
+            }
+            Err(e) => warn!("Resolve DNS seed {} failed: {}", seed, e),
+        }
+    }
+
+    nodes
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_resolve() {
+        let nodes = resolve(
+            &[String::from("127.0.0.1"), String::from("[::1]:4000")],
+            3000,
+        );
+        assert_eq!(nodes, vec!["127.0.0.1:3000", "[::1]:4000"]);
+
+        let nodes = resolve(&[String::from("localhost:4001")], 3000);
+        assert!(nodes.contains(&String::from("127.0.0.1:4001")));
+    }
+}
@@ -0,0 +1,264 @@
+// 导出区块数据: 按高度顺序遍历主链，把区块、交易、输入和输出分别写成扁平的表，
+// 供 pandas / DuckDB 等工具分析；输入的金额和地址通过交易索引查找所花费的输出
+
+use std::{
+    fs::{self, File},
+    io::{BufWriter, Write},
+    path::Path,
+};
+
+use super::Result;
+use crate::{
+    block::Block,
+    blockchain::Blockchain,
+    json::Json,
+    transaction::Transaction,
+    wallets::{address_from_pub_key_hash, hash_pub_key},
+};
+use failure::format_err;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ExportFormat {
+    Csv,
+    // 每行一个 JSON 对象
+    Jsonl,
+}
+
+impl ExportFormat {
+    pub fn parse(format: &str) -> Result<ExportFormat> {
+        match format {
+            "csv" => Ok(ExportFormat::Csv),
+            "jsonl" => Ok(ExportFormat::Jsonl),
+            _ => Err(format_err!(
+                "Unknown export format {}, use csv or jsonl.",
+                format
+            )),
+        }
+    }
+
+    fn extension(&self) -> &str {
+        match self {
+            ExportFormat::Csv => "csv",
+            ExportFormat::Jsonl => "jsonl",
+        }
+    }
+}
+
+// 每张表导出的行数
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ExportSummary {
+    pub blocks: usize,
+    pub transactions: usize,
+    pub inputs: usize,
+    pub outputs: usize,
+}
+
+// 一张表对应的文件，CSV 在第一行写入列名
+struct Table {
+    out: BufWriter<File>,
+    format: ExportFormat,
+    rows: usize,
+}
+
+impl Table {
+    fn create(dir: &Path, name: &str, format: ExportFormat) -> Result<Table> {
+        let path = dir.join(format!("{}.{}", name, format.extension()));
+
+        Ok(Table {
+            out: BufWriter::new(File::create(path)?),
+            format,
+            rows: 0,
+        })
+    }
+
+    fn write(&mut self, row: Vec<(&str, Json)>) -> Result<()> {
+        match self.format {
+            ExportFormat::Csv => {
+                if self.rows == 0 {
+                    let header: Vec<&str> = row.iter().map(|(name, _)| *name).collect();
+                    writeln!(self.out, "{}", header.join(","))?;
+                }
+                let values: Vec<String> = row.iter().map(|(_, value)| csv_value(value)).collect();
+                writeln!(self.out, "{}", values.join(","))?;
+            }
+            ExportFormat::Jsonl => writeln!(self.out, "{}", Json::object(row))?,
+        }
+        self.rows += 1;
+
+        Ok(())
+    }
+}
+
+fn csv_value(value: &Json) -> String {
+    match value {
+        Json::Null => String::new(),
+        Json::Str(s) if s.contains([',', '"', '\n', '\r']) => {
+            format!("\"{}\"", s.replace('"', "\"\""))
+        }
+        Json::Str(s) => s.clone(),
+        value => value.to_string(),
+    }
+}
+
+/**
+ * @desc 把主链导出到 dir 目录下的 blocks、transactions、inputs、outputs 四个文件，
+ *       一次只加载一个区块；coinbase 交易没有输入行
+ */
+pub fn export(bc: &Blockchain, format: ExportFormat, dir: &Path) -> Result<ExportSummary> {
+    fs::create_dir_all(dir)?;
+    let mut blocks = Table::create(dir, "blocks", format)?;
+    let mut transactions = Table::create(dir, "transactions", format)?;
+    let mut inputs = Table::create(dir, "inputs", format)?;
+    let mut outputs = Table::create(dir, "outputs", format)?;
+
+    for block in bc.iter_forward() {
+        blocks.write(block_row(&block)?)?;
+        for (index, tx) in block.get_transaction().iter().enumerate() {
+            transactions.write(tx_row(bc, &block, index, tx)?)?;
+            if !tx.is_coinbase() {
+                for (vin_index, vin) in tx.vin.iter().enumerate() {
+                    let prev_out = bc
+                        .find_transaction(&vin.txid)?
+                        .vout
+                        .get(vin.vout as usize)
+                        .cloned()
+                        .ok_or_else(|| {
+                            format_err!("Output {}:{} not found.", vin.txid, vin.vout)
+                        })?;
+                    let mut pub_key_hash = vin.pub_key.clone();
+                    hash_pub_key(&mut pub_key_hash);
+                    inputs.write(vec![
+                        ("txid", tx.id.clone().into()),
+                        ("index", vin_index.into()),
+                        ("prev_txid", vin.txid.clone().into()),
+                        ("prev_vout", vin.vout.into()),
+                        ("address", address_from_pub_key_hash(&pub_key_hash).into()),
+                        ("value", prev_out.value.into()),
+                    ])?;
+                }
+            }
+            for (vout_index, out) in tx.vout.iter().enumerate() {
+                outputs.write(vec![
+                    ("txid", tx.id.clone().into()),
+                    ("index", vout_index.into()),
+                    (
+                        "address",
+                        address_from_pub_key_hash(&out.pub_key_hash).into(),
+                    ),
+                    ("value", out.value.into()),
+                ])?;
+            }
+        }
+    }
+
+    let mut summary = ExportSummary::default();
+    for (table, rows) in [
+        (&mut blocks, &mut summary.blocks),
+        (&mut transactions, &mut summary.transactions),
+        (&mut inputs, &mut summary.inputs),
+        (&mut outputs, &mut summary.outputs),
+    ] {
+        table.out.flush()?;
+        *rows = table.rows;
+    }
+
+    Ok(summary)
+}
+
+fn block_row(block: &Block) -> Result<Vec<(&'static str, Json)>> {
+    let header = block.get_header();
+
+    Ok(vec![
+        ("height", header.height.into()),
+        ("hash", header.hash.into()),
+        ("prev_block_hash", header.prev_block_hash.into()),
+        ("timestamp", header.timestamp.into()),
+        ("version", header.version.into()),
+        ("bits", (header.bits as u64).into()),
+        ("nonce", header.nonce.into()),
+        ("size", block.get_size()?.into()),
+        ("tx_count", block.get_transaction().len().into()),
+    ])
+}
+
+fn tx_row(
+    bc: &Blockchain,
+    block: &Block,
+    index: usize,
+    tx: &Transaction,
+) -> Result<Vec<(&'static str, Json)>> {
+    Ok(vec![
+        ("txid", tx.id.clone().into()),
+        ("block_hash", block.get_hash().into()),
+        ("height", block.get_height().into()),
+        ("index", index.into()),
+        ("version", tx.version.into()),
+        ("coinbase", tx.is_coinbase().into()),
+        ("input_count", tx.vin.len().into()),
+        ("output_count", tx.vout.len().into()),
+        ("size", tx.get_size()?.into()),
+        ("fee", bc.get_tx_fee(tx)?.into()),
+    ])
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        genesis::GenesisSpec, transaction::SUBSIDY, utxo_set::MemoryUtxoSet, wallets::Wallet,
+    };
+    use rand_core::OsRng;
+    use std::env;
+
+    #[test]
+    fn test_export() {
+        let wallet = Wallet::from_seed(&[1; 32]);
+        let mut spec = GenesisSpec::default();
+        spec.allocations.push((wallet.get_address(), SUBSIDY));
+        let genesis = spec.build().unwrap();
+        let bc = Blockchain::create_temporary(&genesis).unwrap();
+        let mut utxos = MemoryUtxoSet::default();
+        utxos.add_transaction(genesis.get_transaction()[0].clone());
+
+        let to = Wallet::from_seed(&[2; 32]).get_address();
+        let tx = Transaction::new_utxo(&wallet, &to, 5, &utxos).unwrap();
+        let cbtx =
+            Transaction::new_coinbase(wallet.get_address(), String::new(), &mut OsRng).unwrap();
+        bc.mine_block(vec![cbtx, tx.clone()]).unwrap();
+
+        let dir = env::temp_dir().join(format!("export-{}", std::process::id()));
+        let summary = export(&bc, ExportFormat::Csv, &dir).unwrap();
+        assert_eq!(
+            summary,
+            ExportSummary {
+                blocks: 2,
+                transactions: 3,
+                inputs: 1,
+                outputs: 4,
+            }
+        );
+
+        let inputs = fs::read_to_string(dir.join("inputs.csv")).unwrap();
+        let rows: Vec<&str> = inputs.lines().collect();
+        assert_eq!(rows[0], "txid,index,prev_txid,prev_vout,address,value");
+        assert_eq!(
+            rows[1],
+            format!(
+                "{},0,{},0,{},{}",
+                tx.id,
+                genesis.get_transaction()[0].id,
+                wallet.get_address(),
+                SUBSIDY
+            )
+        );
+
+        export(&bc, ExportFormat::Jsonl, &dir).unwrap();
+        let blocks = fs::read_to_string(dir.join("blocks.jsonl")).unwrap();
+        assert_eq!(blocks.lines().count(), 2);
+        assert!(blocks.starts_with("{\"height\":0,"));
+        fs::remove_dir_all(&dir).ok();
+
+        assert!(ExportFormat::parse("parquet").is_err());
+        assert_eq!(csv_value(&Json::from("a,\"b\"")), "\"a,\"\"b\"\"\"");
+    }
+}
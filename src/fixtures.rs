@@ -0,0 +1,242 @@
+use std::{collections::HashMap, fs, path::Path};
+
+use super::Result;
+use crate::{
+    block::Block,
+    genesis::GenesisSpec,
+    json::Json,
+    transaction::{encode_hex, Transaction},
+    versionbits::VERSIONBITS_TOP_BITS,
+    wallets::Wallet,
+};
+use failure::format_err;
+use rand::{rngs::StdRng, SeedableRng};
+
+// gen_fixtures 默认的输出目录，单元测试从这里读取黄金文件
+pub const DEFAULT_FIXTURES_DIR: &str = "tests/fixtures";
+
+// 固定的起始时间戳(毫秒)和出块间隔，生成结果不依赖当前时间
+const FIXTURE_TIMESTAMP: u128 = 1_600_000_000_000;
+const FIXTURE_BLOCK_INTERVAL: u128 = 600_000;
+
+// 最低的难度: 目标值为 0xffff00..00，几乎任何哈希都满足，nonce 固定且生成很快
+const FIXTURE_BITS: u32 = 0x2100_ffff;
+
+// 固定密钥和随机数生成器的种子
+const ALICE_SEED: [u8; 32] = [1; 32];
+const BOB_SEED: [u8; 32] = [2; 32];
+const RNG_SEED: u64 = 0;
+
+/**
+ * @desc 生成确定性的小链: 固定密钥、固定时间戳和最低的难度，相同代码总是得到相同的区块
+ *       创世区块给 alice 分配 100，区块 1 中 alice 转给 bob 30，区块 2 中 bob 再转给 alice 10
+ */
+pub fn build_chain() -> Result<Vec<Block>> {
+    let alice = Wallet::from_seed(&ALICE_SEED);
+    let bob = Wallet::from_seed(&BOB_SEED);
+    let mut rng = StdRng::seed_from_u64(RNG_SEED);
+
+    let genesis = GenesisSpec {
+        timestamp: FIXTURE_TIMESTAMP,
+        message: String::from("Fixture genesis"),
+        bits: FIXTURE_BITS,
+        magic: None,
+        allocations: vec![(alice.get_address(), 100)],
+    }
+    .build()?;
+
+    let alice_to_bob = spend(&alice, &genesis.get_transaction()[0], 0, &bob, 30)?;
+    let block1 = Block::new_at(
+        vec![
+            Transaction::new_coinbase(
+                bob.get_address(),
+                String::from("Fixture block 1"),
+                &mut rng,
+            )?,
+            alice_to_bob.clone(),
+        ],
+        genesis.get_hash(),
+        1,
+        VERSIONBITS_TOP_BITS,
+        FIXTURE_BITS,
+        FIXTURE_TIMESTAMP + FIXTURE_BLOCK_INTERVAL,
+    )?;
+
+    let bob_to_alice = spend(&bob, &alice_to_bob, 0, &alice, 10)?;
+    let block2 = Block::new_at(
+        vec![
+            Transaction::new_coinbase(
+                alice.get_address(),
+                String::from("Fixture block 2"),
+                &mut rng,
+            )?,
+            bob_to_alice,
+        ],
+        block1.get_hash(),
+        2,
+        VERSIONBITS_TOP_BITS,
+        FIXTURE_BITS,
+        FIXTURE_TIMESTAMP + 2 * FIXTURE_BLOCK_INTERVAL,
+    )?;
+
+    Ok(vec![genesis, block1, block2])
+}
+
+/**
+ * @desc 花费 prev_tx 的第 vout 个输出，转 amount 给 to，余额找零给付款人
+ */
+fn spend(
+    from: &Wallet,
+    prev_tx: &Transaction,
+    vout: i32,
+    to: &Wallet,
+    amount: i32,
+) -> Result<Transaction> {
+    let value = prev_tx
+        .vout
+        .get(vout as usize)
+        .ok_or_else(|| format_err!("Fixture output {}:{} is not found.", prev_tx.id, vout))?
+        .value;
+    let spendable = (value, HashMap::from([(prev_tx.id.clone(), vec![vout])]));
+
+    let mut tx = Transaction::new_unsigned(&from.public_key, &to.get_address(), amount, spendable)?;
+    tx.sign(
+        &from.secret_key,
+        HashMap::from([(prev_tx.id.clone(), prev_tx.clone())]),
+    )?;
+
+    Ok(tx)
+}
+
+/**
+ * @desc 把区块和交易渲染成 JSON 黄金文件: (文件名, 内容)，每个元素占一行方便比较差异
+ */
+pub fn render(blocks: &[Block]) -> Result<Vec<(&'static str, String)>> {
+    let mut block_items = Vec::new();
+    let mut tx_items = Vec::new();
+    for block in blocks {
+        block_items.push(block_json(block)?);
+        for tx in block.get_transaction() {
+            tx_items.push(tx_json(tx)?);
+        }
+    }
+
+    Ok(vec![
+        ("blocks.json", json_lines(&block_items)),
+        ("transactions.json", json_lines(&tx_items)),
+    ])
+}
+
+/**
+ * @desc 生成测试向量并写入 dir，返回写入的文件路径
+ */
+pub fn write_fixtures(dir: &str) -> Result<Vec<String>> {
+    fs::create_dir_all(dir)?;
+
+    let mut paths = Vec::new();
+    for (name, content) in render(&build_chain()?)? {
+        let path = Path::new(dir).join(name);
+        fs::write(&path, content)?;
+        paths.push(path.display().to_string());
+    }
+
+    Ok(paths)
+}
+
+fn block_json(block: &Block) -> Result<Json> {
+    let header = block.get_header();
+    let txids: Vec<Json> = block
+        .get_transaction()
+        .iter()
+        .map(|tx| tx.id.clone().into())
+        .collect();
+
+    Ok(Json::object(vec![
+        ("hash", header.hash.into()),
+        ("height", header.height.into()),
+        ("version", header.version.into()),
+        ("prev_block_hash", header.prev_block_hash.into()),
+        ("timestamp", header.timestamp.into()),
+        ("bits", format!("{:08x}", header.bits).into()),
+        ("nonce", header.nonce.into()),
+        ("txids", Json::Array(txids)),
+        ("hex", block.to_hex()?.into()),
+    ]))
+}
+
+fn tx_json(tx: &Transaction) -> Result<Json> {
+    let vin: Vec<Json> = tx
+        .vin
+        .iter()
+        .map(|vin| {
+            Json::object(vec![
+                ("txid", vin.txid.clone().into()),
+                ("vout", vin.vout.into()),
+                ("signature", encode_hex(&vin.signature).into()),
+                ("pub_key", encode_hex(&vin.pub_key).into()),
+            ])
+        })
+        .collect();
+    let vout: Vec<Json> = tx
+        .vout
+        .iter()
+        .map(|out| {
+            Json::object(vec![
+                ("value", out.value.into()),
+                ("pub_key_hash", encode_hex(&out.pub_key_hash).into()),
+            ])
+        })
+        .collect();
+
+    Ok(Json::object(vec![
+        ("txid", tx.id.clone().into()),
+        ("version", tx.version.into()),
+        (
+            "relative_lock_time",
+            u64::from(tx.relative_lock_time).into(),
+        ),
+        ("vin", Json::Array(vin)),
+        ("vout", Json::Array(vout)),
+        ("hex", tx.to_hex()?.into()),
+    ]))
+}
+
+fn json_lines(items: &[Json]) -> String {
+    let lines: Vec<String> = items.iter().map(|item| item.to_string()).collect();
+    format!("[\n{}\n]\n", lines.join(",\n"))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::block::hash_meets_target;
+
+    #[test]
+    fn test_fixture_chain_is_linked() {
+        let blocks = build_chain().unwrap();
+        assert_eq!(blocks.len(), 3);
+        for (height, block) in blocks.iter().enumerate() {
+            assert_eq!(block.get_height(), height as i32);
+            assert!(hash_meets_target(&block.get_hash(), block.get_bits()));
+            if height > 0 {
+                assert_eq!(block.get_prev_hash(), blocks[height - 1].get_hash());
+            }
+        }
+    }
+
+    // 共识相关的序列化、哈希或签名变化会让生成结果和黄金文件不一致，
+    // 确认是有意的改动后运行 `blockchain gen_fixtures` 重新生成
+    #[test]
+    fn test_fixtures_match_golden_files() {
+        let rendered = render(&build_chain().unwrap()).unwrap();
+        let golden = [
+            include_str!("../tests/fixtures/blocks.json"),
+            include_str!("../tests/fixtures/transactions.json"),
+        ];
+
+        assert_eq!(rendered.len(), golden.len());
+        for ((name, content), golden) in rendered.iter().zip(golden) {
+            assert_eq!(content, golden, "{} differs from the golden file", name);
+        }
+    }
+}
@@ -0,0 +1,242 @@
+use std::time::SystemTime;
+
+use super::Result;
+use crate::{
+    block::{target_from_bits, Block},
+    config::Config,
+    network,
+    transaction::Transaction,
+    versionbits,
+};
+use failure::format_err;
+
+const GENESIS_COINBASE_DATA: &str = "The Rust is so hard, 淦~~";
+
+// 创世区块配置，相同配置的节点得到相同的创世区块哈希
+//
+// 配置文件使用和节点配置相同的 TOML 子集，全部字段可选:
+//   timestamp = 1700000000000            # 毫秒，默认 0
+//   message = "Genesis of my network"    # coinbase 数据
+//   bits = "1f010000"                    # 十六进制的 nBits，默认网络的最低难度
+//   magic = "0a0b0c0d"                   # 十六进制的 4 字节网络标识，默认网络参数中的值
+//   allocations = ["<address>:<amount>"] # 初始分配，写在创世 coinbase 交易的输出中
+//
+//   [allocations]                        # 也可以每行一个地址，按地址排序以保证结果确定
+//   <address> = <amount>
+#[derive(Debug, Clone)]
+pub struct GenesisSpec {
+    pub timestamp: u128,
+    pub message: String,
+    pub bits: u32,
+    pub magic: Option<[u8; 4]>,
+    pub allocations: Vec<(String, i32)>,
+}
+
+impl Default for GenesisSpec {
+    /**
+     * @desc 默认创世配置: 当前时间，默认 coinbase 数据，没有初始分配
+     */
+    fn default() -> GenesisSpec {
+        let timestamp = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map(|d| d.as_millis())
+            .unwrap_or(0);
+
+        GenesisSpec {
+            timestamp,
+            message: String::from(GENESIS_COINBASE_DATA),
+            bits: network::params().pow_limit_bits,
+            magic: None,
+            allocations: Vec::new(),
+        }
+    }
+}
+
+impl GenesisSpec {
+    /**
+     * @desc 读取创世配置文件
+     */
+    pub fn load(path: &str) -> Result<GenesisSpec> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| format_err!("Cannot read genesis spec {}: {}", path, e))?;
+
+        GenesisSpec::parse(&Config::parse(&text)?)
+    }
+
+    /**
+     * @desc 从配置中解析创世配置，并检查 nBits 有效
+     */
+    pub fn parse(config: &Config) -> Result<GenesisSpec> {
+        let timestamp = config.get_int("timestamp").unwrap_or(0);
+        if timestamp < 0 {
+            return Err(format_err!("Genesis timestamp must not be negative."));
+        }
+
+        let bits = match config.get_str("bits") {
+            Some(bits) => u32::from_str_radix(bits.trim_start_matches("0x"), 16)
+                .map_err(|_| format_err!("Invalid genesis bits: {}", bits))?,
+            None => network::params().pow_limit_bits,
+        };
+        if target_from_bits(bits).is_none() {
+            return Err(format_err!("Invalid genesis bits: {:#010x}", bits));
+        }
+
+        let magic = match config.get_str("magic") {
+            Some(magic) => Some(parse_magic(&magic)?),
+            None => None,
+        };
+
+        let mut allocations = Vec::new();
+        for allocation in config.get_list("allocations") {
+            allocations.push(parse_allocation(&allocation)?);
+        }
+        for address in config.section_keys("allocations") {
+            let amount = config
+                .get_int(&format!("allocations.{}", address))
+                .ok_or_else(|| format_err!("Invalid genesis allocation amount: {}", address))?;
+            allocations.push(parse_allocation(&format!("{}:{}", address, amount))?);
+        }
+
+        Ok(GenesisSpec {
+            timestamp: timestamp as u128,
+            message: config
+                .get_str("message")
+                .unwrap_or_else(|| String::from(GENESIS_COINBASE_DATA)),
+            bits,
+            magic,
+            allocations,
+        })
+    }
+
+    /**
+     * @desc 按配置挖出创世区块
+     */
+    pub fn build(&self) -> Result<Block> {
+        if self.allocations.is_empty() {
+            return Err(format_err!("Genesis block needs at least one allocation."));
+        }
+        self.allocations
+            .iter()
+            .try_fold(0i32, |total, (_, amount)| total.checked_add(*amount))
+            .ok_or_else(|| format_err!("Genesis allocations overflow."))?;
+
+        let cbtx = Transaction::new_genesis(&self.message, &self.allocations)?;
+        Block::new_at(
+            vec![cbtx],
+            String::new(),
+            0,
+            versionbits::VERSIONBITS_TOP_BITS,
+            self.bits,
+            self.timestamp,
+        )
+    }
+}
+
+/**
+ * @desc 解析 "<address>:<amount>" 形式的初始分配
+ */
+pub fn parse_allocation(allocation: &str) -> Result<(String, i32)> {
+    let (address, amount) = allocation
+        .rsplit_once(':')
+        .ok_or_else(|| format_err!("Invalid genesis allocation: {}", allocation))?;
+    let amount: i32 = amount
+        .trim()
+        .parse()
+        .map_err(|_| format_err!("Invalid genesis allocation amount: {}", allocation))?;
+    if amount <= 0 {
+        return Err(format_err!(
+            "Genesis allocation amount must be positive: {}",
+            allocation
+        ));
+    }
+
+    Ok((address.trim().to_string(), amount))
+}
+
+/**
+ * @desc 解析十六进制的 4 字节网络标识
+ */
+pub fn parse_magic(magic: &str) -> Result<[u8; 4]> {
+    let magic = magic.trim_start_matches("0x");
+    let value = u32::from_str_radix(magic, 16)
+        .ok()
+        .filter(|_| magic.len() == 8)
+        .ok_or_else(|| format_err!("Invalid network magic: {}", magic))?;
+
+    Ok(value.to_be_bytes())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::wallets::Wallet;
+
+    #[test]
+    fn test_same_spec_same_genesis() {
+        let address = Wallet::default().get_address();
+        let config = Config::parse(&format!(
+            r#"
+            timestamp = 1700000000000
+            message = "private net"
+            magic = "0a0b0c0d"
+            allocations = ["{}:100", "{}:5"]
+            "#,
+            address, address
+        ))
+        .unwrap();
+
+        let spec = GenesisSpec::parse(&config).unwrap();
+        assert_eq!(spec.magic, Some([0x0a, 0x0b, 0x0c, 0x0d]));
+        assert_eq!(spec.allocations, vec![(address.clone(), 100), (address, 5)]);
+
+        let a = spec.build().unwrap();
+        let b = GenesisSpec::parse(&config).unwrap().build().unwrap();
+        assert_eq!(a.get_hash(), b.get_hash());
+        assert_eq!(a.get_timestamp(), 1700000000000);
+        assert_eq!(a.get_transaction()[0].vout.len(), 2);
+    }
+
+    #[test]
+    fn test_invalid_spec() {
+        for text in [
+            "bits = \"1f800000\"",
+            "magic = \"0a0b0c\"",
+            "allocations = [\"no-amount\"]",
+            "timestamp = -1",
+        ] {
+            assert!(GenesisSpec::parse(&Config::parse(text).unwrap()).is_err());
+        }
+        assert!(GenesisSpec::default().build().is_err());
+    }
+
+    #[test]
+    fn test_allocation_section() {
+        let (a, b) = (
+            Wallet::default().get_address(),
+            Wallet::default().get_address(),
+        );
+        let first = format!("{} = 1", a);
+        let second = format!("{} = 2", b);
+        let text = |lines: [&str; 2]| {
+            format!(
+                "timestamp = 1\nallocations = [\"{}:7\"]\n[allocations]\n{}\n{}\n",
+                a, lines[0], lines[1]
+            )
+        };
+
+        // section 中的地址顺序不影响创世区块
+        let spec = GenesisSpec::parse(&Config::parse(&text([&first, &second])).unwrap()).unwrap();
+        let reordered =
+            GenesisSpec::parse(&Config::parse(&text([&second, &first])).unwrap()).unwrap();
+        assert_eq!(spec.allocations.len(), 3);
+        assert_eq!(spec.allocations[0], (a.clone(), 7));
+        assert_eq!(
+            spec.build().unwrap().get_hash(),
+            reordered.build().unwrap().get_hash()
+        );
+
+        let mut overflow = spec.clone();
+        overflow.allocations = vec![(a.clone(), i32::MAX), (b.clone(), 1)];
+        assert!(overflow.build().is_err());
+    }
+}
@@ -0,0 +1,199 @@
+// 链上投票: 版本 4 起交易可以带一个提案或投票记录输出
+// 提案的 id 是提案交易的 txid，投票窗口为提案所在区块之后的网络参数 voting_period 个区块，
+// 投票按投票人(交易第一个输入的公钥哈希)在提案高度的余额加权，同一投票人只计最后一次投票
+
+use super::Result;
+use crate::{
+    network,
+    transaction::{TXOutput, Transaction},
+    wallets::hash_pub_key,
+};
+use failure::format_err;
+use serde::{Deserialize, Serialize};
+
+// 从版本 4 起交易可以带提案和投票记录
+pub const TX_VERSION_GOVERNANCE: i32 = 4;
+// 记录输出的锁定数据: 提案为标记 + 标题，投票为标记 + 提案 txid + 赞成(1)/反对(0)，金额为 0
+const PROPOSAL_MARKER: &[u8] = b"PROP";
+const VOTE_MARKER: &[u8] = b"VOTE";
+const TXID_LEN: usize = 64;
+pub const MAX_TITLE_LEN: usize = 80;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum GovernanceRecord {
+    Proposal { title: String },
+    Vote { proposal: String, approve: bool },
+}
+
+// 投票索引中的一次投票，区块可以在分叉上
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct VoteEntry {
+    pub block_hash: String,
+    pub height: i32,
+    pub txid: String,
+    pub voter: Vec<u8>,
+    pub approve: bool,
+}
+
+// 提案的计票结果，票数是赞成和反对的投票人在提案高度的余额之和
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Tally {
+    pub proposal: String,
+    pub title: String,
+    pub height: i32,
+    pub end_height: i32,
+    pub yes: i64,
+    pub no: i64,
+    pub voters: usize,
+    pub closed: bool,
+}
+
+impl GovernanceRecord {
+    pub fn proposal(title: &str) -> Result<GovernanceRecord> {
+        if title.trim().is_empty() || title.len() > MAX_TITLE_LEN {
+            return Err(format_err!("Invalid proposal title: {}", title));
+        }
+
+        Ok(GovernanceRecord::Proposal {
+            title: title.to_string(),
+        })
+    }
+
+    pub fn vote(proposal: &str, approve: bool) -> Result<GovernanceRecord> {
+        if proposal.len() != TXID_LEN || !proposal.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return Err(format_err!("Invalid proposal id: {}", proposal));
+        }
+
+        Ok(GovernanceRecord::Vote {
+            proposal: proposal.to_string(),
+            approve,
+        })
+    }
+
+    pub fn to_output(&self) -> TXOutput {
+        let pub_key_hash = match self {
+            GovernanceRecord::Proposal { title } => [PROPOSAL_MARKER, title.as_bytes()].concat(),
+            GovernanceRecord::Vote { proposal, approve } => {
+                [VOTE_MARKER, proposal.as_bytes(), &[*approve as u8]].concat()
+            }
+        };
+
+        TXOutput {
+            value: 0,
+            pub_key_hash,
+        }
+    }
+
+    /**
+     * @desc 交易中的提案或投票记录，版本低于 TX_VERSION_GOVERNANCE 的交易没有记录，
+     *       记录格式错误、金额不为 0、多于一个记录、没有输入或出现在 coinbase 中时报错
+     */
+    pub fn from_tx(tx: &Transaction) -> Result<Option<GovernanceRecord>> {
+        if tx.version < TX_VERSION_GOVERNANCE {
+            return Ok(None);
+        }
+
+        let invalid = || {
+            format_err!(
+                "ERROR: Transaction {} has an invalid governance record.",
+                tx.id
+            )
+        };
+        let mut records = tx.vout.iter().filter(|out| {
+            out.pub_key_hash.starts_with(PROPOSAL_MARKER)
+                || out.pub_key_hash.starts_with(VOTE_MARKER)
+        });
+        let out = match records.next() {
+            Some(out) => out,
+            None => return Ok(None),
+        };
+        if records.next().is_some() || tx.vin.is_empty() || tx.is_coinbase() || out.value != 0 {
+            return Err(invalid());
+        }
+
+        if let Some(title) = out.pub_key_hash.strip_prefix(PROPOSAL_MARKER) {
+            let title = String::from_utf8(title.to_vec()).map_err(|_| invalid())?;
+            return GovernanceRecord::proposal(&title).map(Some);
+        }
+
+        let data = &out.pub_key_hash[VOTE_MARKER.len()..];
+        if data.len() != TXID_LEN + 1 || data[TXID_LEN] > 1 {
+            return Err(invalid());
+        }
+        let proposal = String::from_utf8(data[..TXID_LEN].to_vec()).map_err(|_| invalid())?;
+
+        GovernanceRecord::vote(&proposal, data[TXID_LEN] == 1).map(Some)
+    }
+}
+
+/**
+ * @desc 投票人: 交易第一个输入的公钥哈希
+ */
+pub fn voter(tx: &Transaction) -> Vec<u8> {
+    let mut pub_key_hash = tx.vin[0].pub_key.clone();
+    hash_pub_key(&mut pub_key_hash);
+
+    pub_key_hash
+}
+
+/**
+ * @desc 提案在 height 的区块中时投票窗口的最后一个高度
+ */
+pub fn voting_end(height: i32) -> i32 {
+    height + network::params().voting_period
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::transaction::TXInput;
+
+    fn record_tx(record: &GovernanceRecord) -> Transaction {
+        let mut tx = Transaction {
+            id: String::new(),
+            vin: vec![TXInput {
+                txid: String::from("prev"),
+                vout: 0,
+                signature: Vec::new(),
+                pub_key: vec![1; 32],
+            }],
+            vout: vec![record.to_output()],
+            version: TX_VERSION_GOVERNANCE,
+            relative_lock_time: 0,
+        };
+        tx.id = tx.hash().unwrap();
+        tx
+    }
+
+    #[test]
+    fn test_governance_records() {
+        let proposal = GovernanceRecord::proposal("Raise the block size").unwrap();
+        let tx = record_tx(&proposal);
+        assert_eq!(GovernanceRecord::from_tx(&tx).unwrap(), Some(proposal));
+
+        for approve in [true, false] {
+            let vote = GovernanceRecord::vote(&tx.id, approve).unwrap();
+            assert_eq!(
+                GovernanceRecord::from_tx(&record_tx(&vote)).unwrap(),
+                Some(vote)
+            );
+        }
+
+        // 旧版本交易中的同样输出不是记录
+        let mut old = tx.clone();
+        old.version = TX_VERSION_GOVERNANCE - 1;
+        assert_eq!(GovernanceRecord::from_tx(&old).unwrap(), None);
+
+        let mut twice = tx.clone();
+        twice.vout.push(twice.vout[0].clone());
+        assert!(GovernanceRecord::from_tx(&twice).is_err());
+
+        let mut bad_vote = record_tx(&GovernanceRecord::vote(&tx.id, true).unwrap());
+        *bad_vote.vout[0].pub_key_hash.last_mut().unwrap() = 2;
+        assert!(GovernanceRecord::from_tx(&bad_vote).is_err());
+
+        assert!(GovernanceRecord::proposal(" ").is_err());
+        assert!(GovernanceRecord::proposal(&"a".repeat(MAX_TITLE_LEN + 1)).is_err());
+        assert!(GovernanceRecord::vote("abc", true).is_err());
+    }
+}
@@ -0,0 +1,448 @@
+// gRPC 接口(grpc 特性): 把 proto/blockchain.proto 中的服务请求转换成节点的 RPC 请求，
+// 权限检查和处理逻辑都和 RPC 相同，凭据通过 authorization 元数据以 HTTP Basic 认证的格式传递
+// tonic 的服务方法固定返回 Result<_, Status>，Status 较大不适合装箱
+#![allow(clippy::result_large_err)]
+
+use std::{net::IpAddr, pin::Pin, sync::Arc, thread};
+
+use super::Result;
+use crate::{
+    auth::parse_basic_auth,
+    block::{Block, BlockHeader},
+    rpc::{BlockchainInfo, RpcRequest, RpcResponse, TransactionInfo, Unspent},
+    transaction::{TXInput, TXOutput, Transaction},
+    wallets::decode_address,
+};
+use log::{error, info};
+use tokio::sync::mpsc;
+use tokio_stream::{
+    wrappers::{ReceiverStream, TcpListenerStream},
+    Stream,
+};
+use tonic::{Request, Response, Status};
+
+pub mod pb {
+    tonic::include_proto!("blockchain");
+}
+
+use pb::node_server::{Node, NodeServer};
+
+// 订阅新区块时每次等待最新区块改变的时间(秒)，超时后检查订阅方是否已断开
+const SUBSCRIBE_POLL_INTERVAL: u64 = 10;
+const SUBSCRIBE_BUFFER: usize = 16;
+
+type GrpcResult<T> = std::result::Result<T, Status>;
+
+// 请求来源 ip 和 Basic 认证凭据
+type Caller = (IpAddr, Option<(String, String)>);
+
+// 检查权限并处理 RPC 请求，会阻塞，在 tokio 的阻塞线程中调用
+pub type Handler = Arc<
+    dyn Fn(IpAddr, Option<&(String, String)>, RpcRequest) -> GrpcResult<RpcResponse> + Send + Sync,
+>;
+
+/**
+ * @desc 在独立线程的 tokio 运行时上启动 gRPC 服务
+ */
+pub fn serve(listener: std::net::TcpListener, handler: Handler) -> Result<()> {
+    listener.set_nonblocking(true)?;
+    let runtime = tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()?;
+    let listener = {
+        let _guard = runtime.enter();
+        tokio::net::TcpListener::from_std(listener)?
+    };
+
+    let service = NodeServer::new(NodeService { handler });
+    thread::spawn(move || {
+        let result = runtime.block_on(
+            tonic::transport::Server::builder()
+                .add_service(service)
+                .serve_with_incoming(TcpListenerStream::new(listener)),
+        );
+        if let Err(e) = result {
+            error!("gRPC server stopped: {}", e);
+        }
+    });
+
+    Ok(())
+}
+
+struct NodeService {
+    handler: Handler,
+}
+
+impl NodeService {
+    async fn call(&self, caller: Caller, request: RpcRequest) -> GrpcResult<RpcResponse> {
+        let handler = Arc::clone(&self.handler);
+        tokio::task::spawn_blocking(move || call(&handler, &caller, request))
+            .await
+            .map_err(|e| Status::internal(e.to_string()))?
+    }
+}
+
+#[tonic::async_trait]
+impl Node for NodeService {
+    async fn get_blockchain_info(
+        &self,
+        request: Request<pb::Empty>,
+    ) -> GrpcResult<Response<pb::BlockchainInfo>> {
+        let caller = caller(&request)?;
+        match self.call(caller, RpcRequest::GetBlockchainInfo).await? {
+            RpcResponse::BlockchainInfo(info) => Ok(Response::new(info.into())),
+            _ => Err(unexpected()),
+        }
+    }
+
+    async fn get_block(&self, request: Request<pb::BlockId>) -> GrpcResult<Response<pb::Block>> {
+        let caller = caller(&request)?;
+        let rpc = RpcRequest::GetBlock(request.into_inner().id);
+        match self.call(caller, rpc).await? {
+            RpcResponse::Block(block) => Ok(Response::new((&block).into())),
+            _ => Err(unexpected()),
+        }
+    }
+
+    async fn get_transaction(
+        &self,
+        request: Request<pb::TxId>,
+    ) -> GrpcResult<Response<pb::TransactionInfo>> {
+        let caller = caller(&request)?;
+        let rpc = RpcRequest::GetTransaction(request.into_inner().txid);
+        match self.call(caller, rpc).await? {
+            RpcResponse::Transaction(info) => Ok(Response::new(info.into())),
+            _ => Err(unexpected()),
+        }
+    }
+
+    async fn get_balance(
+        &self,
+        request: Request<pb::Address>,
+    ) -> GrpcResult<Response<pb::Balance>> {
+        let caller = caller(&request)?;
+        let pub_key_hash = address_arg(&request.into_inner().address)?;
+        match self
+            .call(caller, RpcRequest::ListUtxos(pub_key_hash))
+            .await?
+        {
+            RpcResponse::Utxos(utxos) => Ok(Response::new(pb::Balance {
+                balance: utxos.outputs.iter().map(|out| out.value).sum(),
+            })),
+            _ => Err(unexpected()),
+        }
+    }
+
+    async fn list_unspent(
+        &self,
+        request: Request<pb::ListUnspentRequest>,
+    ) -> GrpcResult<Response<pb::Unspent>> {
+        let caller = caller(&request)?;
+        let request = request.into_inner();
+        let rpc = RpcRequest::ListUnspent {
+            pub_key_hash: address_arg(&request.address)?,
+            amount: request.amount,
+        };
+        match self.call(caller, rpc).await? {
+            RpcResponse::Unspent(unspent) => Ok(Response::new(unspent.into())),
+            _ => Err(unexpected()),
+        }
+    }
+
+    async fn abandon_transaction(
+        &self,
+        request: Request<pb::TxId>,
+    ) -> GrpcResult<Response<pb::TxIds>> {
+        let caller = caller(&request)?;
+        let rpc = RpcRequest::AbandonTransaction(request.into_inner().txid);
+        match self.call(caller, rpc).await? {
+            RpcResponse::TxIds(txids) => Ok(Response::new(pb::TxIds { txids })),
+            _ => Err(unexpected()),
+        }
+    }
+
+    async fn send_raw_transaction(
+        &self,
+        request: Request<pb::Transaction>,
+    ) -> GrpcResult<Response<pb::TxId>> {
+        let caller = caller(&request)?;
+        let rpc = RpcRequest::SendRawTransaction(request.into_inner().into());
+        match self.call(caller, rpc).await? {
+            RpcResponse::TxId(txid) => Ok(Response::new(pb::TxId { txid })),
+            _ => Err(unexpected()),
+        }
+    }
+
+    type SubscribeBlocksStream = Pin<Box<dyn Stream<Item = GrpcResult<pb::Block>> + Send>>;
+
+    /**
+     * @desc 用 WaitForBlock 等待最新区块改变，每次改变推送新的最新区块；出错或订阅方断开时结束
+     */
+    async fn subscribe_blocks(
+        &self,
+        request: Request<pb::Empty>,
+    ) -> GrpcResult<Response<Self::SubscribeBlocksStream>> {
+        let caller = caller(&request)?;
+        let mut tip = match self
+            .call(caller.clone(), RpcRequest::GetBlockchainInfo)
+            .await?
+        {
+            RpcResponse::BlockchainInfo(info) => info.best_block_hash,
+            _ => return Err(unexpected()),
+        };
+        info!("gRPC client {} subscribes to blocks.", caller.0);
+
+        let (sender, receiver) = mpsc::channel(SUBSCRIBE_BUFFER);
+        let handler = Arc::clone(&self.handler);
+        tokio::task::spawn_blocking(move || {
+            while !sender.is_closed() {
+                let wait = RpcRequest::WaitForBlock {
+                    tip: tip.clone(),
+                    timeout_secs: SUBSCRIBE_POLL_INTERVAL,
+                };
+                let block = match call(&handler, &caller, wait) {
+                    Ok(RpcResponse::BlockHeader(header)) if header.hash == tip => continue,
+                    Ok(RpcResponse::BlockHeader(header)) => {
+                        tip = header.hash;
+                        match call(&handler, &caller, RpcRequest::GetBlock(tip.clone())) {
+                            Ok(RpcResponse::Block(block)) => Ok(pb::Block::from(&block)),
+                            Ok(_) => Err(unexpected()),
+                            Err(status) => Err(status),
+                        }
+                    }
+                    Ok(_) => Err(unexpected()),
+                    Err(status) => Err(status),
+                };
+
+                let failed = block.is_err();
+                if sender.blocking_send(block).is_err() || failed {
+                    break;
+                }
+            }
+        });
+
+        Ok(Response::new(Box::pin(ReceiverStream::new(receiver))))
+    }
+}
+
+/**
+ * @desc 调用处理方法，RPC 返回的错误转换为 InvalidArgument
+ */
+fn call(handler: &Handler, caller: &Caller, request: RpcRequest) -> GrpcResult<RpcResponse> {
+    match handler(caller.0, caller.1.as_ref(), request)? {
+        RpcResponse::Error(message) => Err(Status::invalid_argument(message)),
+        response => Ok(response),
+    }
+}
+
+/**
+ * @desc 读取请求的来源 ip 和 authorization 元数据中的凭据
+ */
+fn caller<T>(request: &Request<T>) -> GrpcResult<Caller> {
+    let ip = match request.remote_addr() {
+        Some(addr) => addr.ip(),
+        None => return Err(Status::permission_denied("Unknown client address.")),
+    };
+    let credentials = request
+        .metadata()
+        .get("authorization")
+        .and_then(|value| value.to_str().ok())
+        .and_then(parse_basic_auth);
+
+    Ok((ip, credentials))
+}
+
+fn address_arg(address: &str) -> GrpcResult<Vec<u8>> {
+    decode_address(address).map_err(|e| Status::invalid_argument(e.to_string()))
+}
+
+fn unexpected() -> Status {
+    Status::internal("Unexpected RPC response.")
+}
+
+impl From<&Transaction> for pb::Transaction {
+    fn from(tx: &Transaction) -> pb::Transaction {
+        pb::Transaction {
+            id: tx.id.clone(),
+            vin: tx
+                .vin
+                .iter()
+                .map(|input| pb::TxInput {
+                    txid: input.txid.clone(),
+                    vout: input.vout,
+                    signature: input.signature.clone(),
+                    pub_key: input.pub_key.clone(),
+                })
+                .collect(),
+            vout: tx
+                .vout
+                .iter()
+                .map(|output| pb::TxOutput {
+                    value: output.value,
+                    pub_key_hash: output.pub_key_hash.clone(),
+                })
+                .collect(),
+            version: tx.version,
+            relative_lock_time: tx.relative_lock_time,
+        }
+    }
+}
+
+impl From<pb::Transaction> for Transaction {
+    fn from(tx: pb::Transaction) -> Transaction {
+        Transaction {
+            id: tx.id,
+            vin: tx
+                .vin
+                .into_iter()
+                .map(|input| TXInput {
+                    txid: input.txid,
+                    vout: input.vout,
+                    signature: input.signature,
+                    pub_key: input.pub_key,
+                })
+                .collect(),
+            vout: tx
+                .vout
+                .into_iter()
+                .map(|output| TXOutput {
+                    value: output.value,
+                    pub_key_hash: output.pub_key_hash,
+                })
+                .collect(),
+            version: tx.version,
+            relative_lock_time: tx.relative_lock_time,
+        }
+    }
+}
+
+impl From<BlockHeader> for pb::BlockHeader {
+    fn from(header: BlockHeader) -> pb::BlockHeader {
+        pb::BlockHeader {
+            version: header.version,
+            timestamp: header.timestamp as u64,
+            prev_block_hash: header.prev_block_hash,
+            hash: header.hash,
+            bits: header.bits,
+            nonce: header.nonce,
+            height: header.height,
+        }
+    }
+}
+
+impl From<&Block> for pb::Block {
+    fn from(block: &Block) -> pb::Block {
+        pb::Block {
+            header: Some(block.get_header().into()),
+            transactions: block.get_transaction().iter().map(Into::into).collect(),
+        }
+    }
+}
+
+impl From<BlockchainInfo> for pb::BlockchainInfo {
+    fn from(info: BlockchainInfo) -> pb::BlockchainInfo {
+        pb::BlockchainInfo {
+            network: info.network,
+            best_block_hash: info.best_block_hash,
+            height: info.height,
+            chain_work: info.chain_work.to_string(),
+            median_time: info.median_time as u64,
+            softforks: info
+                .softforks
+                .into_iter()
+                .map(|softfork| pb::SoftforkInfo {
+                    name: softfork.name,
+                    bit: softfork.bit as u32,
+                    state: format!("{:?}", softfork.state),
+                })
+                .collect(),
+            alerts: info.alerts,
+        }
+    }
+}
+
+impl From<TransactionInfo> for pb::TransactionInfo {
+    fn from(info: TransactionInfo) -> pb::TransactionInfo {
+        pb::TransactionInfo {
+            transaction: Some((&info.transaction).into()),
+            block_hash: info.block_hash,
+            confirmations: info.confirmations,
+        }
+    }
+}
+
+impl From<Unspent> for pb::Unspent {
+    fn from(unspent: Unspent) -> pb::Unspent {
+        let mut outputs: Vec<pb::OutPoint> = unspent
+            .outputs
+            .into_iter()
+            .flat_map(|(txid, vouts)| {
+                vouts.into_iter().map(move |vout| pb::OutPoint {
+                    txid: txid.clone(),
+                    vout,
+                })
+            })
+            .collect();
+        outputs.sort_by(|a, b| (&a.txid, a.vout).cmp(&(&b.txid, b.vout)));
+
+        pb::Unspent {
+            accumulated: unspent.accumulated,
+            outputs,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::transaction::TX_VERSION;
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_transaction_roundtrip() {
+        let tx = Transaction {
+            id: String::from("tx"),
+            vin: vec![TXInput {
+                txid: String::from("prev"),
+                vout: 1,
+                signature: vec![1, 2],
+                pub_key: vec![3, 4],
+            }],
+            vout: vec![TXOutput {
+                value: 5,
+                pub_key_hash: vec![6],
+            }],
+            version: TX_VERSION,
+            relative_lock_time: 7,
+        };
+
+        let decoded = Transaction::from(pb::Transaction::from(&tx));
+        assert_eq!(format!("{:?}", decoded), format!("{:?}", tx));
+    }
+
+    #[test]
+    fn test_unspent_outpoints() {
+        let unspent = Unspent {
+            accumulated: 9,
+            outputs: HashMap::from([
+                (String::from("b"), vec![0]),
+                (String::from("a"), vec![2, 1]),
+            ]),
+        };
+
+        // 按 (txid, vout) 展开成有序的输出列表
+        let outputs: Vec<(String, i32)> = pb::Unspent::from(unspent)
+            .outputs
+            .into_iter()
+            .map(|out| (out.txid, out.vout))
+            .collect();
+        assert_eq!(
+            outputs,
+            vec![
+                (String::from("a"), 1),
+                (String::from("a"), 2),
+                (String::from("b"), 0)
+            ]
+        );
+    }
+}
@@ -0,0 +1,390 @@
+use std::time::{Duration, Instant};
+
+use crypto::{digest::Digest, sha2::Sha256};
+use serde::{Deserialize, Serialize};
+
+// 工作量证明和 Merkle 树使用的哈希算法
+pub trait PowHasher {
+    fn hash(&self, data: &[u8]) -> [u8; 32];
+
+    fn hash_hex(&self, data: &[u8]) -> String {
+        self.hash(data)
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect()
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum HashAlgorithm {
+    Sha256,
+    DoubleSha256,
+    Blake3,
+}
+
+impl HashAlgorithm {
+    pub const ALL: [HashAlgorithm; 3] = [
+        HashAlgorithm::Sha256,
+        HashAlgorithm::DoubleSha256,
+        HashAlgorithm::Blake3,
+    ];
+
+    pub fn hasher(&self) -> &'static dyn PowHasher {
+        match self {
+            HashAlgorithm::Sha256 => &Sha256Hasher,
+            HashAlgorithm::DoubleSha256 => &DoubleSha256Hasher,
+            HashAlgorithm::Blake3 => &Blake3Hasher,
+        }
+    }
+}
+
+/**
+ * @desc 模拟挖矿: 在给定时间内不断修改 nonce 计算哈希，返回每秒哈希次数
+ */
+pub fn benchmark(algorithm: HashAlgorithm, duration: Duration) -> f64 {
+    let hasher = algorithm.hasher();
+    // 与区块头序列化后的大小相近
+    let mut data = vec![0u8; 128];
+    let start = Instant::now();
+    let mut count: u64 = 0;
+
+    while start.elapsed() < duration {
+        for _ in 0..1000 {
+            data[..8].copy_from_slice(&count.to_le_bytes());
+            hasher.hash(&data);
+            count += 1;
+        }
+    }
+
+    count as f64 / start.elapsed().as_secs_f64()
+}
+
+pub struct Sha256Hasher;
+
+impl PowHasher for Sha256Hasher {
+    fn hash(&self, data: &[u8]) -> [u8; 32] {
+        let mut hasher = Sha256::new();
+        hasher.input(data);
+        let mut res = [0; 32];
+        hasher.result(&mut res);
+
+        res
+    }
+}
+
+pub struct DoubleSha256Hasher;
+
+impl PowHasher for DoubleSha256Hasher {
+    fn hash(&self, data: &[u8]) -> [u8; 32] {
+        Sha256Hasher.hash(&Sha256Hasher.hash(data))
+    }
+}
+
+pub struct Blake3Hasher;
+
+impl PowHasher for Blake3Hasher {
+    fn hash(&self, data: &[u8]) -> [u8; 32] {
+        blake3::hash(data)
+    }
+}
+
+// BLAKE3 参考实现(仅哈希模式，32 字节输出)
+mod blake3 {
+    const OUT_LEN: usize = 32;
+    const BLOCK_LEN: usize = 64;
+    const CHUNK_LEN: usize = 1024;
+
+    const CHUNK_START: u32 = 1 << 0;
+    const CHUNK_END: u32 = 1 << 1;
+    const PARENT: u32 = 1 << 2;
+    const ROOT: u32 = 1 << 3;
+
+    const IV: [u32; 8] = [
+        0x6A09E667, 0xBB67AE85, 0x3C6EF372, 0xA54FF53A, 0x510E527F, 0x9B05688C, 0x1F83D9AB,
+        0x5BE0CD19,
+    ];
+
+    const MSG_PERMUTATION: [usize; 16] = [2, 6, 3, 10, 7, 0, 4, 13, 1, 11, 12, 5, 9, 14, 15, 8];
+
+    fn g(state: &mut [u32; 16], a: usize, b: usize, c: usize, d: usize, mx: u32, my: u32) {
+        state[a] = state[a].wrapping_add(state[b]).wrapping_add(mx);
+        state[d] = (state[d] ^ state[a]).rotate_right(16);
+        state[c] = state[c].wrapping_add(state[d]);
+        state[b] = (state[b] ^ state[c]).rotate_right(12);
+        state[a] = state[a].wrapping_add(state[b]).wrapping_add(my);
+        state[d] = (state[d] ^ state[a]).rotate_right(8);
+        state[c] = state[c].wrapping_add(state[d]);
+        state[b] = (state[b] ^ state[c]).rotate_right(7);
+    }
+
+    fn round(state: &mut [u32; 16], m: &[u32; 16]) {
+        g(state, 0, 4, 8, 12, m[0], m[1]);
+        g(state, 1, 5, 9, 13, m[2], m[3]);
+        g(state, 2, 6, 10, 14, m[4], m[5]);
+        g(state, 3, 7, 11, 15, m[6], m[7]);
+        g(state, 0, 5, 10, 15, m[8], m[9]);
+        g(state, 1, 6, 11, 12, m[10], m[11]);
+        g(state, 2, 7, 8, 13, m[12], m[13]);
+        g(state, 3, 4, 9, 14, m[14], m[15]);
+    }
+
+    fn permute(m: &mut [u32; 16]) {
+        let mut permuted = [0; 16];
+        for i in 0..16 {
+            permuted[i] = m[MSG_PERMUTATION[i]];
+        }
+        *m = permuted;
+    }
+
+    fn compress(
+        chaining_value: &[u32; 8],
+        block_words: &[u32; 16],
+        counter: u64,
+        block_len: u32,
+        flags: u32,
+    ) -> [u32; 16] {
+        let mut state = [
+            chaining_value[0],
+            chaining_value[1],
+            chaining_value[2],
+            chaining_value[3],
+            chaining_value[4],
+            chaining_value[5],
+            chaining_value[6],
+            chaining_value[7],
+            IV[0],
+            IV[1],
+            IV[2],
+            IV[3],
+            counter as u32,
+            (counter >> 32) as u32,
+            block_len,
+            flags,
+        ];
+        let mut block = *block_words;
+
+        for _ in 0..7 {
+            round(&mut state, &block);
+            permute(&mut block);
+        }
+
+        for i in 0..8 {
+            state[i] ^= state[i + 8];
+            state[i + 8] ^= chaining_value[i];
+        }
+
+        state
+    }
+
+    fn first_8_words(compression_output: [u32; 16]) -> [u32; 8] {
+        let mut words = [0; 8];
+        words.copy_from_slice(&compression_output[..8]);
+        words
+    }
+
+    fn words_from_le_bytes(bytes: &[u8; BLOCK_LEN]) -> [u32; 16] {
+        let mut words = [0; 16];
+        for (i, word) in words.iter_mut().enumerate() {
+            let mut b = [0; 4];
+            b.copy_from_slice(&bytes[i * 4..i * 4 + 4]);
+            *word = u32::from_le_bytes(b);
+        }
+        words
+    }
+
+    struct Output {
+        input_chaining_value: [u32; 8],
+        block_words: [u32; 16],
+        counter: u64,
+        block_len: u32,
+        flags: u32,
+    }
+
+    impl Output {
+        fn chaining_value(&self) -> [u32; 8] {
+            first_8_words(compress(
+                &self.input_chaining_value,
+                &self.block_words,
+                self.counter,
+                self.block_len,
+                self.flags,
+            ))
+        }
+
+        fn root_hash(&self) -> [u8; OUT_LEN] {
+            let words = compress(
+                &self.input_chaining_value,
+                &self.block_words,
+                0,
+                self.block_len,
+                self.flags | ROOT,
+            );
+
+            let mut out = [0; OUT_LEN];
+            for (i, word) in words.iter().take(OUT_LEN / 4).enumerate() {
+                out[i * 4..i * 4 + 4].copy_from_slice(&word.to_le_bytes());
+            }
+            out
+        }
+    }
+
+    struct ChunkState {
+        chaining_value: [u32; 8],
+        chunk_counter: u64,
+        block: [u8; BLOCK_LEN],
+        block_len: usize,
+        blocks_compressed: usize,
+    }
+
+    impl ChunkState {
+        fn new(chunk_counter: u64) -> ChunkState {
+            ChunkState {
+                chaining_value: IV,
+                chunk_counter,
+                block: [0; BLOCK_LEN],
+                block_len: 0,
+                blocks_compressed: 0,
+            }
+        }
+
+        fn len(&self) -> usize {
+            BLOCK_LEN * self.blocks_compressed + self.block_len
+        }
+
+        fn start_flag(&self) -> u32 {
+            if self.blocks_compressed == 0 {
+                CHUNK_START
+            } else {
+                0
+            }
+        }
+
+        fn update(&mut self, mut input: &[u8]) {
+            while !input.is_empty() {
+                if self.block_len == BLOCK_LEN {
+                    let block_words = words_from_le_bytes(&self.block);
+                    self.chaining_value = first_8_words(compress(
+                        &self.chaining_value,
+                        &block_words,
+                        self.chunk_counter,
+                        BLOCK_LEN as u32,
+                        self.start_flag(),
+                    ));
+                    self.blocks_compressed += 1;
+                    self.block = [0; BLOCK_LEN];
+                    self.block_len = 0;
+                }
+
+                let take = (BLOCK_LEN - self.block_len).min(input.len());
+                self.block[self.block_len..self.block_len + take].copy_from_slice(&input[..take]);
+                self.block_len += take;
+                input = &input[take..];
+            }
+        }
+
+        fn output(&self) -> Output {
+            Output {
+                input_chaining_value: self.chaining_value,
+                block_words: words_from_le_bytes(&self.block),
+                counter: self.chunk_counter,
+                block_len: self.block_len as u32,
+                flags: self.start_flag() | CHUNK_END,
+            }
+        }
+    }
+
+    fn parent_output(left_child_cv: [u32; 8], right_child_cv: [u32; 8]) -> Output {
+        let mut block_words = [0; 16];
+        block_words[..8].copy_from_slice(&left_child_cv);
+        block_words[8..].copy_from_slice(&right_child_cv);
+
+        Output {
+            input_chaining_value: IV,
+            block_words,
+            counter: 0,
+            block_len: BLOCK_LEN as u32,
+            flags: PARENT,
+        }
+    }
+
+    pub fn hash(mut input: &[u8]) -> [u8; OUT_LEN] {
+        let mut chunk_state = ChunkState::new(0);
+        let mut cv_stack: Vec<[u32; 8]> = Vec::new();
+
+        while !input.is_empty() {
+            // 当前块已满: 合并到 Merkle 树中，再开始新的块
+            if chunk_state.len() == CHUNK_LEN {
+                let mut new_cv = chunk_state.output().chaining_value();
+                let mut total_chunks = chunk_state.chunk_counter + 1;
+                while total_chunks & 1 == 0 {
+                    let left = cv_stack.pop().unwrap();
+                    new_cv = parent_output(left, new_cv).chaining_value();
+                    total_chunks >>= 1;
+                }
+                cv_stack.push(new_cv);
+                chunk_state = ChunkState::new(chunk_state.chunk_counter + 1);
+            }
+
+            let take = (CHUNK_LEN - chunk_state.len()).min(input.len());
+            chunk_state.update(&input[..take]);
+            input = &input[take..];
+        }
+
+        let mut output = chunk_state.output();
+        while let Some(left) = cv_stack.pop() {
+            output = parent_output(left, output.chaining_value());
+        }
+
+        output.root_hash()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_hashers() {
+        assert_eq!(
+            Blake3Hasher.hash_hex(b""),
+            "af1349b9f5f9a1a6a0404dea36dcc9499bcb25c9adc112b7cc9a93cae41f3262"
+        );
+        assert_eq!(
+            Blake3Hasher.hash_hex(b"abc"),
+            "6437b3ac38465133ffb63b75273a8db548c558465d79db03fd359c6cd5bd9d85"
+        );
+        for (len, expected) in [
+            (
+                1,
+                "2d3adedff11b61f14c886e35afa036736dcd87a74d27b5c1510225d0f592e213",
+            ),
+            (
+                1023,
+                "10108970eeda3eb932baac1428c7a2163b0e924c9a9e25b35bba72b28f70bd11",
+            ),
+            (
+                1024,
+                "42214739f095a406f3fc83deb889744ac00df831c10daa55189b5d121c855af7",
+            ),
+            (
+                1025,
+                "d00278ae47eb27b34faecf67b4fe263f82d5412916c1ffd97c8cb7fb814b8444",
+            ),
+            (
+                2048,
+                "e776b6028c7cd22a4d0ba182a8bf62205d2ef576467e838ed6f2529b85fba24a",
+            ),
+        ] {
+            let input: Vec<u8> = (0..len).map(|i| (i % 251) as u8).collect();
+            assert_eq!(Blake3Hasher.hash_hex(&input), expected, "len {}", len);
+        }
+        assert_eq!(
+            Sha256Hasher.hash_hex(b"abc"),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+        assert_eq!(
+            DoubleSha256Hasher.hash_hex(b"abc"),
+            "4f8b42c22dd3729b519ba6f68d2da7cc5b2d606d05daed5ad5128cc03e6c6358"
+        );
+    }
+}
@@ -0,0 +1,140 @@
+// 节点身份: 每个节点持久保存一对 ed25519 密钥，签名 version 和 addr 消息；
+// 其他节点记住地址对应的身份公钥，之后冒用该地址的消息签名对不上会被拒绝，
+// 运维可以预先固定可信节点的身份，避免第一次连接就被冒充
+
+use std::fs;
+
+use super::Result;
+use crate::transaction::{decode_hex, encode_hex};
+use crypto::ed25519;
+use failure::format_err;
+use rand_core::{OsRng, RngCore};
+
+pub const IDENTITY_FILE: &str = ".node_key";
+const SEED_LEN: usize = 32;
+
+#[derive(Clone)]
+pub struct NodeIdentity {
+    secret_key: Vec<u8>,
+    pub public_key: Vec<u8>,
+}
+
+impl NodeIdentity {
+    /**
+     * @desc 用 rng 生成的种子创建身份，不保存，用于只发送消息的临时节点
+     */
+    pub fn new(rng: &mut impl RngCore) -> NodeIdentity {
+        let mut seed = [0; SEED_LEN];
+        rng.fill_bytes(&mut seed);
+
+        NodeIdentity::from_seed(&seed)
+    }
+
+    pub fn from_seed(seed: &[u8]) -> NodeIdentity {
+        let (secret_key, public_key) = ed25519::keypair(seed);
+
+        NodeIdentity {
+            secret_key: secret_key.to_vec(),
+            public_key: public_key.to_vec(),
+        }
+    }
+
+    /**
+     * @desc 读取保存的种子，不存在时生成新的种子并保存，节点重启后身份不变
+     */
+    pub fn load_or_create(path: &str) -> Result<NodeIdentity> {
+        let seed = match fs::read_to_string(path) {
+            Ok(hex) => decode_hex(hex.trim())
+                .filter(|seed| seed.len() == SEED_LEN)
+                .ok_or_else(|| format_err!("Invalid node identity in {}.", path))?,
+            Err(_) => {
+                let mut seed = vec![0; SEED_LEN];
+                OsRng.fill_bytes(&mut seed);
+                fs::write(path, encode_hex(&seed))?;
+                seed
+            }
+        };
+
+        Ok(NodeIdentity::from_seed(&seed))
+    }
+
+    /**
+     * @desc 身份标识: 公钥的十六进制编码
+     */
+    pub fn id(&self) -> String {
+        encode_hex(&self.public_key)
+    }
+
+    pub fn sign(&self, message: &[u8]) -> Vec<u8> {
+        ed25519::signature(message, &self.secret_key).to_vec()
+    }
+}
+
+pub fn verify(public_key: &[u8], message: &[u8], signature: &[u8]) -> bool {
+    public_key.len() == 32
+        && signature.len() == 64
+        && ed25519::verify(message, public_key, signature)
+}
+
+/**
+ * @desc 解析 "身份公钥@host:port" 格式的可信节点，返回 (地址, 公钥)
+ */
+pub fn parse_trusted_node(text: &str) -> Result<(String, Vec<u8>)> {
+    let (id, addr) = text
+        .split_once('@')
+        .ok_or_else(|| format_err!("Trusted node must be <identity>@<host:port>: {}", text))?;
+    let public_key = decode_hex(id)
+        .filter(|key| key.len() == 32)
+        .ok_or_else(|| format_err!("Invalid node identity: {}", id))?;
+    if addr.is_empty() {
+        return Err(format_err!("Missing address of trusted node {}.", id));
+    }
+
+    Ok((addr.to_string(), public_key))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::env;
+
+    #[test]
+    fn test_sign_and_verify() {
+        let identity = NodeIdentity::from_seed(&[1; 32]);
+        let other = NodeIdentity::from_seed(&[2; 32]);
+
+        let signature = identity.sign(b"version");
+        assert!(verify(&identity.public_key, b"version", &signature));
+        assert!(!verify(&identity.public_key, b"addr", &signature));
+        assert!(!verify(&other.public_key, b"version", &signature));
+        assert!(!verify(&identity.public_key, b"version", &signature[1..]));
+    }
+
+    #[test]
+    fn test_load_or_create() {
+        let path = env::temp_dir().join(format!("node-key-{}", std::process::id()));
+        let path = path.to_str().unwrap();
+        fs::remove_file(path).ok();
+
+        let created = NodeIdentity::load_or_create(path).unwrap();
+        let loaded = NodeIdentity::load_or_create(path).unwrap();
+        assert_eq!(created.id(), loaded.id());
+
+        fs::write(path, "abcd").unwrap();
+        assert!(NodeIdentity::load_or_create(path).is_err());
+        fs::remove_file(path).ok();
+    }
+
+    #[test]
+    fn test_parse_trusted_node() {
+        let identity = NodeIdentity::from_seed(&[1; 32]);
+        let text = format!("{}@localhost:3001", identity.id());
+        let (addr, key) = parse_trusted_node(&text).unwrap();
+        assert_eq!(addr, "localhost:3001");
+        assert_eq!(key, identity.public_key);
+
+        assert!(parse_trusted_node("localhost:3001").is_err());
+        assert!(parse_trusted_node("abcd@localhost:3001").is_err());
+        assert!(parse_trusted_node(&format!("{}@", identity.id())).is_err());
+    }
+}
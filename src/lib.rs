@@ -0,0 +1,58 @@
+pub mod addrbook;
+pub mod alert;
+pub mod anchor;
+#[cfg(test)]
+mod arbitrary;
+pub mod auth;
+pub mod bandwidth;
+pub mod block;
+pub mod blockchain;
+pub mod bumpfee;
+pub mod channel;
+pub mod cli;
+pub mod config;
+pub mod consolidate;
+pub mod descriptor;
+pub mod dnsseed;
+pub mod export;
+pub mod fixtures;
+pub mod genesis;
+pub mod governance;
+#[cfg(feature = "grpc")]
+pub mod grpc;
+pub mod hash;
+pub mod identity;
+pub mod json;
+pub mod mdns;
+pub mod mining;
+pub mod names;
+pub mod natpmp;
+pub mod network;
+pub mod paper;
+pub mod payment;
+#[cfg(feature = "pos")]
+pub mod pos;
+pub mod proxy;
+pub mod psbt;
+pub mod qr;
+pub mod rest;
+pub mod reward;
+pub mod rpc;
+pub mod schema;
+pub mod server;
+pub mod sigverify;
+#[cfg(all(test, feature = "sim"))]
+pub mod sim;
+pub mod stats;
+pub mod timedata;
+pub mod tor;
+pub mod transaction;
+pub mod units;
+pub mod utxo_set;
+pub mod vanity;
+pub mod vault;
+pub mod versionbits;
+pub mod vm;
+pub mod wallets;
+
+pub type Result<T> = std::result::Result<T, failure::Error>;
@@ -1,14 +1,4 @@
-mod block;
-mod blockchain;
-mod cli;
-mod server;
-mod transaction;
-mod utxo_set;
-mod wallets;
-
-pub type Result<T> = std::result::Result<T, failure::Error>;
-
-use cli::Cli;
+use blockchain::cli::Cli;
 use env_logger::Env;
 
 fn main() {
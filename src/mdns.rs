@@ -0,0 +1,320 @@
+use std::net::{IpAddr, Ipv4Addr, SocketAddrV4, UdpSocket};
+
+use super::Result;
+use crate::transaction::encode_hex;
+use failure::format_err;
+
+// 局域网节点发现: 按 mDNS(RFC 6762) 组播公告 _blockchain._tcp.local 服务，
+// 记录包括 PTR、SRV、TXT(网络标识) 和 A 记录，同一局域网中开启发现的节点收到公告后互相连接
+pub const MDNS_ADDR: Ipv4Addr = Ipv4Addr::new(224, 0, 0, 251);
+pub const MDNS_PORT: u16 = 5353;
+const SERVICE: &str = "_blockchain._tcp.local";
+const TYPE_A: u16 = 1;
+const TYPE_PTR: u16 = 12;
+const TYPE_TXT: u16 = 16;
+const TYPE_SRV: u16 = 33;
+const CLASS_IN: u16 = 1;
+// 唯一记录的缓存刷新位
+const CACHE_FLUSH: u16 = 0x8000;
+const FLAGS_RESPONSE: u16 = 0x8400;
+const RECORD_TTL: u32 = 120;
+// 名称压缩指针的最大跳转次数，避免循环指针
+const MAX_POINTER_JUMPS: usize = 16;
+
+// 收到的 mDNS 消息中和本服务相关的内容
+#[derive(Debug, Default, PartialEq)]
+pub struct Message {
+    // 是否在查询本服务
+    pub is_query: bool,
+    // 公告的节点: (ip:port, 网络标识的十六进制)
+    pub peers: Vec<(String, String)>,
+}
+
+/**
+ * @desc 本机在局域网中的 ip: 向组播地址"连接"一个 UDP socket，读取系统选择的本地地址，不会发送数据
+ */
+pub fn local_ip() -> Result<Ipv4Addr> {
+    let socket = UdpSocket::bind("0.0.0.0:0")?;
+    socket.connect(SocketAddrV4::new(MDNS_ADDR, MDNS_PORT))?;
+    match socket.local_addr()?.ip() {
+        IpAddr::V4(ip) if !ip.is_unspecified() => Ok(ip),
+        ip => Err(format_err!("No LAN address found: {}.", ip)),
+    }
+}
+
+/**
+ * @desc 查询本服务的 mDNS 消息
+ */
+pub fn query() -> Vec<u8> {
+    let mut data = header(0, 1, 0);
+    write_name(&mut data, SERVICE);
+    data.extend_from_slice(&TYPE_PTR.to_be_bytes());
+    data.extend_from_slice(&CLASS_IN.to_be_bytes());
+    data
+}
+
+/**
+ * @desc 公告节点的 mDNS 响应，instance 是节点实例名，magic 是网络标识
+ */
+pub fn announcement(instance: &str, ip: Ipv4Addr, port: u16, magic: [u8; 4]) -> Vec<u8> {
+    let full_name = format!("{}.{}", instance, SERVICE);
+    let host = format!("{}.local", instance);
+    let mut data = header(FLAGS_RESPONSE, 0, 4);
+
+    let mut ptr = Vec::new();
+    write_name(&mut ptr, &full_name);
+    write_record(&mut data, SERVICE, TYPE_PTR, CLASS_IN, &ptr);
+
+    let mut srv = vec![0, 0, 0, 0];
+    srv.extend_from_slice(&port.to_be_bytes());
+    write_name(&mut srv, &host);
+    write_record(
+        &mut data,
+        &full_name,
+        TYPE_SRV,
+        CLASS_IN | CACHE_FLUSH,
+        &srv,
+    );
+
+    let txt = format!("magic={}", encode_hex(&magic));
+    let mut rdata = vec![txt.len() as u8];
+    rdata.extend_from_slice(txt.as_bytes());
+    write_record(
+        &mut data,
+        &full_name,
+        TYPE_TXT,
+        CLASS_IN | CACHE_FLUSH,
+        &rdata,
+    );
+
+    write_record(
+        &mut data,
+        &host,
+        TYPE_A,
+        CLASS_IN | CACHE_FLUSH,
+        &ip.octets(),
+    );
+    data
+}
+
+fn header(flags: u16, questions: u16, answers: u16) -> Vec<u8> {
+    let mut data = vec![0, 0];
+    for value in [flags, questions, answers, 0, 0] {
+        data.extend_from_slice(&value.to_be_bytes());
+    }
+    data
+}
+
+fn write_name(data: &mut Vec<u8>, name: &str) {
+    for label in name.split('.') {
+        data.push(label.len() as u8);
+        data.extend_from_slice(label.as_bytes());
+    }
+    data.push(0);
+}
+
+fn write_record(data: &mut Vec<u8>, name: &str, kind: u16, class: u16, rdata: &[u8]) {
+    write_name(data, name);
+    data.extend_from_slice(&kind.to_be_bytes());
+    data.extend_from_slice(&class.to_be_bytes());
+    data.extend_from_slice(&RECORD_TTL.to_be_bytes());
+    data.extend_from_slice(&(rdata.len() as u16).to_be_bytes());
+    data.extend_from_slice(rdata);
+}
+
+/**
+ * @desc 解析 mDNS 消息，忽略其他服务的记录；支持名称压缩
+ */
+pub fn parse(data: &[u8]) -> Result<Message> {
+    let mut reader = Reader { data, pos: 0 };
+    let flags = reader.read_u16_at(2)?;
+    let questions = reader.read_u16_at(4)?;
+    let records = (6..12)
+        .step_by(2)
+        .map(|offset| reader.read_u16_at(offset).map(usize::from))
+        .sum::<Result<usize>>()?;
+    reader.pos = 12;
+
+    let mut message = Message::default();
+    for _ in 0..questions {
+        let name = reader.read_name()?;
+        let kind = reader.read_u16()?;
+        reader.read_u16()?;
+        if flags & 0x8000 == 0 && kind == TYPE_PTR && name.eq_ignore_ascii_case(SERVICE) {
+            message.is_query = true;
+        }
+    }
+
+    // 实例名 -> (端口, 主机名)、实例名 -> 网络标识、主机名 -> ip
+    let mut services = Vec::new();
+    let mut magics = Vec::new();
+    let mut hosts = Vec::new();
+    for _ in 0..records {
+        let name = reader.read_name()?;
+        let kind = reader.read_u16()?;
+        reader.read_u16()?;
+        reader.pos += 4;
+        let len = reader.read_u16()? as usize;
+        let end = reader.pos + len;
+        if end > data.len() {
+            return Err(format_err!("Truncated mDNS record."));
+        }
+
+        match kind {
+            TYPE_SRV if name.ends_with(SERVICE) && len >= 6 => {
+                let port = reader.read_u16_at(reader.pos + 4)?;
+                reader.pos += 6;
+                services.push((name, port, reader.read_name()?));
+            }
+            TYPE_TXT if name.ends_with(SERVICE) => {
+                let mut pos = reader.pos;
+                while pos < end {
+                    let text_end = (pos + 1 + data[pos] as usize).min(end);
+                    let text = String::from_utf8_lossy(&data[pos + 1..text_end]);
+                    if let Some(magic) = text.strip_prefix("magic=") {
+                        magics.push((name.clone(), magic.to_string()));
+                    }
+                    pos = text_end;
+                }
+            }
+            TYPE_A if len == 4 => {
+                let ip = Ipv4Addr::new(data[end - 4], data[end - 3], data[end - 2], data[end - 1]);
+                hosts.push((name, ip));
+            }
+            _ => {}
+        }
+        reader.pos = end;
+    }
+
+    for (instance, port, host) in services {
+        let ip = hosts
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case(&host));
+        let magic = magics.iter().find(|(name, _)| name == &instance);
+        if let (Some((_, ip)), Some((_, magic))) = (ip, magic) {
+            message
+                .peers
+                .push((format!("{}:{}", ip, port), magic.clone()));
+        }
+    }
+
+    Ok(message)
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl Reader<'_> {
+    fn read_u16_at(&self, pos: usize) -> Result<u16> {
+        match self.data.get(pos..pos + 2) {
+            Some(bytes) => Ok(u16::from_be_bytes([bytes[0], bytes[1]])),
+            None => Err(format_err!("Truncated mDNS message.")),
+        }
+    }
+
+    fn read_u16(&mut self) -> Result<u16> {
+        let value = self.read_u16_at(self.pos)?;
+        self.pos += 2;
+        Ok(value)
+    }
+
+    // 读取名称，遇到压缩指针时跳转到指针位置继续读取
+    fn read_name(&mut self) -> Result<String> {
+        let mut labels = Vec::new();
+        let mut pos = self.pos;
+        let mut jumps = 0;
+        loop {
+            let len = *self
+                .data
+                .get(pos)
+                .ok_or_else(|| format_err!("Truncated mDNS name."))? as usize;
+            if len & 0xc0 == 0xc0 {
+                if jumps == 0 {
+                    self.pos = pos + 2;
+                }
+                jumps += 1;
+                if jumps > MAX_POINTER_JUMPS {
+                    return Err(format_err!("Too many mDNS name pointers."));
+                }
+                pos = (self.read_u16_at(pos)? & 0x3fff) as usize;
+                continue;
+            }
+            if len == 0 {
+                if jumps == 0 {
+                    self.pos = pos + 1;
+                }
+                return Ok(labels.join("."));
+            }
+
+            let label = self
+                .data
+                .get(pos + 1..pos + 1 + len)
+                .ok_or_else(|| format_err!("Truncated mDNS name."))?;
+            labels.push(String::from_utf8_lossy(label).to_string());
+            pos += 1 + len;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_announcement() {
+        let data = announcement("node1", Ipv4Addr::new(192, 168, 1, 7), 3001, [1, 2, 3, 4]);
+        let message = parse(&data).unwrap();
+        assert!(!message.is_query);
+        assert_eq!(
+            message.peers,
+            vec![(String::from("192.168.1.7:3001"), String::from("01020304"))]
+        );
+
+        assert_eq!(
+            parse(&query()).unwrap(),
+            Message {
+                is_query: true,
+                peers: Vec::new()
+            }
+        );
+        assert!(parse(&data[..data.len() - 2]).is_err());
+    }
+
+    #[test]
+    fn test_parse_compressed_names() {
+        // SRV 记录的名称和目标主机都用指针指向前面的名称
+        let mut data = header(FLAGS_RESPONSE, 0, 3);
+        let service_at = data.len() as u16;
+        let mut txt = vec![14];
+        txt.extend_from_slice(b"magic=0a0b0c0d");
+        write_record(
+            &mut data,
+            "pc._blockchain._tcp.local",
+            TYPE_TXT,
+            CLASS_IN,
+            &txt,
+        );
+        let host_at = data.len() as u16;
+        write_record(&mut data, "pc.local", TYPE_A, CLASS_IN, &[10, 0, 0, 2]);
+
+        data.extend_from_slice(&(0xc000 | service_at).to_be_bytes());
+        data.extend_from_slice(&TYPE_SRV.to_be_bytes());
+        data.extend_from_slice(&CLASS_IN.to_be_bytes());
+        data.extend_from_slice(&RECORD_TTL.to_be_bytes());
+        data.extend_from_slice(&8u16.to_be_bytes());
+        data.extend_from_slice(&[0, 0, 0, 0, 0x0b, 0xb8]);
+        data.extend_from_slice(&(0xc000 | host_at).to_be_bytes());
+
+        assert_eq!(
+            parse(&data).unwrap().peers,
+            vec![(String::from("10.0.0.2:3000"), String::from("0a0b0c0d"))]
+        );
+
+        // 指向自己的指针
+        let mut data = header(FLAGS_RESPONSE, 1, 0);
+        data.extend_from_slice(&0xc00cu16.to_be_bytes());
+        assert!(parse(&data).is_err());
+    }
+}
@@ -0,0 +1,78 @@
+use std::{sync::Mutex, time::Duration};
+
+use super::Result;
+use crate::{block, blockchain::Blockchain, rpc::MiningInfo, timedata};
+
+// 挖矿统计: 工作量证明尝试的 nonce 数及耗时、挖出的区块数、最近一个区块模板的交易数
+struct MiningStats {
+    hashes: u64,
+    elapsed: Duration,
+    blocks_mined: u64,
+    template_tx_count: usize,
+}
+
+static MINING_STATS: Mutex<MiningStats> = Mutex::new(MiningStats {
+    hashes: 0,
+    elapsed: Duration::ZERO,
+    blocks_mined: 0,
+    template_tx_count: 0,
+});
+
+/**
+ * @desc 记录一次工作量证明尝试的 nonce 数和耗时
+ */
+pub fn record_attempts(hashes: u64, elapsed: Duration) {
+    let mut stats = MINING_STATS.lock().unwrap();
+    stats.hashes += hashes;
+    stats.elapsed += elapsed;
+}
+
+/**
+ * @desc 记录开始挖掘的区块模板中的交易数(含 coinbase 交易)
+ */
+pub fn record_template(tx_count: usize) {
+    MINING_STATS.lock().unwrap().template_tx_count = tx_count;
+}
+
+/**
+ * @desc 记录挖出一个区块
+ */
+pub fn record_block() {
+    MINING_STATS.lock().unwrap().blocks_mined += 1;
+}
+
+/**
+ * @desc 平均算力(每秒尝试的 nonce 数)，还没有挖过矿时为 0
+ */
+pub fn hash_rate() -> f64 {
+    let stats = MINING_STATS.lock().unwrap();
+    if stats.elapsed.is_zero() {
+        return 0.0;
+    }
+
+    stats.hashes as f64 / stats.elapsed.as_secs_f64()
+}
+
+pub fn blocks_mined() -> u64 {
+    MINING_STATS.lock().unwrap().blocks_mined
+}
+
+pub fn template_tx_count() -> usize {
+    MINING_STATS.lock().unwrap().template_tx_count
+}
+
+/**
+ * @desc 汇总挖矿信息
+ */
+pub fn get_mining_info(bc: &Blockchain) -> Result<MiningInfo> {
+    let tip = bc.get_header(&bc.get_tip_hash())?;
+    let since_last_block = timedata::get_adjusted_time().saturating_sub(tip.timestamp) / 1000;
+
+    Ok(MiningInfo {
+        hash_rate: hash_rate(),
+        blocks_mined: blocks_mined(),
+        difficulty: block::block_work(bc.get_next_work_required(&tip.hash)),
+        template_tx_count: template_tx_count(),
+        secs_since_last_block: since_last_block as u64,
+    })
+}
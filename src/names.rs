@@ -0,0 +1,228 @@
+// 名称注册: 版本 3 起交易可以带一个名称记录输出，把可读的名称映射到地址，
+// 先注册者获得名称，注册在网络参数 name_expiry 个区块后过期，
+// 过期前只有持有者(交易的某个输入由持有者签名)可以续期或转给新地址，过期后任何人都可以重新注册
+
+use super::Result;
+use crate::{
+    network,
+    transaction::{TXOutput, Transaction},
+    wallets::{address_from_pub_key_hash, decode_address},
+};
+use failure::format_err;
+use serde::{Deserialize, Serialize};
+
+// 从版本 3 起交易可以注册名称，更低版本交易中形如名称记录的输出没有特殊含义
+pub const TX_VERSION_NAME: i32 = 3;
+// 名称记录输出的锁定数据: 标记 + 20 字节公钥哈希 + 名称，金额为 0，不能被花费
+const NAME_MARKER: &[u8] = b"NAME";
+const PUB_KEY_HASH_LEN: usize = 20;
+pub const MAX_NAME_LEN: usize = 32;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct NameRecord {
+    pub name: String,
+    pub pub_key_hash: Vec<u8>,
+}
+
+// 名称索引中的一次注册，区块可以在分叉上
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct NameClaim {
+    pub block_hash: String,
+    pub height: i32,
+    pub txid: String,
+    pub pub_key_hash: Vec<u8>,
+}
+
+impl NameRecord {
+    pub fn new(name: &str, address: &str) -> Result<NameRecord> {
+        check_name(name)?;
+
+        Ok(NameRecord {
+            name: name.to_string(),
+            pub_key_hash: decode_address(address)?,
+        })
+    }
+
+    pub fn to_output(&self) -> TXOutput {
+        let mut pub_key_hash = NAME_MARKER.to_vec();
+        pub_key_hash.extend_from_slice(&self.pub_key_hash);
+        pub_key_hash.extend_from_slice(self.name.as_bytes());
+
+        TXOutput {
+            value: 0,
+            pub_key_hash,
+        }
+    }
+
+    /**
+     * @desc 交易中的名称记录，版本低于 TX_VERSION_NAME 的交易没有名称记录，
+     *       记录格式错误、金额不为 0、多于一个记录或出现在 coinbase 中时报错
+     */
+    pub fn from_tx(tx: &Transaction) -> Result<Option<NameRecord>> {
+        if tx.version < TX_VERSION_NAME {
+            return Ok(None);
+        }
+
+        let mut records = tx
+            .vout
+            .iter()
+            .filter(|out| out.pub_key_hash.starts_with(NAME_MARKER));
+        let out = match records.next() {
+            Some(out) => out,
+            None => return Ok(None),
+        };
+        if records.next().is_some() || tx.is_coinbase() || out.value != 0 {
+            return Err(format_err!(
+                "ERROR: Transaction {} has an invalid name record.",
+                tx.id
+            ));
+        }
+
+        let data = &out.pub_key_hash[NAME_MARKER.len()..];
+        if data.len() <= PUB_KEY_HASH_LEN {
+            return Err(format_err!(
+                "ERROR: Transaction {} has an invalid name record.",
+                tx.id
+            ));
+        }
+        let name = String::from_utf8(data[PUB_KEY_HASH_LEN..].to_vec())?;
+        check_name(&name)?;
+
+        Ok(Some(NameRecord {
+            name,
+            pub_key_hash: data[..PUB_KEY_HASH_LEN].to_vec(),
+        }))
+    }
+}
+
+impl NameClaim {
+    /**
+     * @desc 注册失效的高度，从这个高度起名称可以被其他人注册
+     */
+    pub fn expires_at(&self) -> i32 {
+        self.height + network::params().name_expiry
+    }
+
+    pub fn address(&self) -> String {
+        address_from_pub_key_hash(&self.pub_key_hash)
+    }
+}
+
+/**
+ * @desc 名称由小写字母、数字和中划线组成，不以中划线开头或结尾
+ */
+pub fn check_name(name: &str) -> Result<()> {
+    let valid = !name.is_empty()
+        && name.len() <= MAX_NAME_LEN
+        && name
+            .bytes()
+            .all(|b| b.is_ascii_lowercase() || b.is_ascii_digit() || b == b'-')
+        && !name.starts_with('-')
+        && !name.ends_with('-');
+    if !valid {
+        return Err(format_err!("Invalid name: {}", name));
+    }
+
+    Ok(())
+}
+
+/**
+ * @desc 检查高度为 height 的交易能否注册名称: 名称没有注册、注册已过期，或者交易由当前持有者签名(续期)
+ */
+pub fn check_claim(
+    tx: &Transaction,
+    record: &NameRecord,
+    current: Option<&NameClaim>,
+    height: i32,
+) -> Result<()> {
+    match current {
+        Some(claim)
+            if height < claim.expires_at()
+                && !tx.vin.iter().any(|vin| vin.uses_key(&claim.pub_key_hash)) =>
+        {
+            Err(format_err!(
+                "ERROR: Name {} is registered to {} until height {}.",
+                record.name,
+                claim.address(),
+                claim.expires_at()
+            ))
+        }
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        transaction::TXInput,
+        wallets::{hash_pub_key, Wallet},
+    };
+
+    fn name_tx(wallet: &Wallet, record: &NameRecord) -> Transaction {
+        let mut tx = Transaction {
+            id: String::new(),
+            vin: vec![TXInput {
+                txid: String::from("prev"),
+                vout: 0,
+                signature: Vec::new(),
+                pub_key: wallet.public_key.clone(),
+            }],
+            vout: vec![record.to_output()],
+            version: TX_VERSION_NAME,
+            relative_lock_time: 0,
+        };
+        tx.id = tx.hash().unwrap();
+        tx
+    }
+
+    #[test]
+    fn test_name_record() {
+        let wallet = Wallet::from_seed(&[1; 32]);
+        let record = NameRecord::new("alice-01", &wallet.get_address()).unwrap();
+        let mut tx = name_tx(&wallet, &record);
+        assert_eq!(NameRecord::from_tx(&tx).unwrap(), Some(record.clone()));
+
+        // 旧版本交易中的同样输出不是名称记录
+        tx.version = 2;
+        assert_eq!(NameRecord::from_tx(&tx).unwrap(), None);
+
+        tx.version = TX_VERSION_NAME;
+        tx.vout.push(record.to_output());
+        assert!(NameRecord::from_tx(&tx).is_err());
+
+        tx.vout.pop();
+        tx.vout[0].value = 1;
+        assert!(NameRecord::from_tx(&tx).is_err());
+
+        for name in ["", "Alice", "-alice", "alice-", "al ice", &"a".repeat(33)] {
+            assert!(check_name(name).is_err(), "{}", name);
+        }
+    }
+
+    #[test]
+    fn test_check_claim() {
+        let owner = Wallet::from_seed(&[1; 32]);
+        let other = Wallet::from_seed(&[2; 32]);
+        let mut owner_hash = owner.public_key.clone();
+        hash_pub_key(&mut owner_hash);
+
+        let claim = NameClaim {
+            block_hash: String::from("block"),
+            height: 10,
+            txid: String::from("tx"),
+            pub_key_hash: owner_hash,
+        };
+        let expires_at = claim.expires_at();
+
+        let record = NameRecord::new("alice", &other.get_address()).unwrap();
+        let by_other = name_tx(&other, &record);
+        let by_owner = name_tx(&owner, &record);
+
+        assert!(check_claim(&by_other, &record, None, 11).is_ok());
+        assert!(check_claim(&by_other, &record, Some(&claim), expires_at - 1).is_err());
+        assert!(check_claim(&by_other, &record, Some(&claim), expires_at).is_ok());
+        // 持有者可以在过期前续期并转给新地址
+        assert!(check_claim(&by_owner, &record, Some(&claim), 11).is_ok());
+    }
+}
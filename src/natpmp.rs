@@ -0,0 +1,120 @@
+use std::{
+    fs,
+    net::{Ipv4Addr, SocketAddrV4, UdpSocket},
+    time::Duration,
+};
+
+use super::Result;
+use failure::format_err;
+
+// NAT-PMP(RFC 6886) 端口映射，让 NAT 后面的节点可以接受外部连接(暂不支持 UPnP)
+const NATPMP_PORT: u16 = 5351;
+const NATPMP_VERSION: u8 = 0;
+const OP_EXTERNAL_ADDRESS: u8 = 0;
+const OP_MAP_TCP: u8 = 2;
+const RESPONSE_TIMEOUT: u64 = 2;
+pub const MAPPING_LIFETIME: u32 = 3600;
+
+/**
+ * @desc 通过 /proc/net/route 查找默认网关(仅 Linux)
+ */
+pub fn default_gateway() -> Result<Ipv4Addr> {
+    let routes = fs::read_to_string("/proc/net/route")?;
+
+    for line in routes.lines().skip(1) {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        if fields.len() > 2 && fields[1] == "00000000" {
+            let gateway = u32::from_str_radix(fields[2], 16)?;
+            return Ok(Ipv4Addr::from(gateway.to_le_bytes()));
+        }
+    }
+
+    Err(format_err!("Default gateway is not found."))
+}
+
+/**
+ * @desc 在网关上映射 TCP 端口，返回外部地址(ip:port)
+ */
+pub fn map_port(gateway: Ipv4Addr, port: u16, lifetime: u32) -> Result<String> {
+    let socket = UdpSocket::bind("0.0.0.0:0")?;
+    socket.set_read_timeout(Some(Duration::from_secs(RESPONSE_TIMEOUT)))?;
+    socket.connect(SocketAddrV4::new(gateway, NATPMP_PORT))?;
+
+    map_port_with(&socket, port, lifetime)
+}
+
+fn map_port_with(socket: &UdpSocket, port: u16, lifetime: u32) -> Result<String> {
+    // 查询外部 ip
+    socket.send(&[NATPMP_VERSION, OP_EXTERNAL_ADDRESS])?;
+    let mut buf = [0; 16];
+    let len = socket.recv(&mut buf)?;
+    check_response(&buf[..len], OP_EXTERNAL_ADDRESS, 12)?;
+    let external_ip = Ipv4Addr::new(buf[8], buf[9], buf[10], buf[11]);
+
+    // 请求端口映射
+    let mut request = vec![NATPMP_VERSION, OP_MAP_TCP, 0, 0];
+    request.extend_from_slice(&port.to_be_bytes());
+    request.extend_from_slice(&port.to_be_bytes());
+    request.extend_from_slice(&lifetime.to_be_bytes());
+    socket.send(&request)?;
+
+    let len = socket.recv(&mut buf)?;
+    check_response(&buf[..len], OP_MAP_TCP, 16)?;
+    let external_port = u16::from_be_bytes([buf[10], buf[11]]);
+
+    Ok(format!("{}:{}", external_ip, external_port))
+}
+
+fn check_response(data: &[u8], op: u8, len: usize) -> Result<()> {
+    if data.len() < len || data[0] != NATPMP_VERSION || data[1] != op + 128 {
+        return Err(format_err!("Invalid NAT-PMP response."));
+    }
+
+    let result_code = u16::from_be_bytes([data[2], data[3]]);
+    if result_code != 0 {
+        return Err(format_err!(
+            "NAT-PMP request failed: result code {}.",
+            result_code
+        ));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn test_map_port() {
+        let gateway = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let gateway_port = gateway.local_addr().unwrap().port();
+
+        let handle = thread::spawn(move || {
+            let mut buf = [0; 16];
+            let (_, from) = gateway.recv_from(&mut buf).unwrap();
+            assert_eq!(&buf[..2], &[0, 0]);
+            gateway
+                .send_to(&[0, 128, 0, 0, 0, 0, 0, 1, 203, 0, 113, 7], from)
+                .unwrap();
+
+            let (len, from) = gateway.recv_from(&mut buf).unwrap();
+            assert_eq!(len, 12);
+            assert_eq!(&buf[..6], &[0, 2, 0, 0, 11, 184]);
+            gateway
+                .send_to(
+                    &[0, 130, 0, 0, 0, 0, 0, 1, 11, 184, 11, 185, 0, 0, 14, 16],
+                    from,
+                )
+                .unwrap();
+        });
+
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        socket.connect(("127.0.0.1", gateway_port)).unwrap();
+        let external = map_port_with(&socket, 3000, MAPPING_LIFETIME).unwrap();
+        assert_eq!(external, "203.0.113.7:3001");
+
+        handle.join().unwrap();
+    }
+}
@@ -0,0 +1,200 @@
+use std::sync::RwLock;
+
+use super::Result;
+use crate::hash::HashAlgorithm;
+use failure::format_err;
+
+// 节点运行的网络，不同网络使用不同的共识参数
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Network {
+    Main,
+    Test,
+    Regtest,
+}
+
+// 网络共识参数
+#[derive(Debug, Clone, Copy)]
+pub struct NetworkParams {
+    pub hash_algorithm: HashAlgorithm,
+    // 网络标识，握手时不一致的节点不属于同一个网络，各网络取不同于比特币的值，避免和比特币节点互连
+    pub magic: [u8; 4],
+    // 最低难度(最大目标值)的压缩编码 nBits
+    pub pow_limit_bits: u32,
+    // 序列化后的最大区块大小(字节)
+    pub max_block_size: usize,
+    // 序列化后的最大交易大小(字节)
+    pub max_tx_size: usize,
+    // 软分叉部署表
+    pub deployments: &'static [Deployment],
+    // 统计信号的窗口大小(区块数)
+    pub miner_confirmation_window: i32,
+    // 窗口内达到该数量的信号区块后锁定
+    pub rule_change_activation_threshold: i32,
+    // 名称注册的有效区块数
+    pub name_expiry: i32,
+    // 提案的投票窗口(区块数)
+    pub voting_period: i32,
+    // 默认端口，P2P 和 RPC 共用同一个端口
+    pub default_port: u16,
+    // 数据目录，主网直接使用 data，其他网络使用 data 下的子目录，不同网络的区块和钱包互不影响
+    pub data_dir: &'static str,
+}
+
+// 通过版本位发出信号的软分叉部署，时间为毫秒，和区块时间戳一致
+#[derive(Debug, Clone, Copy)]
+pub struct Deployment {
+    pub name: &'static str,
+    pub bit: u8,
+    pub start_time: u128,
+    pub timeout: u128,
+}
+
+// 测试用部署，没有附带任何规则
+const TEST_DUMMY: Deployment = Deployment {
+    name: "testdummy",
+    bit: 28,
+    start_time: 0,
+    timeout: u128::MAX,
+};
+
+static NETWORK: RwLock<Network> = RwLock::new(Network::Main);
+// 创世配置指定的网络标识，覆盖网络参数中的默认值
+static MAGIC: RwLock<Option<[u8; 4]>> = RwLock::new(None);
+
+impl Network {
+    pub fn from_name(name: &str) -> Result<Network> {
+        match name {
+            "main" => Ok(Network::Main),
+            "test" => Ok(Network::Test),
+            "regtest" => Ok(Network::Regtest),
+            _ => Err(format_err!("Unknown network: {}", name)),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Network::Main => "main",
+            Network::Test => "test",
+            Network::Regtest => "regtest",
+        }
+    }
+
+    /**
+     * @desc 网络参数，主网保持 SHA-256 以兼容已有的链
+     */
+    pub fn params(&self) -> NetworkParams {
+        match self {
+            Network::Main => NetworkParams {
+                hash_algorithm: HashAlgorithm::Sha256,
+                magic: [0xd3, 0x6e, 0xa1, 0x5c],
+                pow_limit_bits: 0x1f010000,
+                max_block_size: 1_000_000,
+                max_tx_size: 100_000,
+                deployments: &[],
+                miner_confirmation_window: 2016,
+                rule_change_activation_threshold: 1916,
+                name_expiry: 52560,
+                voting_period: 2016,
+                default_port: 3000,
+                data_dir: "data",
+            },
+            Network::Test => NetworkParams {
+                hash_algorithm: HashAlgorithm::DoubleSha256,
+                magic: [0xb7, 0x2c, 0xe4, 0x91],
+                pow_limit_bits: 0x1f010000,
+                max_block_size: 1_000_000,
+                max_tx_size: 100_000,
+                deployments: &[TEST_DUMMY],
+                miner_confirmation_window: 2016,
+                rule_change_activation_threshold: 1512,
+                name_expiry: 52560,
+                voting_period: 2016,
+                default_port: 13000,
+                data_dir: "data/testnet",
+            },
+            Network::Regtest => NetworkParams {
+                hash_algorithm: HashAlgorithm::Blake3,
+                magic: [0xc9, 0x5a, 0x7f, 0xe2],
+                pow_limit_bits: 0x1f010000,
+                max_block_size: 4_000_000,
+                max_tx_size: 400_000,
+                deployments: &[TEST_DUMMY],
+                miner_confirmation_window: 144,
+                rule_change_activation_threshold: 108,
+                name_expiry: 144,
+                voting_period: 20,
+                default_port: 23000,
+                data_dir: "data/regtest",
+            },
+        }
+    }
+}
+
+/**
+ * @desc 设置当前网络，需要在读取区块之前调用
+ */
+pub fn set_network(network: Network) {
+    *NETWORK.write().unwrap() = network;
+}
+
+/**
+ * @desc 当前网络
+ */
+pub fn current() -> Network {
+    *NETWORK.read().unwrap()
+}
+
+/**
+ * @desc 当前网络的参数
+ */
+pub fn params() -> NetworkParams {
+    NETWORK.read().unwrap().params()
+}
+
+/**
+ * @desc 设置私有网络的网络标识，None 时使用网络参数中的默认值
+ */
+pub fn set_magic(magic: Option<[u8; 4]>) {
+    *MAGIC.write().unwrap() = magic;
+}
+
+/**
+ * @desc 当前的网络标识
+ */
+pub fn magic() -> [u8; 4] {
+    MAGIC.read().unwrap().unwrap_or_else(|| params().magic)
+}
+
+/**
+ * @desc 当前网络数据目录下的文件路径，目录不存在时创建
+ */
+pub fn data_path(name: &str) -> String {
+    let dir = params().data_dir;
+    std::fs::create_dir_all(dir).ok();
+
+    format!("{}/{}", dir, name)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_networks_are_separate() {
+        let networks = [Network::Main, Network::Test, Network::Regtest];
+        for (i, a) in networks.iter().enumerate() {
+            for b in &networks[i + 1..] {
+                assert_ne!(a.params().magic, b.params().magic);
+                assert_ne!(a.params().data_dir, b.params().data_dir);
+            }
+            // 比特币主网、测试网和 regtest 的网络标识
+            for bitcoin in [
+                [0xf9, 0xbe, 0xb4, 0xd9],
+                [0x0b, 0x11, 0x09, 0x07],
+                [0xfa, 0xbf, 0xb5, 0xda],
+            ] {
+                assert_ne!(a.params().magic, bitcoin);
+            }
+        }
+    }
+}
@@ -0,0 +1,248 @@
+// 纸钱包: 把地址和私钥打印在纸上离线保存(冷存储)，私钥可以用口令加密(类似 BIP38)；
+// 私钥文本为十六进制: 未加密 01 || 私钥(64) || 校验和(4)，加密 02 || 地址哈希(4) || 密文(64) || 校验和(4)，
+// 校验和是前面内容两次 sha256 的前 4 字节；加密时以地址哈希为盐用 scrypt 由口令派生 96 字节，
+// 前 64 字节与私钥异或，再用后 32 字节做 AES-256 加密，解密后比对地址哈希即可发现口令错误
+
+use super::Result;
+use crate::{
+    hash::{DoubleSha256Hasher, PowHasher},
+    qr::QrCode,
+    transaction::{decode_hex, encode_hex},
+    wallets::Wallet,
+};
+use crypto::{
+    aessafe::{AesSafe256Decryptor, AesSafe256Encryptor},
+    scrypt::{scrypt, ScryptParams},
+    symmetriccipher::{BlockDecryptor, BlockEncryptor},
+};
+use failure::format_err;
+
+const PLAIN_KEY: u8 = 0x01;
+const ENCRYPTED_KEY: u8 = 0x02;
+const SECRET_KEY_LEN: usize = 64;
+const CHECKSUM_LEN: usize = 4;
+const AES_BLOCK_LEN: usize = 16;
+// scrypt 参数与 BIP38 相同: N = 2^14, r = 8, p = 1
+const SCRYPT_LOG_N: u8 = 14;
+const SCRYPT_R: u32 = 8;
+const SCRYPT_P: u32 = 1;
+// HTML 中二维码每个模块的像素
+const SVG_MODULE_PX: usize = 4;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PaperFormat {
+    Text,
+    Html,
+}
+
+impl PaperFormat {
+    pub fn parse(format: &str) -> Result<PaperFormat> {
+        match format {
+            "text" => Ok(PaperFormat::Text),
+            "html" => Ok(PaperFormat::Html),
+            _ => Err(format_err!(
+                "Unknown paper wallet format {}, use text or html.",
+                format
+            )),
+        }
+    }
+}
+
+/**
+ * @desc 私钥编码成纸钱包上的文本，传入口令时加密
+ */
+pub fn encode_key(wallet: &Wallet, passphrase: Option<&str>) -> Result<String> {
+    if wallet.secret_key.len() != SECRET_KEY_LEN {
+        return Err(format_err!("Wallet has an invalid secret key."));
+    }
+
+    let mut data = match passphrase {
+        None => {
+            let mut data = vec![PLAIN_KEY];
+            data.extend_from_slice(&wallet.secret_key);
+            data
+        }
+        Some(passphrase) => {
+            let address_hash = address_hash(&wallet.get_address());
+            let derived = derive_key(passphrase, &address_hash);
+            let encryptor = AesSafe256Encryptor::new(&derived[SECRET_KEY_LEN..]);
+
+            let mut data = vec![ENCRYPTED_KEY];
+            data.extend_from_slice(&address_hash);
+            let masked: Vec<u8> = wallet
+                .secret_key
+                .iter()
+                .zip(derived.iter())
+                .map(|(byte, mask)| byte ^ mask)
+                .collect();
+            for block in masked.chunks(AES_BLOCK_LEN) {
+                let mut output = [0; AES_BLOCK_LEN];
+                encryptor.encrypt_block(block, &mut output);
+                data.extend_from_slice(&output);
+            }
+            data
+        }
+    };
+    let checksum = checksum(&data);
+    data.extend_from_slice(&checksum);
+
+    Ok(encode_hex(&data))
+}
+
+/**
+ * @desc 解析纸钱包上的私钥文本，加密的私钥需要口令；校验和不符或口令错误时返回错误
+ */
+pub fn decode_key(text: &str, passphrase: Option<&str>) -> Result<Wallet> {
+    let data = decode_hex(text).ok_or_else(|| format_err!("Paper wallet key is not hex."))?;
+    if data.len() < 1 + CHECKSUM_LEN {
+        return Err(format_err!("Paper wallet key is too short."));
+    }
+    let (payload, expected) = data.split_at(data.len() - CHECKSUM_LEN);
+    if checksum(payload)[..] != *expected {
+        return Err(format_err!("Paper wallet key has a bad checksum."));
+    }
+
+    let secret_key = match (payload[0], payload.len()) {
+        (PLAIN_KEY, len) if len == 1 + SECRET_KEY_LEN => payload[1..].to_vec(),
+        (ENCRYPTED_KEY, len) if len == 1 + CHECKSUM_LEN + SECRET_KEY_LEN => {
+            let passphrase = passphrase.ok_or_else(|| {
+                format_err!("Paper wallet key is encrypted, a passphrase is required.")
+            })?;
+            let salt = &payload[1..1 + CHECKSUM_LEN];
+            let derived = derive_key(passphrase, salt);
+            let decryptor = AesSafe256Decryptor::new(&derived[SECRET_KEY_LEN..]);
+
+            let mut secret_key = Vec::with_capacity(SECRET_KEY_LEN);
+            for block in payload[1 + CHECKSUM_LEN..].chunks(AES_BLOCK_LEN) {
+                let mut output = [0; AES_BLOCK_LEN];
+                decryptor.decrypt_block(block, &mut output);
+                secret_key.extend_from_slice(&output);
+            }
+            secret_key
+                .iter_mut()
+                .zip(derived.iter())
+                .for_each(|(byte, mask)| *byte ^= mask);
+
+            let wallet = wallet_from_secret_key(secret_key);
+            if salt != address_hash(&wallet.get_address()) {
+                return Err(format_err!("Wrong passphrase for the paper wallet key."));
+            }
+            return Ok(wallet);
+        }
+        _ => return Err(format_err!("Unknown paper wallet key format.")),
+    };
+
+    Ok(wallet_from_secret_key(secret_key))
+}
+
+/**
+ * @desc 生成可打印的纸钱包: 地址和私钥的文本及二维码
+ */
+pub fn render(wallet: &Wallet, passphrase: Option<&str>, format: PaperFormat) -> Result<String> {
+    let address = wallet.get_address();
+    let key = encode_key(wallet, passphrase)?;
+    let address_qr = QrCode::encode(address.as_bytes())?;
+    let key_qr = QrCode::encode(key.as_bytes())?;
+    let key_label = match passphrase {
+        Some(_) => "Private key (encrypted with a passphrase)",
+        None => "Private key (keep secret)",
+    };
+
+    let sheet = match format {
+        PaperFormat::Text => format!(
+            "Paper wallet\n\nAddress\n{}\n{}\n{}\n{}\n{}",
+            address,
+            address_qr.to_printable_string(),
+            key_label,
+            key,
+            key_qr.to_printable_string()
+        ),
+        PaperFormat::Html => format!(
+            "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>Paper wallet {0}</title>\n\
+             <style>body {{ font-family: monospace; }} section {{ display: inline-block; margin: 16px; \
+             vertical-align: top; }} p {{ word-break: break-all; max-width: 480px; }}</style>\n\
+             </head>\n<body>\n<h1>Paper wallet</h1>\n\
+             <section>\n<h2>Address</h2>\n{1}\n<p>{0}</p>\n</section>\n\
+             <section>\n<h2>{2}</h2>\n{3}\n<p>{4}</p>\n</section>\n</body>\n</html>\n",
+            address,
+            address_qr.to_svg(SVG_MODULE_PX),
+            key_label,
+            key_qr.to_svg(SVG_MODULE_PX),
+            key
+        ),
+    };
+
+    Ok(sheet)
+}
+
+fn wallet_from_secret_key(secret_key: Vec<u8>) -> Wallet {
+    // ed25519 私钥的后 32 字节是公钥
+    let public_key = secret_key[SECRET_KEY_LEN / 2..].to_vec();
+    Wallet {
+        secret_key,
+        public_key,
+    }
+}
+
+fn checksum(data: &[u8]) -> [u8; CHECKSUM_LEN] {
+    let hash = DoubleSha256Hasher.hash(data);
+    [hash[0], hash[1], hash[2], hash[3]]
+}
+
+fn address_hash(address: &str) -> [u8; CHECKSUM_LEN] {
+    checksum(address.as_bytes())
+}
+
+// 由口令派生 96 字节: 前 64 字节是与私钥异或的掩码，后 32 字节是 AES 密钥
+fn derive_key(passphrase: &str, salt: &[u8]) -> [u8; SECRET_KEY_LEN + 32] {
+    let params = ScryptParams::new(SCRYPT_LOG_N, SCRYPT_R, SCRYPT_P);
+    let mut derived = [0; SECRET_KEY_LEN + 32];
+    scrypt(passphrase.as_bytes(), salt, &params, &mut derived);
+    derived
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_encode_key() {
+        let wallet = Wallet::default();
+        let plain = encode_key(&wallet, None).unwrap();
+        assert_eq!(decode_key(&plain, None).unwrap(), wallet);
+        assert_eq!(decode_key(&plain, Some("ignored")).unwrap(), wallet);
+
+        let encrypted = encode_key(&wallet, Some("correct horse")).unwrap();
+        assert_ne!(encrypted, plain);
+        assert!(!encrypted.contains(&encode_hex(&wallet.secret_key[..32])));
+        assert_eq!(
+            decode_key(&encrypted, Some("correct horse")).unwrap(),
+            wallet
+        );
+        assert!(decode_key(&encrypted, None).is_err());
+        assert!(decode_key(&encrypted, Some("wrong horse")).is_err());
+
+        // 改动一个字符后校验和不符
+        let mut corrupted = plain.clone().into_bytes();
+        corrupted[10] = if corrupted[10] == b'0' { b'1' } else { b'0' };
+        assert!(decode_key(&String::from_utf8(corrupted).unwrap(), None).is_err());
+        assert!(decode_key("zz", None).is_err());
+        assert!(decode_key(&plain[..plain.len() - 2], None).is_err());
+    }
+
+    #[test]
+    fn test_render() {
+        let wallet = Wallet::default();
+        let address = wallet.get_address();
+        let key = encode_key(&wallet, None).unwrap();
+
+        let text = render(&wallet, None, PaperFormat::Text).unwrap();
+        assert!(text.contains(&address) && text.contains(&key));
+        assert!(text.contains('█'));
+
+        let html = render(&wallet, None, PaperFormat::Html).unwrap();
+        assert!(html.contains(&address) && html.contains(&key));
+        assert_eq!(html.matches("<svg").count(), 2);
+        assert!(PaperFormat::parse("pdf").is_err());
+    }
+}
@@ -0,0 +1,186 @@
+// 付款请求 URI: blockchain:<address>?amount=<amount>&label=<label>&network=<network>
+// 地址编码不区分网络，主网以外的请求带上 network 参数，避免把其他网络的付款请求用在当前网络上
+
+use super::Result;
+use crate::{network::Network, wallets::decode_address};
+use failure::format_err;
+
+const PAYMENT_URI_SCHEME: &str = "blockchain:";
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PaymentRequest {
+    pub address: String,
+    pub amount: Option<i32>,
+    pub label: Option<String>,
+}
+
+impl PaymentRequest {
+    pub fn new(address: &str) -> PaymentRequest {
+        PaymentRequest {
+            address: address.to_string(),
+            amount: None,
+            label: None,
+        }
+    }
+
+    /**
+     * @desc 生成 network 上的付款请求 URI，主网省略 network 参数
+     */
+    pub fn to_uri(&self, network: Network) -> String {
+        let mut params = Vec::new();
+        if let Some(amount) = self.amount {
+            params.push(format!("amount={}", amount));
+        }
+        if let Some(label) = &self.label {
+            params.push(format!("label={}", percent_encode(label)));
+        }
+        if network != Network::Main {
+            params.push(format!("network={}", network.name()));
+        }
+
+        if params.is_empty() {
+            format!("{}{}", PAYMENT_URI_SCHEME, self.address)
+        } else {
+            format!(
+                "{}{}?{}",
+                PAYMENT_URI_SCHEME,
+                self.address,
+                params.join("&")
+            )
+        }
+    }
+
+    /**
+     * @desc 解析付款请求 URI，地址必须有效且请求的网络和 network 相同，不认识的参数忽略
+     */
+    pub fn parse(uri: &str, network: Network) -> Result<PaymentRequest> {
+        let rest = uri
+            .strip_prefix(PAYMENT_URI_SCHEME)
+            .ok_or_else(|| format_err!("Invalid payment URI: {}", uri))?;
+        let (address, query) = rest.split_once('?').unwrap_or((rest, ""));
+        decode_address(address)?;
+
+        let mut request = PaymentRequest::new(address);
+        let mut request_network = Network::Main;
+        for param in query.split('&').filter(|param| !param.is_empty()) {
+            let (key, value) = param.split_once('=').unwrap_or((param, ""));
+            match key {
+                "amount" => match value.parse::<i32>() {
+                    Ok(amount) if amount > 0 => request.amount = Some(amount),
+                    _ => return Err(format_err!("Invalid amount in payment URI: {}", uri)),
+                },
+                "label" => request.label = Some(percent_decode(value)?),
+                "network" => request_network = Network::from_name(value)?,
+                _ => {}
+            }
+        }
+
+        if request_network != network {
+            return Err(format_err!(
+                "Payment request is for the {} network, current network is {}.",
+                request_network.name(),
+                network.name()
+            ));
+        }
+
+        Ok(request)
+    }
+}
+
+pub fn is_payment_uri(uri: &str) -> bool {
+    uri.starts_with(PAYMENT_URI_SCHEME)
+}
+
+/**
+ * @desc 百分号编码，字母、数字和 -._~ 以外的字节编码为 %XX
+ */
+fn percent_encode(value: &str) -> String {
+    value
+        .bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                (byte as char).to_string()
+            }
+            _ => format!("%{:02X}", byte),
+        })
+        .collect()
+}
+
+fn percent_decode(value: &str) -> Result<String> {
+    let invalid = || format_err!("Invalid percent encoding: {}", value);
+
+    let mut bytes = Vec::new();
+    let mut iter = value.bytes();
+    while let Some(byte) = iter.next() {
+        if byte == b'%' {
+            let hex = [
+                iter.next().ok_or_else(invalid)?,
+                iter.next().ok_or_else(invalid)?,
+            ];
+            let hex = std::str::from_utf8(&hex).map_err(|_| invalid())?;
+            bytes.push(u8::from_str_radix(hex, 16).map_err(|_| invalid())?);
+        } else {
+            bytes.push(byte);
+        }
+    }
+
+    String::from_utf8(bytes).map_err(|_| invalid())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::wallets::Wallet;
+
+    #[test]
+    fn test_payment_request_roundtrip() {
+        let address = Wallet::default().get_address();
+        let mut request = PaymentRequest::new(&address);
+        assert_eq!(
+            request.to_uri(Network::Main),
+            format!("blockchain:{}", address)
+        );
+
+        request.amount = Some(25);
+        request.label = Some(String::from("房租 & rent"));
+        let uri = request.to_uri(Network::Regtest);
+        assert!(is_payment_uri(&uri));
+        assert!(uri.ends_with("&network=regtest"));
+        assert_eq!(
+            PaymentRequest::parse(&uri, Network::Regtest).unwrap(),
+            request
+        );
+    }
+
+    #[test]
+    fn test_parse_payment_request() {
+        let address = Wallet::default().get_address();
+        let parse = |uri: String, network| PaymentRequest::parse(&uri, network);
+
+        let request = parse(
+            format!("blockchain:{}?amount=5&label=a%20b&message=hi", address),
+            Network::Main,
+        )
+        .unwrap();
+        assert_eq!(request.amount, Some(5));
+        assert_eq!(request.label.as_deref(), Some("a b"));
+
+        // 网络不一致，没有 network 参数时是主网
+        assert!(parse(format!("blockchain:{}", address), Network::Test).is_err());
+        assert!(parse(
+            format!("blockchain:{}?network=test", address),
+            Network::Main
+        )
+        .is_err());
+        assert!(parse(
+            format!("blockchain:{}?network=moon", address),
+            Network::Main
+        )
+        .is_err());
+
+        assert!(parse(address.clone(), Network::Main).is_err());
+        assert!(parse(String::from("blockchain:not-an-address"), Network::Main).is_err());
+        assert!(parse(format!("blockchain:{}?amount=-1", address), Network::Main).is_err());
+        assert!(parse(format!("blockchain:{}?label=%zz", address), Network::Main).is_err());
+    }
+}
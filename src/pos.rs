@@ -0,0 +1,33 @@
+use crypto::{digest::Digest, sha2::Sha256};
+
+// 权益证明: 每秒一次机会，持币越多越容易满足 kernel 条件
+const STAKE_DIFFICULTY: u64 = 100;
+
+/**
+ * @desc 计算 kernel 哈希值 H(前一个区块 hash + 时间戳(秒) + 出块人公钥哈希)
+ */
+pub fn kernel_hash(prev_block_hash: &str, timestamp_secs: u64, staker: &[u8]) -> u64 {
+    let mut hasher = Sha256::new();
+    hasher.input(prev_block_hash.as_bytes());
+    hasher.input(&timestamp_secs.to_be_bytes());
+    hasher.input(staker);
+
+    let mut res = [0; 32];
+    hasher.result(&mut res);
+
+    let mut head = [0; 8];
+    head.copy_from_slice(&res[..8]);
+    u64::from_be_bytes(head)
+}
+
+/**
+ * @desc 判断是否满足 kernel 条件: kernel 哈希 < 目标值 * 权益
+ */
+pub fn check_kernel(prev_block_hash: &str, timestamp_secs: u64, staker: &[u8], stake: i32) -> bool {
+    if stake <= 0 {
+        return false;
+    }
+
+    let target = (u64::MAX / STAKE_DIFFICULTY).saturating_mul(stake as u64);
+    kernel_hash(prev_block_hash, timestamp_secs, staker) < target
+}
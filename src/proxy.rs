@@ -0,0 +1,117 @@
+use std::{
+    io::prelude::{Read, Write},
+    net::TcpStream,
+};
+
+use super::Result;
+use failure::format_err;
+
+const SOCKS_VERSION: u8 = 5;
+const NO_AUTH: u8 = 0;
+const CMD_CONNECT: u8 = 1;
+const ATYP_IPV4: u8 = 1;
+const ATYP_DOMAIN: u8 = 3;
+const ATYP_IPV6: u8 = 4;
+
+/**
+ * @desc 通过 SOCKS5 代理连接目标地址，域名交给代理解析
+ */
+pub fn connect(proxy: &str, target: &str) -> Result<TcpStream> {
+    let (host, port) = split_host_port(target)?;
+    if host.len() > u8::MAX as usize {
+        return Err(format_err!("SOCKS5 target host is too long: {}", host));
+    }
+
+    let mut stream = TcpStream::connect(proxy)?;
+
+    // 协商认证方式: 不需要认证
+    stream.write_all(&[SOCKS_VERSION, 1, NO_AUTH])?;
+    let mut reply = [0; 2];
+    stream.read_exact(&mut reply)?;
+    if reply != [SOCKS_VERSION, NO_AUTH] {
+        return Err(format_err!("SOCKS5 proxy rejected authentication method."));
+    }
+
+    // 请求连接
+    let mut request = vec![SOCKS_VERSION, CMD_CONNECT, 0, ATYP_DOMAIN, host.len() as u8];
+    request.extend_from_slice(host.as_bytes());
+    request.extend_from_slice(&port.to_be_bytes());
+    stream.write_all(&request)?;
+
+    let mut head = [0; 4];
+    stream.read_exact(&mut head)?;
+    if head[0] != SOCKS_VERSION || head[1] != 0 {
+        return Err(format_err!(
+            "SOCKS5 proxy failed to connect to {}: reply code {}.",
+            target,
+            head[1]
+        ));
+    }
+
+    // 读取并丢弃代理返回的绑定地址和端口
+    let addr_len = match head[3] {
+        ATYP_IPV4 => 4,
+        ATYP_IPV6 => 16,
+        ATYP_DOMAIN => {
+            let mut len = [0; 1];
+            stream.read_exact(&mut len)?;
+            len[0] as usize
+        }
+        atyp => {
+            return Err(format_err!(
+                "SOCKS5 proxy returned unknown address type {}.",
+                atyp
+            ))
+        }
+    };
+    let mut bound = vec![0; addr_len + 2];
+    stream.read_exact(&mut bound)?;
+
+    Ok(stream)
+}
+
+fn split_host_port(target: &str) -> Result<(&str, u16)> {
+    match target.rsplit_once(':') {
+        Some((host, port)) => Ok((host.trim(), port.trim().parse()?)),
+        None => Err(format_err!("Invalid address, expect host:port: {}", target)),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::{net::TcpListener, thread};
+
+    #[test]
+    fn test_connect_through_proxy() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let proxy = listener.local_addr().unwrap().to_string();
+
+        let handle = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+
+            let mut greeting = [0; 3];
+            stream.read_exact(&mut greeting).unwrap();
+            assert_eq!(greeting, [5, 1, 0]);
+            stream.write_all(&[5, 0]).unwrap();
+
+            let mut head = [0; 5];
+            stream.read_exact(&mut head).unwrap();
+            assert_eq!(head, [5, 1, 0, 3, 9]);
+            let mut rest = [0; 11];
+            stream.read_exact(&mut rest).unwrap();
+            assert_eq!(&rest[..9], b"localhost");
+            assert_eq!(&rest[9..], &3000u16.to_be_bytes());
+
+            stream.write_all(&[5, 0, 0, 1, 127, 0, 0, 1, 0, 0]).unwrap();
+            stream.write_all(b"ok").unwrap();
+        });
+
+        let mut stream = connect(&proxy, "localhost: 3000").unwrap();
+        let mut data = [0; 2];
+        stream.read_exact(&mut data).unwrap();
+        assert_eq!(&data, b"ok");
+
+        handle.join().unwrap();
+    }
+}
@@ -0,0 +1,300 @@
+// 部分签名交易: 花费多签地址的交易在各签名方之间传递，每个签名方为自己的公钥补上签名，
+// 签名数达到门限后按公钥顺序拼接成最终交易；
+// 节点之间用 psbt 消息直接交换，分布在多个节点上的多签钱包不需要手工传递文件
+
+use std::collections::{BTreeMap, HashMap};
+
+use super::Result;
+use crate::{descriptor::Descriptor, transaction::*, utxo_set::UtxoProvider, wallets::Wallet};
+use bincode::serialize;
+use crypto::{digest::Digest, ed25519, sha2::Sha256};
+use failure::format_err;
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Psbt {
+    // 未签名的交易
+    pub tx: Transaction,
+    // 输入花费的交易，签名方用来计算签名哈希
+    pub prev_txs: Vec<Transaction>,
+    // 每个输入已有的签名: 公钥在多签脚本中的序号 -> 签名
+    pub signatures: Vec<BTreeMap<usize, Vec<u8>>>,
+}
+
+impl Psbt {
+    /**
+     * @desc 从多签地址支付 amount 到 to，找零回到多签地址，交易还没有签名
+     */
+    pub fn new(
+        descriptor: &Descriptor,
+        to: &str,
+        amount: i32,
+        fee: i32,
+        utxo: &impl UtxoProvider,
+    ) -> Result<Psbt> {
+        if !matches!(descriptor, Descriptor::Multi { .. }) {
+            return Err(format_err!("Descriptor {} is not a multisig.", descriptor));
+        }
+        if amount <= 0 || fee < 0 {
+            return Err(format_err!("Invalid amount {} or fee {}.", amount, fee));
+        }
+
+        let pub_key_hash = descriptor.pub_key_hash();
+        let (accumulated, outputs) = utxo.find_spendable_outputs(&pub_key_hash, amount + fee)?;
+        if accumulated < amount + fee {
+            return Err(format_err!(
+                "Not Enough balance: current balance {}.",
+                accumulated
+            ));
+        }
+
+        let mut tx = Transaction {
+            id: String::new(),
+            vin: Vec::new(),
+            vout: vec![TXOutput::new(amount, to.to_string())?],
+            version: TX_VERSION_MULTISIG,
+            relative_lock_time: 0,
+        };
+        let mut prev_txs = Vec::new();
+        for (txid, outs) in outputs {
+            for out in outs {
+                tx.vin.push(TXInput {
+                    txid: txid.clone(),
+                    vout: out,
+                    signature: Vec::new(),
+                    pub_key: descriptor.script(),
+                });
+            }
+            prev_txs.push(utxo.find_transaction(&txid)?);
+        }
+        if accumulated > amount + fee {
+            tx.vout.push(TXOutput {
+                value: accumulated - amount - fee,
+                pub_key_hash,
+            });
+        }
+        tx.id = tx.hash()?;
+
+        Ok(Psbt {
+            signatures: vec![BTreeMap::new(); tx.vin.len()],
+            tx,
+            prev_txs,
+        })
+    }
+
+    pub fn id(&self) -> String {
+        self.tx.id.clone()
+    }
+
+    /**
+     * @desc 交易和已有签名的摘要，签名集合不同的同一交易摘要不同，用于转发去重
+     */
+    pub fn digest(&self) -> Result<String> {
+        let mut hasher = Sha256::new();
+        hasher.input(&serialize(&self.signatures)?);
+
+        Ok(format!("psbt:{}:{}", self.tx.id, hasher.result_str()))
+    }
+
+    fn prev_tx_map(&self) -> HashMap<String, Transaction> {
+        self.prev_txs
+            .iter()
+            .map(|tx| (tx.id.clone(), tx.clone()))
+            .collect()
+    }
+
+    // 输入的多签门限和公钥
+    fn input_keys(&self, in_id: usize) -> Result<(usize, Vec<Vec<u8>>)> {
+        match Descriptor::from_script(&self.tx.vin[in_id].pub_key)? {
+            Descriptor::Multi { threshold, keys } => Ok((threshold, keys)),
+            _ => Err(format_err!(
+                "Input {} of {} is not a multisig.",
+                in_id,
+                self.id()
+            )),
+        }
+    }
+
+    /**
+     * @desc 是否有输入需要 public_key 签名
+     */
+    pub fn has_key(&self, public_key: &[u8]) -> bool {
+        (0..self.tx.vin.len()).any(|in_id| {
+            self.input_keys(in_id)
+                .is_ok_and(|(_, keys)| keys.iter().any(|key| key == public_key))
+        })
+    }
+
+    /**
+     * @desc 检查收到的部分签名交易: txid 和交易一致，每个输入都是多签，已有签名都有效
+     */
+    pub fn check(&self) -> Result<()> {
+        if self.tx.hash()? != self.tx.id || self.signatures.len() != self.tx.vin.len() {
+            return Err(format_err!(
+                "Invalid partially signed transaction {}.",
+                self.id()
+            ));
+        }
+
+        let prev_txs = self.prev_tx_map();
+        for (in_id, signatures) in self.signatures.iter().enumerate() {
+            let (_, keys) = self.input_keys(in_id)?;
+            let sighash = self.tx.signature_hash(in_id, &prev_txs)?;
+            for (index, signature) in signatures {
+                let valid = keys.get(*index).is_some_and(|key| {
+                    signature.len() == 64 && ed25519::verify(sighash.as_bytes(), key, signature)
+                });
+                if !valid {
+                    return Err(format_err!(
+                        "Invalid signature for input {} of {}.",
+                        in_id,
+                        self.id()
+                    ));
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /**
+     * @desc 用钱包的密钥签名所有包含该公钥的输入，返回新签名的输入数
+     */
+    pub fn sign(&mut self, wallet: &Wallet) -> Result<usize> {
+        let prev_txs = self.prev_tx_map();
+
+        let mut signed = 0;
+        for in_id in 0..self.tx.vin.len() {
+            let (_, keys) = self.input_keys(in_id)?;
+            let index = match keys.iter().position(|key| *key == wallet.public_key) {
+                Some(index) => index,
+                None => continue,
+            };
+            if self.signatures[in_id].contains_key(&index) {
+                continue;
+            }
+
+            let signature = self.tx.sign_input(in_id, &wallet.secret_key, &prev_txs)?;
+            self.signatures[in_id].insert(index, signature);
+            signed += 1;
+        }
+
+        Ok(signed)
+    }
+
+    /**
+     * @desc 合并同一交易的另一份部分签名，返回新增的签名数；other 需要先通过 check
+     */
+    pub fn combine(&mut self, other: &Psbt) -> Result<usize> {
+        if other.tx.id != self.tx.id || other.signatures.len() != self.signatures.len() {
+            return Err(format_err!(
+                "Cannot combine {} with {}.",
+                self.id(),
+                other.id()
+            ));
+        }
+
+        let mut added = 0;
+        for (mine, theirs) in self.signatures.iter_mut().zip(&other.signatures) {
+            for (index, signature) in theirs {
+                if !mine.contains_key(index) {
+                    mine.insert(*index, signature.clone());
+                    added += 1;
+                }
+            }
+        }
+
+        Ok(added)
+    }
+
+    /**
+     * @desc 每个输入的签名数是否都达到门限
+     */
+    pub fn is_complete(&self) -> bool {
+        (0..self.tx.vin.len()).all(|in_id| {
+            self.input_keys(in_id)
+                .is_ok_and(|(threshold, _)| self.signatures[in_id].len() >= threshold)
+        })
+    }
+
+    /**
+     * @desc 按公钥顺序拼接门限个签名，返回可以广播的交易
+     */
+    pub fn finalize(&self) -> Result<Transaction> {
+        if !self.is_complete() {
+            return Err(format_err!(
+                "Transaction {} needs more signatures.",
+                self.id()
+            ));
+        }
+
+        let mut tx = self.tx.clone();
+        for (in_id, signatures) in self.signatures.iter().enumerate() {
+            let (threshold, _) = self.input_keys(in_id)?;
+            tx.vin[in_id].signature = signatures
+                .values()
+                .take(threshold)
+                .flatten()
+                .copied()
+                .collect();
+        }
+
+        Ok(tx)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::utxo_set::MemoryUtxoSet;
+    use rand_core::OsRng;
+
+    #[test]
+    fn test_psbt() {
+        let wallets: Vec<Wallet> = (1..=3).map(|i| Wallet::from_seed(&[i; 32])).collect();
+        let descriptor = Descriptor::Multi {
+            threshold: 2,
+            keys: wallets.iter().map(|w| w.public_key.clone()).collect(),
+        };
+        let mut utxos = MemoryUtxoSet::default();
+        utxos.add_transaction(
+            Transaction::new_coinbase(descriptor.address(), String::from("psbt"), &mut OsRng)
+                .unwrap(),
+        );
+
+        let to = wallets[0].get_address();
+        assert!(Psbt::new(&wallets[0].descriptor(), &to, 5, 1, &utxos).is_err());
+        let psbt = Psbt::new(&descriptor, &to, 5, 1, &utxos).unwrap();
+        assert_eq!(psbt.tx.vout[1].value, SUBSIDY - 6);
+        psbt.check().unwrap();
+
+        // 第一和第三个签名方各自签名，合并后达到门限
+        assert!(psbt.has_key(&wallets[1].public_key));
+        assert!(!psbt.has_key(&Wallet::from_seed(&[4; 32]).public_key));
+        let mut first = psbt.clone();
+        assert_eq!(first.sign(&wallets[0]).unwrap(), 1);
+        assert_eq!(first.sign(&wallets[0]).unwrap(), 0);
+        assert!(!first.is_complete());
+        assert!(first.finalize().is_err());
+
+        let mut third = psbt.clone();
+        third.sign(&wallets[2]).unwrap();
+        third.check().unwrap();
+        assert_ne!(first.digest().unwrap(), third.digest().unwrap());
+
+        assert_eq!(first.combine(&third).unwrap(), 1);
+        assert_eq!(first.combine(&third).unwrap(), 0);
+        assert!(first.is_complete());
+        let tx = first.finalize().unwrap();
+        assert!(tx.verify(first.prev_tx_map()).unwrap());
+
+        // 签名和公钥序号不对应
+        let mut forged = third.clone();
+        let signature = forged.signatures[0].remove(&2).unwrap();
+        forged.signatures[0].insert(1, signature);
+        assert!(forged.check().is_err());
+
+        let mut other = Psbt::new(&descriptor, &to, 4, 1, &utxos).unwrap();
+        assert!(other.combine(&third).is_err());
+    }
+}
@@ -0,0 +1,584 @@
+// 终端显示用的二维码编码: 字节模式，纠错等级 L，版本 1 到 10(最多 271 字节)
+
+use super::Result;
+use failure::format_err;
+
+const MAX_VERSION: usize = 10;
+// 各版本每个块的纠错码字数和块数(纠错等级 L)，下标为版本号
+const ECC_CODEWORDS_PER_BLOCK: [usize; MAX_VERSION + 1] =
+    [0, 7, 10, 15, 20, 26, 18, 20, 24, 30, 18];
+const NUM_ERROR_CORRECTION_BLOCKS: [usize; MAX_VERSION + 1] = [0, 1, 1, 1, 1, 1, 2, 2, 2, 2, 4];
+// 纠错等级 L 在格式信息中的编码
+const ECC_LEVEL_L_BITS: u32 = 1;
+// 二维码四周的空白区宽度(模块数)
+const QUIET_ZONE: usize = 2;
+
+pub struct QrCode {
+    size: usize,
+    // modules[y][x] 为 true 表示深色模块
+    modules: Vec<Vec<bool>>,
+    // 功能图形(定位、对齐、时序、格式和版本信息)所在的模块，不放数据也不掩码
+    is_function: Vec<Vec<bool>>,
+}
+
+impl QrCode {
+    /**
+     * @desc 编码数据，选择能容纳数据的最小版本和惩罚分最低的掩码
+     */
+    pub fn encode(data: &[u8]) -> Result<QrCode> {
+        let version = (1..=MAX_VERSION)
+            .find(|version| data.len() <= data_capacity(*version))
+            .ok_or_else(|| {
+                format_err!(
+                    "Data of {} bytes is too long for a QR code, max {} bytes.",
+                    data.len(),
+                    data_capacity(MAX_VERSION)
+                )
+            })?;
+
+        let mut qr = QrCode::new(version);
+        qr.draw_function_patterns(version);
+        qr.draw_codewords(&add_ecc_and_interleave(
+            &data_codewords(data, version),
+            version,
+        ));
+
+        let mut best = (0, i32::MAX);
+        for mask in 0..8 {
+            qr.apply_mask(mask);
+            qr.draw_format_bits(mask);
+            let penalty = qr.penalty_score();
+            if penalty < best.1 {
+                best = (mask, penalty);
+            }
+            // 掩码是异或，再应用一次即可撤销
+            qr.apply_mask(mask);
+        }
+        qr.apply_mask(best.0);
+        qr.draw_format_bits(best.0);
+
+        Ok(qr)
+    }
+
+    fn new(version: usize) -> QrCode {
+        let size = version * 4 + 17;
+        QrCode {
+            size,
+            modules: vec![vec![false; size]; size],
+            is_function: vec![vec![false; size]; size],
+        }
+    }
+
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /**
+     * @desc 坐标 (x, y) 的模块是否为深色，超出范围时为浅色
+     */
+    pub fn get_module(&self, x: usize, y: usize) -> bool {
+        x < self.size && y < self.size && self.modules[y][x]
+    }
+
+    /**
+     * @desc 用半高方块字符渲染成终端文本，每行字符表示两行模块；浅色模块显示为方块，适合深色背景的终端
+     */
+    pub fn to_terminal_string(&self) -> String {
+        self.to_block_string(false)
+    }
+
+    /**
+     * @desc 用半高方块字符渲染成打印用的文本，深色模块显示为方块，适合打印在白纸上
+     */
+    pub fn to_printable_string(&self) -> String {
+        self.to_block_string(true)
+    }
+
+    /**
+     * @desc 渲染成 SVG 图片，每个模块边长 module_px 像素，包括空白区
+     */
+    pub fn to_svg(&self, module_px: usize) -> String {
+        let total = (self.size + 2 * QUIET_ZONE) * module_px;
+        let mut out = format!(
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{0}\" height=\"{0}\" viewBox=\"0 0 {0} {0}\">\
+             <rect width=\"{0}\" height=\"{0}\" fill=\"#fff\"/><path fill=\"#000\" d=\"",
+            total
+        );
+        for y in 0..self.size {
+            for x in (0..self.size).filter(|x| self.get_module(*x, y)) {
+                out.push_str(&format!(
+                    "M{} {}h{}v{}h-{}z",
+                    (x + QUIET_ZONE) * module_px,
+                    (y + QUIET_ZONE) * module_px,
+                    module_px,
+                    module_px,
+                    module_px
+                ));
+            }
+        }
+        out.push_str("\"/></svg>");
+
+        out
+    }
+
+    // 每行字符表示两行模块，dark_blocks 为 true 时深色模块显示为方块，否则浅色模块显示为方块
+    fn to_block_string(&self, dark_blocks: bool) -> String {
+        let total = self.size + 2 * QUIET_ZONE;
+        // 带空白区的坐标，空白区为浅色
+        let light = |x: usize, y: usize| {
+            x < QUIET_ZONE || y < QUIET_ZONE || !self.get_module(x - QUIET_ZONE, y - QUIET_ZONE)
+        };
+        // 显示为方块的模块
+        let block = |x: usize, y: usize| y < total && light(x, y) != dark_blocks;
+
+        let mut out = String::new();
+        for y in (0..total).step_by(2) {
+            for x in 0..total {
+                let top = block(x, y);
+                let bottom = block(x, y + 1);
+                out.push(match (top, bottom) {
+                    (true, true) => '█',
+                    (true, false) => '▀',
+                    (false, true) => '▄',
+                    (false, false) => ' ',
+                });
+            }
+            out.push('\n');
+        }
+
+        out
+    }
+
+    fn set_function(&mut self, x: usize, y: usize, dark: bool) {
+        self.modules[y][x] = dark;
+        self.is_function[y][x] = true;
+    }
+
+    fn draw_function_patterns(&mut self, version: usize) {
+        let size = self.size;
+        // 时序图形
+        for i in 0..size {
+            self.set_function(6, i, i % 2 == 0);
+            self.set_function(i, 6, i % 2 == 0);
+        }
+
+        // 三个角上的定位图形(包括分隔符)
+        self.draw_finder_pattern(3, 3);
+        self.draw_finder_pattern(size - 4, 3);
+        self.draw_finder_pattern(3, size - 4);
+
+        // 对齐图形，和定位图形重叠的三个位置除外
+        let positions = alignment_positions(version);
+        let last = positions.len().saturating_sub(1);
+        for (i, x) in positions.iter().enumerate() {
+            for (j, y) in positions.iter().enumerate() {
+                if (i == 0 && (j == 0 || j == last)) || (i == last && j == 0) {
+                    continue;
+                }
+                self.draw_alignment_pattern(*x, *y);
+            }
+        }
+
+        // 先占住格式信息的位置，选好掩码后再写入
+        self.draw_format_bits(0);
+        self.draw_version(version);
+    }
+
+    fn draw_finder_pattern(&mut self, x: usize, y: usize) {
+        for dy in -4i32..=4 {
+            for dx in -4i32..=4 {
+                let (xx, yy) = (x as i32 + dx, y as i32 + dy);
+                if xx < 0 || yy < 0 || xx >= self.size as i32 || yy >= self.size as i32 {
+                    continue;
+                }
+                let dist = dx.abs().max(dy.abs());
+                self.set_function(xx as usize, yy as usize, dist != 2 && dist != 4);
+            }
+        }
+    }
+
+    fn draw_alignment_pattern(&mut self, x: usize, y: usize) {
+        for dy in -2i32..=2 {
+            for dx in -2i32..=2 {
+                let dark = dx.abs().max(dy.abs()) != 1;
+                self.set_function((x as i32 + dx) as usize, (y as i32 + dy) as usize, dark);
+            }
+        }
+    }
+
+    fn draw_format_bits(&mut self, mask: u32) {
+        let data = (ECC_LEVEL_L_BITS << 3) | mask;
+        let mut rem = data;
+        for _ in 0..10 {
+            rem = (rem << 1) ^ ((rem >> 9) * 0x537);
+        }
+        let bits = ((data << 10) | rem) ^ 0x5412;
+        let bit = |i: usize| (bits >> i) & 1 != 0;
+
+        // 左上角的一份
+        for i in 0..=5 {
+            self.set_function(8, i, bit(i));
+        }
+        self.set_function(8, 7, bit(6));
+        self.set_function(8, 8, bit(7));
+        self.set_function(7, 8, bit(8));
+        for i in 9..15 {
+            self.set_function(14 - i, 8, bit(i));
+        }
+
+        // 右上角和左下角的另一份
+        let size = self.size;
+        for i in 0..8 {
+            self.set_function(size - 1 - i, 8, bit(i));
+        }
+        for i in 8..15 {
+            self.set_function(8, size - 15 + i, bit(i));
+        }
+        // 固定的深色模块
+        self.set_function(8, size - 8, true);
+    }
+
+    fn draw_version(&mut self, version: usize) {
+        if version < 7 {
+            return;
+        }
+
+        let mut rem = version as u32;
+        for _ in 0..12 {
+            rem = (rem << 1) ^ ((rem >> 11) * 0x1F25);
+        }
+        let bits = ((version as u32) << 12) | rem;
+        for i in 0..18 {
+            let dark = (bits >> i) & 1 != 0;
+            let (a, b) = (self.size - 11 + i % 3, i / 3);
+            self.set_function(a, b, dark);
+            self.set_function(b, a, dark);
+        }
+    }
+
+    /**
+     * @desc 按之字形从右下角开始，每次两列交替向上、向下放置数据位，跳过功能图形和第 6 列
+     */
+    fn draw_codewords(&mut self, codewords: &[u8]) {
+        let size = self.size;
+        let mut i = 0;
+        let mut right = size as i32 - 1;
+        while right >= 1 {
+            if right == 6 {
+                right = 5;
+            }
+            for vert in 0..size {
+                for j in 0..2 {
+                    let x = right as usize - j;
+                    let upward = (right + 1) & 2 == 0;
+                    let y = if upward { size - 1 - vert } else { vert };
+                    if !self.is_function[y][x] && i < codewords.len() * 8 {
+                        self.modules[y][x] = (codewords[i >> 3] >> (7 - (i & 7))) & 1 != 0;
+                        i += 1;
+                    }
+                }
+            }
+            right -= 2;
+        }
+    }
+
+    fn apply_mask(&mut self, mask: u32) {
+        for y in 0..self.size {
+            for x in 0..self.size {
+                let invert = match mask {
+                    0 => (x + y) % 2 == 0,
+                    1 => y % 2 == 0,
+                    2 => x % 3 == 0,
+                    3 => (x + y) % 3 == 0,
+                    4 => (x / 3 + y / 2) % 2 == 0,
+                    5 => x * y % 2 + x * y % 3 == 0,
+                    6 => (x * y % 2 + x * y % 3) % 2 == 0,
+                    _ => ((x + y) % 2 + x * y % 3) % 2 == 0,
+                };
+                if invert && !self.is_function[y][x] {
+                    self.modules[y][x] = !self.modules[y][x];
+                }
+            }
+        }
+    }
+
+    /**
+     * @desc 掩码的惩罚分: 同色连续模块、同色 2x2 方块、类似定位图形的序列和深浅比例失衡
+     */
+    fn penalty_score(&self) -> i32 {
+        let size = self.size;
+        let mut penalty = 0;
+
+        let rows: Vec<Vec<bool>> = self.modules.clone();
+        let cols: Vec<Vec<bool>> = (0..size)
+            .map(|x| (0..size).map(|y| self.modules[y][x]).collect())
+            .collect();
+        for line in rows.iter().chain(cols.iter()) {
+            let mut run = 1;
+            for i in 1..=size {
+                if i < size && line[i] == line[i - 1] {
+                    run += 1;
+                    continue;
+                }
+                if run >= 5 {
+                    penalty += run - 2;
+                }
+                run = 1;
+            }
+
+            // 1:1:3:1:1 的定位图形比例，前面或后面有 4 个浅色模块
+            let finder = [true, false, true, true, true, false, true];
+            for start in 0..size.saturating_sub(6) {
+                if line[start..start + 7] != finder {
+                    continue;
+                }
+                let light_before = start >= 4 && line[start - 4..start].iter().all(|m| !m);
+                let light_after =
+                    start + 11 <= size && line[start + 7..start + 11].iter().all(|m| !m);
+                if light_before || light_after {
+                    penalty += 40;
+                }
+            }
+        }
+
+        for y in 0..size - 1 {
+            for x in 0..size - 1 {
+                let color = self.modules[y][x];
+                if self.modules[y][x + 1] == color
+                    && self.modules[y + 1][x] == color
+                    && self.modules[y + 1][x + 1] == color
+                {
+                    penalty += 3;
+                }
+            }
+        }
+
+        let dark = self.modules.iter().flatten().filter(|m| **m).count() as i32;
+        let total = (size * size) as i32;
+        // 深色比例每偏离 50% 的 5% 罚 10 分
+        let k = ((dark * 20 - total * 10).abs() + total - 1) / total - 1;
+        penalty + k.max(0) * 10
+    }
+}
+
+/**
+ * @desc 版本中去掉功能图形后可放数据和纠错码的模块数
+ */
+fn num_raw_data_modules(version: usize) -> usize {
+    let mut result = (16 * version + 128) * version + 64;
+    if version >= 2 {
+        let num_align = version / 7 + 2;
+        result -= (25 * num_align - 10) * num_align - 55;
+        if version >= 7 {
+            result -= 36;
+        }
+    }
+    result
+}
+
+fn num_data_codewords(version: usize) -> usize {
+    num_raw_data_modules(version) / 8
+        - ECC_CODEWORDS_PER_BLOCK[version] * NUM_ERROR_CORRECTION_BLOCKS[version]
+}
+
+/**
+ * @desc 字节模式下版本能容纳的数据字节数(模式 4 位加长度字段)
+ */
+fn data_capacity(version: usize) -> usize {
+    let count_bits = if version <= 9 { 8 } else { 16 };
+    (num_data_codewords(version) * 8 - 4 - count_bits) / 8
+}
+
+/**
+ * @desc 字节模式的数据码字: 模式、长度、数据、终止符，最后用 0xec、0x11 交替填充
+ */
+fn data_codewords(data: &[u8], version: usize) -> Vec<u8> {
+    let capacity_bits = num_data_codewords(version) * 8;
+    let count_bits = if version <= 9 { 8 } else { 16 };
+
+    let mut bits = Vec::new();
+    let mut push = |value: usize, len: usize| {
+        for i in (0..len).rev() {
+            bits.push((value >> i) & 1 != 0);
+        }
+    };
+    push(0b0100, 4);
+    push(data.len(), count_bits);
+    for byte in data {
+        push(*byte as usize, 8);
+    }
+    let terminator = 4.min(capacity_bits - bits.len());
+    bits.extend(std::iter::repeat_n(false, terminator));
+    while !bits.len().is_multiple_of(8) {
+        bits.push(false);
+    }
+
+    let mut codewords: Vec<u8> = bits
+        .chunks(8)
+        .map(|chunk| chunk.iter().fold(0, |byte, bit| (byte << 1) | *bit as u8))
+        .collect();
+    for pad in [0xec, 0x11].iter().cycle() {
+        if codewords.len() >= capacity_bits / 8 {
+            break;
+        }
+        codewords.push(*pad);
+    }
+
+    codewords
+}
+
+/**
+ * @desc 数据分块并计算各块的纠错码，再按列交错排列
+ */
+fn add_ecc_and_interleave(data: &[u8], version: usize) -> Vec<u8> {
+    let num_blocks = NUM_ERROR_CORRECTION_BLOCKS[version];
+    let block_ecc_len = ECC_CODEWORDS_PER_BLOCK[version];
+    let raw_codewords = num_raw_data_modules(version) / 8;
+    let num_short_blocks = num_blocks - raw_codewords % num_blocks;
+    let short_block_len = raw_codewords / num_blocks;
+
+    let divisor = reed_solomon_divisor(block_ecc_len);
+    let mut blocks = Vec::new();
+    let mut k = 0;
+    for i in 0..num_blocks {
+        let data_len = short_block_len - block_ecc_len + usize::from(i >= num_short_blocks);
+        let mut block = data[k..k + data_len].to_vec();
+        k += data_len;
+        let ecc = reed_solomon_remainder(&block, &divisor);
+        // 短块补一个占位字节，交错时跳过
+        if i < num_short_blocks {
+            block.push(0);
+        }
+        block.extend(ecc);
+        blocks.push(block);
+    }
+
+    let mut result = Vec::new();
+    for i in 0..blocks[0].len() {
+        for (j, block) in blocks.iter().enumerate() {
+            if i != short_block_len - block_ecc_len || j >= num_short_blocks {
+                result.push(block[i]);
+            }
+        }
+    }
+
+    result
+}
+
+/**
+ * @desc 次数为 degree 的 Reed-Solomon 生成多项式(最高次项系数 1 省略)
+ */
+fn reed_solomon_divisor(degree: usize) -> Vec<u8> {
+    let mut result = vec![0; degree];
+    result[degree - 1] = 1;
+    let mut root = 1;
+    for _ in 0..degree {
+        for j in 0..degree {
+            result[j] = gf_multiply(result[j], root);
+            if j + 1 < degree {
+                result[j] ^= result[j + 1];
+            }
+        }
+        root = gf_multiply(root, 0x02);
+    }
+    result
+}
+
+fn reed_solomon_remainder(data: &[u8], divisor: &[u8]) -> Vec<u8> {
+    let mut result = vec![0; divisor.len()];
+    for byte in data {
+        let factor = byte ^ result.remove(0);
+        result.push(0);
+        for (x, y) in result.iter_mut().zip(divisor) {
+            *x ^= gf_multiply(*y, factor);
+        }
+    }
+    result
+}
+
+/**
+ * @desc GF(2^8) 上的乘法，本原多项式 x^8 + x^4 + x^3 + x^2 + 1
+ */
+fn gf_multiply(x: u8, y: u8) -> u8 {
+    let mut z: u32 = 0;
+    for i in (0..8).rev() {
+        z = (z << 1) ^ ((z >> 7) * 0x11d);
+        z ^= ((y as u32 >> i) & 1) * x as u32;
+    }
+    z as u8
+}
+
+/**
+ * @desc 对齐图形中心的坐标，版本 1 没有对齐图形
+ */
+fn alignment_positions(version: usize) -> Vec<usize> {
+    if version == 1 {
+        return Vec::new();
+    }
+
+    let num_align = version / 7 + 2;
+    let step = (version * 4 + num_align * 2 + 1) / (num_align * 2 - 2) * 2;
+    let mut result = vec![6];
+    let mut pos = version * 4 + 17 - 7;
+    for _ in 0..num_align - 1 {
+        result.insert(1, pos);
+        pos -= step;
+    }
+    result
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_reed_solomon() {
+        // "HELLO WORLD" 1-M 的数据码字和纠错码字
+        let data = [
+            32, 91, 11, 120, 209, 114, 220, 77, 67, 64, 236, 17, 236, 17, 236, 17,
+        ];
+        assert_eq!(
+            reed_solomon_remainder(&data, &reed_solomon_divisor(10)),
+            vec![196, 35, 39, 119, 235, 215, 231, 226, 93, 23]
+        );
+    }
+
+    #[test]
+    fn test_capacity_and_layout() {
+        assert_eq!(
+            (1..=MAX_VERSION).map(data_capacity).collect::<Vec<_>>(),
+            vec![17, 32, 53, 78, 106, 134, 154, 192, 230, 271]
+        );
+        assert_eq!(alignment_positions(7), vec![6, 22, 38]);
+        assert_eq!(alignment_positions(10), vec![6, 28, 50]);
+
+        let qr = QrCode::encode(b"blockchain:test").unwrap();
+        assert_eq!(qr.size(), 21);
+        // 定位图形的中心和分隔符
+        assert!(qr.get_module(3, 3) && qr.get_module(17, 3) && qr.get_module(3, 17));
+        assert!(!qr.get_module(7, 7) && !qr.get_module(13, 7));
+        assert!(qr.get_module(8, qr.size() - 8));
+
+        // 打印用的文本与终端文本明暗相反，空白区在打印时是空白
+        let (terminal, printable) = (qr.to_terminal_string(), qr.to_printable_string());
+        assert_eq!(terminal.lines().count(), printable.lines().count());
+        assert!(terminal.starts_with('█') && printable.starts_with(' '));
+        let svg = qr.to_svg(4);
+        assert!(svg.starts_with("<svg") && svg.contains("width=\"100\""));
+
+        assert_eq!(QrCode::encode(&[0; 100]).unwrap().size(), 37);
+        assert!(QrCode::encode(&[0; 272]).is_err());
+    }
+
+    #[test]
+    fn test_format_bits() {
+        // 纠错等级 L、掩码 0 的格式信息为 111011111000100
+        let mut qr = QrCode::new(1);
+        qr.draw_format_bits(0);
+        let bits: Vec<bool> = (0..8).map(|i| qr.get_module(20 - i, 8)).collect();
+        assert_eq!(
+            bits,
+            vec![false, false, true, false, false, false, true, true]
+        );
+    }
+}
@@ -0,0 +1,379 @@
+use std::{
+    collections::HashMap,
+    io::prelude::{Read, Write},
+    net::TcpStream,
+    time::Duration,
+};
+
+use super::Result;
+use crate::{
+    block::Block,
+    blockchain::Blockchain,
+    json::Json,
+    mining, network,
+    transaction::{TXOutputs, Transaction},
+    units,
+    wallets::{address_from_pub_key_hash, decode_address, hash_pub_key},
+};
+use failure::format_err;
+
+// 只读的 REST 接口(HTTP GET + JSON)
+const MAX_REQUEST_SIZE: usize = 8192;
+const REQUEST_TIMEOUT: u64 = 5;
+const DEFAULT_RECENT_BLOCKS: usize = 10;
+const MAX_RECENT_BLOCKS: usize = 100;
+
+#[cfg(feature = "explorer")]
+const EXPLORER_HTML: &str = include_str!("explorer.html");
+
+pub struct Request {
+    pub path: String,
+    // Authorization 请求头
+    pub authorization: Option<String>,
+}
+
+pub struct Response {
+    pub status: u16,
+    pub content_type: &'static str,
+    pub body: String,
+}
+
+impl Response {
+    fn json(status: u16, body: Json) -> Response {
+        Response {
+            status,
+            content_type: "application/json",
+            body: body.to_string(),
+        }
+    }
+
+    fn error(status: u16, message: &str) -> Response {
+        Response::json(status, Json::object(vec![("error", message.into())]))
+    }
+
+    pub fn unauthorized(message: &str) -> Response {
+        Response::error(401, message)
+    }
+}
+
+/**
+ * @desc 读取 HTTP 请求的路径和认证头(只支持 GET)
+ */
+pub fn read_request(stream: &mut TcpStream) -> Result<Request> {
+    stream.set_read_timeout(Some(Duration::from_secs(REQUEST_TIMEOUT)))?;
+
+    let mut buffer = Vec::new();
+    let mut chunk = [0; 1024];
+    while !buffer.windows(4).any(|w| w == b"\r\n\r\n") {
+        let len = stream.read(&mut chunk)?;
+        if len == 0 {
+            break;
+        }
+        buffer.extend_from_slice(&chunk[..len]);
+        if buffer.len() > MAX_REQUEST_SIZE {
+            return Err(format_err!("HTTP request is too large."));
+        }
+    }
+
+    let request = String::from_utf8_lossy(&buffer);
+    let mut lines = request.lines();
+    let mut parts = lines.next().unwrap_or("").split_whitespace();
+    let path = match (parts.next(), parts.next()) {
+        (Some("GET"), Some(path)) => path.to_string(),
+        _ => return Err(format_err!("Unsupported HTTP request.")),
+    };
+
+    let authorization = lines.find_map(|line| {
+        let (name, value) = line.split_once(':')?;
+        if name.trim().eq_ignore_ascii_case("authorization") {
+            Some(value.trim().to_string())
+        } else {
+            None
+        }
+    });
+
+    Ok(Request {
+        path,
+        authorization,
+    })
+}
+
+/**
+ * @desc 写回 HTTP 响应
+ */
+pub fn write_response(stream: &mut TcpStream, response: &Response) -> Result<()> {
+    let reason = match response.status {
+        200 => "OK",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        404 => "Not Found",
+        _ => "Internal Server Error",
+    };
+    let challenge = if response.status == 401 {
+        "WWW-Authenticate: Basic realm=\"blockchain\"\r\n"
+    } else {
+        ""
+    };
+    let head = format!(
+        "HTTP/1.1 {} {}\r\n{}Content-Type: {}; charset=utf-8\r\nContent-Length: {}\r\nAccess-Control-Allow-Origin: *\r\nConnection: close\r\n\r\n",
+        response.status,
+        reason,
+        challenge,
+        response.content_type,
+        response.body.len()
+    );
+
+    stream.write_all(head.as_bytes())?;
+    stream.write_all(response.body.as_bytes())?;
+
+    Ok(())
+}
+
+/**
+ * @desc 根据路径分发请求，find_utxos 通过 UTXO 集的公钥哈希索引查询地址的未花费输出
+ */
+pub fn route(
+    path: &str,
+    bc: &Blockchain,
+    mempool: &HashMap<String, Transaction>,
+    find_utxos: impl Fn(&[u8]) -> Result<TXOutputs>,
+) -> Response {
+    let (path, query) = path.split_once('?').unwrap_or((path, ""));
+    let segments: Vec<&str> = path.trim_matches('/').split('/').collect();
+
+    let result = match segments.as_slice() {
+        #[cfg(feature = "explorer")]
+        [""] | ["index.html"] => {
+            return Response {
+                status: 200,
+                content_type: "text/html",
+                body: EXPLORER_HTML.to_string(),
+            }
+        }
+        ["rest", "chaininfo"] => chain_info(bc),
+        ["rest", "blocks"] => recent_blocks(bc, query),
+        ["rest", "block", hash] => block_detail(bc, hash),
+        ["rest", "blockhash", height] => block_hash(bc, height),
+        ["rest", "tx", txid] => tx_detail(bc, mempool, txid),
+        ["rest", "address", address] => address_detail(bc, address, find_utxos),
+        ["rest", "mininginfo"] => mining_info(bc),
+        ["rest", "mempool"] => Ok(Some(Json::Array(
+            mempool.values().map(|tx| tx_json(bc, tx)).collect(),
+        ))),
+        _ => return Response::error(404, "Not found."),
+    };
+
+    match result {
+        Ok(Some(json)) => Response::json(200, json),
+        Ok(None) => Response::error(404, "Not found."),
+        Err(e) => Response::error(400, &e.to_string()),
+    }
+}
+
+fn chain_info(bc: &Blockchain) -> Result<Option<Json>> {
+    let tip = bc.get_tip_hash();
+
+    Ok(Some(Json::object(vec![
+        ("network", format!("{:?}", network::current()).into()),
+        ("height", bc.get_best_height()?.into()),
+        ("best_block_hash", tip.clone().into()),
+        ("chain_work", bc.get_chain_work(&tip)?.into()),
+    ])))
+}
+
+fn mining_info(bc: &Blockchain) -> Result<Option<Json>> {
+    let info = mining::get_mining_info(bc)?;
+
+    Ok(Some(Json::object(vec![
+        ("hash_rate", (info.hash_rate.round() as u64).into()),
+        ("blocks_mined", info.blocks_mined.into()),
+        ("difficulty", info.difficulty.into()),
+        ("template_tx_count", info.template_tx_count.into()),
+        ("secs_since_last_block", info.secs_since_last_block.into()),
+    ])))
+}
+
+fn recent_blocks(bc: &Blockchain, query: &str) -> Result<Option<Json>> {
+    let count = query
+        .split('&')
+        .find_map(|pair| pair.strip_prefix("count="))
+        .map(|count| count.parse())
+        .transpose()?
+        .unwrap_or(DEFAULT_RECENT_BLOCKS)
+        .min(MAX_RECENT_BLOCKS);
+
+    let blocks: Vec<Json> = bc
+        .iter()
+        .take(count)
+        .map(|block| block_json(&block))
+        .collect::<Result<_>>()?;
+
+    Ok(Some(Json::Array(blocks)))
+}
+
+fn block_detail(bc: &Blockchain, hash: &str) -> Result<Option<Json>> {
+    if !bc.has_block(hash)? {
+        return Ok(None);
+    }
+    let block = bc.get_block(hash)?;
+
+    let confirmations = if bc.is_in_main_chain(hash)? {
+        bc.get_best_height()? - block.get_height() + 1
+    } else {
+        0
+    };
+
+    let mut json = block_json(&block)?;
+    if let Json::Object(fields) = &mut json {
+        fields.push(("confirmations".to_string(), confirmations.into()));
+        fields.push(("chain_work".to_string(), bc.get_chain_work(hash)?.into()));
+        fields.push((
+            "transactions".to_string(),
+            Json::Array(
+                block
+                    .get_transaction()
+                    .iter()
+                    .map(|tx| tx_json(bc, tx))
+                    .collect(),
+            ),
+        ));
+    }
+
+    Ok(Some(json))
+}
+
+fn block_hash(bc: &Blockchain, height: &str) -> Result<Option<Json>> {
+    let hash = bc.get_block_hash_at(height.parse()?)?;
+
+    Ok(hash.map(|hash| Json::object(vec![("hash", hash.into())])))
+}
+
+fn tx_detail(
+    bc: &Blockchain,
+    mempool: &HashMap<String, Transaction>,
+    txid: &str,
+) -> Result<Option<Json>> {
+    let (tx, block_hash) = match bc.find_transaction_block(txid)? {
+        Some(block_hash) => (bc.find_transaction(txid)?, Some(block_hash)),
+        None => match mempool.get(txid) {
+            Some(tx) => (tx.clone(), None),
+            None => return Ok(None),
+        },
+    };
+
+    let mut json = tx_json(bc, &tx);
+    if let Json::Object(fields) = &mut json {
+        fields.push(("block_hash".to_string(), block_hash.into()));
+        fields.push((
+            "confirmations".to_string(),
+            bc.get_confirmations(txid)?.into(),
+        ));
+    }
+
+    Ok(Some(json))
+}
+
+fn address_detail(
+    bc: &Blockchain,
+    address: &str,
+    find_utxos: impl Fn(&[u8]) -> Result<TXOutputs>,
+) -> Result<Option<Json>> {
+    let pub_key_hash = decode_address(address)?;
+
+    let balance: i32 = find_utxos(&pub_key_hash)?
+        .outputs
+        .iter()
+        .map(|out| out.value)
+        .sum();
+
+    // 按区块从新到旧列出和地址相关的交易
+    let mut history = Vec::new();
+    for block in bc.iter() {
+        for tx in block.get_transaction() {
+            let received = tx
+                .vout
+                .iter()
+                .any(|out| out.is_locked_with_key(&pub_key_hash));
+            let sent = !tx.is_coinbase() && tx.vin.iter().any(|vin| vin.uses_key(&pub_key_hash));
+
+            if received || sent {
+                history.push(Json::object(vec![
+                    ("txid", tx.id.clone().into()),
+                    ("block_hash", block.get_hash().into()),
+                    ("height", block.get_height().into()),
+                ]));
+            }
+        }
+    }
+
+    Ok(Some(Json::object(vec![
+        ("address", address.into()),
+        ("balance", units::amount_json(balance)),
+        ("transactions", Json::Array(history)),
+    ])))
+}
+
+fn block_json(block: &Block) -> Result<Json> {
+    let header = block.get_header();
+
+    Ok(Json::object(vec![
+        ("hash", header.hash.into()),
+        ("height", header.height.into()),
+        ("version", header.version.into()),
+        ("timestamp", header.timestamp.into()),
+        ("prev_block_hash", header.prev_block_hash.into()),
+        ("nonce", header.nonce.into()),
+        ("size", block.get_size()?.into()),
+        ("tx_count", block.get_transaction().len().into()),
+        (
+            "coinbase_tag",
+            block.coinbase_tag().map_or(Json::Null, Json::from),
+        ),
+    ]))
+}
+
+fn tx_json(bc: &Blockchain, tx: &Transaction) -> Json {
+    let vin: Vec<Json> = if tx.is_coinbase() {
+        Vec::new()
+    } else {
+        tx.vin
+            .iter()
+            .map(|vin| {
+                let mut pub_key = vin.pub_key.clone();
+                hash_pub_key(&mut pub_key);
+                Json::object(vec![
+                    ("txid", vin.txid.clone().into()),
+                    ("vout", vin.vout.into()),
+                    ("address", address_from_pub_key_hash(&pub_key).into()),
+                ])
+            })
+            .collect()
+    };
+
+    let vout: Vec<Json> = tx
+        .vout
+        .iter()
+        .map(|out| {
+            Json::object(vec![
+                ("value", units::amount_json(out.value)),
+                (
+                    "address",
+                    address_from_pub_key_hash(&out.pub_key_hash).into(),
+                ),
+            ])
+        })
+        .collect();
+
+    Json::object(vec![
+        ("txid", tx.id.clone().into()),
+        ("coinbase", tx.is_coinbase().into()),
+        ("vin", Json::Array(vin)),
+        ("vout", Json::Array(vout)),
+        (
+            "fee",
+            bc.get_tx_fee(tx)
+                .ok()
+                .map_or(Json::Null, units::amount_json),
+        ),
+    ])
+}
@@ -0,0 +1,149 @@
+// 出块奖励分成: 配置的地址按百分比分得 coinbase 奖励，剩余部分归矿工，例如课堂上共享的奖池；
+// 分成后的 coinbase 交易有多个输出，每份向下取整，不足最小单位的部分归矿工
+//   reward_split = ["<address>:<percent>"]
+
+use super::Result;
+use crate::{
+    transaction::{TXOutput, Transaction},
+    wallets::decode_address,
+};
+use failure::format_err;
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RewardSplit {
+    // 地址 -> 分得的百分比，按配置顺序
+    pub shares: Vec<(String, u32)>,
+}
+
+impl RewardSplit {
+    /**
+     * @desc 解析 "<address>:<percent>" 形式的分成，检查地址有效、百分比为正且总和不超过 100
+     */
+    pub fn parse(entries: &[String]) -> Result<RewardSplit> {
+        let mut shares = Vec::new();
+        for entry in entries {
+            let (address, percent) = entry
+                .rsplit_once(':')
+                .ok_or_else(|| format_err!("Invalid reward split: {}", entry))?;
+            let address = address.trim();
+            decode_address(address)?;
+            let percent: u32 = percent
+                .trim()
+                .parse()
+                .ok()
+                .filter(|percent| *percent > 0)
+                .ok_or_else(|| format_err!("Invalid reward split percent: {}", entry))?;
+            shares.push((address.to_string(), percent));
+        }
+
+        let total: u32 = shares.iter().map(|(_, percent)| percent).sum();
+        if total > 100 {
+            return Err(format_err!(
+                "Reward split adds up to {}%, at most 100%.",
+                total
+            ));
+        }
+
+        Ok(RewardSplit { shares })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.shares.is_empty()
+    }
+
+    /**
+     * @desc 奖励 reward 的分配: 分成地址按配置顺序在前，矿工得到剩余部分，金额为 0 的输出省略
+     */
+    pub fn outputs(&self, miner: &str, reward: i32) -> Vec<(String, i32)> {
+        let mut outputs: Vec<(String, i32)> = self
+            .shares
+            .iter()
+            .map(|(address, percent)| {
+                let value = (reward as i64 * *percent as i64 / 100) as i32;
+                (address.clone(), value)
+            })
+            .collect();
+        let rest = reward - outputs.iter().map(|(_, value)| value).sum::<i32>();
+        outputs.push((miner.to_string(), rest));
+        outputs.retain(|(_, value)| *value > 0);
+
+        outputs
+    }
+
+    /**
+     * @desc 按分成改写 coinbase 交易的输出，原输出的收款地址是矿工，coinbase 数据保持不变
+     */
+    pub fn apply(&self, mut coinbase: Transaction, miner: &str) -> Result<Transaction> {
+        if !coinbase.is_coinbase() {
+            return Err(format_err!(
+                "Transaction {} is not a coinbase.",
+                coinbase.id
+            ));
+        }
+        let reward = coinbase.vout.iter().map(|out| out.value).sum();
+
+        coinbase.vout = self
+            .outputs(miner, reward)
+            .into_iter()
+            .map(|(address, value)| TXOutput::new(value, address))
+            .collect::<Result<_>>()?;
+        coinbase.id = coinbase.hash()?;
+
+        Ok(coinbase)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{transaction::SUBSIDY, wallets::Wallet};
+    use rand_core::OsRng;
+
+    #[test]
+    fn test_parse() {
+        let pool = Wallet::default().get_address();
+        let split = RewardSplit::parse(&[format!("{}:30", pool)]).unwrap();
+        assert_eq!(split.shares, vec![(pool.clone(), 30)]);
+        assert!(RewardSplit::parse(&[]).unwrap().is_empty());
+
+        for entry in [
+            pool.clone(),
+            format!("{}:0", pool),
+            format!("{}:-5", pool),
+            String::from("not-an-address:10"),
+        ] {
+            assert!(
+                RewardSplit::parse(std::slice::from_ref(&entry)).is_err(),
+                "{}",
+                entry
+            );
+        }
+        let over = [format!("{}:60", pool), format!("{}:41", pool)];
+        assert!(RewardSplit::parse(&over).is_err());
+    }
+
+    #[test]
+    fn test_apply() {
+        let miner = Wallet::default().get_address();
+        let pool = Wallet::default().get_address();
+        let school = Wallet::default().get_address();
+        let split = RewardSplit::parse(&[format!("{}:30", pool), format!("{}:5", school)]).unwrap();
+
+        // 5% 不足最小单位，归矿工
+        assert_eq!(
+            split.outputs(&miner, SUBSIDY),
+            vec![(pool.clone(), 3), (miner.clone(), 7)]
+        );
+        let all = RewardSplit::parse(&[format!("{}:100", pool)]).unwrap();
+        assert_eq!(all.outputs(&miner, SUBSIDY), vec![(pool.clone(), SUBSIDY)]);
+
+        let coinbase =
+            Transaction::new_tagged_coinbase(miner.clone(), "class", &mut OsRng).unwrap();
+        let split_coinbase = split.apply(coinbase.clone(), &miner).unwrap();
+        assert_ne!(split_coinbase.id, coinbase.id);
+        assert_eq!(split_coinbase.id, split_coinbase.hash().unwrap());
+        assert_eq!(split_coinbase.coinbase_tag(), Some(String::from("class")));
+        assert_eq!(split_coinbase.vout.len(), 2);
+        assert!(split_coinbase.check_coinbase_outputs().is_ok());
+    }
+}
@@ -0,0 +1,696 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs,
+    io::prelude::{Read, Write},
+    net::{Shutdown, TcpStream},
+};
+
+use super::Result;
+use crate::{
+    alert::Alert,
+    auth::Permission,
+    block::{Block, BlockHeader},
+    blockchain::ChainTip,
+    governance::Tally,
+    names::NameClaim,
+    network,
+    psbt::Psbt,
+    server::cmd_to_bytes,
+    transaction::{TXOutputs, Transaction},
+    utxo_set::UtxoProvider,
+    versionbits::ThresholdState,
+};
+use bincode::{deserialize, serialize};
+use failure::format_err;
+use serde::{Deserialize, Serialize};
+
+pub const RPC_CMD: &str = "rpc";
+pub const DEFAULT_RPC_NODE: &str = "localhost:3000";
+// 节点启动时写入自己的 RPC 地址，命令行据此找到本机正在运行的节点
+pub const LOCAL_NODE_FILE: &str = ".node";
+
+// 请求
+#[allow(clippy::enum_variant_names)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub enum RpcRequest {
+    GetPeerInfo,
+    GetNetworkInfo,
+    GetNetTotals,
+    GetSyncStatus,
+    GetBlockchainInfo,
+    GetTransaction(String),
+    // 公钥哈希的可花费输出，累计到 amount 为止
+    ListUnspent {
+        pub_key_hash: Vec<u8>,
+        amount: i32,
+    },
+    // 公钥哈希的全部未花费输出
+    ListUtxos(Vec<u8>),
+    // 余额不为 0 的全部地址
+    ListAddressBalances,
+    // 广播已签名的交易
+    SendRawTransaction(Transaction),
+    // 检查交易能否进入交易池，不加入交易池也不广播
+    TestMempoolAccept(Transaction),
+    // 等待最新区块不再是 tip，最多等待 timeout_secs 秒
+    WaitForBlock {
+        tip: String,
+        timeout_secs: u64,
+    },
+    GetMiningInfo,
+    // 按高度或 hash 统计单个区块
+    GetBlockStats(String),
+    // 统计主链上 [from, to] 高度范围内的区块，to 为 None 时统计到最新区块
+    GetChainStats {
+        from: i32,
+        to: Option<i32>,
+    },
+    // 把 [from, to] 高度范围按每 window 个区块分组统计，用于观察出块时间和难度的变化
+    GetChainHistory {
+        from: i32,
+        to: Option<i32>,
+        window: i32,
+    },
+    GetChainTips,
+    // 回滚次数、回滚深度和孤立区块数
+    GetForkStats,
+    // 仅限 regtest: 忽略与这些节点之间的消息来模拟网络分区，空列表表示恢复连通
+    SimulatePartition(Vec<String>),
+    // 手动把区块标记为无效 / 清除无效标记，用于调试分叉选择和回滚
+    InvalidateBlock(String),
+    ReconsiderBlock(String),
+    // 提交完整区块，按收到区块的流程处理并公告给其他节点
+    SubmitBlock(Block),
+    // 按高度或 hash 获取完整区块
+    GetBlock(String),
+    // 提交由公告密钥签名的公告，节点验证后转发给其他节点
+    SubmitAlert(Alert),
+    // 提交部分签名交易，节点合并本机钱包的签名并转发给其他签名方
+    SubmitPsbt(Psbt),
+    // 从交易池移除放弃的钱包交易和它的后代，输入可以重新花费
+    AbandonTransaction(String),
+    // 未确认的钱包交易与主链上哪些交易冲突
+    GetWalletConflicts(Vec<Transaction>),
+    // 名称当前映射的地址
+    ResolveName(String),
+    // 统计提案的投票
+    TallyProposal(String),
+}
+
+impl RpcRequest {
+    /**
+     * @desc 调用方法所需的权限等级
+     */
+    pub fn permission(&self) -> Permission {
+        match self {
+            RpcRequest::GetPeerInfo
+            | RpcRequest::GetNetworkInfo
+            | RpcRequest::GetNetTotals
+            | RpcRequest::InvalidateBlock(_)
+            | RpcRequest::ReconsiderBlock(_)
+            | RpcRequest::SimulatePartition(_) => Permission::Admin,
+            RpcRequest::ListUnspent { .. }
+            | RpcRequest::ListUtxos(_)
+            | RpcRequest::SendRawTransaction(_)
+            | RpcRequest::TestMempoolAccept(_)
+            | RpcRequest::SubmitBlock(_)
+            | RpcRequest::SubmitAlert(_)
+            | RpcRequest::SubmitPsbt(_)
+            | RpcRequest::AbandonTransaction(_)
+            | RpcRequest::GetWalletConflicts(_) => Permission::Wallet,
+            RpcRequest::GetSyncStatus
+            | RpcRequest::GetBlockchainInfo
+            | RpcRequest::GetTransaction(_)
+            | RpcRequest::WaitForBlock { .. }
+            | RpcRequest::GetMiningInfo
+            | RpcRequest::GetBlockStats(_)
+            | RpcRequest::GetChainStats { .. }
+            | RpcRequest::GetChainHistory { .. }
+            | RpcRequest::GetChainTips
+            | RpcRequest::GetForkStats
+            | RpcRequest::GetBlock(_)
+            | RpcRequest::ResolveName(_)
+            | RpcRequest::TallyProposal(_)
+            | RpcRequest::ListAddressBalances => Permission::Public,
+        }
+    }
+}
+
+// 带凭据(用户名, 密码)的请求
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct RpcCall {
+    pub credentials: Option<(String, String)>,
+    pub request: RpcRequest,
+}
+
+// 响应
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub enum RpcResponse {
+    PeerInfo(Vec<PeerInfo>),
+    NetworkInfo(NetworkInfo),
+    NetTotals(NetTotals),
+    SyncStatus(SyncStatus),
+    BlockchainInfo(BlockchainInfo),
+    Transaction(TransactionInfo),
+    Unspent(Unspent),
+    Utxos(TXOutputs),
+    // (地址, 余额)，按余额从大到小排序
+    AddressBalances(Vec<(String, i32)>),
+    TxId(String),
+    TxIds(Vec<String>),
+    // txid -> 冲突的已确认交易
+    Conflicts(HashMap<String, Vec<String>>),
+    AlertId(u32),
+    BlockHash(String),
+    Block(Block),
+    BlockHeader(BlockHeader),
+    MiningInfo(MiningInfo),
+    MempoolAccept(MempoolAccept),
+    BlockStats(BlockStats),
+    ChainStats(ChainStats),
+    ChainHistory(Vec<WindowStats>),
+    ChainTips(Vec<ChainTip>),
+    ForkStats(ForkStats),
+    // 当前被忽略的节点
+    Partitioned(Vec<String>),
+    // 没有注册或注册已过期时为 None
+    Name(Option<NameClaim>),
+    Tally(Tally),
+    Error(String),
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum Direction {
+    Inbound,
+    Outbound,
+}
+
+// 节点信息
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PeerInfo {
+    pub addr: String,
+    pub direction: Direction,
+    pub version: i32,
+    pub best_height: i32,
+    pub last_send: u128,
+    pub last_recv: u128,
+    pub bytes_sent: u64,
+    pub bytes_recv: u64,
+    pub ban_score: i32,
+    pub time_offset: i64,
+    // 节点的身份公钥(十六进制)，还没有收到签名消息时为 None
+    pub identity: Option<String>,
+    pub handshake: HandshakeState,
+}
+
+// 握手状态: 建立连接 -> 已发送版本信息 -> 收到对方的确认 -> 双方都已确认(可以处理链消息)
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum HandshakeState {
+    Connected,
+    VersionSent,
+    VerackReceived,
+    Ready,
+}
+
+impl PeerInfo {
+    pub fn new(addr: &str, direction: Direction) -> PeerInfo {
+        PeerInfo {
+            addr: addr.to_string(),
+            direction,
+            version: 0,
+            best_height: -1,
+            last_send: 0,
+            last_recv: 0,
+            bytes_sent: 0,
+            bytes_recv: 0,
+            ban_score: 0,
+            time_offset: 0,
+            identity: None,
+            handshake: HandshakeState::Connected,
+        }
+    }
+}
+
+// 网络信息
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct NetworkInfo {
+    pub version: i32,
+    pub local_address: String,
+    pub identity: String,
+    pub known_nodes: usize,
+    pub connections: usize,
+    pub inbound: usize,
+    pub outbound: usize,
+    pub total_bytes_sent: u64,
+    pub total_bytes_recv: u64,
+    pub time_offset: i64,
+}
+
+// 流量统计: 节点启动以来按消息类型和节点分别累计的字节数，upload_limit 为上传限速(字节/秒)
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct NetTotals {
+    pub total_bytes_sent: u64,
+    pub total_bytes_recv: u64,
+    pub sent_per_msg: BTreeMap<String, u64>,
+    pub recv_per_msg: BTreeMap<String, u64>,
+    // (节点, 发送字节数, 接收字节数)
+    pub peers: Vec<(String, u64, u64)>,
+    pub upload_limit: Option<u64>,
+    pub time_millis: u128,
+}
+
+// 分叉统计: 孤立区块是收到后没有进入主链或者因回滚离开主链的区块，
+// 回滚深度为旧主链上被断开的区块数
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct ForkStats {
+    pub blocks_received: u64,
+    pub orphan_blocks: u64,
+    pub reorgs: u64,
+    pub last_reorg_depth: u64,
+    pub max_reorg_depth: u64,
+}
+
+impl ForkStats {
+    /**
+     * @desc 记录收到的区块: disconnected 为旧主链被断开的区块数，connected 为新接入主链的区块数(包括该区块)；
+     *       回滚时新接入的其他区块是之前收到的侧链区块，不再算作孤立区块
+     */
+    pub fn record_block(&mut self, disconnected: u64, connected: u64) {
+        self.blocks_received += 1;
+        if connected == 0 {
+            self.orphan_blocks += 1;
+        } else {
+            self.orphan_blocks = self.orphan_blocks.saturating_sub(connected - 1);
+        }
+        if disconnected > 0 {
+            self.reorgs += 1;
+            self.orphan_blocks += disconnected;
+            self.last_reorg_depth = disconnected;
+            self.max_reorg_depth = self.max_reorg_depth.max(disconnected);
+        }
+    }
+
+    pub fn orphan_rate(&self) -> f64 {
+        if self.blocks_received == 0 {
+            return 0.0;
+        }
+        self.orphan_blocks as f64 / self.blocks_received as f64
+    }
+}
+
+// 同步状态: 请求区块哈希 -> 下载区块 -> 已同步
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum SyncState {
+    HeadersSyncing,
+    BlocksDownloading,
+    Synced,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SyncStatus {
+    pub state: SyncState,
+    pub current_height: i32,
+    pub target_height: i32,
+    pub percentage: f64,
+    pub eta_secs: Option<u64>,
+}
+
+// 区块链信息
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct BlockchainInfo {
+    pub network: String,
+    pub best_block_hash: String,
+    pub height: i32,
+    pub chain_work: u128,
+    pub median_time: u128,
+    pub softforks: Vec<SoftforkInfo>,
+    // 尚未过期的运维公告
+    pub alerts: Vec<String>,
+}
+
+// 挖矿信息: 难度为满足要求平均需要计算的哈希次数，距上一个区块的时间以最新区块的时间戳计算
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct MiningInfo {
+    pub hash_rate: f64,
+    pub blocks_mined: u64,
+    pub difficulty: u128,
+    pub template_tx_count: usize,
+    pub secs_since_last_block: u64,
+}
+
+// 区块概要: 区块头、出块矿工的标签和交易 id
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct BlockInfo {
+    pub hash: String,
+    pub height: i32,
+    pub timestamp: u128,
+    pub prev_block_hash: String,
+    pub coinbase_tag: Option<String>,
+    pub txids: Vec<String>,
+}
+
+impl BlockInfo {
+    pub fn new(block: &Block) -> BlockInfo {
+        BlockInfo {
+            hash: block.get_hash(),
+            height: block.get_height(),
+            timestamp: block.get_timestamp(),
+            prev_block_hash: block.get_prev_hash(),
+            coinbase_tag: block.coinbase_tag(),
+            txids: block
+                .get_transaction()
+                .iter()
+                .map(|tx| tx.id.clone())
+                .collect(),
+        }
+    }
+}
+
+// 区块统计: 费率为每 1000 字节的手续费，不含 coinbase 交易；subsidy 为 coinbase 交易的产出
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct BlockStats {
+    pub hash: String,
+    pub height: i32,
+    pub timestamp: u128,
+    pub tx_count: usize,
+    pub size: usize,
+    pub total_fee: i32,
+    pub min_fee_rate: i32,
+    pub median_fee_rate: i32,
+    pub max_fee_rate: i32,
+    pub subsidy: i32,
+}
+
+// 高度范围内的区块统计，平均出块间隔单位为秒
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ChainStats {
+    pub from_height: i32,
+    pub to_height: i32,
+    pub block_count: usize,
+    pub tx_count: usize,
+    pub total_size: usize,
+    pub total_fee: i32,
+    pub total_subsidy: i32,
+    pub min_fee_rate: i32,
+    pub median_fee_rate: i32,
+    pub max_fee_rate: i32,
+    pub avg_block_interval: u64,
+}
+
+// 一组连续区块的统计: 出块间隔为区块与父区块的时间戳之差(秒)，
+// 难度为区块工作量的平均值，交易数和吞吐量不含 coinbase 交易
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct WindowStats {
+    pub from_height: i32,
+    pub to_height: i32,
+    pub avg_block_interval: f64,
+    pub median_block_interval: f64,
+    pub difficulty: u128,
+    pub tx_count: usize,
+    pub tx_per_sec: f64,
+    pub total_fee: i32,
+}
+
+// 交易池准入检查结果: 拒绝时给出违反的规则，接受时给出费率
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct MempoolAccept {
+    pub txid: String,
+    pub allowed: bool,
+    pub reject_reason: Option<String>,
+    pub fee_rate: Option<i32>,
+}
+
+// 软分叉部署状态
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SoftforkInfo {
+    pub name: String,
+    pub bit: u8,
+    pub state: ThresholdState,
+}
+
+// 交易及其确认情况，交易池中的交易 block_hash 为 None
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct TransactionInfo {
+    pub transaction: Transaction,
+    pub block_hash: Option<String>,
+    pub confirmations: i32,
+}
+
+// 可花费输出: 总额, 交易 id -> 输出序号
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Unspent {
+    pub accumulated: i32,
+    pub outputs: HashMap<String, Vec<i32>>,
+}
+
+/**
+ * @desc 本机当前网络默认端口上的节点，没有指定 RPC 节点时使用
+ */
+pub fn default_node() -> String {
+    format!("localhost:{}", network::params().default_port)
+}
+
+/**
+ * @desc 向节点发送 RPC 请求并等待响应
+ */
+/**
+ * @desc 本机正在运行的节点的 RPC 地址，没有记录或连接不上时为 None
+ */
+pub fn local_node(credentials: Option<(String, String)>) -> Option<String> {
+    let addr = fs::read_to_string(network::data_path(LOCAL_NODE_FILE)).ok()?;
+    let addr = addr.trim();
+
+    // 认证失败也说明节点在运行
+    call(addr, credentials, RpcRequest::GetBlockchainInfo).ok()?;
+    Some(addr.to_string())
+}
+
+pub fn call(
+    addr: &str,
+    credentials: Option<(String, String)>,
+    request: RpcRequest,
+) -> Result<RpcResponse> {
+    let mut stream = TcpStream::connect(addr)?;
+
+    let call = RpcCall {
+        credentials,
+        request,
+    };
+    let data = serialize(&(cmd_to_bytes(RPC_CMD), call))?;
+    stream.write_all(&data)?;
+    stream.shutdown(Shutdown::Write)?;
+
+    let mut buffer = Vec::new();
+    stream.read_to_end(&mut buffer)?;
+
+    match deserialize(&buffer)? {
+        RpcResponse::Error(e) => Err(format_err!("RPC error: {}", e)),
+        response => Ok(response),
+    }
+}
+
+// 通过 RPC 访问的节点，供不保存区块链数据的钱包进程使用
+pub struct RemoteNode {
+    addr: String,
+    credentials: Option<(String, String)>,
+}
+
+impl RemoteNode {
+    pub fn new(addr: &str, credentials: Option<(String, String)>) -> RemoteNode {
+        RemoteNode {
+            addr: addr.to_string(),
+            credentials,
+        }
+    }
+
+    fn call(&self, request: RpcRequest) -> Result<RpcResponse> {
+        call(&self.addr, self.credentials.clone(), request)
+    }
+
+    pub fn send_raw_transaction(&self, tx: &Transaction) -> Result<String> {
+        match self.call(RpcRequest::SendRawTransaction(tx.clone()))? {
+            RpcResponse::TxId(id) => Ok(id),
+            response => Err(format_err!("Unexpected RPC response: {:?}", response)),
+        }
+    }
+
+    /**
+     * @desc 提交区块，返回区块 hash
+     */
+    pub fn submit_block(&self, block: &Block) -> Result<String> {
+        match self.call(RpcRequest::SubmitBlock(block.clone()))? {
+            RpcResponse::BlockHash(hash) => Ok(hash),
+            response => Err(format_err!("Unexpected RPC response: {:?}", response)),
+        }
+    }
+
+    pub fn submit_alert(&self, alert: &Alert) -> Result<u32> {
+        match self.call(RpcRequest::SubmitAlert(alert.clone()))? {
+            RpcResponse::AlertId(id) => Ok(id),
+            response => Err(format_err!("Unexpected RPC response: {:?}", response)),
+        }
+    }
+
+    pub fn submit_psbt(&self, psbt: &Psbt) -> Result<String> {
+        match self.call(RpcRequest::SubmitPsbt(psbt.clone()))? {
+            RpcResponse::TxId(id) => Ok(id),
+            response => Err(format_err!("Unexpected RPC response: {:?}", response)),
+        }
+    }
+
+    pub fn abandon_transaction(&self, txid: &str) -> Result<Vec<String>> {
+        match self.call(RpcRequest::AbandonTransaction(txid.to_string()))? {
+            RpcResponse::TxIds(ids) => Ok(ids),
+            response => Err(format_err!("Unexpected RPC response: {:?}", response)),
+        }
+    }
+
+    pub fn get_wallet_conflicts(
+        &self,
+        txs: &[Transaction],
+    ) -> Result<HashMap<String, Vec<String>>> {
+        match self.call(RpcRequest::GetWalletConflicts(txs.to_vec()))? {
+            RpcResponse::Conflicts(conflicts) => Ok(conflicts),
+            response => Err(format_err!("Unexpected RPC response: {:?}", response)),
+        }
+    }
+
+    pub fn get_chain_history(
+        &self,
+        from: i32,
+        to: Option<i32>,
+        window: i32,
+    ) -> Result<Vec<WindowStats>> {
+        match self.call(RpcRequest::GetChainHistory { from, to, window })? {
+            RpcResponse::ChainHistory(windows) => Ok(windows),
+            response => Err(format_err!("Unexpected RPC response: {:?}", response)),
+        }
+    }
+
+    pub fn get_fork_stats(&self) -> Result<ForkStats> {
+        match self.call(RpcRequest::GetForkStats)? {
+            RpcResponse::ForkStats(stats) => Ok(stats),
+            response => Err(format_err!("Unexpected RPC response: {:?}", response)),
+        }
+    }
+
+    /**
+     * @desc 设置忽略的节点，返回当前被忽略的节点
+     */
+    pub fn simulate_partition(&self, peers: &[String]) -> Result<Vec<String>> {
+        match self.call(RpcRequest::SimulatePartition(peers.to_vec()))? {
+            RpcResponse::Partitioned(peers) => Ok(peers),
+            response => Err(format_err!("Unexpected RPC response: {:?}", response)),
+        }
+    }
+
+    pub fn get_blockchain_info(&self) -> Result<BlockchainInfo> {
+        match self.call(RpcRequest::GetBlockchainInfo)? {
+            RpcResponse::BlockchainInfo(info) => Ok(info),
+            response => Err(format_err!("Unexpected RPC response: {:?}", response)),
+        }
+    }
+
+    /**
+     * @desc 查询交易及其确认情况
+     */
+    pub fn get_transaction(&self, txid: &str) -> Result<TransactionInfo> {
+        match self.call(RpcRequest::GetTransaction(txid.to_string()))? {
+            RpcResponse::Transaction(info) => Ok(info),
+            response => Err(format_err!("Unexpected RPC response: {:?}", response)),
+        }
+    }
+
+    /**
+     * @desc 按高度或 hash 获取区块
+     */
+    pub fn get_block(&self, block: &str) -> Result<Block> {
+        match self.call(RpcRequest::GetBlock(block.to_string()))? {
+            RpcResponse::Block(block) => Ok(block),
+            response => Err(format_err!("Unexpected RPC response: {:?}", response)),
+        }
+    }
+
+    /**
+     * @desc 检查交易能否进入节点的交易池
+     */
+    pub fn test_mempool_accept(&self, tx: &Transaction) -> Result<MempoolAccept> {
+        match self.call(RpcRequest::TestMempoolAccept(tx.clone()))? {
+            RpcResponse::MempoolAccept(result) => Ok(result),
+            response => Err(format_err!("Unexpected RPC response: {:?}", response)),
+        }
+    }
+
+    /**
+     * @desc 余额不为 0 的全部地址和余额
+     */
+    pub fn list_address_balances(&self) -> Result<Vec<(String, i32)>> {
+        match self.call(RpcRequest::ListAddressBalances)? {
+            RpcResponse::AddressBalances(balances) => Ok(balances),
+            response => Err(format_err!("Unexpected RPC response: {:?}", response)),
+        }
+    }
+
+    /**
+     * @desc 查询名称当前映射的地址
+     */
+    pub fn resolve_name(&self, name: &str) -> Result<Option<NameClaim>> {
+        match self.call(RpcRequest::ResolveName(name.to_string()))? {
+            RpcResponse::Name(claim) => Ok(claim),
+            response => Err(format_err!("Unexpected RPC response: {:?}", response)),
+        }
+    }
+
+    /**
+     * @desc 统计提案的投票
+     */
+    pub fn tally_proposal(&self, proposal: &str) -> Result<Tally> {
+        match self.call(RpcRequest::TallyProposal(proposal.to_string()))? {
+            RpcResponse::Tally(tally) => Ok(tally),
+            response => Err(format_err!("Unexpected RPC response: {:?}", response)),
+        }
+    }
+
+    /**
+     * @desc 等待节点出现新的最新区块，超时返回当前最新区块头
+     */
+    pub fn wait_for_block(&self, tip: &str, timeout_secs: u64) -> Result<BlockHeader> {
+        match self.call(RpcRequest::WaitForBlock {
+            tip: tip.to_string(),
+            timeout_secs,
+        })? {
+            RpcResponse::BlockHeader(header) => Ok(header),
+            response => Err(format_err!("Unexpected RPC response: {:?}", response)),
+        }
+    }
+}
+
+impl UtxoProvider for RemoteNode {
+    fn find_spendable_outputs(
+        &self,
+        pub_key_hash: &[u8],
+        amount: i32,
+    ) -> Result<(i32, HashMap<String, Vec<i32>>)> {
+        match self.call(RpcRequest::ListUnspent {
+            pub_key_hash: pub_key_hash.to_vec(),
+            amount,
+        })? {
+            RpcResponse::Unspent(unspent) => Ok((unspent.accumulated, unspent.outputs)),
+            response => Err(format_err!("Unexpected RPC response: {:?}", response)),
+        }
+    }
+
+    fn find_utxos(&self, pub_key_hash: &[u8]) -> Result<TXOutputs> {
+        match self.call(RpcRequest::ListUtxos(pub_key_hash.to_vec()))? {
+            RpcResponse::Utxos(utxos) => Ok(utxos),
+            response => Err(format_err!("Unexpected RPC response: {:?}", response)),
+        }
+    }
+
+    fn find_transaction(&self, id: &str) -> Result<Transaction> {
+        match self.call(RpcRequest::GetTransaction(id.to_string()))? {
+            RpcResponse::Transaction(info) => Ok(info.transaction),
+            response => Err(format_err!("Unexpected RPC response: {:?}", response)),
+        }
+    }
+}
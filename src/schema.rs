@@ -0,0 +1,137 @@
+// 数据库格式版本: 每个 sled 数据库的 meta 树记录其中各部分数据的格式版本，
+// 打开数据库时按版本顺序执行升级步骤，格式变化不需要用户删除数据目录
+
+use super::Result;
+use failure::format_err;
+use log::info;
+
+const META_TREE: &str = "meta";
+
+type MigrationFn<'a> = Box<dyn Fn(&sled::Db) -> Result<()> + 'a>;
+
+// 把数据从 from 版本升级到 from + 1 版本的步骤，格式不变的版本可以没有升级步骤
+pub struct Migration<'a> {
+    pub from: u32,
+    pub description: &'static str,
+    pub run: MigrationFn<'a>,
+}
+
+/**
+ * @desc 读取数据的格式版本，没有记录的是引入版本号之前的数据，版本为 0
+ */
+pub fn get_version(db: &sled::Db, name: &str) -> Result<u32> {
+    match db.open_tree(META_TREE)?.get(name)? {
+        Some(data) => {
+            let bytes: [u8; 4] = data
+                .as_ref()
+                .try_into()
+                .map_err(|_| format_err!("Invalid schema version of {}.", name))?;
+            Ok(u32::from_be_bytes(bytes))
+        }
+        None => Ok(0),
+    }
+}
+
+pub fn set_version(db: &sled::Db, name: &str, version: u32) -> Result<()> {
+    db.open_tree(META_TREE)?
+        .insert(name, &version.to_be_bytes())?;
+
+    Ok(())
+}
+
+/**
+ * @desc 把数据升级到 latest 版本，每完成一步记录一次版本，中途失败时下次从失败的步骤继续
+ *       新建的空数据库直接记录为 latest，比程序更新的版本拒绝打开
+ */
+pub fn upgrade(
+    db: &sled::Db,
+    name: &str,
+    latest: u32,
+    fresh: bool,
+    migrations: &[Migration],
+) -> Result<()> {
+    let mut version = get_version(db, name)?;
+    if version == latest {
+        return Ok(());
+    }
+    if version > latest {
+        return Err(format_err!(
+            "The {} database has version {}, newer than the supported version {}.",
+            name,
+            version,
+            latest
+        ));
+    }
+    if fresh {
+        return set_version(db, name, latest);
+    }
+
+    while version < latest {
+        if let Some(migration) = migrations.iter().find(|m| m.from == version) {
+            info!(
+                "Upgrading {} from version {}: {}",
+                name, version, migration.description
+            );
+            (migration.run)(db)?;
+        }
+        version += 1;
+        set_version(db, name, version)?;
+    }
+    db.flush()?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::cell::RefCell;
+
+    #[test]
+    fn test_upgrade() {
+        let db = sled::Config::new().temporary(true).open().unwrap();
+        let steps = RefCell::new(Vec::new());
+        let migrations = [
+            Migration {
+                from: 0,
+                description: "first",
+                run: Box::new(|_| {
+                    steps.borrow_mut().push(0);
+                    Ok(())
+                }),
+            },
+            Migration {
+                from: 2,
+                description: "third",
+                run: Box::new(|_| {
+                    steps.borrow_mut().push(2);
+                    Ok(())
+                }),
+            },
+        ];
+
+        // 新建的数据库不执行升级步骤
+        upgrade(&db, "fresh", 3, true, &migrations).unwrap();
+        assert_eq!(get_version(&db, "fresh").unwrap(), 3);
+        assert!(steps.borrow().is_empty());
+
+        // 旧数据依次执行升级步骤，已经是最新版本时不再执行
+        assert_eq!(get_version(&db, "old").unwrap(), 0);
+        upgrade(&db, "old", 3, false, &migrations).unwrap();
+        upgrade(&db, "old", 3, false, &migrations).unwrap();
+        assert_eq!(get_version(&db, "old").unwrap(), 3);
+        assert_eq!(*steps.borrow(), vec![0, 2]);
+
+        set_version(&db, "newer", 4).unwrap();
+        assert!(upgrade(&db, "newer", 3, false, &migrations).is_err());
+
+        // 失败的步骤之前的版本已经记录
+        let failing = [Migration {
+            from: 1,
+            description: "fails",
+            run: Box::new(|_| Err(format_err!("failed"))),
+        }];
+        assert!(upgrade(&db, "partial", 2, false, &failing).is_err());
+        assert_eq!(get_version(&db, "partial").unwrap(), 1);
+    }
+}
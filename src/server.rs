@@ -1,67 +1,319 @@
 use std::{
-    collections::{HashMap, HashSet},
+    collections::{BTreeMap, HashMap, HashSet, VecDeque},
     io::prelude::{Read, Write},
-    net::{TcpListener, TcpStream},
-    sync::{Arc, Mutex},
+    net::{IpAddr, Ipv4Addr, SocketAddrV4, TcpListener, TcpStream, UdpSocket},
+    sync::{Arc, Mutex, RwLock},
     thread,
-    time::Duration,
+    time::{Duration, Instant, SystemTime},
 };
 
 use super::Result;
-use crate::{block::Block, transaction::Transaction, utxo_set::UTXOSet};
+#[cfg(feature = "grpc")]
+use crate::grpc;
+use crate::{
+    addrbook::AddrBook,
+    alert::Alert,
+    auth::{parse_basic_auth, Permission, RpcAuth},
+    bandwidth::{UploadLimiter, UPLOAD_CHUNK_SIZE},
+    block::{Block, BlockHeader, CompactBlock},
+    blockchain::{check_tx_size, tx_fee, Blockchain},
+    consolidate::ConsolidationPolicy,
+    identity::{self, NodeIdentity},
+    mdns, mining,
+    names::NameRecord,
+    natpmp,
+    network::{self, Network},
+    proxy,
+    psbt::Psbt,
+    rest,
+    reward::RewardSplit,
+    rpc::{
+        BlockchainInfo, Direction, ForkStats, HandshakeState, MempoolAccept, NetTotals,
+        NetworkInfo, PeerInfo, RpcCall, RpcRequest, RpcResponse, SoftforkInfo, SyncState,
+        SyncStatus, TransactionInfo, Unspent, RPC_CMD,
+    },
+    sigverify, stats, timedata, tor,
+    transaction::{self, encode_hex, Transaction},
+    utxo_set::{UTXOSet, UtxoProvider},
+    versionbits, wallets,
+};
 use bincode::{deserialize, serialize};
 use failure::format_err;
-use log::{debug, info};
+use log::{debug, error, info, warn};
+use rand::{rngs::StdRng, seq::IteratorRandom, thread_rng, Rng, SeedableRng};
 use serde::{Deserialize, Serialize};
 
 // 消息
 #[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
-struct VersionMsg {
+pub struct VersionMsg {
     addr_from: String,
     version: i32,
     best_height: i32,
+    timestamp: u128,
+    // 网络标识，不同创世配置的私有网络之间拒绝连接
+    magic: [u8; 4],
+    // 是否是对收到的版本信息的回复，对方不会再回复版本信息
+    reply: bool,
+    // 发送方的身份公钥和对以上字段的签名
+    identity: Vec<u8>,
+    signature: Vec<u8>,
+}
+
+impl VersionMsg {
+    fn signed_data(&self) -> Result<Vec<u8>> {
+        Ok(serialize(&(
+            &self.addr_from,
+            self.version,
+            self.best_height,
+            self.timestamp,
+            self.magic,
+            self.reply,
+            &self.identity,
+        ))?)
+    }
+}
+
+// 确认收到对方的版本信息
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct VerackMsg {
+    addr_from: String,
 }
 
+// 已知节点地址，由发送方的身份签名
 #[derive(Deserialize, Serialize, Debug, Clone)]
-struct TxMsg {
+pub struct AddrMsg {
+    addr_from: String,
+    nodes: Vec<String>,
+    identity: Vec<u8>,
+    signature: Vec<u8>,
+}
+
+impl AddrMsg {
+    fn signed_data(&self) -> Result<Vec<u8>> {
+        Ok(serialize(&(&self.addr_from, &self.nodes, &self.identity))?)
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct TxMsg {
     addr_from: String,
     transaction: Transaction,
 }
 
 #[derive(Deserialize, Serialize, Debug, Clone)]
-struct GetDataMsg {
+pub struct GetDataMsg {
+    addr_from: String,
+    kind: String,
+    id: String,
+}
+
+// 对方没有请求的数据
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct NotFoundMsg {
     addr_from: String,
     kind: String,
     id: String,
 }
 
 #[derive(Deserialize, Serialize, Debug, Clone)]
-struct GetBlockMsg {
+pub struct GetBlockMsg {
     addr_from: String,
+    locator: Vec<String>,
 }
 
 #[derive(Deserialize, Serialize, Debug, Clone)]
-struct InvMsg {
+pub struct InvMsg {
     addr_from: String,
     kind: String,
     items: Vec<String>,
 }
 
 #[derive(Deserialize, Serialize, Debug, Clone)]
-struct BlockMsg {
+pub struct BlockMsg {
     addr_from: String,
     block: Block,
 }
 
 #[derive(Deserialize, Serialize, Debug, Clone)]
-enum Message {
-    Addr(Vec<String>),
+pub struct CmpctBlockMsg {
+    addr_from: String,
+    block: CompactBlock,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct GetBlockTxnMsg {
+    addr_from: String,
+    block_hash: String,
+    indexes: Vec<usize>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct BlockTxnMsg {
+    addr_from: String,
+    block_hash: String,
+    transactions: Vec<Transaction>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct FeeFilterMsg {
+    addr_from: String,
+    fee_rate: i32,
+}
+
+// 请求对方公告交易池中的交易
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct MempoolMsg {
+    addr_from: String,
+}
+
+// 运维公告
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct AlertMsg {
+    addr_from: String,
+    alert: Alert,
+}
+
+// 多签签名方之间交换的部分签名交易
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct PsbtMsg {
+    addr_from: String,
+    psbt: Psbt,
+}
+
+// 节点间消息，按命令名解析
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub enum Message {
+    Addr(AddrMsg),
     Version(VersionMsg),
+    Verack(VerackMsg),
     Tx(TxMsg),
     GetData(GetDataMsg),
+    NotFound(NotFoundMsg),
     GetBlock(GetBlockMsg),
     Inv(InvMsg),
     Block(BlockMsg),
+    CmpctBlock(CmpctBlockMsg),
+    GetBlockTxn(GetBlockTxnMsg),
+    BlockTxn(BlockTxnMsg),
+    FeeFilter(FeeFilterMsg),
+    Mempool(MempoolMsg),
+    Alert(AlertMsg),
+    Psbt(PsbtMsg),
+    StemTx(TxMsg),
+    Rpc(RpcCall),
+}
+
+impl Message {
+    /**
+     * @desc 获取消息发送方地址
+     */
+    fn addr_from(&self) -> Option<&str> {
+        match self {
+            Message::Rpc(_) => None,
+            Message::Addr(m) => Some(&m.addr_from),
+            Message::Version(m) => Some(&m.addr_from),
+            Message::Verack(m) => Some(&m.addr_from),
+            Message::Tx(m) | Message::StemTx(m) => Some(&m.addr_from),
+            Message::GetData(m) => Some(&m.addr_from),
+            Message::NotFound(m) => Some(&m.addr_from),
+            Message::GetBlock(m) => Some(&m.addr_from),
+            Message::Inv(m) => Some(&m.addr_from),
+            Message::Block(m) => Some(&m.addr_from),
+            Message::CmpctBlock(m) => Some(&m.addr_from),
+            Message::GetBlockTxn(m) => Some(&m.addr_from),
+            Message::BlockTxn(m) => Some(&m.addr_from),
+            Message::FeeFilter(m) => Some(&m.addr_from),
+            Message::Mempool(m) => Some(&m.addr_from),
+            Message::Alert(m) => Some(&m.addr_from),
+            Message::Psbt(m) => Some(&m.addr_from),
+        }
+    }
+}
+
+// 最近见过的库存(区块/交易哈希)及发来或公告过它的节点，超出容量时淘汰最久未使用的
+struct InventoryCache {
+    capacity: usize,
+    items: HashMap<String, HashSet<String>>,
+    order: VecDeque<String>,
+}
+
+impl InventoryCache {
+    fn new(capacity: usize) -> InventoryCache {
+        InventoryCache {
+            capacity,
+            items: HashMap::new(),
+            order: VecDeque::new(),
+        }
+    }
+
+    fn contains(&self, item: &str) -> bool {
+        self.items.contains_key(item)
+    }
+
+    /**
+     * @desc 节点是否发来或公告过该库存
+     */
+    fn is_known_by(&self, item: &str, peer: &str) -> bool {
+        match self.items.get(item) {
+            Some(peers) => peers.contains(peer),
+            None => false,
+        }
+    }
+
+    fn insert_from(&mut self, item: &str, peer: &str) {
+        self.insert(item);
+        if let Some(peers) = self.items.get_mut(item) {
+            peers.insert(peer.to_string());
+        }
+    }
+
+    fn clear(&mut self) {
+        self.items.clear();
+        self.order.clear();
+    }
+
+    fn insert(&mut self, item: &str) {
+        if self.items.contains_key(item) {
+            self.order.retain(|i| i != item);
+        } else {
+            self.items.insert(item.to_string(), HashSet::new());
+        }
+        self.order.push_back(item.to_string());
+
+        while self.order.len() > self.capacity {
+            if let Some(oldest) = self.order.pop_front() {
+                self.items.remove(&oldest);
+            }
+        }
+    }
+}
+
+// 交易池中未确认交易链的限制: 祖先和后代的数量(包含交易本身)及总大小(字节)
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MempoolLimits {
+    pub max_ancestors: usize,
+    pub max_ancestor_size: usize,
+    pub max_descendants: usize,
+    pub max_descendant_size: usize,
+}
+
+impl Default for MempoolLimits {
+    fn default() -> Self {
+        MempoolLimits {
+            max_ancestors: 25,
+            max_ancestor_size: 101_000,
+            max_descendants: 25,
+            max_descendant_size: 101_000,
+        }
+    }
+}
+
+// 已发出还没有收到的数据请求，tried 是已经请求过的节点
+struct DataRequest {
+    kind: String,
+    peer: String,
+    requested_at: u128,
+    tried: HashSet<String>,
 }
 
 // 服务
@@ -69,267 +321,2195 @@ struct ServerInner {
     known_nodes: HashSet<String>,
     utxo: UTXOSet,
     blocks_in_transit: Vec<String>,
+    requests_in_flight: HashMap<String, DataRequest>,
+    sync_has_more: bool,
     mempool: HashMap<String, Transaction>,
+    // 交易进入交易池的时间(毫秒)
+    mempool_times: HashMap<String, u128>,
+    mempool_expiry: u64,
+    known_inventory: InventoryCache,
+    // 最近验证失败的区块和交易，最新区块改变时清空(缺少的父区块或输入可能已经到达)
+    recent_rejects: InventoryCache,
+    pending_inv: HashMap<(String, String), Vec<String>>,
+    // 每个节点下一次发送交易公告的时间(毫秒)
+    next_tx_inv: HashMap<String, u128>,
+    // 等待缺失交易的紧凑区块: 区块 hash -> (紧凑区块, 已填充的交易, 收到的时间(毫秒))
+    partial_blocks: HashMap<String, (CompactBlock, Vec<Option<Transaction>>, u128)>,
+    min_relay_fee_rate: i32,
+    mempool_limits: MempoolLimits,
+    peer_fee_filters: HashMap<String, i32>,
+    peers: HashMap<String, PeerInfo>,
+    // 按消息类型累计的发送和接收字节数
+    sent_per_msg: BTreeMap<String, u64>,
+    recv_per_msg: BTreeMap<String, u64>,
+    upload_limiter: Option<UploadLimiter>,
+    sync_state: SyncState,
+    target_height: i32,
+    sync_started: (u128, i32),
+    last_block_time: u128,
+    stale_tip_timeout: u64,
+    proxy: Option<String>,
+    external_address: Option<String>,
+    onion_address: Option<String>,
+    // 有洋葱节点可选时只选洋葱节点作为交易主干和数据请求的对象
+    prefer_onion: bool,
+    whitelist: HashSet<String>,
+    stempool: HashMap<String, (Transaction, u128)>,
+    stem_peer: Option<(String, u128)>,
+    rpc_auth: RpcAuth,
+    // 生成 coinbase 交易随机数据的随机数生成器，可以设置固定种子
+    rng: StdRng,
+    // 自动归集挖矿地址的小额输出，None 表示不归集
+    consolidation: Option<ConsolidationPolicy>,
+    // 挖矿收款的命名钱包，每个区块的奖励支付到从它新派生的地址，None 时支付到固定的挖矿地址
+    mining_wallet: Option<String>,
+    // 写在挖出的区块 coinbase 交易中的矿工标签
+    coinbase_tag: Option<String>,
+    // 出块奖励分成，为空时奖励全部归矿工
+    reward_split: RewardSplit,
+    identity: NodeIdentity,
+    // 节点地址 -> 身份公钥，包括固定的可信节点和第一次收到签名消息时记录的节点
+    peer_identities: HashMap<String, Vec<u8>>,
+    // 公告公钥，没有配置时不接受公告
+    alert_key: Option<Vec<u8>>,
+    alerts: HashMap<u32, Alert>,
+    fork_stats: ForkStats,
+    // 模拟网络分区时忽略的节点，不向它们发送也不处理它们的消息
+    partitioned: HashSet<String>,
+    // 持久保存的节点地址表，None 表示不保存(只发送消息的临时节点)
+    addr_book: Option<AddrBook>,
+    // 正在握手的节点 -> 握手开始时间(毫秒)，握手完成或超时后删除
+    handshake_started: HashMap<String, u128>,
 }
 pub struct Server {
     node_address: String,
     mining_address: String,
+    // 区块链句柄，查询区块链不需要获取 inner 的锁
+    chain: Blockchain,
     inner: Arc<Mutex<ServerInner>>,
 }
 
-const KNOWN_NODE_01: &str = "localhost: 3000";
 const CMD_LEN: usize = 12;
-const VERSION: i32 = 1;
+// 配置的引导节点，None 时使用本机当前网络的默认端口
+static BOOTSTRAP_NODE: RwLock<Option<String>> = RwLock::new(None);
+// 协议版本 2: 交易加入 version 和 relative_lock_time 字段
+// 协议版本 3: version 消息加入网络标识 magic
+// 协议版本 4: version 和 addr 消息加入节点身份签名
+// 协议版本 5: 收到 version 后回复 verack，握手完成前不处理链消息
+pub const VERSION: i32 = 5;
+const MAX_BLOCKS_PER_INV: usize = 500;
+const INVENTORY_CACHE_SIZE: usize = 5000;
+const RECENT_REJECTS_SIZE: usize = 5000;
+const INV_BATCH_INTERVAL: u64 = 500;
+const MAX_TX_INV_PER_ROUND: usize = 100;
+// 交易公告的平均随机间隔(毫秒)，每个节点的发送时间互相错开，避免公告风暴
+const TX_INV_TRICKLE_INTERVAL: u64 = 2000;
+// 握手后向高度相同的节点公告的最近区块数
+const BACKLOG_BLOCKS: i32 = 16;
+const DEFAULT_MIN_RELAY_FEE_RATE: i32 = 0;
+const DEFAULT_STALE_TIP_TIMEOUT: u64 = 600;
+const DEFAULT_MEMPOOL_EXPIRY: u64 = 336;
+const STALE_TIP_CHECK_INTERVAL: u64 = 10;
+const WAIT_FOR_BLOCK_INTERVAL: u64 = 200;
+const WHITELIST_RECONNECT_INTERVAL: u64 = 60;
+const REBROADCAST_INTERVAL: u64 = 60;
+// 局域网发现的公告间隔(秒)
+const LAN_ANNOUNCE_INTERVAL: u64 = 30;
+// 握手的超时时间(秒)，超时后回到未握手状态并计入封禁分数
+const HANDSHAKE_TIMEOUT: u64 = 10;
+// 握手进行中时收到链消息，最多等待握手完成的时间(毫秒)；每条消息单独连接、并发处理，
+// 对方握手完成后立即发出的链消息可能先于它的 verack 被处理
+const HANDSHAKE_WAIT: u64 = 2000;
+const HANDSHAKE_POLL_INTERVAL: u64 = 20;
+// 握手完成前不发送也不处理的链消息
+const CHAIN_MESSAGES: [&str; 9] = [
+    "block",
+    "cmpct_block",
+    "get_blocktxn",
+    "block_txn",
+    "inv",
+    "get_blocks",
+    "get_data",
+    "not_found",
+    "mempool",
+];
+// 数据请求的超时时间(秒)，超时后向其他节点重新请求
+const GETDATA_TIMEOUT: u64 = 30;
+// 同时等待缺失交易的紧凑区块上限和等待时间(秒)，超时未补全的区块被丢弃
+const MAX_PARTIAL_BLOCKS: usize = 16;
+const PARTIAL_BLOCK_TIMEOUT: u64 = 30;
+const FLUFF_PROBABILITY: f64 = 0.1;
+const STEM_EPOCH: u64 = 600;
+const STEM_EMBARGO: u64 = 30;
+// 消息除区块外的额外开销上限(命令、地址等字段)
+const MESSAGE_OVERHEAD: usize = 1024;
 
 impl Server {
     pub fn new(port: &str, miner_address: &str, utxo: UTXOSet) -> Result<Server> {
+        Server::with_host("localhost", port, miner_address, utxo)
+    }
+
+    /**
+     * @desc 以 host 作为节点地址创建服务，host 不是 localhost 时监听所有网卡，其他机器上的节点可以连接
+     */
+    pub fn with_host(host: &str, port: &str, miner_address: &str, utxo: UTXOSet) -> Result<Server> {
         let mut node_set = HashSet::new();
-        node_set.insert(String::from(KNOWN_NODE_01));
+        node_set.insert(bootstrap_node());
+        let mut rng = StdRng::from_entropy();
+        let identity = NodeIdentity::new(&mut rng);
 
         Ok(Server {
-            node_address: String::from("localhost:") + port,
+            node_address: format!("{}:{}", host, port),
             mining_address: miner_address.to_string(),
+            chain: utxo.blockchain.clone(),
             inner: Arc::new(Mutex::new(ServerInner {
                 known_nodes: node_set,
                 utxo,
                 blocks_in_transit: Vec::new(),
+                requests_in_flight: HashMap::new(),
+                sync_has_more: false,
                 mempool: HashMap::new(),
+                mempool_times: HashMap::new(),
+                mempool_expiry: DEFAULT_MEMPOOL_EXPIRY,
+                known_inventory: InventoryCache::new(INVENTORY_CACHE_SIZE),
+                recent_rejects: InventoryCache::new(RECENT_REJECTS_SIZE),
+                pending_inv: HashMap::new(),
+                next_tx_inv: HashMap::new(),
+                partial_blocks: HashMap::new(),
+                min_relay_fee_rate: DEFAULT_MIN_RELAY_FEE_RATE,
+                mempool_limits: MempoolLimits::default(),
+                peer_fee_filters: HashMap::new(),
+                peers: HashMap::new(),
+                sent_per_msg: BTreeMap::new(),
+                recv_per_msg: BTreeMap::new(),
+                upload_limiter: None,
+                sync_state: SyncState::HeadersSyncing,
+                target_height: -1,
+                sync_started: (now_millis(), -1),
+                last_block_time: now_millis(),
+                stale_tip_timeout: DEFAULT_STALE_TIP_TIMEOUT,
+                proxy: None,
+                external_address: None,
+                onion_address: None,
+                prefer_onion: false,
+                whitelist: HashSet::new(),
+                stempool: HashMap::new(),
+                stem_peer: None,
+                rpc_auth: RpcAuth::default(),
+                rng,
+                consolidation: None,
+                mining_wallet: None,
+                coinbase_tag: None,
+                reward_split: RewardSplit::default(),
+                identity,
+                peer_identities: HashMap::new(),
+                alert_key: None,
+                alerts: HashMap::new(),
+                fork_stats: ForkStats::default(),
+                partitioned: HashSet::new(),
+                addr_book: None,
+                handshake_started: HashMap::new(),
             })),
         })
     }
 
-    pub fn start_server(&self) -> Result<()> {
-        let server_01 = Server {
-            node_address: self.node_address.clone(),
-            mining_address: self.mining_address.clone(),
-            inner: Arc::clone(&self.inner),
-        };
-
-        info!(
-            "Start server at {}, mining address: {}.",
-            &self.node_address, &self.mining_address
-        );
+    /**
+     * @desc 设置最低转发费率(每 1000 字节的手续费)，低于该费率的交易不会被接受和转发
+     */
+    pub fn set_min_relay_fee_rate(&self, fee_rate: i32) {
+        self.inner.lock().unwrap().min_relay_fee_rate = fee_rate;
+    }
 
-        thread::spawn(move || {
-            thread::sleep(Duration::from_millis(1000));
+    /**
+     * @desc 设置交易池中未确认交易链的祖先和后代限制
+     */
+    pub fn set_mempool_limits(&self, limits: MempoolLimits) {
+        self.inner.lock().unwrap().mempool_limits = limits;
+    }
 
-            if server_01.get_best_height()? == -1 {
-                server_01.request_blocks()
-            } else {
-                server_01.send_version(KNOWN_NODE_01)
-            }
-        });
+    /**
+     * @desc 设置交易在交易池中的过期时间(小时)，超时仍未确认的交易和它的后代被移出交易池
+     */
+    pub fn set_mempool_expiry(&self, hours: u64) {
+        self.inner.lock().unwrap().mempool_expiry = hours;
+    }
 
-        let listener = TcpListener::bind(&self.node_address).unwrap();
-        info!("Server listen...");
+    /**
+     * @desc 设置随机数种子，相同的种子挖出的 coinbase 交易相同，用于可复现的演示和测试
+     */
+    pub fn set_rng_seed(&self, seed: u64) {
+        self.inner.lock().unwrap().rng = StdRng::seed_from_u64(seed);
+    }
 
-        for stream in listener.incoming() {
-            let stream = stream?;
-            let server_01 = Server {
-                node_address: self.node_address.clone(),
-                mining_address: self.mining_address.clone(),
-                inner: Arc::clone(&self.inner),
-            };
+    /**
+     * @desc 开启自动归集，矿工节点在低费率时段把挖矿地址的小额出块奖励归集成一个输出
+     */
+    pub fn set_consolidation(&self, policy: ConsolidationPolicy) {
+        self.inner.lock().unwrap().consolidation = Some(policy);
+    }
 
-            thread::spawn(move || server_01.handle_connection(stream));
-        }
+    /**
+     * @desc 按命名钱包挖矿: 每个区块的奖励支付到从钱包新派生的地址，不再重复使用同一个地址
+     */
+    pub fn set_mining_wallet(&self, name: &str) {
+        info!("Mining to fresh addresses of wallet {}.", name);
+        self.inner.lock().unwrap().mining_wallet = Some(name.to_string());
+    }
 
+    /**
+     * @desc 设置矿工标签，挖出的区块在 coinbase 交易中带上标签，多个矿工的测试网络可以区分区块的来源
+     */
+    pub fn set_coinbase_tag(&self, tag: &str) -> Result<()> {
+        transaction::check_coinbase_tag(tag)?;
+        self.inner.lock().unwrap().coinbase_tag = Some(tag.to_string());
         Ok(())
     }
 
-    pub fn send_transaction(tx: &Transaction, utxoset: UTXOSet) -> Result<()> {
-        let server = Server::new("7000", "", utxoset)?;
-        server.send_tx(KNOWN_NODE_01, tx)?;
-
-        Ok(())
+    /**
+     * @desc 设置出块奖励分成，挖出的区块把奖励按百分比分给配置的地址，剩余部分归矿工
+     */
+    pub fn set_reward_split(&self, split: RewardSplit) {
+        self.inner.lock().unwrap().reward_split = split;
     }
 
-    fn get_best_height(&self) -> Result<i32> {
-        self.inner.lock().unwrap().utxo.blockchain.get_best_height()
+    fn is_miner(&self) -> bool {
+        !self.mining_address.is_empty() || self.inner.lock().unwrap().mining_wallet.is_some()
     }
 
-    fn get_known_nodes(&self) -> HashSet<String> {
-        self.inner.lock().unwrap().known_nodes.clone()
+    /**
+     * @desc 下一个区块奖励的收款地址，按命名钱包挖矿时派生新地址并保存到钱包
+     */
+    fn next_payout_address(&self) -> Result<String> {
+        let name = match self.inner.lock().unwrap().mining_wallet.clone() {
+            Some(name) => name,
+            None => return Ok(self.mining_address.clone()),
+        };
+
+        let mut wlts = wallets::Wallets::new()?;
+        let address = wlts.new_address(&name)?;
+        wlts.save_all()?;
+
+        Ok(address)
     }
 
-    fn node_is_known(&self, addr: &str) -> bool {
-        self.inner.lock().unwrap().known_nodes.get(addr).is_some()
+    /**
+     * @desc 设置最新区块过期时间(秒)，超过该时间没有收到新区块时主动向所有节点重新同步
+     */
+    pub fn set_stale_tip_timeout(&self, secs: u64) {
+        self.inner.lock().unwrap().stale_tip_timeout = secs;
     }
 
-    fn add_nodes(&self, addr: &str) {
-        self.inner
-            .lock()
-            .unwrap()
-            .known_nodes
-            .insert(String::from(addr));
+    /**
+     * @desc 设置 SOCKS5 代理(host:port)，所有向外的节点连接都经过代理
+     */
+    pub fn set_proxy(&self, proxy: &str) {
+        self.inner.lock().unwrap().proxy = Some(proxy.to_string());
     }
 
-    fn remove_node(&self, addr: &str) {
-        self.inner.lock().unwrap().known_nodes.remove(addr);
+    /**
+     * @desc 设置节点身份，默认是不保存的临时身份
+     */
+    pub fn set_identity(&self, identity: NodeIdentity) {
+        self.inner.lock().unwrap().identity = identity;
     }
 
-    fn send_data(&self, addr: &str, data: &[u8]) -> Result<()> {
-        if addr == &self.node_address {
-            return Ok(());
+    /**
+     * @desc 固定可信节点的身份(地址, 公钥)，这些地址只接受对应身份签名的 version 和 addr 消息
+     */
+    pub fn set_trusted_nodes(&self, nodes: Vec<(String, Vec<u8>)>) {
+        let mut inner = self.inner.lock().unwrap();
+        for (addr, public_key) in nodes {
+            inner.known_nodes.insert(addr.clone());
+            inner.peer_identities.insert(addr, public_key);
         }
+    }
 
-        let mut stream = match TcpStream::connect(addr) {
-            Ok(s) => s,
-            Err(_) => {
-                self.remove_node(addr);
-                return Ok(());
-            }
-        };
-
-        stream.write(data)?;
-        info!("Data send successfully.");
+    /**
+     * @desc 设置公告公钥，只接受和转发由该公钥签名的公告
+     */
+    pub fn set_alert_key(&self, public_key: Vec<u8>) {
+        self.inner.lock().unwrap().alert_key = Some(public_key);
+    }
 
-        Ok(())
+    /**
+     * @desc 设置上传限速(字节/秒)，白名单节点不受限制
+     */
+    pub fn set_upload_limit(&self, rate: u64) {
+        self.inner.lock().unwrap().upload_limiter = Some(UploadLimiter::new(rate));
     }
 
-    pub fn send_tx(&self, addr: &str, tx: &Transaction) -> Result<()> {
-        info!("Send tx to: {} txid: {}.", addr, &tx.id);
+    /**
+     * @desc 通过 NAT-PMP 在默认网关上映射监听端口，并定期续期；映射得到的外部地址会在 addr 消息中公告
+     */
+    pub fn enable_port_mapping(&self) -> Result<()> {
+        let gateway = natpmp::default_gateway()?;
+        let port = self.listen_port()?;
 
-        let data = TxMsg {
-            addr_from: self.node_address.clone(),
-            transaction: tx.clone(),
-        };
-        let data = serialize(&(cmd_to_bytes("tx"), data))?;
-        self.send_data(addr, &data)
-    }
+        let external = natpmp::map_port(gateway, port, natpmp::MAPPING_LIFETIME)?;
+        info!("Port mapped by NAT-PMP, external address: {}.", external);
+        self.inner.lock().unwrap().external_address = Some(external);
 
-    fn send_version(&self, addr: &str) -> Result<()> {
-        info!("Send version info to: {}.", addr);
+        let inner = Arc::clone(&self.inner);
+        thread::spawn(move || loop {
+            thread::sleep(Duration::from_secs(natpmp::MAPPING_LIFETIME as u64 / 2));
 
-        let data = VersionMsg {
-            addr_from: self.node_address.clone(),
-            best_height: self.get_best_height()?,
-            version: VERSION,
-        };
-        let data = serialize(&(cmd_to_bytes("version"), data))?;
-        self.send_data(addr, &data)
-    }
+            match natpmp::map_port(gateway, port, natpmp::MAPPING_LIFETIME) {
+                Ok(external) => inner.lock().unwrap().external_address = Some(external),
+                Err(e) => error!("Renew port mapping failed: {}", e),
+            }
+        });
 
-    fn send_inv(&self, addr: &str, kind: &str, items: Vec<String>) -> Result<()> {
-        info!(
-            "Send inv message to: {} kind: {} data: {:?}",
-            addr, kind, items
-        );
+        Ok(())
+    }
 
-        let data = InvMsg {
-            addr_from: self.node_address.clone(),
-            kind: kind.to_string(),
-            items,
+    /**
+     * @desc 开启局域网节点发现: 开始监听后查询并定期通过 mDNS 组播公告本节点，收到同一网络的其他节点公告后连接；
+     *       组播端口已被占用(例如同一台机器上的其他节点)时只公告不监听
+     */
+    pub fn enable_lan_discovery(&self, mdns_port: u16) -> Result<()> {
+        let ip: Ipv4Addr = match self.node_address.rsplit_once(':') {
+            Some((host, _)) => host
+                .parse()
+                .map_err(|_| format_err!("LAN discovery needs a LAN address, not {}.", host))?,
+            None => return Err(format_err!("Invalid node address.")),
         };
-        let data = serialize(&(cmd_to_bytes("inv"), data))?;
+        let instance = self.inner.lock().unwrap().identity.id()[..12].to_string();
+        let announcement = mdns::announcement(&instance, ip, self.listen_port()?, network::magic());
+        let group = SocketAddrV4::new(mdns::MDNS_ADDR, mdns_port);
 
-        self.send_data(addr, &data)
-    }
+        let sender = UdpSocket::bind("0.0.0.0:0")?;
+        info!("LAN discovery enabled, announce {} as {}.", ip, instance);
 
-    fn send_get_blocks(&self, addr: &str) -> Result<()> {
-        info!("Send get blocks message to: {}.", addr);
+        match UdpSocket::bind(("0.0.0.0", mdns_port)) {
+            Ok(socket) => {
+                socket.join_multicast_v4(&mdns::MDNS_ADDR, &Ipv4Addr::UNSPECIFIED)?;
+                let server = Server {
+                    node_address: self.node_address.clone(),
+                    mining_address: self.mining_address.clone(),
+                    chain: self.chain.clone(),
+                    inner: Arc::clone(&self.inner),
+                };
+                let announcement = announcement.clone();
+                thread::spawn(move || {
+                    if let Err(e) = server.listen_lan(&socket, group, &announcement) {
+                        error!("LAN discovery stopped: {}", e);
+                    }
+                });
+            }
+            Err(e) => warn!(
+                "Listen mDNS port {} failed: {}, only announce this node.",
+                mdns_port, e
+            ),
+        }
 
-        let data = GetBlockMsg {
-            addr_from: self.node_address.clone(),
-        };
-        let data = serialize(&(cmd_to_bytes("get_blocks"), data))?;
-        self.send_data(addr, &data)
-    }
+        // 等待开始监听后再查询和公告，其他节点收到后才能连接
+        thread::spawn(move || {
+            thread::sleep(Duration::from_millis(1000));
+            if let Err(e) = sender.send_to(&mdns::query(), group) {
+                error!("LAN query failed: {}", e);
+            }
 
-    fn send_get_data(&self, addr: &str, kind: &str, id: &str) -> Result<()> {
-        info!(
-            "Send get data message to: {} kind: {} id: {}.",
-            addr, kind, id
-        );
+            loop {
+                if let Err(e) = sender.send_to(&announcement, group) {
+                    error!("LAN announcement failed: {}", e);
+                }
+                thread::sleep(Duration::from_secs(LAN_ANNOUNCE_INTERVAL));
+            }
+        });
 
-        let data = GetDataMsg {
-            addr_from: self.node_address.clone(),
-            kind: kind.to_string(),
-            id: id.to_string(),
-        };
-        let data = serialize(&(cmd_to_bytes("get_data"), data))?;
-        self.send_data(addr, &data)
+        Ok(())
     }
 
-    fn send_block(&self, addr: &str, b: &Block) -> Result<()> {
-        info!("Send block data to: {} block hash: {}", addr, b.get_hash());
+    // 回答其他节点的查询，连接公告中同一网络的新节点
+    fn listen_lan(
+        &self,
+        socket: &UdpSocket,
+        group: SocketAddrV4,
+        announcement: &[u8],
+    ) -> Result<()> {
+        let magic = encode_hex(&network::magic());
+        let mut buf = [0; 4096];
+        loop {
+            let (len, _) = socket.recv_from(&mut buf)?;
+            let message = match mdns::parse(&buf[..len]) {
+                Ok(message) => message,
+                Err(_) => continue,
+            };
 
-        let data = BlockMsg {
-            addr_from: self.node_address.clone(),
-            block: b.clone(),
-        };
-        let data = serialize(&(cmd_to_bytes("block"), data))?;
-        self.send_data(addr, &data)
+            if message.is_query {
+                socket.send_to(announcement, group)?;
+            }
+            for (addr, peer_magic) in message.peers {
+                if peer_magic != magic || addr == self.node_address || self.node_is_known(&addr) {
+                    continue;
+                }
+                info!("Discover LAN node {}.", addr);
+                self.add_nodes(&addr);
+                self.send_version(&addr)?;
+            }
+        }
     }
 
-    fn send_addr(&self, addr: &str) -> Result<()> {
-        info!("Send address info to: {}.", addr);
-        let nodes = self.get_known_nodes();
-        let data = serialize(&(cmd_to_bytes("addr"), nodes))?;
+    /**
+     * @desc 通过 Tor 控制端口为监听端口创建洋葱服务，洋葱地址会在 addr 消息中公告；
+     *       控制连接断开时 Tor 删除该服务
+     */
+    pub fn enable_onion_service(&self, control: &str, password: Option<&str>) -> Result<()> {
+        let service = tor::add_onion(control, password, self.listen_port()?)?;
+        info!("Onion service created: {}.", service.address);
+        self.inner.lock().unwrap().onion_address = Some(service.address.clone());
 
-        self.send_data(addr, &data)
-    }
+        let inner = Arc::clone(&self.inner);
+        thread::spawn(move || {
+            service.wait();
+            error!("Tor control connection closed, onion service removed.");
+            inner.lock().unwrap().onion_address = None;
+        });
 
-    fn add_block(&self, block: Block) -> Result<()> {
-        self.inner.lock().unwrap().utxo.blockchain.add_block(block)
+        Ok(())
     }
 
-    fn mine_block(&self, txs: Vec<Transaction>) -> Result<Block> {
-        self.inner.lock().unwrap().utxo.blockchain.mine_block(txs)
+    /**
+     * @desc 优先使用洋葱节点，需要同时用 set_proxy 设置 Tor 的 SOCKS5 端口才能连接洋葱节点
+     */
+    pub fn set_prefer_onion(&self, prefer: bool) {
+        self.inner.lock().unwrap().prefer_onion = prefer;
     }
 
-    fn request_blocks(&self) -> Result<()> {
-        for node in self.get_known_nodes() {
-            self.send_get_blocks(&node)?
+    fn listen_port(&self) -> Result<u16> {
+        match self.node_address.rsplit_once(':') {
+            Some((_, port)) => Ok(port.parse()?),
+            None => Err(format_err!("Invalid node address.")),
         }
-
-        Ok(())
     }
 
-    fn get_block_hashes(&self) -> Vec<String> {
-        self.inner
-            .lock()
-            .unwrap()
-            .utxo
-            .blockchain
-            .get_block_hashes()
+    /**
+     * @desc 设置 RPC 和 REST 接口的认证方式
+     */
+    pub fn set_rpc_auth(&self, auth: RpcAuth) {
+        self.inner.lock().unwrap().rpc_auth = auth;
     }
 
-    fn get_block(&self, block_hash: &str) -> Result<Block> {
-        self.inner
-            .lock()
-            .unwrap()
-            .utxo
-            .blockchain
-            .get_block(block_hash)
-    }
+    /**
+     * @desc 在指定地址启动只读的 REST 接口(开启 explorer 特性时同时提供区块浏览器)
+     */
+    pub fn start_rest(&self, bind: &str, port: u16) -> Result<()> {
+        let listener = TcpListener::bind((bind, port))?;
+        info!("REST server listen at {}.", listener.local_addr()?);
 
-    fn get_in_transit(&self) -> Vec<String> {
-        self.inner.lock().unwrap().blocks_in_transit.clone()
-    }
+        let inner = Arc::clone(&self.inner);
+        let chain = self.chain.clone();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let result = stream.map_err(failure::Error::from).and_then(|mut stream| {
+                    let request = rest::read_request(&mut stream)?;
+                    let ip = stream.peer_addr()?.ip();
+                    let credentials = request.authorization.as_deref().and_then(parse_basic_auth);
 
-    fn get_mempool_tx(&self, addr: &str) -> Option<Transaction> {
-        match self.inner.lock().unwrap().mempool.get(addr) {
-            Some(tx) => Some(tx.clone()),
-            None => None,
+                    // 只在检查权限和复制交易池时持有锁，查询区块链不阻塞网络、RPC 和挖矿
+                    let allowed = {
+                        let inner = inner.lock().unwrap();
+                        inner
+                            .rpc_auth
+                            .check(ip, credentials.as_ref(), Permission::Public)
+                            .map(|()| inner.mempool.clone())
+                    };
+                    let response = match allowed {
+                        Ok(mempool) => {
+                            rest::route(&request.path, &chain, &mempool, |pub_key_hash| {
+                                inner.lock().unwrap().utxo.find_utxos(pub_key_hash)
+                            })
+                        }
+                        Err(e) => rest::Response::unauthorized(&e.to_string()),
+                    };
+                    rest::write_response(&mut stream, &response)
+                });
+
+                if let Err(e) = result {
+                    error!("Handle REST request failed: {}", e);
+                }
+            }
+        });
+
+        Ok(())
+    }
+
+    /**
+     * @desc 在指定地址启动 gRPC 接口，请求按 RPC 的权限检查后由同一套逻辑处理
+     */
+    #[cfg(feature = "grpc")]
+    #[allow(clippy::result_large_err)]
+    pub fn start_grpc(&self, bind: &str, port: u16) -> Result<()> {
+        let listener = TcpListener::bind((bind, port))?;
+        info!("gRPC server listen at {}.", listener.local_addr()?);
+
+        let server = Server {
+            node_address: self.node_address.clone(),
+            mining_address: self.mining_address.clone(),
+            chain: self.chain.clone(),
+            inner: Arc::clone(&self.inner),
+        };
+        grpc::serve(
+            listener,
+            Arc::new(move |ip, credentials, request| {
+                if let Err(e) = server.check_rpc_auth(ip, credentials, &request) {
+                    return Err(tonic::Status::permission_denied(e.to_string()));
+                }
+                server
+                    .rpc_response(request)
+                    .map_err(|e| tonic::Status::internal(e.to_string()))
+            }),
+        )
+    }
+
+    #[cfg(not(feature = "grpc"))]
+    pub fn start_grpc(&self, _bind: &str, _port: u16) -> Result<()> {
+        Err(format_err!(
+            "gRPC is not supported by this build, rebuild with --features grpc."
+        ))
+    }
+
+    /**
+     * @desc 打开节点地址表并恢复其中的节点，地址表不为空时不再连接引导节点，返回恢复的节点数
+     */
+    pub fn load_addr_book(&self, path: &str) -> Result<usize> {
+        let book = AddrBook::open(path)?;
+        let addrs = book.load()?;
+
+        let mut inner = self.inner.lock().unwrap();
+        if !addrs.is_empty() {
+            let bootstrap = bootstrap_node();
+            if !inner.whitelist.contains(&bootstrap)
+                && !inner.peer_identities.contains_key(&bootstrap)
+            {
+                inner.known_nodes.remove(&bootstrap);
+            }
+        }
+        for (addr, _) in &addrs {
+            if addr != &self.node_address {
+                inner.known_nodes.insert(addr.clone());
+            }
+        }
+        inner.addr_book = Some(book);
+        info!("Load {} nodes from address book.", addrs.len());
+
+        Ok(addrs.len())
+    }
+
+    /**
+     * @desc 加入 DNS 种子解析得到的节点，和收到的节点地址一样记入地址表
+     */
+    pub fn add_seed_nodes(&self, nodes: Vec<String>) {
+        for node in nodes {
+            self.add_nodes(&node);
+        }
+    }
+
+    /**
+     * @desc 设置白名单节点: 不计封禁分数、不受转发费率限制、断开后总会重新连接
+     */
+    pub fn set_whitelist(&self, nodes: Vec<String>) {
+        let mut inner = self.inner.lock().unwrap();
+        for node in &nodes {
+            inner.known_nodes.insert(node.clone());
+        }
+        inner.whitelist = nodes.into_iter().collect();
+    }
+
+    pub fn start_server(&self) -> Result<()> {
+        let server_01 = Server {
+            node_address: self.node_address.clone(),
+            mining_address: self.mining_address.clone(),
+            chain: self.chain.clone(),
+            inner: Arc::clone(&self.inner),
+        };
+
+        info!(
+            "Start server at {}, mining address: {}.",
+            &self.node_address, &self.mining_address
+        );
+
+        thread::spawn(move || {
+            thread::sleep(Duration::from_millis(1000));
+
+            // 握手完成后再按双方的高度请求区块；已经主动握手的节点不用再发起
+            for node in server_01.get_known_nodes() {
+                if server_01.handshake_state(&node) == HandshakeState::Connected {
+                    server_01.send_version(&node)?;
+                }
+            }
+
+            // 没有可连接的其他节点时，视为已同步
+            if !server_01.has_remote_peers() {
+                server_01.set_sync_state(SyncState::Synced)?;
+            }
+
+            Ok::<(), failure::Error>(())
+        });
+
+        let server_02 = Server {
+            node_address: self.node_address.clone(),
+            mining_address: self.mining_address.clone(),
+            chain: self.chain.clone(),
+            inner: Arc::clone(&self.inner),
+        };
+
+        thread::spawn(move || loop {
+            thread::sleep(Duration::from_millis(INV_BATCH_INTERVAL));
+
+            server_02.flush_inv();
+        });
+
+        let server_03 = Server {
+            node_address: self.node_address.clone(),
+            mining_address: self.mining_address.clone(),
+            chain: self.chain.clone(),
+            inner: Arc::clone(&self.inner),
+        };
+
+        thread::spawn(move || loop {
+            thread::sleep(Duration::from_secs(STALE_TIP_CHECK_INTERVAL));
+
+            if let Err(e) = server_03.check_stale_tip() {
+                error!("Check stale tip failed: {}", e);
+            }
+            if let Err(e) = server_03.reconnect_whitelist() {
+                error!("Reconnect whitelist failed: {}", e);
+            }
+            if let Err(e) = server_03.fluff_embargoed() {
+                error!("Fluff embargoed transactions failed: {}", e);
+            }
+            if let Err(e) = server_03.check_request_timeouts() {
+                error!("Check data request timeouts failed: {}", e);
+            }
+            server_03.check_handshake_timeouts();
+            server_03.expire_mempool();
+        });
+
+        let server_04 = Server {
+            node_address: self.node_address.clone(),
+            mining_address: self.mining_address.clone(),
+            chain: self.chain.clone(),
+            inner: Arc::clone(&self.inner),
+        };
+
+        thread::spawn(move || loop {
+            thread::sleep(Duration::from_secs(REBROADCAST_INTERVAL));
+
+            if let Err(e) = server_04.rebroadcast_wallet_txs() {
+                error!("Rebroadcast wallet transactions failed: {}", e);
+            }
+            if let Err(e) = server_04.consolidate_rewards() {
+                error!("Consolidate reward outputs failed: {}", e);
+            }
+        });
+
+        let listener = if self.node_address.starts_with("localhost:") {
+            TcpListener::bind(&self.node_address).unwrap()
+        } else {
+            TcpListener::bind(("0.0.0.0", self.listen_port()?)).unwrap()
+        };
+        info!("Server listen...");
+
+        for stream in listener.incoming() {
+            let stream = stream?;
+            let server_01 = Server {
+                node_address: self.node_address.clone(),
+                mining_address: self.mining_address.clone(),
+                chain: self.chain.clone(),
+                inner: Arc::clone(&self.inner),
+            };
+
+            thread::spawn(move || server_01.handle_connection(stream));
+        }
+
+        Ok(())
+    }
+
+    pub fn send_transaction(tx: &Transaction, utxoset: UTXOSet) -> Result<()> {
+        // 只发送不监听，端口 0 不会和本机的其他节点冲突
+        let server = Server::new("0", "", utxoset)?;
+        server.send_stem_tx(&bootstrap_node(), tx)?;
+
+        Ok(())
+    }
+
+    fn get_best_height(&self) -> Result<i32> {
+        self.chain.get_best_height()
+    }
+
+    fn has_remote_peers(&self) -> bool {
+        self.get_known_nodes()
+            .iter()
+            .any(|node| node != &self.node_address)
+    }
+
+    fn get_sync_state(&self) -> SyncState {
+        self.inner.lock().unwrap().sync_state
+    }
+
+    fn is_synced(&self) -> bool {
+        self.get_sync_state() == SyncState::Synced
+    }
+
+    /**
+     * @desc 切换同步状态，从已同步进入同步时记录起始时间和高度用于估算剩余时间
+     */
+    fn set_sync_state(&self, state: SyncState) -> Result<()> {
+        let best_height = self.get_best_height()?;
+        let mut inner = self.inner.lock().unwrap();
+
+        if inner.sync_state != state {
+            info!("Sync state: {:?} -> {:?}.", inner.sync_state, state);
+        }
+        if inner.sync_state == SyncState::Synced && state != SyncState::Synced {
+            inner.sync_started = (now_millis(), best_height);
+        }
+        inner.sync_state = state;
+
+        Ok(())
+    }
+
+    fn touch_last_block_time(&self) {
+        self.inner.lock().unwrap().last_block_time = now_millis();
+    }
+
+    /**
+     * @desc 超过过期时间没有收到新区块时，向所有节点请求区块，并尝试连接地址表中尚未通信过的节点
+     */
+    fn check_stale_tip(&self) -> Result<()> {
+        let (last_block_time, timeout) = {
+            let inner = self.inner.lock().unwrap();
+            (inner.last_block_time, inner.stale_tip_timeout)
+        };
+
+        if now_millis().saturating_sub(last_block_time) < timeout as u128 * 1000 {
+            return Ok(());
+        }
+
+        info!(
+            "No block received for {}s, tip may be stale. Re-sync.",
+            timeout
+        );
+        self.touch_last_block_time();
+
+        let contacted: HashSet<String> = self.inner.lock().unwrap().peers.keys().cloned().collect();
+        for node in self.get_known_nodes() {
+            if node == self.node_address {
+                continue;
+            }
+
+            if contacted.contains(&node) {
+                self.send_get_blocks(&node)?;
+            } else {
+                self.send_version(&node)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn update_target_height(&self, height: i32) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.target_height = inner.target_height.max(height);
+    }
+
+    fn get_blockchain_info(&self) -> Result<BlockchainInfo> {
+        let bc = &self.chain;
+        let best_block_hash = bc.get_tip_hash();
+        let softforks = versionbits::get_deployment_states(bc, &best_block_hash)?
+            .into_iter()
+            .map(|(deployment, state)| SoftforkInfo {
+                name: deployment.name.to_string(),
+                bit: deployment.bit,
+                state,
+            })
+            .collect();
+
+        Ok(BlockchainInfo {
+            network: format!("{:?}", network::current()),
+            height: bc.get_best_height()?,
+            chain_work: bc.get_chain_work(&best_block_hash)?,
+            median_time: bc.get_median_time_past(&best_block_hash)?,
+            softforks,
+            best_block_hash,
+            alerts: self
+                .get_active_alerts()
+                .into_iter()
+                .map(|alert| alert.message)
+                .collect(),
+        })
+    }
+
+    fn get_transaction_info(&self, id: &str) -> Result<Option<TransactionInfo>> {
+        let bc = &self.chain;
+
+        if let Some(block_hash) = bc.find_transaction_block(id)? {
+            return Ok(Some(TransactionInfo {
+                transaction: bc.find_transaction(id)?,
+                confirmations: bc.get_confirmations(id)?,
+                block_hash: Some(block_hash),
+            }));
+        }
+
+        Ok(self
+            .inner
+            .lock()
+            .unwrap()
+            .mempool
+            .get(id)
+            .map(|tx| TransactionInfo {
+                transaction: tx.clone(),
+                block_hash: None,
+                confirmations: 0,
+            }))
+    }
+
+    fn list_unspent(&self, pub_key_hash: &[u8], amount: i32) -> Result<Unspent> {
+        let (accumulated, outputs) = self
+            .inner
+            .lock()
+            .unwrap()
+            .utxo
+            .find_spendable_outputs(pub_key_hash, amount)?;
+
+        Ok(Unspent {
+            accumulated,
+            outputs,
+        })
+    }
+
+    /**
+     * @desc 接收钱包进程提交的已签名交易，校验后按收到普通交易的流程处理
+     */
+    fn send_raw_transaction(&self, tx: Transaction) -> Result<String> {
+        self.check_mempool_accept(&tx)?;
+
+        let id = tx.id.clone();
+        self.handle_tx(TxMsg {
+            addr_from: self.node_address.clone(),
+            transaction: tx,
+        })?;
+
+        Ok(id)
+    }
+
+    /**
+     * @desc 接收外部提交的完整区块，进入主链时移除交易池中的相同交易，并公告给其他节点
+     */
+    fn submit_block(&self, block: Block) -> Result<String> {
+        let hash = block.get_hash();
+        if self.has_block(&hash)? {
+            return Err(format_err!("Block {} is already known.", hash));
+        }
+
+        self.mark_inventory(&hash);
+        self.add_block(block.clone())?;
+        self.touch_last_block_time();
+        self.utxo_reindex()?;
+        if self.chain.is_in_main_chain(&hash)? {
+            self.remove_mempool_txs(&block);
+        }
+
+        for node in self.get_known_nodes() {
+            if node != self.node_address {
+                self.queue_inv(&node, "block", &hash);
+            }
+        }
+
+        Ok(hash)
+    }
+
+    fn test_mempool_accept(&self, tx: &Transaction) -> MempoolAccept {
+        let result = self.check_mempool_accept(tx);
+
+        MempoolAccept {
+            txid: tx.id.clone(),
+            allowed: result.is_ok(),
+            reject_reason: result.as_ref().err().map(|e| e.to_string()),
+            fee_rate: result.ok(),
+        }
+    }
+
+    /**
+     * @desc 向所有节点重新广播钱包中未确认的交易，已进入主链的交易不再跟踪
+     */
+    fn rebroadcast_wallet_txs(&self) -> Result<()> {
+        for tx in wallets::get_pending_txs()? {
+            if self.chain.find_transaction_block(&tx.id)?.is_some() {
+                info!("Wallet tx {} confirmed, stop rebroadcasting.", tx.id);
+                wallets::remove_pending_tx(&tx.id)?;
+                continue;
+            }
+
+            if self.get_mempool_tx(&tx.id).is_none() {
+                if let Err(e) = self.check_mempool_accept(&tx) {
+                    info!("Skip rebroadcasting wallet tx {}: {}", tx.id, e);
+                    continue;
+                }
+                self.insert_mempool(tx.clone());
+            }
+
+            for node in self.get_known_nodes() {
+                if node != self.node_address {
+                    if let Err(e) = self.send_tx(&node, &tx) {
+                        error!("Rebroadcast tx {} to {} failed: {}", tx.id, node, e);
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /**
+     * @desc 低费率时段把挖矿地址的小额输出归集到挖矿地址，挖矿地址不在本机钱包中时跳过
+     */
+    fn consolidate_rewards(&self) -> Result<()> {
+        let policy = match self.inner.lock().unwrap().consolidation {
+            Some(policy) => policy,
+            None => return Ok(()),
+        };
+        if self.mining_address.is_empty() || !self.is_synced() {
+            return Ok(());
+        }
+
+        let wlts = wallets::Wallets::new()?;
+        let wallet = match wlts.get_wallet(&self.mining_address) {
+            Some(wallet) => wallet,
+            None => return Ok(()),
+        };
+
+        // 上一笔归集交易还在交易池中等待确认
+        let mempool = self.get_mempool();
+        if mempool
+            .values()
+            .any(|tx| tx.vin.iter().any(|vin| vin.pub_key == wallet.public_key))
+        {
+            return Ok(());
+        }
+        let mut fee_rates = Vec::new();
+        for tx in mempool.values() {
+            fee_rates.push(self.get_fee_rate(tx)?);
+        }
+
+        let pub_key_hash = wallets::decode_address(&self.mining_address)?;
+        let values: Vec<i32> = self
+            .inner
+            .lock()
+            .unwrap()
+            .utxo
+            .find_utxos(&pub_key_hash)?
+            .outputs
+            .iter()
+            .map(|out| out.value)
+            .collect();
+        if !policy.should_consolidate(&values, &fee_rates) {
+            return Ok(());
+        }
+
+        let txs = Transaction::new_consolidation(
+            wallet,
+            policy.max_value,
+            self.get_min_relay_fee_rate(),
+            &self.inner.lock().unwrap().utxo,
+        )?;
+        for tx in txs {
+            info!(
+                "Consolidate {} outputs of {} in tx {}.",
+                tx.vin.len(),
+                self.mining_address,
+                tx.id
+            );
+            wallets::add_pending_tx(&tx)?;
+            self.send_raw_transaction(tx)?;
+        }
+
+        Ok(())
+    }
+
+    /**
+     * @desc 本地提交的交易进入交易池需要满足的全部规则，返回违反的第一条规则，全部满足时返回费率
+     */
+    fn check_mempool_accept(&self, tx: &Transaction) -> Result<i32> {
+        check_tx_size(tx)?;
+        if tx.is_coinbase() {
+            return Err(format_err!(
+                "Coinbase transaction {} cannot enter the mempool.",
+                tx.id
+            ));
+        }
+        if self.get_mempool_tx(&tx.id).is_some() {
+            return Err(format_err!(
+                "Transaction {} is already in the mempool.",
+                tx.id
+            ));
+        }
+        if self.chain.find_transaction_block(&tx.id)?.is_some() {
+            return Err(format_err!(
+                "Transaction {} is already in the blockchain.",
+                tx.id
+            ));
+        }
+        self.check_tx_policy(tx)?;
+
+        // 输入不能已被主链花费，被交易池中的交易花费时按替换规则处理
+        let spent = self.chain.find_spent_outputs();
+        for vin in &tx.vin {
+            let spent_in_chain = spent
+                .get(&vin.txid)
+                .map(|outs| outs.contains(&vin.vout))
+                .unwrap_or(false);
+            if spent_in_chain {
+                return Err(format_err!(
+                    "Transaction {} spends output {}:{} that is already spent.",
+                    tx.id,
+                    vin.txid,
+                    vin.vout
+                ));
+            }
+        }
+
+        if !self.verify_tx(tx)? {
+            return Err(format_err!(
+                "Transaction {} has an invalid signature or spends a missing output.",
+                tx.id
+            ));
+        }
+        let fee_rate = self.get_fee_rate(tx)?;
+        if fee_rate < self.get_min_relay_fee_rate() {
+            return Err(format_err!(
+                "Fee rate {} below minimum relay fee rate.",
+                fee_rate
+            ));
+        }
+        self.check_replacement(tx)?;
+        self.check_mempool_limits(tx)?;
+
+        Ok(fee_rate)
+    }
+
+    /**
+     * @desc 等待新区块，返回最新区块头，超时则返回当前 tip
+     */
+    fn wait_for_block(&self, tip: &str, timeout_secs: u64) -> Result<BlockHeader> {
+        let deadline = SystemTime::now() + Duration::from_secs(timeout_secs);
+        loop {
+            let best = self.chain.get_tip_hash();
+            if best != tip || SystemTime::now() >= deadline {
+                return self.chain.get_header(&best);
+            }
+            thread::sleep(Duration::from_millis(WAIT_FOR_BLOCK_INTERVAL));
+        }
+    }
+
+    fn get_sync_status(&self) -> Result<SyncStatus> {
+        let current_height = self.get_best_height()?;
+        let inner = self.inner.lock().unwrap();
+        let target_height = inner.target_height.max(current_height);
+
+        let percentage = if target_height <= 0 {
+            100.0
+        } else {
+            (current_height.max(0) as f64) * 100.0 / target_height as f64
+        };
+
+        let (started_at, start_height) = inner.sync_started;
+        let elapsed_ms = now_millis().saturating_sub(started_at);
+        let downloaded = current_height - start_height;
+        let eta_secs = if inner.sync_state == SyncState::Synced {
+            Some(0)
+        } else if downloaded > 0 && elapsed_ms > 0 {
+            let remaining = (target_height - current_height) as u128;
+            Some((remaining * elapsed_ms / downloaded as u128 / 1000) as u64)
+        } else {
+            None
+        };
+
+        Ok(SyncStatus {
+            state: inner.sync_state,
+            current_height,
+            target_height,
+            percentage,
+            eta_secs,
+        })
+    }
+
+    fn get_known_nodes(&self) -> HashSet<String> {
+        self.inner.lock().unwrap().known_nodes.clone()
+    }
+
+    fn node_is_known(&self, addr: &str) -> bool {
+        self.inner.lock().unwrap().known_nodes.get(addr).is_some()
+    }
+
+    fn add_nodes(&self, addr: &str) {
+        self.inner
+            .lock()
+            .unwrap()
+            .known_nodes
+            .insert(String::from(addr));
+        if addr != self.node_address {
+            self.update_addr_book(|book| book.add(addr));
+        }
+    }
+
+    fn remove_node(&self, addr: &str) {
+        {
+            let mut inner = self.inner.lock().unwrap();
+            if !inner.whitelist.contains(addr) {
+                inner.known_nodes.remove(addr);
+            }
+            // 连接失败时握手作废，之后重新握手
+            if let Some(peer) = inner.peers.get_mut(addr) {
+                peer.handshake = HandshakeState::Connected;
+                peer.version = 0;
+            }
+            inner.handshake_started.remove(addr);
+        }
+        self.update_addr_book(|book| book.mark_failed(addr).map(|_| ()));
+    }
+
+    // 地址表写入失败不影响消息处理，只记录日志
+    fn update_addr_book<F: FnOnce(&AddrBook) -> Result<()>>(&self, update: F) {
+        let book = self.inner.lock().unwrap().addr_book.clone();
+        if let Some(book) = book {
+            if let Err(e) = update(&book) {
+                warn!("Update address book failed: {}", e);
+            }
+        }
+    }
+
+    fn is_partitioned(&self, addr: &str) -> bool {
+        self.inner.lock().unwrap().partitioned.contains(addr)
+    }
+
+    /**
+     * @desc 仅限 regtest: 忽略与 peers 之间的消息来模拟网络分区，返回当前被忽略的节点；
+     *       恢复连通的节点重新握手，双方交换分区期间的区块和交易
+     */
+    fn simulate_partition(&self, peers: Vec<String>) -> Result<Vec<String>> {
+        if network::current() != Network::Regtest {
+            return Err(format_err!(
+                "simulate_partition is only available on regtest."
+            ));
+        }
+
+        let peers: HashSet<String> = peers.into_iter().collect();
+        let healed: Vec<String> = {
+            let mut inner = self.inner.lock().unwrap();
+            let healed = inner.partitioned.difference(&peers).cloned().collect();
+            inner.partitioned = peers;
+            healed
+        };
+        for node in healed {
+            info!("Heal partition with node {}.", node);
+            self.send_version(&node)?;
+        }
+
+        let mut partitioned: Vec<String> = self
+            .inner
+            .lock()
+            .unwrap()
+            .partitioned
+            .iter()
+            .cloned()
+            .collect();
+        partitioned.sort();
+        Ok(partitioned)
+    }
+
+    fn is_whitelisted(&self, addr: &str) -> bool {
+        self.inner.lock().unwrap().whitelist.contains(addr)
+    }
+
+    /**
+     * @desc 重新连接一段时间内没有收到消息的白名单节点
+     */
+    fn reconnect_whitelist(&self) -> Result<()> {
+        let whitelist = self.inner.lock().unwrap().whitelist.clone();
+
+        for node in whitelist {
+            let last_recv = match self.inner.lock().unwrap().peers.get(&node) {
+                Some(peer) => peer.last_recv,
+                None => 0,
+            };
+
+            if now_millis().saturating_sub(last_recv) > WHITELIST_RECONNECT_INTERVAL as u128 * 1000
+            {
+                info!("Reconnect whitelisted node: {}.", node);
+                self.send_version(&node)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn send_data(&self, addr: &str, data: &[u8]) -> Result<()> {
+        if addr == self.node_address || self.is_partitioned(addr) {
+            return Ok(());
+        }
+
+        // 握手完成前不发送链消息，还没有握手时先发起握手，握手完成后对方会收到最近的区块和交易
+        let cmd = cmd_name(data);
+        if CHAIN_MESSAGES.contains(&cmd.as_str()) {
+            match self.handshake_state(addr) {
+                HandshakeState::Ready => {}
+                HandshakeState::Connected => {
+                    info!("Skip {} message to {}, start handshake.", cmd, addr);
+                    return self.send_version(addr);
+                }
+                _ => {
+                    info!("Skip {} message to {} during handshake.", cmd, addr);
+                    return Ok(());
+                }
+            }
+        }
+
+        let mut stream = match self.connect(addr) {
+            Ok(s) => s,
+            Err(_) => {
+                self.remove_node(addr);
+                return Ok(());
+            }
+        };
+
+        if self.is_whitelisted(addr) {
+            stream.write_all(data)?;
+        } else {
+            self.write_limited(&mut stream, data)?;
+        }
+        self.record_send(addr, &cmd, data.len());
+        info!("Data send successfully.");
+
+        Ok(())
+    }
+
+    fn connect(&self, addr: &str) -> Result<TcpStream> {
+        let proxy = self.inner.lock().unwrap().proxy.clone();
+
+        match proxy {
+            Some(proxy) => proxy::connect(&proxy, addr),
+            None => Ok(TcpStream::connect(addr)?),
+        }
+    }
+
+    /**
+     * @desc 按上传限速分块写入，等待额度时不持有锁
+     */
+    fn write_limited(&self, stream: &mut TcpStream, data: &[u8]) -> Result<()> {
+        for chunk in data.chunks(UPLOAD_CHUNK_SIZE) {
+            let wait = match self.inner.lock().unwrap().upload_limiter.as_mut() {
+                Some(limiter) => limiter.reserve(chunk.len(), Instant::now()),
+                None => Duration::ZERO,
+            };
+            if !wait.is_zero() {
+                debug!("Upload limited, wait {:?}.", wait);
+                thread::sleep(wait);
+            }
+            stream.write_all(chunk)?;
+        }
+
+        Ok(())
+    }
+
+    fn record_send(&self, addr: &str, cmd: &str, bytes: usize) {
+        let mut inner = self.inner.lock().unwrap();
+        *inner.sent_per_msg.entry(cmd.to_string()).or_insert(0) += bytes as u64;
+
+        let peer = inner
+            .peers
+            .entry(addr.to_string())
+            .or_insert_with(|| PeerInfo::new(addr, Direction::Outbound));
+
+        peer.bytes_sent += bytes as u64;
+        peer.last_send = now_millis();
+    }
+
+    fn record_recv(&self, addr: &str, cmd: &str, bytes: usize) {
+        let mut inner = self.inner.lock().unwrap();
+        *inner.recv_per_msg.entry(cmd.to_string()).or_insert(0) += bytes as u64;
+
+        let peer = inner
+            .peers
+            .entry(addr.to_string())
+            .or_insert_with(|| PeerInfo::new(addr, Direction::Inbound));
+
+        peer.bytes_recv += bytes as u64;
+        peer.last_recv = now_millis();
+    }
+
+    // 记录节点的版本信息；对方发起的握手使已完成的握手重新开始(对方重启或重新连接)
+    fn record_version(&self, msg: &VersionMsg) {
+        let time_offset = msg.timestamp as i64 - now_millis() as i64;
+        timedata::add_time_sample(&msg.addr_from, time_offset);
+
+        let mut inner = self.inner.lock().unwrap();
+        let peer = inner
+            .peers
+            .entry(msg.addr_from.clone())
+            .or_insert_with(|| PeerInfo::new(&msg.addr_from, Direction::Inbound));
+        peer.version = msg.version;
+        peer.best_height = msg.best_height;
+        peer.time_offset = time_offset;
+        if !msg.reply && peer.handshake == HandshakeState::Ready {
+            peer.handshake = HandshakeState::Connected;
+        }
+    }
+
+    fn handshake_state(&self, addr: &str) -> HandshakeState {
+        match self.inner.lock().unwrap().peers.get(addr) {
+            Some(peer) => peer.handshake,
+            None => HandshakeState::Connected,
+        }
+    }
+
+    /**
+     * @desc 发出版本信息前进入 VersionSent；已完成的握手重新开始，需要重新收到对方的版本信息和确认
+     */
+    fn start_handshake(&self, addr: &str) {
+        let mut inner = self.inner.lock().unwrap();
+        let peer = inner
+            .peers
+            .entry(addr.to_string())
+            .or_insert_with(|| PeerInfo::new(addr, Direction::Outbound));
+        match peer.handshake {
+            HandshakeState::Connected => {}
+            HandshakeState::Ready => peer.version = 0,
+            HandshakeState::VersionSent | HandshakeState::VerackReceived => return,
+        }
+        peer.handshake = HandshakeState::VersionSent;
+        inner
+            .handshake_started
+            .insert(addr.to_string(), now_millis());
+    }
+
+    /**
+     * @desc 收到了对方的确认和版本信息时握手完成，返回是否刚刚完成
+     */
+    fn finish_handshake(&self, addr: &str) -> bool {
+        let mut inner = self.inner.lock().unwrap();
+        match inner.peers.get_mut(addr) {
+            Some(peer) if peer.handshake == HandshakeState::VerackReceived && peer.version != 0 => {
+                peer.handshake = HandshakeState::Ready;
+            }
+            _ => return false,
+        }
+        inner.handshake_started.remove(addr);
+        true
+    }
+
+    /**
+     * @desc 握手进行中时等待握手完成，返回握手是否已完成
+     */
+    fn wait_handshake(&self, addr: &str) -> bool {
+        let deadline = Instant::now() + Duration::from_millis(HANDSHAKE_WAIT);
+        loop {
+            match self.handshake_state(addr) {
+                HandshakeState::Ready => return true,
+                HandshakeState::VersionSent | HandshakeState::VerackReceived
+                    if Instant::now() < deadline =>
+                {
+                    thread::sleep(Duration::from_millis(HANDSHAKE_POLL_INTERVAL));
+                }
+                _ => return false,
+            }
+        }
+    }
+
+    /**
+     * @desc 超时未完成的握手回到未握手状态，并计入封禁分数
+     */
+    fn check_handshake_timeouts(&self) {
+        let expired: Vec<String> = {
+            let mut inner = self.inner.lock().unwrap();
+            let now = now_millis();
+            let expired: Vec<String> = inner
+                .handshake_started
+                .iter()
+                .filter(|(_, started)| {
+                    now.saturating_sub(**started) > HANDSHAKE_TIMEOUT as u128 * 1000
+                })
+                .map(|(addr, _)| addr.clone())
+                .collect();
+            for addr in &expired {
+                inner.handshake_started.remove(addr);
+                if let Some(peer) = inner.peers.get_mut(addr) {
+                    peer.handshake = HandshakeState::Connected;
+                    peer.version = 0;
+                }
+            }
+            expired
+        };
+
+        for addr in expired {
+            warn!("Handshake with {} timed out.", addr);
+            self.add_ban_score(&addr, 1);
+        }
+    }
+
+    fn add_ban_score(&self, addr: &str, score: i32) {
+        if self.is_whitelisted(addr) {
+            return;
+        }
+
+        if let Some(peer) = self.inner.lock().unwrap().peers.get_mut(addr) {
+            peer.ban_score += score;
+        }
+    }
+
+    /**
+     * @desc 获取所有已知和已通信节点的信息
+     */
+    fn get_peer_info(&self) -> Vec<PeerInfo> {
+        let inner = self.inner.lock().unwrap();
+        let mut peers: Vec<PeerInfo> = inner.peers.values().cloned().collect();
+
+        for node in &inner.known_nodes {
+            if !inner.peers.contains_key(node) && node != &self.node_address {
+                peers.push(PeerInfo::new(node, Direction::Outbound));
+            }
+        }
+        for peer in &mut peers {
+            peer.identity = inner
+                .peer_identities
+                .get(&peer.addr)
+                .map(|key| encode_hex(key));
+        }
+        peers.sort_by(|a, b| a.addr.cmp(&b.addr));
+
+        peers
+    }
+
+    fn get_network_info(&self) -> NetworkInfo {
+        let inner = self.inner.lock().unwrap();
+        let peers = inner.peers.values();
+
+        NetworkInfo {
+            version: VERSION,
+            local_address: self.node_address.clone(),
+            identity: inner.identity.id(),
+            known_nodes: inner.known_nodes.len(),
+            connections: inner.peers.len(),
+            inbound: peers
+                .clone()
+                .filter(|p| p.direction == Direction::Inbound)
+                .count(),
+            outbound: peers
+                .clone()
+                .filter(|p| p.direction == Direction::Outbound)
+                .count(),
+            total_bytes_sent: peers.clone().map(|p| p.bytes_sent).sum(),
+            total_bytes_recv: peers.map(|p| p.bytes_recv).sum(),
+            time_offset: timedata::get_time_offset(),
+        }
+    }
+
+    /**
+     * @desc 节点启动以来的流量统计
+     */
+    fn get_net_totals(&self) -> NetTotals {
+        let inner = self.inner.lock().unwrap();
+        let mut peers: Vec<(String, u64, u64)> = inner
+            .peers
+            .values()
+            .map(|p| (p.addr.clone(), p.bytes_sent, p.bytes_recv))
+            .collect();
+        peers.sort();
+
+        NetTotals {
+            total_bytes_sent: inner.sent_per_msg.values().sum(),
+            total_bytes_recv: inner.recv_per_msg.values().sum(),
+            sent_per_msg: inner.sent_per_msg.clone(),
+            recv_per_msg: inner.recv_per_msg.clone(),
+            peers,
+            upload_limit: inner.upload_limiter.as_ref().map(|l| l.rate()),
+            time_millis: now_millis(),
+        }
+    }
+
+    pub fn send_tx(&self, addr: &str, tx: &Transaction) -> Result<()> {
+        info!("Send tx to: {} txid: {}.", addr, &tx.id);
+
+        let data = TxMsg {
+            addr_from: self.node_address.clone(),
+            transaction: tx.clone(),
+        };
+        let data = serialize(&(cmd_to_bytes("tx"), data))?;
+        self.send_data(addr, &data)
+    }
+
+    /**
+     * @desc 以 Dandelion 主干(stem)阶段发送交易，对方不会立即广播
+     */
+    fn send_stem_tx(&self, addr: &str, tx: &Transaction) -> Result<()> {
+        info!("Send stem tx to: {} txid: {}.", addr, &tx.id);
+
+        let data = TxMsg {
+            addr_from: self.node_address.clone(),
+            transaction: tx.clone(),
+        };
+        let data = serialize(&(cmd_to_bytes("stem_tx"), data))?;
+        self.send_data(addr, &data)
+    }
+
+    fn send_version(&self, addr: &str) -> Result<()> {
+        self.send_version_msg(addr, false)
+    }
+
+    /**
+     * @desc 回复对方发起握手的版本信息
+     */
+    fn reply_version(&self, addr: &str) -> Result<()> {
+        self.send_version_msg(addr, true)
+    }
+
+    fn send_version_msg(&self, addr: &str, reply: bool) -> Result<()> {
+        info!("Send version info to: {}.", addr);
+
+        // 先进入 VersionSent，对方的 verack 可能在发送返回前就到达
+        if addr != self.node_address && !self.is_partitioned(addr) {
+            self.start_handshake(addr);
+        }
+
+        let identity = self.inner.lock().unwrap().identity.clone();
+        let mut data = VersionMsg {
+            addr_from: self.node_address.clone(),
+            best_height: self.get_best_height()?,
+            version: VERSION,
+            timestamp: now_millis(),
+            magic: network::magic(),
+            reply,
+            identity: identity.public_key.clone(),
+            signature: Vec::new(),
+        };
+        data.signature = identity.sign(&data.signed_data()?);
+        let data = serialize(&(cmd_to_bytes("version"), data))?;
+        self.send_data(addr, &data)
+    }
+
+    fn send_verack(&self, addr: &str) -> Result<()> {
+        info!("Send verack to: {}.", addr);
+
+        let data = VerackMsg {
+            addr_from: self.node_address.clone(),
+        };
+        let data = serialize(&(cmd_to_bytes("verack"), data))?;
+        self.send_data(addr, &data)
+    }
+
+    fn send_inv(&self, addr: &str, kind: &str, items: Vec<String>) -> Result<()> {
+        info!(
+            "Send inv message to: {} kind: {} data: {:?}",
+            addr, kind, items
+        );
+
+        let data = InvMsg {
+            addr_from: self.node_address.clone(),
+            kind: kind.to_string(),
+            items,
+        };
+        let data = serialize(&(cmd_to_bytes("inv"), data))?;
+
+        self.send_data(addr, &data)
+    }
+
+    fn send_get_blocks(&self, addr: &str) -> Result<()> {
+        info!("Send get blocks message to: {}.", addr);
+
+        if self.get_sync_state() != SyncState::BlocksDownloading {
+            self.set_sync_state(SyncState::HeadersSyncing)?;
+        }
+
+        let data = GetBlockMsg {
+            addr_from: self.node_address.clone(),
+            locator: self.get_block_locator(),
+        };
+        let data = serialize(&(cmd_to_bytes("get_blocks"), data))?;
+        self.send_data(addr, &data)
+    }
+
+    fn send_get_data(&self, addr: &str, kind: &str, id: &str) -> Result<()> {
+        info!(
+            "Send get data message to: {} kind: {} id: {}.",
+            addr, kind, id
+        );
+
+        {
+            let requests = &mut self.inner.lock().unwrap().requests_in_flight;
+            let request = requests
+                .entry(id.to_string())
+                .or_insert_with(|| DataRequest {
+                    kind: String::new(),
+                    peer: String::new(),
+                    requested_at: 0,
+                    tried: HashSet::new(),
+                });
+            request.kind = kind.to_string();
+            request.peer = addr.to_string();
+            request.requested_at = now_millis();
+            request.tried.insert(addr.to_string());
+        }
+
+        let data = GetDataMsg {
+            addr_from: self.node_address.clone(),
+            kind: kind.to_string(),
+            id: id.to_string(),
+        };
+        let data = serialize(&(cmd_to_bytes("get_data"), data))?;
+        self.send_data(addr, &data)
+    }
+
+    fn send_not_found(&self, addr: &str, kind: &str, id: &str) -> Result<()> {
+        info!(
+            "Send not found message to: {} kind: {} id: {}.",
+            addr, kind, id
+        );
+
+        let data = NotFoundMsg {
+            addr_from: self.node_address.clone(),
+            kind: kind.to_string(),
+            id: id.to_string(),
+        };
+        let data = serialize(&(cmd_to_bytes("not_found"), data))?;
+        self.send_data(addr, &data)
+    }
+
+    /**
+     * @desc 收到请求的数据，不再等待
+     */
+    fn complete_request(&self, id: &str) {
+        self.inner.lock().unwrap().requests_in_flight.remove(id);
+    }
+
+    /**
+     * @desc 向还没有请求过的节点重新请求数据(区块总是请求完整区块)，
+     *       没有可用的节点时放弃，放弃区块时清空待下载的区块，等待下一次同步
+     */
+    fn retry_request(&self, id: &str) -> Result<()> {
+        let (kind, tried) = match self.inner.lock().unwrap().requests_in_flight.get(id) {
+            Some(request) => (request.kind.clone(), request.tried.clone()),
+            None => return Ok(()),
+        };
+        let kind = if kind == "cmpct_block" {
+            String::from("block")
+        } else {
+            kind
+        };
+
+        let candidates = self
+            .get_known_nodes()
+            .into_iter()
+            .filter(|node| node != &self.node_address && !tried.contains(node))
+            .collect();
+        let alternative = self.prefer_onion_peers(candidates).into_iter().next();
+        if let Some(peer) = alternative {
+            info!("Re-request {} {} from {}.", kind, id, peer);
+            return self.send_get_data(&peer, &kind, id);
+        }
+
+        info!("No peer left to request {} {}, give up.", kind, id);
+        self.complete_request(id);
+        if kind == "block" {
+            self.replace_in_transit(Vec::new());
+            self.set_sync_has_more(false);
+            if self.get_sync_state() == SyncState::BlocksDownloading {
+                self.set_sync_state(SyncState::HeadersSyncing)?;
+            }
         }
+
+        Ok(())
     }
 
-    fn verify_tx(&self, tx: &Transaction) -> Result<bool> {
+    /**
+     * @desc 重新请求超时没有收到的数据
+     */
+    fn check_request_timeouts(&self) -> Result<()> {
+        let deadline = now_millis().saturating_sub(GETDATA_TIMEOUT as u128 * 1000);
+        let expired: Vec<String> = self
+            .inner
+            .lock()
+            .unwrap()
+            .requests_in_flight
+            .iter()
+            .filter(|(_, request)| request.requested_at < deadline)
+            .map(|(id, _)| id.clone())
+            .collect();
+
+        for id in expired {
+            info!("Data request {} timed out.", id);
+            self.retry_request(&id)?;
+        }
+
+        Ok(())
+    }
+
+    fn send_block(&self, addr: &str, b: &Block) -> Result<()> {
+        info!("Send block data to: {} block hash: {}", addr, b.get_hash());
+
+        let data = BlockMsg {
+            addr_from: self.node_address.clone(),
+            block: b.clone(),
+        };
+        let data = serialize(&(cmd_to_bytes("block"), data))?;
+        self.send_data(addr, &data)
+    }
+
+    fn send_cmpct_block(&self, addr: &str, b: &Block) -> Result<()> {
+        info!(
+            "Send compact block to: {} block hash: {}",
+            addr,
+            b.get_hash()
+        );
+
+        let data = CmpctBlockMsg {
+            addr_from: self.node_address.clone(),
+            block: b.to_compact(),
+        };
+        let data = serialize(&(cmd_to_bytes("cmpct_block"), data))?;
+        self.send_data(addr, &data)
+    }
+
+    fn send_get_block_txn(&self, addr: &str, block_hash: &str, indexes: Vec<usize>) -> Result<()> {
+        info!(
+            "Send get block txn to: {} block hash: {} missing: {}.",
+            addr,
+            block_hash,
+            indexes.len()
+        );
+
+        let data = GetBlockTxnMsg {
+            addr_from: self.node_address.clone(),
+            block_hash: block_hash.to_string(),
+            indexes,
+        };
+        let data = serialize(&(cmd_to_bytes("get_blocktxn"), data))?;
+        self.send_data(addr, &data)
+    }
+
+    fn send_block_txn(
+        &self,
+        addr: &str,
+        block_hash: &str,
+        transactions: Vec<Transaction>,
+    ) -> Result<()> {
+        info!("Send block txn to: {} block hash: {}.", addr, block_hash);
+
+        let data = BlockTxnMsg {
+            addr_from: self.node_address.clone(),
+            block_hash: block_hash.to_string(),
+            transactions,
+        };
+        let data = serialize(&(cmd_to_bytes("block_txn"), data))?;
+        self.send_data(addr, &data)
+    }
+
+    fn send_fee_filter(&self, addr: &str) -> Result<()> {
+        let fee_rate = self.get_min_relay_fee_rate();
+        info!("Send fee filter to: {} fee rate: {}.", addr, fee_rate);
+
+        let data = FeeFilterMsg {
+            addr_from: self.node_address.clone(),
+            fee_rate,
+        };
+        let data = serialize(&(cmd_to_bytes("fee_filter"), data))?;
+        self.send_data(addr, &data)
+    }
+
+    fn send_alert(&self, addr: &str, alert: &Alert) -> Result<()> {
+        info!("Send alert {} to: {}.", alert.id, addr);
+
+        let data = AlertMsg {
+            addr_from: self.node_address.clone(),
+            alert: alert.clone(),
+        };
+        let data = serialize(&(cmd_to_bytes("alert"), data))?;
+        self.send_data(addr, &data)
+    }
+
+    fn send_psbt(&self, addr: &str, psbt: &Psbt) -> Result<()> {
+        info!("Send psbt {} to: {}.", psbt.id(), addr);
+
+        let data = PsbtMsg {
+            addr_from: self.node_address.clone(),
+            psbt: psbt.clone(),
+        };
+        let data = serialize(&(cmd_to_bytes("psbt"), data))?;
+        self.send_data(addr, &data)
+    }
+
+    fn send_mempool(&self, addr: &str) -> Result<()> {
+        info!("Send mempool request to: {}.", addr);
+
+        let data = MempoolMsg {
+            addr_from: self.node_address.clone(),
+        };
+        let data = serialize(&(cmd_to_bytes("mempool"), data))?;
+        self.send_data(addr, &data)
+    }
+
+    fn send_addr(&self, addr: &str) -> Result<()> {
+        info!("Send address info to: {}.", addr);
+        let mut nodes = self.get_known_nodes();
+        let inner = self.inner.lock().unwrap();
+        nodes.extend(inner.external_address.clone());
+        nodes.extend(inner.onion_address.clone());
+        let identity = inner.identity.clone();
+        drop(inner);
+
+        let mut data = AddrMsg {
+            addr_from: self.node_address.clone(),
+            nodes: nodes.into_iter().collect(),
+            identity: identity.public_key.clone(),
+            signature: Vec::new(),
+        };
+        data.signature = identity.sign(&data.signed_data()?);
+        let data = serialize(&(cmd_to_bytes("addr"), data))?;
+
+        self.send_data(addr, &data)
+    }
+
+    fn add_block(&self, block: Block) -> Result<()> {
+        self.complete_request(&block.get_hash());
+        let old_tip = self.chain.get_tip_hash();
+        let old_height = self.get_best_height()?;
+        let disconnected = self.chain.add_block(block)?;
+        if self.chain.get_tip_hash() != old_tip {
+            self.inner.lock().unwrap().recent_rejects.clear();
+        }
+        self.record_fork_stats(&old_tip, old_height)?;
+        if !disconnected.is_empty() {
+            self.resurrect_mempool_txs(disconnected)?;
+        }
+
+        Ok(())
+    }
+
+    // 比较加入区块前后的最新区块，记录回滚深度和孤立区块
+    fn record_fork_stats(&self, old_tip: &str, old_height: i32) -> Result<()> {
+        let (disconnected, connected) = if self.chain.get_tip_hash() == old_tip {
+            (0, 0)
+        } else {
+            let depth = self.chain.reorg_depth(old_tip)?;
+            if depth > 0 {
+                info!("Chain reorganized, depth {}.", depth);
+            }
+            (depth, self.get_best_height()? - (old_height - depth))
+        };
         self.inner
             .lock()
             .unwrap()
-            .utxo
-            .blockchain
-            .verify_transaction(tx)
+            .fork_stats
+            .record_block(disconnected as u64, connected as u64);
+
+        Ok(())
+    }
+
+    /**
+     * @desc 手动标记区块无效或清除标记，最新区块改变时更新交易池和 UTXO 集
+     */
+    fn set_block_validity(&self, block_hash: &str, valid: bool) -> Result<BlockchainInfo> {
+        let tip = self.chain.get_tip_hash();
+        let disconnected = if valid {
+            self.chain.reconsider_block(block_hash)?
+        } else {
+            self.chain.invalidate_block(block_hash)?
+        };
+
+        if self.chain.get_tip_hash() != tip {
+            self.resurrect_mempool_txs(disconnected)?;
+            self.utxo_reindex()?;
+        }
+
+        self.get_blockchain_info()
+    }
+
+    /**
+     * @desc 回滚后把被断开区块中的交易放回交易池，并移除和新主链冲突的交易及其后代
+     */
+    fn resurrect_mempool_txs(&self, txs: Vec<Transaction>) -> Result<()> {
+        info!("Chain reorganized, return {} txs to mempool.", txs.len());
+
+        let mut inner = self.inner.lock().unwrap();
+        let mut mempool = std::mem::take(&mut inner.mempool);
+        let bc = &inner.utxo.blockchain;
+
+        for tx in txs {
+            if bc.verify_transaction(&tx).unwrap_or(false) {
+                mempool.insert(tx.id.clone(), tx);
+            } else {
+                info!("Drop invalid disconnected tx {}.", tx.id);
+            }
+        }
+
+        // 反复移除无效交易，直到剩下的交易都只花费主链或交易池中未花费的输出
+        let mut spent = bc.find_spent_outputs();
+        loop {
+            let mut evicted = Vec::new();
+            for tx in mempool.values() {
+                let valid = tx.vin.iter().all(|vin| {
+                    let is_spent = spent
+                        .get(&vin.txid)
+                        .map(|outs| outs.contains(&vin.vout))
+                        .unwrap_or(false);
+                    let exists = mempool.contains_key(&vin.txid)
+                        || bc
+                            .find_transaction_block(&vin.txid)
+                            .unwrap_or(None)
+                            .is_some();
+
+                    exists && !is_spent
+                });
+
+                if valid {
+                    for vin in &tx.vin {
+                        spent.entry(vin.txid.clone()).or_default().push(vin.vout);
+                    }
+                } else {
+                    evicted.push(tx.id.clone());
+                }
+            }
+
+            if evicted.is_empty() {
+                break;
+            }
+            for id in evicted {
+                info!("Evict conflicting tx {} from mempool.", id);
+                mempool.remove(&id);
+            }
+            spent = bc.find_spent_outputs();
+        }
+
+        inner.mempool = mempool;
+
+        Ok(())
+    }
+
+    fn mine_block(&self, txs: Vec<Transaction>) -> Result<Block> {
+        self.chain.mine_block(txs)
+    }
+
+    fn get_block_locator(&self) -> Vec<String> {
+        self.chain.get_block_locator()
+    }
+
+    fn get_hashes_after_locator(&self, locator: &[String]) -> Vec<String> {
+        self.chain
+            .get_hashes_after_locator(locator, MAX_BLOCKS_PER_INV)
+    }
+
+    fn get_block(&self, block_hash: &str) -> Result<Block> {
+        self.chain.get_block(block_hash)
+    }
+
+    fn get_in_transit(&self) -> Vec<String> {
+        self.inner.lock().unwrap().blocks_in_transit.clone()
+    }
+
+    fn get_mempool_tx(&self, addr: &str) -> Option<Transaction> {
+        match self.inner.lock().unwrap().mempool.get(addr) {
+            Some(tx) => Some(tx.clone()),
+            None => None,
+        }
+    }
+
+    /**
+     * @desc 验证交易签名，输入可以引用交易池中未确认的交易
+     */
+    fn verify_tx(&self, tx: &Transaction) -> Result<bool> {
+        if tx.is_coinbase() {
+            return Ok(true);
+        }
+
+        let prev_txs = self.get_prev_txs(tx)?;
+        Ok(sigverify::verify_transactions(std::slice::from_ref(tx), &prev_txs, true)[0])
+    }
+
+    /**
+     * @desc 交易引用的前序交易，未确认的前序交易从交易池中查找
+     */
+    fn get_prev_txs(&self, tx: &Transaction) -> Result<HashMap<String, Transaction>> {
+        let mut prev_txs = HashMap::new();
+        for vin in &tx.vin {
+            if prev_txs.contains_key(&vin.txid) {
+                continue;
+            }
+            let prev_tx = match self.get_mempool_tx(&vin.txid) {
+                Some(prev_tx) => prev_tx,
+                None => self.chain.find_transaction(&vin.txid)?,
+            };
+            prev_txs.insert(vin.txid.clone(), prev_tx);
+        }
+
+        Ok(prev_txs)
+    }
+
+    fn verify_txs(&self, txs: &[Transaction]) -> Result<Vec<bool>> {
+        self.chain.verify_transactions(txs)
     }
 
     fn get_mempool(&self) -> HashMap<String, Transaction> {
         self.inner.lock().unwrap().mempool.clone()
     }
 
+    fn check_mempool_limits(&self, tx: &Transaction) -> Result<()> {
+        let inner = self.inner.lock().unwrap();
+        check_mempool_limits(&inner.mempool, tx, &inner.mempool_limits)
+    }
+
     fn insert_mempool(&self, tx: Transaction) {
-        self.inner.lock().unwrap().mempool.insert(tx.id.clone(), tx);
+        let mut inner = self.inner.lock().unwrap();
+        inner.mempool_times.insert(tx.id.clone(), now_millis());
+        inner.mempool.insert(tx.id.clone(), tx);
+    }
+
+    /**
+     * @desc 移出交易池中过期的交易
+     */
+    fn expire_mempool(&self) {
+        let mut inner = self.inner.lock().unwrap();
+        let expiry = inner.mempool_expiry as u128 * 3600 * 1000;
+        let ServerInner {
+            mempool,
+            mempool_times,
+            ..
+        } = &mut *inner;
+
+        for txid in expire_mempool(mempool, mempool_times, now_millis(), expiry) {
+            info!("Evicted expired tx {} from mempool.", txid);
+        }
+    }
+
+    fn remove_mempool_txs(&self, block: &Block) {
+        let mempool = &mut self.inner.lock().unwrap().mempool;
+        for tx in block.get_transaction() {
+            mempool.remove(&tx.id);
+        }
+    }
+
+    fn take_partial_block(
+        &self,
+        block_hash: &str,
+    ) -> Option<(CompactBlock, Vec<Option<Transaction>>)> {
+        self.inner
+            .lock()
+            .unwrap()
+            .partial_blocks
+            .remove(block_hash)
+            .map(|(block, slots, _)| (block, slots))
+    }
+
+    fn insert_partial_block(&self, block: CompactBlock, slots: Vec<Option<Transaction>>) {
+        let partial_blocks = &mut self.inner.lock().unwrap().partial_blocks;
+        let now = now_millis();
+        let timeout = PARTIAL_BLOCK_TIMEOUT as u128 * 1000;
+        for hash in prune_partial_blocks(partial_blocks, now, timeout, MAX_PARTIAL_BLOCKS - 1) {
+            info!("Drop partial block {}.", hash);
+        }
+        partial_blocks.insert(block.get_hash(), (block, slots, now));
+    }
+
+    fn get_tx_fee(&self, tx: &Transaction) -> Result<i32> {
+        if tx.is_coinbase() {
+            return Ok(0);
+        }
+
+        tx_fee(tx, &self.get_prev_txs(tx)?)
+    }
+
+    /**
+     * @desc 交易费率，前序交易先在交易池中查找，转发和打包花费未确认交易的子交易(CPFP)时也能计算
+     */
+    fn get_fee_rate(&self, tx: &Transaction) -> Result<i32> {
+        let fee = self.get_tx_fee(tx)?;
+        let size = tx.get_size()? as i32;
+
+        Ok(fee * 1000 / size.max(1))
+    }
+
+    /**
+     * @desc 替换规则(RBF): 和交易池中的交易花费相同输出时，费率必须高于每一笔冲突交易，
+     *       手续费必须高于冲突交易及其后代的手续费之和；返回替换后要移出交易池的交易
+     */
+    fn check_replacement(&self, tx: &Transaction) -> Result<HashSet<String>> {
+        let mempool = self.get_mempool();
+        let conflicts = mempool_conflicts(&mempool, tx);
+        if conflicts.is_empty() {
+            return Ok(conflicts);
+        }
+
+        let fee_rate = self.get_fee_rate(tx)?;
+        let mut replaced = HashSet::new();
+        for id in &conflicts {
+            if self.get_fee_rate(&mempool[id])? >= fee_rate {
+                return Err(format_err!(
+                    "Transaction {} conflicts with {} and does not pay a higher fee rate.",
+                    tx.id,
+                    id
+                ));
+            }
+            replaced.extend(mempool_descendants(&mempool, id));
+            replaced.insert(id.clone());
+        }
+
+        let mut replaced_fee = 0;
+        for id in &replaced {
+            replaced_fee += self.get_tx_fee(&mempool[id])?;
+        }
+        let fee = self.get_tx_fee(tx)?;
+        if fee <= replaced_fee {
+            return Err(format_err!(
+                "Transaction {} pays fee {}, not more than the fee {} of the transactions it replaces.",
+                tx.id,
+                fee,
+                replaced_fee
+            ));
+        }
+
+        Ok(replaced)
+    }
+
+    fn remove_replaced_txs(&self, txids: &HashSet<String>) {
+        let mut inner = self.inner.lock().unwrap();
+        for txid in txids {
+            info!("Replace tx {} in mempool.", txid);
+            inner.mempool.remove(txid);
+            inner.mempool_times.remove(txid);
+        }
+    }
+
+    /**
+     * @desc 从交易池移除交易和它的后代，返回移除的交易 id
+     */
+    fn abandon_mempool_tx(&self, txid: &str) -> Vec<String> {
+        let mut inner = self.inner.lock().unwrap();
+        if !inner.mempool.contains_key(txid) {
+            return Vec::new();
+        }
+
+        let mut removed: Vec<String> = mempool_descendants(&inner.mempool, txid)
+            .into_iter()
+            .collect();
+        removed.push(txid.to_string());
+        removed.sort();
+        for id in &removed {
+            info!("Abandon tx {} in mempool.", id);
+            inner.mempool.remove(id);
+            inner.mempool_times.remove(id);
+        }
+
+        removed
+    }
+
+    fn get_min_relay_fee_rate(&self) -> i32 {
+        self.inner.lock().unwrap().min_relay_fee_rate
+    }
+
+    fn get_peer_fee_filter(&self, addr: &str) -> i32 {
+        match self.inner.lock().unwrap().peer_fee_filters.get(addr) {
+            Some(fee_rate) => *fee_rate,
+            None => 0,
+        }
     }
 
     fn clear_mempool(&self) {
@@ -341,15 +2521,156 @@ impl Server {
         bit.clone_from(&hashes);
     }
 
+    fn set_sync_has_more(&self, has_more: bool) {
+        self.inner.lock().unwrap().sync_has_more = has_more;
+    }
+
+    fn get_sync_has_more(&self) -> bool {
+        self.inner.lock().unwrap().sync_has_more
+    }
+
+    fn has_block(&self, block_hash: &str) -> Result<bool> {
+        self.chain.has_block(block_hash)
+    }
+
+    fn has_mempool_tx(&self, txid: &str) -> bool {
+        self.inner.lock().unwrap().mempool.contains_key(txid)
+    }
+
+    fn inventory_is_known(&self, item: &str) -> bool {
+        self.inner.lock().unwrap().known_inventory.contains(item)
+    }
+
+    fn mark_inventory(&self, item: &str) {
+        self.inner.lock().unwrap().known_inventory.insert(item);
+    }
+
+    /**
+     * @desc 记录库存来自哪个节点，之后不再向它公告
+     */
+    fn mark_inventory_from(&self, item: &str, addr: &str) {
+        self.inner
+            .lock()
+            .unwrap()
+            .known_inventory
+            .insert_from(item, addr);
+    }
+
+    fn is_recently_rejected(&self, item: &str) -> bool {
+        self.inner.lock().unwrap().recent_rejects.contains(item)
+    }
+
+    fn add_recent_reject(&self, item: &str) {
+        self.inner.lock().unwrap().recent_rejects.insert(item);
+    }
+
+    /**
+     * @desc 区块已经在区块数据库中，或者最近验证失败过
+     */
+    fn already_have_block(&self, block_hash: &str) -> Result<bool> {
+        Ok(self.has_block(block_hash)? || self.is_recently_rejected(block_hash))
+    }
+
+    /**
+     * @desc 交易已经在交易池或主链中，或者最近验证失败过
+     */
+    fn already_have_tx(&self, txid: &str) -> Result<bool> {
+        Ok(self.has_mempool_tx(txid)
+            || self.is_recently_rejected(txid)
+            || self.chain.find_transaction_block(txid)?.is_some())
+    }
+
+    /**
+     * @desc 将库存公告加入待发送队列，由后台线程定期批量发送
+     */
+    fn queue_inv(&self, addr: &str, kind: &str, item: &str) {
+        let mut inner = self.inner.lock().unwrap();
+        // 不向库存的来源节点回传
+        if inner.known_inventory.is_known_by(item, addr) {
+            return;
+        }
+
+        let items = inner
+            .pending_inv
+            .entry((addr.to_string(), kind.to_string()))
+            .or_default();
+
+        if !items.iter().any(|i| i == item) {
+            items.push(item.to_string());
+        }
+    }
+
+    /**
+     * @desc 向每个节点按类型批量发送待公告的库存
+     *
+     * 交易按手续费率从高到低公告，每轮最多 MAX_TX_INV_PER_ROUND 个，其余留到下一轮
+     */
+    fn flush_inv(&self) {
+        // 区块公告立即发送；交易公告到了该节点的下一次发送时间才发送，之后随机推迟下一次
+        let pending = {
+            let mut inner = self.inner.lock().unwrap();
+            let now = now_millis();
+            let due: Vec<(String, String)> = inner
+                .pending_inv
+                .keys()
+                .filter(|(addr, kind)| {
+                    kind != "tx" || !matches!(inner.next_tx_inv.get(addr), Some(t) if *t > now)
+                })
+                .cloned()
+                .collect();
+
+            let mut pending = Vec::new();
+            for key in due {
+                if key.1 == "tx" {
+                    inner
+                        .next_tx_inv
+                        .insert(key.0.clone(), now + trickle_delay(TX_INV_TRICKLE_INTERVAL));
+                }
+                if let Some(items) = inner.pending_inv.remove(&key) {
+                    pending.push((key, items));
+                }
+            }
+            pending
+        };
+
+        for ((addr, kind), mut items) in pending {
+            if kind == "tx" {
+                let rest = prioritize_txs(&mut items, MAX_TX_INV_PER_ROUND, |txid| {
+                    self.get_mempool_tx(txid)
+                        .and_then(|tx| self.get_fee_rate(&tx).ok())
+                });
+                for txid in rest {
+                    self.queue_inv(&addr, &kind, &txid);
+                }
+            }
+            // 公告已经移出队列，一个节点发送失败时记录后继续发送给其他节点
+            if !items.is_empty() {
+                if let Err(e) = self.send_inv(&addr, &kind, items) {
+                    error!("Send {} inv to {} failed: {}", kind, addr, e);
+                }
+            }
+        }
+    }
+
     fn utxo_reindex(&self) -> Result<()> {
         self.inner.lock().unwrap().utxo.reindex()
     }
 
-    fn handle_addr(&self, msg: Vec<String>) -> Result<()> {
-        info!("Receive address msg: {:#?}.", msg);
+    fn handle_addr(&self, msg: AddrMsg) -> Result<()> {
+        info!("Receive address msg: {:#?}.", msg.nodes);
+        self.check_identity(
+            &msg.addr_from,
+            &msg.identity,
+            &msg.signed_data()?,
+            &msg.signature,
+        )?;
 
-        for node in msg {
-            self.add_nodes(&node);
+        let onion_address = self.inner.lock().unwrap().onion_address.clone();
+        for node in msg.nodes {
+            // 不把自己的洋葱地址当作其他节点
+            if onion_address.as_ref() != Some(&node) {
+                self.add_nodes(&node);
+            }
         }
 
         Ok(())
@@ -361,7 +2682,19 @@ impl Server {
             msg.addr_from,
             msg.block.get_hash()
         );
-        self.add_block(msg.block)?;
+        let block_hash = msg.block.get_hash();
+        self.mark_inventory_from(&block_hash, &msg.addr_from);
+        // 已有的区块不再重复加入，但仍继续请求下一个在途区块
+        if self.already_have_block(&block_hash)? {
+            info!("Already have block {}, skip.", block_hash);
+            self.complete_request(&block_hash);
+        } else {
+            if let Err(e) = self.add_block(msg.block) {
+                self.add_recent_reject(&block_hash);
+                return Err(e);
+            }
+            self.touch_last_block_time();
+        }
 
         let mut in_transit = self.get_in_transit();
         if in_transit.len() > 0 {
@@ -371,6 +2704,15 @@ impl Server {
             self.replace_in_transit(in_transit);
         } else {
             self.utxo_reindex()?;
+
+            // 上一批区块哈希已达到上限，继续用新的定位器请求剩余区块
+            if self.get_sync_has_more() {
+                self.set_sync_has_more(false);
+                self.set_sync_state(SyncState::HeadersSyncing)?;
+                self.send_get_blocks(&msg.addr_from)?;
+            } else if self.get_best_height()? >= self.get_sync_status()?.target_height {
+                self.set_sync_state(SyncState::Synced)?;
+            }
         }
 
         Ok(())
@@ -380,156 +2722,1022 @@ impl Server {
         info!("Recieve inv msg: {:#?}.", msg);
 
         if msg.kind == "block" {
-            let block_hash = &msg.items[0];
-            self.send_get_data(&msg.addr_from, "block", block_hash)?;
+            let mut unseen = Vec::new();
+            for b in &msg.items {
+                if !self.inventory_is_known(b) && !self.already_have_block(b)? {
+                    unseen.push(b.clone());
+                }
+            }
+
+            for b in &msg.items {
+                self.mark_inventory_from(b, &msg.addr_from);
+            }
+
+            if unseen.is_empty() {
+                if self.get_sync_state() == SyncState::HeadersSyncing
+                    && self.get_in_transit().is_empty()
+                {
+                    self.set_sync_state(SyncState::Synced)?;
+                }
+                return Ok(());
+            }
+
+            // 已同步的节点收到单个新区块公告时，请求紧凑区块以节省带宽
+            let block_hash = unseen.remove(0);
+            if unseen.is_empty() && self.get_in_transit().is_empty() {
+                self.send_get_data(&msg.addr_from, "cmpct_block", &block_hash)?;
+                return Ok(());
+            }
+            self.set_sync_state(SyncState::BlocksDownloading)?;
+
+            // 先记录剩余的区块再请求，区块可能在请求返回前就到达
+            let mut in_transit = self.get_in_transit();
+            for b in unseen {
+                if !in_transit.contains(&b) {
+                    in_transit.push(b);
+                }
+            }
+            self.replace_in_transit(in_transit);
+            self.set_sync_has_more(msg.items.len() >= MAX_BLOCKS_PER_INV);
+            self.send_get_data(&msg.addr_from, "block", &block_hash)?;
+        }
+        if msg.kind == "tx" {
+            for txid in &msg.items {
+                let known = self.inventory_is_known(txid) || self.already_have_tx(txid)?;
+                self.mark_inventory_from(txid, &msg.addr_from);
+                if known {
+                    continue;
+                }
+
+                self.send_get_data(&msg.addr_from, "tx", txid)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn handle_get_blocks(&self, msg: GetBlockMsg) -> Result<()> {
+        info!("Receive get blocks msg: {:#?}.", msg);
+
+        let block_hashes = self.get_hashes_after_locator(&msg.locator);
+        self.send_inv(&msg.addr_from, "block", block_hashes)?;
+
+        Ok(())
+    }
+
+    fn handle_get_data(&self, msg: GetDataMsg) -> Result<()> {
+        info!("Receive get data msg: {:#?}.", msg);
+
+        if msg.kind == "block" || msg.kind == "cmpct_block" {
+            let block = match self.get_block(&msg.id) {
+                Ok(block) => block,
+                Err(_) => return self.send_not_found(&msg.addr_from, &msg.kind, &msg.id),
+            };
+            if msg.kind == "block" {
+                self.send_block(&msg.addr_from, &block)?;
+            } else {
+                self.send_cmpct_block(&msg.addr_from, &block)?;
+            }
+        }
+        if msg.kind == "tx" {
+            match self.get_mempool_tx(&msg.id) {
+                Some(tx) => self.send_tx(&msg.addr_from, &tx)?,
+                None => self.send_not_found(&msg.addr_from, &msg.kind, &msg.id)?,
+            }
+        }
+
+        Ok(())
+    }
+
+    fn handle_not_found(&self, msg: NotFoundMsg) -> Result<()> {
+        info!("Receive not found msg: {:#?}.", msg);
+
+        let requested_from_sender = self
+            .inner
+            .lock()
+            .unwrap()
+            .requests_in_flight
+            .get(&msg.id)
+            .map(|request| request.peer == msg.addr_from)
+            .unwrap_or(false);
+        if requested_from_sender {
+            self.retry_request(&msg.id)?;
+        }
+
+        Ok(())
+    }
+
+    /**
+     * @desc 交易池的准入规则: 标准版本，且满足进入下一个区块的版本规则、相对锁定时间和名称注册规则，
+     *       同一个名称在交易池中只能有一笔注册
+     */
+    fn check_tx_policy(&self, tx: &Transaction) -> Result<()> {
+        if !tx.is_standard_version() {
+            return Err(format_err!(
+                "Transaction {} has non-standard version {}.",
+                tx.id,
+                tx.version
+            ));
+        }
+
+        let bc = &self.chain;
+        let height = bc.get_best_height()? + 1;
+        bc.check_transaction_rules(std::slice::from_ref(tx), height)?;
+        bc.check_record_rules(std::slice::from_ref(tx), &bc.get_tip_hash(), height)?;
+
+        if let Some(record) = NameRecord::from_tx(tx)? {
+            for other in self.get_mempool().values() {
+                if NameRecord::from_tx(other)?.map(|other| other.name) == Some(record.name.clone())
+                {
+                    return Err(format_err!(
+                        "Name {} is already being registered by transaction {}.",
+                        record.name,
+                        other.id
+                    ));
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn handle_tx(&self, msg: TxMsg) -> Result<()> {
+        info!("Receive tx msg: {} {}.", msg.addr_from, &msg.transaction.id);
+
+        let txid = msg.transaction.id.clone();
+        self.mark_inventory_from(&txid, &msg.addr_from);
+        self.complete_request(&txid);
+        // 已有或最近拒绝过的交易不再验证和转发，避免节点之间来回转发
+        if self.already_have_tx(&txid)? {
+            info!("Already have tx {}, skip.", txid);
+            return Ok(());
+        }
+
+        let (fee_rate, replaced) = match self.check_relay_tx(&msg) {
+            Ok(Some(accepted)) => accepted,
+            Ok(None) => {
+                self.add_recent_reject(&txid);
+                return Ok(());
+            }
+            Err(e) => {
+                self.add_recent_reject(&txid);
+                return Err(e);
+            }
+        };
+
+        self.remove_replaced_txs(&replaced);
+        self.insert_mempool(msg.transaction.clone());
+
+        // 同步完成前只保存交易，暂不转发和挖矿
+        if !self.is_synced() {
+            info!("Node is syncing, defer relay and mining.");
+            return Ok(());
+        }
+
+        let known_nodes = self.get_known_nodes();
+        if self.node_address == bootstrap_node() {
+            for node in known_nodes {
+                if node != self.node_address
+                    && node != msg.addr_from
+                    && fee_rate >= self.get_peer_fee_filter(&node)
+                {
+                    self.queue_inv(&node, "tx", &msg.transaction.id);
+                }
+            }
+        } else {
+            let mut mempool = self.get_mempool();
+
+            debug!("Current mempool: {:#?}", &mempool);
+            if !mempool.is_empty() && self.is_miner() {
+                loop {
+                    let mut txs = Vec::new();
+                    // 为区块头和 coinbase 交易预留空间
+                    let mut block_size = MESSAGE_OVERHEAD;
+
+                    let candidates: Vec<Transaction> = mempool.values().cloned().collect();
+                    let valid = self.verify_txs(&candidates)?;
+                    for (tx, valid) in candidates.into_iter().zip(valid) {
+                        let tx_size = tx.get_size()?;
+                        if !valid || block_size + tx_size > network::params().max_block_size {
+                            continue;
+                        }
+                        block_size += tx_size;
+                        txs.push(tx);
+                    }
+
+                    if txs.is_empty() {
+                        return Ok(());
+                    }
+
+                    let payout = self.next_payout_address()?;
+                    let mut inner = self.inner.lock().unwrap();
+                    let mut cbtx = match inner.coinbase_tag.clone() {
+                        Some(tag) => {
+                            Transaction::new_tagged_coinbase(payout.clone(), &tag, &mut inner.rng)?
+                        }
+                        None => Transaction::new_coinbase(
+                            payout.clone(),
+                            String::new(),
+                            &mut inner.rng,
+                        )?,
+                    };
+                    if !inner.reward_split.is_empty() {
+                        cbtx = inner.reward_split.apply(cbtx, &payout)?;
+                    }
+                    drop(inner);
+                    txs.push(cbtx);
+
+                    for tx in &txs {
+                        mempool.remove(&tx.id);
+                    }
+
+                    let new_block = self.mine_block(txs)?;
+                    self.touch_last_block_time();
+                    self.utxo_reindex()?;
+                    self.mark_inventory(&new_block.get_hash());
+
+                    for node in self.get_known_nodes() {
+                        if node != self.node_address {
+                            self.queue_inv(&node, "block", &new_block.get_hash());
+                        }
+                    }
+
+                    if mempool.len() == 0 {
+                        break;
+                    }
+                }
+
+                self.clear_mempool();
+            }
+        }
+
+        Ok(())
+    }
+
+    /**
+     * @desc 检查交易能否进入交易池，返回手续费率和被替换的交易；不满足转发策略时返回 None
+     */
+    fn check_relay_tx(&self, msg: &TxMsg) -> Result<Option<(i32, HashSet<String>)>> {
+        check_tx_size(&msg.transaction)?;
+        self.check_tx_policy(&msg.transaction)?;
+
+        let fee_rate = self.get_fee_rate(&msg.transaction)?;
+        if fee_rate < self.get_min_relay_fee_rate() && !self.is_whitelisted(&msg.addr_from) {
+            info!(
+                "Reject tx {}: fee rate {} below minimum relay fee rate.",
+                &msg.transaction.id, fee_rate
+            );
+            return Ok(None);
+        }
+        let replaced = match self.check_replacement(&msg.transaction) {
+            Ok(replaced) => replaced,
+            Err(e) => {
+                info!("Reject tx {}: {}", &msg.transaction.id, e);
+                return Ok(None);
+            }
+        };
+        self.check_mempool_limits(&msg.transaction)?;
+
+        Ok(Some((fee_rate, replaced)))
+    }
+
+    fn handle_cmpct_block(&self, msg: CmpctBlockMsg) -> Result<()> {
+        let block_hash = msg.block.get_hash();
+        info!(
+            "Receive compact block msg: {}, {}.",
+            msg.addr_from, block_hash
+        );
+
+        self.mark_inventory_from(&block_hash, &msg.addr_from);
+        if self.already_have_block(&block_hash)? {
+            return Ok(());
+        }
+        // 请求缺失的交易之前先校验区块头的工作量，避免为伪造的区块占用部分区块的位置
+        #[cfg(not(feature = "pos"))]
+        self.chain.check_block_work(&msg.block.get_header())?;
+
+        let slots = msg.block.fill_from(&self.get_mempool());
+        let missing: Vec<usize> = slots
+            .iter()
+            .enumerate()
+            .filter(|(_, tx)| tx.is_none())
+            .map(|(index, _)| index)
+            .collect();
+
+        if missing.is_empty() {
+            let transactions = slots.into_iter().flatten().collect();
+            return self.connect_compact_block(&msg.addr_from, &msg.block, transactions);
+        }
+
+        self.insert_partial_block(msg.block, slots);
+        self.send_get_block_txn(&msg.addr_from, &block_hash, missing)
+    }
+
+    fn handle_get_block_txn(&self, msg: GetBlockTxnMsg) -> Result<()> {
+        info!("Receive get block txn msg: {:#?}.", msg);
+
+        let block = self.get_block(&msg.block_hash)?;
+        let mut transactions = Vec::new();
+        for index in msg.indexes {
+            match block.get_transaction().get(index) {
+                Some(tx) => transactions.push(tx.clone()),
+                None => return Err(format_err!("Block txn index out of range.")),
+            }
+        }
+
+        self.send_block_txn(&msg.addr_from, &msg.block_hash, transactions)
+    }
+
+    fn handle_block_txn(&self, msg: BlockTxnMsg) -> Result<()> {
+        info!(
+            "Receive block txn msg: {}, {}.",
+            msg.addr_from, msg.block_hash
+        );
+
+        let (block, slots) = match self.take_partial_block(&msg.block_hash) {
+            Some(partial) => partial,
+            None => return Ok(()),
+        };
+
+        let mut received = msg.transactions.into_iter();
+        let mut transactions = Vec::new();
+        for slot in slots {
+            match slot.or_else(|| received.next()) {
+                Some(tx) => transactions.push(tx),
+                None => return self.send_get_data(&msg.addr_from, "block", &msg.block_hash),
+            }
+        }
+
+        self.connect_compact_block(&msg.addr_from, &block, transactions)
+    }
+
+    /**
+     * @desc 还原紧凑区块并加入区块链，还原失败时退回请求完整区块
+     */
+    fn connect_compact_block(
+        &self,
+        addr_from: &str,
+        compact: &CompactBlock,
+        transactions: Vec<Transaction>,
+    ) -> Result<()> {
+        let block = match compact.reconstruct(transactions) {
+            Ok(block) => block,
+            Err(e) => {
+                info!("{} Request full block.", e);
+                return self.send_get_data(addr_from, "block", &compact.get_hash());
+            }
+        };
+
+        let hash = block.get_hash();
+        self.add_block(block.clone())?;
+        self.touch_last_block_time();
+        self.utxo_reindex()?;
+        // 区块加入侧链时其中的交易仍未确认，留在交易池中
+        if self.chain.is_in_main_chain(&hash)? {
+            self.remove_mempool_txs(&block);
+        }
+
+        Ok(())
+    }
+
+    /**
+     * @desc Dandelion 主干阶段: 以一定概率转为扩散(fluff)，否则转发给本轮选定的下一跳节点
+     */
+    fn handle_stem_tx(&self, msg: TxMsg) -> Result<()> {
+        info!(
+            "Receive stem tx msg: {} {}.",
+            msg.addr_from, &msg.transaction.id
+        );
+
+        let txid = msg.transaction.id.clone();
+        if self.already_have_tx(&txid)? {
+            return Ok(());
+        }
+
+        let stem_peer = self.get_stem_peer(&msg.addr_from);
+        let fluff = thread_rng().gen_bool(FLUFF_PROBABILITY);
+
+        match stem_peer {
+            Some(peer) if !fluff => {
+                let deadline = now_millis() + STEM_EMBARGO as u128 * 1000;
+                self.inner
+                    .lock()
+                    .unwrap()
+                    .stempool
+                    .insert(txid, (msg.transaction.clone(), deadline));
+                self.send_stem_tx(&peer, &msg.transaction)
+            }
+            _ => self.fluff_tx(msg),
+        }
+    }
+
+    /**
+     * @desc 获取本轮的主干下一跳节点，每个周期随机重新选择一次
+     */
+    fn get_stem_peer(&self, exclude: &str) -> Option<String> {
+        let mut inner = self.inner.lock().unwrap();
+
+        if let Some((peer, chosen_at)) = &inner.stem_peer {
+            let expired = now_millis().saturating_sub(*chosen_at) > STEM_EPOCH as u128 * 1000;
+            if !expired && peer != exclude && inner.known_nodes.contains(peer) {
+                return Some(peer.clone());
+            }
+        }
+
+        let candidates: Vec<String> = inner
+            .known_nodes
+            .iter()
+            .filter(|node| *node != &self.node_address && *node != exclude)
+            .cloned()
+            .collect();
+        let peer = prefer_onion_peers(candidates, inner.prefer_onion)
+            .into_iter()
+            .choose(&mut thread_rng());
+        inner.stem_peer = peer.clone().map(|p| (p, now_millis()));
+
+        peer
+    }
+
+    fn prefer_onion_peers(&self, peers: Vec<String>) -> Vec<String> {
+        prefer_onion_peers(peers, self.inner.lock().unwrap().prefer_onion)
+    }
+
+    /**
+     * @desc 扩散阶段: 作为普通交易处理，并向所有节点公告
+     */
+    fn fluff_tx(&self, msg: TxMsg) -> Result<()> {
+        info!("Fluff tx: {}.", &msg.transaction.id);
+
+        self.inner
+            .lock()
+            .unwrap()
+            .stempool
+            .remove(&msg.transaction.id);
+
+        let txid = msg.transaction.id.clone();
+        let addr_from = msg.addr_from.clone();
+        self.handle_tx(msg)?;
+
+        if self.has_mempool_tx(&txid) && self.is_synced() {
+            for node in self.get_known_nodes() {
+                if node != self.node_address && node != addr_from {
+                    self.queue_inv(&node, "tx", &txid);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /**
+     * @desc 主干阶段的交易超过禁运时间仍未在交易池中出现时，由本节点扩散，防止交易丢失
+     */
+    fn fluff_embargoed(&self) -> Result<()> {
+        let now = now_millis();
+        let expired: Vec<Transaction> = self
+            .inner
+            .lock()
+            .unwrap()
+            .stempool
+            .values()
+            .filter(|(_, deadline)| *deadline <= now)
+            .map(|(tx, _)| tx.clone())
+            .collect();
+
+        for tx in expired {
+            if self.has_mempool_tx(&tx.id) {
+                self.inner.lock().unwrap().stempool.remove(&tx.id);
+                continue;
+            }
+
+            self.fluff_tx(TxMsg {
+                addr_from: self.node_address.clone(),
+                transaction: tx,
+            })?;
+        }
+
+        Ok(())
+    }
+
+    /**
+     * @desc 把交易池中满足对方手续费过滤的交易加入公告队列，由 flush_inv 按手续费率分批发送
+     */
+    fn handle_mempool(&self, msg: MempoolMsg) -> Result<()> {
+        info!("Receive mempool msg: {:#?}.", msg);
+        self.queue_mempool_inv(&msg.addr_from)
+    }
+
+    // 公告交易池中费率不低于对方过滤条件的交易
+    fn queue_mempool_inv(&self, addr: &str) -> Result<()> {
+        let min_fee_rate = self.get_peer_fee_filter(addr);
+        for tx in self.get_mempool().values() {
+            if self.get_fee_rate(tx)? >= min_fee_rate {
+                self.queue_inv(addr, "tx", &tx.id);
+            }
+        }
+
+        Ok(())
+    }
+
+    /**
+     * @desc 握手后公告交易池中的交易，高度相同时还公告最近的区块:
+     *       分区期间两边各自出块时高度可能相同，只比较高度不会发现对方的分叉
+     */
+    fn send_inventory_backlog(&self, addr: &str, same_height: bool) -> Result<()> {
+        if same_height {
+            let best_height = self.get_best_height()?;
+            let mut hashes = Vec::new();
+            for height in (best_height - BACKLOG_BLOCKS + 1).max(0)..=best_height {
+                if let Some(hash) = self.chain.get_block_hash_at(height)? {
+                    hashes.push(hash);
+                }
+            }
+            self.send_inv(addr, "block", hashes)?;
+        }
+
+        self.queue_mempool_inv(addr)
+    }
+
+    fn handle_alert(&self, msg: AlertMsg) -> Result<()> {
+        info!("Receive alert msg: {}, {}.", msg.addr_from, msg.alert.id);
+        self.accept_alert(msg.alert, &msg.addr_from)?;
+
+        Ok(())
+    }
+
+    /**
+     * @desc 验证公告并记录，新的公告转发给来源以外的所有节点；返回是否是新的公告
+     */
+    fn accept_alert(&self, alert: Alert, addr_from: &str) -> Result<bool> {
+        let mut inner = self.inner.lock().unwrap();
+        match &inner.alert_key {
+            Some(key) => alert.verify(key)?,
+            None => return Err(format_err!("No alert key configured, alert ignored.")),
+        }
+
+        let now = now_millis();
+        inner.alerts.retain(|_, alert| !alert.is_expired(now));
+        if alert.is_expired(now) || inner.alerts.contains_key(&alert.id) {
+            return Ok(false);
+        }
+        warn!("ALERT {}: {}", alert.id, alert.message);
+        inner.alerts.insert(alert.id, alert.clone());
+        drop(inner);
+
+        for node in self.get_known_nodes() {
+            if node != self.node_address && node != addr_from {
+                self.send_alert(&node, &alert)?;
+            }
+        }
+
+        Ok(true)
+    }
+
+    fn handle_psbt(&self, msg: PsbtMsg) -> Result<()> {
+        info!("Receive psbt msg: {}, {}.", msg.addr_from, msg.psbt.id());
+        self.accept_psbt(msg.psbt, &msg.addr_from)?;
+
+        Ok(())
+    }
+
+    /**
+     * @desc 检查部分签名交易，新的签名转发给来源以外的所有节点；
+     *       本机钱包持有其中的公钥时合并保存，签名达到门限后广播最终交易；返回是否有新的签名
+     */
+    fn accept_psbt(&self, psbt: Psbt, addr_from: &str) -> Result<bool> {
+        let digest = psbt.digest()?;
+        if self.inventory_is_known(&digest) {
+            return Ok(false);
+        }
+        psbt.check()?;
+        self.mark_inventory(&digest);
 
-            let mut new_in_transit = Vec::new();
-            for b in &msg.items {
-                if b != block_hash {
-                    new_in_transit.push(b.clone());
+        let wlts = wallets::Wallets::new()?;
+        let cosigner = wlts
+            .get_all_addresses()
+            .iter()
+            .filter_map(|address| wlts.get_wallet(address))
+            .any(|wallet| psbt.has_key(&wallet.public_key));
+        // 交易已经在交易池中时不再保存
+        if cosigner && !self.has_mempool_tx(&psbt.id()) {
+            let merged = match wallets::get_psbt(&psbt.id())? {
+                Some(mut known) => {
+                    known.combine(&psbt)?;
+                    known
+                }
+                None => psbt.clone(),
+            };
+            if merged.is_complete() {
+                let tx = merged.finalize()?;
+                info!("Psbt {} is fully signed, broadcast it.", tx.id);
+                wallets::add_pending_tx(&tx)?;
+                wallets::remove_psbt(&tx.id)?;
+                // 其他签名方可能已经广播了同一笔交易
+                if let Err(e) = self.send_raw_transaction(tx) {
+                    info!("Psbt broadcast skipped: {}", e);
                 }
+            } else {
+                wallets::save_psbt(&merged)?;
             }
         }
-        if msg.kind == "tx" {
-            let txid = &msg.items[0];
 
-            match self.get_mempool_tx(txid) {
-                Some(tx) => {
-                    if tx.id.is_empty() {
-                        self.send_get_data(&msg.addr_from, "tx", txid)?
-                    }
-                }
-                None => self.send_get_data(&msg.addr_from, "tx", txid)?,
+        for node in self.get_known_nodes() {
+            if node != self.node_address && node != addr_from {
+                self.send_psbt(&node, &psbt)?;
             }
         }
 
-        Ok(())
+        Ok(true)
     }
 
-    fn handle_get_blocks(&self, msg: GetBlockMsg) -> Result<()> {
-        info!("Receive get blocks msg: {:#?}.", msg);
+    /**
+     * @desc 尚未过期的公告，按编号排序
+     */
+    fn get_active_alerts(&self) -> Vec<Alert> {
+        let now = now_millis();
+        let mut alerts: Vec<Alert> = self
+            .inner
+            .lock()
+            .unwrap()
+            .alerts
+            .values()
+            .filter(|alert| !alert.is_expired(now))
+            .cloned()
+            .collect();
+        alerts.sort_by_key(|alert| alert.id);
 
-        let block_hashes = self.get_block_hashes();
-        self.send_inv(&msg.addr_from, "block", block_hashes)?;
+        alerts
+    }
+
+    fn handle_fee_filter(&self, msg: FeeFilterMsg) -> Result<()> {
+        info!("Receive fee filter msg: {:#?}.", msg);
+
+        self.inner
+            .lock()
+            .unwrap()
+            .peer_fee_filters
+            .insert(msg.addr_from, msg.fee_rate);
 
         Ok(())
     }
 
-    fn handle_get_data(&self, msg: GetDataMsg) -> Result<()> {
-        info!("Receive get data msg: {:#?}.", msg);
+    fn handle_version(&self, msg: VersionMsg) -> Result<()> {
+        info!("Receive version msg: {:#?}", msg);
 
-        if msg.kind == "block" {
-            let block = self.get_block(&msg.id)?;
-            self.send_block(&msg.addr_from, &block)?;
+        if msg.magic != network::magic() {
+            return Err(format_err!(
+                "Peer {} is on a different network (magic {:02x?}).",
+                msg.addr_from,
+                msg.magic
+            ));
         }
-        if msg.kind == "tx" {
-            let tx = self.get_mempool_tx(&msg.id).unwrap();
-            self.send_tx(&msg.addr_from, &tx)?;
+        self.check_identity(
+            &msg.addr_from,
+            &msg.identity,
+            &msg.signed_data()?,
+            &msg.signature,
+        )?;
+
+        self.record_version(&msg);
+        self.update_addr_book(|book| book.mark_good(&msg.addr_from, now_millis()));
+        self.update_target_height(msg.best_height);
+        if !self.node_is_known(&msg.addr_from) {
+            self.add_nodes(&msg.addr_from);
+        }
+
+        // 对方发起的握手总是回复版本信息，对方之前可能没有收到我们的版本信息
+        if !msg.reply {
+            self.reply_version(&msg.addr_from)?;
+        }
+        self.send_verack(&msg.addr_from)?;
+
+        if self.finish_handshake(&msg.addr_from) {
+            self.on_handshake_completed(&msg.addr_from)?;
         }
 
         Ok(())
     }
 
-    fn handle_tx(&self, msg: TxMsg) -> Result<()> {
-        info!("Receive tx msg: {} {}.", msg.addr_from, &msg.transaction.id);
-
-        self.insert_mempool(msg.transaction.clone());
+    fn handle_verack(&self, msg: VerackMsg) -> Result<()> {
+        info!("Receive verack msg: {:#?}", msg);
 
-        let known_nodes = self.get_known_nodes();
-        if self.node_address == KNOWN_NODE_01 {
-            for node in known_nodes {
-                if node != self.node_address && node != msg.addr_from {
-                    self.send_inv(&node, "tx", vec![msg.transaction.id.clone()])?;
+        {
+            let mut inner = self.inner.lock().unwrap();
+            match inner.peers.get_mut(&msg.addr_from) {
+                Some(peer) if peer.handshake == HandshakeState::VersionSent => {
+                    peer.handshake = HandshakeState::VerackReceived;
                 }
+                // 重复发送的版本信息得到的重复确认
+                Some(peer) if peer.handshake != HandshakeState::Connected => return Ok(()),
+                _ => return Err(format_err!("Unexpected verack from {}.", msg.addr_from)),
             }
-        } else {
-            let mut mempool = self.get_mempool();
-
-            debug!("Current mempool: {:#?}", &mempool);
-            if mempool.len() >= 1 && !self.mining_address.is_empty() {
-                loop {
-                    let mut txs = Vec::new();
+        }
 
-                    for (_, tx) in &mempool {
-                        if self.verify_tx(tx)? {
-                            txs.push(tx.clone());
-                        }
-                    }
+        if self.finish_handshake(&msg.addr_from) {
+            self.on_handshake_completed(&msg.addr_from)?;
+        }
 
-                    if txs.is_empty() {
-                        return Ok(());
-                    }
+        Ok(())
+    }
 
-                    let cbtx =
-                        Transaction::new_coinbase(self.mining_address.clone(), String::new())?;
-                    txs.push(cbtx);
+    /**
+     * @desc 握手完成: 按双方高度请求或公告区块，交换交易池、节点地址、费率过滤和公告
+     */
+    fn on_handshake_completed(&self, addr: &str) -> Result<()> {
+        info!("Handshake with {} completed.", addr);
 
-                    for tx in &txs {
-                        mempool.remove(&tx.id);
-                    }
+        let peer_height = match self.inner.lock().unwrap().peers.get(addr) {
+            Some(peer) => peer.best_height,
+            None => return Ok(()),
+        };
+        let my_best_height = self.get_best_height()?;
+        if my_best_height < peer_height {
+            self.send_get_blocks(addr)?;
+        } else if self.get_sync_state() == SyncState::HeadersSyncing
+            && self.get_in_transit().is_empty()
+        {
+            self.set_sync_state(SyncState::Synced)?;
+        }
+        if my_best_height == peer_height && self.is_synced() {
+            self.send_mempool(addr)?;
+        }
+        self.send_inventory_backlog(addr, my_best_height == peer_height)?;
 
-                    let new_block = self.mine_block(txs)?;
-                    self.utxo_reindex()?;
+        self.send_addr(addr)?;
+        if self.get_min_relay_fee_rate() > 0 {
+            self.send_fee_filter(addr)?;
+        }
+        for alert in self.get_active_alerts() {
+            self.send_alert(addr, &alert)?;
+        }
 
-                    for node in self.get_known_nodes() {
-                        if node != self.node_address {
-                            self.send_inv(&node, "block", vec![new_block.get_hash()])?;
-                        }
-                    }
+        Ok(())
+    }
 
-                    if mempool.len() == 0 {
-                        break;
-                    }
-                }
+    /**
+     * @desc 检查消息的身份签名，并且签名身份要和该地址固定或之前记录的身份一致；
+     *       第一次收到某个地址的签名消息时记录它的身份
+     */
+    fn check_identity(
+        &self,
+        addr: &str,
+        public_key: &[u8],
+        data: &[u8],
+        signature: &[u8],
+    ) -> Result<()> {
+        if !identity::verify(public_key, data, signature) {
+            return Err(format_err!("Invalid identity signature from {}.", addr));
+        }
 
-                self.clear_mempool();
+        let mut inner = self.inner.lock().unwrap();
+        match inner.peer_identities.get(addr) {
+            Some(known) if known.as_slice() != public_key => Err(format_err!(
+                "Peer {} signed with identity {}, expected {}.",
+                addr,
+                encode_hex(public_key),
+                encode_hex(known)
+            )),
+            Some(_) => Ok(()),
+            None => {
+                inner
+                    .peer_identities
+                    .insert(addr.to_string(), public_key.to_vec());
+                Ok(())
             }
         }
+    }
+
+    fn handle_rpc(&self, call: RpcCall, stream: &mut TcpStream) -> Result<()> {
+        let request = call.request;
+        info!("Receive rpc request: {:?}.", request);
+
+        let ip = stream.peer_addr()?.ip();
+        let response = match self.check_rpc_auth(ip, call.credentials.as_ref(), &request) {
+            Ok(()) => self.rpc_response(request)?,
+            Err(e) => RpcResponse::Error(e.to_string()),
+        };
+        stream.write_all(&serialize(&response)?)?;
 
         Ok(())
     }
 
-    fn handle_version(&self, msg: VersionMsg) -> Result<()> {
-        info!("Receive version msg: {:#?}", msg);
-
-        let my_best_height = self.get_best_height()?;
-        if my_best_height < msg.best_height {
-            self.send_get_blocks(&msg.addr_from)?;
-        }
-        if my_best_height > msg.best_height {
-            self.send_version(&msg.addr_from)?;
+    /**
+     * @desc 检查来源 ip 和凭据有没有调用该方法的权限
+     */
+    fn check_rpc_auth(
+        &self,
+        ip: IpAddr,
+        credentials: Option<&(String, String)>,
+        request: &RpcRequest,
+    ) -> Result<()> {
+        let checked =
+            self.inner
+                .lock()
+                .unwrap()
+                .rpc_auth
+                .check(ip, credentials, request.permission());
+        if let Err(e) = &checked {
+            info!("Reject rpc request from {}: {}", ip, e);
         }
 
-        self.send_addr(&msg.addr_from)?;
+        checked
+    }
 
-        if !self.node_is_known(&msg.addr_from) {
-            self.add_nodes(&msg.addr_from);
-        }
+    /**
+     * @desc 处理已通过权限检查的 RPC 请求，RPC 和 gRPC 接口共用
+     */
+    fn rpc_response(&self, request: RpcRequest) -> Result<RpcResponse> {
+        let response = match request {
+            RpcRequest::GetPeerInfo => RpcResponse::PeerInfo(self.get_peer_info()),
+            RpcRequest::GetNetworkInfo => RpcResponse::NetworkInfo(self.get_network_info()),
+            RpcRequest::GetNetTotals => RpcResponse::NetTotals(self.get_net_totals()),
+            RpcRequest::GetSyncStatus => RpcResponse::SyncStatus(self.get_sync_status()?),
+            RpcRequest::GetBlockchainInfo => {
+                RpcResponse::BlockchainInfo(self.get_blockchain_info()?)
+            }
+            RpcRequest::GetTransaction(id) => match self.get_transaction_info(&id)? {
+                Some(info) => RpcResponse::Transaction(info),
+                None => RpcResponse::Error(format!("Transaction {} is not found.", id)),
+            },
+            RpcRequest::ListUnspent {
+                pub_key_hash,
+                amount,
+            } => RpcResponse::Unspent(self.list_unspent(&pub_key_hash, amount)?),
+            RpcRequest::ListUtxos(pub_key_hash) => {
+                RpcResponse::Utxos(self.inner.lock().unwrap().utxo.find_utxos(&pub_key_hash)?)
+            }
+            RpcRequest::ListAddressBalances => {
+                RpcResponse::AddressBalances(self.inner.lock().unwrap().utxo.address_balances()?)
+            }
+            RpcRequest::SendRawTransaction(tx) => match self.send_raw_transaction(tx) {
+                Ok(id) => RpcResponse::TxId(id),
+                Err(e) => RpcResponse::Error(e.to_string()),
+            },
+            RpcRequest::WaitForBlock { tip, timeout_secs } => {
+                RpcResponse::BlockHeader(self.wait_for_block(&tip, timeout_secs)?)
+            }
+            RpcRequest::TestMempoolAccept(tx) => {
+                RpcResponse::MempoolAccept(self.test_mempool_accept(&tx))
+            }
+            RpcRequest::GetMiningInfo => {
+                RpcResponse::MiningInfo(mining::get_mining_info(&self.chain)?)
+            }
+            RpcRequest::GetBlockStats(block) => {
+                match stats::resolve_block(&self.chain, &block)
+                    .and_then(|hash| stats::get_block_stats(&self.chain, &hash))
+                {
+                    Ok(stats) => RpcResponse::BlockStats(stats),
+                    Err(e) => RpcResponse::Error(e.to_string()),
+                }
+            }
+            RpcRequest::GetChainTips => RpcResponse::ChainTips(self.chain.get_chain_tips()?),
+            RpcRequest::ResolveName(name) => match self.chain.resolve_name(&name) {
+                Ok(claim) => RpcResponse::Name(claim),
+                Err(e) => RpcResponse::Error(e.to_string()),
+            },
+            RpcRequest::TallyProposal(proposal) => match self.chain.tally_proposal(&proposal) {
+                Ok(tally) => RpcResponse::Tally(tally),
+                Err(e) => RpcResponse::Error(e.to_string()),
+            },
+            RpcRequest::SubmitAlert(alert) => {
+                let id = alert.id;
+                match self.accept_alert(alert, &self.node_address) {
+                    Ok(_) => RpcResponse::AlertId(id),
+                    Err(e) => RpcResponse::Error(e.to_string()),
+                }
+            }
+            RpcRequest::SubmitPsbt(psbt) => {
+                let id = psbt.id();
+                match self.accept_psbt(psbt, &self.node_address) {
+                    Ok(_) => RpcResponse::TxId(id),
+                    Err(e) => RpcResponse::Error(e.to_string()),
+                }
+            }
+            RpcRequest::AbandonTransaction(txid) => {
+                RpcResponse::TxIds(self.abandon_mempool_tx(&txid))
+            }
+            RpcRequest::GetWalletConflicts(txs) => match self.chain.find_conflicts(&txs) {
+                Ok(conflicts) => RpcResponse::Conflicts(conflicts),
+                Err(e) => RpcResponse::Error(e.to_string()),
+            },
+            RpcRequest::SubmitBlock(block) => match self.submit_block(block) {
+                Ok(hash) => RpcResponse::BlockHash(hash),
+                Err(e) => RpcResponse::Error(e.to_string()),
+            },
+            RpcRequest::GetBlock(block) => {
+                match stats::resolve_block(&self.chain, &block)
+                    .and_then(|hash| self.chain.get_block(&hash))
+                {
+                    Ok(block) => RpcResponse::Block(block),
+                    Err(e) => RpcResponse::Error(e.to_string()),
+                }
+            }
+            RpcRequest::InvalidateBlock(hash) => match self.set_block_validity(&hash, false) {
+                Ok(info) => RpcResponse::BlockchainInfo(info),
+                Err(e) => RpcResponse::Error(e.to_string()),
+            },
+            RpcRequest::ReconsiderBlock(hash) => match self.set_block_validity(&hash, true) {
+                Ok(info) => RpcResponse::BlockchainInfo(info),
+                Err(e) => RpcResponse::Error(e.to_string()),
+            },
+            RpcRequest::GetChainStats { from, to } => {
+                let to = match to {
+                    Some(to) => to,
+                    None => self.get_best_height()?,
+                };
+                match stats::get_chain_stats(&self.chain, from, to) {
+                    Ok(stats) => RpcResponse::ChainStats(stats),
+                    Err(e) => RpcResponse::Error(e.to_string()),
+                }
+            }
+            RpcRequest::GetForkStats => {
+                RpcResponse::ForkStats(self.inner.lock().unwrap().fork_stats.clone())
+            }
+            RpcRequest::SimulatePartition(peers) => match self.simulate_partition(peers) {
+                Ok(peers) => RpcResponse::Partitioned(peers),
+                Err(e) => RpcResponse::Error(e.to_string()),
+            },
+            RpcRequest::GetChainHistory { from, to, window } => {
+                let to = match to {
+                    Some(to) => to,
+                    None => self.get_best_height()?,
+                };
+                match stats::get_chain_history(&self.chain, from, to, window) {
+                    Ok(windows) => RpcResponse::ChainHistory(windows),
+                    Err(e) => RpcResponse::Error(e.to_string()),
+                }
+            }
+        };
 
-        Ok(())
+        Ok(response)
     }
 
     fn handle_connection(&self, mut stream: TcpStream) -> Result<()> {
+        // 限制读取的长度，避免超大消息占满内存
+        let limit = network::params().max_block_size + MESSAGE_OVERHEAD;
         let mut buffer = Vec::new();
-        let count = stream.read_to_end(&mut buffer)?;
+        let count = (&mut stream)
+            .take(limit as u64 + 1)
+            .read_to_end(&mut buffer)?;
+        if count > limit {
+            return Err(format_err!("Message exceeds the size limit {}.", limit));
+        }
         info!("Accept request: length {}", count);
 
         let cmd = bytes_to_cmd(&buffer)?;
-        match cmd {
-            Message::Addr(data) => self.handle_addr(data)?,
-            Message::Block(data) => self.handle_block(data)?,
-            Message::Inv(data) => self.handle_inv(data)?,
-            Message::GetBlock(data) => self.handle_get_blocks(data)?,
-            Message::GetData(data) => self.handle_get_data(data)?,
-            Message::Tx(data) => self.handle_tx(data)?,
-            Message::Version(data) => self.handle_version(data)?,
+        let addr_from = cmd.addr_from().map(String::from);
+        if let Some(addr) = &addr_from {
+            if self.is_partitioned(addr) {
+                info!("Ignore message from partitioned node {}.", addr);
+                return Ok(());
+            }
+            let cmd = cmd_name(&buffer);
+            self.record_recv(addr, &cmd, count);
+
+            // 握手完成前收到的链消息不处理，计入封禁分数；还没有握手时发起握手
+            if CHAIN_MESSAGES.contains(&cmd.as_str()) && !self.wait_handshake(addr) {
+                info!("Ignore {} message from {} before handshake.", cmd, addr);
+                self.add_ban_score(addr, 1);
+                if self.handshake_state(addr) == HandshakeState::Connected {
+                    self.send_version(addr)?;
+                }
+                return Ok(());
+            }
         }
 
-        Ok(())
+        let result = match cmd {
+            Message::Addr(data) => self.handle_addr(data),
+            Message::Block(data) => self.handle_block(data),
+            Message::CmpctBlock(data) => self.handle_cmpct_block(data),
+            Message::GetBlockTxn(data) => self.handle_get_block_txn(data),
+            Message::BlockTxn(data) => self.handle_block_txn(data),
+            Message::FeeFilter(data) => self.handle_fee_filter(data),
+            Message::Mempool(data) => self.handle_mempool(data),
+            Message::Alert(data) => self.handle_alert(data),
+            Message::Psbt(data) => self.handle_psbt(data),
+            Message::Inv(data) => self.handle_inv(data),
+            Message::GetBlock(data) => self.handle_get_blocks(data),
+            Message::GetData(data) => self.handle_get_data(data),
+            Message::NotFound(data) => self.handle_not_found(data),
+            Message::Tx(data) => self.handle_tx(data),
+            Message::StemTx(data) => self.handle_stem_tx(data),
+            Message::Version(data) => self.handle_version(data),
+            Message::Verack(data) => self.handle_verack(data),
+            Message::Rpc(data) => self.handle_rpc(data, &mut stream),
+        };
+
+        // 处理失败的消息计入发送方的封禁分数
+        if result.is_err() {
+            if let Some(addr) = &addr_from {
+                self.add_ban_score(addr, 1);
+            }
+        }
+
+        result
     }
 }
 
-fn cmd_to_bytes(cmd: &str) -> [u8; CMD_LEN] {
+pub fn cmd_to_bytes(cmd: &str) -> [u8; CMD_LEN] {
     let mut data = [0; CMD_LEN];
 
     for (i, d) in cmd.as_bytes().iter().enumerate() {
@@ -539,7 +3747,40 @@ fn cmd_to_bytes(cmd: &str) -> [u8; CMD_LEN] {
     data
 }
 
-fn bytes_to_cmd(bytes: &[u8]) -> Result<Message> {
+/**
+ * @desc 设置引导节点(host:port)，节点启动时向它请求区块，没有运行中节点的命令通过它广播交易
+ */
+pub fn set_bootstrap_node(node: Option<String>) {
+    *BOOTSTRAP_NODE.write().unwrap() = node;
+}
+
+/**
+ * @desc 当前的引导节点
+ */
+pub fn bootstrap_node() -> String {
+    BOOTSTRAP_NODE
+        .read()
+        .unwrap()
+        .clone()
+        .unwrap_or_else(|| format!("localhost:{}", network::params().default_port))
+}
+
+// 消息的命令名，用于流量统计
+fn cmd_name(bytes: &[u8]) -> String {
+    let cmd = &bytes[..CMD_LEN.min(bytes.len())];
+    String::from_utf8_lossy(cmd)
+        .trim_end_matches('\0')
+        .to_string()
+}
+
+/**
+ * @desc 解析收到的消息: 定长命令名 + bincode 编码的数据，不访问节点状态，输入不可信
+ */
+pub fn bytes_to_cmd(bytes: &[u8]) -> Result<Message> {
+    if bytes.len() < CMD_LEN {
+        return Err(format_err!("Message is shorter than the command."));
+    }
+
     let mut cmd = Vec::new();
     let cmd_bytes = &bytes[..CMD_LEN];
     let data = &bytes[CMD_LEN..];
@@ -558,6 +3799,24 @@ fn bytes_to_cmd(bytes: &[u8]) -> Result<Message> {
     } else if cmd == "block".as_bytes() {
         let data = deserialize(data)?;
         Ok(Message::Block(data))
+    } else if cmd == "cmpct_block".as_bytes() {
+        let data = deserialize(data)?;
+        Ok(Message::CmpctBlock(data))
+    } else if cmd == "get_blocktxn".as_bytes() {
+        let data = deserialize(data)?;
+        Ok(Message::GetBlockTxn(data))
+    } else if cmd == "block_txn".as_bytes() {
+        let data = deserialize(data)?;
+        Ok(Message::BlockTxn(data))
+    } else if cmd == "fee_filter".as_bytes() {
+        let data = deserialize(data)?;
+        Ok(Message::FeeFilter(data))
+    } else if cmd == "mempool".as_bytes() {
+        let data = deserialize(data)?;
+        Ok(Message::Mempool(data))
+    } else if cmd == RPC_CMD.as_bytes() {
+        let data = deserialize(data)?;
+        Ok(Message::Rpc(data))
     } else if cmd == "inv".as_bytes() {
         let data = deserialize(data)?;
         Ok(Message::Inv(data))
@@ -567,13 +3826,646 @@ fn bytes_to_cmd(bytes: &[u8]) -> Result<Message> {
     } else if cmd == "get_data".as_bytes() {
         let data = deserialize(data)?;
         Ok(Message::GetData(data))
+    } else if cmd == "not_found".as_bytes() {
+        let data = deserialize(data)?;
+        Ok(Message::NotFound(data))
     } else if cmd == "tx".as_bytes() {
         let data = deserialize(data)?;
         Ok(Message::Tx(data))
+    } else if cmd == "stem_tx".as_bytes() {
+        let data = deserialize(data)?;
+        Ok(Message::StemTx(data))
     } else if cmd == "version".as_bytes() {
         let data = deserialize(data)?;
         Ok(Message::Version(data))
+    } else if cmd == "verack".as_bytes() {
+        let data = deserialize(data)?;
+        Ok(Message::Verack(data))
+    } else if cmd == "alert".as_bytes() {
+        let data = deserialize(data)?;
+        Ok(Message::Alert(data))
+    } else if cmd == "psbt".as_bytes() {
+        let data = deserialize(data)?;
+        Ok(Message::Psbt(data))
     } else {
         Err(format_err!("Unknown command in the server."))
     }
 }
+
+/**
+ * @desc 开启 prefer 时，有洋葱节点可选就只保留洋葱节点
+ */
+fn prefer_onion_peers(peers: Vec<String>, prefer: bool) -> Vec<String> {
+    if prefer && peers.iter().any(|peer| tor::is_onion(peer)) {
+        peers
+            .into_iter()
+            .filter(|peer| tor::is_onion(peer))
+            .collect()
+    } else {
+        peers
+    }
+}
+
+/**
+ * @desc 交易在交易池中的全部祖先(直接或间接花费的未确认交易)
+ */
+fn mempool_ancestors(mempool: &HashMap<String, Transaction>, tx: &Transaction) -> HashSet<String> {
+    let mut ancestors = HashSet::new();
+    let mut stack: Vec<&Transaction> = vec![tx];
+
+    while let Some(tx) = stack.pop() {
+        for vin in &tx.vin {
+            if let Some(parent) = mempool.get(&vin.txid) {
+                if ancestors.insert(parent.id.clone()) {
+                    stack.push(parent);
+                }
+            }
+        }
+    }
+
+    ancestors
+}
+
+/**
+ * @desc 交易在交易池中的全部后代(直接或间接花费它的未确认交易)
+ */
+fn mempool_descendants(mempool: &HashMap<String, Transaction>, txid: &str) -> HashSet<String> {
+    let mut descendants = HashSet::new();
+    let mut stack = vec![txid.to_string()];
+
+    while let Some(id) = stack.pop() {
+        for child in mempool.values() {
+            if child.vin.iter().any(|vin| vin.txid == id) && descendants.insert(child.id.clone()) {
+                stack.push(child.id.clone());
+            }
+        }
+    }
+
+    descendants
+}
+
+/**
+ * @desc 交易池中和 tx 花费相同输出的其他交易
+ */
+fn mempool_conflicts(mempool: &HashMap<String, Transaction>, tx: &Transaction) -> HashSet<String> {
+    mempool
+        .values()
+        .filter(|other| {
+            other.id != tx.id
+                && other.vin.iter().any(|o| {
+                    tx.vin
+                        .iter()
+                        .any(|vin| vin.txid == o.txid && vin.vout == o.vout)
+                })
+        })
+        .map(|other| other.id.clone())
+        .collect()
+}
+
+/**
+ * @desc 检查交易加入交易池后，它的祖先链和每个祖先的后代都不超过限制
+ */
+fn check_mempool_limits(
+    mempool: &HashMap<String, Transaction>,
+    tx: &Transaction,
+    limits: &MempoolLimits,
+) -> Result<()> {
+    let tx_size = tx.get_size()?;
+    let size_of = |ids: &HashSet<String>| -> Result<usize> {
+        ids.iter()
+            .filter_map(|id| mempool.get(id))
+            .map(|tx| tx.get_size())
+            .sum()
+    };
+
+    let ancestors = mempool_ancestors(mempool, tx);
+    if ancestors.len() + 1 > limits.max_ancestors {
+        return Err(format_err!(
+            "Transaction {} has too many unconfirmed ancestors ({} > {}).",
+            tx.id,
+            ancestors.len() + 1,
+            limits.max_ancestors
+        ));
+    }
+    let ancestor_size = size_of(&ancestors)? + tx_size;
+    if ancestor_size > limits.max_ancestor_size {
+        return Err(format_err!(
+            "Transaction {} exceeds the ancestor size limit ({} > {}).",
+            tx.id,
+            ancestor_size,
+            limits.max_ancestor_size
+        ));
+    }
+
+    for ancestor in &ancestors {
+        let mut package = mempool_descendants(mempool, ancestor);
+        package.insert(ancestor.clone());
+        if package.len() + 1 > limits.max_descendants {
+            return Err(format_err!(
+                "Transaction {} would exceed the descendant limit of {} ({} > {}).",
+                tx.id,
+                ancestor,
+                package.len() + 1,
+                limits.max_descendants
+            ));
+        }
+        let package_size = size_of(&package)? + tx_size;
+        if package_size > limits.max_descendant_size {
+            return Err(format_err!(
+                "Transaction {} would exceed the descendant size limit of {} ({} > {}).",
+                tx.id,
+                ancestor,
+                package_size,
+                limits.max_descendant_size
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+/**
+ * @desc 移出进入交易池超过 expiry 毫秒的交易和它们的后代，返回被移出的交易
+ *
+ * 没有记录时间的交易(例如重组后返回交易池的交易)从 now 开始计时
+ */
+fn expire_mempool(
+    mempool: &mut HashMap<String, Transaction>,
+    times: &mut HashMap<String, u128>,
+    now: u128,
+    expiry: u128,
+) -> Vec<String> {
+    times.retain(|txid, _| mempool.contains_key(txid));
+    for txid in mempool.keys() {
+        times.entry(txid.clone()).or_insert(now);
+    }
+
+    let mut evicted = HashSet::new();
+    for (txid, time) in times.iter() {
+        if now.saturating_sub(*time) >= expiry {
+            evicted.extend(mempool_descendants(mempool, txid));
+            evicted.insert(txid.clone());
+        }
+    }
+    for txid in &evicted {
+        mempool.remove(txid);
+        times.remove(txid);
+    }
+
+    let mut evicted: Vec<String> = evicted.into_iter().collect();
+    evicted.sort();
+    evicted
+}
+
+/**
+ * @desc 移出收到超过 timeout 毫秒的部分区块，剩余数量仍超过 cap 时从最早收到的开始移出，返回被移出的区块
+ */
+fn prune_partial_blocks(
+    partial_blocks: &mut HashMap<String, (CompactBlock, Vec<Option<Transaction>>, u128)>,
+    now: u128,
+    timeout: u128,
+    cap: usize,
+) -> Vec<String> {
+    let mut received: Vec<(u128, String)> = partial_blocks
+        .iter()
+        .map(|(hash, (_, _, time))| (*time, hash.clone()))
+        .collect();
+    received.sort();
+
+    let excess = received.len().saturating_sub(cap);
+    let mut pruned = Vec::new();
+    for (i, (time, hash)) in received.into_iter().enumerate() {
+        if i < excess || now.saturating_sub(time) >= timeout {
+            partial_blocks.remove(&hash);
+            pruned.push(hash);
+        }
+    }
+    pruned
+}
+
+/**
+ * @desc 按手续费率从高到低排列待公告的交易并保留前 cap 个，返回超出的部分
+ *
+ * 已不在交易池中的交易(fee_rate 返回 None)排在最后
+ */
+fn prioritize_txs<F: Fn(&str) -> Option<i32>>(
+    txids: &mut Vec<String>,
+    cap: usize,
+    fee_rate: F,
+) -> Vec<String> {
+    let mut rated: Vec<(Option<i32>, String)> = txids
+        .drain(..)
+        .map(|txid| (fee_rate(&txid), txid))
+        .collect();
+    rated.sort_by_key(|(fee_rate, _)| std::cmp::Reverse(*fee_rate));
+
+    let rest = rated.split_off(cap.min(rated.len()));
+    txids.extend(rated.into_iter().map(|(_, txid)| txid));
+    rest.into_iter().map(|(_, txid)| txid).collect()
+}
+
+/**
+ * @desc 平均 mean 毫秒的指数分布随机间隔，最长不超过平均值的 5 倍
+ */
+fn trickle_delay(mean: u64) -> u128 {
+    let u: f64 = thread_rng().gen_range(f64::MIN_POSITIVE..1.0);
+    ((-u.ln() * mean as f64) as u128).min(mean as u128 * 5)
+}
+
+fn now_millis() -> u128 {
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map(|d| d.as_millis())
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        arbitrary,
+        transaction::{TXInput, TX_VERSION},
+    };
+
+    #[test]
+    fn test_mempool_limits() {
+        let spend = |parents: &[&str], id: &str| Transaction {
+            id: id.to_string(),
+            vin: parents
+                .iter()
+                .map(|parent| TXInput {
+                    txid: parent.to_string(),
+                    vout: 0,
+                    signature: Vec::new(),
+                    pub_key: Vec::new(),
+                })
+                .collect(),
+            vout: Vec::new(),
+            version: TX_VERSION,
+            relative_lock_time: 0,
+        };
+        let limits = MempoolLimits {
+            max_ancestors: 3,
+            max_ancestor_size: usize::MAX,
+            max_descendants: 3,
+            max_descendant_size: usize::MAX,
+        };
+
+        // 链 a <- b，c 花费 b 后祖先数为 3
+        let mut mempool = HashMap::new();
+        for tx in [spend(&["confirmed"], "a"), spend(&["a"], "b")] {
+            mempool.insert(tx.id.clone(), tx);
+        }
+        let c = spend(&["b"], "c");
+        assert_eq!(mempool_ancestors(&mempool, &c).len(), 2);
+        assert!(check_mempool_limits(&mempool, &c, &limits).is_ok());
+        mempool.insert(c.id.clone(), c);
+
+        assert!(check_mempool_limits(&mempool, &spend(&["c"], "d"), &limits).is_err());
+
+        // a 已有后代 b、c，再加一个直接花费 a 的交易超出后代限制
+        assert_eq!(mempool_descendants(&mempool, "a").len(), 2);
+        assert!(check_mempool_limits(&mempool, &spend(&["a"], "e"), &limits).is_err());
+        assert!(check_mempool_limits(&mempool, &spend(&["confirmed"], "f"), &limits).is_ok());
+
+        // 花费 confirmed:0 的 f 和 a 冲突，替换 a 时 a 的后代也被移出
+        let f = spend(&["confirmed"], "f");
+        assert_eq!(
+            mempool_conflicts(&mempool, &f),
+            HashSet::from([String::from("a")])
+        );
+        assert!(mempool_conflicts(&mempool, &spend(&["other"], "h")).is_empty());
+
+        let size = spend(&["b"], "g").get_size().unwrap();
+        let small = MempoolLimits {
+            max_ancestor_size: size,
+            ..MempoolLimits::default()
+        };
+        assert!(check_mempool_limits(&mempool, &spend(&["x"], "g"), &small).is_ok());
+        assert!(check_mempool_limits(&mempool, &spend(&["b"], "g"), &small).is_err());
+    }
+
+    #[test]
+    fn test_expire_mempool() {
+        let spend = |parent: &str, id: &str| Transaction {
+            id: id.to_string(),
+            vin: vec![TXInput {
+                txid: parent.to_string(),
+                vout: 0,
+                signature: Vec::new(),
+                pub_key: Vec::new(),
+            }],
+            vout: Vec::new(),
+            version: TX_VERSION,
+            relative_lock_time: 0,
+        };
+
+        let mut mempool = HashMap::new();
+        for tx in [spend("x", "a"), spend("a", "b"), spend("y", "c")] {
+            mempool.insert(tx.id.clone(), tx);
+        }
+        let mut times = HashMap::new();
+        times.insert(String::from("a"), 1000);
+        times.insert(String::from("b"), 5000);
+        times.insert(String::from("gone"), 0);
+
+        // c 没有记录时间，从现在开始计时；已不在交易池中的记录被清理
+        assert!(expire_mempool(&mut mempool, &mut times, 5000, 5000).is_empty());
+        assert_eq!(times.len(), 3);
+
+        // a 过期时它的后代 b 也被移出
+        assert_eq!(
+            expire_mempool(&mut mempool, &mut times, 6000, 5000),
+            vec![String::from("a"), String::from("b")]
+        );
+        assert_eq!(mempool.keys().collect::<Vec<_>>(), vec!["c"]);
+        assert_eq!(times.keys().collect::<Vec<_>>(), vec!["c"]);
+    }
+
+    #[test]
+    fn test_prune_partial_blocks() {
+        let block = arbitrary::block(&mut StdRng::seed_from_u64(0)).to_compact();
+        let mut partial_blocks = HashMap::new();
+        for (hash, time) in [("a", 1000), ("b", 3000), ("c", 2000), ("d", 4000)] {
+            partial_blocks.insert(hash.to_string(), (block.clone(), Vec::new(), time));
+        }
+
+        // 没有超时也没有超出上限时不移出
+        assert!(prune_partial_blocks(&mut partial_blocks, 4000, 5000, 4).is_empty());
+
+        // a 超时后被移出，剩余的仍超出上限时从最早收到的 c 开始移出
+        assert_eq!(
+            prune_partial_blocks(&mut partial_blocks, 6000, 5000, 2),
+            vec![String::from("a"), String::from("c")]
+        );
+        let mut rest: Vec<&String> = partial_blocks.keys().collect();
+        rest.sort();
+        assert_eq!(rest, vec!["b", "d"]);
+    }
+
+    #[test]
+    fn test_prioritize_txs() {
+        let rates: HashMap<&str, i32> = [("a", 5), ("b", 50), ("c", 10), ("d", 50)]
+            .into_iter()
+            .collect();
+        let mut txids: Vec<String> = ["a", "b", "c", "d", "gone"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+
+        let rest = prioritize_txs(&mut txids, 3, |txid| rates.get(txid).copied());
+        assert_eq!(txids, vec!["b", "d", "c"]);
+        assert_eq!(rest, vec!["a", "gone"]);
+
+        let rest = prioritize_txs(&mut txids, 10, |txid| rates.get(txid).copied());
+        assert_eq!(txids.len(), 3);
+        assert!(rest.is_empty());
+    }
+
+    #[test]
+    fn test_prefer_onion_peers() {
+        let peers = vec![
+            String::from("localhost:3001"),
+            String::from("abc.onion:3000"),
+        ];
+        assert_eq!(
+            prefer_onion_peers(peers.clone(), true),
+            vec!["abc.onion:3000"]
+        );
+        assert_eq!(prefer_onion_peers(peers.clone(), false), peers);
+
+        let clearnet = vec![String::from("localhost:3001")];
+        assert_eq!(prefer_onion_peers(clearnet.clone(), true), clearnet);
+    }
+
+    #[test]
+    fn test_cmd_name() {
+        assert_eq!(cmd_name(&wire("version", &1)), "version");
+        assert_eq!(cmd_name(&cmd_to_bytes("get_blocktxn")), "get_blocktxn");
+        assert_eq!(cmd_name(b"tx"), "tx");
+    }
+
+    #[test]
+    fn test_inventory_cache_evicts_least_recently_used() {
+        let mut cache = InventoryCache::new(2);
+        cache.insert("a");
+        cache.insert("b");
+        cache.insert("a");
+        cache.insert("c");
+
+        assert!(cache.contains("a"));
+        assert!(!cache.contains("b"));
+        assert!(cache.contains("c"));
+    }
+
+    #[test]
+    fn test_inventory_cache_tracks_sources() {
+        let mut cache = InventoryCache::new(2);
+        cache.insert_from("a", "localhost:3001");
+        cache.insert_from("a", "localhost:3002");
+        cache.insert("b");
+
+        assert!(cache.is_known_by("a", "localhost:3001"));
+        assert!(cache.is_known_by("a", "localhost:3002"));
+        assert!(!cache.is_known_by("b", "localhost:3001"));
+
+        // 淘汰时一起删除来源
+        cache.insert("c");
+        cache.insert_from("b", "localhost:3001");
+        assert!(!cache.is_known_by("a", "localhost:3001"));
+        assert!(cache.is_known_by("b", "localhost:3001"));
+    }
+
+    #[test]
+    fn test_trickle_delay() {
+        let delays: Vec<u128> = (0..10000).map(|_| trickle_delay(1000)).collect();
+        assert!(delays.iter().all(|d| *d <= 5000));
+
+        let mean = delays.iter().sum::<u128>() / delays.len() as u128;
+        assert!((900..1100).contains(&mean), "mean delay {}", mean);
+    }
+
+    fn wire<T: Serialize>(cmd: &str, msg: &T) -> Vec<u8> {
+        serialize(&(cmd_to_bytes(cmd), msg)).unwrap()
+    }
+
+    #[test]
+    fn test_message_roundtrip() {
+        arbitrary::check(|rng| {
+            let addr_from = arbitrary::string(rng, 32);
+            let messages = vec![
+                (
+                    "addr",
+                    Message::Addr(AddrMsg {
+                        addr_from: addr_from.clone(),
+                        nodes: arbitrary::strings(rng, 8),
+                        identity: arbitrary::bytes(rng, 32),
+                        signature: arbitrary::bytes(rng, 64),
+                    }),
+                ),
+                (
+                    "version",
+                    Message::Version(VersionMsg {
+                        addr_from: addr_from.clone(),
+                        version: rng.gen(),
+                        best_height: rng.gen(),
+                        timestamp: rng.gen(),
+                        magic: rng.gen(),
+                        reply: rng.gen(),
+                        identity: arbitrary::bytes(rng, 32),
+                        signature: arbitrary::bytes(rng, 64),
+                    }),
+                ),
+                (
+                    "verack",
+                    Message::Verack(VerackMsg {
+                        addr_from: addr_from.clone(),
+                    }),
+                ),
+                (
+                    "tx",
+                    Message::Tx(TxMsg {
+                        addr_from: addr_from.clone(),
+                        transaction: arbitrary::transaction(rng),
+                    }),
+                ),
+                (
+                    "stem_tx",
+                    Message::StemTx(TxMsg {
+                        addr_from: addr_from.clone(),
+                        transaction: arbitrary::transaction(rng),
+                    }),
+                ),
+                (
+                    "get_data",
+                    Message::GetData(GetDataMsg {
+                        addr_from: addr_from.clone(),
+                        kind: arbitrary::string(rng, 8),
+                        id: arbitrary::string(rng, 64),
+                    }),
+                ),
+                (
+                    "not_found",
+                    Message::NotFound(NotFoundMsg {
+                        addr_from: addr_from.clone(),
+                        kind: arbitrary::string(rng, 8),
+                        id: arbitrary::string(rng, 64),
+                    }),
+                ),
+                (
+                    "get_blocks",
+                    Message::GetBlock(GetBlockMsg {
+                        addr_from: addr_from.clone(),
+                        locator: arbitrary::strings(rng, 8),
+                    }),
+                ),
+                (
+                    "inv",
+                    Message::Inv(InvMsg {
+                        addr_from: addr_from.clone(),
+                        kind: arbitrary::string(rng, 8),
+                        items: arbitrary::strings(rng, 8),
+                    }),
+                ),
+                (
+                    "block",
+                    Message::Block(BlockMsg {
+                        addr_from: addr_from.clone(),
+                        block: arbitrary::block(rng),
+                    }),
+                ),
+                (
+                    "cmpct_block",
+                    Message::CmpctBlock(CmpctBlockMsg {
+                        addr_from: addr_from.clone(),
+                        block: arbitrary::block(rng).to_compact(),
+                    }),
+                ),
+                (
+                    "get_blocktxn",
+                    Message::GetBlockTxn(GetBlockTxnMsg {
+                        addr_from: addr_from.clone(),
+                        block_hash: arbitrary::string(rng, 64),
+                        indexes: (0..rng.gen_range(0..8)).map(|_| rng.gen()).collect(),
+                    }),
+                ),
+                (
+                    "block_txn",
+                    Message::BlockTxn(BlockTxnMsg {
+                        addr_from: addr_from.clone(),
+                        block_hash: arbitrary::string(rng, 64),
+                        transactions: (0..rng.gen_range(0..4))
+                            .map(|_| arbitrary::transaction(rng))
+                            .collect(),
+                    }),
+                ),
+                (
+                    "fee_filter",
+                    Message::FeeFilter(FeeFilterMsg {
+                        addr_from: addr_from.clone(),
+                        fee_rate: rng.gen(),
+                    }),
+                ),
+                (
+                    "alert",
+                    Message::Alert(AlertMsg {
+                        addr_from: addr_from.clone(),
+                        alert: Alert {
+                            id: rng.gen(),
+                            expiration: rng.gen(),
+                            message: arbitrary::string(rng, 32),
+                            signature: arbitrary::bytes(rng, 64),
+                        },
+                    }),
+                ),
+                (
+                    "psbt",
+                    Message::Psbt(PsbtMsg {
+                        addr_from: addr_from.clone(),
+                        psbt: Psbt {
+                            tx: arbitrary::transaction(rng),
+                            prev_txs: vec![arbitrary::transaction(rng)],
+                            signatures: vec![BTreeMap::from([(
+                                rng.gen_range(0..3),
+                                arbitrary::bytes(rng, 64),
+                            )])],
+                        },
+                    }),
+                ),
+                ("mempool", Message::Mempool(MempoolMsg { addr_from })),
+                (
+                    RPC_CMD,
+                    Message::Rpc(RpcCall {
+                        credentials: Some((arbitrary::string(rng, 16), arbitrary::string(rng, 16))),
+                        request: RpcRequest::SendRawTransaction(arbitrary::transaction(rng)),
+                    }),
+                ),
+            ];
+
+            for (cmd, message) in messages {
+                let data = match &message {
+                    Message::Addr(m) => wire(cmd, m),
+                    Message::Version(m) => wire(cmd, m),
+                    Message::Verack(m) => wire(cmd, m),
+                    Message::Tx(m) | Message::StemTx(m) => wire(cmd, m),
+                    Message::GetData(m) => wire(cmd, m),
+                    Message::NotFound(m) => wire(cmd, m),
+                    Message::GetBlock(m) => wire(cmd, m),
+                    Message::Inv(m) => wire(cmd, m),
+                    Message::Block(m) => wire(cmd, m),
+                    Message::CmpctBlock(m) => wire(cmd, m),
+                    Message::GetBlockTxn(m) => wire(cmd, m),
+                    Message::BlockTxn(m) => wire(cmd, m),
+                    Message::FeeFilter(m) => wire(cmd, m),
+                    Message::Mempool(m) => wire(cmd, m),
+                    Message::Alert(m) => wire(cmd, m),
+                    Message::Psbt(m) => wire(cmd, m),
+                    Message::Rpc(m) => wire(cmd, m),
+                };
+
+                let parsed = bytes_to_cmd(&data).unwrap();
+                assert_eq!(serialize(&parsed).unwrap(), serialize(&message).unwrap());
+            }
+        });
+    }
+}
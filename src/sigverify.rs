@@ -0,0 +1,263 @@
+use std::{
+    collections::{BTreeSet, HashMap, VecDeque},
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Mutex,
+    },
+    thread,
+};
+
+use crate::transaction::Transaction;
+
+// 签名缓存最多保存的已验证签名数
+const SIG_CACHE_CAPACITY: usize = 50_000;
+
+// 已验证通过的签名，交易进入交易池时验证过的签名在区块连接时不必重复验证
+static SIG_CACHE: Mutex<SigCache> = Mutex::new(SigCache {
+    capacity: SIG_CACHE_CAPACITY,
+    entries: BTreeSet::new(),
+    order: VecDeque::new(),
+});
+
+// 是否使用多个线程验证签名
+static PAR_VERIFY: AtomicBool = AtomicBool::new(true);
+
+/**
+ * @desc 开启或关闭并行验证签名
+ */
+pub fn set_par_verify(enabled: bool) {
+    PAR_VERIFY.store(enabled, Ordering::Relaxed);
+}
+
+pub fn par_verify() -> bool {
+    PAR_VERIFY.load(Ordering::Relaxed)
+}
+
+// 签名缓存的键: (交易 id, 输入序号, 公钥, sighash, 签名)
+// 交易 id 没有和交易内容核对过，所以签名本身也要作为键的一部分
+type SigCacheKey = (String, usize, Vec<u8>, String, Vec<u8>);
+
+// 容量有限的签名缓存，超出容量时淘汰最早加入的签名
+struct SigCache {
+    capacity: usize,
+    entries: BTreeSet<SigCacheKey>,
+    order: VecDeque<SigCacheKey>,
+}
+
+impl SigCache {
+    fn contains(&self, key: &SigCacheKey) -> bool {
+        self.entries.contains(key)
+    }
+
+    fn insert(&mut self, key: SigCacheKey) {
+        if !self.entries.insert(key.clone()) {
+            return;
+        }
+        self.order.push_back(key);
+
+        while self.order.len() > self.capacity {
+            if let Some(oldest) = self.order.pop_front() {
+                self.entries.remove(&oldest);
+            }
+        }
+    }
+}
+
+/**
+ * @desc 验证单个输入的签名，命中签名缓存时跳过签名验证，验证通过的签名加入缓存
+ */
+fn verify_input_cached(
+    tx: &Transaction,
+    in_id: usize,
+    prev_txs: &HashMap<String, Transaction>,
+) -> bool {
+    let sighash = match tx.signature_hash(in_id, prev_txs) {
+        Ok(sighash) => sighash,
+        Err(_) => return false,
+    };
+
+    let vin = &tx.vin[in_id];
+    let key = (
+        tx.id.clone(),
+        in_id,
+        vin.pub_key.clone(),
+        sighash,
+        vin.signature.clone(),
+    );
+    if SIG_CACHE.lock().unwrap().contains(&key) {
+        return true;
+    }
+
+    if !tx.verify_signature(in_id, &key.3) {
+        return false;
+    }
+    SIG_CACHE.lock().unwrap().insert(key);
+
+    true
+}
+
+/**
+ * @desc 验证多笔交易全部输入的签名，返回每笔交易是否有效，前序交易缺失的交易视为无效
+ *       abort_on_failure 时发现第一个无效输入后停止剩余的验证，此时只有结果中存在 false 是可信的
+ */
+pub fn verify_transactions(
+    txs: &[Transaction],
+    prev_txs: &HashMap<String, Transaction>,
+    abort_on_failure: bool,
+) -> Vec<bool> {
+    // 每个输入是一个任务: (交易序号, 输入序号)
+    let jobs: Vec<(usize, usize)> = txs
+        .iter()
+        .enumerate()
+        .filter(|(_, tx)| !tx.is_coinbase())
+        .flat_map(|(i, tx)| (0..tx.vin.len()).map(move |j| (i, j)))
+        .collect();
+
+    let valid: Vec<AtomicBool> = txs.iter().map(|_| AtomicBool::new(true)).collect();
+    let aborted = AtomicBool::new(false);
+    let next = AtomicUsize::new(0);
+
+    let worker = || loop {
+        let job = next.fetch_add(1, Ordering::Relaxed);
+        if job >= jobs.len() || aborted.load(Ordering::Relaxed) {
+            break;
+        }
+
+        let (tx, input) = jobs[job];
+        if !verify_input_cached(&txs[tx], input, prev_txs) {
+            valid[tx].store(false, Ordering::Relaxed);
+            if abort_on_failure {
+                aborted.store(true, Ordering::Relaxed);
+            }
+        }
+    };
+
+    let threads = if par_verify() {
+        thread::available_parallelism()
+            .map(|n| n.get())
+            .unwrap_or(1)
+            .min(jobs.len())
+    } else {
+        1
+    };
+
+    if threads <= 1 {
+        worker();
+    } else {
+        thread::scope(|s| {
+            for _ in 0..threads {
+                s.spawn(worker);
+            }
+        });
+    }
+
+    valid.into_iter().map(AtomicBool::into_inner).collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::transaction::{TXInput, TXOutput, TX_VERSION};
+
+    #[test]
+    fn test_missing_prev_tx_is_invalid() {
+        let coinbase = Transaction {
+            id: String::from("coinbase"),
+            vin: vec![TXInput {
+                txid: String::new(),
+                vout: -1,
+                signature: Vec::new(),
+                pub_key: Vec::new(),
+            }],
+            vout: Vec::new(),
+            version: TX_VERSION,
+            relative_lock_time: 0,
+        };
+        let spend = |txid: &str| Transaction {
+            id: String::from(txid),
+            vin: vec![TXInput {
+                txid: String::from("missing"),
+                vout: 0,
+                signature: vec![0; 64],
+                pub_key: vec![0; 32],
+            }],
+            vout: vec![TXOutput {
+                value: 1,
+                pub_key_hash: Vec::new(),
+            }],
+            version: TX_VERSION,
+            relative_lock_time: 0,
+        };
+        let txs = vec![coinbase, spend("a"), spend("b")];
+
+        for enabled in [true, false] {
+            set_par_verify(enabled);
+            assert_eq!(
+                verify_transactions(&txs, &HashMap::new(), false),
+                vec![true, false, false]
+            );
+        }
+        set_par_verify(true);
+    }
+
+    #[test]
+    fn test_sig_cache_evicts_oldest() {
+        let mut cache = SigCache {
+            capacity: 2,
+            entries: BTreeSet::new(),
+            order: VecDeque::new(),
+        };
+        let key = |id: &str| (String::from(id), 0, Vec::new(), String::new(), Vec::new());
+
+        cache.insert(key("a"));
+        cache.insert(key("b"));
+        cache.insert(key("a"));
+        cache.insert(key("c"));
+
+        assert!(!cache.contains(&key("a")));
+        assert!(cache.contains(&key("b")));
+        assert!(cache.contains(&key("c")));
+    }
+
+    #[test]
+    fn test_cached_signature_skips_verification() {
+        let prev = Transaction {
+            id: String::from("prev"),
+            vin: Vec::new(),
+            vout: vec![TXOutput {
+                value: 1,
+                pub_key_hash: vec![1; 20],
+            }],
+            version: TX_VERSION,
+            relative_lock_time: 0,
+        };
+        let mut tx = Transaction {
+            id: String::from("cached"),
+            vin: vec![TXInput {
+                txid: prev.id.clone(),
+                vout: 0,
+                signature: vec![2; 64],
+                pub_key: vec![3; 32],
+            }],
+            vout: Vec::new(),
+            version: TX_VERSION,
+            relative_lock_time: 0,
+        };
+        let mut prev_txs = HashMap::new();
+        prev_txs.insert(prev.id.clone(), prev);
+
+        let sighash = tx.signature_hash(0, &prev_txs).unwrap();
+        SIG_CACHE.lock().unwrap().insert((
+            tx.id.clone(),
+            0,
+            tx.vin[0].pub_key.clone(),
+            sighash,
+            tx.vin[0].signature.clone(),
+        ));
+        assert!(verify_input_cached(&tx, 0, &prev_txs));
+
+        // 同一交易 id 下换一个签名不能命中缓存
+        tx.vin[0].signature = vec![4; 64];
+        assert!(!verify_input_cached(&tx, 0, &prev_txs));
+    }
+}
@@ -0,0 +1,376 @@
+// 确定性的多节点模拟: 进程内运行多个使用临时数据库的节点，
+// 通过虚拟网络(可配置延迟、丢包和分区)转发区块，检查各节点最终收敛到同一条链。
+
+use std::collections::BTreeMap;
+
+use super::Result;
+use crate::{
+    block::Block, blockchain::Blockchain, network, rpc::ForkStats, transaction::Transaction,
+    versionbits, wallets::Wallet,
+};
+use log::info;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+// 虚拟网络参数，时间单位为毫秒
+#[derive(Debug, Clone, Copy)]
+pub struct NetworkConfig {
+    pub min_latency: u64,
+    pub max_latency: u64,
+    // 每条消息被丢弃的概率
+    pub loss: f64,
+}
+
+impl Default for NetworkConfig {
+    fn default() -> Self {
+        NetworkConfig {
+            min_latency: 10,
+            max_latency: 100,
+            loss: 0.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Payload {
+    Block(Block),
+    GetBlock(String),
+}
+
+#[derive(Debug, Clone)]
+struct Envelope {
+    from: usize,
+    to: usize,
+    payload: Payload,
+}
+
+struct SimNode {
+    chain: Blockchain,
+    address: String,
+    // 父区块未知的区块: hash -> 区块
+    orphans: BTreeMap<String, Block>,
+    // 和节点相同口径的回滚和孤立区块统计
+    forks: ForkStats,
+}
+
+pub struct Simulation {
+    nodes: Vec<SimNode>,
+    config: NetworkConfig,
+    rng: StdRng,
+    // 虚拟时间
+    now: u64,
+    // (送达时间, 序号) -> 消息，按送达顺序处理
+    queue: BTreeMap<(u64, u64), Envelope>,
+    seq: u64,
+    // 节点所在的分区，None 表示网络连通
+    partitions: Option<Vec<usize>>,
+}
+
+impl Simulation {
+    /**
+     * @desc 创建 n 个共享同一创世区块的节点，随机数由 seed 决定
+     */
+    pub fn new(n: usize, seed: u64, config: NetworkConfig) -> Result<Simulation> {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let cbtx = Transaction::new_coinbase(
+            Wallet::new(&mut rng).get_address(),
+            String::from("sim"),
+            &mut rng,
+        )?;
+        let genesis = Block::new(
+            vec![cbtx],
+            String::new(),
+            0,
+            versionbits::VERSIONBITS_TOP_BITS,
+            network::params().pow_limit_bits,
+        )?;
+
+        let mut nodes = Vec::new();
+        for _ in 0..n {
+            nodes.push(SimNode {
+                chain: Blockchain::create_temporary(&genesis)?,
+                address: Wallet::new(&mut rng).get_address(),
+                orphans: BTreeMap::new(),
+                forks: ForkStats::default(),
+            });
+        }
+
+        Ok(Simulation {
+            nodes,
+            config,
+            rng,
+            now: 0,
+            queue: BTreeMap::new(),
+            seq: 0,
+            partitions: None,
+        })
+    }
+
+    /**
+     * @desc 在节点的最新区块上挖出新区块并广播，返回区块 hash
+     */
+    pub fn mine(&mut self, node: usize) -> Result<String> {
+        let cbtx = Transaction::new_coinbase(
+            self.nodes[node].address.clone(),
+            String::new(),
+            &mut self.rng,
+        )?;
+        let block = self.nodes[node].chain.mine_block(vec![cbtx])?;
+        info!("Sim node {} mined block {}.", node, block.get_hash());
+
+        self.broadcast(node, &block);
+        Ok(block.get_hash())
+    }
+
+    /**
+     * @desc 把节点划分到不同分区，分区之间的消息全部丢弃
+     */
+    pub fn partition(&mut self, groups: &[&[usize]]) {
+        let mut partitions = vec![0; self.nodes.len()];
+        for (group, nodes) in groups.iter().enumerate() {
+            for &node in nodes.iter() {
+                partitions[node] = group;
+            }
+        }
+        self.partitions = Some(partitions);
+    }
+
+    /**
+     * @desc 恢复网络连通，各节点重新广播最新区块
+     */
+    pub fn heal(&mut self) -> Result<()> {
+        self.partitions = None;
+        self.announce_tips()
+    }
+
+    /**
+     * @desc 各节点向其他节点广播自己的最新区块
+     */
+    pub fn announce_tips(&mut self) -> Result<()> {
+        for node in 0..self.nodes.len() {
+            let chain = &self.nodes[node].chain;
+            let tip = chain.get_block(&chain.get_tip_hash())?;
+            self.broadcast(node, &tip);
+        }
+
+        Ok(())
+    }
+
+    /**
+     * @desc 按虚拟时间处理消息，直到没有待送达的消息
+     */
+    pub fn run_until_idle(&mut self) -> Result<()> {
+        while let Some((&(at, seq), _)) = self.queue.iter().next() {
+            let envelope = self.queue.remove(&(at, seq)).unwrap();
+            self.now = at;
+            self.deliver(envelope)?;
+        }
+
+        Ok(())
+    }
+
+    /**
+     * @desc 反复广播最新区块直到所有节点收敛，丢包时可能需要多轮
+     */
+    pub fn run_until_converged(&mut self, max_rounds: usize) -> Result<bool> {
+        for _ in 0..max_rounds {
+            self.run_until_idle()?;
+            if self.is_converged() {
+                return Ok(true);
+            }
+            self.announce_tips()?;
+        }
+
+        self.run_until_idle()?;
+        Ok(self.is_converged())
+    }
+
+    pub fn tip(&self, node: usize) -> String {
+        self.nodes[node].chain.get_tip_hash()
+    }
+
+    pub fn height(&self, node: usize) -> Result<i32> {
+        self.nodes[node].chain.get_best_height()
+    }
+
+    pub fn reorgs(&self, node: usize) -> usize {
+        self.nodes[node].forks.reorgs as usize
+    }
+
+    pub fn fork_stats(&self, node: usize) -> &ForkStats {
+        &self.nodes[node].forks
+    }
+
+    pub fn is_converged(&self) -> bool {
+        self.nodes
+            .iter()
+            .all(|node| node.chain.get_tip_hash() == self.tip(0))
+    }
+
+    fn broadcast(&mut self, from: usize, block: &Block) {
+        for to in 0..self.nodes.len() {
+            if to != from {
+                self.send(from, to, Payload::Block(block.clone()));
+            }
+        }
+    }
+
+    fn send(&mut self, from: usize, to: usize, payload: Payload) {
+        if let Some(partitions) = &self.partitions {
+            if partitions[from] != partitions[to] {
+                return;
+            }
+        }
+        if self.rng.gen_bool(self.config.loss) {
+            return;
+        }
+
+        let latency = self
+            .rng
+            .gen_range(self.config.min_latency..=self.config.max_latency);
+        self.seq += 1;
+        self.queue.insert(
+            (self.now + latency, self.seq),
+            Envelope { from, to, payload },
+        );
+    }
+
+    fn deliver(&mut self, envelope: Envelope) -> Result<()> {
+        match envelope.payload {
+            Payload::Block(block) => self.receive_block(envelope.to, envelope.from, block),
+            Payload::GetBlock(hash) => {
+                let chain = &self.nodes[envelope.to].chain;
+                if chain.has_block(&hash)? {
+                    let block = chain.get_block(&hash)?;
+                    self.send(envelope.to, envelope.from, Payload::Block(block));
+                }
+                Ok(())
+            }
+        }
+    }
+
+    /**
+     * @desc 接收区块: 父区块未知时暂存并向发送方请求缺失的祖先，否则加入链并连接等待中的后代
+     */
+    fn receive_block(&mut self, node: usize, from: usize, block: Block) -> Result<()> {
+        let hash = block.get_hash();
+        if self.nodes[node].chain.has_block(&hash)? {
+            return Ok(());
+        }
+
+        if !self.nodes[node].chain.has_block(&block.get_prev_hash())? {
+            let missing = self.missing_ancestor(node, &block);
+            self.nodes[node].orphans.insert(hash, block);
+            self.send(node, from, Payload::GetBlock(missing));
+            return Ok(());
+        }
+
+        let mut pending = vec![block];
+        while let Some(block) = pending.pop() {
+            let hash = block.get_hash();
+            let sim_node = &mut self.nodes[node];
+            let old_tip = sim_node.chain.get_tip_hash();
+            let old_height = sim_node.chain.get_best_height()?;
+            sim_node.chain.add_block(block.clone())?;
+            let (disconnected, connected) = if sim_node.chain.get_tip_hash() == old_tip {
+                (0, 0)
+            } else {
+                let depth = sim_node.chain.reorg_depth(&old_tip)?;
+                (
+                    depth,
+                    sim_node.chain.get_best_height()? - (old_height - depth),
+                )
+            };
+            sim_node
+                .forks
+                .record_block(disconnected as u64, connected as u64);
+
+            let children: Vec<String> = sim_node
+                .orphans
+                .iter()
+                .filter(|(_, orphan)| orphan.get_prev_hash() == hash)
+                .map(|(child, _)| child.clone())
+                .collect();
+            for child in children {
+                pending.push(sim_node.orphans.remove(&child).unwrap());
+            }
+        }
+
+        Ok(())
+    }
+
+    // 沿暂存区块向前找到第一个缺失的祖先
+    fn missing_ancestor(&self, node: usize, block: &Block) -> String {
+        let orphans = &self.nodes[node].orphans;
+        let mut prev = block.get_prev_hash();
+        while let Some(orphan) = orphans.get(&prev) {
+            prev = orphan.get_prev_hash();
+        }
+        prev
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_mining_race() {
+        let mut sim = Simulation::new(4, 1, NetworkConfig::default()).unwrap();
+
+        // 两个节点同时挖出高度 1 的区块，下一个区块决定胜出的分支
+        sim.mine(0).unwrap();
+        sim.mine(3).unwrap();
+        sim.run_until_idle().unwrap();
+        let winner = sim.mine(1).unwrap();
+
+        assert!(sim.run_until_converged(5).unwrap());
+        assert_eq!(sim.tip(2), winner);
+        assert_eq!(sim.height(2).unwrap(), 2);
+    }
+
+    #[test]
+    fn test_partition_heal() {
+        let mut sim = Simulation::new(4, 2, NetworkConfig::default()).unwrap();
+        sim.partition(&[&[0, 1], &[2, 3]]);
+
+        sim.mine(0).unwrap();
+        sim.mine(2).unwrap();
+        sim.run_until_idle().unwrap();
+        let longest = sim.mine(3).unwrap();
+        sim.run_until_idle().unwrap();
+        assert!(!sim.is_converged());
+
+        sim.heal().unwrap();
+        assert!(sim.run_until_converged(5).unwrap());
+        assert_eq!(sim.tip(0), longest);
+        assert_eq!(sim.reorgs(0), 1);
+        assert_eq!(sim.reorgs(3), 0);
+
+        // 节点 0 自己挖出的区块被回滚，节点 2、3 的分支整体接入主链
+        let stats = sim.fork_stats(0);
+        assert_eq!(stats.max_reorg_depth, 1);
+        assert_eq!(stats.orphan_blocks, 1);
+        assert_eq!(stats.blocks_received, 2);
+        assert_eq!(stats.orphan_rate(), 0.5);
+        assert_eq!(sim.fork_stats(1).orphan_blocks, 1);
+        // 节点 2 只收到一个落败的分叉区块
+        assert_eq!(sim.fork_stats(2).orphan_blocks, 1);
+        assert_eq!(sim.fork_stats(2).max_reorg_depth, 0);
+    }
+
+    #[test]
+    fn test_packet_loss() {
+        let config = NetworkConfig {
+            loss: 0.3,
+            ..NetworkConfig::default()
+        };
+        let mut sim = Simulation::new(5, 3, config).unwrap();
+
+        for i in 0..6 {
+            sim.mine(i % 5).unwrap();
+            sim.run_until_idle().unwrap();
+        }
+
+        assert!(sim.run_until_converged(20).unwrap());
+    }
+}
@@ -0,0 +1,326 @@
+use super::Result;
+use crate::{
+    block,
+    blockchain::Blockchain,
+    rpc::{BlockStats, ChainStats, WindowStats},
+};
+use failure::format_err;
+
+/**
+ * @desc 按高度或区块 hash 查找主链上的区块 hash
+ */
+pub fn resolve_block(bc: &Blockchain, block: &str) -> Result<String> {
+    if let Ok(height) = block.parse::<i32>() {
+        return bc
+            .get_block_hash_at(height)?
+            .ok_or_else(|| format_err!("Block at height {} is not found.", height));
+    }
+    if !bc.has_block(block)? {
+        return Err(format_err!("Block {} is not found.", block));
+    }
+
+    Ok(block.to_string())
+}
+
+/**
+ * @desc 统计单个区块: 交易数、手续费、费率分布、大小和 coinbase 产出
+ */
+pub fn get_block_stats(bc: &Blockchain, block_hash: &str) -> Result<BlockStats> {
+    Ok(collect_block_stats(bc, block_hash)?.0)
+}
+
+/**
+ * @desc 统计单个区块，同时返回区块中每笔非 coinbase 交易的费率
+ */
+fn collect_block_stats(bc: &Blockchain, block_hash: &str) -> Result<(BlockStats, Vec<i32>)> {
+    let block = bc.get_block(block_hash)?;
+
+    let mut total_fee = 0;
+    let mut fee_rates = Vec::new();
+    let mut subsidy = 0;
+    for tx in block.get_transaction() {
+        if tx.is_coinbase() {
+            subsidy += tx.vout.iter().map(|out| out.value).sum::<i32>();
+            continue;
+        }
+        total_fee += bc.get_tx_fee(tx)?;
+        fee_rates.push(bc.get_fee_rate(tx)?);
+    }
+    let (min_fee_rate, median_fee_rate, max_fee_rate) = summarize_fee_rates(&mut fee_rates);
+
+    let stats = BlockStats {
+        hash: block.get_hash(),
+        height: block.get_height(),
+        timestamp: block.get_timestamp(),
+        tx_count: block.get_transaction().len(),
+        size: block.get_size()?,
+        total_fee,
+        min_fee_rate,
+        median_fee_rate,
+        max_fee_rate,
+        subsidy,
+    };
+
+    Ok((stats, fee_rates))
+}
+
+/**
+ * @desc 统计主链上 [from, to] 高度范围内的区块，费率分布按范围内全部交易计算
+ */
+pub fn get_chain_stats(bc: &Blockchain, from: i32, to: i32) -> Result<ChainStats> {
+    check_height_range(bc, from, to)?;
+
+    let mut stats = ChainStats {
+        from_height: from,
+        to_height: to,
+        block_count: 0,
+        tx_count: 0,
+        total_size: 0,
+        total_fee: 0,
+        total_subsidy: 0,
+        min_fee_rate: 0,
+        median_fee_rate: 0,
+        max_fee_rate: 0,
+        avg_block_interval: 0,
+    };
+    let mut fee_rates = Vec::new();
+    let mut timestamps = (0, 0);
+    for height in from..=to {
+        let block_hash = resolve_block(bc, &height.to_string())?;
+        let (block_stats, block_fee_rates) = collect_block_stats(bc, &block_hash)?;
+
+        stats.block_count += 1;
+        stats.tx_count += block_stats.tx_count;
+        stats.total_size += block_stats.size;
+        stats.total_fee += block_stats.total_fee;
+        stats.total_subsidy += block_stats.subsidy;
+        fee_rates.extend(block_fee_rates);
+
+        if height == from {
+            timestamps.0 = block_stats.timestamp;
+        }
+        timestamps.1 = block_stats.timestamp;
+    }
+
+    let (min_fee_rate, median_fee_rate, max_fee_rate) = summarize_fee_rates(&mut fee_rates);
+    stats.min_fee_rate = min_fee_rate;
+    stats.median_fee_rate = median_fee_rate;
+    stats.max_fee_rate = max_fee_rate;
+    if to > from {
+        // 时间戳为毫秒
+        stats.avg_block_interval =
+            (timestamps.1.saturating_sub(timestamps.0) / (to - from) as u128 / 1000) as u64;
+    }
+
+    Ok(stats)
+}
+
+/**
+ * @desc 把主链上 [from, to] 高度范围按每 window 个区块分组，统计每组的出块间隔、难度、交易吞吐量和手续费；
+ *       最后一组可能不足 window 个区块
+ */
+pub fn get_chain_history(
+    bc: &Blockchain,
+    from: i32,
+    to: i32,
+    window: i32,
+) -> Result<Vec<WindowStats>> {
+    check_height_range(bc, from, to)?;
+    if window <= 0 {
+        return Err(format_err!("Invalid window size {}.", window));
+    }
+
+    // 第一个区块的出块间隔从它的父区块算起，创世区块没有出块间隔
+    let mut prev_timestamp = match from {
+        0 => None,
+        _ => Some(
+            bc.get_header(&resolve_block(bc, &(from - 1).to_string())?)?
+                .timestamp,
+        ),
+    };
+    let mut windows = Vec::new();
+    let mut start = from;
+    while start <= to {
+        let end = to.min(start.saturating_add(window - 1));
+
+        let mut intervals = Vec::new();
+        let mut total_work = 0u128;
+        let mut tx_count = 0;
+        let mut total_fee = 0;
+        for height in start..=end {
+            let block_hash = resolve_block(bc, &height.to_string())?;
+            let header = bc.get_header(&block_hash)?;
+            let (block_stats, fee_rates) = collect_block_stats(bc, &block_hash)?;
+
+            if let Some(prev) = prev_timestamp {
+                intervals.push(header.timestamp.saturating_sub(prev));
+            }
+            prev_timestamp = Some(header.timestamp);
+            total_work = total_work.saturating_add(block::block_work(header.bits));
+            tx_count += fee_rates.len();
+            total_fee += block_stats.total_fee;
+        }
+
+        // 时间戳为毫秒
+        let elapsed = intervals.iter().sum::<u128>() as f64 / 1000.0;
+        windows.push(WindowStats {
+            from_height: start,
+            to_height: end,
+            avg_block_interval: match intervals.len() {
+                0 => 0.0,
+                count => elapsed / count as f64,
+            },
+            median_block_interval: median(&mut intervals) as f64 / 1000.0,
+            difficulty: total_work / (end - start + 1) as u128,
+            tx_count,
+            tx_per_sec: if elapsed > 0.0 {
+                tx_count as f64 / elapsed
+            } else {
+                0.0
+            },
+            total_fee,
+        });
+        start = end + 1;
+    }
+
+    Ok(windows)
+}
+
+/**
+ * @desc 分组统计导出为 CSV，第一行是列名
+ */
+pub fn history_to_csv(windows: &[WindowStats]) -> String {
+    let mut csv = String::from(
+        "from_height,to_height,avg_block_interval,median_block_interval,difficulty,tx_count,tx_per_sec,total_fee\n",
+    );
+    for w in windows {
+        csv.push_str(&format!(
+            "{},{},{:.3},{:.3},{},{},{:.3},{}\n",
+            w.from_height,
+            w.to_height,
+            w.avg_block_interval,
+            w.median_block_interval,
+            w.difficulty,
+            w.tx_count,
+            w.tx_per_sec,
+            w.total_fee
+        ));
+    }
+
+    csv
+}
+
+fn check_height_range(bc: &Blockchain, from: i32, to: i32) -> Result<()> {
+    let best_height = bc.get_best_height()?;
+    if from < 0 || from > to || to > best_height {
+        return Err(format_err!(
+            "Invalid height range {}..{}, best height is {}.",
+            from,
+            to,
+            best_height
+        ));
+    }
+
+    Ok(())
+}
+
+// 出块间隔的中位数(毫秒)，没有间隔时为 0
+fn median(intervals: &mut [u128]) -> u128 {
+    if intervals.is_empty() {
+        return 0;
+    }
+    intervals.sort_unstable();
+
+    let mid = intervals.len() / 2;
+    if intervals.len().is_multiple_of(2) {
+        (intervals[mid - 1] + intervals[mid]) / 2
+    } else {
+        intervals[mid]
+    }
+}
+
+/**
+ * @desc 费率的最小值、中位数和最大值，没有交易时都为 0
+ */
+fn summarize_fee_rates(fee_rates: &mut [i32]) -> (i32, i32, i32) {
+    if fee_rates.is_empty() {
+        return (0, 0, 0);
+    }
+    fee_rates.sort_unstable();
+
+    let mid = fee_rates.len() / 2;
+    let median = if fee_rates.len().is_multiple_of(2) {
+        (fee_rates[mid - 1] + fee_rates[mid]) / 2
+    } else {
+        fee_rates[mid]
+    };
+
+    (fee_rates[0], median, fee_rates[fee_rates.len() - 1])
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        genesis::GenesisSpec,
+        transaction::{Transaction, SUBSIDY},
+        wallets::Wallet,
+    };
+    use rand_core::OsRng;
+
+    #[test]
+    fn test_summarize_fee_rates() {
+        assert_eq!(summarize_fee_rates(&mut []), (0, 0, 0));
+        assert_eq!(summarize_fee_rates(&mut [30, 10, 20]), (10, 20, 30));
+        assert_eq!(summarize_fee_rates(&mut [40, 10, 20, 30]), (10, 25, 40));
+    }
+
+    #[test]
+    fn test_block_and_chain_stats() {
+        let address = Wallet::default().get_address();
+        let mut spec = GenesisSpec::default();
+        spec.allocations.push((address.clone(), 100));
+        let bc = Blockchain::create_temporary(&spec.build().unwrap()).unwrap();
+        for _ in 0..2 {
+            let cbtx =
+                Transaction::new_coinbase(address.clone(), String::new(), &mut OsRng).unwrap();
+            bc.mine_block(vec![cbtx]).unwrap();
+        }
+
+        let genesis = get_block_stats(&bc, &resolve_block(&bc, "0").unwrap()).unwrap();
+        assert_eq!(genesis.height, 0);
+        assert_eq!(genesis.subsidy, 100);
+        assert_eq!(genesis.total_fee, 0);
+
+        let tip = bc.get_tip_hash();
+        let stats = get_block_stats(&bc, &resolve_block(&bc, &tip).unwrap()).unwrap();
+        assert_eq!(
+            (stats.height, stats.tx_count, stats.subsidy),
+            (2, 1, SUBSIDY)
+        );
+        assert!(stats.size > 0);
+
+        let chain = get_chain_stats(&bc, 1, 2).unwrap();
+        assert_eq!((chain.block_count, chain.tx_count), (2, 2));
+        assert_eq!(chain.total_subsidy, 2 * SUBSIDY);
+
+        assert!(get_chain_stats(&bc, 2, 3).is_err());
+
+        // 三个区块按每两个分组，第二组只有一个区块
+        let history = get_chain_history(&bc, 0, 2, 2).unwrap();
+        assert_eq!(history.len(), 2);
+        assert_eq!((history[0].from_height, history[0].to_height), (0, 1));
+        assert_eq!((history[1].from_height, history[1].to_height), (2, 2));
+        assert!(history.iter().all(|w| w.tx_count == 0 && w.difficulty > 0));
+        assert!(history[1].avg_block_interval >= 0.0);
+        assert_eq!(get_chain_history(&bc, 1, 2, 5).unwrap().len(), 1);
+        assert!(get_chain_history(&bc, 0, 2, 0).is_err());
+
+        let csv = history_to_csv(&history);
+        assert_eq!(csv.lines().count(), 3);
+        assert!(csv.starts_with("from_height,to_height,"));
+        assert!(csv.lines().nth(2).unwrap().starts_with("2,2,"));
+        assert!(resolve_block(&bc, "5").is_err());
+        assert!(resolve_block(&bc, "not-a-block").is_err());
+    }
+}
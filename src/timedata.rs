@@ -0,0 +1,101 @@
+use std::{sync::Mutex, time::SystemTime};
+
+use log::warn;
+
+// 网络调整时间: 本地时间加上各节点时间偏移(毫秒)的中位数
+const MAX_TIME_SAMPLES: usize = 200;
+const MIN_TIME_SAMPLES: usize = 5;
+// 偏移超过 70 分钟时不再调整，并提示本地时钟可能有误
+const MAX_TIME_ADJUSTMENT: i64 = 70 * 60 * 1000;
+
+struct TimeData {
+    samples: Vec<(String, i64)>,
+    offset: i64,
+    warned: bool,
+}
+
+static TIME_DATA: Mutex<TimeData> = Mutex::new(TimeData {
+    samples: Vec::new(),
+    offset: 0,
+    warned: false,
+});
+
+/**
+ * @desc 记录节点在握手时报告的时间偏移，每个节点只记录一次
+ */
+pub fn add_time_sample(peer: &str, offset: i64) {
+    let mut data = TIME_DATA.lock().unwrap();
+    if data.samples.iter().any(|(addr, _)| addr == peer) {
+        return;
+    }
+
+    if data.samples.len() >= MAX_TIME_SAMPLES {
+        data.samples.remove(0);
+    }
+    data.samples.push((peer.to_string(), offset));
+
+    let offsets: Vec<i64> = data.samples.iter().map(|(_, offset)| *offset).collect();
+    if offsets.len() < MIN_TIME_SAMPLES {
+        return;
+    }
+
+    match median_offset(offsets) {
+        Some(offset) => data.offset = offset,
+        None => {
+            data.offset = 0;
+            if !data.warned {
+                data.warned = true;
+                warn!("Peers' time differs from the local clock by more than 70 minutes, please check that your date and time are correct!");
+            }
+        }
+    }
+}
+
+/**
+ * @desc 当前使用的时间偏移(毫秒)
+ */
+pub fn get_time_offset() -> i64 {
+    TIME_DATA.lock().unwrap().offset
+}
+
+/**
+ * @desc 网络调整后的当前时间(毫秒)
+ */
+pub fn get_adjusted_time() -> u128 {
+    let now = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map(|d| d.as_millis() as i64)
+        .unwrap_or(0);
+
+    (now + get_time_offset()).max(0) as u128
+}
+
+/**
+ * @desc 偏移的中位数，超过允许的调整范围时返回 None
+ */
+fn median_offset(mut offsets: Vec<i64>) -> Option<i64> {
+    offsets.sort_unstable();
+    let median = offsets[offsets.len() / 2];
+
+    if median.abs() > MAX_TIME_ADJUSTMENT {
+        None
+    } else {
+        Some(median)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_median_offset() {
+        assert_eq!(median_offset(vec![0]), Some(0));
+        assert_eq!(median_offset(vec![-3000, 10, 2000, 5, -1]), Some(5));
+        assert_eq!(median_offset(vec![MAX_TIME_ADJUSTMENT + 1; 5]), None);
+        assert_eq!(
+            median_offset(vec![-MAX_TIME_ADJUSTMENT, 0, -MAX_TIME_ADJUSTMENT]),
+            Some(-MAX_TIME_ADJUSTMENT)
+        );
+    }
+}
@@ -0,0 +1,158 @@
+// Tor 洋葱服务: 通过本机 Tor 的控制端口为 P2P 监听端口创建洋葱服务，
+// 服务随控制连接存在，连接断开后 Tor 自动删除；连接洋葱节点需要把 --proxy 指向 Tor 的 SOCKS 端口
+
+use std::{
+    io::{prelude::Write, BufRead, BufReader},
+    net::TcpStream,
+};
+
+use super::Result;
+use failure::format_err;
+
+pub const DEFAULT_CONTROL_ADDR: &str = "127.0.0.1:9051";
+const REPLY_OK: &str = "250";
+
+// 已创建的洋葱服务，保持控制连接
+pub struct OnionService {
+    pub address: String,
+    control: BufReader<TcpStream>,
+}
+
+impl OnionService {
+    /**
+     * @desc 阻塞直到控制连接断开，期间洋葱服务一直有效
+     */
+    pub fn wait(mut self) {
+        let mut line = String::new();
+        while matches!(self.control.read_line(&mut line), Ok(n) if n > 0) {
+            line.clear();
+        }
+    }
+}
+
+/**
+ * @desc 连接控制端口并认证，为本机 port 端口创建洋葱服务，洋葱地址的端口和本机端口相同；
+ *       没有密码时使用无认证方式
+ */
+pub fn add_onion(control: &str, password: Option<&str>, port: u16) -> Result<OnionService> {
+    let stream = TcpStream::connect(control)?;
+    let mut writer = stream.try_clone()?;
+    let mut reader = BufReader::new(stream);
+
+    let auth = match password {
+        Some(password) => format!("AUTHENTICATE {}\r\n", quote(password)),
+        None => String::from("AUTHENTICATE\r\n"),
+    };
+    writer.write_all(auth.as_bytes())?;
+    read_reply(&mut reader)?;
+
+    // 不需要保存私钥，每次启动使用新的洋葱地址
+    writer.write_all(
+        format!(
+            "ADD_ONION NEW:ED25519-V3 Flags=DiscardPK Port={},127.0.0.1:{}\r\n",
+            port, port
+        )
+        .as_bytes(),
+    )?;
+    let service_id = read_reply(&mut reader)?
+        .iter()
+        .find_map(|line| line.strip_prefix("ServiceID=").map(String::from))
+        .ok_or_else(|| format_err!("Tor did not return the onion service id."))?;
+
+    Ok(OnionService {
+        address: format!("{}.onion:{}", service_id, port),
+        control: reader,
+    })
+}
+
+/**
+ * @desc 读取一条回复，返回各行去掉状态码后的内容，状态码不是 250 时报错
+ */
+fn read_reply(reader: &mut impl BufRead) -> Result<Vec<String>> {
+    let mut lines = Vec::new();
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line)? == 0 {
+            return Err(format_err!("Tor control connection closed."));
+        }
+
+        let line = line.trim_end();
+        if line.len() < 4 || !line.is_char_boundary(4) {
+            return Err(format_err!("Invalid Tor control reply: {}", line));
+        }
+        if &line[..3] != REPLY_OK {
+            return Err(format_err!("Tor control error: {}", line));
+        }
+        lines.push(line[4..].to_string());
+
+        // "250-" 后面还有行，"250 " 是最后一行
+        if &line[3..4] == " " {
+            return Ok(lines);
+        }
+    }
+}
+
+fn quote(text: &str) -> String {
+    format!("\"{}\"", text.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/**
+ * @desc 节点地址(host:port)是否是洋葱地址
+ */
+pub fn is_onion(addr: &str) -> bool {
+    let host = addr.rsplit_once(':').map_or(addr, |(host, _)| host);
+    host.trim().ends_with(".onion")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::{net::TcpListener, thread};
+
+    #[test]
+    fn test_add_onion() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let control = listener.local_addr().unwrap().to_string();
+
+        let handle = thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut writer = stream.try_clone().unwrap();
+            let mut reader = BufReader::new(stream);
+
+            let mut line = String::new();
+            reader.read_line(&mut line).unwrap();
+            assert_eq!(line, "AUTHENTICATE \"pa\\\"ss\"\r\n");
+            writer.write_all(b"250 OK\r\n").unwrap();
+
+            line.clear();
+            reader.read_line(&mut line).unwrap();
+            assert_eq!(
+                line,
+                "ADD_ONION NEW:ED25519-V3 Flags=DiscardPK Port=3001,127.0.0.1:3001\r\n"
+            );
+            writer
+                .write_all(b"250-ServiceID=abcdef\r\n250 OK\r\n")
+                .unwrap();
+        });
+
+        let service = add_onion(&control, Some("pa\"ss"), 3001).unwrap();
+        assert_eq!(service.address, "abcdef.onion:3001");
+        handle.join().unwrap();
+        // 控制端口关闭后返回
+        service.wait();
+    }
+
+    #[test]
+    fn test_read_reply() {
+        let mut reply = "250-a\r\n250 OK\r\n".as_bytes();
+        assert_eq!(read_reply(&mut reply).unwrap(), vec!["a", "OK"]);
+
+        let mut reply = "515 Authentication failed\r\n".as_bytes();
+        assert!(read_reply(&mut reply).is_err());
+        let mut reply = "250-a\r\n".as_bytes();
+        assert!(read_reply(&mut reply).is_err());
+
+        assert!(is_onion("abcdef.onion:3000"));
+        assert!(!is_onion("localhost:3000"));
+    }
+}
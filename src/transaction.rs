@@ -1,16 +1,55 @@
-use std::collections::HashMap;
+use std::{
+    collections::HashMap,
+    sync::atomic::{AtomicBool, Ordering},
+};
 
 use super::Result;
-use crate::{utxo_set::*, wallets::*};
-use bincode::serialize;
-use bitcoincash_addr::Address;
+use crate::{
+    anchor::Anchor,
+    descriptor::Descriptor,
+    governance::{GovernanceRecord, TX_VERSION_GOVERNANCE},
+    names::{NameRecord, TX_VERSION_NAME},
+    network,
+    utxo_set::*,
+    vm::{self, TX_VERSION_PROGRAM},
+    wallets::*,
+};
+use bincode::{serialize, Options};
 use crypto::{digest::Digest, ed25519, sha2::Sha256};
 use failure::format_err;
 use log::{debug, error, info};
-use rand_core::{OsRng, RngCore};
+use rand_core::RngCore;
 use serde::{Deserialize, Serialize};
 
-const SUBSIDY: i32 = 10;
+pub const SUBSIDY: i32 = 10;
+
+// 新建交易的版本
+pub const TX_VERSION: i32 = 1;
+// 从版本 2 起启用相对锁定时间
+pub const TX_VERSION_RELATIVE_LOCK: i32 = 2;
+// 从版本 5 起输入可以花费多签输出: 公钥字段是多签脚本，签名字段是按公钥顺序排列的 k 个签名
+pub const TX_VERSION_MULTISIG: i32 = 5;
+// 交易池接受和转发的最高版本，更高版本的交易在区块中仍然有效，留给以后的升级
+pub const MAX_STANDARD_TX_VERSION: i32 = 6;
+const SIGNATURE_LEN: usize = 64;
+// coinbase 数据末尾的随机数长度
+const COINBASE_NONCE_LEN: usize = 32;
+// 矿工标签的最大长度(字节)，标签写在 coinbase 数据开头，形如 "/alice-node/"
+pub const MAX_COINBASE_TAG_LEN: usize = 32;
+
+// 新建的转账交易是否按规范顺序排列输入和输出，避免交易结构暴露钱包选择输入、放置找零的方式
+static CANONICAL_ORDER: AtomicBool = AtomicBool::new(true);
+
+/**
+ * @desc 开启或关闭规范排序，需要固定顺序(付款在前、找零在后)的测试可以关闭
+ */
+pub fn set_canonical_order(enabled: bool) {
+    CANONICAL_ORDER.store(enabled, Ordering::Relaxed);
+}
+
+pub fn canonical_order() -> bool {
+    CANONICAL_ORDER.load(Ordering::Relaxed)
+}
 
 // 输入
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -28,6 +67,18 @@ pub struct TXOutput {
     pub pub_key_hash: Vec<u8>,
 }
 
+impl TXInput {
+    /**
+     * @desc 判断输入是否由公钥哈希对应的密钥签名
+     */
+    pub fn uses_key(&self, pub_key_hash: &[u8]) -> bool {
+        let mut locking_hash = self.pub_key.clone();
+        hash_pub_key(&mut locking_hash);
+
+        locking_hash == pub_key_hash
+    }
+}
+
 impl TXOutput {
     pub fn new(value: i32, address: String) -> Result<Self> {
         let mut txo = TXOutput {
@@ -46,7 +97,7 @@ impl TXOutput {
     fn lock(&mut self, address: &str) -> Result<()> {
         println!("Please input base58 address.");
 
-        let pub_key_hash = Address::decode(address).unwrap().body;
+        let pub_key_hash = decode_address(address)?;
         debug!("lock: {}", address);
         self.pub_key_hash = pub_key_hash;
 
@@ -65,11 +116,39 @@ pub struct Transaction {
     pub id: String,
     pub vin: Vec<TXInput>,
     pub vout: Vec<TXOutput>,
+    pub version: i32,
+    // 版本 2 起有效: 所有输入引用的交易至少要被确认这么多个区块后，交易才能进入区块
+    pub relative_lock_time: u32,
+}
+
+// 加入版本字段之前的交易格式，用于读取旧数据
+#[derive(Serialize, Deserialize)]
+pub(crate) struct LegacyTransaction {
+    id: String,
+    vin: Vec<TXInput>,
+    vout: Vec<TXOutput>,
+}
+
+impl From<LegacyTransaction> for Transaction {
+    fn from(tx: LegacyTransaction) -> Transaction {
+        Transaction {
+            id: tx.id,
+            vin: tx.vin,
+            vout: tx.vout,
+            version: 1,
+            relative_lock_time: 0,
+        }
+    }
 }
 
 impl Transaction {
     // 生成一笔新的交易
-    pub fn new_utxo(wallet: &Wallet, to: &str, amount: i32, utxo: &UTXOSet) -> Result<Transaction> {
+    pub fn new_utxo(
+        wallet: &Wallet,
+        to: &str,
+        amount: i32,
+        utxo: &impl UtxoProvider,
+    ) -> Result<Transaction> {
         info!(
             "New UTXO Transaction from: {} to: {}.",
             wallet.get_address(),
@@ -79,58 +158,474 @@ impl Transaction {
         let mut pub_key_hash = wallet.public_key.clone();
         hash_pub_key(&mut pub_key_hash);
 
-        let acc_v = utxo.find_spendable_outputs(&pub_key_hash, amount)?;
-        if acc_v.0 < amount {
+        let spendable = utxo.find_spendable_outputs(&pub_key_hash, amount)?;
+        let mut tx = Transaction::new_unsigned(&wallet.public_key, to, amount, spendable)?;
+        if canonical_order() {
+            tx.sort_canonical()?;
+        }
+
+        let mut prev_txs = HashMap::new();
+        for vin in &tx.vin {
+            if !prev_txs.contains_key(&vin.txid) {
+                prev_txs.insert(vin.txid.clone(), utxo.find_transaction(&vin.txid)?);
+            }
+        }
+        tx.sign(&wallet.secret_key, prev_txs)?;
+
+        Ok(tx)
+    }
+
+    /**
+     * @desc 名称注册交易: 由钱包支付手续费 fee，找零回到钱包地址，
+     *       续期时钱包必须是名称的当前持有者
+     */
+    pub fn new_name_registration(
+        wallet: &Wallet,
+        record: &NameRecord,
+        fee: i32,
+        utxo: &impl UtxoProvider,
+    ) -> Result<Transaction> {
+        info!(
+            "New name registration {} from: {}.",
+            record.name,
+            wallet.get_address()
+        );
+
+        Transaction::new_record(wallet, record.to_output(), TX_VERSION_NAME, fee, utxo)
+    }
+
+    /**
+     * @desc 提案或投票交易: 由钱包支付手续费 fee，找零回到钱包地址，投票人是钱包地址
+     */
+    pub fn new_governance(
+        wallet: &Wallet,
+        record: &GovernanceRecord,
+        fee: i32,
+        utxo: &impl UtxoProvider,
+    ) -> Result<Transaction> {
+        info!(
+            "New governance record {:?} from: {}.",
+            record,
+            wallet.get_address()
+        );
+
+        Transaction::new_record(wallet, record.to_output(), TX_VERSION_GOVERNANCE, fee, utxo)
+    }
+
+    /**
+     * @desc 锚定交易: 带一个记录其他链区块 hash 的数据输出，由钱包支付手续费 fee，找零回到钱包地址
+     */
+    pub fn new_anchor(
+        wallet: &Wallet,
+        anchor: &Anchor,
+        fee: i32,
+        utxo: &impl UtxoProvider,
+    ) -> Result<Transaction> {
+        info!(
+            "New anchor of block {} from: {}.",
+            anchor.block_hash,
+            wallet.get_address()
+        );
+
+        Transaction::new_record(wallet, anchor.to_output(), TX_VERSION, fee, utxo)
+    }
+
+    /**
+     * @desc 花费程序锁定的输出: 输入公开程序 code，签名字段是见证数据 witness，扣除手续费 fee 后全部付给 to
+     */
+    pub fn new_program_spend(
+        txid: &str,
+        vout: i32,
+        code: &[u8],
+        witness: &[u8],
+        to: &str,
+        fee: i32,
+        utxo: &impl UtxoProvider,
+    ) -> Result<Transaction> {
+        info!("New program spend of {}:{} to: {}.", txid, vout, to);
+
+        let prev_tx = utxo.find_transaction(txid)?;
+        let prev_out = prev_tx
+            .vout
+            .get(vout as usize)
+            .ok_or_else(|| format_err!("Output {}:{} does not exist.", txid, vout))?;
+        if prev_out.pub_key_hash != vm::program_hash(code) {
+            return Err(format_err!(
+                "Output {}:{} is not locked by this program.",
+                txid,
+                vout
+            ));
+        }
+        if fee < 0 || prev_out.value <= fee {
+            return Err(format_err!(
+                "Fee {} is not payable from {}.",
+                fee,
+                prev_out.value
+            ));
+        }
+
+        let mut tx = Transaction {
+            id: String::new(),
+            vin: vec![TXInput {
+                txid: txid.to_string(),
+                vout,
+                signature: Vec::new(),
+                pub_key: vm::lock_script(code),
+            }],
+            vout: vec![TXOutput::new(prev_out.value - fee, to.to_string())?],
+            version: TX_VERSION_PROGRAM,
+            relative_lock_time: 0,
+        };
+        tx.id = tx.hash()?;
+        tx.vin[0].signature = witness.to_vec();
+
+        Ok(tx)
+    }
+
+    // 带一个记录输出的交易，输入全部来自钱包，至少有一个输入
+    fn new_record(
+        wallet: &Wallet,
+        record: TXOutput,
+        version: i32,
+        fee: i32,
+        utxo: &impl UtxoProvider,
+    ) -> Result<Transaction> {
+        let mut pub_key_hash = wallet.public_key.clone();
+        hash_pub_key(&mut pub_key_hash);
+
+        let (accumulated, outputs) = utxo.find_spendable_outputs(&pub_key_hash, fee.max(1))?;
+        if accumulated < fee || outputs.is_empty() {
+            return Err(format_err!(
+                "Not Enough balance: current balance {}.",
+                accumulated
+            ));
+        }
+
+        let mut vin = Vec::new();
+        let mut prev_txs = HashMap::new();
+        for (txid, outs) in outputs {
+            for out in outs {
+                vin.push(TXInput {
+                    txid: txid.clone(),
+                    vout: out,
+                    signature: Vec::new(),
+                    pub_key: wallet.public_key.clone(),
+                });
+            }
+            prev_txs.insert(txid.clone(), utxo.find_transaction(&txid)?);
+        }
+
+        let mut vout = vec![record];
+        if accumulated > fee {
+            vout.push(TXOutput {
+                value: accumulated - fee,
+                pub_key_hash,
+            });
+        }
+
+        let mut tx = Transaction {
+            id: String::new(),
+            vin,
+            vout,
+            version,
+            relative_lock_time: 0,
+        };
+        tx.id = tx.hash()?;
+        tx.sign(&wallet.secret_key, prev_txs)?;
+
+        Ok(tx)
+    }
+
+    /**
+     * @desc 把钱包的全部可花费输出转到 to，每笔交易只有一个输出；超过网络的交易大小限制时分成多笔，
+     *       每笔交易的手续费按 fee_rate(每 1000 字节)从金额中扣除
+     */
+    pub fn new_sweep(
+        wallet: &Wallet,
+        to: &str,
+        fee_rate: i32,
+        utxo: &impl UtxoProvider,
+    ) -> Result<Vec<Transaction>> {
+        Transaction::new_sweep_with_limit(
+            wallet,
+            to,
+            fee_rate,
+            utxo,
+            i32::MAX,
+            network::params().max_tx_size,
+        )
+    }
+
+    /**
+     * @desc 把钱包中金额不超过 max_value 的小额输出归集到钱包自己的地址
+     */
+    pub fn new_consolidation(
+        wallet: &Wallet,
+        max_value: i32,
+        fee_rate: i32,
+        utxo: &impl UtxoProvider,
+    ) -> Result<Vec<Transaction>> {
+        Transaction::new_sweep_with_limit(
+            wallet,
+            &wallet.get_address(),
+            fee_rate,
+            utxo,
+            max_value,
+            network::params().max_tx_size,
+        )
+    }
+
+    fn new_sweep_with_limit(
+        wallet: &Wallet,
+        to: &str,
+        fee_rate: i32,
+        utxo: &impl UtxoProvider,
+        max_value: i32,
+        max_tx_size: usize,
+    ) -> Result<Vec<Transaction>> {
+        info!(
+            "New sweep Transaction from: {} to: {}.",
+            wallet.get_address(),
+            to
+        );
+
+        let mut pub_key_hash = wallet.public_key.clone();
+        hash_pub_key(&mut pub_key_hash);
+
+        let (_, outputs) = utxo.find_spendable_outputs(&pub_key_hash, i32::MAX)?;
+        let mut prev_txs = HashMap::new();
+        let mut inputs = Vec::new();
+        for (txid, vouts) in outputs {
+            let prev_tx = utxo.find_transaction(&txid)?;
+            for vout in vouts {
+                let out = prev_tx
+                    .vout
+                    .get(vout as usize)
+                    .ok_or_else(|| format_err!("Output {}:{} is not found.", txid, vout))?;
+                if out.value <= max_value {
+                    inputs.push((txid.clone(), vout, out.value));
+                }
+            }
+            prev_txs.insert(txid, prev_tx);
+        }
+        if inputs.is_empty() {
+            return Err(format_err!(
+                "No spendable outputs for {}.",
+                wallet.get_address()
+            ));
+        }
+        // 输入顺序固定，相同的 UTXO 集得到相同的交易
+        inputs.sort();
+
+        let mut txs = Vec::new();
+        let mut chunk = Vec::new();
+        for input in inputs {
+            chunk.push(input);
+            let size = Transaction::new_sweep_unsigned(&wallet.public_key, to, &chunk, 0, true)?
+                .get_size()?;
+            if chunk.len() > 1 && size > max_tx_size {
+                let input = chunk.pop().unwrap();
+                txs.push(Transaction::sign_sweep(
+                    wallet, to, fee_rate, &chunk, &prev_txs,
+                )?);
+                chunk = vec![input];
+            }
+        }
+        txs.push(Transaction::sign_sweep(
+            wallet, to, fee_rate, &chunk, &prev_txs,
+        )?);
+
+        Ok(txs)
+    }
+
+    /**
+     * @desc 构建一笔归集交易并签名，手续费按签名后的大小计算
+     */
+    fn sign_sweep(
+        wallet: &Wallet,
+        to: &str,
+        fee_rate: i32,
+        inputs: &[(String, i32, i32)],
+        prev_txs: &HashMap<String, Transaction>,
+    ) -> Result<Transaction> {
+        let total = inputs
+            .iter()
+            .try_fold(0i32, |total, (_, _, value)| total.checked_add(*value))
+            .ok_or_else(|| format_err!("Sweep amount overflows."))?;
+        // 签名长度固定，金额是定长编码，用占位签名得到的大小就是签名后的大小
+        let size = Transaction::new_sweep_unsigned(&wallet.public_key, to, inputs, total, true)?
+            .get_size()? as i64;
+        let fee = ((fee_rate as i64 * size + 999) / 1000) as i32;
+        if total <= fee {
+            return Err(format_err!(
+                "Sweep amount {} does not cover the fee {}.",
+                total,
+                fee
+            ));
+        }
+
+        let mut tx =
+            Transaction::new_sweep_unsigned(&wallet.public_key, to, inputs, total - fee, false)?;
+        tx.sign(&wallet.secret_key, prev_txs.clone())?;
+
+        Ok(tx)
+    }
+
+    /**
+     * @desc 花费 inputs(交易 id, 输出序号, 金额)、只有一个输出的未签名交易，placeholder 为 true 时填入占位签名用于估算大小
+     */
+    fn new_sweep_unsigned(
+        pub_key: &[u8],
+        to: &str,
+        inputs: &[(String, i32, i32)],
+        value: i32,
+        placeholder: bool,
+    ) -> Result<Transaction> {
+        let signature = if placeholder { vec![0; 64] } else { Vec::new() };
+        let vin = inputs
+            .iter()
+            .map(|(txid, vout, _)| TXInput {
+                txid: txid.clone(),
+                vout: *vout,
+                signature: signature.clone(),
+                pub_key: pub_key.to_vec(),
+            })
+            .collect();
+
+        let mut tx = Transaction {
+            id: String::new(),
+            vin,
+            vout: vec![TXOutput::new(value, to.to_string())?],
+            version: TX_VERSION,
+            relative_lock_time: 0,
+        };
+        tx.id = tx.hash()?;
+
+        Ok(tx)
+    }
+
+    /**
+     * @desc 用可花费的输出(总额, 交易 id -> 输出序号)构建未签名的交易，只需要付款人的公钥
+     */
+    pub fn new_unsigned(
+        pub_key: &[u8],
+        to: &str,
+        amount: i32,
+        spendable: (i32, HashMap<String, Vec<i32>>),
+    ) -> Result<Transaction> {
+        let (accumulated, outputs) = spendable;
+        if accumulated < amount {
             error!("Not Enough balance.");
 
             return Err(format_err!(
                 "Not Enough balance: current balance {}.",
-                acc_v.0
+                accumulated
             ));
         }
 
         let mut vin = Vec::new();
-        for tx in acc_v.1 {
+        for tx in outputs {
             for out in tx.1 {
                 let input = TXInput {
                     txid: tx.0.clone(),
                     vout: out,
                     signature: Vec::new(),
-                    pub_key: wallet.public_key.clone(),
+                    pub_key: pub_key.to_vec(),
                 };
 
                 vin.push(input);
             }
         }
 
+        let mut pub_key_hash = pub_key.to_vec();
+        hash_pub_key(&mut pub_key_hash);
+
         let mut vout = vec![TXOutput::new(amount, to.to_string())?];
-        if acc_v.0 > amount {
-            vout.push(TXOutput::new(acc_v.0 - amount, wallet.get_address())?);
+        if accumulated > amount {
+            vout.push(TXOutput::new(
+                accumulated - amount,
+                address_from_pub_key_hash(&pub_key_hash),
+            )?);
         }
 
         let mut tx = Transaction {
             id: String::new(),
             vin,
             vout,
+            version: TX_VERSION,
+            relative_lock_time: 0,
         };
         tx.id = tx.hash()?;
-        utxo.blockchain
-            .sign_transaction(&mut tx, &wallet.secret_key)?;
 
         Ok(tx)
     }
 
-    // 生成新币 - 矿工获得挖出新块的奖励
-    pub fn new_coinbase(to: String, mut data: String) -> Result<Transaction> {
-        info!("New coinbase Transaction to: {}", to);
+    /**
+     * @desc 创世交易: 不含随机数的 coinbase 交易，按 allocations 分配初始金额，相同参数得到相同的交易
+     */
+    pub fn new_genesis(data: &str, allocations: &[(String, i32)]) -> Result<Transaction> {
+        let mut pub_key = Vec::from(data.as_bytes());
+        pub_key.extend_from_slice(&[0; 32]);
+
+        let vout = allocations
+            .iter()
+            .map(|(address, value)| TXOutput::new(*value, address.clone()))
+            .collect::<Result<Vec<TXOutput>>>()?;
+
+        let mut tx = Transaction {
+            id: String::new(),
+            vin: vec![TXInput {
+                txid: String::new(),
+                vout: -1,
+                signature: Vec::new(),
+                pub_key,
+            }],
+            vout,
+            version: TX_VERSION,
+            relative_lock_time: 0,
+        };
+        tx.id = tx.hash()?;
 
-        let mut key: [u8; 32] = [0; 32];
+        Ok(tx)
+    }
+
+    // 生成新币 - 矿工获得挖出新块的奖励，data 为空时用 rng 生成随机数据，避免不同 coinbase 交易的 id 相同
+    pub fn new_coinbase(
+        to: String,
+        mut data: String,
+        rng: &mut impl RngCore,
+    ) -> Result<Transaction> {
+        let mut key = [0; COINBASE_NONCE_LEN];
         if data.is_empty() {
-            OsRng.fill_bytes(&mut key);
+            rng.fill_bytes(&mut key);
             data = format!("Reward to '{}'", to);
         }
+
+        Transaction::coinbase_with_data(to, &data, &key)
+    }
+
+    /**
+     * @desc 带矿工标签的 coinbase 交易，标签以 "/标签/" 写在 coinbase 数据开头，用于识别出块的矿工
+     */
+    pub fn new_tagged_coinbase(
+        to: String,
+        tag: &str,
+        rng: &mut impl RngCore,
+    ) -> Result<Transaction> {
+        check_coinbase_tag(tag)?;
+
+        let mut key = [0; COINBASE_NONCE_LEN];
+        rng.fill_bytes(&mut key);
+        let data = format!("/{}/Reward to '{}'", tag, to);
+
+        Transaction::coinbase_with_data(to, &data, &key)
+    }
+
+    fn coinbase_with_data(to: String, data: &str, key: &[u8]) -> Result<Transaction> {
+        info!("New coinbase Transaction to: {}", to);
+
         let mut pub_key = Vec::from(data.as_bytes());
-        pub_key.append(&mut Vec::from(key));
+        pub_key.extend_from_slice(key);
 
         let mut tx = Transaction {
             id: String::new(),
@@ -141,47 +636,222 @@ impl Transaction {
                 pub_key,
             }],
             vout: vec![TXOutput::new(SUBSIDY, to)?],
+            version: TX_VERSION,
+            relative_lock_time: 0,
         };
         tx.id = tx.hash()?;
 
         Ok(tx)
     }
 
+    /**
+     * @desc 获取 coinbase 交易的 extranonce(保存在 coinbase 输入未使用的签名字段中)，没有设置过时为 0
+     */
+    pub fn get_extranonce(&self) -> u64 {
+        match self.vin.first() {
+            Some(vin) if self.is_coinbase() && vin.signature.len() == 8 => {
+                let mut bytes = [0; 8];
+                bytes.copy_from_slice(&vin.signature);
+                u64::from_le_bytes(bytes)
+            }
+            _ => 0,
+        }
+    }
+
+    /**
+     * @desc 设置 coinbase 交易的 extranonce 并重新计算交易 id
+     */
+    pub fn set_extranonce(&mut self, extranonce: u64) -> Result<()> {
+        if !self.is_coinbase() {
+            return Err(format_err!(
+                "Extranonce can only be set on a coinbase transaction."
+            ));
+        }
+
+        self.vin[0].signature = extranonce.to_le_bytes().to_vec();
+        self.id = self.hash()?;
+
+        Ok(())
+    }
+
+    /**
+     * @desc 检查版本规则: 版本至少为 1，版本 1 的交易不能设置相对锁定时间(版本 1 的 txid 不包含该字段)
+     */
+    pub fn check_version(&self) -> Result<()> {
+        if self.version < 1 {
+            return Err(format_err!(
+                "ERROR: Transaction {} has invalid version {}.",
+                self.id,
+                self.version
+            ));
+        }
+        if self.version < TX_VERSION_RELATIVE_LOCK && self.relative_lock_time != 0 {
+            return Err(format_err!(
+                "ERROR: Transaction {} version {} does not support relative lock time.",
+                self.id,
+                self.version
+            ));
+        }
+
+        Ok(())
+    }
+
+    /**
+     * @desc 检查 coinbase 的输出: 每个输出金额为正，总额不超过出块奖励(奖励可以分成多个输出)
+     */
+    pub fn check_coinbase_outputs(&self) -> Result<()> {
+        let mut total: i32 = 0;
+        for out in &self.vout {
+            if out.value <= 0 {
+                return Err(format_err!(
+                    "ERROR: Coinbase {} has a non-positive output.",
+                    self.id
+                ));
+            }
+            total = total.saturating_add(out.value);
+        }
+        if total > SUBSIDY {
+            return Err(format_err!(
+                "ERROR: Coinbase {} pays {}, more than the block reward {}.",
+                self.id,
+                total,
+                SUBSIDY
+            ));
+        }
+
+        Ok(())
+    }
+
+    /**
+     * @desc 交易版本是否是交易池接受的标准版本
+     */
+    pub fn is_standard_version(&self) -> bool {
+        (1..=MAX_STANDARD_TX_VERSION).contains(&self.version)
+    }
+
     pub fn is_coinbase(&self) -> bool {
         self.vin.len() == 1 && self.vin[0].txid.is_empty() && self.vin[0].vout == -1
     }
 
+    /**
+     * @desc coinbase 交易的矿工标签，不是 coinbase 交易或没有标签时返回 None
+     */
+    pub fn coinbase_tag(&self) -> Option<String> {
+        if !self.is_coinbase() {
+            return None;
+        }
+        let pub_key = &self.vin[0].pub_key;
+        let data = pub_key.get(..pub_key.len().checked_sub(COINBASE_NONCE_LEN)?)?;
+        let (tag, _) = std::str::from_utf8(data)
+            .ok()?
+            .strip_prefix('/')?
+            .split_once('/')?;
+
+        Some(tag.to_string()).filter(|tag| check_coinbase_tag(tag).is_ok())
+    }
+
     pub fn verify(&self, prev_txs: HashMap<String, Transaction>) -> Result<bool> {
         if self.is_coinbase() {
             return Ok(true);
         }
 
-        for vin in &self.vin {
-            if prev_txs.get(&vin.txid).unwrap().id.is_empty() {
-                return Err(format_err!("ERROR: Previous transaction is not correct."));
+        for in_id in 0..self.vin.len() {
+            if !self.verify_input(in_id, &prev_txs)? {
+                return Ok(false);
             }
         }
 
+        Ok(true)
+    }
+
+    /**
+     * @desc 验证单个输入的签名，各输入之间互不依赖，可以并行验证
+     */
+    pub fn verify_input(
+        &self,
+        in_id: usize,
+        prev_txs: &HashMap<String, Transaction>,
+    ) -> Result<bool> {
+        let sighash = self.signature_hash(in_id, prev_txs)?;
+
+        Ok(self.verify_signature(in_id, &sighash))
+    }
+
+    /**
+     * @desc 计算单个输入签名的数据哈希: 清空全部签名和公钥，并把该输入的公钥换成所花费输出的公钥哈希
+     */
+    pub fn signature_hash(
+        &self,
+        in_id: usize,
+        prev_txs: &HashMap<String, Transaction>,
+    ) -> Result<String> {
+        let vin = &self.vin[in_id];
+        let prev_out = prev_txs
+            .get(&vin.txid)
+            .filter(|prev_tx| !prev_tx.id.is_empty())
+            .and_then(|prev_tx| prev_tx.vout.get(vin.vout as usize))
+            .ok_or_else(|| format_err!("ERROR: Previous transaction is not correct."))?;
+
+        // 多签和程序输入公开的脚本必须和所花费输出的公钥哈希一致，单个公钥的输入沿用原来的规则
+        if self.is_script_input(in_id) && !vin.uses_key(&prev_out.pub_key_hash) {
+            return Err(format_err!(
+                "ERROR: Input script does not match the spent output."
+            ));
+        }
+
         let mut tx_copy = self.trim_copy();
-        for in_id in 0..self.vin.len() {
-            let prev_tx = prev_txs.get(&self.vin[in_id].txid).unwrap();
-            tx_copy.vin[in_id].signature.clear();
-            tx_copy.vin[in_id].pub_key = prev_tx.vout[self.vin[in_id].vout as usize]
-                .pub_key_hash
-                .clone();
-            tx_copy.id = tx_copy.hash()?;
-            tx_copy.vin[in_id].pub_key = Vec::new();
+        tx_copy.vin[in_id].pub_key = prev_out.pub_key_hash.clone();
 
-            if !ed25519::verify(
-                &tx_copy.id.as_bytes(),
-                &self.vin[in_id].pub_key,
-                &self.vin[in_id].signature,
-            ) {
-                return Ok(false);
-            }
+        tx_copy.hash()
+    }
+
+    // 公钥字段不是单个公钥的输入: 多签脚本或程序
+    fn is_script_input(&self, in_id: usize) -> bool {
+        self.version >= TX_VERSION_MULTISIG && self.vin[in_id].pub_key.len() != 32
+    }
+
+    fn is_program_input(&self, in_id: usize) -> bool {
+        self.version >= TX_VERSION_PROGRAM && vm::is_program(&self.vin[in_id].pub_key)
+    }
+
+    /**
+     * @desc 用输入的公钥验证该输入对 sighash 的签名
+     */
+    pub fn verify_signature(&self, in_id: usize, sighash: &str) -> bool {
+        let vin = &self.vin[in_id];
+        if self.is_program_input(in_id) {
+            return vm::execute(
+                &vin.pub_key,
+                &vin.signature,
+                sighash,
+                self.relative_lock_time,
+            )
+            .is_ok();
+        }
+        if self.is_script_input(in_id) {
+            return verify_multisig(sighash, &vin.pub_key, &vin.signature);
         }
 
-        Ok(true)
+        // 长度不对的公钥和签名直接视为无效
+        if vin.pub_key.len() != 32 || vin.signature.len() != 64 {
+            return false;
+        }
+
+        ed25519::verify(sighash.as_bytes(), &vin.pub_key, &vin.signature)
+    }
+
+    /**
+     * @desc 一方对单个输入的签名，多签输入由各方分别签名后按公钥顺序拼接
+     */
+    pub fn sign_input(
+        &self,
+        in_id: usize,
+        private_key: &[u8],
+        prev_txs: &HashMap<String, Transaction>,
+    ) -> Result<Vec<u8>> {
+        let sighash = self.signature_hash(in_id, prev_txs)?;
+
+        Ok(ed25519::signature(sighash.as_bytes(), private_key).to_vec())
     }
 
     pub fn sign(
@@ -215,11 +885,70 @@ impl Transaction {
         Ok(())
     }
 
-    pub fn hash(&self) -> Result<String> {
-        let mut copy = self.clone();
-        copy.id = String::new();
+    /**
+     * @desc 获取交易序列化后的字节数
+     */
+    pub fn get_size(&self) -> Result<usize> {
+        Ok(serialize(self)?.len())
+    }
 
-        let data = serialize(&copy)?;
+    /**
+     * @desc 从字节解析交易，长度超过当前网络最大交易大小的数据直接拒绝，不是当前格式时按旧格式解析
+     */
+    pub fn from_bytes(data: &[u8]) -> Result<Transaction> {
+        let options = bincode::options()
+            .with_fixint_encoding()
+            .allow_trailing_bytes()
+            .with_limit(network::params().max_tx_size as u64);
+
+        match options.deserialize(data) {
+            Ok(tx) => Ok(tx),
+            Err(e) => match options.deserialize::<LegacyTransaction>(data) {
+                Ok(tx) => Ok(tx.into()),
+                Err(_) => Err(e.into()),
+            },
+        }
+    }
+
+    /**
+     * @desc 交易的十六进制编码，用于命令行传递原始交易
+     */
+    pub fn to_hex(&self) -> Result<String> {
+        Ok(encode_hex(&serialize(self)?))
+    }
+
+    /**
+     * @desc 解析十六进制编码的交易
+     */
+    pub fn from_hex(hex: &str) -> Result<Transaction> {
+        let data = decode_hex(hex).ok_or_else(|| format_err!("Invalid transaction hex."))?;
+
+        Transaction::from_bytes(&data)
+    }
+
+    /**
+     * @desc 按规范顺序(BIP69)排列: 输入按引用的交易 id 和输出序号，输出按金额和公钥哈希，排序后重新计算交易 id；
+     *       签名覆盖输入输出的顺序，所以要在签名前排序
+     */
+    pub fn sort_canonical(&mut self) -> Result<()> {
+        self.vin
+            .sort_by(|a, b| (&a.txid, a.vout).cmp(&(&b.txid, b.vout)));
+        self.vout
+            .sort_by(|a, b| (a.value, &a.pub_key_hash).cmp(&(b.value, &b.pub_key_hash)));
+        self.id = self.hash()?;
+
+        Ok(())
+    }
+
+    pub fn hash(&self) -> Result<String> {
+        // 版本 1 的交易按加入版本字段之前的格式计算，已有区块中的 txid 保持不变
+        let data = if self.version == 1 {
+            serialize(&(String::new(), &self.vin, &self.vout))?
+        } else {
+            let mut copy = self.clone();
+            copy.id = String::new();
+            serialize(&copy)?
+        };
         let mut hasher = Sha256::new();
         hasher.input(&data[..]);
 
@@ -249,24 +978,88 @@ impl Transaction {
             id: self.id.clone(),
             vin,
             vout,
+            version: self.version,
+            relative_lock_time: self.relative_lock_time,
         }
     }
 }
 
+/**
+ * @desc 检查矿工标签: 非空、不超过最大长度、只包含可打印的 ASCII 字符且不含分隔符 '/'
+ */
+pub fn check_coinbase_tag(tag: &str) -> Result<()> {
+    let valid = !tag.is_empty()
+        && tag.len() <= MAX_COINBASE_TAG_LEN
+        && tag.chars().all(|c| c.is_ascii_graphic() || c == ' ')
+        && !tag.contains('/');
+    if !valid {
+        return Err(format_err!(
+            "Invalid coinbase tag: {:?}, use at most {} printable characters without '/'.",
+            tag,
+            MAX_COINBASE_TAG_LEN
+        ));
+    }
+
+    Ok(())
+}
+
+/**
+ * @desc 字节的十六进制编码
+ */
+/**
+ * @desc 验证多签: 签名按公钥顺序排列，每个签名依次匹配后面的公钥，签名数必须等于门限
+ */
+fn verify_multisig(sighash: &str, script: &[u8], signatures: &[u8]) -> bool {
+    let (threshold, keys) = match Descriptor::from_script(script) {
+        Ok(Descriptor::Multi { threshold, keys }) => (threshold, keys),
+        _ => return false,
+    };
+    if signatures.len() != threshold * SIGNATURE_LEN {
+        return false;
+    }
+
+    let mut keys = keys.iter();
+    signatures
+        .chunks(SIGNATURE_LEN)
+        .all(|signature| keys.any(|key| ed25519::verify(sighash.as_bytes(), key, signature)))
+}
+
+pub fn encode_hex(data: &[u8]) -> String {
+    data.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/**
+ * @desc 解析十六进制编码的字节，忽略首尾空白
+ */
+pub fn decode_hex(hex: &str) -> Option<Vec<u8>> {
+    let hex = hex.trim();
+    if !hex.len().is_multiple_of(2) || !hex.is_ascii() {
+        return None;
+    }
+
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).ok())
+        .collect()
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::arbitrary;
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+    use rand_core::OsRng;
 
     #[test]
     fn test_signature() {
         let mut ws = Wallets::new().unwrap();
-        let wlt_address = ws.create_wallet();
+        let wlt_address = ws.create_wallet(&mut OsRng);
         let wlt = ws.get_wallet(&wlt_address).unwrap().clone();
         ws.save_all().unwrap();
         drop(ws);
 
         let data = String::from("test");
-        let tx = Transaction::new_coinbase(wlt_address, data).unwrap();
+        let tx = Transaction::new_coinbase(wlt_address, data, &mut OsRng).unwrap();
         assert!(tx.is_coinbase());
 
         let signature = ed25519::signature(tx.id.as_bytes(), &wlt.secret_key);
@@ -276,4 +1069,381 @@ mod test {
             &signature
         ));
     }
+
+    #[test]
+    fn test_coinbase_tag() {
+        let to = Wallet::default().get_address();
+        let tx = Transaction::new_tagged_coinbase(to.clone(), "alice-node", &mut OsRng).unwrap();
+        assert!(tx.is_coinbase());
+        assert_eq!(tx.coinbase_tag(), Some(String::from("alice-node")));
+        // 标签相同的 coinbase 交易 id 仍然不同
+        let other = Transaction::new_tagged_coinbase(to.clone(), "alice-node", &mut OsRng).unwrap();
+        assert_ne!(tx.id, other.id);
+
+        let untagged = Transaction::new_coinbase(to.clone(), String::new(), &mut OsRng).unwrap();
+        assert_eq!(untagged.coinbase_tag(), None);
+        let data = Transaction::new_coinbase(to.clone(), String::from("/x/"), &mut OsRng).unwrap();
+        assert_eq!(data.coinbase_tag(), Some(String::from("x")));
+
+        for tag in ["", "a/b", "line\n", &"x".repeat(MAX_COINBASE_TAG_LEN + 1)] {
+            assert!(Transaction::new_tagged_coinbase(to.clone(), tag, &mut OsRng).is_err());
+        }
+    }
+
+    #[test]
+    fn test_check_coinbase_outputs() {
+        let to = Wallet::default().get_address();
+        let mut tx = Transaction::new_coinbase(to.clone(), String::new(), &mut OsRng).unwrap();
+        assert!(tx.check_coinbase_outputs().is_ok());
+
+        tx.vout[0].value = SUBSIDY - 4;
+        tx.vout.push(TXOutput::new(4, to.clone()).unwrap());
+        assert!(tx.check_coinbase_outputs().is_ok());
+
+        tx.vout[1].value = 5;
+        assert!(tx.check_coinbase_outputs().is_err());
+        tx.vout[1].value = 0;
+        assert!(tx.check_coinbase_outputs().is_err());
+    }
+
+    #[test]
+    fn test_sort_canonical() {
+        let from = Wallet::default();
+        let to = Wallet::default().get_address();
+        let spendable = HashMap::from([
+            (String::from("bb"), vec![2, 0]),
+            (String::from("aa"), vec![1]),
+        ]);
+        let mut tx = Transaction::new_unsigned(&from.public_key, &to, 7, (12, spendable)).unwrap();
+        let unsorted = tx.id.clone();
+
+        tx.sort_canonical().unwrap();
+        let inputs: Vec<(&str, i32)> = tx
+            .vin
+            .iter()
+            .map(|vin| (vin.txid.as_str(), vin.vout))
+            .collect();
+        assert_eq!(inputs, vec![("aa", 1), ("bb", 0), ("bb", 2)]);
+        // 找零 5 排在付款 7 之前
+        let values: Vec<i32> = tx.vout.iter().map(|out| out.value).collect();
+        assert_eq!(values, vec![5, 7]);
+        assert!(tx.vout[0].is_locked_with_key(&decode_address(&from.get_address()).unwrap()));
+        assert_ne!(tx.id, unsorted);
+        assert_eq!(tx.id, tx.hash().unwrap());
+
+        // 排序与原始顺序无关
+        let mut reversed = tx.clone();
+        reversed.vin.reverse();
+        reversed.vout.reverse();
+        reversed.sort_canonical().unwrap();
+        assert_eq!(reversed.id, tx.id);
+    }
+
+    #[test]
+    fn test_new_sweep() {
+        let from = Wallet::default();
+        let to = Wallet::default().get_address();
+        let mut utxos = MemoryUtxoSet::default();
+        for i in 0..3 {
+            utxos.add_transaction(
+                Transaction::new_coinbase(from.get_address(), format!("sweep {}", i), &mut OsRng)
+                    .unwrap(),
+            );
+        }
+
+        let txs = Transaction::new_sweep(&from, &to, 0, &utxos).unwrap();
+        assert_eq!(txs.len(), 1);
+        assert_eq!((txs[0].vin.len(), txs[0].vout.len()), (3, 1));
+        assert_eq!(txs[0].vout[0].value, 3 * SUBSIDY);
+
+        // 手续费按签名后的大小计算
+        let tx = &Transaction::new_sweep(&from, &to, 10, &utxos).unwrap()[0];
+        let fee = 3 * SUBSIDY - tx.vout[0].value;
+        let size = tx.get_size().unwrap() as i32;
+        assert!(fee > 0 && fee * 1000 >= 10 * size && (fee - 1) * 1000 < 10 * size);
+
+        // 超过大小限制时分成多笔
+        let limit = txs[0].get_size().unwrap() - 1;
+        let chunks =
+            Transaction::new_sweep_with_limit(&from, &to, 0, &utxos, i32::MAX, limit).unwrap();
+        assert!(chunks.len() > 1);
+        assert!(chunks.iter().all(|tx| tx.get_size().unwrap() <= limit));
+        assert_eq!(chunks.iter().map(|tx| tx.vin.len()).sum::<usize>(), 3);
+        assert_eq!(
+            chunks.iter().map(|tx| tx.vout[0].value).sum::<i32>(),
+            3 * SUBSIDY
+        );
+
+        assert!(Transaction::new_sweep(&from, &to, 100_000, &utxos).is_err());
+        assert!(Transaction::new_sweep(&Wallet::default(), &to, 0, &utxos).is_err());
+    }
+
+    #[test]
+    fn test_new_consolidation() {
+        let wallet = Wallet::default();
+        let mut utxos = MemoryUtxoSet::default();
+        for i in 0..3 {
+            utxos.add_transaction(
+                Transaction::new_coinbase(
+                    wallet.get_address(),
+                    format!("reward {}", i),
+                    &mut OsRng,
+                )
+                .unwrap(),
+            );
+        }
+        let large = Transaction::new_sweep(&wallet, &wallet.get_address(), 0, &utxos).unwrap();
+        utxos.add_transaction(large[0].clone());
+
+        // 只归集小额的出块奖励，大额输出保持不动
+        let txs = Transaction::new_consolidation(&wallet, SUBSIDY, 0, &utxos).unwrap();
+        assert_eq!(txs.len(), 1);
+        assert_eq!(txs[0].vin.len(), 3);
+        assert!(txs[0].vin.iter().all(|vin| vin.txid != large[0].id));
+        assert_eq!(txs[0].vout[0].value, 3 * SUBSIDY);
+        let pub_key_hash = decode_address(&wallet.get_address()).unwrap();
+        assert!(txs[0].vout[0].is_locked_with_key(&pub_key_hash));
+
+        assert!(Transaction::new_consolidation(&wallet, SUBSIDY - 1, 0, &utxos).is_err());
+    }
+
+    #[test]
+    fn test_seeded_coinbase() {
+        let address = Wallet::default().get_address();
+        let coinbase = |seed| {
+            Transaction::new_coinbase(
+                address.clone(),
+                String::new(),
+                &mut StdRng::seed_from_u64(seed),
+            )
+            .unwrap()
+        };
+
+        assert_eq!(coinbase(7).id, coinbase(7).id);
+        assert_ne!(coinbase(7).id, coinbase(8).id);
+    }
+
+    #[test]
+    fn test_transaction_roundtrip() {
+        arbitrary::check(|rng| {
+            let tx = arbitrary::transaction(rng);
+            let data = serialize(&tx).unwrap();
+            let decoded = Transaction::from_bytes(&data).unwrap();
+
+            assert_eq!(serialize(&decoded).unwrap(), data);
+            assert_eq!(decoded.hash().unwrap(), tx.id);
+
+            let from_hex = Transaction::from_hex(&tx.to_hex().unwrap()).unwrap();
+            assert_eq!(serialize(&from_hex).unwrap(), data);
+        });
+
+        assert!(Transaction::from_hex("0").is_err());
+        assert!(Transaction::from_hex("zz").is_err());
+    }
+
+    #[test]
+    fn test_txid_is_canonical() {
+        arbitrary::check(|rng| {
+            let tx = arbitrary::transaction(rng);
+
+            // id 不参与哈希，输出金额参与哈希
+            let mut renamed = tx.clone();
+            renamed.id = arbitrary::string(rng, 64);
+            assert_eq!(renamed.hash().unwrap(), tx.id);
+
+            if let Some(out) = renamed.vout.first_mut() {
+                out.value = out.value.wrapping_add(1);
+                assert_ne!(renamed.hash().unwrap(), tx.id);
+            }
+        });
+    }
+
+    #[test]
+    fn test_verify_accepts_signed() {
+        arbitrary::check(|rng| {
+            let wallet = Wallet::new(rng);
+            let mut pub_key_hash = wallet.public_key.clone();
+            hash_pub_key(&mut pub_key_hash);
+
+            let mut prev = arbitrary::transaction(rng);
+            prev.vout.push(TXOutput {
+                value: rng.gen_range(1..1000),
+                pub_key_hash,
+            });
+            prev.id = prev.hash().unwrap();
+
+            let mut tx = Transaction {
+                id: String::new(),
+                vin: vec![TXInput {
+                    txid: prev.id.clone(),
+                    vout: prev.vout.len() as i32 - 1,
+                    signature: Vec::new(),
+                    pub_key: wallet.public_key.clone(),
+                }],
+                vout: (0..rng.gen_range(1..4))
+                    .map(|_| arbitrary::tx_output(rng))
+                    .collect(),
+                version: TX_VERSION,
+                relative_lock_time: 0,
+            };
+            tx.id = tx.hash().unwrap();
+
+            let prev_txs: HashMap<String, Transaction> =
+                [(prev.id.clone(), prev)].into_iter().collect();
+            tx.sign(&wallet.secret_key, prev_txs.clone()).unwrap();
+            assert!(tx.verify(prev_txs).unwrap());
+        });
+    }
+
+    #[test]
+    fn test_program_spend() {
+        let wallet = Wallet::from_seed(&[1; 32]);
+        let to = Wallet::from_seed(&[2; 32]).get_address();
+        let code = vm::assemble(&format!(
+            "0x{} OP_CHECKSIGVERIFY OP_ADD 7 OP_NUMEQUAL",
+            encode_hex(&wallet.public_key)
+        ))
+        .unwrap();
+
+        let mut prev =
+            Transaction::new_coinbase(to.clone(), String::from("program"), &mut OsRng).unwrap();
+        prev.vout[0].pub_key_hash = vm::program_hash(&code);
+        prev.id = prev.hash().unwrap();
+        let mut utxos = MemoryUtxoSet::default();
+        utxos.add_transaction(prev.clone());
+        let prev_txs = HashMap::from([(prev.id.clone(), prev.clone())]);
+
+        let unsigned =
+            Transaction::new_program_spend(&prev.id, 0, &code, &[], &to, 1, &utxos).unwrap();
+        assert_eq!(unsigned.vout[0].value, SUBSIDY - 1);
+        let signature = unsigned
+            .sign_input(0, &wallet.secret_key, &prev_txs)
+            .unwrap();
+
+        let spend = |witness: &str| {
+            let mut tx = unsigned.clone();
+            tx.vin[0].signature = vm::assemble(witness).unwrap();
+            tx.verify(prev_txs.clone()).unwrap()
+        };
+        let sig = format!("0x{}", encode_hex(&signature));
+        assert!(spend(&format!("3 4 {}", sig)));
+        assert!(!spend(&format!("3 3 {}", sig)));
+        assert!(!spend(&format!("3 4 0x{}", encode_hex(&[0; 64]))));
+
+        let other = vm::assemble("1").unwrap();
+        assert!(Transaction::new_program_spend(&prev.id, 0, &other, &[], &to, 1, &utxos).is_err());
+        assert!(Transaction::new_program_spend(&prev.id, 0, &code, &[], &to, 10, &utxos).is_err());
+    }
+
+    #[test]
+    fn test_legacy_transaction_decodes_as_version_1() {
+        arbitrary::check(|rng| {
+            let mut tx = arbitrary::transaction(rng);
+            tx.version = 1;
+            tx.relative_lock_time = 0;
+            tx.id = tx.hash().unwrap();
+
+            let legacy = serialize(&(&tx.id, &tx.vin, &tx.vout)).unwrap();
+            let decoded = Transaction::from_bytes(&legacy).unwrap();
+
+            assert_eq!(decoded.version, 1);
+            assert_eq!(decoded.relative_lock_time, 0);
+            assert_eq!(decoded.hash().unwrap(), tx.id);
+        });
+    }
+
+    #[test]
+    fn test_legacy_encoding_is_stable() {
+        // 旧链数据中输入输出的编码: 整数小端，字符串和字节数组前面是 u64 长度
+        let input = TXInput {
+            txid: String::from("ab"),
+            vout: 1,
+            signature: vec![2],
+            pub_key: vec![3, 4],
+        };
+        let output = TXOutput {
+            value: 10,
+            pub_key_hash: vec![5],
+        };
+        let input_bytes = [
+            vec![2, 0, 0, 0, 0, 0, 0, 0, b'a', b'b'],
+            vec![1, 0, 0, 0],
+            vec![1, 0, 0, 0, 0, 0, 0, 0, 2],
+            vec![2, 0, 0, 0, 0, 0, 0, 0, 3, 4],
+        ]
+        .concat();
+        let output_bytes = [vec![10, 0, 0, 0], vec![1, 0, 0, 0, 0, 0, 0, 0, 5]].concat();
+
+        assert_eq!(serialize(&input).unwrap(), input_bytes);
+        assert_eq!(serialize(&output).unwrap(), output_bytes);
+
+        let legacy = [
+            vec![0, 0, 0, 0, 0, 0, 0, 0],
+            vec![1, 0, 0, 0, 0, 0, 0, 0],
+            input_bytes,
+            vec![1, 0, 0, 0, 0, 0, 0, 0],
+            output_bytes,
+        ]
+        .concat();
+        let tx = Transaction::from_bytes(&legacy).unwrap();
+        assert_eq!(tx.vin[0].txid, "ab");
+        assert_eq!(tx.vin[0].pub_key, vec![3, 4]);
+        assert_eq!(tx.vout[0].value, 10);
+        assert_eq!(tx.version, TX_VERSION);
+    }
+
+    #[test]
+    fn test_input_uses_key() {
+        let wallet = Wallet::default();
+        let mut pub_key_hash = wallet.public_key.clone();
+        hash_pub_key(&mut pub_key_hash);
+
+        let input = TXInput {
+            txid: String::new(),
+            vout: 0,
+            signature: Vec::new(),
+            pub_key: wallet.public_key.clone(),
+        };
+        assert!(input.uses_key(&pub_key_hash));
+        assert!(!input.uses_key(&[0; 20]));
+    }
+
+    #[test]
+    fn test_version_is_committed_to_txid() {
+        arbitrary::check(|rng| {
+            let mut tx = arbitrary::transaction(rng);
+            tx.version = TX_VERSION_RELATIVE_LOCK;
+            tx.id = tx.hash().unwrap();
+
+            let mut upgraded = tx.clone();
+            upgraded.version += 1;
+            assert_ne!(upgraded.hash().unwrap(), tx.id);
+
+            let mut relocked = tx.clone();
+            relocked.relative_lock_time = relocked.relative_lock_time.wrapping_add(1);
+            assert_ne!(relocked.hash().unwrap(), tx.id);
+        });
+    }
+
+    #[test]
+    fn test_version_rules() {
+        arbitrary::check(|rng| {
+            let mut tx = arbitrary::transaction(rng);
+
+            tx.version = 0;
+            assert!(tx.check_version().is_err());
+
+            tx.version = 1;
+            tx.relative_lock_time = 1;
+            assert!(tx.check_version().is_err());
+
+            tx.version = TX_VERSION_RELATIVE_LOCK;
+            assert!(tx.check_version().is_ok());
+            assert!(tx.is_standard_version());
+
+            tx.version = MAX_STANDARD_TX_VERSION + 1;
+            assert!(tx.check_version().is_ok());
+            assert!(!tx.is_standard_version());
+        });
+    }
 }
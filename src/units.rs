@@ -0,0 +1,212 @@
+// 金额单位: 链上和程序内部的金额一律是最小单位的整数，
+// 命令行和 JSON 输出可以改用 "币" 为单位，1 币 = 10^decimals 个最小单位
+
+use std::sync::RwLock;
+
+use super::Result;
+use crate::json::Json;
+use failure::format_err;
+
+// 默认小数位数，一次出块奖励(10 个最小单位)正好是 1 币
+pub const DEFAULT_DECIMALS: u32 = 1;
+// i32 最多 10 位数字，更多的小数位没有意义
+const MAX_DECIMALS: u32 = 9;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Units {
+    Base,
+    Coins(u32),
+}
+
+static UNITS: RwLock<Units> = RwLock::new(Units::Base);
+
+pub fn set_units(units: Units) {
+    *UNITS.write().unwrap() = units;
+}
+
+pub fn current() -> Units {
+    *UNITS.read().unwrap()
+}
+
+impl Units {
+    /**
+     * @desc 由单位名称和小数位数创建，小数位数只对 coins 有效，不指定时使用 DEFAULT_DECIMALS
+     */
+    pub fn from_name(name: &str, decimals: Option<u32>) -> Result<Units> {
+        match name {
+            "base" => Ok(Units::Base),
+            "coins" => {
+                let decimals = decimals.unwrap_or(DEFAULT_DECIMALS);
+                if decimals > MAX_DECIMALS {
+                    return Err(format_err!(
+                        "Too many decimals: {}, at most {}.",
+                        decimals,
+                        MAX_DECIMALS
+                    ));
+                }
+                Ok(Units::Coins(decimals))
+            }
+            _ => Err(format_err!("Unknown units: {}", name)),
+        }
+    }
+
+    fn decimals(&self) -> u32 {
+        match self {
+            Units::Base => 0,
+            Units::Coins(decimals) => *decimals,
+        }
+    }
+
+    /**
+     * @desc 解析金额，返回最小单位的整数，小数位超过单位精度或超出范围时报错
+     */
+    pub fn parse_amount(&self, text: &str) -> Result<i32> {
+        let invalid = || format_err!("Invalid amount: {}", text);
+
+        let (negative, digits) = match text.strip_prefix('-') {
+            Some(digits) => (true, digits),
+            None => (false, text),
+        };
+        let (int, frac) = match digits.split_once('.') {
+            Some((int, frac)) => (int, frac),
+            None => (digits, ""),
+        };
+        if int.is_empty() || !int.bytes().chain(frac.bytes()).all(|b| b.is_ascii_digit()) {
+            return Err(invalid());
+        }
+        if digits.contains('.') && frac.is_empty() {
+            return Err(invalid());
+        }
+
+        let decimals = self.decimals();
+        if frac.len() > decimals as usize {
+            return Err(format_err!(
+                "Amount {} has more than {} decimals.",
+                text,
+                decimals
+            ));
+        }
+
+        let mut amount: i64 = 0;
+        let padding = decimals as usize - frac.len();
+        for b in int.bytes().chain(frac.bytes()) {
+            amount = amount * 10 + (b - b'0') as i64;
+            if amount > i32::MAX as i64 {
+                return Err(invalid());
+            }
+        }
+        for _ in 0..padding {
+            amount *= 10;
+            if amount > i32::MAX as i64 {
+                return Err(invalid());
+            }
+        }
+
+        Ok(if negative { -amount } else { amount } as i32)
+    }
+
+    /**
+     * @desc 按单位格式化最小单位的金额，coins 总是输出全部小数位
+     */
+    pub fn format_amount(&self, amount: i64) -> String {
+        let decimals = self.decimals();
+        if decimals == 0 {
+            return amount.to_string();
+        }
+
+        let scale = 10_i64.pow(decimals);
+        let sign = if amount < 0 { "-" } else { "" };
+        let amount = amount.unsigned_abs();
+        format!(
+            "{}{}.{:0width$}",
+            sign,
+            amount / scale as u64,
+            amount % scale as u64,
+            width = decimals as usize
+        )
+    }
+}
+
+/**
+ * @desc 按当前单位解析金额
+ */
+pub fn parse_amount(text: &str) -> Result<i32> {
+    current().parse_amount(text)
+}
+
+/**
+ * @desc 按当前单位格式化金额
+ */
+pub fn format_amount(amount: impl Into<i64>) -> String {
+    current().format_amount(amount.into())
+}
+
+/**
+ * @desc 金额的 JSON 值，最小单位时是整数，coins 时是带小数的数字
+ */
+pub fn amount_json(amount: impl Into<i64>) -> Json {
+    Json::Number(format_amount(amount))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_amount() {
+        assert_eq!(Units::Base.parse_amount("10").unwrap(), 10);
+        assert_eq!(Units::Base.parse_amount("-3").unwrap(), -3);
+        assert!(Units::Base.parse_amount("1.0").is_err());
+
+        let coins = Units::Coins(2);
+        assert_eq!(coins.parse_amount("1").unwrap(), 100);
+        assert_eq!(coins.parse_amount("1.5").unwrap(), 150);
+        assert_eq!(coins.parse_amount("0.05").unwrap(), 5);
+        assert_eq!(coins.parse_amount("-0.05").unwrap(), -5);
+        assert_eq!(coins.parse_amount("21474836.47").unwrap(), i32::MAX);
+
+        // 超出精度、超出范围和格式错误
+        for text in [
+            "0.001",
+            "21474836.48",
+            "",
+            ".5",
+            "1.",
+            "1e3",
+            "+1",
+            "1,5",
+            "- 1",
+        ] {
+            assert!(coins.parse_amount(text).is_err(), "{}", text);
+        }
+    }
+
+    #[test]
+    fn test_format_amount() {
+        assert_eq!(Units::Base.format_amount(-42), "-42");
+        assert_eq!(Units::Coins(1).format_amount(10), "1.0");
+        assert_eq!(Units::Coins(3).format_amount(5), "0.005");
+        assert_eq!(Units::Coins(3).format_amount(-1234), "-1.234");
+        assert_eq!(
+            Units::Coins(9).format_amount(2 * i32::MAX as i64),
+            "4.294967294"
+        );
+
+        for amount in [0, 7, 150, i32::MAX] {
+            let text = Units::Coins(2).format_amount(amount as i64);
+            assert_eq!(Units::Coins(2).parse_amount(&text).unwrap(), amount);
+        }
+    }
+
+    #[test]
+    fn test_units_from_name() {
+        assert_eq!(Units::from_name("base", Some(4)).unwrap(), Units::Base);
+        assert_eq!(
+            Units::from_name("coins", None).unwrap(),
+            Units::Coins(DEFAULT_DECIMALS)
+        );
+        assert_eq!(Units::from_name("coins", Some(0)).unwrap(), Units::Coins(0));
+        assert!(Units::from_name("coins", Some(10)).is_err());
+        assert!(Units::from_name("sats", None).is_err());
+    }
+}
@@ -1,17 +1,62 @@
-use std::collections::HashMap;
+use std::{
+    collections::{HashMap, HashSet},
+    thread,
+};
 
 use super::Result;
-use crate::{block::*, blockchain::*, transaction::*};
+use crate::{
+    block::*,
+    blockchain::*,
+    network,
+    schema::{self, Migration},
+    transaction::*,
+    wallets::address_from_pub_key_hash,
+};
 use bincode::{deserialize, serialize};
+use failure::format_err;
+use log::info;
 use sled;
 
+// UTXO 数据库在网络数据目录中的名称
+pub const UTXO_DB: &str = "utxos";
+// 重建 UTXO 集时每处理这么多区块报告一次进度
+const REINDEX_PROGRESS_INTERVAL: i32 = 1000;
+// 公钥哈希索引: [公钥哈希长度] + 公钥哈希 + 输出键 -> 空，
+// 查询地址的输出时只反序列化索引给出的输出
+const PUB_KEY_INDEX_TREE: &str = "pub_key_index";
+// UTXO 集的格式版本，版本 0 按交易 id 保存输出列表，版本 1 每个输出按 "交易 id:输出序号" 单独保存
+const UTXOS_SCHEMA: &str = "utxos";
+const UTXOS_VERSION: u32 = 1;
+
+// 并行重建时每个区块范围新建的输出和花费的输出先写入临时树，
+// 按区块范围的顺序合并得到 UTXO 集后删除，重建过程中不在内存中保留整个 UTXO 集
+const REINDEX_CREATED_TREE: &str = "reindex_created";
+const REINDEX_SPENT_TREE: &str = "reindex_spent";
+// 合并临时树时每批写入的条目数
+const REINDEX_MERGE_BATCH: usize = 10_000;
+
 pub struct UTXOSet {
     pub blockchain: Blockchain,
 }
 
-impl UTXOSet {
-    // 获取包含 未花费交易输出 的交易列表
-    pub fn find_spendable_outputs(
+// 构建交易所需的 UTXO 查询，钱包逻辑不依赖具体的存储
+pub trait UtxoProvider {
+    // 获取包含 未花费交易输出 的交易列表，累计到 amount 为止
+    fn find_spendable_outputs(
+        &self,
+        pub_key_hash: &[u8],
+        amount: i32,
+    ) -> Result<(i32, HashMap<String, Vec<i32>>)>;
+
+    // 通过 pub_key_hash 获取 未花费输出
+    fn find_utxos(&self, pub_key_hash: &[u8]) -> Result<TXOutputs>;
+
+    // 查询交易，签名时需要输入引用的前序交易
+    fn find_transaction(&self, id: &str) -> Result<Transaction>;
+}
+
+impl UtxoProvider for UTXOSet {
+    fn find_spendable_outputs(
         &self,
         pub_key_hash: &[u8],
         amount: i32,
@@ -19,115 +64,574 @@ impl UTXOSet {
         let mut unspent_outputs: HashMap<String, Vec<i32>> = HashMap::new();
         let mut accumulated = 0;
 
-        let db = sled::open("data/utxos")?;
-        for kv in db.iter() {
-            let (k, v) = kv?;
-            let txid = String::from_utf8(k.to_vec())?;
-            let outs: TXOutputs = deserialize(&v.to_vec())?;
-
-            for out_idx in 0..outs.outputs.len() {
-                if outs.outputs[out_idx].is_locked_with_key(pub_key_hash) && accumulated < amount {
-                    accumulated += outs.outputs[out_idx].value;
-
-                    match unspent_outputs.get_mut(&txid) {
-                        Some(v) => v.push(out_idx as i32),
-                        None => {
-                            unspent_outputs.insert(txid.clone(), vec![out_idx as i32]);
-                        }
-                    }
-                }
+        let db = self.open_db()?;
+        for (txid, vout, out) in find_candidates(&db, pub_key_hash)? {
+            if out.is_locked_with_key(pub_key_hash) && accumulated < amount {
+                accumulated += out.value;
+                unspent_outputs.entry(txid).or_default().push(vout);
             }
         }
 
         Ok((accumulated, unspent_outputs))
     }
 
-    // 通过 pub_key_hash 获取 未花费输出
-    pub fn find_utxos(&self, pub_key_hash: &[u8]) -> Result<TXOutputs> {
+    fn find_utxos(&self, pub_key_hash: &[u8]) -> Result<TXOutputs> {
         let mut utxos = TXOutputs {
             outputs: Vec::new(),
         };
 
-        let db = sled::open("data/utxos")?;
-        for kv in db.iter() {
-            let (_, v) = kv?;
-            let outs: TXOutputs = deserialize(&v.to_vec())?;
-
-            for out in outs.outputs {
-                if out.is_locked_with_key(pub_key_hash) {
-                    utxos.outputs.push(out.clone());
-                }
+        let db = self.open_db()?;
+        for (_, _, out) in find_candidates(&db, pub_key_hash)? {
+            if out.is_locked_with_key(pub_key_hash) {
+                utxos.outputs.push(out);
             }
         }
 
         Ok(utxos)
     }
 
+    fn find_transaction(&self, id: &str) -> Result<Transaction> {
+        self.blockchain.find_transaction(id)
+    }
+}
+
+impl UTXOSet {
+    /**
+     * @desc 打开 UTXO 数据库，并把旧版本的格式升级到当前版本
+     */
+    fn open_db(&self) -> Result<sled::Db> {
+        let db = sled::open(network::data_path(UTXO_DB))?;
+        let fresh = db.is_empty();
+        schema::upgrade(
+            &db,
+            UTXOS_SCHEMA,
+            UTXOS_VERSION,
+            fresh,
+            &[Migration {
+                from: 0,
+                description: "key outputs by txid:vout, replaying the chain",
+                run: Box::new(|db| self.replay_chain(db)),
+            }],
+        )?;
+
+        Ok(db)
+    }
+
+    /**
+     * @desc 清空数据库中的 UTXO 集和索引，从创世区块开始重放主链
+     */
+    fn replay_chain(&self, db: &sled::Db) -> Result<()> {
+        db.clear()?;
+        db.open_tree(PUB_KEY_INDEX_TREE)?.clear()?;
+        for block in self.blockchain.iter_forward() {
+            apply_block(db, &block)?;
+        }
+
+        Ok(())
+    }
+
     // 获取交易总数
     pub fn count_transactions(&self) -> Result<i32> {
         let mut counter = 0;
+        let mut last_txid = String::new();
 
-        let db = sled::open("data/utxos")?;
+        // 同一笔交易的输出键有相同的前缀，按键排序后相邻
+        let db = self.open_db()?;
         for kv in db.iter() {
-            kv?;
-            counter += 1;
+            let (k, _) = kv?;
+            let (txid, _) = parse_outpoint(&k)?;
+            if txid != last_txid {
+                counter += 1;
+                last_txid = txid;
+            }
         }
 
         Ok(counter)
     }
 
+    /**
+     * @desc 按公钥哈希汇总未花费输出，返回余额不为 0 的地址和余额，按余额从大到小排序
+     */
+    pub fn address_balances(&self) -> Result<Vec<(String, i32)>> {
+        let mut balances: HashMap<Vec<u8>, i32> = HashMap::new();
+
+        let db = self.open_db()?;
+        for kv in db.iter() {
+            let (_, v) = kv?;
+            let out: TXOutput = deserialize(&v)?;
+            *balances.entry(out.pub_key_hash).or_insert(0) += out.value;
+        }
+
+        let mut balances: Vec<(String, i32)> = balances
+            .into_iter()
+            .filter(|(_, balance)| *balance != 0)
+            .map(|(pub_key_hash, balance)| (address_from_pub_key_hash(&pub_key_hash), balance))
+            .collect();
+        balances.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+
+        Ok(balances)
+    }
+
     // 通过区块交易更新 utxo 集
     pub fn update(&self, block: &Block) -> Result<()> {
-        let db = sled::open("data/utxos")?;
+        let db = self.open_db()?;
+
+        apply_block(&db, block)
+    }
+
+    /**
+     * @desc 重建数据库
+     */
+    pub fn reindex(&self) -> Result<()> {
+        self.reindex_with_progress(|_, _| {})
+    }
+
+    /**
+     * @desc 从创世区块开始逐个区块重放到数据库，内存中只保留当前区块，
+     *       每处理 REINDEX_PROGRESS_INTERVAL 个区块回调一次 (已处理区块数, 区块总数)
+     */
+    pub fn reindex_with_progress(&self, mut progress: impl FnMut(i32, i32)) -> Result<()> {
+        let db = recreate_db()?;
+
+        let total = self.blockchain.get_best_height()? + 1;
+        let mut done = 0;
+        for block in self.blockchain.iter_forward() {
+            apply_block(&db, &block)?;
+            done += 1;
+            if done % REINDEX_PROGRESS_INTERVAL == 0 || done == total {
+                info!("Reindexed {}/{} blocks.", done, total);
+                progress(done, total);
+            }
+        }
+        db.flush()?;
+
+        Ok(())
+    }
+
+    /**
+     * @desc 多个线程分别扫描互不重叠的区块范围并写入各自的临时树，再按高度顺序合并到数据库，
+     *       threads 不超过 1 时使用逐个区块的重建
+     */
+    pub fn reindex_parallel(&self, threads: usize) -> Result<()> {
+        let total = self.blockchain.get_best_height()? + 1;
+        let threads = threads.min(total.max(1) as usize);
+        if threads <= 1 {
+            return self.reindex();
+        }
+
+        let db = recreate_db()?;
+        let step = (total as usize).div_ceil(threads) as i32;
+        let scanned: Vec<Result<()>> = thread::scope(|s| {
+            let handles: Vec<_> = (0..threads)
+                .map(|i| {
+                    let bc = &self.blockchain;
+                    let db = &db;
+                    let start = i as i32 * step;
+                    s.spawn(move || scan_range(bc, db, i, start, start + step))
+                })
+                .collect();
+            handles
+                .into_iter()
+                .map(|handle| handle.join().unwrap())
+                .collect()
+        });
+        scanned.into_iter().collect::<Result<Vec<()>>>()?;
+        info!("Scanned {} blocks on {} threads.", total, threads);
+
+        merge_ranges(&db, threads)?;
+        open_pub_key_index(&db)?;
+        db.flush()?;
+
+        Ok(())
+    }
+}
+
+/**
+ * @desc UTXO 数据库中一个输出的键: "交易 id:输出序号"
+ */
+pub fn outpoint_key(txid: &str, vout: i32) -> String {
+    format!("{}:{}", txid, vout)
+}
+
+fn parse_outpoint(key: &[u8]) -> Result<(String, i32)> {
+    let key = std::str::from_utf8(key)?;
+    let (txid, vout) = key
+        .rsplit_once(':')
+        .ok_or_else(|| format_err!("Invalid outpoint key: {}", key))?;
+
+    Ok((txid.to_string(), vout.parse()?))
+}
+
+/**
+ * @desc 删除旧的 UTXO 数据库，创建当前格式的空数据库
+ */
+fn recreate_db() -> Result<sled::Db> {
+    std::fs::remove_dir_all(network::data_path(UTXO_DB)).ok();
+    let db = sled::open(network::data_path(UTXO_DB))?;
+    schema::set_version(&db, UTXOS_SCHEMA, UTXOS_VERSION)?;
+
+    Ok(db)
+}
+
+fn index_prefix(pub_key_hash: &[u8]) -> Vec<u8> {
+    let mut prefix = vec![pub_key_hash.len() as u8];
+    prefix.extend_from_slice(pub_key_hash);
+    prefix
+}
+
+fn index_key(pub_key_hash: &[u8], outpoint: &str) -> Vec<u8> {
+    let mut key = index_prefix(pub_key_hash);
+    key.extend_from_slice(outpoint.as_bytes());
+    key
+}
+
+/**
+ * @desc 打开公钥哈希索引，没有索引的数据库先根据 UTXO 集建立
+ */
+fn open_pub_key_index(db: &sled::Db) -> Result<sled::Tree> {
+    let index = db.open_tree(PUB_KEY_INDEX_TREE)?;
+    if !index.is_empty() || db.is_empty() {
+        return Ok(index);
+    }
+
+    info!("Building pub key index of the UTXO set...");
+    let mut batch = sled::Batch::default();
+    for kv in db.iter() {
+        let (k, v) = kv?;
+        let out: TXOutput = deserialize(&v)?;
+        batch.insert(index_key(&out.pub_key_hash, std::str::from_utf8(&k)?), &[]);
+    }
+    index.apply_batch(batch)?;
+
+    Ok(index)
+}
+
+/**
+ * @desc 通过索引找到公钥哈希的输出 (交易 id, 输出序号, 输出)，按输出键排序，调用方仍需检查输出
+ */
+fn find_candidates(db: &sled::Db, pub_key_hash: &[u8]) -> Result<Vec<(String, i32, TXOutput)>> {
+    let index = open_pub_key_index(db)?;
+    let prefix = index_prefix(pub_key_hash);
+
+    let mut candidates = Vec::new();
+    for kv in index.scan_prefix(&prefix) {
+        let (k, _) = kv?;
+        let outpoint = &k[prefix.len()..];
+        if let Some(v) = db.get(outpoint)? {
+            let (txid, vout) = parse_outpoint(outpoint)?;
+            candidates.push((txid, vout, deserialize(&v)?));
+        }
+    }
+
+    Ok(candidates)
+}
+
+/**
+ * @desc 把区块花费和新建的输出作为一个批次写入数据库，每个输出单独一条记录
+ *       先写入新索引、最后删除失效的索引，中途失败时索引只会多出候选输出
+ */
+fn apply_block(db: &sled::Db, block: &Block) -> Result<()> {
+    let index = open_pub_key_index(db)?;
+    let mut created: HashMap<String, TXOutput> = HashMap::new();
+    let mut spent: HashSet<String> = HashSet::new();
+    let mut batch = sled::Batch::default();
+    let mut new_keys = sled::Batch::default();
+    let mut stale_keys = sled::Batch::default();
+
+    for tx in block.get_transaction() {
+        if !tx.is_coinbase() {
+            for vin in &tx.vin {
+                let key = outpoint_key(&vin.txid, vin.vout);
+                let out: TXOutput = match created.remove(&key) {
+                    Some(out) => out,
+                    None => match db.get(&key)? {
+                        Some(data) if spent.insert(key.clone()) => deserialize(&data)?,
+                        _ => return Err(format_err!("Output {} is not in the UTXO set.", key)),
+                    },
+                };
+
+                batch.remove(key.as_bytes());
+                stale_keys.remove(index_key(&out.pub_key_hash, &key));
+            }
+        }
+
+        for (vout, out) in tx.vout.iter().enumerate() {
+            let key = outpoint_key(&tx.id, vout as i32);
+            batch.insert(key.as_bytes(), serialize(out)?);
+            new_keys.insert(index_key(&out.pub_key_hash, &key), &[]);
+            created.insert(key, out.clone());
+        }
+    }
 
+    index.apply_batch(new_keys)?;
+    db.apply_batch(batch)?;
+    index.apply_batch(stale_keys)?;
+
+    Ok(())
+}
+
+// 第 range 个区块范围的临时树: (新建的输出, 花费的输出)
+fn range_trees(db: &sled::Db, range: usize) -> Result<(sled::Tree, sled::Tree)> {
+    Ok((
+        db.open_tree(format!("{}:{}", REINDEX_CREATED_TREE, range))?,
+        db.open_tree(format!("{}:{}", REINDEX_SPENT_TREE, range))?,
+    ))
+}
+
+/**
+ * @desc 把 [start, end) 高度范围内区块新建的输出和花费的输出逐个区块写入第 range 个范围的临时树
+ */
+fn scan_range(bc: &Blockchain, db: &sled::Db, range: usize, start: i32, end: i32) -> Result<()> {
+    let (created, spent) = range_trees(db, range)?;
+
+    for block in bc.iter_range(start, end) {
+        let mut created_batch = sled::Batch::default();
+        let mut spent_batch = sled::Batch::default();
         for tx in block.get_transaction() {
             if !tx.is_coinbase() {
                 for vin in &tx.vin {
-                    let mut update_outputs = TXOutputs {
-                        outputs: Vec::new(),
-                    };
-
-                    let outs: TXOutputs = deserialize(&db.get(&vin.txid)?.unwrap().to_vec())?;
-                    for out_idx in 0..outs.outputs.len() {
-                        if out_idx != vin.vout as usize {
-                            update_outputs.outputs.push(outs.outputs[out_idx].clone());
-                        }
-                    }
-
-                    if update_outputs.outputs.is_empty() {
-                        db.remove(&vin.txid)?;
-                    } else {
-                        db.insert(vin.txid.as_bytes(), serialize(&update_outputs)?)?;
-                    }
+                    spent_batch.insert(outpoint_key(&vin.txid, vin.vout).as_bytes(), &[]);
                 }
             }
+            for (vout, out) in tx.vout.iter().enumerate() {
+                let key = outpoint_key(&tx.id, vout as i32);
+                created_batch.insert(key.as_bytes(), serialize(out)?);
+            }
+        }
+        created.apply_batch(created_batch)?;
+        spent.apply_batch(spent_batch)?;
+    }
+
+    Ok(())
+}
 
-            let mut new_outputs = TXOutputs {
-                outputs: Vec::new(),
-            };
-            for out in &tx.vout {
-                new_outputs.outputs.push(out.clone());
+/**
+ * @desc 按区块范围的顺序合并临时树: 先加入范围内新建的输出，再删除范围内花费的输出，
+ *       花费只会引用更早的交易，所以结果和逐个区块重放一致；合并完的临时树随即删除
+ */
+fn merge_ranges(db: &sled::Db, ranges: usize) -> Result<()> {
+    for range in 0..ranges {
+        let (created, spent) = range_trees(db, range)?;
+
+        let mut batch = sled::Batch::default();
+        for (i, item) in created.iter().enumerate() {
+            let (key, out) = item?;
+            batch.insert(key, out);
+            if (i + 1) % REINDEX_MERGE_BATCH == 0 {
+                db.apply_batch(std::mem::take(&mut batch))?;
             }
+        }
+        db.apply_batch(batch)?;
 
-            db.insert(tx.id.as_bytes(), serialize(&new_outputs)?)?;
+        let mut batch = sled::Batch::default();
+        for (i, item) in spent.iter().enumerate() {
+            let (key, _) = item?;
+            if !db.contains_key(&key)? {
+                return Err(format_err!(
+                    "Output {} is not in the UTXO set.",
+                    String::from_utf8_lossy(&key)
+                ));
+            }
+            batch.remove(key);
+            if (i + 1) % REINDEX_MERGE_BATCH == 0 {
+                db.apply_batch(std::mem::take(&mut batch))?;
+            }
         }
+        db.apply_batch(batch)?;
 
-        Ok(())
+        db.drop_tree(format!("{}:{}", REINDEX_CREATED_TREE, range))?;
+        db.drop_tree(format!("{}:{}", REINDEX_SPENT_TREE, range))?;
     }
 
+    Ok(())
+}
+
+// 内存中的 UTXO 集，用于测试交易构建
+#[cfg(test)]
+#[derive(Default)]
+pub struct MemoryUtxoSet {
+    pub utxos: HashMap<String, TXOutputs>,
+    pub transactions: HashMap<String, Transaction>,
+}
+
+#[cfg(test)]
+impl MemoryUtxoSet {
     /**
-     * @desc 重建数据库
+     * @desc 加入交易，其全部输出视为未花费
      */
-    pub fn reindex(&self) -> Result<()> {
-        std::fs::remove_dir_all("data/utxos").ok();
-        let db = sled::open("data/utxos")?;
+    pub fn add_transaction(&mut self, tx: Transaction) {
+        self.utxos.insert(
+            tx.id.clone(),
+            TXOutputs {
+                outputs: tx.vout.clone(),
+            },
+        );
+        self.transactions.insert(tx.id.clone(), tx);
+    }
+}
 
-        let utxos = self.blockchain.find_utxo();
+#[cfg(test)]
+impl UtxoProvider for MemoryUtxoSet {
+    fn find_spendable_outputs(
+        &self,
+        pub_key_hash: &[u8],
+        amount: i32,
+    ) -> Result<(i32, HashMap<String, Vec<i32>>)> {
+        let mut unspent_outputs: HashMap<String, Vec<i32>> = HashMap::new();
+        let mut accumulated = 0;
 
-        for (txid, outs) in utxos {
-            db.insert(txid.as_bytes(), serialize(&outs)?)?;
+        for (txid, outs) in &self.utxos {
+            for (out_idx, out) in outs.outputs.iter().enumerate() {
+                if out.is_locked_with_key(pub_key_hash) && accumulated < amount {
+                    accumulated += out.value;
+                    unspent_outputs
+                        .entry(txid.clone())
+                        .or_default()
+                        .push(out_idx as i32);
+                }
+            }
         }
 
-        Ok(())
+        Ok((accumulated, unspent_outputs))
+    }
+
+    fn find_utxos(&self, pub_key_hash: &[u8]) -> Result<TXOutputs> {
+        let outputs = self
+            .utxos
+            .values()
+            .flat_map(|outs| outs.outputs.iter())
+            .filter(|out| out.is_locked_with_key(pub_key_hash))
+            .cloned()
+            .collect();
+
+        Ok(TXOutputs { outputs })
+    }
+
+    fn find_transaction(&self, id: &str) -> Result<Transaction> {
+        self.transactions
+            .get(id)
+            .cloned()
+            .ok_or_else(|| format_err!("Transaction {} is not found.", id))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        genesis::GenesisSpec,
+        wallets::{decode_address, Wallet},
+    };
+    use rand_core::OsRng;
+
+    #[test]
+    fn test_new_utxo_with_memory_provider() {
+        let from = Wallet::default();
+        let to = Wallet::default();
+
+        let mut utxos = MemoryUtxoSet::default();
+        utxos.add_transaction(
+            Transaction::new_coinbase(from.get_address(), String::from("test"), &mut OsRng)
+                .unwrap(),
+        );
+
+        let tx = Transaction::new_utxo(&from, &to.get_address(), 3, &utxos).unwrap();
+        assert_eq!(tx.vin.len(), 1);
+        let values: Vec<i32> = tx.vout.iter().map(|out| out.value).collect();
+        assert_eq!(values, vec![3, 7]);
+
+        let mut pub_key_hash = to.public_key.clone();
+        crate::wallets::hash_pub_key(&mut pub_key_hash);
+        assert!(utxos.find_utxos(&pub_key_hash).unwrap().outputs.is_empty());
+
+        assert!(Transaction::new_utxo(&from, &to.get_address(), 11, &utxos).is_err());
+    }
+
+    #[test]
+    fn test_apply_blocks_forward() {
+        let from = Wallet::from_seed(&[1; 32]);
+        let to = Wallet::from_seed(&[2; 32]);
+        let mut spec = GenesisSpec::default();
+        spec.allocations.push((from.get_address(), SUBSIDY));
+        let bc = Blockchain::create_temporary(&spec.build().unwrap()).unwrap();
+
+        let cbtx =
+            Transaction::new_coinbase(from.get_address(), String::new(), &mut OsRng).unwrap();
+        let mut utxos = MemoryUtxoSet::default();
+        utxos.add_transaction(cbtx.clone());
+        bc.mine_block(vec![cbtx]).unwrap();
+        let tx = Transaction::new_utxo(&from, &to.get_address(), 3, &utxos).unwrap();
+        let cbtx = Transaction::new_coinbase(to.get_address(), String::new(), &mut OsRng).unwrap();
+        bc.mine_block(vec![cbtx, tx]).unwrap();
+
+        // 逐个区块写入的结果和一次性扫描整条链的结果一致
+        let db = sled::Config::new().temporary(true).open().unwrap();
+        for block in bc.iter_forward() {
+            apply_block(&db, &block).unwrap();
+        }
+        let expected = bc.find_utxo();
+        let count: usize = expected.values().map(|outs| outs.outputs.len()).sum();
+        assert_eq!(db.len(), count);
+        for (txid, outs) in &expected {
+            let stored: Vec<i32> = db
+                .scan_prefix(format!("{}:", txid))
+                .map(|kv| deserialize::<TXOutput>(&kv.unwrap().1).unwrap().value)
+                .collect();
+            let outs: Vec<i32> = outs.outputs.iter().map(|out| out.value).collect();
+            assert_eq!(stored, outs);
+        }
+
+        // 索引只给出该地址未花费的输出
+        let from_hash = decode_address(&from.get_address()).unwrap();
+        let to_hash = decode_address(&to.get_address()).unwrap();
+        let candidates = |pub_key_hash: &[u8]| -> Vec<String> {
+            find_candidates(&db, pub_key_hash)
+                .unwrap()
+                .into_iter()
+                .map(|(txid, vout, _)| outpoint_key(&txid, vout))
+                .collect()
+        };
+        assert_eq!(candidates(&from_hash).len(), 2);
+        assert_eq!(candidates(&to_hash).len(), 2);
+        assert!(candidates(&from_hash)
+            .iter()
+            .all(|key| db.contains_key(key).unwrap()));
+        assert!(candidates(&[0; 20]).is_empty());
+
+        // 旧版本数据库没有索引时自动建立
+        let index = db.open_tree(PUB_KEY_INDEX_TREE).unwrap();
+        let len = index.len();
+        index.clear().unwrap();
+        assert_eq!(open_pub_key_index(&db).unwrap().len(), len);
+        assert_eq!(candidates(&to_hash).len(), 2);
+
+        // 按区块范围并行扫描后合并，和逐个区块写入的结果一致，合并后不留下临时树
+        for ranges in [vec![(0, 3)], vec![(0, 1), (1, 3)], vec![(0, 2), (2, 4)]] {
+            let merged = sled::Config::new().temporary(true).open().unwrap();
+            for (range, &(start, end)) in ranges.iter().enumerate() {
+                scan_range(&bc, &merged, range, start, end).unwrap();
+            }
+            merge_ranges(&merged, ranges.len()).unwrap();
+            assert_eq!(merged.len(), db.len());
+            for item in merged.iter() {
+                let (key, out) = item.unwrap();
+                assert_eq!(db.get(&key).unwrap(), Some(out));
+            }
+            assert!(merged
+                .tree_names()
+                .iter()
+                .all(|name| !name.starts_with(REINDEX_CREATED_TREE.as_bytes())
+                    && !name.starts_with(REINDEX_SPENT_TREE.as_bytes())));
+        }
+    }
+
+    #[test]
+    fn test_outpoint_key() {
+        let key = outpoint_key(&"a".repeat(64), 12);
+        assert_eq!(
+            parse_outpoint(key.as_bytes()).unwrap(),
+            ("a".repeat(64), 12)
+        );
+        assert!(parse_outpoint(b"no-vout").is_err());
     }
 }
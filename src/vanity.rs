@@ -0,0 +1,148 @@
+// 靓号地址: 多个线程不断生成随机密钥，直到地址以指定前缀开头；
+// base58 地址的开头几位受版本字节限制(例如所有地址的第一位相同)，搜索前先检查前缀能否出现并估算需要的尝试次数
+
+use std::{
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        mpsc,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use super::Result;
+use crate::wallets::{address_from_pub_key_hash, Wallet};
+use failure::format_err;
+use rand::{rngs::StdRng, SeedableRng};
+
+const BASE58_ALPHABET: &str = "123456789ABCDEFGHJKLMNPQRSTUVWXYZabcdefghijkmnopqrstuvwxyz";
+// 汇报进度的间隔
+pub const PROGRESS_INTERVAL: Duration = Duration::from_secs(1);
+
+/**
+ * @desc 随机密钥的地址以 prefix 开头的概率，前缀包含非 base58 字符或不可能出现时返回错误
+ */
+pub fn probability(prefix: &str) -> Result<f64> {
+    let digits = base58_digits(prefix)?;
+    // 公钥哈希全 0 和全 1 的地址是地址范围的两端，所有地址长度相同
+    let min = base58_digits(&address_from_pub_key_hash(&[0; 20]))?;
+    let max = base58_digits(&address_from_pub_key_hash(&[0xff; 20]))?;
+    if digits.is_empty() || digits.len() > min.len() {
+        return Err(format_err!(
+            "Vanity prefix must have 1 to {} characters.",
+            min.len()
+        ));
+    }
+
+    // 以 prefix 开头的地址数值范围，与全部地址的范围取交集
+    let pad = |digit: usize| {
+        let mut padded = digits.clone();
+        padded.resize(min.len(), digit);
+        value(&padded)
+    };
+    let (low, high) = (pad(0).max(value(&min)), pad(57).min(value(&max)));
+    if low > high {
+        return Err(format_err!(
+            "No address can start with {}, addresses range from {} to {}.",
+            prefix,
+            &address_from_pub_key_hash(&[0; 20])[..digits.len()],
+            &address_from_pub_key_hash(&[0xff; 20])[..digits.len()]
+        ));
+    }
+
+    Ok(((high - low) / (value(&max) - value(&min))).clamp(f64::MIN_POSITIVE, 1.0))
+}
+
+fn base58_digits(text: &str) -> Result<Vec<usize>> {
+    text.chars()
+        .map(|c| {
+            BASE58_ALPHABET
+                .find(c)
+                .ok_or_else(|| format_err!("{:?} is not a base58 character.", c))
+        })
+        .collect()
+}
+
+fn value(digits: &[usize]) -> f64 {
+    digits
+        .iter()
+        .fold(0.0, |value, digit| value * 58.0 + *digit as f64)
+}
+
+/**
+ * @desc 用 threads 个线程搜索地址以 prefix 开头的密钥，每隔 PROGRESS_INTERVAL 用已尝试的次数和耗时调用 progress
+ */
+pub fn search(
+    prefix: &str,
+    threads: usize,
+    mut progress: impl FnMut(u64, Duration),
+) -> Result<Wallet> {
+    probability(prefix)?;
+
+    let attempts = AtomicU64::new(0);
+    let stop = AtomicBool::new(false);
+    let (sender, receiver) = mpsc::channel();
+    let started = Instant::now();
+
+    let wallet = thread::scope(|s| {
+        for _ in 0..threads.max(1) {
+            let sender = sender.clone();
+            let (attempts, stop) = (&attempts, &stop);
+            s.spawn(move || {
+                let mut rng = StdRng::from_entropy();
+                while !stop.load(Ordering::Relaxed) {
+                    let wallet = Wallet::new(&mut rng);
+                    attempts.fetch_add(1, Ordering::Relaxed);
+                    if wallet.get_address().starts_with(prefix) {
+                        sender.send(wallet).ok();
+                        break;
+                    }
+                }
+            });
+        }
+
+        let wallet = loop {
+            match receiver.recv_timeout(PROGRESS_INTERVAL) {
+                Ok(wallet) => break wallet,
+                Err(_) => progress(attempts.load(Ordering::Relaxed), started.elapsed()),
+            }
+        };
+        stop.store(true, Ordering::Relaxed);
+        wallet
+    });
+
+    Ok(wallet)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_probability() {
+        let address = Wallet::default().get_address();
+        let first = &address[..1];
+        // 所有地址的第一位相同
+        assert_eq!(probability(first).unwrap(), 1.0);
+
+        let p = probability(&format!("{}A", first)).unwrap();
+        assert!(p > 0.0 && p < 1.0);
+        assert!(probability(&format!("{}Ab", first)).unwrap() < p);
+
+        assert!(probability("").is_err());
+        assert!(probability(&format!("{}0", first)).is_err());
+        assert!(probability(&format!("{}z", first)).is_err());
+        let other = BASE58_ALPHABET.chars().find(|c| !address.starts_with(*c));
+        assert!(probability(&other.unwrap().to_string()).is_err());
+    }
+
+    #[test]
+    fn test_search() {
+        let first = Wallet::default().get_address()[..1].to_string();
+        let prefix = format!("{}A", first);
+
+        let wallet = search(&prefix, 2, |_, _| {}).unwrap();
+        assert!(wallet.get_address().starts_with(&prefix));
+        assert!(search(&format!("{}z", first), 2, |_, _| {}).is_err());
+    }
+}
@@ -0,0 +1,369 @@
+// 保险库: 存入的资金锁定在程序中，恢复密钥可以随时取走，正常密钥只能分两步取出:
+// 先广播触发交易把资金转入延迟输出，等待 delay 个区块后再取出；
+// 触发交易需要恢复密钥和正常密钥共同签名，恢复密钥在存入时预先签好，
+// 因此正常密钥被盗时也只能触发取出，恢复密钥在延迟期内可以取消取出，把资金转到恢复密钥
+
+use std::collections::HashMap;
+
+use super::Result;
+use crate::{
+    transaction::*,
+    utxo_set::UtxoProvider,
+    vm::{self, TX_VERSION_PROGRAM},
+    wallets::{hash_pub_key, Wallet},
+};
+use failure::format_err;
+use serde::{Deserialize, Serialize};
+
+// 存入交易和触发交易中保险库输出的序号
+const VAULT_VOUT: i32 = 0;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum VaultState {
+    // 资金在保险库输出中
+    Deposited,
+    // 触发交易已广播，资金在延迟输出中
+    Triggered,
+    Withdrawn,
+    Cancelled,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Vault {
+    pub deposit: Transaction,
+    pub normal: Vec<u8>,
+    pub recovery: Vec<u8>,
+    pub amount: i32,
+    // 延迟输出的相对锁定时间(区块数)
+    pub delay: u32,
+    // 触发交易的手续费，从保险库的资金中扣除
+    pub fee: i32,
+    // 恢复密钥对触发交易的签名
+    pub recovery_signature: Vec<u8>,
+    pub state: VaultState,
+}
+
+impl Vault {
+    /**
+     * @desc 存入保险库: 存入交易把 amount 锁定在保险库程序中，找零回到正常密钥的地址，
+     *       同时用恢复密钥预先签署触发交易
+     */
+    pub fn deposit(
+        wallet: &Wallet,
+        recovery: &Wallet,
+        amount: i32,
+        delay: u32,
+        fee: i32,
+        utxo: &impl UtxoProvider,
+    ) -> Result<Vault> {
+        if recovery.public_key == wallet.public_key {
+            return Err(format_err!(
+                "The recovery key must differ from the normal key."
+            ));
+        }
+        if fee < 0 || amount <= fee || delay == 0 || delay > i32::MAX as u32 {
+            return Err(format_err!(
+                "Invalid vault: amount {}, fee {}, delay {}.",
+                amount,
+                fee,
+                delay
+            ));
+        }
+
+        let mut pub_key_hash = wallet.public_key.clone();
+        hash_pub_key(&mut pub_key_hash);
+        let (accumulated, outputs) = utxo.find_spendable_outputs(&pub_key_hash, amount + fee)?;
+        if accumulated < amount + fee {
+            return Err(format_err!(
+                "Not Enough balance: current balance {}.",
+                accumulated
+            ));
+        }
+
+        let mut vault = Vault {
+            deposit: Transaction {
+                id: String::new(),
+                vin: Vec::new(),
+                vout: Vec::new(),
+                version: TX_VERSION,
+                relative_lock_time: 0,
+            },
+            normal: wallet.public_key.clone(),
+            recovery: recovery.public_key.clone(),
+            amount,
+            delay,
+            fee,
+            recovery_signature: Vec::new(),
+            state: VaultState::Deposited,
+        };
+
+        let mut prev_txs = HashMap::new();
+        for (txid, outs) in outputs {
+            for out in outs {
+                vault.deposit.vin.push(TXInput {
+                    txid: txid.clone(),
+                    vout: out,
+                    signature: Vec::new(),
+                    pub_key: wallet.public_key.clone(),
+                });
+            }
+            prev_txs.insert(txid.clone(), utxo.find_transaction(&txid)?);
+        }
+        vault.deposit.vout.push(TXOutput {
+            value: amount,
+            pub_key_hash: vm::program_hash(&vault.vault_program()?),
+        });
+        if accumulated > amount + fee {
+            vault.deposit.vout.push(TXOutput {
+                value: accumulated - amount - fee,
+                pub_key_hash,
+            });
+        }
+        vault.deposit.id = vault.deposit.hash()?;
+        vault.deposit.sign(&wallet.secret_key, prev_txs)?;
+
+        vault.recovery_signature =
+            vault
+                .trigger_tx()?
+                .sign_input(0, &recovery.secret_key, &vault.deposit_prev_txs())?;
+
+        Ok(vault)
+    }
+
+    /**
+     * @desc 保险库 id 是存入交易的 txid
+     */
+    pub fn id(&self) -> String {
+        self.deposit.id.clone()
+    }
+
+    /**
+     * @desc 保险库程序: 恢复密钥单独签名(见证数据以 1 结尾)，或者两个密钥共同签名(以 0 结尾)
+     */
+    pub fn vault_program(&self) -> Result<Vec<u8>> {
+        vm::assemble(&format!(
+            "OP_IF 0x{recovery} OP_CHECKSIG OP_ELSE 0x{normal} OP_CHECKSIGVERIFY 0x{recovery} OP_CHECKSIG OP_ENDIF",
+            recovery = encode_hex(&self.recovery),
+            normal = encode_hex(&self.normal)
+        ))
+    }
+
+    /**
+     * @desc 延迟输出的程序: 恢复密钥随时可以签名取消，正常密钥要等待 delay 个区块
+     */
+    pub fn unvault_program(&self) -> Result<Vec<u8>> {
+        vm::assemble(&format!(
+            "OP_IF 0x{} OP_CHECKSIG OP_ELSE {} OP_CHECKSEQUENCEVERIFY OP_DROP 0x{} OP_CHECKSIG OP_ENDIF",
+            encode_hex(&self.recovery),
+            self.delay,
+            encode_hex(&self.normal)
+        ))
+    }
+
+    /**
+     * @desc 触发交易: 保险库输出扣除手续费后转入延迟输出，未签名
+     */
+    pub fn trigger_tx(&self) -> Result<Transaction> {
+        let mut tx = Transaction {
+            id: String::new(),
+            vin: vec![TXInput {
+                txid: self.deposit.id.clone(),
+                vout: VAULT_VOUT,
+                signature: Vec::new(),
+                pub_key: vm::lock_script(&self.vault_program()?),
+            }],
+            vout: vec![TXOutput {
+                value: self.amount - self.fee,
+                pub_key_hash: vm::program_hash(&self.unvault_program()?),
+            }],
+            version: TX_VERSION_PROGRAM,
+            relative_lock_time: 0,
+        };
+        tx.id = tx.hash()?;
+
+        Ok(tx)
+    }
+
+    fn deposit_prev_txs(&self) -> HashMap<String, Transaction> {
+        HashMap::from([(self.deposit.id.clone(), self.deposit.clone())])
+    }
+
+    fn check_key(&self, wallet: &Wallet, key: &[u8]) -> Result<()> {
+        if wallet.public_key != key {
+            return Err(format_err!(
+                "The wallet does not hold the key of vault {}.",
+                self.id()
+            ));
+        }
+
+        Ok(())
+    }
+
+    /**
+     * @desc 触发取出: 正常密钥补上签名，返回可以广播的触发交易
+     */
+    pub fn trigger(&self, wallet: &Wallet) -> Result<Transaction> {
+        self.check_key(wallet, &self.normal)?;
+        if self.state != VaultState::Deposited {
+            return Err(format_err!(
+                "Vault {} is {:?}, not deposited.",
+                self.id(),
+                self.state
+            ));
+        }
+
+        let mut tx = self.trigger_tx()?;
+        let signature = tx.sign_input(0, &wallet.secret_key, &self.deposit_prev_txs())?;
+        tx.vin[0].signature = vm::assemble(&format!(
+            "0x{} 0x{} OP_0",
+            encode_hex(&self.recovery_signature),
+            encode_hex(&signature)
+        ))?;
+
+        Ok(tx)
+    }
+
+    /**
+     * @desc 取消: 恢复密钥把保险库输出(未触发)或延迟输出(已触发)扣除 fee 后转到恢复密钥的地址
+     */
+    pub fn cancel(&self, wallet: &Wallet, fee: i32) -> Result<Transaction> {
+        self.check_key(wallet, &self.recovery)?;
+        let (prev_tx, program) = match self.state {
+            VaultState::Deposited => (self.deposit.clone(), self.vault_program()?),
+            VaultState::Triggered => (self.trigger_tx()?, self.unvault_program()?),
+            state => return Err(format_err!("Vault {} is already {:?}.", self.id(), state)),
+        };
+
+        let mut tx = self.spend(&prev_tx, &program, &wallet.get_address(), fee, 0)?;
+        let signature = tx.sign_input(
+            0,
+            &wallet.secret_key,
+            &HashMap::from([(prev_tx.id.clone(), prev_tx)]),
+        )?;
+        tx.vin[0].signature = vm::assemble(&format!("0x{} OP_1", encode_hex(&signature)))?;
+
+        Ok(tx)
+    }
+
+    /**
+     * @desc 取出: 触发交易确认 delay 个区块后，正常密钥把延迟输出扣除 fee 后转到 to
+     */
+    pub fn withdraw(&self, wallet: &Wallet, to: &str, fee: i32) -> Result<Transaction> {
+        self.check_key(wallet, &self.normal)?;
+        if self.state != VaultState::Triggered {
+            return Err(format_err!(
+                "Vault {} is {:?}, trigger it first.",
+                self.id(),
+                self.state
+            ));
+        }
+
+        let trigger = self.trigger_tx()?;
+        let mut tx = self.spend(&trigger, &self.unvault_program()?, to, fee, self.delay)?;
+        let signature = tx.sign_input(
+            0,
+            &wallet.secret_key,
+            &HashMap::from([(trigger.id.clone(), trigger)]),
+        )?;
+        tx.vin[0].signature = vm::assemble(&format!("0x{} OP_0", encode_hex(&signature)))?;
+
+        Ok(tx)
+    }
+
+    // 花费 prev_tx 中程序锁定的输出，扣除 fee 后转到地址 to，未签名
+    fn spend(
+        &self,
+        prev_tx: &Transaction,
+        program: &[u8],
+        to: &str,
+        fee: i32,
+        relative_lock_time: u32,
+    ) -> Result<Transaction> {
+        let value = prev_tx.vout[VAULT_VOUT as usize].value;
+        if fee < 0 || value <= fee {
+            return Err(format_err!("Fee {} is not payable from {}.", fee, value));
+        }
+
+        let mut tx = Transaction {
+            id: String::new(),
+            vin: vec![TXInput {
+                txid: prev_tx.id.clone(),
+                vout: VAULT_VOUT,
+                signature: Vec::new(),
+                pub_key: vm::lock_script(program),
+            }],
+            vout: vec![TXOutput::new(value - fee, to.to_string())?],
+            version: TX_VERSION_PROGRAM,
+            relative_lock_time,
+        };
+        tx.id = tx.hash()?;
+
+        Ok(tx)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::utxo_set::MemoryUtxoSet;
+    use rand_core::OsRng;
+
+    #[test]
+    fn test_vault() {
+        let normal = Wallet::from_seed(&[1; 32]);
+        let recovery = Wallet::from_seed(&[2; 32]);
+        let mut utxos = MemoryUtxoSet::default();
+        utxos.add_transaction(
+            Transaction::new_coinbase(normal.get_address(), String::from("vault"), &mut OsRng)
+                .unwrap(),
+        );
+
+        assert!(Vault::deposit(&normal, &normal, 8, 10, 1, &utxos).is_err());
+        assert!(Vault::deposit(&normal, &recovery, 8, 0, 1, &utxos).is_err());
+        let mut vault = Vault::deposit(&normal, &recovery, 8, 10, 1, &utxos).unwrap();
+        assert_eq!(vault.deposit.vout[1].value, SUBSIDY - 9);
+        assert!(vault.withdraw(&normal, &normal.get_address(), 1).is_err());
+
+        // 正常密钥单独不能花费保险库输出，触发交易需要预先签好的恢复签名
+        assert!(vault.trigger(&recovery).is_err());
+        let trigger = vault.trigger(&normal).unwrap();
+        assert_eq!(trigger.vout[0].value, 7);
+        assert!(trigger.verify(vault.deposit_prev_txs()).unwrap());
+        let mut forged = trigger.clone();
+        forged.vout[0].pub_key_hash = pub_key_hash_of(&normal);
+        assert!(!forged.verify(vault.deposit_prev_txs()).unwrap());
+
+        let cancel = vault.cancel(&recovery, 1).unwrap();
+        assert!(cancel.verify(vault.deposit_prev_txs()).unwrap());
+        assert_eq!(cancel.vout[0].pub_key_hash, pub_key_hash_of(&recovery));
+
+        vault.state = VaultState::Triggered;
+        let trigger_txs = HashMap::from([(trigger.id.clone(), trigger)]);
+        let withdraw = vault.withdraw(&normal, &normal.get_address(), 1).unwrap();
+        assert_eq!(withdraw.vout[0].value, 6);
+        assert_eq!(withdraw.relative_lock_time, 10);
+        assert!(withdraw.verify(trigger_txs.clone()).unwrap());
+
+        // 相对锁定时间短于延迟时程序不通过
+        let mut early = withdraw;
+        early.relative_lock_time = 9;
+        early.vin[0].signature = Vec::new();
+        let signature = early
+            .sign_input(0, &normal.secret_key, &trigger_txs)
+            .unwrap();
+        early.vin[0].signature =
+            vm::assemble(&format!("0x{} OP_0", encode_hex(&signature))).unwrap();
+        assert!(!early.verify(trigger_txs.clone()).unwrap());
+
+        let cancel = vault.cancel(&recovery, 1).unwrap();
+        assert!(cancel.verify(trigger_txs).unwrap());
+        assert!(vault.cancel(&normal, 1).is_err());
+    }
+
+    fn pub_key_hash_of(wallet: &Wallet) -> Vec<u8> {
+        let mut pub_key_hash = wallet.public_key.clone();
+        hash_pub_key(&mut pub_key_hash);
+
+        pub_key_hash
+    }
+}
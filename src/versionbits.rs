@@ -0,0 +1,157 @@
+use super::Result;
+use crate::{
+    blockchain::Blockchain,
+    network::{self, Deployment},
+};
+use serde::{Deserialize, Serialize};
+
+// BIP9 版本位: 高 3 位固定为 001，低 29 位每一位对应一个软分叉部署
+pub const VERSIONBITS_TOP_BITS: i32 = 0x2000_0000;
+const VERSIONBITS_TOP_MASK: i32 = 0xE000_0000_u32 as i32;
+
+// 软分叉部署状态，只在每个窗口结束时变化
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum ThresholdState {
+    Defined,
+    Started,
+    LockedIn,
+    Active,
+    Failed,
+}
+
+/**
+ * @desc 区块版本是否对指定位发出了信号
+ */
+pub fn signals(version: i32, bit: u8) -> bool {
+    version & VERSIONBITS_TOP_MASK == VERSIONBITS_TOP_BITS && version & (1 << bit) != 0
+}
+
+/**
+ * @desc 计算 prev_hash 之后的下一个区块所处的部署状态
+ */
+pub fn get_state(
+    bc: &Blockchain,
+    deployment: &Deployment,
+    prev_hash: &str,
+) -> Result<ThresholdState> {
+    let params = network::params();
+    let window = params.miner_confirmation_window;
+
+    // 从后往前统计每个完整窗口的信号数，窗口以最后一个区块的 hash 标识
+    let mut windows: Vec<(String, i32)> = Vec::new();
+    let mut hash = prev_hash.to_string();
+    while !hash.is_empty() {
+        let header = bc.get_header(&hash)?;
+        if header.height % window == window - 1 {
+            windows.push((header.hash.clone(), 0));
+        }
+        if let Some((_, count)) = windows.last_mut() {
+            if signals(header.version, deployment.bit) {
+                *count += 1;
+            }
+        }
+        hash = header.prev_block_hash;
+    }
+
+    let mut state = ThresholdState::Defined;
+    for (boundary, count) in windows.into_iter().rev() {
+        let median_time = bc.get_median_time_past(&boundary)?;
+        state = next_state(
+            state,
+            deployment,
+            median_time,
+            count,
+            params.rule_change_activation_threshold,
+        );
+    }
+
+    Ok(state)
+}
+
+/**
+ * @desc 窗口结束时的状态转换
+ */
+fn next_state(
+    state: ThresholdState,
+    deployment: &Deployment,
+    median_time: u128,
+    count: i32,
+    threshold: i32,
+) -> ThresholdState {
+    match state {
+        ThresholdState::Defined if median_time >= deployment.timeout => ThresholdState::Failed,
+        ThresholdState::Defined if median_time >= deployment.start_time => ThresholdState::Started,
+        ThresholdState::Started if count >= threshold => ThresholdState::LockedIn,
+        ThresholdState::Started if median_time >= deployment.timeout => ThresholdState::Failed,
+        ThresholdState::LockedIn => ThresholdState::Active,
+        state => state,
+    }
+}
+
+/**
+ * @desc 当前网络所有部署在下一个区块的状态
+ */
+pub fn get_deployment_states(
+    bc: &Blockchain,
+    prev_hash: &str,
+) -> Result<Vec<(&'static Deployment, ThresholdState)>> {
+    let mut states = Vec::new();
+    for deployment in network::params().deployments {
+        states.push((deployment, get_state(bc, deployment, prev_hash)?));
+    }
+
+    Ok(states)
+}
+
+/**
+ * @desc 矿工使用的区块版本: 对已开始或已锁定的部署发出信号
+ */
+pub fn compute_block_version(bc: &Blockchain, prev_hash: &str) -> Result<i32> {
+    let mut version = VERSIONBITS_TOP_BITS;
+    for (deployment, state) in get_deployment_states(bc, prev_hash)? {
+        if state == ThresholdState::Started || state == ThresholdState::LockedIn {
+            version |= 1 << deployment.bit;
+        }
+    }
+
+    Ok(version)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_version_bits() {
+        assert!(signals(VERSIONBITS_TOP_BITS | 1 << 28, 28));
+        assert!(!signals(VERSIONBITS_TOP_BITS, 28));
+        assert!(!signals(1 << 28, 28));
+
+        let deployment = Deployment {
+            name: "test",
+            bit: 1,
+            start_time: 100,
+            timeout: 200,
+        };
+        let state = next_state(ThresholdState::Defined, &deployment, 50, 10, 8);
+        assert_eq!(state, ThresholdState::Defined);
+        let state = next_state(state, &deployment, 100, 10, 8);
+        assert_eq!(state, ThresholdState::Started);
+        assert_eq!(
+            next_state(state, &deployment, 150, 7, 8),
+            ThresholdState::Started
+        );
+        assert_eq!(
+            next_state(state, &deployment, 200, 7, 8),
+            ThresholdState::Failed
+        );
+        let state = next_state(state, &deployment, 150, 8, 8);
+        assert_eq!(state, ThresholdState::LockedIn);
+        let state = next_state(state, &deployment, 300, 0, 8);
+        assert_eq!(state, ThresholdState::Active);
+        assert_eq!(
+            next_state(state, &deployment, 400, 0, 8),
+            ThresholdState::Active
+        );
+    }
+}
@@ -0,0 +1,548 @@
+// 输出谓词的栈式虚拟机: 版本 6 起输出可以由一段小程序锁定，
+// 输出的公钥哈希是程序的哈希，花费时输入的公钥字段公开程序，签名字段是见证数据(只含压栈操作)，
+// 先执行见证数据再执行程序，结束时栈顶为真则输入有效；
+// 执行是确定性的，每个操作消耗 gas，程序长度、栈深度、元素长度和 gas 都有共识上限
+
+use super::Result;
+use crate::{
+    transaction::{decode_hex, encode_hex},
+    wallets::hash_pub_key,
+};
+use crypto::{digest::Digest, ed25519, sha2::Sha256};
+use failure::format_err;
+
+// 从版本 6 起输入可以花费程序锁定的输出
+pub const TX_VERSION_PROGRAM: i32 = 6;
+// 程序锁定脚本以标记字节开头，和单个公钥(32 字节)及多签脚本(首字节为门限 1..=16)区分
+pub const PROGRAM_MARKER: u8 = 0xff;
+pub const MAX_PROGRAM_LEN: usize = 1000;
+pub const MAX_STACK_SIZE: usize = 100;
+pub const MAX_ELEMENT_LEN: usize = 520;
+pub const GAS_LIMIT: u64 = 1000;
+// 算术操作数最多 4 字节，结果不会溢出 i64
+const MAX_NUM_LEN: usize = 4;
+
+pub const OP_0: u8 = 0x00;
+pub const OP_PUSHDATA1: u8 = 0x4c;
+pub const OP_PUSHDATA2: u8 = 0x4d;
+pub const OP_1NEGATE: u8 = 0x4f;
+pub const OP_1: u8 = 0x51;
+pub const OP_16: u8 = 0x60;
+pub const OP_IF: u8 = 0x63;
+pub const OP_NOTIF: u8 = 0x64;
+pub const OP_ELSE: u8 = 0x67;
+pub const OP_ENDIF: u8 = 0x68;
+pub const OP_VERIFY: u8 = 0x69;
+pub const OP_RETURN: u8 = 0x6a;
+pub const OP_DROP: u8 = 0x75;
+pub const OP_DUP: u8 = 0x76;
+pub const OP_OVER: u8 = 0x78;
+pub const OP_SWAP: u8 = 0x7c;
+pub const OP_SIZE: u8 = 0x82;
+pub const OP_EQUAL: u8 = 0x87;
+pub const OP_EQUALVERIFY: u8 = 0x88;
+pub const OP_NOT: u8 = 0x91;
+pub const OP_ADD: u8 = 0x93;
+pub const OP_SUB: u8 = 0x94;
+pub const OP_NUMEQUAL: u8 = 0x9c;
+pub const OP_LESSTHAN: u8 = 0x9f;
+pub const OP_GREATERTHAN: u8 = 0xa0;
+pub const OP_SHA256: u8 = 0xa8;
+pub const OP_HASH160: u8 = 0xa9;
+pub const OP_CHECKSEQUENCEVERIFY: u8 = 0xb2;
+pub const OP_CHECKSIG: u8 = 0xac;
+pub const OP_CHECKSIGVERIFY: u8 = 0xad;
+
+// 汇编使用的操作名称，压栈数据和 OP_1..OP_16 单独处理
+const OP_NAMES: &[(&str, u8)] = &[
+    ("OP_0", OP_0),
+    ("OP_1NEGATE", OP_1NEGATE),
+    ("OP_IF", OP_IF),
+    ("OP_NOTIF", OP_NOTIF),
+    ("OP_ELSE", OP_ELSE),
+    ("OP_ENDIF", OP_ENDIF),
+    ("OP_VERIFY", OP_VERIFY),
+    ("OP_RETURN", OP_RETURN),
+    ("OP_DROP", OP_DROP),
+    ("OP_DUP", OP_DUP),
+    ("OP_OVER", OP_OVER),
+    ("OP_SWAP", OP_SWAP),
+    ("OP_SIZE", OP_SIZE),
+    ("OP_EQUAL", OP_EQUAL),
+    ("OP_EQUALVERIFY", OP_EQUALVERIFY),
+    ("OP_NOT", OP_NOT),
+    ("OP_ADD", OP_ADD),
+    ("OP_SUB", OP_SUB),
+    ("OP_NUMEQUAL", OP_NUMEQUAL),
+    ("OP_LESSTHAN", OP_LESSTHAN),
+    ("OP_GREATERTHAN", OP_GREATERTHAN),
+    ("OP_SHA256", OP_SHA256),
+    ("OP_HASH160", OP_HASH160),
+    ("OP_CHECKSEQUENCEVERIFY", OP_CHECKSEQUENCEVERIFY),
+    ("OP_CHECKSIG", OP_CHECKSIG),
+    ("OP_CHECKSIGVERIFY", OP_CHECKSIGVERIFY),
+];
+
+/**
+ * @desc 操作消耗的 gas，哈希和签名验证按计算量计价
+ */
+fn gas_cost(op: u8) -> u64 {
+    match op {
+        OP_SHA256 | OP_HASH160 => 10,
+        OP_CHECKSIG | OP_CHECKSIGVERIFY => 100,
+        _ => 1,
+    }
+}
+
+/**
+ * @desc 输入的公钥字段是否是程序锁定脚本
+ */
+pub fn is_program(script: &[u8]) -> bool {
+    script.first() == Some(&PROGRAM_MARKER)
+}
+
+/**
+ * @desc 由程序代码生成锁定脚本
+ */
+pub fn lock_script(code: &[u8]) -> Vec<u8> {
+    [&[PROGRAM_MARKER], code].concat()
+}
+
+/**
+ * @desc 程序锁定的输出使用的公钥哈希
+ */
+pub fn program_hash(code: &[u8]) -> Vec<u8> {
+    let mut pub_key_hash = lock_script(code);
+    hash_pub_key(&mut pub_key_hash);
+
+    pub_key_hash
+}
+
+/**
+ * @desc 验证花费程序锁定输出的输入: script 是锁定脚本，witness 是见证数据，sighash 供签名验证使用，
+ *       relative_lock_time 是花费交易的相对锁定时间，供 OP_CHECKSEQUENCEVERIFY 使用
+ */
+pub fn execute(
+    script: &[u8],
+    witness: &[u8],
+    sighash: &str,
+    relative_lock_time: u32,
+) -> Result<()> {
+    if !is_program(script) || script.len() > MAX_PROGRAM_LEN || witness.len() > MAX_PROGRAM_LEN {
+        return Err(format_err!("Program is too long or not a program."));
+    }
+
+    let mut machine = Machine {
+        stack: Vec::new(),
+        gas: 0,
+        sighash,
+        relative_lock_time,
+    };
+    machine.run(witness, true)?;
+    machine.run(&script[1..], false)?;
+
+    match machine.stack.last() {
+        Some(top) if to_bool(top) => Ok(()),
+        _ => Err(format_err!("Program finished with a false result.")),
+    }
+}
+
+struct Machine<'a> {
+    stack: Vec<Vec<u8>>,
+    gas: u64,
+    sighash: &'a str,
+    relative_lock_time: u32,
+}
+
+impl Machine<'_> {
+    fn run(&mut self, code: &[u8], push_only: bool) -> Result<()> {
+        // 条件分支的执行状态，全部为真时才执行当前操作
+        let mut branches: Vec<bool> = Vec::new();
+        let mut pc = 0;
+
+        while pc < code.len() {
+            let op = code[pc];
+            pc += 1;
+            self.gas += gas_cost(op);
+            if self.gas > GAS_LIMIT {
+                return Err(format_err!("Program ran out of gas."));
+            }
+
+            let executing = branches.iter().all(|b| *b);
+            if let Some((data, next)) = read_push(code, pc - 1)? {
+                pc = next;
+                if executing {
+                    self.push(data.to_vec())?;
+                }
+                continue;
+            }
+            if push_only && op != OP_1NEGATE && !(OP_1..=OP_16).contains(&op) {
+                return Err(format_err!("Witness may only push data."));
+            }
+
+            match op {
+                OP_IF | OP_NOTIF => {
+                    let mut value = false;
+                    if executing {
+                        value = to_bool(&self.pop()?) == (op == OP_IF);
+                    }
+                    branches.push(value);
+                }
+                OP_ELSE => {
+                    let last = branches
+                        .last_mut()
+                        .ok_or_else(|| format_err!("OP_ELSE without OP_IF."))?;
+                    *last = !*last;
+                }
+                OP_ENDIF => {
+                    branches
+                        .pop()
+                        .ok_or_else(|| format_err!("OP_ENDIF without OP_IF."))?;
+                }
+                _ if !executing => {}
+                _ => self.step(op)?,
+            }
+        }
+
+        if !branches.is_empty() {
+            return Err(format_err!("OP_IF without OP_ENDIF."));
+        }
+
+        Ok(())
+    }
+
+    fn step(&mut self, op: u8) -> Result<()> {
+        match op {
+            OP_VERIFY => self.verify(),
+            OP_RETURN => Err(format_err!("OP_RETURN executed.")),
+            OP_DROP => self.pop().map(|_| ()),
+            OP_DUP => self.push(self.peek(0)?),
+            OP_OVER => self.push(self.peek(1)?),
+            OP_SWAP => {
+                let (b, a) = (self.pop()?, self.pop()?);
+                self.push(b)?;
+                self.push(a)
+            }
+            OP_SIZE => self.push(from_num(self.peek(0)?.len() as i64)),
+            OP_EQUAL | OP_EQUALVERIFY => {
+                let equal = self.pop()? == self.pop()?;
+                self.push(from_bool(equal))?;
+                if op == OP_EQUALVERIFY {
+                    return self.verify();
+                }
+                Ok(())
+            }
+            OP_NOT => {
+                let a = to_num(&self.pop()?)?;
+                self.push(from_bool(a == 0))
+            }
+            OP_ADD | OP_SUB | OP_NUMEQUAL | OP_LESSTHAN | OP_GREATERTHAN => {
+                let b = to_num(&self.pop()?)?;
+                let a = to_num(&self.pop()?)?;
+                self.push(match op {
+                    OP_ADD => from_num(a + b),
+                    OP_SUB => from_num(a - b),
+                    OP_NUMEQUAL => from_bool(a == b),
+                    OP_LESSTHAN => from_bool(a < b),
+                    _ => from_bool(a > b),
+                })
+            }
+            OP_SHA256 => {
+                let data = self.pop()?;
+                let mut hasher = Sha256::new();
+                hasher.input(&data);
+                let mut hash = vec![0; 32];
+                hasher.result(&mut hash);
+                self.push(hash)
+            }
+            OP_HASH160 => {
+                let mut data = self.pop()?;
+                hash_pub_key(&mut data);
+                self.push(data)
+            }
+            OP_CHECKSIG | OP_CHECKSIGVERIFY => {
+                let pub_key = self.pop()?;
+                let signature = self.pop()?;
+                let valid = pub_key.len() == 32
+                    && signature.len() == 64
+                    && ed25519::verify(self.sighash.as_bytes(), &pub_key, &signature);
+                self.push(from_bool(valid))?;
+                if op == OP_CHECKSIGVERIFY {
+                    return self.verify();
+                }
+                Ok(())
+            }
+            // 栈顶的区块数不超过交易的相对锁定时间，不弹出栈顶；
+            // 区块链保证输入至少被确认了相对锁定时间个区块，程序由此限制输出最早何时能被花费
+            OP_CHECKSEQUENCEVERIFY => {
+                let blocks = to_num(&self.peek(0)?)?;
+                if blocks < 0 || blocks > self.relative_lock_time as i64 {
+                    return Err(format_err!(
+                        "Relative lock time {} is less than {} blocks.",
+                        self.relative_lock_time,
+                        blocks
+                    ));
+                }
+                Ok(())
+            }
+            OP_1..=OP_16 => self.push(from_num((op - OP_1 + 1) as i64)),
+            OP_1NEGATE => self.push(from_num(-1)),
+            _ => Err(format_err!("Unknown opcode 0x{:02x}.", op)),
+        }
+    }
+
+    fn push(&mut self, data: Vec<u8>) -> Result<()> {
+        if data.len() > MAX_ELEMENT_LEN || self.stack.len() >= MAX_STACK_SIZE {
+            return Err(format_err!("Program exceeded the stack limits."));
+        }
+        self.stack.push(data);
+
+        Ok(())
+    }
+
+    fn pop(&mut self) -> Result<Vec<u8>> {
+        self.stack
+            .pop()
+            .ok_or_else(|| format_err!("Program popped an empty stack."))
+    }
+
+    fn peek(&self, depth: usize) -> Result<Vec<u8>> {
+        self.stack
+            .len()
+            .checked_sub(depth + 1)
+            .map(|index| self.stack[index].clone())
+            .ok_or_else(|| format_err!("Program read past the stack."))
+    }
+
+    fn verify(&mut self) -> Result<()> {
+        if !to_bool(&self.pop()?) {
+            return Err(format_err!("Program failed a verify."));
+        }
+
+        Ok(())
+    }
+}
+
+/**
+ * @desc 读取 pc 处的压栈操作，返回压入的数据和下一个操作的位置，不是压栈操作时返回 None
+ */
+fn read_push(code: &[u8], pc: usize) -> Result<Option<(&[u8], usize)>> {
+    let op = code[pc];
+    let (len, start) = match op {
+        OP_0 => (0, pc + 1),
+        0x01..=0x4b => (op as usize, pc + 1),
+        OP_PUSHDATA1 => match code.get(pc + 1) {
+            Some(len) => (*len as usize, pc + 2),
+            None => return Err(format_err!("Truncated push.")),
+        },
+        OP_PUSHDATA2 => match code.get(pc + 1..pc + 3) {
+            Some(len) => (u16::from_le_bytes([len[0], len[1]]) as usize, pc + 3),
+            None => return Err(format_err!("Truncated push.")),
+        },
+        _ => return Ok(None),
+    };
+
+    code.get(start..start + len)
+        .map(|data| Some((data, start + len)))
+        .ok_or_else(|| format_err!("Truncated push."))
+}
+
+// 数字是小端的符号-数值编码，最高字节的最高位是符号位，0 是空数组
+fn to_num(data: &[u8]) -> Result<i64> {
+    if data.len() > MAX_NUM_LEN {
+        return Err(format_err!("Number is longer than {} bytes.", MAX_NUM_LEN));
+    }
+    let mut value: i64 = 0;
+    for (i, b) in data.iter().enumerate() {
+        value |= (*b as i64) << (8 * i);
+    }
+    match data.last() {
+        Some(last) if last & 0x80 != 0 => Ok(-(value & !(0x80_i64 << (8 * (data.len() - 1))))),
+        _ => Ok(value),
+    }
+}
+
+fn from_num(value: i64) -> Vec<u8> {
+    let mut data = Vec::new();
+    let mut abs = value.unsigned_abs();
+    while abs > 0 {
+        data.push((abs & 0xff) as u8);
+        abs >>= 8;
+    }
+    match data.last_mut() {
+        Some(last) if *last & 0x80 != 0 => data.push(if value < 0 { 0x80 } else { 0 }),
+        Some(last) if value < 0 => *last |= 0x80,
+        _ => {}
+    }
+
+    data
+}
+
+// 全零和负零为假
+fn to_bool(data: &[u8]) -> bool {
+    match data.split_last() {
+        Some((last, rest)) => rest.iter().any(|b| *b != 0) || (*last & 0x7f) != 0,
+        None => false,
+    }
+}
+
+fn from_bool(value: bool) -> Vec<u8> {
+    from_num(value as i64)
+}
+
+/**
+ * @desc 汇编: 操作名称(OP_DUP 等)、十进制整数或 0x 开头的十六进制数据，以空白分隔
+ */
+pub fn assemble(text: &str) -> Result<Vec<u8>> {
+    let mut code = Vec::new();
+    for token in text.split_whitespace() {
+        if let Some(&(_, op)) = OP_NAMES.iter().find(|(name, _)| *name == token) {
+            code.push(op);
+        } else if let Some(n) = token
+            .strip_prefix("OP_")
+            .and_then(|n| n.parse::<u8>().ok())
+            .filter(|n| (1..=16).contains(n))
+        {
+            code.push(OP_1 + n - 1);
+        } else if let Some(hex) = token.strip_prefix("0x") {
+            let data = decode_hex(hex).ok_or_else(|| format_err!("Invalid data: {}", token))?;
+            push_data(&mut code, &data)?;
+        } else if let Ok(n) = token.parse::<i32>() {
+            push_data(&mut code, &from_num(n as i64))?;
+        } else {
+            return Err(format_err!("Unknown token: {}", token));
+        }
+    }
+
+    Ok(code)
+}
+
+fn push_data(code: &mut Vec<u8>, data: &[u8]) -> Result<()> {
+    match data.len() {
+        0 => code.push(OP_0),
+        len @ 1..=0x4b => code.push(len as u8),
+        len @ 0x4c..=0xff => code.extend_from_slice(&[OP_PUSHDATA1, len as u8]),
+        len if len <= MAX_ELEMENT_LEN => {
+            code.push(OP_PUSHDATA2);
+            code.extend_from_slice(&(len as u16).to_le_bytes());
+        }
+        len => return Err(format_err!("Data is too long: {} bytes.", len)),
+    }
+    code.extend_from_slice(data);
+
+    Ok(())
+}
+
+/**
+ * @desc 反汇编，assemble 的逆操作，数据一律输出为十六进制
+ */
+pub fn disassemble(code: &[u8]) -> Result<String> {
+    let mut tokens = Vec::new();
+    let mut pc = 0;
+    while pc < code.len() {
+        if let Some((data, next)) = read_push(code, pc)? {
+            tokens.push(if data.is_empty() {
+                String::from("OP_0")
+            } else {
+                format!("0x{}", encode_hex(data))
+            });
+            pc = next;
+            continue;
+        }
+
+        let op = code[pc];
+        tokens.push(match OP_NAMES.iter().find(|(_, code)| *code == op) {
+            Some((name, _)) => name.to_string(),
+            None if (OP_1..=OP_16).contains(&op) => format!("OP_{}", op - OP_1 + 1),
+            None => format!("OP_UNKNOWN_{:02x}", op),
+        });
+        pc += 1;
+    }
+
+    Ok(tokens.join(" "))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn run(program: &str, witness: &str) -> Result<()> {
+        run_locked(program, witness, 0)
+    }
+
+    fn run_locked(program: &str, witness: &str, relative_lock_time: u32) -> Result<()> {
+        execute(
+            &lock_script(&assemble(program).unwrap()),
+            &assemble(witness).unwrap(),
+            "sighash",
+            relative_lock_time,
+        )
+    }
+
+    #[test]
+    fn test_numbers() {
+        for value in [0, 1, -1, 127, 128, -128, 255, 256, -32768, i32::MAX as i64] {
+            assert_eq!(to_num(&from_num(value)).unwrap(), value, "{}", value);
+        }
+        assert_eq!(from_num(128), vec![0x80, 0]);
+        assert_eq!(from_num(-1), vec![0x81]);
+        assert!(!to_bool(&[0, 0x80]));
+        assert!(to_bool(&[0, 1]));
+        assert!(to_num(&[1, 2, 3, 4, 5]).is_err());
+    }
+
+    #[test]
+    fn test_programs() {
+        assert!(run("OP_ADD 5 OP_NUMEQUAL", "2 3").is_ok());
+        assert!(run("OP_ADD 5 OP_NUMEQUAL", "2 2").is_err());
+        assert!(run("OP_IF 1 OP_ELSE 0 OP_ENDIF", "1").is_ok());
+        assert!(run("OP_IF 1 OP_ELSE 0 OP_ENDIF", "0").is_err());
+        assert!(run("OP_NOTIF OP_RETURN OP_ENDIF 1", "1").is_ok());
+        assert!(run("OP_IF 1", "1").is_err());
+        assert!(run("OP_DROP", "").is_err());
+
+        // 哈希锁: 见证数据是原像
+        let mut hasher = Sha256::new();
+        hasher.input(b"secret");
+        let program = format!("OP_SHA256 0x{} OP_EQUAL", hasher.result_str());
+        assert!(run(&program, &format!("0x{}", encode_hex(b"secret"))).is_ok());
+        assert!(run(&program, &format!("0x{}", encode_hex(b"guess"))).is_err());
+
+        // 见证数据只能压栈
+        assert!(run("1", "OP_DUP").is_err());
+    }
+
+    #[test]
+    fn test_check_sequence_verify() {
+        let program = "10 OP_CHECKSEQUENCEVERIFY";
+        assert!(run_locked(program, "", 10).is_ok());
+        assert!(run_locked(program, "", 11).is_ok());
+        assert!(run_locked(program, "", 9).is_err());
+        assert!(run_locked("OP_1NEGATE OP_CHECKSEQUENCEVERIFY", "", 10).is_err());
+        assert!(run("OP_CHECKSEQUENCEVERIFY", "").is_err());
+    }
+
+    #[test]
+    fn test_limits() {
+        // 死循环不存在，但长程序会耗尽 gas
+        let hashes = "OP_SHA256 ".repeat(GAS_LIMIT as usize / 10 + 1);
+        assert!(run(&format!("1 {}", hashes), "").is_err());
+
+        let dups = "OP_DUP ".repeat(MAX_STACK_SIZE);
+        assert!(run(&dups, "1").is_err());
+
+        let big = format!("0x{}", "00".repeat(MAX_ELEMENT_LEN + 1));
+        assert!(assemble(&big).is_err());
+        assert!(execute(&[PROGRAM_MARKER; MAX_PROGRAM_LEN + 1], &[], "", 0).is_err());
+    }
+
+    #[test]
+    fn test_assemble_roundtrip() {
+        let text = "OP_DUP OP_HASH160 0x0102 OP_EQUALVERIFY OP_16 OP_0 OP_CHECKSIG";
+        let code = assemble(text).unwrap();
+        assert_eq!(disassemble(&code).unwrap(), text);
+        assert_eq!(assemble("-1").unwrap(), vec![1, 0x81]);
+        assert!(assemble("OP_FOO").is_err());
+        assert!(disassemble(&[OP_PUSHDATA1]).is_err());
+    }
+}
@@ -1,24 +1,81 @@
-use std::collections::HashMap;
+use std::{
+    collections::{BTreeMap, HashMap},
+    io, thread,
+    time::{Duration, Instant},
+};
 
 use super::Result;
+use crate::{
+    channel::{Channel, Role},
+    descriptor::Descriptor,
+    network,
+    psbt::Psbt,
+    schema,
+    transaction::Transaction,
+    vault::Vault,
+};
 use bincode::{deserialize, serialize};
 use bitcoincash_addr::{Address, HashType, Scheme};
 use crypto::{digest::Digest, ed25519, ripemd160::Ripemd160, sha2::Sha256};
+use failure::format_err;
 use log::info;
 use rand_core::{OsRng, RngCore};
 use serde::{Deserialize, Serialize};
 
+// 钱包创建的未确认交易，和钱包密钥分开保存，节点定期读取时不会和钱包操作争用数据库
+const WALLET_TXS_DB: &str = "wallet_txs";
+// 交易备注保存在单独的树中，交易确认后仍然保留，备注不会上链
+const TX_NOTES_TREE: &str = "notes";
+// 支付通道的状态: "通道 id:角色" -> 通道，通道双方可以是同一节点上的两个钱包
+const CHANNELS_TREE: &str = "channels";
+// 保险库的状态: 保险库 id -> 保险库，正常密钥和恢复密钥都在本节点的钱包中
+const VAULTS_TREE: &str = "vaults";
+// 等待签名的部分签名交易: txid -> 部分签名交易，节点收到其他签名方发来的签名后合并保存
+const PSBTS_TREE: &str = "psbts";
+const WALLETS_DB: &str = "wallets";
+// 命令行和节点进程都会短暂打开钱包数据库，另一个进程持有锁时等待它释放，最多等待 DB_LOCK_TIMEOUT
+const DB_LOCK_TIMEOUT: Duration = Duration::from_secs(1);
+const DB_LOCK_RETRY_INTERVAL: Duration = Duration::from_millis(20);
+// 只读钱包导入的描述符: 地址 -> 描述符文本，没有私钥，只能查询余额
+const WATCH_ONLY_TREE: &str = "watch_only";
+// 命名钱包: 钱包名 -> 种子和下一个派生序号，派生出的密钥同时保存在钱包密钥中
+const NAMED_WALLETS_TREE: &str = "named";
+// ed25519 的种子固定为 32 字节，其他长度生成的密钥对签名无法通过验证
+pub const SEED_LEN: usize = 32;
+// 钱包密钥和钱包交易的格式版本
+const WALLETS_SCHEMA: &str = "wallets";
+const WALLETS_VERSION: u32 = 1;
+const WALLET_TXS_SCHEMA: &str = "wallet_txs";
+const WALLET_TXS_VERSION: u32 = 1;
+
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
 pub struct Wallet {
     pub secret_key: Vec<u8>,
     pub public_key: Vec<u8>,
 }
 
+impl Default for Wallet {
+    fn default() -> Self {
+        Self::new(&mut OsRng)
+    }
+}
+
 impl Wallet {
-    pub fn new() -> Self {
-        let mut key: [u8; 64] = [0; 64];
-        OsRng.fill_bytes(&mut key);
-        let (secret_key, public_key) = ed25519::keypair(&key);
+    /**
+     * @desc 用 rng 生成的种子创建钱包，传入固定种子的 rng 时生成的钱包可以复现
+     */
+    pub fn new(rng: &mut impl RngCore) -> Self {
+        let mut seed = [0; SEED_LEN];
+        rng.fill_bytes(&mut seed);
+
+        Wallet::from_seed(&seed)
+    }
+
+    /**
+     * @desc 由种子生成钱包，相同的种子得到相同的密钥(用于测试向量)
+     */
+    pub fn from_seed(seed: &[u8; SEED_LEN]) -> Self {
+        let (secret_key, public_key) = ed25519::keypair(seed);
         let secret_key = secret_key.to_vec();
         let public_key = public_key.to_vec();
 
@@ -32,42 +89,90 @@ impl Wallet {
         let mut pub_hash: Vec<u8> = self.public_key.clone();
         hash_pub_key(&mut pub_hash);
 
-        let address = Address {
-            body: pub_hash,
-            scheme: Scheme::Base58,
-            hash_type: HashType::Script,
-            ..Default::default()
-        };
+        address_from_pub_key_hash(&pub_hash)
+    }
+
+    /**
+     * @desc 钱包密钥对应的描述符
+     */
+    pub fn descriptor(&self) -> Descriptor {
+        Descriptor::Pkh(self.public_key.clone())
+    }
+}
+
+// 命名钱包: 由一个种子按序号派生任意多个密钥，每次收款都可以使用新地址，备份种子即可恢复全部地址
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct NamedWallet {
+    pub seed: Vec<u8>,
+    // 下一个未使用的派生序号
+    pub next_index: u32,
+}
+
+impl NamedWallet {
+    pub fn new(rng: &mut impl RngCore) -> Self {
+        let mut seed = vec![0; 64];
+        rng.fill_bytes(&mut seed);
+
+        NamedWallet {
+            seed,
+            next_index: 0,
+        }
+    }
+
+    /**
+     * @desc 派生第 index 个密钥: 子种子是 sha256(种子 || 序号)，相同的种子和序号得到相同的密钥
+     */
+    pub fn derive(&self, index: u32) -> Wallet {
+        let mut hasher = Sha256::new();
+        hasher.input(&self.seed);
+        hasher.input(&index.to_be_bytes());
+        let mut child_seed = [0; 32];
+        hasher.result(&mut child_seed);
 
-        address.encode().unwrap()
+        Wallet::from_seed(&child_seed)
     }
 }
 
 pub struct Wallets {
     wallets: HashMap<String, Wallet>,
+    watch_only: BTreeMap<String, Descriptor>,
+    named: BTreeMap<String, NamedWallet>,
 }
 
 impl Wallets {
     pub fn new() -> Result<Wallets> {
         let mut wlts = Wallets {
             wallets: HashMap::<String, Wallet>::new(),
+            watch_only: BTreeMap::new(),
+            named: BTreeMap::new(),
         };
 
-        let db = sled::open("data/wallets")?;
+        let db = open_wallets_db()?;
         for item in db.into_iter() {
             let i = item?;
             let address = String::from_utf8(i.0.to_vec())?;
             let wallet = deserialize(&i.1.to_vec())?;
             wlts.wallets.insert(address, wallet);
         }
+        for item in db.open_tree(WATCH_ONLY_TREE)?.iter() {
+            let (address, descriptor) = item?;
+            let address = String::from_utf8(address.to_vec())?;
+            let descriptor = Descriptor::parse(&String::from_utf8(descriptor.to_vec())?)?;
+            wlts.watch_only.insert(address, descriptor);
+        }
+        for item in db.open_tree(NAMED_WALLETS_TREE)?.iter() {
+            let (name, named) = item?;
+            wlts.named
+                .insert(String::from_utf8(name.to_vec())?, deserialize(&named)?);
+        }
 
         drop(db);
 
         Ok(wlts)
     }
 
-    pub fn create_wallet(&mut self) -> String {
-        let wallet = Wallet::new();
+    pub fn create_wallet(&mut self, rng: &mut impl RngCore) -> String {
+        let wallet = Wallet::new(rng);
         let address = wallet.get_address();
         self.wallets.insert(address.clone(), wallet);
         info!("create wallet: {}", address);
@@ -75,10 +180,84 @@ impl Wallets {
         address
     }
 
+    /**
+     * @desc 导入已有的密钥，返回它的地址
+     */
+    pub fn add_wallet(&mut self, wallet: Wallet) -> String {
+        let address = wallet.get_address();
+        self.wallets.insert(address.clone(), wallet);
+        info!("import wallet: {}", address);
+
+        address
+    }
+
     pub fn get_wallet(&self, address: &str) -> Option<&Wallet> {
         self.wallets.get(address)
     }
 
+    /**
+     * @desc 创建命名钱包并派生第一个地址，钱包名已存在时返回错误
+     */
+    pub fn create_named_wallet(&mut self, name: &str, rng: &mut impl RngCore) -> Result<String> {
+        if self.named.contains_key(name) {
+            return Err(format_err!("Wallet {} already exists.", name));
+        }
+        self.named.insert(name.to_string(), NamedWallet::new(rng));
+        info!("create named wallet: {}", name);
+
+        self.new_address(name)
+    }
+
+    pub fn get_named_wallet(&self, name: &str) -> Option<&NamedWallet> {
+        self.named.get(name)
+    }
+
+    /**
+     * @desc 从命名钱包派生下一个新地址，派生的密钥加入钱包，调用方负责保存
+     */
+    pub fn new_address(&mut self, name: &str) -> Result<String> {
+        let named = self
+            .named
+            .get_mut(name)
+            .ok_or_else(|| format_err!("Wallet {} does not exist.", name))?;
+        let wallet = named.derive(named.next_index);
+        named.next_index += 1;
+
+        let address = wallet.get_address();
+        self.wallets.insert(address.clone(), wallet);
+        info!("derive address of wallet {}: {}", name, address);
+
+        Ok(address)
+    }
+
+    /**
+     * @desc 导入只读描述符，返回描述符的地址，已有私钥的地址不需要导入
+     */
+    pub fn import_descriptor(&mut self, descriptor: Descriptor) -> String {
+        let address = descriptor.address();
+        if !self.wallets.contains_key(&address) {
+            info!("import descriptor: {} ({})", descriptor, address);
+            self.watch_only.insert(address.clone(), descriptor);
+        }
+
+        address
+    }
+
+    /**
+     * @desc 地址的描述符，包括有私钥的地址和只读地址
+     */
+    pub fn get_descriptor(&self, address: &str) -> Option<Descriptor> {
+        match self.wallets.get(address) {
+            Some(wallet) => Some(wallet.descriptor()),
+            None => self.watch_only.get(address).cloned(),
+        }
+    }
+
+    // 只读地址，按地址排序
+    pub fn get_watch_only_addresses(&self) -> Vec<String> {
+        self.watch_only.keys().cloned().collect()
+    }
+
     // 获取所有钱包地址
     pub fn get_all_addresses(&self) -> Vec<String> {
         let mut addresses = Vec::<String>::new();
@@ -91,12 +270,20 @@ impl Wallets {
     }
 
     pub fn save_all(&self) -> Result<()> {
-        let db = sled::open("data/wallets")?;
+        let db = open_wallets_db()?;
 
         for (address, wallet) in &self.wallets {
             let data = serialize(&wallet)?;
             db.insert(address, data)?;
         }
+        let watch_only = db.open_tree(WATCH_ONLY_TREE)?;
+        for (address, descriptor) in &self.watch_only {
+            watch_only.insert(address, descriptor.to_string().as_bytes())?;
+        }
+        let named = db.open_tree(NAMED_WALLETS_TREE)?;
+        for (name, wallet) in &self.named {
+            named.insert(name, serialize(wallet)?)?;
+        }
 
         db.flush()?;
         drop(db);
@@ -105,9 +292,506 @@ impl Wallets {
     }
 }
 
+// 钱包审计发现的问题
+#[derive(Debug, Clone, PartialEq)]
+pub enum AuditIssue {
+    // 记录无法解析: 树名、键和错误信息
+    Corrupted {
+        tree: String,
+        key: String,
+        error: String,
+    },
+    // 私钥长度错误，或者私钥中的公钥与保存的公钥不一致
+    KeyMismatch {
+        address: String,
+    },
+    // 保存的地址不是公钥或描述符对应的地址
+    AddressMismatch {
+        address: String,
+        expected: String,
+    },
+    // 用私钥签名后无法用公钥验证
+    CannotSign {
+        address: String,
+    },
+    // 命名钱包已派生的地址不在钱包中
+    MissingDerivedKey {
+        name: String,
+        index: u32,
+        address: String,
+    },
+    // 钱包中保存的密钥和由命名钱包种子重新派生的不同
+    DerivedKeyMismatch {
+        name: String,
+        index: u32,
+        address: String,
+    },
+    // 只读描述符的地址已经有私钥，描述符不会再被使用
+    OrphanedWatchOnly {
+        address: String,
+    },
+}
+
+// 钱包审计结果
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct WalletAudit {
+    // 检查过的密钥数
+    pub keys: usize,
+    // 能由命名钱包种子重新派生的密钥数，其余是随机生成或导入的密钥
+    pub derived: usize,
+    pub named_wallets: usize,
+    pub watch_only: usize,
+    pub issues: Vec<AuditIssue>,
+}
+
+/**
+ * @desc 审计钱包数据库: 直接读取原始记录，单条记录损坏不影响检查其他记录
+ */
+pub fn audit_wallets() -> Result<WalletAudit> {
+    let db = open_wallets_db()?;
+    let read = |tree: &sled::Tree| -> Result<Vec<(Vec<u8>, Vec<u8>)>> {
+        tree.iter()
+            .map(|item| Ok(item.map(|(key, value)| (key.to_vec(), value.to_vec()))?))
+            .collect()
+    };
+    let keys = read(&db)?;
+    let watch_only = read(&db.open_tree(WATCH_ONLY_TREE)?)?;
+    let named = read(&db.open_tree(NAMED_WALLETS_TREE)?)?;
+    drop(db);
+
+    Ok(audit_entries(&keys, &watch_only, &named))
+}
+
+/**
+ * @desc 审计钱包记录: 由公钥和描述符重新推出地址、由命名钱包的种子重新派生已派生的密钥，
+ *       检查保存的公钥和地址一致、每个私钥都能签名，并找出损坏或不再使用的记录
+ */
+pub fn audit_entries(
+    keys: &[(Vec<u8>, Vec<u8>)],
+    watch_only: &[(Vec<u8>, Vec<u8>)],
+    named: &[(Vec<u8>, Vec<u8>)],
+) -> WalletAudit {
+    let mut audit = WalletAudit::default();
+    let corrupted = |tree: &str, key: &[u8], error: String| AuditIssue::Corrupted {
+        tree: tree.to_string(),
+        key: String::from_utf8_lossy(key).to_string(),
+        error,
+    };
+
+    let mut wallets = HashMap::new();
+    for (key, value) in keys {
+        let parsed = String::from_utf8(key.clone())
+            .map_err(failure::Error::from)
+            .and_then(|address| Ok((address, deserialize::<Wallet>(value)?)));
+        let (address, wallet) = match parsed {
+            Ok(entry) => entry,
+            Err(e) => {
+                // 密钥保存在默认树中
+                audit.issues.push(corrupted("keys", key, e.to_string()));
+                continue;
+            }
+        };
+        audit.keys += 1;
+
+        if wallet.secret_key.len() != 64 || wallet.secret_key[32..] != wallet.public_key[..] {
+            audit.issues.push(AuditIssue::KeyMismatch { address });
+            continue;
+        }
+        let expected = wallet.get_address();
+        if expected != address {
+            audit
+                .issues
+                .push(AuditIssue::AddressMismatch { address, expected });
+            continue;
+        }
+        let message = address.as_bytes();
+        let signature = ed25519::signature(message, &wallet.secret_key);
+        if !ed25519::verify(message, &wallet.public_key, &signature) {
+            audit.issues.push(AuditIssue::CannotSign { address });
+            continue;
+        }
+        wallets.insert(address, wallet);
+    }
+
+    for (key, value) in watch_only {
+        let parsed = String::from_utf8(key.clone())
+            .map_err(failure::Error::from)
+            .and_then(|address| {
+                let text = String::from_utf8(value.clone())?;
+                Ok((address, Descriptor::parse(&text)?))
+            });
+        let (address, descriptor) = match parsed {
+            Ok(entry) => entry,
+            Err(e) => {
+                audit
+                    .issues
+                    .push(corrupted(WATCH_ONLY_TREE, key, e.to_string()));
+                continue;
+            }
+        };
+        audit.watch_only += 1;
+
+        let expected = descriptor.address();
+        if expected != address {
+            audit
+                .issues
+                .push(AuditIssue::AddressMismatch { address, expected });
+        } else if keys.iter().any(|(key, _)| key == address.as_bytes()) {
+            audit.issues.push(AuditIssue::OrphanedWatchOnly { address });
+        }
+    }
+
+    for (key, value) in named {
+        let parsed = String::from_utf8(key.clone())
+            .map_err(failure::Error::from)
+            .and_then(|name| Ok((name, deserialize::<NamedWallet>(value)?)));
+        let (name, named) = match parsed {
+            Ok(entry) => entry,
+            Err(e) => {
+                audit
+                    .issues
+                    .push(corrupted(NAMED_WALLETS_TREE, key, e.to_string()));
+                continue;
+            }
+        };
+        audit.named_wallets += 1;
+
+        for index in 0..named.next_index {
+            let derived = named.derive(index);
+            let address = derived.get_address();
+            match wallets.get(&address) {
+                Some(wallet) if *wallet == derived => audit.derived += 1,
+                Some(_) => audit.issues.push(AuditIssue::DerivedKeyMismatch {
+                    name: name.clone(),
+                    index,
+                    address,
+                }),
+                // 记录存在但没通过前面的检查时已经报告过
+                None if keys.iter().any(|(key, _)| key == address.as_bytes()) => {}
+                None => audit.issues.push(AuditIssue::MissingDerivedKey {
+                    name: name.clone(),
+                    index,
+                    address,
+                }),
+            }
+        }
+    }
+
+    audit
+}
+
+fn open_shared_db(path: &str) -> Result<sled::Db> {
+    let deadline = Instant::now() + DB_LOCK_TIMEOUT;
+    loop {
+        match sled::open(path) {
+            Err(sled::Error::Io(e)) if is_lock_contention(&e) && Instant::now() < deadline => {
+                thread::sleep(DB_LOCK_RETRY_INTERVAL);
+            }
+            result => return Ok(result?),
+        }
+    }
+}
+
+/**
+ * @desc 打开数据库失败是否因为文件锁被其他进程持有
+ */
+fn is_lock_contention(e: &io::Error) -> bool {
+    // sled 0.34 把 flock 返回的 WouldBlock 包装成 ErrorKind::Other，原始错误种类只保留在消息里
+    match e.kind() {
+        io::ErrorKind::WouldBlock => true,
+        io::ErrorKind::Other => e.to_string().contains("WouldBlock"),
+        _ => false,
+    }
+}
+
+fn open_wallets_db() -> Result<sled::Db> {
+    let db = open_shared_db(&network::data_path(WALLETS_DB))?;
+    schema::upgrade(&db, WALLETS_SCHEMA, WALLETS_VERSION, db.is_empty(), &[])?;
+
+    Ok(db)
+}
+
+fn open_wallet_txs_db() -> Result<sled::Db> {
+    let db = open_shared_db(&network::data_path(WALLET_TXS_DB))?;
+    schema::upgrade(
+        &db,
+        WALLET_TXS_SCHEMA,
+        WALLET_TXS_VERSION,
+        db.is_empty(),
+        &[],
+    )?;
+
+    Ok(db)
+}
+
+// 钱包交易列表中的一项，pending 表示交易还在等待确认并会被重新广播
+#[derive(Debug, Clone, PartialEq)]
+pub struct WalletTx {
+    pub txid: String,
+    pub pending: bool,
+    pub note: Option<String>,
+    // 与交易花费相同输出的已确认交易，由调用方查询区块链后填写
+    pub conflicts: Vec<String>,
+}
+
+/**
+ * @desc 记录钱包发出的交易，确认或放弃之前节点会定期重新广播
+ */
+pub fn add_pending_tx(tx: &Transaction) -> Result<()> {
+    let db = open_wallet_txs_db()?;
+    db.insert(tx.id.as_bytes(), serialize(tx)?)?;
+    db.flush()?;
+
+    Ok(())
+}
+
+/**
+ * @desc 钱包发出的尚未确认的交易
+ */
+pub fn get_pending_txs() -> Result<Vec<Transaction>> {
+    let db = open_wallet_txs_db()?;
+
+    let mut txs = Vec::new();
+    for item in db.iter() {
+        let (_, data) = item?;
+        txs.push(Transaction::from_bytes(&data)?);
+    }
+
+    Ok(txs)
+}
+
+/**
+ * @desc 钱包发出的尚未确认的单笔交易
+ */
+pub fn get_pending_tx(txid: &str) -> Result<Option<Transaction>> {
+    let db = open_wallet_txs_db()?;
+    match db.get(txid.as_bytes())? {
+        Some(data) => Ok(Some(Transaction::from_bytes(&data)?)),
+        None => Ok(None),
+    }
+}
+
+/**
+ * @desc 停止跟踪交易，返回交易是否在跟踪中
+ */
+pub fn remove_pending_tx(txid: &str) -> Result<bool> {
+    let db = open_wallet_txs_db()?;
+    let removed = db.remove(txid.as_bytes())?.is_some();
+    db.flush()?;
+
+    Ok(removed)
+}
+
+/**
+ * @desc 放弃未确认的交易和花费它的输出的钱包交易，返回放弃的交易 id
+ */
+pub fn abandon_pending_tx(txid: &str) -> Result<Vec<String>> {
+    let pending = get_pending_txs()?;
+    if !pending.iter().any(|tx| tx.id == txid) {
+        return Err(format_err!(
+            "Transaction {} is not a pending wallet transaction.",
+            txid
+        ));
+    }
+
+    let mut abandoned = vec![txid.to_string()];
+    let mut index = 0;
+    while index < abandoned.len() {
+        for tx in &pending {
+            let spends = tx.vin.iter().any(|vin| vin.txid == abandoned[index]);
+            if spends && !abandoned.contains(&tx.id) {
+                abandoned.push(tx.id.clone());
+            }
+        }
+        index += 1;
+    }
+    for id in &abandoned {
+        remove_pending_tx(id)?;
+    }
+
+    Ok(abandoned)
+}
+
+/**
+ * @desc 给钱包发出的交易添加备注，已有备注时覆盖
+ */
+pub fn set_tx_note(txid: &str, note: &str) -> Result<()> {
+    let db = open_wallet_txs_db()?;
+    db.open_tree(TX_NOTES_TREE)?
+        .insert(txid.as_bytes(), note.as_bytes())?;
+    db.flush()?;
+
+    Ok(())
+}
+
+/**
+ * @desc 钱包中的交易: 尚未确认的交易和带备注的交易，按 txid 排序
+ */
+pub fn list_wallet_txs() -> Result<Vec<WalletTx>> {
+    let db = open_wallet_txs_db()?;
+
+    let mut txs = BTreeMap::new();
+    for item in db.iter() {
+        let (txid, _) = item?;
+        let txid = String::from_utf8(txid.to_vec())?;
+        txs.insert(
+            txid.clone(),
+            WalletTx {
+                txid,
+                pending: true,
+                note: None,
+                conflicts: Vec::new(),
+            },
+        );
+    }
+    for item in db.open_tree(TX_NOTES_TREE)?.iter() {
+        let (txid, note) = item?;
+        let txid = String::from_utf8(txid.to_vec())?;
+        txs.entry(txid.clone())
+            .or_insert(WalletTx {
+                txid,
+                pending: false,
+                note: None,
+                conflicts: Vec::new(),
+            })
+            .note = Some(String::from_utf8(note.to_vec())?);
+    }
+
+    Ok(txs.into_values().collect())
+}
+
+fn channel_key(id: &str, role: Role) -> String {
+    match role {
+        Role::Payer => format!("{}:payer", id),
+        Role::Payee => format!("{}:payee", id),
+    }
+}
+
+/**
+ * @desc 保存支付通道的状态，同一通道同一角色只保留最新的状态
+ */
+pub fn save_channel(channel: &Channel) -> Result<()> {
+    let db = open_wallet_txs_db()?;
+    db.open_tree(CHANNELS_TREE)?.insert(
+        channel_key(&channel.id(), channel.role).as_bytes(),
+        serialize(channel)?,
+    )?;
+    db.flush()?;
+
+    Ok(())
+}
+
+pub fn get_channel(id: &str, role: Role) -> Result<Option<Channel>> {
+    let db = open_wallet_txs_db()?;
+    match db
+        .open_tree(CHANNELS_TREE)?
+        .get(channel_key(id, role).as_bytes())?
+    {
+        Some(data) => Ok(Some(deserialize(&data)?)),
+        None => Ok(None),
+    }
+}
+
+/**
+ * @desc 钱包中的全部支付通道，按通道 id 排序
+ */
+pub fn list_channels() -> Result<Vec<Channel>> {
+    let db = open_wallet_txs_db()?;
+
+    let mut channels = Vec::new();
+    for item in db.open_tree(CHANNELS_TREE)?.iter() {
+        let (_, data) = item?;
+        channels.push(deserialize(&data)?);
+    }
+
+    Ok(channels)
+}
+
+/**
+ * @desc 保存保险库，状态变化后覆盖旧的记录
+ */
+pub fn save_vault(vault: &Vault) -> Result<()> {
+    let db = open_wallet_txs_db()?;
+    db.open_tree(VAULTS_TREE)?
+        .insert(vault.id().as_bytes(), serialize(vault)?)?;
+    db.flush()?;
+
+    Ok(())
+}
+
+pub fn get_vault(id: &str) -> Result<Option<Vault>> {
+    let db = open_wallet_txs_db()?;
+    match db.open_tree(VAULTS_TREE)?.get(id.as_bytes())? {
+        Some(data) => Ok(Some(deserialize(&data)?)),
+        None => Ok(None),
+    }
+}
+
+/**
+ * @desc 钱包中的全部保险库，按保险库 id 排序
+ */
+pub fn list_vaults() -> Result<Vec<Vault>> {
+    let db = open_wallet_txs_db()?;
+
+    let mut vaults = Vec::new();
+    for item in db.open_tree(VAULTS_TREE)?.iter() {
+        let (_, data) = item?;
+        vaults.push(deserialize(&data)?);
+    }
+
+    Ok(vaults)
+}
+
+pub fn save_psbt(psbt: &Psbt) -> Result<()> {
+    let db = open_wallet_txs_db()?;
+    db.open_tree(PSBTS_TREE)?
+        .insert(psbt.id().as_bytes(), serialize(psbt)?)?;
+    db.flush()?;
+
+    Ok(())
+}
+
+pub fn get_psbt(id: &str) -> Result<Option<Psbt>> {
+    let db = open_wallet_txs_db()?;
+    match db.open_tree(PSBTS_TREE)?.get(id.as_bytes())? {
+        Some(data) => Ok(Some(deserialize(&data)?)),
+        None => Ok(None),
+    }
+}
+
+/**
+ * @desc 交易广播后不再需要部分签名
+ */
+pub fn remove_psbt(id: &str) -> Result<bool> {
+    let db = open_wallet_txs_db()?;
+    let removed = db.open_tree(PSBTS_TREE)?.remove(id.as_bytes())?.is_some();
+    db.flush()?;
+
+    Ok(removed)
+}
+
+/**
+ * @desc 等待签名的部分签名交易，按 txid 排序
+ */
+pub fn list_psbts() -> Result<Vec<Psbt>> {
+    let db = open_wallet_txs_db()?;
+
+    let mut psbts = Vec::new();
+    for item in db.open_tree(PSBTS_TREE)?.iter() {
+        let (_, data) = item?;
+        psbts.push(deserialize(&data)?);
+    }
+
+    Ok(psbts)
+}
+
 pub fn hash_pub_key(pub_key: &mut Vec<u8>) {
     let mut hasher_01 = Sha256::new();
     hasher_01.input(pub_key);
+    // 程序脚本等短于摘要的数据先补足长度，再写入摘要
+    if pub_key.len() < 32 {
+        pub_key.resize(32, 0);
+    }
     hasher_01.result(pub_key);
 
     let mut hasher_02 = Ripemd160::new();
@@ -116,14 +800,139 @@ pub fn hash_pub_key(pub_key: &mut Vec<u8>) {
     hasher_02.result(pub_key);
 }
 
+/**
+ * @desc 解析 base58 地址，返回公钥哈希
+ */
+pub fn decode_address(address: &str) -> Result<Vec<u8>> {
+    Address::decode(address)
+        .map(|address| address.body)
+        .map_err(|_| format_err!("Invalid address: {}", address))
+}
+
+/**
+ * @desc 由公钥哈希生成 base58 地址
+ */
+pub fn address_from_pub_key_hash(pub_key_hash: &[u8]) -> String {
+    let address = Address {
+        body: pub_key_hash.to_vec(),
+        scheme: Scheme::Base58,
+        hash_type: HashType::Script,
+        ..Default::default()
+    };
+
+    address.encode().unwrap()
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
+    use rand::{rngs::StdRng, SeedableRng};
+    use std::env;
+
+    #[test]
+    fn test_open_shared_db_waits_for_lock() {
+        let path = env::temp_dir().join(format!("wallet-lock-{}", std::process::id()));
+        let path = path.to_str().unwrap().to_string();
+
+        // 另一个句柄持有锁一段时间后释放
+        let held = sled::open(&path).unwrap();
+        let handle = thread::spawn(move || {
+            thread::sleep(DB_LOCK_RETRY_INTERVAL * 5);
+            drop(held);
+        });
+        assert!(open_shared_db(&path).is_ok());
+        handle.join().unwrap();
+        std::fs::remove_dir_all(&path).ok();
+    }
+
+    #[test]
+    fn test_open_shared_db_gives_up() {
+        let path = env::temp_dir().join(format!("wallet-lock-timeout-{}", std::process::id()));
+        let path = path.to_str().unwrap().to_string();
+
+        // 锁一直不释放时等待 DB_LOCK_TIMEOUT 后返回错误
+        let held = sled::open(&path).unwrap();
+        let start = Instant::now();
+        assert!(open_shared_db(&path).is_err());
+        assert!(start.elapsed() >= DB_LOCK_TIMEOUT);
+        drop(held);
+        std::fs::remove_dir_all(&path).ok();
+    }
+
+    #[test]
+    fn test_audit_entries() {
+        let mut rng = StdRng::seed_from_u64(3);
+        let named = NamedWallet {
+            next_index: 2,
+            ..NamedWallet::new(&mut rng)
+        };
+        let imported = Wallet::new(&mut rng);
+        let entry = |wallet: &Wallet| {
+            (
+                wallet.get_address().into_bytes(),
+                serialize(wallet).unwrap(),
+            )
+        };
+        let mut keys = vec![
+            entry(&named.derive(0)),
+            entry(&named.derive(1)),
+            entry(&imported),
+        ];
+        let named_entries = vec![(b"savings".to_vec(), serialize(&named).unwrap())];
+
+        let audit = audit_entries(&keys, &[], &named_entries);
+        assert_eq!((audit.keys, audit.derived, audit.named_wallets), (3, 2, 1));
+        assert!(audit.issues.is_empty(), "{:?}", audit.issues);
+
+        // 丢失派生的密钥、私钥与公钥不符、私钥的种子部分损坏无法签名、记录无法解析、地址与公钥不符
+        let lost = keys.remove(1);
+        let mut wrong_key = Wallet::new(&mut rng);
+        wrong_key.public_key = Wallet::new(&mut rng).public_key;
+        keys.push(entry(&wrong_key));
+        let mut cannot_sign = Wallet::new(&mut rng);
+        cannot_sign.secret_key[0] ^= 1;
+        keys.push(entry(&cannot_sign));
+        keys.push((b"garbage".to_vec(), vec![1, 2, 3]));
+        keys.push((b"elsewhere".to_vec(), serialize(&imported).unwrap()));
+        let watch_only = vec![(
+            imported.get_address().into_bytes(),
+            imported.descriptor().to_string().into_bytes(),
+        )];
+
+        let audit = audit_entries(&keys, &watch_only, &named_entries);
+        assert_eq!(audit.issues.len(), 6, "{:?}", audit.issues);
+        assert!(matches!(
+            &audit.issues[2],
+            AuditIssue::Corrupted { key, .. } if key == "garbage"
+        ));
+        for issue in [
+            AuditIssue::KeyMismatch {
+                address: wrong_key.get_address(),
+            },
+            AuditIssue::CannotSign {
+                address: cannot_sign.get_address(),
+            },
+            AuditIssue::AddressMismatch {
+                address: String::from("elsewhere"),
+                expected: imported.get_address(),
+            },
+            AuditIssue::OrphanedWatchOnly {
+                address: imported.get_address(),
+            },
+            AuditIssue::MissingDerivedKey {
+                name: String::from("savings"),
+                index: 1,
+                address: String::from_utf8(lost.0).unwrap(),
+            },
+        ] {
+            assert!(audit.issues.contains(&issue), "{:?}", issue);
+        }
+    }
 
     #[test]
     fn test_create_wallet_and_hash() {
-        let w1 = Wallet::new();
-        let w2 = Wallet::new();
+        let w1 = Wallet::default();
+        let w2 = Wallet::default();
         assert_ne!(w1, w2);
         assert_ne!(w1.get_address(), w2.get_address());
 
@@ -135,10 +944,18 @@ mod test {
         assert_eq!(pub_key_hash, p2);
     }
 
+    #[test]
+    fn test_seeded_wallet() {
+        let w1 = Wallet::new(&mut StdRng::seed_from_u64(7));
+        let w2 = Wallet::new(&mut StdRng::seed_from_u64(7));
+        assert_eq!(w1, w2);
+        assert_ne!(w1, Wallet::new(&mut StdRng::seed_from_u64(8)));
+    }
+
     #[test]
     fn test_wallets() {
         let mut wlts = Wallets::new().unwrap();
-        let wlt_address = wlts.create_wallet();
+        let wlt_address = wlts.create_wallet(&mut OsRng);
         let wlt1 = wlts.get_wallet(&wlt_address).unwrap().clone();
         wlts.save_all().unwrap();
 
@@ -147,17 +964,65 @@ mod test {
         assert_eq!(&wlt1, wlt2);
     }
 
+    #[test]
+    fn test_import_descriptor() {
+        let keys: Vec<Wallet> = (0..2).map(|_| Wallet::default()).collect();
+        let descriptor = Descriptor::Multi {
+            threshold: 2,
+            keys: keys.iter().map(|key| key.public_key.clone()).collect(),
+        };
+
+        let mut wlts = Wallets::new().unwrap();
+        let address = wlts.import_descriptor(descriptor.clone());
+        assert!(wlts.get_wallet(&address).is_none());
+        wlts.save_all().unwrap();
+
+        let wlts2 = Wallets::new().unwrap();
+        assert_eq!(wlts2.get_descriptor(&address), Some(descriptor));
+        assert!(wlts2.get_watch_only_addresses().contains(&address));
+        assert!(!wlts2.get_all_addresses().contains(&address));
+
+        // 有私钥的地址不会变成只读地址
+        let mut wlts3 = Wallets::new().unwrap();
+        let owned = wlts3.create_wallet(&mut OsRng);
+        let wallet = wlts3.get_wallet(&owned).unwrap().clone();
+        assert_eq!(wlts3.import_descriptor(wallet.descriptor()), owned);
+        assert!(!wlts3.get_watch_only_addresses().contains(&owned));
+        assert_eq!(wlts3.get_descriptor(&owned), Some(wallet.descriptor()));
+    }
+
+    #[test]
+    fn test_named_wallet() {
+        let name = format!("named-{}", OsRng.next_u64());
+        let mut wlts = Wallets::new().unwrap();
+        let first = wlts.create_named_wallet(&name, &mut OsRng).unwrap();
+        let second = wlts.new_address(&name).unwrap();
+        assert_ne!(first, second);
+        assert!(wlts.create_named_wallet(&name, &mut OsRng).is_err());
+        assert!(wlts.new_address("no-such-wallet").is_err());
+        wlts.save_all().unwrap();
+
+        // 派生序号和派生的密钥都会保存，地址可以由种子重新派生
+        let mut wlts2 = Wallets::new().unwrap();
+        let named = wlts2.get_named_wallet(&name).unwrap().clone();
+        assert_eq!(named.next_index, 2);
+        assert_eq!(named.derive(0).get_address(), first);
+        assert_eq!(wlts2.get_wallet(&second), Some(&named.derive(1)));
+        let third = wlts2.new_address(&name).unwrap();
+        assert_eq!(third, named.derive(2).get_address());
+    }
+
     #[test]
     #[should_panic]
     fn test_wallets_not_exist() {
-        let wlt = Wallet::new();
+        let wlt = Wallet::default();
         let wlts2 = Wallets::new().unwrap();
         wlts2.get_wallet(&wlt.get_address()).unwrap();
     }
 
     #[test]
     fn test_signature() {
-        let w = Wallet::new();
+        let w = Wallet::default();
         let signature = ed25519::signature("test".as_bytes(), &w.secret_key);
 
         assert!(ed25519::verify(
@@ -0,0 +1,492 @@
+// 集成测试工具: 在临时目录中以 regtest 启动节点，并提供构造区块和交易的辅助方法
+
+#![allow(dead_code)]
+
+use std::{
+    env, fs,
+    io::{Read, Write},
+    net::{Shutdown, TcpListener, TcpStream},
+    path::{Path, PathBuf},
+    process::{Child, Command, Stdio},
+    sync::atomic::{AtomicUsize, Ordering},
+    thread,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use bincode::serialize;
+use blockchain::{
+    auth::read_cookie,
+    block::Block,
+    identity::NodeIdentity,
+    network::{self, Network},
+    rpc::{self, BlockchainInfo, RpcRequest, RpcResponse},
+    server::{cmd_to_bytes, VERSION},
+    transaction::{TXInput, TXOutput, Transaction, TX_VERSION},
+    versionbits::VERSIONBITS_TOP_BITS,
+};
+use rand_core::OsRng;
+use serde::Serialize;
+
+const BIN: &str = env!("CARGO_BIN_EXE_blockchain");
+const START_TIMEOUT: Duration = Duration::from_secs(10);
+const CMD_LEN: usize = 12;
+
+static NEXT_DIR: AtomicUsize = AtomicUsize::new(0);
+
+// 在独立数据目录和随机端口上运行的节点，离开作用域时结束进程并删除目录
+pub struct TestNode {
+    pub dir: PathBuf,
+    pub port: u16,
+    child: Option<Child>,
+}
+
+impl TestNode {
+    pub fn new() -> TestNode {
+        let dir = env::temp_dir().join(format!(
+            "blockchain-test-{}-{}",
+            std::process::id(),
+            NEXT_DIR.fetch_add(1, Ordering::SeqCst)
+        ));
+        fs::remove_dir_all(&dir).ok();
+        fs::create_dir_all(dir.join(Network::Regtest.params().data_dir)).unwrap();
+
+        TestNode {
+            dir,
+            port: free_port(),
+            child: None,
+        }
+    }
+
+    /**
+     * @desc 节点的 regtest 数据目录，配置文件仍在 data 目录中
+     */
+    pub fn data_dir(&self) -> PathBuf {
+        self.dir.join(Network::Regtest.params().data_dir)
+    }
+
+    pub fn addr(&self) -> String {
+        format!("localhost:{}", self.port)
+    }
+
+    /**
+     * @desc 在节点目录中运行命令行，失败时 panic，返回标准输出
+     */
+    pub fn cli(&self, args: &[&str]) -> String {
+        let output = Command::new(BIN)
+            .current_dir(&self.dir)
+            .args(["--network", "regtest"])
+            .args(args)
+            .output()
+            .unwrap();
+        let stdout = String::from_utf8_lossy(&output.stdout).to_string();
+        assert!(
+            output.status.success() && !stdout.contains("Error:"),
+            "{:?} failed: {}",
+            args,
+            stdout
+        );
+
+        stdout
+    }
+
+    /**
+     * @desc 运行预期失败的命令行，返回错误信息
+     */
+    pub fn cli_error(&self, args: &[&str]) -> String {
+        let output = Command::new(BIN)
+            .current_dir(&self.dir)
+            .args(["--network", "regtest"])
+            .args(args)
+            .output()
+            .unwrap();
+        let stdout = String::from_utf8_lossy(&output.stdout).to_string();
+        assert!(
+            stdout.contains("Error:"),
+            "{:?} succeeded: {}",
+            args,
+            stdout
+        );
+
+        stdout
+    }
+
+    pub fn create_wallet(&self) -> String {
+        let out = self.cli(&["create_wallets"]);
+        out.trim().rsplit(' ').next().unwrap().to_string()
+    }
+
+    /**
+     * @desc 创建区块链，创世区块奖励给 address
+     */
+    pub fn create_blockchain(&self, address: &str) {
+        self.cli(&["create_blockchain", address]);
+    }
+
+    /**
+     * @desc 立即挖出 count 个区块，需要节点未运行(数据库被节点进程锁定)
+     */
+    pub fn generate(&self, address: &str, count: usize) -> Vec<String> {
+        self.cli(&["generate", address, &count.to_string()])
+            .lines()
+            .filter(|line| line.len() == 64)
+            .map(String::from)
+            .collect()
+    }
+
+    pub fn balance(&self, address: &str) -> i32 {
+        let out = match self.child {
+            Some(_) => self.cli(&["get_balance", address, "--node", &self.addr()]),
+            None => self.cli(&["get_balance", address]),
+        };
+        out.trim().trim_start_matches("Balance: ").parse().unwrap()
+    }
+
+    /**
+     * @desc 把区块和 UTXO 数据复制到另一个节点，使两者共享创世区块
+     */
+    pub fn copy_chain_to(&self, other: &TestNode) {
+        for db in ["blocks", "utxos"] {
+            copy_dir(&self.data_dir().join(db), &other.data_dir().join(db));
+        }
+    }
+
+    /**
+     * @desc 启动节点，peers 写入白名单以便相互连接，等待 RPC 可用
+     */
+    pub fn start(&mut self, peers: &[&TestNode]) {
+        self.start_with_config(peers, "");
+    }
+
+    /**
+     * @desc 启动节点，config 是追加到配置文件的内容
+     */
+    pub fn start_with_config(&mut self, peers: &[&TestNode], config: &str) {
+        let whitelist: Vec<String> = peers.iter().map(|p| format!("\"{}\"", p.addr())).collect();
+        fs::write(
+            self.dir.join("data/config.toml"),
+            format!("whitelist = [{}]\n{}", whitelist.join(", "), config),
+        )
+        .unwrap();
+
+        self.spawn(&["start_node", &self.port.to_string()]);
+        self.wait_ready();
+    }
+
+    /**
+     * @desc 启动矿工节点，payout 是收款地址或命名钱包名，config 是配置文件的内容
+     */
+    pub fn start_miner(&mut self, payout: &str, config: &str) {
+        fs::write(self.dir.join("data/config.toml"), config).unwrap();
+        self.spawn(&["start_miner", &self.port.to_string(), payout]);
+        self.wait_ready();
+    }
+
+    /**
+     * @desc 以端口 0 启动节点，由节点选择空闲端口，从数据目录的 .node 读取实际端口
+     */
+    pub fn start_on_free_port(&mut self) {
+        self.spawn(&["start_node", "0"]);
+
+        let node_file = self.data_dir().join(".node");
+        wait_until(START_TIMEOUT, || node_file.exists());
+        let addr = fs::read_to_string(node_file).unwrap();
+        self.port = addr.trim().rsplit(':').next().unwrap().parse().unwrap();
+        self.wait_ready();
+    }
+
+    fn spawn(&mut self, args: &[&str]) {
+        let child = Command::new(BIN)
+            .current_dir(&self.dir)
+            .args(["--network", "regtest"])
+            .args(args)
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+            .unwrap();
+        self.child = Some(child);
+    }
+
+    fn wait_ready(&self) {
+        wait_until(START_TIMEOUT, || {
+            self.data_dir().join(".cookie").exists() && self.try_blockchain_info().is_some()
+        });
+    }
+
+    pub fn stop(&mut self) {
+        if let Some(mut child) = self.child.take() {
+            child.kill().ok();
+            child.wait().ok();
+        }
+    }
+
+    pub fn rpc(&self, request: RpcRequest) -> blockchain::Result<RpcResponse> {
+        let cookie = self.data_dir().join(".cookie");
+        let credentials = read_cookie(cookie.to_str().unwrap())?;
+        rpc::call(&self.addr(), Some(credentials), request)
+    }
+
+    fn try_blockchain_info(&self) -> Option<BlockchainInfo> {
+        match self.rpc(RpcRequest::GetBlockchainInfo) {
+            Ok(RpcResponse::BlockchainInfo(info)) => Some(info),
+            _ => None,
+        }
+    }
+
+    pub fn blockchain_info(&self) -> BlockchainInfo {
+        self.try_blockchain_info().unwrap()
+    }
+
+    /**
+     * @desc 通过 RPC 向节点提交区块
+     */
+    pub fn submit_block(&self, block: &Block) {
+        self.rpc(RpcRequest::SubmitBlock(block.clone())).unwrap();
+    }
+
+    /**
+     * @desc 向节点发送一条 P2P 消息
+     */
+    pub fn send_message<T: Serialize>(&self, cmd: &str, msg: &T) {
+        let data = serialize(&(cmd_to_bytes(cmd), msg)).unwrap();
+
+        let mut stream = TcpStream::connect(self.addr()).unwrap();
+        stream.write_all(&data).unwrap();
+        stream.shutdown(Shutdown::Write).unwrap();
+    }
+}
+
+impl Drop for TestNode {
+    fn drop(&mut self) {
+        self.stop();
+        fs::remove_dir_all(&self.dir).ok();
+    }
+}
+
+// 只接收消息的假节点，用于检查节点发出的 P2P 消息
+pub struct FakePeer {
+    listener: TcpListener,
+}
+
+impl FakePeer {
+    pub fn new() -> FakePeer {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        listener.set_nonblocking(true).unwrap();
+
+        FakePeer { listener }
+    }
+
+    pub fn addr(&self) -> String {
+        format!("localhost:{}", self.listener.local_addr().unwrap().port())
+    }
+
+    /**
+     * @desc 和节点完成握手: 发送签名的版本信息，收到节点的确认后回复确认
+     */
+    pub fn handshake(&self, node: &TestNode) {
+        let identity = NodeIdentity::new(&mut OsRng);
+        let fields = (
+            self.addr(),
+            VERSION,
+            -1,
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap()
+                .as_millis(),
+            Network::Regtest.params().magic,
+            false,
+            identity.public_key.clone(),
+        );
+        // bincode 编码的嵌套元组和展开的字段相同
+        let signature = identity.sign(&serialize(&fields).unwrap());
+        node.send_message("version", &(fields, signature));
+
+        self.expect("verack", START_TIMEOUT);
+        node.send_message("verack", &(self.addr(),));
+    }
+
+    /**
+     * @desc 已经收到的指定命令的消息内容(不含命令)，不等待
+     */
+    pub fn received(&self, cmd: &str) -> Vec<Vec<u8>> {
+        let mut messages = Vec::new();
+        while let Ok((mut stream, _)) = self.listener.accept() {
+            stream.set_nonblocking(false).unwrap();
+            let mut data = Vec::new();
+            stream.read_to_end(&mut data).unwrap();
+            if data.len() >= CMD_LEN && data[..CMD_LEN] == cmd_to_bytes(cmd) {
+                messages.push(data[CMD_LEN..].to_vec());
+            }
+        }
+        messages
+    }
+
+    /**
+     * @desc 等待节点发来指定命令的消息，返回消息内容(不含命令)
+     */
+    pub fn expect(&self, cmd: &str, timeout: Duration) -> Vec<u8> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            match self.listener.accept() {
+                Ok((mut stream, _)) => {
+                    stream.set_nonblocking(false).unwrap();
+                    let mut data = Vec::new();
+                    stream.read_to_end(&mut data).unwrap();
+                    if data.len() >= CMD_LEN && data[..CMD_LEN] == cmd_to_bytes(cmd) {
+                        return data[CMD_LEN..].to_vec();
+                    }
+                }
+                Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => {
+                    assert!(
+                        Instant::now() < deadline,
+                        "No {} message after {:?}.",
+                        cmd,
+                        timeout
+                    );
+                    thread::sleep(Duration::from_millis(50));
+                }
+                Err(e) => panic!("{}", e),
+            }
+        }
+    }
+}
+
+/**
+ * @desc 在超时前反复检查条件，超时则 panic
+ */
+pub fn wait_until<F: FnMut() -> bool>(timeout: Duration, mut cond: F) {
+    let deadline = Instant::now() + timeout;
+    while !cond() {
+        assert!(Instant::now() < deadline, "Timed out after {:?}.", timeout);
+        thread::sleep(Duration::from_millis(200));
+    }
+}
+
+pub fn free_port() -> u16 {
+    TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port()
+}
+
+/**
+ * @desc 向本机端口发送 HTTP GET 请求，返回响应正文
+ */
+pub fn http_get(port: u16, path: &str) -> String {
+    let mut stream = TcpStream::connect(("127.0.0.1", port)).unwrap();
+    write!(stream, "GET {} HTTP/1.1\r\nHost: localhost\r\n\r\n", path).unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+
+    response
+        .split_once("\r\n\r\n")
+        .map(|(_, body)| body.to_string())
+        .unwrap_or_default()
+}
+
+fn copy_dir(from: &Path, to: &Path) {
+    fs::create_dir_all(to).unwrap();
+    for entry in fs::read_dir(from).unwrap() {
+        let entry = entry.unwrap();
+        let target = to.join(entry.file_name());
+        if entry.file_type().unwrap().is_dir() {
+            copy_dir(&entry.path(), &target);
+        } else {
+            fs::copy(entry.path(), target).unwrap();
+        }
+    }
+}
+
+// 构造交易，输入的签名和公钥默认为空
+#[derive(Default)]
+pub struct TxBuilder {
+    vin: Vec<TXInput>,
+    vout: Vec<TXOutput>,
+}
+
+impl TxBuilder {
+    pub fn new() -> TxBuilder {
+        TxBuilder::default()
+    }
+
+    pub fn input(mut self, txid: &str, vout: i32) -> TxBuilder {
+        self.vin.push(TXInput {
+            txid: txid.to_string(),
+            vout,
+            signature: Vec::new(),
+            pub_key: Vec::new(),
+        });
+        self
+    }
+
+    pub fn output(mut self, value: i32, address: &str) -> TxBuilder {
+        self.vout
+            .push(TXOutput::new(value, address.to_string()).unwrap());
+        self
+    }
+
+    pub fn build(self) -> Transaction {
+        let mut tx = Transaction {
+            id: String::new(),
+            vin: self.vin,
+            vout: self.vout,
+            version: TX_VERSION,
+            relative_lock_time: 0,
+        };
+        tx.id = tx.hash().unwrap();
+        tx
+    }
+}
+
+// 构造 regtest 区块，工作量证明在 build 时完成
+pub struct BlockBuilder {
+    prev_block_hash: String,
+    height: i32,
+    version: i32,
+    transactions: Vec<Transaction>,
+}
+
+impl BlockBuilder {
+    pub fn new(prev_block_hash: &str, height: i32) -> BlockBuilder {
+        BlockBuilder {
+            prev_block_hash: prev_block_hash.to_string(),
+            height,
+            version: VERSIONBITS_TOP_BITS,
+            transactions: Vec::new(),
+        }
+    }
+
+    /**
+     * @desc 在节点当前最新区块之上构造
+     */
+    pub fn on_tip(node: &TestNode) -> BlockBuilder {
+        let info = node.blockchain_info();
+        BlockBuilder::new(&info.best_block_hash, info.height + 1)
+    }
+
+    pub fn version(mut self, version: i32) -> BlockBuilder {
+        self.version = version;
+        self
+    }
+
+    pub fn tx(mut self, tx: Transaction) -> BlockBuilder {
+        self.transactions.push(tx);
+        self
+    }
+
+    pub fn coinbase(self, address: &str) -> BlockBuilder {
+        self.tx(Transaction::new_coinbase(address.to_string(), String::new(), &mut OsRng).unwrap())
+    }
+
+    pub fn build(self) -> Block {
+        network::set_network(Network::Regtest);
+        Block::new(
+            self.transactions,
+            self.prev_block_hash,
+            self.height,
+            self.version,
+            network::params().pow_limit_bits,
+        )
+        .unwrap()
+    }
+}
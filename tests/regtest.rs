@@ -0,0 +1,1081 @@
+mod common;
+
+use std::{net::UdpSocket, time::Duration};
+
+use blockchain::{
+    rpc::{HandshakeState, RpcRequest, RpcResponse, SyncState},
+    transaction::{encode_hex, Transaction},
+};
+use common::{wait_until, BlockBuilder, FakePeer, TestNode, TxBuilder};
+
+const SYNC_TIMEOUT: Duration = Duration::from_secs(40);
+
+/**
+ * @desc 创建钱包和区块链，并挖出 blocks 个区块
+ */
+fn funded_node(blocks: usize) -> (TestNode, String) {
+    let node = TestNode::new();
+    let address = node.create_wallet();
+    node.create_blockchain(&address);
+    node.generate(&address, blocks);
+
+    (node, address)
+}
+
+/**
+ * @desc 节点钱包中第一个地址的公钥(十六进制)
+ */
+fn wallet_key(node: &TestNode) -> String {
+    node.cli(&["list_descriptors"])
+        .split("pkh(")
+        .nth(1)
+        .and_then(|rest| rest.split(')').next())
+        .unwrap()
+        .to_string()
+}
+
+/**
+ * @desc 等待节点完成同步，同步完成前收到的交易只进入交易池，不会触发挖矿
+ */
+fn wait_synced(node: &TestNode) {
+    wait_until(SYNC_TIMEOUT, || {
+        matches!(
+            node.rpc(RpcRequest::GetSyncStatus),
+            Ok(RpcResponse::SyncStatus(status)) if status.state == SyncState::Synced
+        )
+    });
+}
+
+#[test]
+fn test_generate_funds_wallet() {
+    let (node, address) = funded_node(2);
+
+    assert_eq!(node.balance(&address), 30);
+}
+
+#[test]
+fn test_list_address_balances() {
+    let (mut node, address) = funded_node(2);
+    let other = node.create_wallet();
+    node.generate(&other, 1);
+
+    let expected = format!("{}: 30\n{}: 10\n", address, other);
+    assert!(node.cli(&["list_address_balances"]).ends_with(&expected));
+
+    // 节点运行时通过 RPC 查询
+    node.start(&[]);
+    assert!(node.cli(&["list_address_balances"]).ends_with(&expected));
+}
+
+#[test]
+fn test_get_wallet_balance() {
+    let (node, address) = funded_node(2);
+    let other = node.create_wallet();
+    node.generate(&other, 1);
+    let empty = node.create_wallet();
+
+    assert!(node.cli(&["get_wallet_balance"]).contains("Balance: 40\n"));
+    let verbose = node.cli(&["get_wallet_balance", "--verbose"]);
+    assert!(verbose.contains(&format!("{}: 30\n", address)));
+    assert!(verbose.contains(&format!("{}: 10\n", other)));
+    assert!(verbose.contains(&format!("{}: 0\n", empty)));
+}
+
+#[test]
+fn test_vanity_address() {
+    let node = TestNode::new();
+    let prefix = format!("{}A", &node.create_wallet()[..1]);
+
+    let out = node.cli(&["vanity_address", &prefix, "--threads", "2"]);
+    let address = out.trim().rsplit(' ').next().unwrap();
+    assert!(address.starts_with(&prefix), "{}", out);
+    assert!(node.cli(&["list_addresses"]).contains(address));
+
+    let err = node.cli_error(&["vanity_address", &format!("{}z", &prefix[..1])]);
+    assert!(err.contains("No address can start with"), "{}", err);
+}
+
+#[test]
+fn test_paper_wallet() {
+    let node = TestNode::new();
+    let address = node.create_wallet();
+
+    let sheet = node.cli(&["export_paper_wallet", &address, "--passphrase", "hunter2"]);
+    let mut lines = sheet.lines();
+    lines.find(|line| line.starts_with("Private key"));
+    let key = lines.next().unwrap();
+
+    let other = TestNode::new();
+    let err = other.cli_error(&["import_paper_wallet", key, "--passphrase", "wrong"]);
+    assert!(err.contains("Wrong passphrase"), "{}", err);
+    let out = other.cli(&["import_paper_wallet", key, "--passphrase", "hunter2"]);
+    assert!(out.contains(&address), "{}", out);
+    assert!(other.cli(&["list_addresses"]).contains(&address));
+
+    let path = node.dir.join("paper.html");
+    node.cli(&[
+        "export_paper_wallet",
+        &address,
+        "--format",
+        "html",
+        "--output",
+        path.to_str().unwrap(),
+    ]);
+    let html = std::fs::read_to_string(&path).unwrap();
+    assert!(html.contains(&address) && html.contains("<svg"));
+    assert!(node
+        .cli_error(&["export_paper_wallet", &other.create_wallet()])
+        .contains("is not in the wallet"));
+}
+
+#[test]
+fn test_audit_wallet() {
+    let node = TestNode::new();
+    node.create_wallet();
+    node.cli(&["create_wallets", "--name", "savings"]);
+
+    let out = node.cli(&["audit_wallet"]);
+    assert!(
+        out.contains("keys: 2") && out.contains("derived: 1"),
+        "{}",
+        out
+    );
+    assert!(out.contains("issues: []"), "{}", out);
+
+    // 直接写入一条无法解析的密钥记录
+    let db = sled::open(node.data_dir().join("wallets")).unwrap();
+    db.insert("3broken", &[1, 2, 3]).unwrap();
+    db.flush().unwrap();
+    drop(db);
+
+    let err = node.cli_error(&["audit_wallet"]);
+    assert!(
+        err.contains("Corrupted") && err.contains("3broken"),
+        "{}",
+        err
+    );
+    assert!(err.contains("Wallet audit found 1 issues."), "{}", err);
+}
+
+#[test]
+fn test_rest_address() {
+    let (mut node, address) = funded_node(2);
+    let rest_port = common::free_port();
+    node.start_with_config(
+        &[],
+        &format!("rest_port = {}\n\n[rpc]\npublic = true\n", rest_port),
+    );
+
+    let body = common::http_get(rest_port, &format!("/rest/address/{}", address));
+    assert!(
+        body.contains(&format!("\"balance\":{}", node.balance(&address))),
+        "{}",
+        body
+    );
+    assert_eq!(body.matches("\"txid\"").count(), 3, "{}", body);
+}
+
+#[cfg(feature = "grpc")]
+#[test]
+fn test_grpc_interface() {
+    use blockchain::grpc::pb::{node_client::NodeClient, Address, BlockId, Empty};
+    use tonic::{metadata::MetadataValue, Code, Request};
+
+    let (mut node, address) = funded_node(2);
+    let grpc_port = common::free_port();
+    node.start_with_config(
+        &[],
+        &format!(
+            "grpc_port = {}\n\n[rpc]\nuser = \"alice\"\npassword = \"pw\"\n",
+            grpc_port
+        ),
+    );
+    // 带 alice:pw 的 Basic 认证的请求
+    fn authorized<T>(message: T) -> Request<T> {
+        let mut request = Request::new(message);
+        request.metadata_mut().insert(
+            "authorization",
+            MetadataValue::from_static("Basic YWxpY2U6cHc="),
+        );
+        request
+    }
+
+    tokio::runtime::Runtime::new().unwrap().block_on(async {
+        let mut client = NodeClient::connect(format!("http://127.0.0.1:{}", grpc_port))
+            .await
+            .unwrap();
+
+        // 和 RPC 相同的权限检查，没有凭据时拒绝
+        let status = client.get_blockchain_info(Empty {}).await.unwrap_err();
+        assert_eq!(status.code(), Code::PermissionDenied);
+
+        let info = client
+            .get_blockchain_info(authorized(Empty {}))
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(info.height, 2);
+        let block = client
+            .get_block(authorized(BlockId {
+                id: info.best_block_hash.clone(),
+            }))
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(block.header.unwrap().hash, info.best_block_hash);
+        let balance = client
+            .get_balance(authorized(Address {
+                address: address.clone(),
+            }))
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(balance.balance, node.balance(&address));
+
+        // RPC 拒绝的交易返回 InvalidArgument
+        let tx = Transaction::new_coinbase(address.clone(), String::new(), &mut rand_core::OsRng)
+            .unwrap();
+        let status = client
+            .send_raw_transaction(authorized((&tx).into()))
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), Code::InvalidArgument);
+
+        // 订阅之后加入主链的区块被推送
+        let mut blocks = client
+            .subscribe_blocks(authorized(Empty {}))
+            .await
+            .unwrap()
+            .into_inner();
+        let next = BlockBuilder::on_tip(&node).coinbase(&address).build();
+        node.submit_block(&next);
+        let pushed = blocks.message().await.unwrap().unwrap();
+        assert_eq!(pushed.header.unwrap().hash, next.get_hash());
+        assert_eq!(pushed.transactions.len(), 1);
+    });
+}
+
+#[test]
+fn test_send() {
+    let (node, from) = funded_node(1);
+    let to = node.create_wallet();
+
+    node.cli(&["send", &from, &to, "3", "--mine"]);
+
+    assert_eq!(node.balance(&to), 3);
+    assert_eq!(node.balance(&from), 27);
+}
+
+#[test]
+fn test_reindex_parallel() {
+    let (node, from) = funded_node(3);
+    let to = node.create_wallet();
+    node.cli(&["send", &from, &to, "3", "--mine"]);
+
+    // 并行重建的 UTXO 集和逐个区块写入的一致
+    let out = node.cli(&["reindex", "--threads", "3"]);
+    assert!(out.contains("Done!"), "{}", out);
+    assert_eq!(node.balance(&to), 3);
+    assert_eq!(node.balance(&from), 47);
+}
+
+#[test]
+fn test_send_note() {
+    let (node, from) = funded_node(1);
+    let to = node.create_wallet();
+
+    let hex = node.cli(&["send", &from, &to, "3", "--raw", "--note", "rent"]);
+    let tx = Transaction::from_hex(hex.lines().last().unwrap()).unwrap();
+
+    // 备注只保存在钱包中，交易本身不包含备注
+    let txs = node.cli(&["list_wallet_txs"]);
+    assert!(txs.contains(&tx.id));
+    assert!(txs.contains("\"rent\""));
+    assert!(!hex.contains(&encode_hex(b"rent")));
+}
+
+#[test]
+fn test_send_payment_request() {
+    let (node, from) = funded_node(1);
+    let to = node.create_wallet();
+
+    let out = node.cli(&["show_address", &to, "--amount", "4", "--label", "rent"]);
+    let uri = out.lines().last().unwrap();
+    assert!(uri.contains("network=regtest"));
+
+    // 金额和备注都来自付款请求
+    let hex = node.cli(&["send", &from, uri, "--raw"]);
+    let tx = Transaction::from_hex(hex.lines().last().unwrap()).unwrap();
+    assert_eq!(tx.vout[0].value, 4);
+    assert!(node.cli(&["list_wallet_txs"]).contains("\"rent\""));
+
+    let error = node.cli_error(&["send", &from, &uri.replace("regtest", "test"), "--raw"]);
+    assert!(error.contains("network"));
+}
+
+#[test]
+fn test_sweep() {
+    let (node, from) = funded_node(2);
+    let to = node.create_wallet();
+
+    let out = node.cli(&["sweep", &to, "--raw"]);
+    let tx = Transaction::from_hex(out.lines().last().unwrap()).unwrap();
+    assert_eq!(tx.vin.len(), 3);
+    assert_eq!(tx.vout.len(), 1);
+    assert_eq!(tx.vout[0].value, 30);
+
+    let out = node.cli(&["sweep", &to, "--from", &from, "--fee_rate", "10", "--raw"]);
+    let tx = Transaction::from_hex(out.lines().last().unwrap()).unwrap();
+    assert!(tx.vout[0].value < 30);
+
+    let err = node.cli_error(&["sweep", &from, "--from", &to, "--raw"]);
+    assert!(err.contains("No spendable outputs"));
+}
+
+#[test]
+fn test_sync() {
+    let (mut a, _) = funded_node(3);
+    a.start(&[]);
+
+    let mut b = TestNode::new();
+    b.start(&[&a]);
+
+    let target = a.blockchain_info();
+    wait_until(SYNC_TIMEOUT, || {
+        b.blockchain_info().best_block_hash == target.best_block_hash
+    });
+    assert_eq!(b.blockchain_info().height, 3);
+}
+
+#[test]
+fn test_alert_reaches_new_peer() {
+    let (mut a, address) = funded_node(0);
+    let config = format!("alert_key = \"{}\"\n", wallet_key(&a));
+    let other = a.create_wallet();
+    let mut b = TestNode::new();
+    a.copy_chain_to(&b);
+    a.start_with_config(&[], &config);
+
+    let addr = a.addr();
+    let out = a.cli(&["send_alert", &address, "Upgrade now.", "--id", "1", &addr]);
+    assert_eq!(out.trim(), "Alert 1 sent.");
+    assert_eq!(a.blockchain_info().alerts, vec!["Upgrade now."]);
+
+    // 新节点握手后收到尚未过期的公告
+    b.start_with_config(&[&a], &config);
+    wait_until(SYNC_TIMEOUT, || !b.blockchain_info().alerts.is_empty());
+    assert_eq!(b.blockchain_info().alerts, vec!["Upgrade now."]);
+
+    // 其他密钥签名的公告被拒绝
+    a.cli_error(&["send_alert", &other, "Fake.", "--id", "2", &addr]);
+}
+
+#[test]
+fn test_psbt_cosigners_over_network() {
+    let (mut a, address) = funded_node(1);
+    let mut b = TestNode::new();
+    b.create_wallet();
+    let c = TestNode::new();
+    c.create_wallet();
+
+    // 2-of-3 多签分布在三个节点上，第三个签名方不需要在线
+    let descriptor = format!(
+        "multi(2,{},{},{})",
+        wallet_key(&a),
+        wallet_key(&b),
+        wallet_key(&c)
+    );
+    let multisig = a.cli(&["import_descriptor", &descriptor]);
+    let multisig = multisig.trim().rsplit(' ').next().unwrap().to_string();
+    b.cli(&["import_descriptor", &descriptor]);
+    a.cli(&["send", &address, &multisig, "6", "--mine"]);
+
+    a.copy_chain_to(&b);
+    a.start(&[&b]);
+    b.start(&[&a]);
+    wait_until(
+        SYNC_TIMEOUT,
+        || matches!(a.rpc(RpcRequest::GetPeerInfo), Ok(RpcResponse::PeerInfo(peers)) if !peers.is_empty()),
+    );
+
+    let out = a.cli(&["create_psbt", &multisig, &address, "5", &a.addr()]);
+    let id = out
+        .split("Psbt: ")
+        .nth(1)
+        .and_then(|rest| rest.split(',').next())
+        .unwrap()
+        .to_string();
+    wait_until(SYNC_TIMEOUT, || b.cli(&["list_psbts"]).contains(&id));
+
+    let out = b.cli(&["sign_psbt", &id, &b.addr()]);
+    assert!(out.contains("fully signed"));
+    wait_until(SYNC_TIMEOUT, || {
+        a.rpc(RpcRequest::GetTransaction(id.clone()))
+            .is_ok_and(|response| matches!(response, RpcResponse::Transaction(_)))
+    });
+    assert!(!b.cli(&["list_psbts"]).contains(&id));
+}
+
+#[test]
+fn test_reorg() {
+    let (mut a, address) = funded_node(0);
+    let mut b = TestNode::new();
+    a.copy_chain_to(&b);
+
+    // 两个节点在同一创世区块上各自挖矿，b 的链更长
+    a.generate(&address, 1);
+    b.generate(&address, 2);
+
+    a.start(&[&b]);
+    b.start(&[&a]);
+
+    let longest = b.blockchain_info().best_block_hash;
+    wait_until(SYNC_TIMEOUT, || {
+        a.blockchain_info().best_block_hash == longest
+    });
+    assert_eq!(a.blockchain_info().height, 2);
+}
+
+#[test]
+fn test_backlog_after_partition() {
+    let (mut a, address) = funded_node(1);
+    let mut b = TestNode::new();
+    a.copy_chain_to(&b);
+    let hex = a.cli(&["send", &address, &address, "3", "--raw"]);
+    let tx = Transaction::from_hex(hex.lines().last().unwrap()).unwrap();
+
+    // 分区期间两边各出一个块，高度相同但最新区块不同
+    a.generate(&address, 1);
+    b.generate(&address, 1);
+    b.start(&[&a]);
+    let fork = b.blockchain_info().best_block_hash;
+    b.rpc(RpcRequest::SendRawTransaction(tx.clone())).unwrap();
+
+    // 只比较高度不会同步，握手后双方公告最近区块和交易池，互相收到对方的分叉
+    a.start(&[&b]);
+    let tip = a.blockchain_info().best_block_hash;
+    wait_until(SYNC_TIMEOUT, || {
+        matches!(
+            a.rpc(RpcRequest::GetBlock(fork.clone())),
+            Ok(RpcResponse::Block(_))
+        ) && matches!(
+            b.rpc(RpcRequest::GetBlock(tip.clone())),
+            Ok(RpcResponse::Block(_))
+        ) && matches!(
+            a.rpc(RpcRequest::GetTransaction(tx.id.clone())),
+            Ok(RpcResponse::Transaction(_))
+        )
+    });
+}
+
+#[test]
+fn test_simulate_partition() {
+    let (mut a, address) = funded_node(0);
+    let mut b = TestNode::new();
+    a.copy_chain_to(&b);
+    a.start(&[&b]);
+    b.start(&[&a]);
+    let node = a.addr();
+    let out = a.cli(&["simulate_partition", &b.addr(), "--node", &node]);
+    assert!(out.contains(&b.addr()));
+
+    // 分区期间 b 出一个块，a 出两个块，a 不接收也不发送消息
+    let fork = BlockBuilder::on_tip(&b).coinbase(&address).build();
+    b.submit_block(&fork);
+    for _ in 0..2 {
+        let block = BlockBuilder::on_tip(&a).coinbase(&address).build();
+        a.submit_block(&block);
+    }
+    wait_until(SYNC_TIMEOUT, || a.blockchain_info().height == 2);
+    assert_eq!(b.blockchain_info().best_block_hash, fork.get_hash());
+
+    // 恢复连通后 b 回滚自己的区块，切换到 a 的更长链
+    a.cli(&["simulate_partition", "--node", &node]);
+    wait_until(SYNC_TIMEOUT, || {
+        b.blockchain_info().best_block_hash == a.blockchain_info().best_block_hash
+    });
+    match b.rpc(RpcRequest::GetForkStats).unwrap() {
+        RpcResponse::ForkStats(stats) => {
+            assert_eq!(stats.reorgs, 1);
+            assert_eq!(stats.max_reorg_depth, 1);
+            assert_eq!(stats.orphan_blocks, 1);
+        }
+        response => panic!("unexpected response: {:?}", response),
+    }
+}
+
+#[test]
+fn test_known_nodes_survive_restart() {
+    let (mut a, _) = funded_node(0);
+    let mut b = TestNode::new();
+    a.copy_chain_to(&b);
+    a.start(&[]);
+    b.start(&[&a]);
+    let has_peer = |node: &TestNode, peer: &TestNode| {
+        matches!(
+            node.rpc(RpcRequest::GetPeerInfo),
+            Ok(RpcResponse::PeerInfo(peers)) if peers.iter().any(|p| p.addr == peer.addr())
+        )
+    };
+    wait_until(SYNC_TIMEOUT, || has_peer(&a, &b));
+
+    // a 没有配置 b，重启后从地址表恢复 b 并主动连接
+    a.stop();
+    b.stop();
+    b.start(&[]);
+    a.start(&[]);
+    wait_until(SYNC_TIMEOUT, || has_peer(&b, &a));
+}
+
+#[test]
+fn test_dns_seeds() {
+    let (mut a, _) = funded_node(0);
+    let mut b = TestNode::new();
+    a.copy_chain_to(&b);
+    b.start(&[]);
+
+    // a 的地址表为空，启动时解析种子域名得到 b 并连接
+    a.start_with_config(&[], &format!("dns_seeds = [\"localhost:{}\"]", b.port));
+    wait_until(SYNC_TIMEOUT, || {
+        matches!(
+            b.rpc(RpcRequest::GetPeerInfo),
+            Ok(RpcResponse::PeerInfo(peers)) if peers.iter().any(|p| p.addr == a.addr())
+        )
+    });
+}
+
+#[test]
+fn test_lan_discovery() {
+    let (mut a, _) = funded_node(0);
+    let mut b = TestNode::new();
+    a.copy_chain_to(&b);
+    let port = UdpSocket::bind("0.0.0.0:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port();
+    let config = format!("lan_discovery = true\nlan_discovery_port = {}", port);
+
+    // 同一台机器上 b 无法再监听组播端口，只公告；a 收到公告后连接 b
+    a.start_with_config(&[], &config);
+    b.start_with_config(&[], &config);
+    let suffix = format!(":{}", a.port);
+    wait_until(SYNC_TIMEOUT, || {
+        matches!(
+            b.rpc(RpcRequest::GetPeerInfo),
+            Ok(RpcResponse::PeerInfo(peers)) if peers.iter().any(|p| p.addr.ends_with(&suffix))
+        )
+    });
+}
+
+#[test]
+fn test_submit_block() {
+    let (mut node, address) = funded_node(1);
+    node.start(&[]);
+
+    let block = BlockBuilder::on_tip(&node).coinbase(&address).build();
+    node.submit_block(&block);
+
+    wait_until(SYNC_TIMEOUT, || {
+        node.blockchain_info().best_block_hash == block.get_hash()
+    });
+    assert_eq!(node.blockchain_info().height, 2);
+}
+
+#[test]
+fn test_reject_unknown_input() {
+    let (mut node, address) = funded_node(0);
+    node.start(&[]);
+
+    let tx = TxBuilder::new()
+        .input(&"0".repeat(64), 0)
+        .output(1, &address)
+        .build();
+
+    assert!(node.rpc(RpcRequest::SendRawTransaction(tx)).is_err());
+}
+
+#[test]
+fn test_create_blockchain_keeps_existing_chain() {
+    let (node, address) = funded_node(1);
+
+    let err = node.cli_error(&["create_blockchain", &address]);
+    assert!(err.contains("--force"), "{}", err);
+    assert_eq!(node.balance(&address), 20);
+
+    node.cli(&["create_blockchain", &address, "--force"]);
+    assert_eq!(node.balance(&address), 10);
+
+    node.cli(&["wipe_chain", "--yes"]);
+    node.create_blockchain(&address);
+    assert_eq!(node.balance(&address), 10);
+}
+
+#[test]
+fn test_mempool_accept_reports_reason() {
+    let (mut node, address) = funded_node(0);
+    node.start(&[]);
+
+    let test_accept = |tx: Transaction| match node.rpc(RpcRequest::TestMempoolAccept(tx)) {
+        Ok(RpcResponse::MempoolAccept(result)) => result,
+        response => panic!("unexpected response: {:?}", response),
+    };
+
+    let unknown_input = TxBuilder::new()
+        .input(&"0".repeat(64), 0)
+        .output(1, &address)
+        .build();
+    let result = test_accept(unknown_input.clone());
+    assert_eq!(result.txid, unknown_input.id);
+    assert!(!result.allowed);
+    assert!(result.reject_reason.is_some());
+    assert_eq!(result.fee_rate, None);
+
+    let coinbase = TxBuilder::new().input("", -1).output(1, &address).build();
+    let reason = test_accept(coinbase).reject_reason.unwrap();
+    assert!(reason.contains("Coinbase"), "{}", reason);
+}
+
+#[test]
+fn test_chain_stats_csv() {
+    let (mut node, _) = funded_node(4);
+    node.start(&[]);
+
+    // 高度 0 到 4 的五个区块按每两个分组
+    let csv = node.cli(&["chain_stats", &node.addr(), "--window", "2", "--csv"]);
+    let rows: Vec<&str> = csv.lines().collect();
+    assert_eq!(rows.len(), 4, "{}", csv);
+    assert!(rows[0].starts_with("from_height,to_height,avg_block_interval"));
+    assert!(rows[1].starts_with("0,1,"));
+    assert!(rows[3].starts_with("4,4,"));
+}
+
+#[test]
+fn test_export() {
+    let (node, from) = funded_node(1);
+    let to = node.create_wallet();
+    node.cli(&["send", &from, &to, "3", "--mine"]);
+
+    let out = node.cli(&["export", "--format", "jsonl", "export"]);
+    assert!(
+        out.contains("3 blocks, 4 transactions, 1 inputs"),
+        "{}",
+        out
+    );
+    let outputs = std::fs::read_to_string(node.dir.join("export/outputs.jsonl")).unwrap();
+    assert!(outputs.contains(&format!("\"address\":\"{}\",\"value\":3", to)));
+
+    node.cli(&["export", "export"]);
+    let blocks = std::fs::read_to_string(node.dir.join("export/blocks.csv")).unwrap();
+    assert_eq!(blocks.lines().count(), 4);
+}
+
+#[test]
+fn test_abandon_unknown_tx() {
+    let node = TestNode::new();
+
+    let err = node.cli_error(&["abandon_tx", &"0".repeat(64)]);
+    assert!(err.contains("not a pending wallet transaction"), "{}", err);
+}
+
+/**
+ * @desc 发送交易，返回 txid
+ */
+fn send(node: &TestNode, args: &[&str]) -> String {
+    let out = node.cli(&[&["send"], args].concat());
+    out.split("Send success: ")
+        .nth(1)
+        .unwrap()
+        .trim()
+        .to_string()
+}
+
+#[test]
+fn test_abandon_tx_frees_inputs() {
+    let (mut node, from) = funded_node(1);
+    let to = node.create_wallet();
+    node.start(&[]);
+    let in_mempool = |id: &str| {
+        matches!(
+            node.rpc(RpcRequest::GetTransaction(id.to_string())),
+            Ok(RpcResponse::Transaction(_))
+        )
+    };
+
+    let txid = send(&node, &[&from, &to, "3"]);
+    assert!(in_mempool(&txid));
+    let out = node.cli(&["abandon_tx", &txid]);
+    assert!(out.contains(&format!("Abandon transaction success: {}", txid)));
+    assert!(!in_mempool(&txid));
+
+    // 放弃的交易不再占用输入，花费相同输出的新交易不需要更高的手续费
+    let resent = send(&node, &[&from, &to, "4"]);
+    assert!(in_mempool(&resent));
+    assert!(!node.cli(&["list_wallet_txs"]).contains(&txid));
+}
+
+#[test]
+fn test_list_wallet_txs_flags_conflicts() {
+    let (mut node, from) = funded_node(1);
+    let to = node.create_wallet();
+    node.start(&[]);
+    let txid = send(&node, &[&from, &to, "3"]);
+    node.stop();
+
+    // 交易池随节点停止丢失，花费相同输出的另一笔交易进入主链
+    let confirmed = send(&node, &[&from, &to, "5", "--mine"]);
+    let txs = node.cli(&["list_wallet_txs"]);
+    let entry = txs.split("WalletTx").find(|tx| tx.contains(&txid)).unwrap();
+    assert!(entry.contains(&confirmed), "{}", txs);
+}
+
+#[test]
+fn test_bump_fee_replaces_mempool_tx() {
+    let (mut node, from) = funded_node(1);
+    let to = node.create_wallet();
+    node.start(&[]);
+
+    let out = node.cli(&["send", &from, &to, "3"]);
+    let txid = out
+        .split("Send success: ")
+        .nth(1)
+        .unwrap()
+        .trim()
+        .to_string();
+    let out = node.cli(&["bump_fee", &txid, "--fee", "2"]);
+    let bumped = out
+        .split("Bump fee success (Replace): ")
+        .nth(1)
+        .unwrap()
+        .trim()
+        .to_string();
+
+    // 替换交易进入交易池，原交易被移除，钱包只跟踪替换交易
+    let in_mempool = |id: &str| {
+        matches!(
+            node.rpc(RpcRequest::GetTransaction(id.to_string())),
+            Ok(RpcResponse::Transaction(_))
+        )
+    };
+    assert!(in_mempool(&bumped));
+    assert!(!in_mempool(&txid));
+    let txs = node.cli(&["list_wallet_txs"]);
+    assert!(txs.contains(&bumped) && !txs.contains(&txid));
+}
+
+#[test]
+fn test_relay_accepts_child_of_mempool_tx() {
+    let (mut node, from) = funded_node(1);
+    let to = node.create_wallet();
+    node.start(&[]);
+    let get_tx = |id: &str| match node.rpc(RpcRequest::GetTransaction(id.to_string())) {
+        Ok(RpcResponse::Transaction(info)) => Some(info.transaction),
+        _ => None,
+    };
+
+    // 父交易还在交易池中，子交易(CPFP)的手续费要从交易池中的父交易计算
+    let parent = get_tx(&send(&node, &[&from, &to, "3"])).unwrap();
+    let vout = parent.vout.iter().position(|out| out.value == 3).unwrap();
+    let child = TxBuilder::new()
+        .input(&parent.id, vout as i32)
+        .output(2, &to)
+        .build();
+    let peer = FakePeer::new();
+    peer.handshake(&node);
+    node.send_message("tx", &(peer.addr(), &child));
+
+    wait_until(SYNC_TIMEOUT, || get_tx(&child.id).is_some());
+}
+
+#[test]
+fn test_compact_block_relay() {
+    let (mut node, from) = funded_node(1);
+    let to = node.create_wallet();
+    node.start(&[]);
+    let peer = FakePeer::new();
+    peer.handshake(&node);
+    let get_tx = |id: &str| match node.rpc(RpcRequest::GetTransaction(id.to_string())) {
+        Ok(RpcResponse::Transaction(info)) => Some(info),
+        _ => None,
+    };
+    let tip = || node.blockchain_info().best_block_hash;
+
+    // 交易池中的交易足以还原紧凑区块；区块加入侧链时交易仍留在交易池中
+    let txid = send(&node, &[&from, &to, "3"]);
+    let tx = get_tx(&txid).unwrap().transaction;
+    let fork_point = tip();
+    let main = BlockBuilder::on_tip(&node).coinbase(&to).build();
+    node.submit_block(&main);
+    let side = BlockBuilder::new(&fork_point, 2)
+        .coinbase(&to)
+        .tx(tx.clone())
+        .build();
+    node.send_message("cmpct_block", &(peer.addr(), side.to_compact()));
+    wait_until(SYNC_TIMEOUT, || {
+        matches!(
+            node.rpc(RpcRequest::GetBlock(side.get_hash())),
+            Ok(RpcResponse::Block(_))
+        )
+    });
+    assert_eq!(tip(), main.get_hash());
+    assert_eq!(get_tx(&txid).unwrap().block_hash, None);
+
+    // 交易池中没有的交易向对方请求，补全后哈希不符时退回请求完整区块
+    node.cli(&["abandon_tx", &txid]);
+    let block = BlockBuilder::on_tip(&node)
+        .coinbase(&to)
+        .tx(tx.clone())
+        .build();
+    let hash = block.get_hash();
+    node.send_message("cmpct_block", &(peer.addr(), block.to_compact()));
+    let (_, requested, indexes): (String, String, Vec<usize>) =
+        bincode::deserialize(&peer.expect("get_blocktxn", SYNC_TIMEOUT)).unwrap();
+    assert_eq!((requested, indexes), (hash.clone(), vec![1]));
+    let wrong = &side.get_transaction()[0];
+    node.send_message("block_txn", &(peer.addr(), &hash, vec![wrong]));
+    let (_, kind, id): (String, String, String) =
+        bincode::deserialize(&peer.expect("get_data", SYNC_TIMEOUT)).unwrap();
+    assert_eq!((kind.as_str(), id), ("block", hash.clone()));
+
+    // 收到正确的交易后还原区块并加入主链
+    node.send_message("cmpct_block", &(peer.addr(), block.to_compact()));
+    peer.expect("get_blocktxn", SYNC_TIMEOUT);
+    node.send_message("block_txn", &(peer.addr(), &hash, vec![&tx]));
+    wait_until(SYNC_TIMEOUT, || tip() == hash);
+    assert_eq!(get_tx(&txid).unwrap().block_hash, Some(hash));
+}
+
+#[test]
+fn test_get_data_unknown_block_replies_not_found() {
+    let (mut node, _) = funded_node(0);
+    node.start(&[]);
+    let peer = FakePeer::new();
+    peer.handshake(&node);
+
+    let id = "0".repeat(64);
+    node.send_message("get_data", &(peer.addr(), "block", &id));
+
+    let data = peer.expect("not_found", SYNC_TIMEOUT);
+    let (addr_from, kind, not_found): (String, String, String) =
+        bincode::deserialize(&data).unwrap();
+    assert_eq!(addr_from, node.addr());
+    assert_eq!(kind, "block");
+    assert_eq!(not_found, id);
+}
+
+#[test]
+fn test_chain_messages_require_handshake() {
+    let (mut node, _) = funded_node(0);
+    node.start(&[]);
+    let peer = FakePeer::new();
+    let peer_info = || match node.rpc(RpcRequest::GetPeerInfo) {
+        Ok(RpcResponse::PeerInfo(peers)) => peers.into_iter().find(|p| p.addr == peer.addr()),
+        _ => None,
+    };
+
+    // 握手前的链消息被忽略并计入封禁分数，节点向对方发起握手
+    let id = "0".repeat(64);
+    node.send_message("get_data", &(peer.addr(), "block", &id));
+    peer.expect("version", SYNC_TIMEOUT);
+    wait_until(
+        SYNC_TIMEOUT,
+        || matches!(peer_info(), Some(p) if p.ban_score == 1),
+    );
+    assert_eq!(peer_info().unwrap().handshake, HandshakeState::VersionSent);
+
+    peer.handshake(&node);
+    wait_until(
+        SYNC_TIMEOUT,
+        || matches!(peer_info(), Some(p) if p.handshake == HandshakeState::Ready),
+    );
+    node.send_message("get_data", &(peer.addr(), "block", &id));
+    peer.expect("not_found", SYNC_TIMEOUT);
+}
+
+#[test]
+fn test_duplicate_block_is_ignored() {
+    let (mut node, address) = funded_node(0);
+    node.start(&[]);
+    let peer = FakePeer::new();
+    peer.handshake(&node);
+    let block_bytes = || match node.rpc(RpcRequest::GetNetTotals) {
+        Ok(RpcResponse::NetTotals(totals)) => totals.recv_per_msg.get("block").copied(),
+        _ => None,
+    };
+
+    // 同一个区块收到两次，只加入区块链一次，第二次不会被当作孤立区块
+    let block = BlockBuilder::on_tip(&node).coinbase(&address).build();
+    node.send_message("block", &(peer.addr(), &block));
+    wait_until(SYNC_TIMEOUT, || node.blockchain_info().height == 1);
+    let size = block_bytes().unwrap();
+    node.send_message("block", &(peer.addr(), &block));
+    wait_until(SYNC_TIMEOUT, || block_bytes() == Some(2 * size));
+
+    match node.rpc(RpcRequest::GetForkStats).unwrap() {
+        RpcResponse::ForkStats(stats) => {
+            assert_eq!(stats.blocks_received, 1);
+            assert_eq!(stats.orphan_blocks, 0);
+        }
+        response => panic!("unexpected response: {:?}", response),
+    }
+}
+
+#[test]
+fn test_inventory_not_echoed_to_source() {
+    let (mut node, address) = funded_node(0);
+    node.start(&[]);
+    let source = FakePeer::new();
+    let other = FakePeer::new();
+    source.handshake(&node);
+    other.handshake(&node);
+
+    // source 先公告了区块，节点收到区块后只向 other 公告
+    let block = BlockBuilder::on_tip(&node).coinbase(&address).build();
+    let hash = block.get_hash();
+    node.send_message("inv", &(source.addr(), "block", vec![&hash]));
+    source.expect("get_data", SYNC_TIMEOUT);
+    node.submit_block(&block);
+
+    let announces = |data: &[u8]| {
+        let (_, kind, items): (String, String, Vec<String>) = bincode::deserialize(data).unwrap();
+        kind == "block" && items.contains(&hash)
+    };
+    wait_until(SYNC_TIMEOUT, || {
+        other.received("inv").iter().any(|data| announces(data))
+    });
+    assert!(!source.received("inv").iter().any(|data| announces(data)));
+}
+
+#[test]
+fn test_miner_pays_fresh_wallet_addresses() {
+    let (mut node, from) = funded_node(1);
+    let to = node.create_wallet();
+    let out = node.cli(&["create_wallets", "--name", "miner"]);
+    let first = out.trim().rsplit(' ').next().unwrap().to_string();
+    node.start_miner("miner", "");
+    wait_synced(&node);
+
+    // 每笔交易触发挖出一个区块，奖励支付到从钱包新派生的地址
+    for height in [2, 3] {
+        node.cli(&["send", &from, &to, "1"]);
+        wait_until(SYNC_TIMEOUT, || node.blockchain_info().height == height);
+    }
+
+    let balances = node.cli(&["list_address_balances"]);
+    let paid: Vec<&str> = balances
+        .lines()
+        .filter_map(|line| line.split_once(": "))
+        .filter(|(address, balance)| {
+            ![from.as_str(), to.as_str()].contains(address) && *balance != "0"
+        })
+        .map(|(address, _)| address)
+        .collect();
+    assert_eq!(paid.len(), 2, "{}", balances);
+    assert!(!paid.contains(&first.as_str()));
+}
+
+#[test]
+fn test_coinbase_tag() {
+    let (mut node, from) = funded_node(1);
+    let to = node.create_wallet();
+    node.start_miner(&from, "coinbase_tag = \"alice-node\"");
+    wait_synced(&node);
+
+    node.cli(&["send", &from, &to, "1"]);
+    wait_until(SYNC_TIMEOUT, || node.blockchain_info().height == 2);
+
+    // 矿工挖出的区块带有标签，generate 挖出的区块没有
+    let mined = node.cli(&["get_block", "2", &node.addr()]);
+    assert!(mined.contains("\"alice-node\""), "{}", mined);
+    let generated = node.cli(&["get_block", "1", &node.addr()]);
+    assert!(generated.contains("coinbase_tag: None"), "{}", generated);
+}
+
+#[test]
+fn test_reward_split() {
+    let (mut node, from) = funded_node(1);
+    let to = node.create_wallet();
+    let pool = node.create_wallet();
+    node.start_miner(&from, &format!("reward_split = [\"{}:30\"]", pool));
+    wait_synced(&node);
+
+    node.cli(&["send", &from, &to, "1"]);
+    wait_until(SYNC_TIMEOUT, || node.blockchain_info().height == 2);
+
+    // 奖励的 30% 归奖池，其余归矿工
+    assert_eq!(node.balance(&pool), 3);
+    assert_eq!(node.balance(&from), 19 + 7);
+}
+
+#[test]
+fn test_cli_uses_running_node() {
+    let (mut node, address) = funded_node(2);
+    node.start(&[]);
+
+    // 节点锁定了数据库，不带 --node 的查询也通过它的 RPC 完成
+    let out = node.cli(&["get_balance", &address]);
+    assert_eq!(out.trim(), "Balance: 30");
+    assert!(node
+        .cli_error(&["generate", &address])
+        .contains("A local node is running"));
+
+    node.stop();
+    assert_eq!(node.balance(&address), 30);
+    node.generate(&address, 1);
+}
+
+#[test]
+fn test_light_wallet_uses_remote_node() {
+    let (mut node, address) = funded_node(2);
+    node.start(&[]);
+    let light = TestNode::new();
+    let addr = node.addr();
+    std::fs::copy(
+        node.data_dir().join(".cookie"),
+        light.data_dir().join(".cookie"),
+    )
+    .unwrap();
+
+    // 轻钱包目录中没有区块链，余额来自远程节点，也不会创建数据库
+    let out = light.cli(&["--light", "--light_node", &addr, "get_balance", &address]);
+    assert_eq!(out.trim(), "Balance: 30");
+    assert!(!light.data_dir().join("blocks").exists());
+
+    let err = light.cli_error(&["--light", "--light_node", &addr, "generate", &address]);
+    assert!(err.contains("--light mode"), "{}", err);
+}
+
+#[test]
+fn test_start_node_on_free_port() {
+    let (mut node, address) = funded_node(1);
+    node.start_on_free_port();
+
+    // 节点选择的端口记录在数据目录的 .node
+    assert_ne!(node.port, 0);
+    assert_eq!(node.blockchain_info().height, 1);
+    assert_eq!(node.balance(&address), 20);
+}
+
+#[test]
+fn test_submit_and_fetch_raw_block() {
+    let (mut node, address) = funded_node(1);
+    node.start(&[]);
+    let addr = node.addr();
+
+    let block = BlockBuilder::on_tip(&node).coinbase(&address).build();
+    let hex = block.to_hex().unwrap();
+    let out = node.cli(&["submit_block", &hex, &addr]);
+    assert_eq!(out.trim(), block.get_hash());
+    assert_eq!(node.blockchain_info().height, 2);
+
+    // 按高度和 hash 取回的区块和提交的相同
+    assert_eq!(node.cli(&["get_block_raw", "2", &addr]).trim(), hex);
+    assert_eq!(
+        node.cli(&["get_block_raw", &block.get_hash(), &addr])
+            .trim(),
+        hex
+    );
+    assert!(node
+        .cli_error(&["submit_block", &hex, &addr])
+        .contains("already known"));
+}